@@ -4,12 +4,12 @@ use super::mc_msa::{
 use anyhow::{anyhow, Context};
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
 pub use oauth2::basic::{BasicClient, BasicTokenType};
-pub use oauth2::reqwest::async_http_client;
 pub use oauth2::{
 	AuthUrl, ClientId, DeviceAuthorizationUrl, EmptyExtraTokenFields, ErrorResponse, RefreshToken,
 	RequestTokenError, Scope, StandardDeviceAuthorizationResponse, StandardTokenResponse,
 	TokenResponse, TokenUrl,
 };
+use oauth2::{HttpRequest, HttpResponse};
 use reqwest::Response;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
@@ -27,13 +27,13 @@ pub async fn authenticate_microsoft_user(
 	o: &mut impl MCVMOutput,
 ) -> anyhow::Result<MicrosoftAuthResult> {
 	let oauth_client = create_client(client_id).context("Failed to create OAuth client")?;
-	let response = generate_login_page(&oauth_client)
+	let response = generate_login_page(&oauth_client, client)
 		.await
 		.context("Failed to execute authorization and generate login page")?;
 
 	o.display_special_ms_auth(response.verification_uri(), response.user_code().secret());
 
-	let token = get_microsoft_token(&oauth_client, response)
+	let token = get_microsoft_token(&oauth_client, response, client)
 		.await
 		.context("Failed to get Microsoft token")?;
 
@@ -98,15 +98,63 @@ pub fn create_client(client_id: ClientId) -> anyhow::Result<BasicClient> {
 	Ok(client)
 }
 
+/// An error from sending an OAuth request with a reqwest client
+#[derive(Debug, thiserror::Error)]
+pub enum OAuthHttpError {
+	/// The request failed to send or the response failed to be read
+	#[error("Request failed: {0}")]
+	Request(#[from] reqwest::Error),
+	/// The request or response contained an invalid method or header
+	#[error("Invalid HTTP data: {0}")]
+	InvalidData(String),
+}
+
+/// Send an OAuth request using a reqwest client, so that the client's
+/// settings like proxies and timeouts are used
+pub async fn send_oauth_request(
+	client: &reqwest::Client,
+	request: HttpRequest,
+) -> Result<HttpResponse, OAuthHttpError> {
+	let method = reqwest::Method::from_bytes(request.method.as_str().as_bytes())
+		.map_err(|e| OAuthHttpError::InvalidData(e.to_string()))?;
+	let mut builder = client
+		.request(method, request.url.as_str())
+		.body(request.body);
+	for (name, value) in &request.headers {
+		builder = builder.header(name.as_str(), value.as_bytes());
+	}
+
+	let response = builder.send().await?;
+
+	let status_code = oauth2::http::StatusCode::from_u16(response.status().as_u16())
+		.map_err(|e| OAuthHttpError::InvalidData(e.to_string()))?;
+	let mut headers = oauth2::http::HeaderMap::new();
+	for (name, value) in response.headers() {
+		let name = oauth2::http::HeaderName::from_bytes(name.as_str().as_bytes())
+			.map_err(|e| OAuthHttpError::InvalidData(e.to_string()))?;
+		let value = oauth2::http::HeaderValue::from_bytes(value.as_bytes())
+			.map_err(|e| OAuthHttpError::InvalidData(e.to_string()))?;
+		headers.append(name, value);
+	}
+	let body = response.bytes().await?.to_vec();
+
+	Ok(HttpResponse {
+		status_code,
+		headers,
+		body,
+	})
+}
+
 /// First part of the auth process
 pub async fn generate_login_page(
 	client: &BasicClient,
+	http_client: &reqwest::Client,
 ) -> anyhow::Result<StandardDeviceAuthorizationResponse> {
 	let out = client
 		.exchange_device_code()
 		.context("Failed to exchange device code")?
 		.add_scope(Scope::new("XboxLive.signin offline_access".into()))
-		.request_async(async_http_client)
+		.request_async(|request| send_oauth_request(http_client, request))
 		.await;
 
 	out.map_err(decorate_request_token_error)
@@ -120,11 +168,12 @@ pub type MicrosoftToken = StandardTokenResponse<EmptyExtraTokenFields, BasicToke
 pub async fn get_microsoft_token(
 	client: &BasicClient,
 	auth_response: StandardDeviceAuthorizationResponse,
+	http_client: &reqwest::Client,
 ) -> anyhow::Result<MicrosoftToken> {
 	let out = client
 		.exchange_device_access_token(&auth_response)
 		.request_async(
-			async_http_client,
+			|request| send_oauth_request(http_client, request),
 			|x| async move { std::thread::sleep(x) },
 			None,
 		)
@@ -137,10 +186,11 @@ pub async fn get_microsoft_token(
 pub async fn refresh_microsoft_token(
 	client: &BasicClient,
 	refresh_token: &RefreshToken,
+	http_client: &reqwest::Client,
 ) -> anyhow::Result<MicrosoftToken> {
 	let out = client
 		.exchange_refresh_token(refresh_token)
-		.request_async(async_http_client)
+		.request_async(|request| send_oauth_request(http_client, request))
 		.await;
 
 	out.map_err(decorate_request_token_error)
@@ -251,3 +301,81 @@ pub struct Keypair {
 	#[serde(alias = "publicKey")]
 	pub public_key: String,
 }
+
+#[cfg(test)]
+mod tests {
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+	use tokio::net::TcpListener;
+
+	use super::*;
+
+	/// Starts a fake HTTP proxy that answers a single request with a token response
+	/// and returns its address along with the request line it received
+	async fn serve_proxy() -> (String, tokio::task::JoinHandle<String>) {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		let task = tokio::spawn(async move {
+			let (mut stream, _) = listener.accept().await.unwrap();
+			let mut buf = [0; 4096];
+			let len = stream.read(&mut buf).await.unwrap();
+			let request = String::from_utf8_lossy(&buf[..len]).to_string();
+
+			let body =
+				r#"{"access_token":"access","token_type":"bearer","refresh_token":"refresh"}"#;
+			let response = format!(
+				"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+				body.len()
+			);
+			stream.write_all(response.as_bytes()).await.unwrap();
+
+			request.lines().next().unwrap_or_default().to_string()
+		});
+
+		(format!("http://{addr}"), task)
+	}
+
+	#[tokio::test]
+	async fn test_oauth_request_uses_proxy() {
+		let (proxy, task) = serve_proxy().await;
+		let client = reqwest::Client::builder()
+			.proxy(reqwest::Proxy::all(proxy).unwrap())
+			.build()
+			.unwrap();
+
+		let request = HttpRequest {
+			url: "http://login.example.com/token".parse().unwrap(),
+			method: oauth2::http::Method::POST,
+			headers: oauth2::http::HeaderMap::new(),
+			body: b"grant_type=refresh_token".to_vec(),
+		};
+		let response = send_oauth_request(&client, request).await.unwrap();
+		assert_eq!(response.status_code, oauth2::http::StatusCode::OK);
+		assert_eq!(
+			response.headers.get("content-type").unwrap(),
+			"application/json"
+		);
+
+		// Requests through a proxy use the absolute URL
+		let request_line = task.await.unwrap();
+		assert_eq!(request_line, "POST http://login.example.com/token HTTP/1.1");
+	}
+
+	#[tokio::test]
+	async fn test_refresh_token_uses_proxy() {
+		let (proxy, task) = serve_proxy().await;
+		let client = reqwest::Client::builder()
+			.proxy(reqwest::Proxy::all(proxy).unwrap())
+			.build()
+			.unwrap();
+
+		let oauth_client = create_client(ClientId::new("client".into())).unwrap();
+		let result =
+			refresh_microsoft_token(&oauth_client, &RefreshToken::new("refresh".into()), &client)
+				.await;
+		// The fake proxy can't tunnel TLS, but the request must still have gone to it
+		assert!(result.is_err());
+
+		let request_line = task.await.unwrap();
+		assert!(request_line.starts_with("CONNECT login.microsoftonline.com:443"));
+	}
+}
@@ -0,0 +1,345 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use clap::{Command as ClapCommand, CommandFactory};
+use clap_complete::Shell;
+use itertools::Itertools;
+use mcvm::io::paths::Paths;
+
+use super::{command_with_plugins, Cli};
+
+/// The name of the binary that completions are generated for
+const BIN_NAME: &str = "mcvm";
+
+/// A source of values for dynamic completion. Each one is backed by a list
+/// command with stable raw output that the completion script calls back into
+#[derive(Debug, Clone, Copy)]
+enum ValueSource {
+	Instances,
+	Profiles,
+	AvailablePackages,
+	Plugins,
+	Repositories,
+}
+
+impl ValueSource {
+	/// Get the arguments to mcvm that will print the values of this source
+	fn list_args(&self) -> &'static str {
+		match self {
+			Self::Instances => "instance list --raw",
+			Self::Profiles => "profile list --raw",
+			Self::AvailablePackages => "package list --raw --available",
+			Self::Plugins => "plugin list --raw",
+			Self::Repositories => "package repository list --raw",
+		}
+	}
+}
+
+/// A positional value that is completed dynamically
+struct DynamicValue {
+	/// The subcommand path leading up to the value
+	path: &'static [&'static str],
+	/// Where the values come from
+	source: ValueSource,
+	/// Whether the positional argument accepts multiple values
+	multiple: bool,
+}
+
+macro_rules! dynamic_value {
+	([$($path:literal),+], $source:ident) => {
+		DynamicValue {
+			path: &[$($path),+],
+			source: ValueSource::$source,
+			multiple: false,
+		}
+	};
+
+	([$($path:literal),+], $source:ident, multiple) => {
+		DynamicValue {
+			path: &[$($path),+],
+			source: ValueSource::$source,
+			multiple: true,
+		}
+	};
+}
+
+/// All of the positional values that are completed dynamically
+const DYNAMIC_VALUES: &[DynamicValue] = &[
+	dynamic_value!(["launch"], Instances),
+	dynamic_value!(["instance", "launch"], Instances),
+	dynamic_value!(["instance", "info"], Instances),
+	dynamic_value!(["instance", "update"], Instances, multiple),
+	dynamic_value!(["instance", "dir"], Instances),
+	dynamic_value!(["instance", "logs"], Instances),
+	dynamic_value!(["instance", "orphans"], Instances),
+	dynamic_value!(["profile", "info"], Profiles),
+	dynamic_value!(["profile", "update"], Profiles, multiple),
+	dynamic_value!(["profile", "rollback"], Profiles),
+	dynamic_value!(["daemon", "start"], Instances),
+	dynamic_value!(["daemon", "stop"], Instances),
+	dynamic_value!(["daemon", "restart"], Instances),
+	dynamic_value!(["daemon", "log"], Instances),
+	dynamic_value!(["package", "cat"], AvailablePackages),
+	dynamic_value!(["package", "info"], AvailablePackages),
+	dynamic_value!(["package", "repository", "info"], Repositories),
+	dynamic_value!(["plugin", "info"], Plugins),
+	dynamic_value!(["plugin", "enable"], Plugins),
+	dynamic_value!(["plugin", "disable"], Plugins),
+];
+
+pub fn run(shell: Shell, paths: &Paths) -> anyhow::Result<()> {
+	// Completions still work for the built-in subcommands if the plugins are broken
+	let cmd = command_with_plugins(paths).unwrap_or_else(|_| Cli::command());
+	let script = generate(shell, cmd).context("Failed to generate completion script")?;
+	print!("{script}");
+
+	Ok(())
+}
+
+/// Generate the full completion script for a shell
+fn generate(shell: Shell, mut cmd: ClapCommand) -> anyhow::Result<String> {
+	let mut buf = Vec::new();
+	clap_complete::generate(shell, &mut cmd, BIN_NAME, &mut buf);
+	let mut script =
+		String::from_utf8(buf).context("Generated completion script was not valid UTF-8")?;
+
+	let values = DYNAMIC_VALUES
+		.iter()
+		.map(|value| (get_patterns(&cmd, value), value.source.list_args()))
+		.collect::<Vec<_>>();
+
+	match shell {
+		Shell::Bash => write_bash(&mut script, &values)?,
+		Shell::Zsh => write_zsh(&mut script, &values)?,
+		Shell::Fish => write_fish(&mut script, &values)?,
+		Shell::PowerShell => write_powershell(&mut script, &values)?,
+		// Other shells only get the static completions
+		_ => {}
+	}
+
+	Ok(script)
+}
+
+/// Get the space-separated subcommand patterns that lead up to a dynamic value,
+/// including every combination of aliases
+fn get_patterns(cmd: &ClapCommand, value: &DynamicValue) -> Vec<String> {
+	let mut current = cmd;
+	let mut names_per_segment = Vec::new();
+	for segment in value.path {
+		let Some(subcommand) = current.find_subcommand(segment) else {
+			panic!("Dynamic completion path segment '{segment}' does not exist");
+		};
+		let names = std::iter::once(subcommand.get_name())
+			.chain(subcommand.get_all_aliases())
+			.collect::<Vec<_>>();
+		names_per_segment.push(names);
+		current = subcommand;
+	}
+
+	let mut out = Vec::new();
+	for names in names_per_segment.into_iter().multi_cartesian_product() {
+		let path = names.join(" ");
+		if value.multiple {
+			out.push(format!("{path} *"));
+		}
+		out.push(path);
+	}
+
+	out
+}
+
+/// Add dynamic completions to a Bash script
+fn write_bash(script: &mut String, values: &[(Vec<String>, &str)]) -> anyhow::Result<()> {
+	writeln!(script)?;
+	writeln!(script, "_{BIN_NAME}_dynamic() {{")?;
+	writeln!(script, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"")?;
+	writeln!(script, "    local words=()")?;
+	writeln!(script, "    local i")?;
+	writeln!(script, "    for ((i = 1; i < COMP_CWORD; i++)); do")?;
+	writeln!(
+		script,
+		"        [[ \"${{COMP_WORDS[i]}}\" == -* ]] || words+=(\"${{COMP_WORDS[i]}}\")"
+	)?;
+	writeln!(script, "    done")?;
+	writeln!(script, "    if [[ \"${{cur}}\" != -* ]]; then")?;
+	writeln!(script, "        case \"${{words[*]}}\" in")?;
+	for (patterns, args) in values {
+		let patterns = patterns.iter().map(|x| quote_glob(x)).join("|");
+		writeln!(script, "            {patterns})")?;
+		writeln!(
+			script,
+			"                COMPREPLY=($(compgen -W \"$({BIN_NAME} {args} 2>/dev/null)\" -- \"${{cur}}\"))"
+		)?;
+		writeln!(script, "                return 0")?;
+		writeln!(script, "                ;;")?;
+	}
+	writeln!(script, "        esac")?;
+	writeln!(script, "    fi")?;
+	writeln!(script, "    _{BIN_NAME} \"$@\"")?;
+	writeln!(script, "}}")?;
+	writeln!(
+		script,
+		"complete -F _{BIN_NAME}_dynamic -o bashdefault -o default {BIN_NAME}"
+	)?;
+
+	Ok(())
+}
+
+/// Add dynamic completions to a Zsh script
+fn write_zsh(script: &mut String, values: &[(Vec<String>, &str)]) -> anyhow::Result<()> {
+	writeln!(script)?;
+	writeln!(script, "_{BIN_NAME}_dynamic() {{")?;
+	writeln!(script, "    local -a path_words")?;
+	writeln!(script, "    path_words=(${{words[2,CURRENT-1]:#-*}})")?;
+	writeln!(script, "    if [[ \"${{words[CURRENT]}}\" != -* ]]; then")?;
+	writeln!(script, "        case \"${{path_words[*]}}\" in")?;
+	for (patterns, args) in values {
+		let patterns = patterns.iter().map(|x| quote_glob(x)).join("|");
+		writeln!(script, "            {patterns})")?;
+		writeln!(
+			script,
+			"                compadd -- ${{(f)\"$({BIN_NAME} {args} 2>/dev/null)\"}}"
+		)?;
+		writeln!(script, "                return")?;
+		writeln!(script, "                ;;")?;
+	}
+	writeln!(script, "        esac")?;
+	writeln!(script, "    fi")?;
+	writeln!(script, "    _{BIN_NAME} \"$@\"")?;
+	writeln!(script, "}}")?;
+	writeln!(script, "compdef _{BIN_NAME}_dynamic {BIN_NAME}")?;
+
+	Ok(())
+}
+
+/// Add dynamic completions to a Fish script
+fn write_fish(script: &mut String, values: &[(Vec<String>, &str)]) -> anyhow::Result<()> {
+	writeln!(script)?;
+	writeln!(script, "function __{BIN_NAME}_dynamic_matches")?;
+	writeln!(script, "    set -l words")?;
+	writeln!(script, "    for word in (commandline -opc)[2..-1]")?;
+	writeln!(
+		script,
+		"        string match -q -- '-*' $word; or set -a words $word"
+	)?;
+	writeln!(script, "    end")?;
+	writeln!(script, "    set -l joined (string join ' ' -- $words)")?;
+	writeln!(script, "    for pattern in $argv")?;
+	writeln!(
+		script,
+		"        string match -q -- $pattern \"$joined\"; and return 0"
+	)?;
+	writeln!(script, "    end")?;
+	writeln!(script, "    return 1")?;
+	writeln!(script, "end")?;
+	for (patterns, args) in values {
+		let patterns = patterns.iter().map(|x| format!("'{x}'")).join(" ");
+		writeln!(
+			script,
+			"complete -c {BIN_NAME} -f -n \"__{BIN_NAME}_dynamic_matches {patterns}\" -a \"({BIN_NAME} {args} 2>/dev/null)\""
+		)?;
+	}
+
+	Ok(())
+}
+
+/// Add dynamic completions to a PowerShell script. The static completer is
+/// stored in a variable instead of being registered so that we can fall back to it
+fn write_powershell(script: &mut String, values: &[(Vec<String>, &str)]) -> anyhow::Result<()> {
+	let registration =
+		format!("Register-ArgumentCompleter -Native -CommandName '{BIN_NAME}' -ScriptBlock {{");
+	if !script.contains(&registration) {
+		// Leave the static completions alone if we don't recognize them
+		return Ok(());
+	}
+	*script = script.replacen(&registration, "$global:__mcvm_static_completer = {", 1);
+
+	writeln!(script)?;
+	writeln!(script, "{registration}")?;
+	writeln!(
+		script,
+		"    param($wordToComplete, $commandAst, $cursorPosition)"
+	)?;
+	writeln!(script)?;
+	writeln!(
+		script,
+		"    $words = @($commandAst.CommandElements | Select-Object -Skip 1 | Where-Object {{"
+	)?;
+	writeln!(
+		script,
+		"        $_.Extent.EndOffset -lt $cursorPosition -and -not $_.ToString().StartsWith('-')"
+	)?;
+	writeln!(
+		script,
+		"    }} | ForEach-Object {{ $_.ToString() }}) -join ' '"
+	)?;
+	writeln!(script)?;
+	writeln!(script, "    $source = switch -Wildcard ($words) {{")?;
+	for (patterns, args) in values {
+		for pattern in patterns {
+			writeln!(script, "        '{pattern}' {{ '{args}'; break }}")?;
+		}
+	}
+	writeln!(script, "    }}")?;
+	writeln!(
+		script,
+		"    if ($source -and -not $wordToComplete.StartsWith('-')) {{"
+	)?;
+	writeln!(
+		script,
+		"        & {BIN_NAME} @($source -split ' ') 2>$null | Where-Object {{ $_ -like \"$wordToComplete*\" }} | ForEach-Object {{"
+	)?;
+	writeln!(
+		script,
+		"            [CompletionResult]::new($_, $_, [CompletionResultType]::ParameterValue, $_)"
+	)?;
+	writeln!(script, "        }}")?;
+	writeln!(script, "        return")?;
+	writeln!(script, "    }}")?;
+	writeln!(
+		script,
+		"    & $global:__mcvm_static_completer $wordToComplete $commandAst $cursorPosition"
+	)?;
+	writeln!(script, "}}")?;
+
+	Ok(())
+}
+
+/// Quote a space-separated pattern for use in a shell case statement,
+/// leaving a trailing wildcard unquoted
+fn quote_glob(pattern: &str) -> String {
+	if let Some(pattern) = pattern.strip_suffix('*') {
+		format!("\"{pattern}\"*")
+	} else {
+		format!("\"{pattern}\"")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use mcvm::plugin::hooks::SubcommandDescription;
+
+	use super::super::add_plugin_subcommands;
+	use super::*;
+
+	#[test]
+	fn test_dynamic_value_paths() {
+		let cmd = Cli::command();
+		for value in DYNAMIC_VALUES {
+			assert!(!get_patterns(&cmd, value).is_empty());
+		}
+	}
+
+	#[test]
+	fn test_plugin_subcommand_completion() {
+		let subcommands = [SubcommandDescription {
+			name: "backup".into(),
+			aliases: vec!["back".into()],
+			about: "Manage backups".into(),
+		}];
+		let cmd = add_plugin_subcommands(Cli::command(), &subcommands);
+		let script = generate(Shell::Fish, cmd).unwrap();
+		assert!(script.contains("backup"));
+		assert!(script.contains("Manage backups"));
+	}
+}
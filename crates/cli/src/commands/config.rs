@@ -1,19 +1,22 @@
 use super::CmdData;
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use clap::Subcommand;
+use color_print::cprintln;
 use mcvm::config::{plugin::PluginManager, Config};
 
 use std::{path::PathBuf, process::Command};
 
 #[derive(Debug, Subcommand)]
-pub enum ConfigSubcommand {
+pub(crate) enum ConfigSubcommand {
 	#[command(about = "Edit config using your default text editor")]
 	Edit,
 	#[command(about = "Edit plugin config using your default text editor")]
 	EditPlugins,
 	#[command(about = "Backup configuration files to identical copies")]
 	Backup,
+	#[command(about = "Check that the config can be loaded")]
+	Validate,
 }
 
 pub async fn run(subcommand: ConfigSubcommand, data: &mut CmdData) -> anyhow::Result<()> {
@@ -21,6 +24,7 @@ pub async fn run(subcommand: ConfigSubcommand, data: &mut CmdData) -> anyhow::Re
 		ConfigSubcommand::Edit => edit(data).await,
 		ConfigSubcommand::EditPlugins => edit_plugins(data).await,
 		ConfigSubcommand::Backup => backup(data).await,
+		ConfigSubcommand::Validate => validate(data).await,
 	}
 }
 
@@ -62,6 +66,21 @@ async fn backup(data: &mut CmdData) -> anyhow::Result<()> {
 	Ok(())
 }
 
+async fn validate(data: &mut CmdData) -> anyhow::Result<()> {
+	let path = Config::get_path(&data.paths);
+	if !path.exists() {
+		bail!(
+			"There is no config at {}. Run `mcvm init` to create one",
+			path.display()
+		);
+	}
+
+	data.ensure_config(true).await?;
+	cprintln!("<g>Config is valid.");
+
+	Ok(())
+}
+
 /// Run the text editor on the user's system
 fn edit_text(path: PathBuf) -> anyhow::Result<()> {
 	#[cfg(target_os = "linux")]
@@ -0,0 +1,1252 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ChildStdin;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Local, Utc};
+use clap::Subcommand;
+use color_print::cprintln;
+use mcvm::config::daemon::{ScheduleAction, ScheduleConfig, SupervisedInstanceConfig};
+use mcvm::daemon::protocol::{
+	DaemonRequest, DaemonResponse, InstanceState, InstanceStatus, ScheduleStatus,
+};
+use mcvm::daemon::reload::{diff_daemon_configs, DaemonConfigDiff};
+use mcvm::daemon::restart::RestartTracker;
+use mcvm::daemon::schedule::Schedule;
+use mcvm::daemon::{get_log_dir, get_socket_path};
+use mcvm::instance::crash::CrashSummary;
+use mcvm::instance::launch::InstanceHandle;
+use mcvm::instance::snapshot::backup_instance;
+use mcvm::io::logging::{tail_log, RotatingLog, DEFAULT_MAX_LOG_FILES, DEFAULT_MAX_LOG_SIZE};
+use mcvm::shared::id::InstanceID;
+use mcvm::shared::output::{MessageLevel, Simple};
+use mcvm::shared::Side;
+use tokio::io::{
+	AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader as AsyncBufReader,
+};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use super::instance::launch_instance;
+use super::CmdData;
+use crate::output::HYPHEN_POINT;
+
+/// How long a server has to shut down after being asked to stop before it is killed
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often the daemon checks on its instances
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait for a client to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A request read from a client, along with where to send its response
+type PendingRequest = (DaemonRequest, oneshot::Sender<DaemonResponse>);
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum DaemonSubcommand {
+	#[command(about = "Get the status of supervised instances")]
+	Status {
+		/// Whether to remove formatting from the output
+		#[arg(short, long)]
+		raw: bool,
+	},
+	#[command(about = "Start a supervised instance")]
+	Start {
+		/// The instance to start
+		instance: String,
+	},
+	#[command(about = "Stop a supervised instance")]
+	Stop {
+		/// The instance to stop
+		instance: String,
+	},
+	#[command(about = "Restart a supervised instance")]
+	Restart {
+		/// The instance to restart
+		instance: String,
+	},
+	#[command(about = "Print the latest output of a supervised instance")]
+	Log {
+		/// The instance to print the log of
+		instance: String,
+		/// The number of lines to print
+		#[arg(short = 'n', long, default_value_t = 50)]
+		lines: usize,
+	},
+	#[command(about = "List scheduled actions and when they will next run")]
+	Schedules {
+		/// Whether to remove formatting from the output
+		#[arg(short, long)]
+		raw: bool,
+	},
+	#[command(
+		about = "Reload the config of the running daemon without stopping unchanged instances"
+	)]
+	Reload,
+}
+
+pub async fn run(subcommand: DaemonSubcommand, data: &mut CmdData) -> anyhow::Result<()> {
+	match subcommand {
+		DaemonSubcommand::Status { raw } => status(data, raw).await,
+		DaemonSubcommand::Start { instance } => {
+			send_ok(
+				data,
+				DaemonRequest::Start {
+					instance: instance.into(),
+				},
+			)
+			.await
+		}
+		DaemonSubcommand::Stop { instance } => {
+			send_ok(
+				data,
+				DaemonRequest::Stop {
+					instance: instance.into(),
+				},
+			)
+			.await
+		}
+		DaemonSubcommand::Restart { instance } => {
+			send_ok(
+				data,
+				DaemonRequest::Restart {
+					instance: instance.into(),
+				},
+			)
+			.await
+		}
+		DaemonSubcommand::Log { instance, lines } => log(data, instance, lines).await,
+		DaemonSubcommand::Schedules { raw } => schedules(data, raw).await,
+		DaemonSubcommand::Reload => reload(data).await,
+	}
+}
+
+async fn status(data: &mut CmdData, raw: bool) -> anyhow::Result<()> {
+	let response = send_request(data, DaemonRequest::Status).await?;
+	let DaemonResponse::Status { instances } = response else {
+		bail!("Unexpected response from daemon");
+	};
+
+	for instance in instances {
+		let state = match instance.state {
+			InstanceState::Running => "running",
+			InstanceState::Stopping => "stopping",
+			InstanceState::WaitingToRestart => "waiting to restart",
+			InstanceState::Stopped => "stopped",
+		};
+		if raw {
+			println!("{} {state}", instance.id);
+			continue;
+		}
+
+		match instance.state {
+			InstanceState::Running => {
+				cprintln!("{}<b!>{}</> <g>{}", HYPHEN_POINT, instance.id, state)
+			}
+			InstanceState::Stopping | InstanceState::WaitingToRestart => {
+				cprintln!("{}<b!>{}</> <y>{}", HYPHEN_POINT, instance.id, state)
+			}
+			InstanceState::Stopped => {
+				cprintln!("{}<b!>{}</> <r>{}", HYPHEN_POINT, instance.id, state)
+			}
+		}
+		if let Some(pid) = instance.pid {
+			cprintln!("   <s>PID:</s> {}", pid);
+		}
+		if instance.restarts > 0 {
+			cprintln!("   <s>Restarts:</s> {}", instance.restarts);
+		}
+		if let Some(code) = instance.last_exit_code {
+			cprintln!("   <s>Last exit code:</s> {}", code);
+		}
+		if let Some(restart_in) = instance.restart_in {
+			cprintln!("   <s>Restarting in:</s> {}s", restart_in);
+		}
+		if instance.crash_looping {
+			cprintln!(
+				"   <r>Crash looping ({} failures in a row)",
+				instance.consecutive_failures
+			);
+		}
+		if let Some(error) = instance.last_error {
+			cprintln!("   <s>Last error:</s> <r>{}", error);
+		}
+	}
+
+	Ok(())
+}
+
+async fn log(data: &mut CmdData, instance: String, lines: usize) -> anyhow::Result<()> {
+	let request = DaemonRequest::TailLog {
+		instance: instance.into(),
+		lines,
+	};
+	let response = send_request(data, request).await?;
+	let DaemonResponse::Log { lines } = response else {
+		bail!("Unexpected response from daemon");
+	};
+	for line in lines {
+		println!("{line}");
+	}
+
+	Ok(())
+}
+
+async fn schedules(data: &mut CmdData, raw: bool) -> anyhow::Result<()> {
+	let response = send_request(data, DaemonRequest::Schedules).await?;
+	let DaemonResponse::Schedules { schedules } = response else {
+		bail!("Unexpected response from daemon");
+	};
+
+	for schedule in schedules {
+		let next_run = schedule.next_run.map(format_time);
+		if raw {
+			println!("{} {}", schedule.id, next_run.as_deref().unwrap_or("never"));
+			continue;
+		}
+
+		cprintln!(
+			"{}<b!>{}</> <k!>({} on {})",
+			HYPHEN_POINT,
+			schedule.id,
+			schedule.action,
+			schedule.instance
+		);
+		cprintln!("   <s>Cron:</s> {}", schedule.cron);
+		match next_run {
+			Some(next_run) => cprintln!("   <s>Next run:</s> <g>{}", next_run),
+			None => cprintln!("   <s>Next run:</s> <r>never"),
+		}
+		if let Some(last_run) = schedule.last_run {
+			cprintln!("   <s>Last run:</s> {}", format_time(last_run));
+		}
+		if schedule.running {
+			cprintln!("   <y>Running");
+		}
+		if let Some(error) = schedule.last_error {
+			cprintln!("   <s>Last error:</s> <r>{}", error);
+		}
+	}
+
+	Ok(())
+}
+
+async fn reload(data: &mut CmdData) -> anyhow::Result<()> {
+	let response = send_request(data, DaemonRequest::Reload).await?;
+	let DaemonResponse::Reloaded { diff } = response else {
+		bail!("Unexpected response from daemon");
+	};
+
+	if diff.is_empty() {
+		cprintln!("<g>Config reloaded. No supervised instances or schedules changed.");
+		return Ok(());
+	}
+	cprintln!("<g>Config reloaded.");
+	for line in describe_diff(&diff) {
+		cprintln!("{}{}", HYPHEN_POINT, line);
+	}
+
+	Ok(())
+}
+
+/// Describe the changes from a config reload, with one line for each kind of change
+fn describe_diff(diff: &DaemonConfigDiff) -> Vec<String> {
+	let lists = [
+		("Started supervising", join_ids(&diff.added_instances)),
+		("Stopped supervising", join_ids(&diff.removed_instances)),
+		("Updated supervision of", join_ids(&diff.changed_instances)),
+		("Added schedules", diff.added_schedules.join(", ")),
+		("Removed schedules", diff.removed_schedules.join(", ")),
+		("Updated schedules", diff.changed_schedules.join(", ")),
+	];
+	lists
+		.into_iter()
+		.filter(|(_, list)| !list.is_empty())
+		.map(|(label, list)| format!("{label}: {list}"))
+		.collect()
+}
+
+/// Join a list of instance IDs with commas
+fn join_ids(ids: &[InstanceID]) -> String {
+	ids.iter()
+		.map(|x| x.to_string())
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+/// Format a time in the local time zone
+fn format_time(time: DateTime<Utc>) -> String {
+	time.with_timezone(&Local)
+		.format("%Y-%m-%d %H:%M:%S")
+		.to_string()
+}
+
+/// Send a request to the daemon that should just succeed
+async fn send_ok(data: &mut CmdData, request: DaemonRequest) -> anyhow::Result<()> {
+	send_request(data, request).await?;
+	cprintln!("<g>Done.");
+	Ok(())
+}
+
+/// Send a request to the running daemon and get its response
+async fn send_request(
+	data: &mut CmdData,
+	request: DaemonRequest,
+) -> anyhow::Result<DaemonResponse> {
+	let path = get_socket_path(&data.paths);
+	let stream = connect(&path)
+		.await
+		.context("Failed to connect to the daemon. Is `mcvm serve` running?")?;
+	let (read, mut write) = tokio::io::split(stream);
+
+	write
+		.write_all(request.to_line()?.as_bytes())
+		.await
+		.context("Failed to send request to daemon")?;
+
+	let mut line = String::new();
+	AsyncBufReader::new(read)
+		.read_line(&mut line)
+		.await
+		.context("Failed to read response from daemon")?;
+
+	match DaemonResponse::from_line(&line)? {
+		DaemonResponse::Error { message } => bail!("Daemon returned an error: {message}"),
+		response => Ok(response),
+	}
+}
+
+/// Run the daemon
+pub async fn serve(data: &mut CmdData) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+	let mut daemon = Daemon::new(data)?;
+
+	let path = get_socket_path(&data.paths);
+	let mut listener = Listener::bind(&path).context("Failed to start listening for commands")?;
+	let mut reload_signal = DaemonSignal::reload().context("Failed to listen for reload signal")?;
+	let mut terminate_signal =
+		DaemonSignal::terminate().context("Failed to listen for shutdown signal")?;
+	cprintln!("<s>Listening on <b>{}", path.display());
+
+	daemon.autostart(data).await;
+
+	// Each connection is handled in its own task so that a slow client can't hold up the
+	// daemon. The requests are sent back here since only this loop can access the daemon state
+	let (request_tx, mut request_rx) = mpsc::unbounded_channel::<PendingRequest>();
+
+	// The interval keeps its schedule across loop iterations, so a steady stream of
+	// requests can't keep the tick from running
+	let mut tick = tokio::time::interval(TICK_INTERVAL);
+	tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+	loop {
+		tokio::select! {
+			stream = listener.accept() => {
+				match stream {
+					Ok(stream) => {
+						let request_tx = request_tx.clone();
+						tokio::spawn(async move {
+							if let Err(e) = handle_connection(stream, request_tx).await {
+								cprintln!("<r>Failed to handle daemon request: {:?}", e);
+							}
+						});
+					}
+					Err(e) => cprintln!("<r>Failed to accept connection: {:?}", e),
+				}
+			}
+			Some((request, respond)) = request_rx.recv() => {
+				let response = daemon
+					.handle_request(request, data)
+					.await
+					.unwrap_or_else(DaemonResponse::error);
+				// The client may have disconnected already
+				let _ = respond.send(response);
+			}
+			_ = tick.tick() => {
+				daemon.tick(data).await;
+			}
+			_ = reload_signal.recv(), if !daemon.shutting_down => {
+				cprintln!("<s>Reloading config...");
+				if let Err(e) = daemon.reload(data).await {
+					cprintln!("<r>Failed to reload config, so the old one is still being used: {:?}", e);
+				}
+			}
+			result = tokio::signal::ctrl_c(), if !daemon.shutting_down => {
+				result.context("Failed to listen for shutdown signal")?;
+				daemon.shut_down(data);
+			}
+			_ = terminate_signal.recv(), if !daemon.shutting_down => {
+				daemon.shut_down(data);
+			}
+		}
+
+		if daemon.shutting_down && !daemon.is_any_running() {
+			break;
+		}
+	}
+
+	listener.close();
+
+	Ok(())
+}
+
+/// State for the daemon
+struct Daemon {
+	instances: HashMap<InstanceID, SupervisedInstance>,
+	schedules: HashMap<String, ScheduledAction>,
+	shutting_down: bool,
+}
+
+/// State for an instance supervised by the daemon
+struct SupervisedInstance {
+	config: SupervisedInstanceConfig,
+	tracker: RestartTracker,
+	handle: Option<InstanceHandle>,
+	stdin: Option<ChildStdin>,
+	/// Log of events for the instance, such as starts and stops
+	event_log: RotatingLog,
+	/// When the instance will be killed if it has not stopped yet
+	stop_deadline: Option<Instant>,
+	/// Whether to start the instance again once it has stopped
+	start_after_stop: bool,
+	/// Whether the instance was removed from the config and should stop being supervised once it stops
+	removed: bool,
+	last_exit_code: Option<i32>,
+	last_error: Option<String>,
+}
+
+/// State for an action that the daemon runs on a schedule
+struct ScheduledAction {
+	config: ScheduleConfig,
+	schedule: Schedule,
+	next_run: Option<DateTime<Utc>>,
+	last_run: Option<DateTime<Utc>>,
+	/// The run of the schedule that is still in progress, if any
+	run: Option<ScheduleRun>,
+	last_error: Option<String>,
+}
+
+/// A run of a schedule that has not finished yet
+enum ScheduleRun {
+	/// A backup running in the background
+	Backup(JoinHandle<anyhow::Result<String>>),
+	/// A restart that is waiting for the instance to stop
+	Restart,
+}
+
+impl Daemon {
+	fn new(data: &CmdData) -> anyhow::Result<Self> {
+		let config = data.config.get();
+		let log_dir = get_log_dir(&data.paths);
+
+		let mut instances = HashMap::new();
+		for (id, instance_config) in &config.daemon.instances {
+			let instance = SupervisedInstance::new(id, instance_config.clone(), &log_dir)?;
+			instances.insert(id.clone(), instance);
+		}
+
+		// Schedules only run at times after the daemon starts, so any runs that were missed
+		// while it was down are skipped
+		let now = Utc::now();
+		let mut schedules = HashMap::new();
+		for (id, schedule_config) in &config.daemon.schedules {
+			let schedule = ScheduledAction::new(id, schedule_config.clone(), now)?;
+			schedules.insert(id.clone(), schedule);
+		}
+
+		Ok(Self {
+			instances,
+			schedules,
+			shutting_down: false,
+		})
+	}
+
+	/// Start all of the instances that should be started with the daemon
+	async fn autostart(&mut self, data: &mut CmdData) {
+		let ids: Vec<_> = self
+			.instances
+			.iter()
+			.filter(|(_, x)| x.config.autostart)
+			.map(|(id, _)| id.clone())
+			.collect();
+		for id in ids {
+			// Failures are recorded in the status and retried by the restart policy
+			let _ = self.start(&id, data).await;
+		}
+	}
+
+	/// Check on all of the instances, handling exits, stop timeouts, and restarts
+	async fn tick(&mut self, data: &mut CmdData) {
+		let ids: Vec<_> = self.instances.keys().cloned().collect();
+		for id in ids {
+			let now = Instant::now();
+			let instance = self.instances.get_mut(&id).expect("Instance should exist");
+
+			if let Some(handle) = &mut instance.handle {
+				let status = match handle.try_wait() {
+					Ok(status) => status,
+					Err(e) => {
+						cprintln!("<r>Failed to check on instance '{}': {:?}", id, e);
+						continue;
+					}
+				};
+
+				if let Some(status) = status {
+					let handle = instance.handle.take().expect("Handle should exist");
+					let launched_at = handle.get_launch_time();
+					instance.stdin = None;
+					let requested = instance.stop_deadline.take().is_some();
+					instance.last_exit_code = status.code();
+					if let Err(e) =
+						handle.wait(&data.config.get().plugins, &data.paths, &mut data.output)
+					{
+						instance.last_error = Some(format!("{e:?}"));
+					}
+					instance.log_event(&format!("Instance exited with {status}"));
+					if !status.success() && !requested {
+						match report_crash(&id, status.code(), launched_at, data).await {
+							Ok(Some(summary)) => instance.log_event(&format!(
+								"Crash information was saved to {}",
+								summary.bundle_path
+							)),
+							Ok(None) => {}
+							Err(e) => {
+								cprintln!("<r>Failed to report crash of instance '{}': {:?}", id, e)
+							}
+						}
+					}
+
+					let delay = instance.tracker.on_exit(now, status.success(), requested);
+					match delay {
+						Some(delay) if !self.shutting_down => {
+							cprintln!(
+								"<y>Instance '{}' exited with {}, restarting in {}s",
+								id,
+								status,
+								delay.as_secs()
+							);
+						}
+						_ => {
+							instance.tracker.cancel_restart();
+							cprintln!("<s>Instance '{}' exited with {}", id, status);
+						}
+					}
+				} else if instance.stop_deadline.is_some_and(|x| now >= x) {
+					cprintln!("<y>Instance '{}' did not stop in time, killing it", id);
+					instance.kill(data);
+				}
+			}
+
+			let instance = self.instances.get_mut(&id).expect("Instance should exist");
+			if instance.removed && instance.handle.is_none() {
+				cprintln!("<s>Instance '{}' is no longer supervised", id);
+				self.instances.remove(&id);
+				continue;
+			}
+			if instance.handle.is_none() && !self.shutting_down {
+				if instance.start_after_stop {
+					instance.start_after_stop = false;
+					let _ = self.start(&id, data).await;
+				} else if instance.tracker.should_restart(now) {
+					let _ = self.start(&id, data).await;
+				}
+			}
+		}
+
+		self.run_schedules(data).await;
+	}
+
+	/// Check on schedules that are running and start the ones that are due
+	async fn run_schedules(&mut self, data: &mut CmdData) {
+		let now = Utc::now();
+		let ids: Vec<_> = self.schedules.keys().cloned().collect();
+		for id in ids {
+			self.check_schedule_run(&id).await;
+
+			let schedule = self.schedules.get_mut(&id).expect("Schedule should exist");
+			if !schedule.next_run.is_some_and(|x| now >= x) {
+				continue;
+			}
+			schedule.next_run = schedule.schedule.next_run(now);
+			if self.shutting_down {
+				continue;
+			}
+			// Don't let runs of the same schedule overlap
+			if schedule.run.is_some() {
+				cprintln!(
+					"<y>Skipping run of schedule '{}' because the last one is still in progress",
+					id
+				);
+				continue;
+			}
+			schedule.last_run = Some(now);
+
+			let result = self.run_schedule(&id, data).await;
+			let schedule = self.schedules.get_mut(&id).expect("Schedule should exist");
+			match result {
+				Ok(run) => {
+					schedule.run = run;
+					schedule.last_error = None;
+				}
+				Err(e) => {
+					cprintln!("<r>Failed to run schedule '{}': {:?}", id, e);
+					schedule.last_error = Some(format!("{e:?}"));
+				}
+			}
+		}
+	}
+
+	/// Start running the action for a schedule
+	async fn run_schedule(
+		&mut self,
+		id: &str,
+		data: &mut CmdData,
+	) -> anyhow::Result<Option<ScheduleRun>> {
+		let config = self.schedules[id].config.clone();
+		let instance_id = &config.instance;
+		cprintln!(
+			"<s>Running schedule '{}': {} on instance '{}'",
+			id,
+			config.action,
+			instance_id
+		);
+		if let Some(instance) = self.instances.get_mut(instance_id) {
+			instance.log_event(&format!("Running schedule '{id}': {}", config.action));
+		}
+
+		match &config.action {
+			ScheduleAction::Backup { group } => {
+				let instance = data
+					.config
+					.get_mut()
+					.instances
+					.get_mut(instance_id)
+					.with_context(|| format!("Instance '{instance_id}' does not exist"))?;
+				let arg = instance.get_backup_arg(group.clone(), &data.paths)?;
+				let plugins = data.config.get().plugins.clone();
+				let paths = data.paths.clone();
+				let handle = tokio::task::spawn_blocking(move || {
+					backup_instance(&arg, &plugins, &paths, &mut Simple(MessageLevel::Important))
+				});
+				Ok(Some(ScheduleRun::Backup(handle)))
+			}
+			ScheduleAction::Restart => {
+				self.restart(instance_id, data).await?;
+				Ok(Some(ScheduleRun::Restart))
+			}
+			ScheduleAction::Command { command } => {
+				self.send_command(instance_id, command)?;
+				Ok(None)
+			}
+		}
+	}
+
+	/// Finish the run of a schedule if it is done
+	async fn check_schedule_run(&mut self, id: &str) {
+		let schedule = self.schedules.get_mut(id).expect("Schedule should exist");
+		let finished = match &schedule.run {
+			Some(ScheduleRun::Backup(handle)) => handle.is_finished(),
+			Some(ScheduleRun::Restart) => self
+				.instances
+				.get(&schedule.config.instance)
+				.map_or(true, |x| !x.start_after_stop && x.stop_deadline.is_none()),
+			None => false,
+		};
+		if !finished {
+			return;
+		}
+
+		let Some(ScheduleRun::Backup(handle)) = schedule.run.take() else {
+			return;
+		};
+		let result = handle.await.context("Backup task panicked").and_then(|x| x);
+		match result {
+			Ok(backup_id) => {
+				cprintln!("<g>Schedule '{}' created backup '{}'", id, backup_id);
+			}
+			Err(e) => {
+				cprintln!("<r>Schedule '{}' failed to create backup: {:?}", id, e);
+				schedule.last_error = Some(format!("{e:?}"));
+			}
+		}
+	}
+
+	/// Write a command to the console of a running instance
+	fn send_command(&mut self, id: &InstanceID, command: &str) -> anyhow::Result<()> {
+		let instance = self
+			.instances
+			.get_mut(id)
+			.with_context(|| format!("Instance '{id}' is not supervised by the daemon"))?;
+		let Some(stdin) = &mut instance.stdin else {
+			bail!("Instance '{id}' is not running");
+		};
+		writeln!(stdin, "{command}")
+			.and_then(|_| stdin.flush())
+			.context("Failed to write command to instance")?;
+
+		Ok(())
+	}
+
+	/// Get the status of all schedules
+	fn get_schedule_status(&self) -> Vec<ScheduleStatus> {
+		let mut out: Vec<_> = self
+			.schedules
+			.iter()
+			.map(|(id, schedule)| ScheduleStatus {
+				id: id.clone(),
+				instance: schedule.config.instance.clone(),
+				action: schedule.config.action.to_string(),
+				cron: schedule.config.cron.clone(),
+				next_run: schedule.next_run,
+				last_run: schedule.last_run,
+				running: schedule.run.is_some(),
+				last_error: schedule.last_error.clone(),
+			})
+			.collect();
+		out.sort_by(|a, b| a.id.cmp(&b.id));
+
+		out
+	}
+
+	/// Launch a supervised instance
+	async fn start(&mut self, id: &InstanceID, data: &mut CmdData) -> anyhow::Result<()> {
+		if self.shutting_down {
+			bail!("The daemon is shutting down");
+		}
+		let instance = self
+			.instances
+			.get_mut(id)
+			.with_context(|| format!("Instance '{id}' is not supervised by the daemon"))?;
+		if instance.handle.is_some() {
+			bail!("Instance '{id}' is already running");
+		}
+
+		cprintln!("<s>Starting instance '{}'", id);
+		instance.log_event("Starting instance");
+		let now = Instant::now();
+		let result = launch_instance(
+			id,
+			data.config.get_mut(),
+			&data.paths,
+			None,
+			false,
+			true,
+			false,
+			Default::default(),
+			None,
+			true,
+			None,
+			&mut data.output,
+		)
+		.await;
+
+		let mut handle = match result {
+			Ok(handle) => handle,
+			Err(e) => {
+				let message = format!("{e:?}");
+				cprintln!("<r>Failed to launch instance '{}': {}", id, message);
+				instance.log_event(&format!("Failed to launch instance: {message}"));
+				instance.last_error = Some(message);
+				instance.tracker.on_launch_failure(now);
+				return Err(e);
+			}
+		};
+
+		instance.stdin = handle.take_stdin();
+		instance.handle = Some(handle);
+		instance.last_error = None;
+		instance.tracker.on_start(now);
+
+		Ok(())
+	}
+
+	/// Ask a supervised instance to stop. Servers are given time to shut down cleanly
+	fn stop(&mut self, id: &InstanceID, data: &mut CmdData) -> anyhow::Result<()> {
+		let instance = self
+			.instances
+			.get_mut(id)
+			.with_context(|| format!("Instance '{id}' is not supervised by the daemon"))?;
+		instance.tracker.cancel_restart();
+		instance.start_after_stop = false;
+		if instance.handle.is_none() || instance.stop_deadline.is_some() {
+			return Ok(());
+		}
+
+		cprintln!("<s>Stopping instance '{}'", id);
+		instance.log_event("Stopping instance");
+		let side = data
+			.config
+			.get()
+			.instances
+			.get(id)
+			.map(|x| x.get_side())
+			.unwrap_or(Side::Client);
+
+		let sent_stop = match (side, &mut instance.stdin) {
+			(Side::Server, Some(stdin)) => stdin
+				.write_all(b"stop\n")
+				.and_then(|_| stdin.flush())
+				.is_ok(),
+			_ => false,
+		};
+		// If the stop command couldn't be sent, ask the process to exit before killing it
+		let sent_stop = sent_stop
+			|| instance
+				.handle
+				.as_mut()
+				.is_some_and(|x| x.terminate().is_ok());
+		if sent_stop {
+			instance.stop_deadline = Some(Instant::now() + STOP_TIMEOUT);
+		} else {
+			instance.kill(data);
+		}
+
+		Ok(())
+	}
+
+	/// Start stopping all of the instances so that the daemon can exit once they have stopped
+	fn shut_down(&mut self, data: &mut CmdData) {
+		cprintln!("<s>Stopping all instances...");
+		self.shutting_down = true;
+		self.stop_all(data);
+	}
+
+	/// Stop all running instances
+	fn stop_all(&mut self, data: &mut CmdData) {
+		let ids: Vec<_> = self.instances.keys().cloned().collect();
+		for id in ids {
+			let _ = self.stop(&id, data);
+		}
+	}
+
+	/// Stop an instance and start it again once it has exited
+	async fn restart(&mut self, id: &InstanceID, data: &mut CmdData) -> anyhow::Result<()> {
+		self.stop(id, data)?;
+		let instance = self.instances.get_mut(id).expect("Instance should exist");
+		if instance.handle.is_some() {
+			instance.start_after_stop = true;
+			Ok(())
+		} else {
+			self.start(id, data).await
+		}
+	}
+
+	/// Load the config again and apply the changes to supervised instances and schedules.
+	/// Instances whose supervision config didn't change are left alone. If the new config is
+	/// invalid, nothing is changed and the old config stays in use
+	async fn reload(&mut self, data: &mut CmdData) -> anyhow::Result<DaemonConfigDiff> {
+		if self.shutting_down {
+			bail!("The daemon is shutting down");
+		}
+		let new_config = data
+			.load_config(true)
+			.context("The new config is invalid, so the old one is still being used")?;
+		let diff = diff_daemon_configs(&data.config.get().daemon, &new_config.daemon);
+
+		// Prepare everything that can fail before anything is changed
+		let log_dir = get_log_dir(&data.paths);
+		let mut added_instances = Vec::new();
+		for id in &diff.added_instances {
+			let config = new_config.daemon.instances[id].clone();
+			// An instance that was removed and then added back may still be stopping
+			let instance = if self.instances.contains_key(id) {
+				None
+			} else {
+				Some(SupervisedInstance::new(id, config.clone(), &log_dir)?)
+			};
+			added_instances.push((id.clone(), config, instance));
+		}
+		let now = Utc::now();
+		let mut new_schedules = Vec::new();
+		for id in diff.added_schedules.iter().chain(&diff.changed_schedules) {
+			let config = new_config.daemon.schedules[id].clone();
+			new_schedules.push((id.clone(), ScheduledAction::new(id, config, now)?));
+		}
+
+		// Removed instances are stopped before the config is replaced, since stopping
+		// them depends on their instance config
+		for id in &diff.removed_instances {
+			self.stop(id, data)?;
+			let instance = self.instances.get_mut(id).expect("Instance should exist");
+			instance.removed = true;
+			instance.log_event("Instance was removed from the config");
+		}
+		data.config.fill(new_config);
+
+		for id in &diff.changed_instances {
+			let config = data.config.get().daemon.instances[id].clone();
+			let instance = self.instances.get_mut(id).expect("Instance should exist");
+			instance.tracker.set_config(config.clone());
+			instance.config = config;
+			instance.log_event("Supervision config was changed");
+		}
+		for (id, config, instance) in added_instances {
+			let Some(instance) = instance else {
+				let instance = self.instances.get_mut(&id).expect("Instance should exist");
+				instance.removed = false;
+				instance.start_after_stop = config.autostart;
+				instance.tracker.set_config(config.clone());
+				instance.config = config;
+				continue;
+			};
+			self.instances.insert(id.clone(), instance);
+			if config.autostart {
+				// Failures are recorded in the status and retried by the restart policy
+				let _ = self.start(&id, data).await;
+			}
+		}
+
+		for id in &diff.removed_schedules {
+			self.schedules.remove(id);
+		}
+		for (id, mut schedule) in new_schedules {
+			// Keep the history of schedules that were changed
+			if let Some(old) = self.schedules.remove(&id) {
+				schedule.last_run = old.last_run;
+				schedule.run = old.run;
+				schedule.last_error = old.last_error;
+			}
+			self.schedules.insert(id, schedule);
+		}
+
+		for line in describe_diff(&diff) {
+			cprintln!("<s>{}", line);
+		}
+
+		Ok(diff)
+	}
+
+	/// Check whether any instances are still running
+	fn is_any_running(&self) -> bool {
+		self.instances.values().any(|x| x.handle.is_some())
+	}
+
+	/// Get the status of all instances
+	fn get_status(&self) -> Vec<InstanceStatus> {
+		let now = Instant::now();
+		let mut out: Vec<_> = self
+			.instances
+			.iter()
+			.map(|(id, instance)| {
+				let restart_in = instance.tracker.get_time_until_restart(now);
+				let state = if instance.handle.is_some() {
+					if instance.stop_deadline.is_some() {
+						InstanceState::Stopping
+					} else {
+						InstanceState::Running
+					}
+				} else if restart_in.is_some() {
+					InstanceState::WaitingToRestart
+				} else {
+					InstanceState::Stopped
+				};
+
+				InstanceStatus {
+					id: id.clone(),
+					state,
+					pid: instance.handle.as_ref().map(|x| x.get_pid()),
+					restarts: instance.tracker.get_restarts(),
+					consecutive_failures: instance.tracker.get_consecutive_failures(),
+					crash_looping: instance.tracker.is_crash_looping(),
+					last_exit_code: instance.last_exit_code,
+					restart_in: restart_in.map(|x| x.as_secs()),
+					last_error: instance.last_error.clone(),
+				}
+			})
+			.collect();
+		out.sort_by(|a, b| a.id.cmp(&b.id));
+
+		out
+	}
+
+	/// Respond to a single request
+	async fn handle_request(
+		&mut self,
+		request: DaemonRequest,
+		data: &mut CmdData,
+	) -> anyhow::Result<DaemonResponse> {
+		match request {
+			DaemonRequest::Status => Ok(DaemonResponse::Status {
+				instances: self.get_status(),
+			}),
+			DaemonRequest::Start { instance } => {
+				self.start(&instance, data).await?;
+				Ok(DaemonResponse::Ok)
+			}
+			DaemonRequest::Stop { instance } => {
+				self.stop(&instance, data)?;
+				Ok(DaemonResponse::Ok)
+			}
+			DaemonRequest::Restart { instance } => {
+				self.restart(&instance, data).await?;
+				Ok(DaemonResponse::Ok)
+			}
+			DaemonRequest::TailLog { instance, lines } => {
+				if !self.instances.contains_key(&instance) {
+					bail!("Instance '{instance}' is not supervised by the daemon");
+				}
+				let path = data
+					.config
+					.get_mut()
+					.instances
+					.get_mut(&instance)
+					.with_context(|| format!("Instance '{instance}' does not exist"))?
+					.get_output_log_path(&data.paths)?;
+				let lines = tail_log(&path, lines)?;
+				Ok(DaemonResponse::Log { lines })
+			}
+			DaemonRequest::Schedules => Ok(DaemonResponse::Schedules {
+				schedules: self.get_schedule_status(),
+			}),
+			DaemonRequest::Reload => Ok(DaemonResponse::Reloaded {
+				diff: self.reload(data).await?,
+			}),
+		}
+	}
+}
+
+impl SupervisedInstance {
+	/// Set up supervision for an instance, opening its event log
+	fn new(
+		id: &InstanceID,
+		config: SupervisedInstanceConfig,
+		log_dir: &std::path::Path,
+	) -> anyhow::Result<Self> {
+		let log_path = log_dir.join(format!("{id}.log"));
+		let event_log = RotatingLog::open(&log_path, DEFAULT_MAX_LOG_SIZE, DEFAULT_MAX_LOG_FILES)
+			.with_context(|| format!("Failed to open log for instance '{id}'"))?;
+
+		Ok(Self {
+			tracker: RestartTracker::new(config.clone()),
+			config,
+			handle: None,
+			stdin: None,
+			event_log,
+			stop_deadline: None,
+			start_after_stop: false,
+			removed: false,
+			last_exit_code: None,
+			last_error: None,
+		})
+	}
+
+	/// Kill the instance process immediately
+	fn kill(&mut self, data: &mut CmdData) {
+		let Some(handle) = self.handle.take() else {
+			return;
+		};
+		self.stdin = None;
+		self.stop_deadline = None;
+		if let Err(e) = handle.kill(&data.config.get().plugins, &data.paths, &mut data.output) {
+			self.last_error = Some(format!("{e:?}"));
+		}
+		self.log_event("Instance was killed");
+		self.tracker.on_exit(Instant::now(), false, true);
+	}
+
+	/// Write a message to the instance's event log
+	fn log_event(&mut self, message: &str) {
+		let time = format_time(Utc::now());
+		let _ = self.event_log.write_line(&format!("[{time}] {message}"));
+	}
+}
+
+impl ScheduledAction {
+	/// Parse a schedule from its config, finding its next run after the given time
+	fn new(id: &str, config: ScheduleConfig, now: DateTime<Utc>) -> anyhow::Result<Self> {
+		let schedule =
+			Schedule::from_config(&config).with_context(|| format!("Invalid schedule '{id}'"))?;
+
+		Ok(Self {
+			next_run: schedule.next_run(now),
+			config,
+			schedule,
+			last_run: None,
+			run: None,
+			last_error: None,
+		})
+	}
+}
+
+/// Read a request from a client, pass it to the daemon, and write back its response
+async fn handle_connection(
+	stream: impl AsyncRead + AsyncWrite,
+	requests: mpsc::UnboundedSender<PendingRequest>,
+) -> anyhow::Result<()> {
+	let (read, mut write) = tokio::io::split(stream);
+	let mut line = String::new();
+	tokio::time::timeout(
+		REQUEST_TIMEOUT,
+		AsyncBufReader::new(read).read_line(&mut line),
+	)
+	.await
+	.context("Timed out waiting for request")?
+	.context("Failed to read request")?;
+
+	let response = match DaemonRequest::from_line(&line) {
+		Ok(request) => {
+			let (respond, response) = oneshot::channel();
+			requests
+				.send((request, respond))
+				.ok()
+				.context("The daemon is no longer running")?;
+			response.await.context("The daemon did not respond")?
+		}
+		Err(e) => DaemonResponse::error(e),
+	};
+
+	write
+		.write_all(response.to_line()?.as_bytes())
+		.await
+		.context("Failed to write response")?;
+	write
+		.shutdown()
+		.await
+		.context("Failed to close connection")?;
+
+	Ok(())
+}
+
+/// Create a crash bundle and send notifications for a server that exited abnormally.
+/// Returns None if the instance is not a server
+async fn report_crash(
+	id: &InstanceID,
+	exit_code: Option<i32>,
+	launched_at: SystemTime,
+	data: &mut CmdData,
+) -> anyhow::Result<Option<CrashSummary>> {
+	let config = data.config.get_mut();
+	let instance = config
+		.instances
+		.get_mut(id)
+		.with_context(|| format!("Instance '{id}' does not exist"))?;
+	if instance.get_side() != Side::Server {
+		return Ok(None);
+	}
+
+	let summary = instance
+		.report_crash(
+			exit_code,
+			launched_at,
+			&config.prefs.crash_notification,
+			&data.paths,
+			&config.client,
+			&mut data.output,
+		)
+		.await?;
+
+	Ok(Some(summary))
+}
+
+#[cfg(not(windows))]
+type Stream = tokio::net::UnixStream;
+#[cfg(windows)]
+type Stream = tokio::net::windows::named_pipe::NamedPipeServer;
+
+/// Listens for connections from clients
+struct Listener {
+	#[cfg(not(windows))]
+	inner: tokio::net::UnixListener,
+	#[cfg(windows)]
+	inner: tokio::net::windows::named_pipe::NamedPipeServer,
+	path: PathBuf,
+}
+
+impl Listener {
+	/// Start listening at a socket path
+	fn bind(path: &std::path::Path) -> anyhow::Result<Self> {
+		#[cfg(not(windows))]
+		let inner = {
+			if let Some(parent) = path.parent() {
+				std::fs::create_dir_all(parent).context("Failed to create socket directory")?;
+			}
+			// Remove the socket left over from a previous run
+			if path.exists() {
+				if std::os::unix::net::UnixStream::connect(path).is_ok() {
+					bail!("The daemon is already running");
+				}
+				std::fs::remove_file(path).context("Failed to remove old socket")?;
+			}
+			tokio::net::UnixListener::bind(path).context("Failed to bind socket")?
+		};
+		#[cfg(windows)]
+		let inner = tokio::net::windows::named_pipe::ServerOptions::new()
+			.first_pipe_instance(true)
+			.create(path)
+			.context("Failed to create named pipe")?;
+
+		Ok(Self {
+			inner,
+			path: path.to_owned(),
+		})
+	}
+
+	/// Wait for a client to connect
+	async fn accept(&mut self) -> anyhow::Result<Stream> {
+		#[cfg(not(windows))]
+		{
+			let (stream, _) = self.inner.accept().await?;
+			Ok(stream)
+		}
+		#[cfg(windows)]
+		{
+			self.inner.connect().await?;
+			let next = tokio::net::windows::named_pipe::ServerOptions::new().create(&self.path)?;
+			Ok(std::mem::replace(&mut self.inner, next))
+		}
+	}
+
+	/// Stop listening and clean up the socket
+	fn close(self) {
+		#[cfg(not(windows))]
+		let _ = std::fs::remove_file(&self.path);
+	}
+}
+
+/// Waits for a Unix signal that is sent to the daemon. There are no such signals on Windows,
+/// so `mcvm daemon reload` and Ctrl-C have to be used there
+struct DaemonSignal {
+	#[cfg(not(windows))]
+	inner: tokio::signal::unix::Signal,
+}
+
+impl DaemonSignal {
+	/// Start listening for SIGHUP, which asks the daemon to reload its config
+	fn reload() -> anyhow::Result<Self> {
+		Ok(Self {
+			#[cfg(not(windows))]
+			inner: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
+		})
+	}
+
+	/// Start listening for SIGTERM, which service managers like systemd send to stop the daemon.
+	/// It stops the instances gracefully, the same as Ctrl-C
+	fn terminate() -> anyhow::Result<Self> {
+		Ok(Self {
+			#[cfg(not(windows))]
+			inner: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?,
+		})
+	}
+
+	/// Wait until the signal is received
+	async fn recv(&mut self) {
+		#[cfg(not(windows))]
+		self.inner.recv().await;
+		#[cfg(windows)]
+		std::future::pending::<()>().await;
+	}
+}
+
+/// Connect to the daemon
+async fn connect(path: &std::path::Path) -> anyhow::Result<impl AsyncRead + AsyncWrite> {
+	#[cfg(not(windows))]
+	{
+		Ok(tokio::net::UnixStream::connect(path).await?)
+	}
+	#[cfg(windows)]
+	{
+		Ok(tokio::net::windows::named_pipe::ClientOptions::new().open(path)?)
+	}
+}
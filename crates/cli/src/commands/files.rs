@@ -1,13 +1,17 @@
-use super::CmdData;
+use super::{CmdData, ExitStatus};
 
 use anyhow::Context;
 use clap::Subcommand;
-use color_print::cprintln;
+use color_print::{cprint, cprintln};
+use mcvm::api;
+use mcvm::core::net::game_files::assets::AssetLayout;
+use mcvm::doctor::{self, CheckStatus};
+use mcvm::io::paths::PathKind;
 
 use std::fs;
 
 #[derive(Debug, Subcommand)]
-pub enum FilesSubcommand {
+pub(crate) enum FilesSubcommand {
 	#[command(
 		about = "Remove cached files",
 		long_about = "Remove cached files downloaded by mcvm. This does not include
@@ -19,18 +23,81 @@ files in your instances or any other user data."
 		#[arg(short, long)]
 		data: bool,
 	},
+	#[command(
+		about = "Check for problems with mcvm's files and environment",
+		long_about = "Check for problems with mcvm's files and environment, such as unwritable
+directories, an invalid config or lockfile, Java installations that don't run, and broken plugins.
+Use --network to check which services can be reached through the configured proxy.
+Exits with code 1 if any check fails."
+	)]
+	Doctor {
+		/// Whether to check that the services mcvm uses are reachable
+		#[arg(long)]
+		network: bool,
+		/// Whether to print the results as JSON, for including in issue reports
+		#[arg(long)]
+		json: bool,
+	},
+	#[command(
+		about = "Move downloaded game files to the cache directory",
+		long_about = "Move downloaded game files like assets, libraries, and jars from the
+internal data directory to the cache directory. Data directories created by older
+versions of mcvm keep using the old locations until they are migrated."
+	)]
+	MigrateLayout,
+	#[command(
+		about = "Remove stored files that are no longer used",
+		long_about = "Remove stored Paper and Folia JARs for builds that none of your instances
+use anymore, including the builds in their update history. Builds are kept when a
+newer one comes out so that instances can share them until this is run. Asset objects
+that no installed asset index uses are removed as well."
+	)]
+	Clean {
+		/// Only print the files that would be removed
+		#[arg(long)]
+		dry_run: bool,
+	},
+	#[command(
+		about = "Show how game assets are shared between versions",
+		long_about = "Show how many asset objects each installed asset index uses and how many of them
+are shared with other indexes. Assets are stored once by their hash, so versions that use the same
+assets don't take up more space. Objects that no installed index uses anymore can be removed
+with files clean."
+	)]
+	Assets {
+		/// Whether to list the paths of the objects that no index uses
+		#[arg(long)]
+		orphans: bool,
+	},
+	#[command(
+		about = "Print the path to one of mcvm's directories",
+		long_about = "Print the absolute path to one of mcvm's directories and nothing else,
+so that it can be used in scripts. The directory might not exist yet."
+	)]
+	Path {
+		/// The directory to print. Possible values are: data, cache, config, internal,
+		/// instances, assets, libraries, jars, java, addons, logs, and plugins
+		kind: PathKind,
+	},
 }
 
 pub async fn run(subcommand: FilesSubcommand, data: &mut CmdData) -> anyhow::Result<()> {
 	match subcommand {
 		FilesSubcommand::Remove { data: remove_data } => remove(data, remove_data).await,
+		FilesSubcommand::Doctor { network, json } => doctor(data, network, json).await,
+		FilesSubcommand::MigrateLayout => migrate_layout(data).await,
+		FilesSubcommand::Clean { dry_run } => clean(data, dry_run).await,
+		FilesSubcommand::Assets { orphans } => assets(data, orphans).await,
+		FilesSubcommand::Path { kind } => {
+			println!("{}", data.paths.get(kind).display());
+			Ok(())
+		}
 	}
 }
 
 pub async fn remove(data: &mut CmdData, remove_data: bool) -> anyhow::Result<()> {
 	cprintln!("<g>Removing cached files...");
-	fs::remove_dir_all(data.paths.project.cache_dir())
-		.context("Failed to remove cache directory")?;
+	fs::remove_dir_all(&data.paths.cache).context("Failed to remove cache directory")?;
 	if remove_data {
 		cprintln!("<g>Removing internal files...");
 		fs::remove_dir_all(&data.paths.internal)
@@ -39,3 +106,143 @@ pub async fn remove(data: &mut CmdData, remove_data: bool) -> anyhow::Result<()>
 
 	Ok(())
 }
+
+pub async fn clean(data: &mut CmdData, dry_run: bool) -> anyhow::Result<()> {
+	let mut removed =
+		api::clean_server_jars(&data.paths, dry_run).context("Failed to clean files")?;
+	removed.extend(api::clean_assets(&data.paths, dry_run).context("Failed to clean assets")?);
+	if removed.is_empty() {
+		cprintln!("<g>No unused files to remove");
+		return Ok(());
+	}
+
+	for path in &removed {
+		if dry_run {
+			cprintln!("<y>  Would remove</> {}", path.display());
+		} else {
+			cprintln!("<g>  Removed</> {}", path.display());
+		}
+	}
+	if !dry_run {
+		cprintln!("<g>Removed {} files", removed.len());
+	}
+
+	Ok(())
+}
+
+pub async fn assets(data: &mut CmdData, orphans: bool) -> anyhow::Result<()> {
+	let report = api::get_asset_report(&data.paths)?;
+	if report.indexes.is_empty() {
+		cprintln!("<g>No asset indexes are installed");
+		return Ok(());
+	}
+
+	for index in &report.indexes {
+		cprint!(
+			"<s>{}</> - {} objects, {}, {} shared",
+			index.id,
+			index.objects,
+			format_mib(index.size),
+			index.shared_objects
+		);
+		if index.layout == AssetLayout::Virtual {
+			cprint!(" <k!>(also stored by name in a virtual directory)");
+		}
+		println!();
+	}
+	cprintln!(
+		"<g>{} unique objects using {}",
+		report.unique_objects,
+		format_mib(report.unique_size)
+	);
+	cprintln!(
+		"<g>{} objects are shared between indexes, saving {}",
+		report.shared_objects,
+		format_mib(report.saved_size)
+	);
+	if report.orphans.is_empty() {
+		cprintln!("<g>No orphaned objects");
+	} else {
+		cprintln!(
+			"<y>{} objects using {} are not used by any index. Remove them with <b>mcvm files clean</>",
+			report.orphans.len(),
+			format_mib(report.orphaned_size)
+		);
+		if orphans {
+			for path in &report.orphans {
+				println!("  {}", path.display());
+			}
+		}
+	}
+
+	Ok(())
+}
+
+fn format_mib(bytes: u64) -> String {
+	format!("{:.1} MiB", bytes as f64 / 1024.0 / 1024.0)
+}
+
+pub async fn migrate_layout(data: &mut CmdData) -> anyhow::Result<()> {
+	if !data.paths.core.legacy_layout {
+		cprintln!("<g>Downloaded files are already stored in the cache directory");
+		return Ok(());
+	}
+
+	cprintln!(
+		"<g>Moving downloaded files to {}...",
+		data.paths.cache.display()
+	);
+	let moved = data
+		.paths
+		.core
+		.migrate_layout()
+		.context("Failed to migrate files")?;
+	for dir in moved {
+		cprintln!("<g>  Moved</> {}", dir);
+	}
+	cprintln!("<g>Files migrated");
+
+	Ok(())
+}
+
+pub async fn doctor(data: &mut CmdData, network: bool, json: bool) -> anyhow::Result<()> {
+	let mut results = doctor::run_local_checks(&data.paths);
+	if network {
+		data.ensure_config(!json).await?;
+		results.extend(doctor::check_network(data.config.get()).await);
+	}
+
+	if json {
+		println!("{}", serde_json::to_string_pretty(&results)?);
+	} else {
+		let mut last_check = None;
+		for result in &results {
+			if last_check != Some(&result.check) {
+				cprintln!("<s>{}:", result.check);
+				last_check = Some(&result.check);
+			}
+			match result.status {
+				CheckStatus::Pass => cprint!("<g>  PASS</>"),
+				CheckStatus::Warn => cprint!("<y>  WARN</>"),
+				CheckStatus::Fail => cprint!("<r>  FAIL</>"),
+			}
+			cprintln!(" {} <k!>({})", result.name, result.message);
+		}
+	}
+
+	let failures = results
+		.iter()
+		.filter(|x| x.status == CheckStatus::Fail)
+		.count();
+	if failures > 0 {
+		if !json {
+			cprintln!("<r>{} checks failed", failures);
+		}
+		return Err(ExitStatus(1).into());
+	}
+	if !json {
+		cprintln!("<g>No problems found");
+	}
+
+	Ok(())
+}
@@ -0,0 +1,242 @@
+use std::io::IsTerminal;
+
+use anyhow::{bail, Context};
+use color_print::cprintln;
+use mcvm::api::{self, UpdateOptions};
+use mcvm::config::init::{
+	get_profile_id, parse_init_modloader, write_config, InitOptions, InitSides, InitUserType,
+	INIT_MODLOADERS,
+};
+use mcvm::config::preferences::ProxyConfig;
+use mcvm::config::Config;
+use mcvm::core::io::update::UpdateManager;
+use mcvm::core::net::download::{create_client, TimeoutSettings};
+use mcvm::core::net::game_files::version_manifest;
+use mcvm::shared::modifications::Modloader;
+use mcvm::shared::output::{MCVMOutput, MessageContents, MessageLevel};
+
+use super::CmdData;
+
+/// The user ID used when one isn't given
+const DEFAULT_USER_ID: &str = "player";
+
+/// Answers to the setup questions that were given on the command line
+pub(crate) struct InitFlags {
+	pub user: Option<String>,
+	pub user_type: Option<InitUserType>,
+	pub version: Option<String>,
+	pub side: Option<InitSides>,
+	pub modloader: Option<Modloader>,
+	pub update: Option<bool>,
+	/// Whether to use defaults instead of asking for answers that weren't given
+	pub yes: bool,
+	pub force: bool,
+}
+
+impl InitFlags {
+	/// Flags for when the wizard is offered because there is no config yet
+	pub fn empty() -> Self {
+		Self {
+			user: None,
+			user_type: None,
+			version: None,
+			side: None,
+			modloader: None,
+			update: None,
+			yes: false,
+			force: false,
+		}
+	}
+}
+
+pub async fn run(data: &mut CmdData, flags: InitFlags) -> anyhow::Result<()> {
+	let path = Config::get_path(&data.paths);
+	if path.exists() && !flags.force {
+		bail!(
+			"A config already exists at {}. Use --force to replace it",
+			path.display()
+		);
+	}
+	let interactive = !flags.yes && std::io::stdin().is_terminal();
+
+	let user_id = match flags.user {
+		Some(user) => user,
+		None if interactive => inquire::Text::new("What ID do you want for your user?")
+			.with_default(DEFAULT_USER_ID)
+			.prompt()?,
+		None => DEFAULT_USER_ID.into(),
+	};
+
+	let user_type = match flags.user_type {
+		Some(user_type) => user_type,
+		None if interactive => {
+			let options = InitUserType::ALL.map(|x| x.as_str()).to_vec();
+			let user_type = inquire::Select::new("What kind of user do you want?", options)
+				.with_help_message(
+					"Microsoft users log in with their account the first time they launch",
+				)
+				.prompt()?;
+			user_type.parse()?
+		}
+		None => InitUserType::default(),
+	};
+	// Unverified users play with a username instead of an account
+	let username = match user_type {
+		InitUserType::Unverified if interactive => {
+			inquire::Text::new("What username do you want to play as?")
+				.with_default(&user_id)
+				.prompt()?
+		}
+		_ => user_id.clone(),
+	};
+
+	let version = match flags.version {
+		Some(version) => version,
+		None => {
+			let latest = get_latest_release(data).await;
+			match latest {
+				Ok(latest) if interactive => {
+					inquire::Text::new("What Minecraft version do you want to play?")
+						.with_default(&latest)
+						.prompt()?
+				}
+				Ok(latest) => latest,
+				Err(e) if interactive => {
+					data.output.display(
+						MessageContents::Warning(format!(
+							"Failed to get the latest Minecraft version: {e:?}"
+						)),
+						MessageLevel::Important,
+					);
+					inquire::Text::new("What Minecraft version do you want to play?").prompt()?
+				}
+				Err(e) => {
+					return Err(e.context(
+						"Failed to get the latest Minecraft version. Use --version to set one",
+					));
+				}
+			}
+		}
+	};
+
+	let sides = match flags.side {
+		Some(side) => side,
+		None if interactive => {
+			let options = InitSides::ALL.map(|x| x.as_str()).to_vec();
+			let side = inquire::Select::new("Do you want a client, a server, or both?", options)
+				.prompt()?;
+			side.parse()?
+		}
+		None => InitSides::default(),
+	};
+
+	let modloader = match flags.modloader {
+		Some(modloader) => modloader,
+		None if interactive => {
+			let options = INIT_MODLOADERS.map(|x| get_modloader_name(&x)).to_vec();
+			let modloader =
+				inquire::Select::new("Which modloader do you want to use?", options).prompt()?;
+			parse_init_modloader(modloader)?
+		}
+		None => Modloader::Vanilla,
+	};
+
+	let options = InitOptions {
+		user_id,
+		user: user_type.get_variant(username),
+		version,
+		sides,
+		modloader,
+	};
+	// Put the old config back if the new one doesn't load, so that a failed setup doesn't leave a broken config
+	let previous = std::fs::read(&path).ok();
+	write_config(&options, &path).context("Failed to write config")?;
+	if let Err(e) = data.ensure_config(true).await {
+		let restored = match previous {
+			Some(previous) => std::fs::write(&path, previous),
+			None => std::fs::remove_file(&path),
+		};
+		restored.context("Failed to remove the invalid config")?;
+		return Err(e.context("The generated config is not valid"));
+	}
+	cprintln!("<g>Config written to {}.", path.display());
+
+	let update = match flags.update {
+		Some(update) => update,
+		None if interactive => inquire::Confirm::new("Download the game files now?")
+			.with_default(true)
+			.prompt()?,
+		None => false,
+	};
+	let profile = get_profile_id(&options);
+	if update {
+		let config = data.config.get_mut();
+		api::update_profile(
+			config,
+			&profile,
+			&UpdateOptions::default(),
+			&data.paths,
+			&mut data.output,
+		)
+		.await?;
+	} else {
+		cprintln!("Run <b>mcvm profile update {profile}</> to download the game files.");
+	}
+
+	Ok(())
+}
+
+/// Offer to run the setup wizard if there is no config yet and someone is there to answer it
+pub async fn offer(data: &mut CmdData) -> anyhow::Result<()> {
+	if Config::get_path(&data.paths).exists() || !std::io::stdin().is_terminal() {
+		return Ok(());
+	}
+
+	let run_wizard = inquire::Confirm::new("No config was found. Do you want to set one up now?")
+		.with_default(true)
+		.prompt()?;
+	if run_wizard {
+		run(data, InitFlags::empty()).await?;
+	}
+
+	Ok(())
+}
+
+/// Get the latest release version from the version manifest. When an existing config is being
+/// replaced, its proxy and timeout preferences are used
+async fn get_latest_release(data: &mut CmdData) -> anyhow::Result<String> {
+	// Opening a config that doesn't exist would create the default one
+	let existing = Config::get_path(&data.paths)
+		.exists()
+		.then(|| data.get_raw_config().ok())
+		.flatten();
+	let (proxy, timeouts) = match existing {
+		Some(config) => (
+			config
+				.preferences
+				.proxy
+				.as_ref()
+				.map(ProxyConfig::to_settings),
+			config.preferences.get_timeouts(),
+		),
+		None => (None, TimeoutSettings::default()),
+	};
+	let client = create_client(proxy.as_ref(), timeouts)?;
+	let manager = UpdateManager::new(false, true);
+	let manifest =
+		version_manifest::get_with_output(&data.paths.core, &manager, &client, &mut data.output)
+			.await?;
+
+	Ok(manifest.latest.release.to_string())
+}
+
+/// Get the name of a modloader as it is written in the config
+fn get_modloader_name(modloader: &Modloader) -> &'static str {
+	match modloader {
+		Modloader::Fabric => "fabric",
+		Modloader::Quilt => "quilt",
+		Modloader::Forge => "forge",
+		Modloader::NeoForged => "neoforged",
+		_ => "vanilla",
+	}
+}
@@ -1,25 +1,50 @@
+use std::fs::File;
+use std::io::{IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Context;
+use anyhow::{bail, Context};
+use chrono::{DateTime, Local};
 use clap::Subcommand;
 use color_print::{cprint, cprintln};
 use inquire::Select;
 use itertools::Itertools;
+use mcvm::api::{self, UpdateOptions};
+use mcvm::config::instance::{ClientWindowConfig, WindowResolution};
+use mcvm::config::modifications::{
+	apply_modifications_and_write, check_instance_id_available, ConfigModification,
+};
 use mcvm::config::Config;
-use mcvm::instance::update::InstanceUpdateContext;
-use mcvm::io::lock::Lockfile;
+use mcvm::core::launch::ACCESS_TOKEN_VAR;
+use mcvm::instance::create::InstanceDirs;
+use mcvm::instance::import::{self, ImportSource};
+use mcvm::instance::last_launched::LastLaunched;
+use mcvm::instance::rename::InstanceRename;
+use mcvm::instance::running::{
+	list_running_instances, sample_usage, ProcessUsage, RunningInstance, DEFAULT_SAMPLE_INTERVAL,
+};
+use mcvm::instance::InstanceSubdir;
+use mcvm::io::lock::{FileOwner, Lockfile};
+use mcvm::io::logging::tail_log;
 use mcvm::shared::id::InstanceID;
+use mcvm::shared::modifications::Modloader;
 
-use mcvm::instance::launch::LaunchSettings;
+use mcvm::instance::launch::{InstanceHandle, LargeDownload, LaunchSettings};
+use mcvm::io::paths::Paths;
+use mcvm::options::diff::is_secret_key;
+use mcvm::shared::output::MessageLevel;
+use mcvm::shared::util::utc_timestamp;
 use mcvm::shared::Side;
-use reqwest::Client;
+use serde::Serialize;
 
-use super::CmdData;
+use super::{catch_interrupts, CmdData};
+use crate::output::TerminalOutput;
 use crate::output::{icons_enabled, HYPHEN_POINT, INSTANCE, LOADER, PACKAGE, VERSION};
 use crate::secrets::get_ms_client_id;
 
 #[derive(Debug, Subcommand)]
-pub enum InstanceSubcommand {
+pub(crate) enum InstanceSubcommand {
 	#[command(about = "List all instances in all profiles")]
 	#[clap(alias = "ls")]
 	List {
@@ -32,18 +57,55 @@ pub enum InstanceSubcommand {
 	},
 	#[command(about = "Launch instances to play the game")]
 	Launch {
-		/// An optional user to choose when launching
+		/// An optional user to choose when launching, instead of the user of the instance
 		#[arg(short, long)]
 		user: Option<String>,
+		/// Whether to save the chosen user to the config so that the instance always launches with it
+		#[arg(long, requires_all = ["user", "instance"])]
+		remember: bool,
 		/// Whether to launch in offline mode, skipping authentication. This only works
 		/// if you have authenticated at least once
 		#[arg(short, long)]
 		offline: bool,
+		/// Width of the game window, overriding the instance configuration
+		#[arg(long, requires = "height")]
+		width: Option<u32>,
+		/// Height of the game window, overriding the instance configuration
+		#[arg(long, requires = "width")]
+		height: Option<u32>,
+		/// Whether to launch the game in fullscreen, overriding the instance configuration.
+		/// Pass `--fullscreen false` to force the game to be windowed
+		#[arg(long, num_args = 0..=1, default_missing_value = "true")]
+		fullscreen: Option<bool>,
+		/// The number of seconds that the update before the first launch can take before it is cancelled
+		#[arg(long)]
+		timeout: Option<u64>,
+		/// Whether to skip checking for and repairing missing or corrupted files before launching
+		#[arg(long)]
+		no_verify: bool,
+		/// Whether to refuse to launch if any game files would need to be downloaded first,
+		/// such as on a metered connection
+		#[arg(long)]
+		no_download: bool,
+		/// Instead of launching, write a script to this path that runs the same launch command.
+		/// A .bat or .cmd extension writes a batch file instead of a shell script
+		#[arg(long)]
+		export_script: Option<PathBuf>,
 		/// The instance to launch, as an instance reference (profile:instance)
 		instance: Option<String>,
 	},
 	#[command(about = "Print useful information about an instance")]
 	Info { instance: String },
+	#[command(
+		about = "List running instances and their resource usage",
+		long_about = "List the instances that were launched by mcvm and are still running, along with
+their PID, uptime, memory, and CPU usage."
+	)]
+	Ps {
+		/// Whether to print the list as JSON
+		#[arg(long)]
+		json: bool,
+	},
 	Update {
 		/// Whether to force update files that have already been downloaded
 		#[arg(short, long)]
@@ -54,9 +116,30 @@ pub enum InstanceSubcommand {
 		/// Whether to skip updating packages
 		#[arg(short = 'P', long)]
 		skip_packages: bool,
+		/// Whether to skip backing up world data when the Minecraft version changes
+		#[arg(long)]
+		no_snapshot: bool,
+		/// Whether to fail instead of warning about problems with the configuration,
+		/// such as unknown package features
+		#[arg(long)]
+		strict: bool,
+		/// Whether to stop at the first package that fails to install instead of
+		/// skipping it and installing the rest
+		#[arg(long)]
+		fail_fast: bool,
+		/// Whether to make the whitelist and ops of servers match their config exactly,
+		/// removing players that were added in-game
+		#[arg(long)]
+		sync_exact: bool,
 		/// Additional instance groups to update
 		#[arg(short, long)]
 		groups: Vec<String>,
+		/// The maximum number of Minecraft versions to create instances for at the same time
+		#[arg(short, long)]
+		jobs: Option<usize>,
+		/// The number of seconds that the update can take before it is cancelled
+		#[arg(long)]
+		timeout: Option<u64>,
 		/// The instances to update
 		instances: Vec<String>,
 	},
@@ -65,6 +148,113 @@ pub enum InstanceSubcommand {
 		/// The instance to print the directory of
 		instance: Option<String>,
 	},
+	#[command(
+		about = "Print the path to an instance or one of its subdirectories",
+		long_about = "Print the absolute path to an instance's game directory, or one of its
+subdirectories, and nothing else so that it can be used in scripts. The directory might not exist yet."
+	)]
+	Path {
+		/// The instance to print the path of
+		instance: String,
+		/// The subdirectory to print instead of the game directory.
+		/// Possible values are: game, mods, config, plugins, and world
+		#[arg(short, long)]
+		subdir: Option<InstanceSubdir>,
+	},
+	#[command(about = "Print the captured output of an instance")]
+	Logs {
+		/// Whether to keep printing new output as it is written
+		#[arg(short, long)]
+		follow: bool,
+		/// The number of lines to print from the end of the log
+		#[arg(short = 'n', long, default_value_t = 50)]
+		lines: usize,
+		/// The instance to print the logs of
+		instance: Option<String>,
+	},
+	#[command(
+		about = "Run a command on a running server through RCON",
+		long_about = "Run a command on a running server instance through RCON and print its output.
+RCON has to be enabled with the 'rcon.enable' and 'rcon.password' server options."
+	)]
+	RunCommand {
+		/// The server instance to run the command on
+		instance: String,
+		/// The command to run, without a leading slash
+		#[arg(required = true)]
+		command: Vec<String>,
+	},
+	#[command(about = "List files in addon folders that were not installed by MCVM")]
+	Orphans {
+		/// Whether to remove formatting from the output
+		#[arg(short, long)]
+		raw: bool,
+		/// The instance to check
+		instance: Option<String>,
+	},
+	#[command(
+		about = "Print which package a file in an instance came from",
+		long_about = "Print the package and addon that put a file into an instance, along with the
+URL or path it came from, its hash, and when it was installed. Relative paths are relative
+to the game directory of the instance."
+	)]
+	Whichpkg {
+		/// The instance that the file is in
+		instance: String,
+		/// The file to look up
+		file: PathBuf,
+	},
+	#[command(
+		about = "Create a copy of an instance",
+		long_about = "Create a copy of an instance, including its configuration, files, and worlds.
+Addons are installed again on the new instance so that it can be changed without affecting the original."
+	)]
+	Clone {
+		/// The profile for the new instance to use. Defaults to the profile of the original instance
+		#[arg(short, long)]
+		profile: Option<String>,
+		/// Whether to leave out the world data of the instance
+		#[arg(long)]
+		no_world: bool,
+		/// The instance to copy
+		src: String,
+		/// The ID of the new instance
+		dest: String,
+	},
+	#[command(
+		about = "Change the ID of an instance",
+		long_about = "Change the ID of an instance, moving its files and updating the config,
+lockfile, and any data that plugins store for it."
+	)]
+	Rename {
+		/// The current ID of the instance
+		old: String,
+		/// The new ID of the instance
+		new: String,
+	},
+	#[command(
+		about = "Import an instance from another launcher",
+		long_about = "Create a new client instance from a MultiMC or Prism Launcher instance folder,
+or from the .minecraft folder of the official launcher. The version and modloader are read from the
+other launcher, and worlds, settings, and other files are copied into the new instance.
+Mods are copied as plain files that MCVM doesn't update."
+	)]
+	Import {
+		/// The launcher to import from: prism or dotminecraft
+		#[arg(long)]
+		from: ImportSource,
+		/// The instance folder or .minecraft folder to import
+		path: PathBuf,
+		/// The ID of the new instance. Defaults to the name of the folder
+		#[arg(long)]
+		id: Option<String>,
+		/// The modloader to use when the other launcher lists more than one
+		#[arg(long, value_parser = mcvm::config::init::parse_init_modloader)]
+		modloader: Option<Modloader>,
+		/// Link the folders of the other launcher instead of copying them, so that both launchers share them
+		#[arg(long)]
+		link: bool,
+	},
 }
 
 pub async fn run(command: InstanceSubcommand, data: &mut CmdData) -> anyhow::Result<()> {
@@ -72,24 +262,118 @@ pub async fn run(command: InstanceSubcommand, data: &mut CmdData) -> anyhow::Res
 		InstanceSubcommand::List { raw, side } => list(data, raw, side).await,
 		InstanceSubcommand::Launch {
 			user,
+			remember,
 			offline,
+			width,
+			height,
+			fullscreen,
+			timeout,
+			no_verify,
+			no_download,
+			export_script,
 			instance,
-		} => launch(instance, user, offline, data).await,
+		} => {
+			let window = ClientWindowConfig {
+				resolution: width
+					.zip(height)
+					.map(|(width, height)| WindowResolution { width, height }),
+				fullscreen,
+			};
+			let timeout = timeout.map(Duration::from_secs);
+			if remember {
+				if let (Some(instance), Some(user)) = (&instance, &user) {
+					remember_user(data, instance, user)?;
+				}
+			}
+			if let Some(script_path) = export_script {
+				return export_launch_script(
+					instance,
+					user,
+					offline,
+					window,
+					!no_verify,
+					no_download,
+					&script_path,
+					data,
+				)
+				.await;
+			}
+			launch(
+				instance,
+				user,
+				offline,
+				window,
+				timeout,
+				!no_verify,
+				no_download,
+				data,
+			)
+			.await
+		}
 		InstanceSubcommand::Info { instance } => info(data, &instance).await,
+		InstanceSubcommand::Ps { json } => ps(data, json).await,
 		InstanceSubcommand::Update {
 			force,
 			all,
 			skip_packages,
+			no_snapshot,
+			strict,
+			fail_fast,
+			sync_exact,
 			groups,
+			jobs,
+			timeout,
 			instances,
-		} => update(data, instances, groups, all, force, skip_packages).await,
+		} => {
+			update(
+				data,
+				instances,
+				groups,
+				all,
+				force,
+				skip_packages,
+				no_snapshot,
+				strict,
+				fail_fast,
+				sync_exact,
+				jobs,
+				timeout.map(Duration::from_secs),
+			)
+			.await
+		}
 		InstanceSubcommand::Dir { instance } => dir(data, instance).await,
+		InstanceSubcommand::Path { instance, subdir } => path(data, instance, subdir).await,
+		InstanceSubcommand::Logs {
+			follow,
+			lines,
+			instance,
+		} => logs(data, instance, follow, lines).await,
+		InstanceSubcommand::RunCommand { instance, command } => {
+			run_command(data, instance, command).await
+		}
+		InstanceSubcommand::Orphans { raw, instance } => orphans(data, instance, raw).await,
+		InstanceSubcommand::Whichpkg { instance, file } => whichpkg(data, &instance, &file).await,
+		InstanceSubcommand::Clone {
+			profile,
+			no_world,
+			src,
+			dest,
+		} => clone(data, src, dest, profile, no_world).await,
+		InstanceSubcommand::Rename { old, new } => rename(data, old, new).await,
+		InstanceSubcommand::Import {
+			from,
+			path,
+			id,
+			modloader,
+			link,
+		} => import(data, from, path, id, modloader, link).await,
 	}
 }
 
 async fn list(data: &mut CmdData, raw: bool, side: Option<Side>) -> anyhow::Result<()> {
 	data.ensure_config(!raw).await?;
 	let config = data.config.get_mut();
+	let last_launched = LastLaunched::open(&data.paths);
 
 	for (id, instance) in config.instances.iter().sorted_by_key(|x| x.0) {
 		if let Some(side) = side {
@@ -102,15 +386,107 @@ async fn list(data: &mut CmdData, raw: bool, side: Option<Side>) -> anyhow::Resu
 			println!("{id}");
 		} else {
 			match instance.get_side() {
-				Side::Client => cprintln!("{}<y!>{}", HYPHEN_POINT, id),
-				Side::Server => cprintln!("{}<c!>{}", HYPHEN_POINT, id),
+				Side::Client => cprint!("{}<y!>{}", HYPHEN_POINT, id),
+				Side::Server => cprint!("{}<c!>{}", HYPHEN_POINT, id),
+			}
+			if last_launched.get(None) == Some(id) {
+				cprint!(" <g>(last launched)");
 			}
+			if let Some(description) = &instance.get_config().description {
+				cprint!(" <k!>- {}", description);
+			}
+			cprintln!();
 		}
 	}
 
 	Ok(())
 }
 
+/// A running instance in the output of the ps command
+#[derive(Serialize)]
+struct PsEntry {
+	#[serde(flatten)]
+	instance: RunningInstance,
+	uptime: u64,
+	#[serde(flatten)]
+	usage: ProcessUsage,
+	/// Not measured yet, but kept so that the format doesn't change when it is
+	tps: Option<f64>,
+}
+
+async fn ps(data: &mut CmdData, json: bool) -> anyhow::Result<()> {
+	let instances = list_running_instances(&data.paths)?;
+	let pids: Vec<_> = instances.iter().map(|x| x.pid).collect();
+	let mut usage = sample_usage(&pids, DEFAULT_SAMPLE_INTERVAL).await;
+	let now = utc_timestamp()?;
+	let entries: Vec<_> = instances
+		.into_iter()
+		.map(|instance| PsEntry {
+			uptime: instance.get_uptime(now),
+			usage: usage.remove(&instance.pid).unwrap_or_default(),
+			instance,
+			tps: None,
+		})
+		.collect();
+
+	if json {
+		println!("{}", serde_json::to_string_pretty(&entries)?);
+		return Ok(());
+	}
+
+	if entries.is_empty() {
+		cprintln!("<s>No instances are running");
+		return Ok(());
+	}
+
+	cprintln!(
+		"<s>{:<20} {:>8} {:>10} {:>10} {:>7} {:>5}",
+		"INSTANCE",
+		"PID",
+		"UPTIME",
+		"MEMORY",
+		"CPU",
+		"TPS"
+	);
+	for entry in entries {
+		let memory = entry.usage.rss.map_or("-".into(), |x| {
+			format!("{:.0} MiB", x as f64 / 1024.0 / 1024.0)
+		});
+		let cpu = entry
+			.usage
+			.cpu_percent
+			.map_or("-".into(), |x| format!("{x:.1}%"));
+		let id = match entry.instance.side {
+			Side::Client => color_print::cformat!("<y!>{:<20}", entry.instance.id),
+			Side::Server => color_print::cformat!("<c!>{:<20}", entry.instance.id),
+		};
+		println!(
+			"{id} {:>8} {:>10} {:>10} {:>7} {:>5}",
+			entry.instance.pid,
+			format_uptime(entry.uptime),
+			memory,
+			cpu,
+			"-"
+		);
+	}
+
+	Ok(())
+}
+
+/// Format a number of seconds like 1d 2h 3m
+fn format_uptime(seconds: u64) -> String {
+	let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+	if days > 0 {
+		format!("{days}d {hours}h {minutes}m")
+	} else if hours > 0 {
+		format!("{hours}h {minutes}m")
+	} else if minutes > 0 {
+		format!("{minutes}m {}s", seconds % 60)
+	} else {
+		format!("{seconds}s")
+	}
+}
+
 async fn info(data: &mut CmdData, id: &str) -> anyhow::Result<()> {
 	data.ensure_config(true).await?;
 	let config = data.config.get_mut();
@@ -128,6 +504,18 @@ async fn info(data: &mut CmdData, id: &str) -> anyhow::Result<()> {
 		print!("{} ", INSTANCE);
 	}
 	cprintln!("<s><g>Instance <b>{}", id);
+	if let Some(name) = &instance.get_config().name {
+		print_indent();
+		cprintln!("<s>Name:</s> {}", name);
+	}
+	if let Some(description) = &instance.get_config().description {
+		print_indent();
+		cprintln!("<s>Description:</s> {}", description);
+	}
+	if let Some(icon) = &instance.get_config().icon {
+		print_indent();
+		cprintln!("<s>Icon:</s> <m!>{}", icon.display());
+	}
 	print_indent();
 	if icons_enabled() {
 		print!("{} ", VERSION);
@@ -142,6 +530,17 @@ async fn info(data: &mut CmdData, id: &str) -> anyhow::Result<()> {
 	}
 	cprintln!();
 
+	if instance.get_side() == Side::Client {
+		print_indent();
+		match &instance.get_config().user {
+			Some(user) => cprintln!("<s>User:</s> <b>{}", user),
+			None => match config.users.get_chosen_user() {
+				Some(user) => cprintln!("<s>User:</s> <b>{}</> (default)", user.get_id()),
+				None => cprintln!("<s>User:</s> <k!>None"),
+			},
+		}
+	}
+
 	if instance.get_config().modifications.common_modloader() {
 		print_indent();
 		if icons_enabled() {
@@ -173,6 +572,31 @@ async fn info(data: &mut CmdData, id: &str) -> anyhow::Result<()> {
 		);
 	}
 
+	let jvm_args = &instance.get_config().launch.jvm_args;
+	if !jvm_args.is_empty() {
+		print_indent();
+		cprintln!("<s>JVM arguments:");
+		for arg in jvm_args {
+			print_indent();
+			cprintln!("{}<m!>{}", HYPHEN_POINT, arg);
+		}
+	}
+
+	let launch = &instance.get_config().launch;
+	if let Some(working_dir) = &launch.working_dir {
+		print_indent();
+		cprintln!("<s>Working directory:</s> <g>{}", working_dir);
+	}
+	if !launch.env.is_empty() {
+		print_indent();
+		cprintln!("<s>Environment variables:");
+		for (var, value) in launch.env.iter().sorted_by_key(|x| x.0) {
+			let value = if is_secret_key(var) { "***" } else { value };
+			print_indent();
+			cprintln!("{}<m!>{}</>=<g>{}", HYPHEN_POINT, var, value);
+		}
+	}
+
 	print_indent();
 	if icons_enabled() {
 		print!("{} ", PACKAGE);
@@ -188,10 +612,15 @@ async fn info(data: &mut CmdData, id: &str) -> anyhow::Result<()> {
 	Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn launch(
 	instance: Option<String>,
 	user: Option<String>,
 	offline: bool,
+	window: ClientWindowConfig,
+	update_timeout: Option<Duration>,
+	verify: bool,
+	no_download: bool,
 	data: &mut CmdData,
 ) -> anyhow::Result<()> {
 	data.ensure_config(true).await?;
@@ -199,68 +628,199 @@ pub async fn launch(
 
 	let instance_id = pick_instance(instance, config).context("Failed to pick instance")?;
 
+	// The output of the game is shown live like any other important message, so quiet output hides it
+	let mirror_output = data.output.shows_level(MessageLevel::Important);
+	let download_limit = if no_download {
+		Some(0)
+	} else {
+		config.prefs.confirm_large_downloads
+	};
+	let result = launch_instance(
+		&instance_id,
+		config,
+		&data.paths,
+		user.clone(),
+		offline,
+		false,
+		mirror_output,
+		window.clone(),
+		update_timeout,
+		verify,
+		download_limit,
+		&mut data.output,
+	)
+	.await;
+	let instance_handle = match result {
+		Ok(handle) => handle,
+		Err(e) => {
+			let large_download = e.chain().find_map(|x| x.downcast_ref::<LargeDownload>());
+			match large_download {
+				Some(large_download) if !no_download && std::io::stdin().is_terminal() => {
+					let confirmed =
+						inquire::Confirm::new(&format!("{large_download}. Continue anyway?"))
+							.with_default(false)
+							.prompt()?;
+					if !confirmed {
+						return Ok(());
+					}
+					launch_instance(
+						&instance_id,
+						config,
+						&data.paths,
+						user,
+						offline,
+						false,
+						mirror_output,
+						window,
+						update_timeout,
+						verify,
+						None,
+						&mut data.output,
+					)
+					.await?
+				}
+				_ => return Err(e),
+			}
+		}
+	};
+
+	let launched_at = instance_handle.get_launch_time();
+	// Wrapped instances run in their own process group, so Ctrl-C has to be passed on to them
+	let interrupter = instance_handle.get_interrupter();
+	let forward_interrupts = tokio::spawn(async move {
+		while tokio::signal::ctrl_c().await.is_ok() {
+			let _ = interrupter.interrupt();
+		}
+	});
+	let status = instance_handle.wait(&config.plugins, &data.paths, &mut data.output);
+	forward_interrupts.abort();
+	let status = status.context("Failed to wait for instance child process")?;
+
 	let instance = config
 		.instances
 		.get_mut(&instance_id)
 		.context("Instance does not exist")?;
-
-	// Perform first update if needed
-	let mut lock = Lockfile::open(&data.paths).context("Failed to open lockfile")?;
-	if !lock.has_instance_done_first_update(&instance_id) {
-		cprintln!("<s>Performing first update of instance profile...");
-
-		let client = Client::new();
-		let mut ctx = InstanceUpdateContext {
-			packages: &mut config.packages,
-			users: &config.users,
-			plugins: &config.plugins,
-			prefs: &config.prefs,
-			paths: &data.paths,
-			lock: &mut lock,
-			client: &client,
-			output: &mut data.output,
-		};
-
+	if !status.success() && instance.get_side() == Side::Server {
 		instance
-			.update(true, false, &mut ctx)
+			.report_crash(
+				status.code(),
+				launched_at,
+				&config.prefs.crash_notification,
+				&data.paths,
+				&config.client,
+				&mut data.output,
+			)
 			.await
-			.context("Failed to perform first update for instance")?;
-
-		// Since the update was successful, we can mark the instance as ready
-		lock.update_instance_has_done_first_update(&instance_id);
-		lock.finish(&data.paths)
-			.context("Failed to finish using lockfile")?;
+			.context("Failed to report crash")?;
 	}
 
-	if let Some(user) = user {
-		config
-			.users
-			.choose_user(&user)
-			.context("Failed to choose user")?;
+	Ok(())
+}
+
+/// Save the user that an instance launches with to the config
+fn remember_user(data: &mut CmdData, instance: &str, user: &str) -> anyhow::Result<()> {
+	let mut raw_config = data.get_raw_config()?;
+	let instance = InstanceID::from(instance);
+	if !raw_config.instances.contains_key(&instance) {
+		bail!("Instance '{instance}' does not exist");
 	}
 
-	let launch_settings = LaunchSettings {
+	apply_modifications_and_write(
+		&mut raw_config,
+		vec![ConfigModification::SetInstanceUser(
+			instance.clone(),
+			Some(user.to_string()),
+		)],
+		&data.paths,
+	)
+	.context("Failed to write modified config")?;
+	cprintln!(
+		"<g>Instance '{}' will now launch with user '{}'.",
+		instance,
+		user
+	);
+
+	Ok(())
+}
+
+/// Write a script that launches an instance instead of launching it
+#[allow(clippy::too_many_arguments)]
+async fn export_launch_script(
+	instance: Option<String>,
+	user: Option<String>,
+	offline: bool,
+	window: ClientWindowConfig,
+	verify: bool,
+	no_download: bool,
+	script_path: &Path,
+	data: &mut CmdData,
+) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+	let config = data.config.get_mut();
+
+	let instance_id = pick_instance(instance, config).context("Failed to pick instance")?;
+
+	let settings = LaunchSettings {
 		ms_client_id: get_ms_client_id(),
 		offline_auth: offline,
+		pipe_stdin: false,
+		mirror_output: false,
+		window,
+		update_timeout: None,
+		verify,
+		download_limit: no_download.then_some(0),
+		user,
 	};
-	let instance_handle = instance
-		.launch(
-			&data.paths,
-			&mut config.users,
-			&config.plugins,
-			launch_settings,
-			&mut data.output,
-		)
-		.await
-		.context("Instance failed to launch")?;
+	api::export_launch_script(
+		config,
+		&instance_id,
+		settings,
+		script_path,
+		&data.paths,
+		&mut data.output,
+	)
+	.await?;
 
-	instance_handle
-		.wait(&config.plugins, &data.paths, &mut data.output)
-		.context("Failed to wait for instance child process")?;
+	cprintln!("<g>Launch script written to {}.", script_path.display());
+	cprintln!(
+		"Secrets are left out of the script. Set <b>{}</> to the access token of the user and set any secret environment variables before running it.",
+		ACCESS_TOKEN_VAR
+	);
 
 	Ok(())
 }
 
+/// Launch an instance, performing its first update if needed
+#[allow(clippy::too_many_arguments)]
+pub async fn launch_instance(
+	instance_id: &InstanceID,
+	config: &mut Config,
+	paths: &Paths,
+	user: Option<String>,
+	offline: bool,
+	pipe_stdin: bool,
+	mirror_output: bool,
+	window: ClientWindowConfig,
+	update_timeout: Option<Duration>,
+	verify: bool,
+	download_limit: Option<u64>,
+	o: &mut TerminalOutput,
+) -> anyhow::Result<InstanceHandle> {
+	let launch_settings = LaunchSettings {
+		ms_client_id: get_ms_client_id(),
+		offline_auth: offline,
+		pipe_stdin,
+		mirror_output,
+		window,
+		update_timeout,
+		verify,
+		download_limit,
+		user,
+	};
+
+	api::launch_instance(config, instance_id, launch_settings, paths, o).await
+}
+
 async fn dir(data: &mut CmdData, instance: Option<String>) -> anyhow::Result<()> {
 	data.ensure_config(true).await?;
 
@@ -278,6 +838,346 @@ async fn dir(data: &mut CmdData, instance: Option<String>) -> anyhow::Result<()>
 	Ok(())
 }
 
+async fn path(
+	data: &mut CmdData,
+	instance: String,
+	subdir: Option<InstanceSubdir>,
+) -> anyhow::Result<()> {
+	// Warnings would end up in the output of scripts that use this
+	data.ensure_config(false).await?;
+
+	let instance = data
+		.config
+		.get_mut()
+		.instances
+		.get_mut(&InstanceID::from(instance.clone()))
+		.with_context(|| format!("Instance '{instance}' does not exist"))?;
+	let path = instance.get_subdir(subdir.unwrap_or(InstanceSubdir::Game), &data.paths);
+
+	println!("{}", path.display());
+
+	Ok(())
+}
+
+async fn logs(
+	data: &mut CmdData,
+	instance: Option<String>,
+	follow: bool,
+	lines: usize,
+) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+
+	let instance = pick_instance(instance, data.config.get()).context("Failed to pick instance")?;
+	let instance = data
+		.config
+		.get_mut()
+		.instances
+		.get_mut(&instance)
+		.context("Instance does not exist")?;
+	let path = instance.get_output_log_path(&data.paths)?;
+
+	for line in tail_log(&path, lines)? {
+		println!("{line}");
+	}
+
+	if follow {
+		follow_log(&path).await?;
+	}
+
+	Ok(())
+}
+
+async fn run_command(
+	data: &mut CmdData,
+	instance: String,
+	command: Vec<String>,
+) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+
+	let instance = data
+		.config
+		.get_mut()
+		.instances
+		.get_mut(&InstanceID::from(instance.clone()))
+		.with_context(|| format!("Instance '{instance}' does not exist"))?;
+	let command = command.join(" ");
+	let response = instance
+		.run_rcon_command(&data.paths, &command)
+		.context("Failed to run command")?;
+
+	if !response.is_empty() {
+		println!("{response}");
+	}
+
+	Ok(())
+}
+
+async fn orphans(data: &mut CmdData, instance: Option<String>, raw: bool) -> anyhow::Result<()> {
+	data.ensure_config(!raw).await?;
+
+	let instance = pick_instance(instance, data.config.get()).context("Failed to pick instance")?;
+	let instance = data
+		.config
+		.get()
+		.instances
+		.get(&instance)
+		.context("Instance does not exist")?;
+	let lock = Lockfile::open(&data.paths).context("Failed to open lockfile")?;
+
+	let ignore = instance.load_ignore(&data.paths, &mut data.output);
+	for path in instance.get_unmanaged_addon_files(&lock, &ignore) {
+		if raw {
+			println!("{}", path.to_string_lossy());
+		} else {
+			cprintln!("{}<y>{}", HYPHEN_POINT, path.to_string_lossy());
+		}
+	}
+
+	Ok(())
+}
+
+async fn whichpkg(data: &mut CmdData, instance: &str, file: &Path) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+	let config = data.config.get_mut();
+
+	let instance_id = InstanceID::from(instance);
+	let instance = config
+		.instances
+		.get_mut(&instance_id)
+		.with_context(|| format!("Unknown instance '{instance_id}'"))?;
+	let lock = Lockfile::open(&data.paths).context("Failed to open lockfile")?;
+
+	let owner = instance
+		.get_file_owner(file, &data.paths, &lock)
+		.context("Failed to look up file")?;
+	match owner {
+		Some(FileOwner::Addon { package, addon }) => {
+			cprintln!("<s>{}</> is from package <b>{}</>", file.display(), package);
+			cprint!(
+				"{}Addon: <g>{}</> ({})",
+				HYPHEN_POINT,
+				addon.addon.id,
+				addon.addon.kind
+			);
+			if let Some(version) = &addon.addon.version {
+				cprint!(" version <c>{}</>", version);
+			}
+			println!();
+			if let Some(provenance) = &addon.provenance {
+				if let Some(url) = &provenance.url {
+					println!("{HYPHEN_POINT}Source: {url}");
+				}
+				if let Some(path) = &provenance.path {
+					println!("{HYPHEN_POINT}Source: {path}");
+				}
+				println!("{HYPHEN_POINT}SHA-256: {}", provenance.sha256);
+				if let Some(installed) = DateTime::from_timestamp(provenance.installed as i64, 0) {
+					println!(
+						"{HYPHEN_POINT}Installed: {}",
+						installed.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+					);
+				}
+			} else {
+				cprintln!(
+					"{}<k!>Where the addon came from wasn't recorded. Update the instance to record it",
+					HYPHEN_POINT
+				);
+			}
+		}
+		Some(FileOwner::ConfigFile { package }) => {
+			cprintln!(
+				"<s>{}</> is a config file created by package <b>{}</>",
+				file.display(),
+				package
+			);
+		}
+		None => {
+			cprintln!("<y>{} is not managed by MCVM", file.display());
+		}
+	}
+
+	Ok(())
+}
+
+async fn clone(
+	data: &mut CmdData,
+	src: String,
+	dest: String,
+	profile: Option<String>,
+	no_world: bool,
+) -> anyhow::Result<()> {
+	let mut raw_config = data.get_raw_config()?;
+	let src = InstanceID::from(src);
+	let dest = InstanceID::from(dest);
+	let mut instance = raw_config
+		.instances
+		.get(&src)
+		.with_context(|| format!("Instance '{src}' does not exist"))?
+		.clone();
+	if let Some(profile) = profile {
+		if !raw_config.profiles.contains_key(profile.as_str()) {
+			bail!("Profile '{profile}' does not exist");
+		}
+		instance.common.from = Some(profile);
+	}
+
+	apply_modifications_and_write(
+		&mut raw_config,
+		vec![ConfigModification::AddInstance(dest.clone(), instance)],
+		&data.paths,
+	)
+	.context("Failed to write modified config")?;
+
+	data.ensure_config(true).await?;
+	let config = data.config.get_mut();
+	let mut new_instance = config
+		.instances
+		.remove(&dest)
+		.context("New instance was not loaded")?;
+	let original = config
+		.instances
+		.get_mut(&src)
+		.context("Instance does not exist")?;
+
+	let mut lock = Lockfile::open(&data.paths).context("Failed to open lockfile")?;
+	let result = original.clone_into(
+		&mut new_instance,
+		!no_world,
+		&mut lock,
+		&data.paths,
+		&mut data.output,
+	);
+	config.instances.insert(dest.clone(), new_instance);
+	result.context("Failed to copy instance")?;
+	lock.finish(&data.paths)
+		.context("Failed to finish using lockfile")?;
+
+	cprintln!("<g>Instance '{}' cloned to '{}'.", src, dest);
+
+	Ok(())
+}
+
+async fn rename(data: &mut CmdData, old: String, new: String) -> anyhow::Result<()> {
+	let mut raw_config = data.get_raw_config()?;
+	let old = InstanceID::from(old);
+	let new = InstanceID::from(new);
+	if !raw_config.instances.contains_key(&old) {
+		bail!("Instance '{old}' does not exist");
+	}
+	check_instance_id_available(&raw_config, &new)?;
+
+	let mut lock = Lockfile::open(&data.paths).context("Failed to open lockfile")?;
+	let rename = InstanceRename::plan(old.clone(), new.clone(), &lock, &data.paths)
+		.context("Failed to check instance files")?;
+	rename.move_files()?;
+
+	apply_modifications_and_write(
+		&mut raw_config,
+		vec![ConfigModification::RenameInstance(old.clone(), new.clone())],
+		&data.paths,
+	)
+	.with_context(|| {
+		format!("The files of instance '{old}' were moved, but the config could not be updated")
+	})?;
+
+	data.ensure_config(true).await?;
+	let config = data.config.get();
+	rename.finish(&mut lock, &config.plugins, &data.paths, &mut data.output)?;
+	lock.finish(&data.paths)
+		.context("Failed to finish using lockfile")?;
+
+	cprintln!("<g>Instance '{}' renamed to '{}'.", old, new);
+
+	Ok(())
+}
+
+async fn import(
+	data: &mut CmdData,
+	from: ImportSource,
+	path: PathBuf,
+	id: Option<String>,
+	modloader: Option<Modloader>,
+	link: bool,
+) -> anyhow::Result<()> {
+	let candidates =
+		import::read_candidates(from, &path).context("Failed to read the instance to import")?;
+	let matching = import::filter_candidates(candidates.clone(), modloader.as_ref());
+	let candidate = if matching.len() > 1 && std::io::stdin().is_terminal() {
+		Select::new(
+			"The instance can be imported in more than one way. Which one do you want?",
+			matching,
+		)
+		.prompt()?
+	} else {
+		import::pick_candidate(candidates, modloader.as_ref())?
+	};
+
+	let id = match id {
+		Some(id) => InstanceID::from(id),
+		None => import::get_default_id(&path)
+			.context("Could not pick an ID for the instance. Set one with --id")?,
+	};
+	let mut raw_config = data.get_raw_config()?;
+	check_instance_id_available(&raw_config, &id)?;
+	let options =
+		import::read_candidate_options(&candidate).context("Failed to read options.txt")?;
+	let modifications =
+		import::get_import_modifications(&raw_config, &id, &candidate, options.as_ref())?;
+
+	let dirs = InstanceDirs::new(&data.paths, &id, &Side::Client);
+	let files = import::import_files(&candidate, &dirs.game_dir, link)
+		.context("Failed to import instance files")?;
+
+	apply_modifications_and_write(&mut raw_config, modifications, &data.paths)
+		.context("Failed to write modified config")?;
+
+	cprintln!("<g>Imported {} as instance '{}'.", candidate, id);
+	if options.is_some() {
+		cprintln!("Settings from options.txt were added to the instance config.");
+	}
+	if !files.mods.is_empty() {
+		cprintln!(
+			"<y>{} mods were copied as files that MCVM doesn't manage. Add them as packages to have them updated, or list them with <b>mcvm instance orphans {}</>.",
+			files.mods.len(),
+			id
+		);
+	}
+
+	Ok(())
+}
+
+/// Keep printing lines as they are added to a log file
+async fn follow_log(path: &Path) -> anyhow::Result<()> {
+	let mut position = std::fs::metadata(path).map(|x| x.len()).unwrap_or(0);
+	let mut buf = String::new();
+	loop {
+		tokio::time::sleep(Duration::from_millis(500)).await;
+
+		let Ok(mut file) = File::open(path) else {
+			continue;
+		};
+		let len = file.metadata().map(|x| x.len()).unwrap_or(0);
+		// The log was rotated, so start reading the new one from the beginning
+		if len < position {
+			position = 0;
+		}
+		if len == position {
+			continue;
+		}
+
+		file.seek(SeekFrom::Start(position))
+			.context("Failed to seek in log file")?;
+		buf.clear();
+		let read = file
+			.read_to_string(&mut buf)
+			.context("Failed to read log file")?;
+		position += read as u64;
+		print!("{buf}");
+		let _ = std::io::stdout().flush();
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn update(
 	data: &mut CmdData,
 	instances: Vec<String>,
@@ -285,6 +1185,12 @@ async fn update(
 	all: bool,
 	force: bool,
 	skip_packages: bool,
+	no_snapshot: bool,
+	strict: bool,
+	fail_fast: bool,
+	sync_exact: bool,
+	jobs: Option<usize>,
+	timeout: Option<Duration>,
 ) -> anyhow::Result<()> {
 	data.ensure_config(true).await?;
 	let config = data.config.get_mut();
@@ -304,35 +1210,96 @@ async fn update(
 		ids.extend(group.clone());
 	}
 
-	let client = Client::new();
-	let mut lock = Lockfile::open(&data.paths).context("Failed to open lockfile")?;
-	for id in ids {
-		let instance = config
+	let options = UpdateOptions {
+		force,
+		skip_packages,
+		strict,
+		fail_fast,
+		sync_exact,
+		no_snapshot,
+		jobs,
+		timeout,
+		..Default::default()
+	};
+	catch_interrupts(api::update_instances(
+		config,
+		&ids,
+		&options,
+		&data.paths,
+		&mut data.output,
+	))
+	.await
+}
+
+/// Pick which instance to use
+/// Launch an instance, defaulting to the one that was launched last on a side, or on either side
+pub async fn launch_last(
+	instance: Option<String>,
+	side: Option<Side>,
+	data: &mut CmdData,
+) -> anyhow::Result<()> {
+	let instance = match instance {
+		Some(instance) => instance,
+		None => {
+			data.ensure_config(true).await?;
+			pick_last_instance(side, data.config.get(), &data.paths)?.to_string()
+		}
+	};
+
+	launch(
+		Some(instance),
+		None,
+		false,
+		Default::default(),
+		None,
+		true,
+		false,
+		data,
+	)
+	.await
+}
+
+/// Pick the instance that was launched last, falling back to an instance named "default"
+fn pick_last_instance(
+	side: Option<Side>,
+	config: &Config,
+	paths: &Paths,
+) -> anyhow::Result<InstanceID> {
+	let on_side = |id: &InstanceID| {
+		config
 			.instances
-			.get_mut(&id)
-			.with_context(|| format!("Unknown instance '{id}'"))?;
-
-		let mut ctx = InstanceUpdateContext {
-			packages: &mut config.packages,
-			users: &config.users,
-			plugins: &config.plugins,
-			prefs: &config.prefs,
-			paths: &data.paths,
-			lock: &mut lock,
-			client: &client,
-			output: &mut data.output,
-		};
+			.get(id)
+			.is_some_and(|x| side.map_or(true, |side| x.get_side() == side))
+	};
 
-		instance
-			.update(!skip_packages, force, &mut ctx)
-			.await
-			.context("Failed to update instance")?;
+	let last_launched = LastLaunched::open(paths);
+	if let Some(id) = last_launched.get(side) {
+		if on_side(id) {
+			return Ok(id.clone());
+		}
+	}
+	let default = InstanceID::from("default");
+	if on_side(&default) {
+		return Ok(default);
 	}
 
-	Ok(())
+	let kind = match side {
+		Some(Side::Client) => "client",
+		Some(Side::Server) => "server",
+		None => "instance",
+	};
+	let instances = config
+		.instances
+		.keys()
+		.filter(|x| on_side(x))
+		.sorted()
+		.join(", ");
+	if instances.is_empty() {
+		bail!("There is no {kind} to launch. Create one in your config first");
+	}
+	bail!("No {kind} has been launched yet. Choose one to launch: {instances}");
 }
 
-/// Pick which instance to use
 pub fn pick_instance(instance: Option<String>, config: &Config) -> anyhow::Result<InstanceID> {
 	if let Some(instance) = instance {
 		Ok(instance.into())
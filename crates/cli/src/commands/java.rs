@@ -0,0 +1,158 @@
+use super::CmdData;
+
+use anyhow::Context;
+use clap::Subcommand;
+use color_print::cprintln;
+use itertools::Itertools;
+use mcvm::api;
+use mcvm::core::io::java::JavaMajorVersion;
+use mcvm::core::io::persistent::PersistentDataJavaInstallation;
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum JavaSubcommand {
+	#[command(
+		about = "List the Java installations downloaded by mcvm",
+		long_about = "List the Java installations that mcvm downloaded, along with the instances
+that launch with them. Instances that haven't been updated yet aren't shown, since their
+Java version isn't known until then."
+	)]
+	#[clap(alias = "ls")]
+	List {
+		/// Whether to print the list as JSON
+		#[arg(long)]
+		json: bool,
+	},
+	#[command(
+		about = "Download a Java installation",
+		long_about = "Download the latest Java installation of a major version ahead of time,
+without updating any instances. Nothing is downloaded if it is already up to date."
+	)]
+	Install {
+		/// The major Java version to install, such as 17
+		major_version: u16,
+		/// The vendor to download Java from: adoptium, zulu, or graalvm
+		#[arg(long, default_value = "adoptium", value_parser = parse_vendor)]
+		vendor: PersistentDataJavaInstallation,
+	},
+	#[command(
+		about = "Remove a downloaded Java installation",
+		long_about = "Remove the downloaded Java installations of a major version. Installations
+that a configured instance launches with are kept unless --force is used."
+	)]
+	#[clap(alias = "rm")]
+	Remove {
+		/// The major Java version to remove
+		major_version: u16,
+		/// Only remove the installation from this vendor
+		#[arg(long, value_parser = parse_vendor)]
+		vendor: Option<PersistentDataJavaInstallation>,
+		/// Remove the installation even if an instance needs it
+		#[arg(short, long)]
+		force: bool,
+	},
+}
+
+pub async fn run(command: JavaSubcommand, data: &mut CmdData) -> anyhow::Result<()> {
+	match command {
+		JavaSubcommand::List { json } => list(data, json).await,
+		JavaSubcommand::Install {
+			major_version,
+			vendor,
+		} => install(data, major_version, vendor).await,
+		JavaSubcommand::Remove {
+			major_version,
+			vendor,
+			force,
+		} => remove(data, major_version, vendor, force).await,
+	}
+}
+
+fn parse_vendor(string: &str) -> anyhow::Result<PersistentDataJavaInstallation> {
+	PersistentDataJavaInstallation::parse_from_str(string).with_context(|| {
+		format!("Unknown Java vendor '{string}'. Possible values are: adoptium, zulu, graalvm")
+	})
+}
+
+async fn list(data: &mut CmdData, json: bool) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+	let config = data.config.get();
+
+	let runtimes = api::list_java_runtimes(config, &data.paths)?;
+	if json {
+		println!("{}", serde_json::to_string_pretty(&runtimes)?);
+		return Ok(());
+	}
+
+	if runtimes.is_empty() {
+		cprintln!("<s>No Java installations have been downloaded");
+		return Ok(());
+	}
+
+	for runtime in runtimes {
+		let installation = &runtime.installation;
+		cprintln!(
+			"<s>{} {}</> ({}) <k!>{}",
+			installation.vendor.as_str(),
+			installation.major_version,
+			installation.version,
+			installation.path.display()
+		);
+		if runtime.required_by.is_empty() {
+			cprintln!("   <k!>Not used by any instance");
+		} else {
+			cprintln!("   Used by <b>{}", runtime.required_by.iter().join(", "));
+		}
+	}
+
+	Ok(())
+}
+
+async fn install(
+	data: &mut CmdData,
+	major_version: u16,
+	vendor: PersistentDataJavaInstallation,
+) -> anyhow::Result<()> {
+	let path = api::install_java_runtime(
+		JavaMajorVersion::new(major_version),
+		vendor,
+		&data.paths,
+		&mut data.output,
+	)
+	.await?;
+	cprintln!(
+		"<g>Java {major_version} from {} is installed at {}",
+		vendor.as_str(),
+		path.display()
+	);
+
+	Ok(())
+}
+
+async fn remove(
+	data: &mut CmdData,
+	major_version: u16,
+	vendor: Option<PersistentDataJavaInstallation>,
+	force: bool,
+) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+	let config = data.config.get();
+
+	let removed = api::remove_java_runtime(
+		config,
+		JavaMajorVersion::new(major_version),
+		vendor,
+		force,
+		&data.paths,
+	)
+	.await?;
+	for installation in removed {
+		cprintln!(
+			"<g>Removed {} {} ({})",
+			installation.vendor.as_str(),
+			installation.major_version,
+			installation.version
+		);
+	}
+
+	Ok(())
+}
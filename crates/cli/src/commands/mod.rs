@@ -1,38 +1,95 @@
+mod complete;
 mod config;
+mod daemon;
 mod files;
+mod init;
 mod instance;
+mod java;
 mod package;
 mod plugin;
+mod profile;
+mod repo;
 mod user;
 
+use std::future::Future;
+
 use anyhow::{bail, Context};
-use clap::{Parser, Subcommand};
+use clap::{Arg, Command as ClapCommand, CommandFactory, Parser, Subcommand};
 use color_print::{cformat, cprintln};
 
-use mcvm::config::plugin::PluginManager;
+use mcvm::config::init::{InitSides, InitUserType};
+use mcvm::config::plugin::{get_plugin_subcommands, PluginManager};
 use mcvm::config::{Config, ConfigDeser};
+use mcvm::core::util::versions::VersionNotFound;
+use mcvm::instance::create::journal::{request_interrupt, CreationInterrupted};
 use mcvm::io::paths::Paths;
-use mcvm::plugin::hooks::{self, AddTranslations};
+use mcvm::plugin::hooks::{self, AddTranslations, SubcommandDescription};
 use mcvm::shared::later::Later;
-use mcvm::shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm::shared::modifications::Modloader;
+use mcvm::shared::output::{self, MCVMOutput, MessageContents, MessageLevel, Verbosity};
+use mcvm::shared::Side;
 
 use self::config::ConfigSubcommand;
+use self::daemon::DaemonSubcommand;
 use self::files::FilesSubcommand;
 use self::instance::InstanceSubcommand;
+use self::java::JavaSubcommand;
 use self::package::PackageSubcommand;
 use self::plugin::PluginSubcommand;
+use self::profile::ProfileSubcommand;
+use self::repo::RepoSubcommand;
 use self::user::UserSubcommand;
 
 use super::output::TerminalOutput;
 
 #[derive(Debug, Subcommand)]
-pub enum Command {
+pub(crate) enum Command {
+	#[command(
+		about = "Set up a new config",
+		long_about = "Set up a new config by answering a few questions about how you want to play.
+Every question can be answered with a flag instead, and --yes uses defaults for the rest."
+	)]
+	Init {
+		/// The ID of the user to create
+		#[arg(long)]
+		user: Option<String>,
+		/// The kind of user to create: microsoft, demo, or unverified
+		#[arg(long)]
+		user_type: Option<InitUserType>,
+		/// The Minecraft version to play. Defaults to the latest release
+		#[arg(long)]
+		version: Option<String>,
+		/// Which instances to create: client, server, or both
+		#[arg(long)]
+		side: Option<InitSides>,
+		/// The modloader to use: vanilla, fabric, quilt, forge, or neoforged
+		#[arg(long, value_parser = mcvm::config::init::parse_init_modloader)]
+		modloader: Option<Modloader>,
+		/// Download the game files for the new profile after the config is written
+		#[arg(long)]
+		update: bool,
+		/// Don't download the game files for the new profile
+		#[arg(long, conflicts_with = "update")]
+		no_update: bool,
+		/// Don't ask any questions, and use defaults for anything not given with flags
+		#[arg(short, long)]
+		yes: bool,
+		/// Replace the existing config
+		#[arg(short, long)]
+		force: bool,
+	},
 	#[command(about = "Manage instances")]
 	#[clap(alias = "inst")]
 	Instance {
 		#[command(subcommand)]
 		command: InstanceSubcommand,
 	},
+	#[command(about = "Manage profiles")]
+	#[clap(alias = "prof")]
+	Profile {
+		#[command(subcommand)]
+		command: ProfileSubcommand,
+	},
 	#[command(about = "Manage users and authentication")]
 	User {
 		#[command(subcommand)]
@@ -40,8 +97,14 @@ pub enum Command {
 	},
 	#[command(about = "Launch instances to play the game")]
 	Launch {
-		/// The instance to launch
+		/// The instance to launch. Defaults to the instance that was launched last
 		instance: Option<String>,
+		/// Launch the server that was launched last
+		#[arg(long, conflicts_with_all = ["instance", "last_client"])]
+		last_server: bool,
+		/// Launch the client that was launched last
+		#[arg(long, conflicts_with = "instance")]
+		last_client: bool,
 	},
 	#[command(about = "Manage packages")]
 	#[clap(alias = "pkg")]
@@ -49,6 +112,11 @@ pub enum Command {
 		#[command(subcommand)]
 		command: PackageSubcommand,
 	},
+	#[command(about = "Create and maintain your own package repository")]
+	Repo {
+		#[command(subcommand)]
+		command: RepoSubcommand,
+	},
 	#[command(about = "Manage plugins")]
 	#[clap(alias = "plug")]
 	Plugin {
@@ -61,6 +129,18 @@ pub enum Command {
 		#[command(subcommand)]
 		command: ConfigSubcommand,
 	},
+	#[command(about = "Run the daemon that supervises instances")]
+	Serve,
+	#[command(about = "Control the running daemon")]
+	Daemon {
+		#[command(subcommand)]
+		command: DaemonSubcommand,
+	},
+	#[command(about = "Manage the Java installations downloaded by mcvm")]
+	Java {
+		#[command(subcommand)]
+		command: JavaSubcommand,
+	},
 	#[command(about = "Print the mcvm version")]
 	Version,
 	#[command(about = "Deal with files created by mcvm")]
@@ -68,84 +148,254 @@ pub enum Command {
 		#[command(subcommand)]
 		command: FilesSubcommand,
 	},
+	#[command(
+		about = "Print shell completions",
+		long_about = "Print the completion script for a shell to standard out.
+Instance, profile, and package IDs are completed by calling back into mcvm."
+	)]
+	Complete {
+		/// The shell to generate completions for
+		shell: clap_complete::Shell,
+	},
 	#[clap(external_subcommand)]
 	External(Vec<String>),
 }
 
 #[derive(Debug, Parser)]
-pub struct Cli {
+pub(crate) struct Cli {
 	#[command(subcommand)]
 	command: Command,
+	/// Only print errors
+	#[arg(short, long, conflicts_with_all = ["verbose", "debug", "trace"])]
+	quiet: bool,
+	/// Print more messages. Use twice for debug messages and three times for trace messages
+	#[arg(short, long, action = clap::ArgAction::Count)]
+	verbose: u8,
 	#[arg(short, long)]
 	debug: bool,
 	#[arg(short = 'D', long)]
 	trace: bool,
 }
 
+/// An error that ends the program with an exit code without showing a message,
+/// for commands that report their result through the exit code
+#[derive(Debug)]
+pub(crate) struct ExitStatus(pub u8);
+
+impl std::fmt::Display for ExitStatus {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Exited with code {}", self.0)
+	}
+}
+
+impl std::error::Error for ExitStatus {}
+
 /// Run the command line interface
 pub async fn run_cli() -> anyhow::Result<()> {
 	// Parse the CLI
-	let cli = Cli::try_parse();
-	if let Err(e) = &cli {
-		if let clap::error::ErrorKind::DisplayHelp
-		| clap::error::ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
-		| clap::error::ErrorKind::DisplayVersion = e.kind()
-		{
-			println!("{e}");
-			return Ok(());
-		} else {
-			eprintln!("{}", cformat!("<r>{e}"));
-			bail!("");
+	let cli = match Cli::try_parse() {
+		Ok(cli) => cli,
+		Err(e) => {
+			use clap::error::ErrorKind;
+			// Plugins are only asked about their subcommands when help is shown or a subcommand
+			// isn't recognized (like with `mcvm help backup`) so that they don't slow down everything else
+			let e = if let ErrorKind::DisplayHelp
+			| ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+			| ErrorKind::InvalidSubcommand = e.kind()
+			{
+				let help = match get_command_with_plugins().await {
+					Ok(cmd) => cmd.try_get_matches().err(),
+					Err(..) => None,
+				};
+				help.unwrap_or(e)
+			} else {
+				e
+			};
+
+			if let ErrorKind::DisplayHelp
+			| ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+			| ErrorKind::DisplayVersion = e.kind()
+			{
+				println!("{e}");
+				return Ok(());
+			} else {
+				eprintln!("{}", cformat!("<r>{e}"));
+				bail!("");
+			}
 		}
-	}
-	let cli = cli?;
+	};
 
 	// Prepare the command data
 	let mut data = CmdData::new().await?;
-	let log_level = get_log_level(&cli);
-	data.output.set_log_level(log_level);
+	data.output.set_verbosity(get_verbosity(&cli));
+
+	if !matches!(
+		cli.command,
+		Command::Init { .. }
+			| Command::Version
+			| Command::Files { .. }
+			| Command::Repo { .. }
+			| Command::Complete { .. }
+	) {
+		init::offer(&mut data).await?;
+	}
 
 	let res = match cli.command {
+		Command::Init {
+			user,
+			user_type,
+			version,
+			side,
+			modloader,
+			update,
+			no_update,
+			yes,
+			force,
+		} => {
+			let flags = init::InitFlags {
+				user,
+				user_type,
+				version,
+				side,
+				modloader,
+				update: (update || no_update).then_some(update),
+				yes,
+				force,
+			};
+			init::run(&mut data, flags).await
+		}
 		Command::User { command } => user::run(command, &mut data).await,
-		Command::Launch { instance } => instance::launch(instance, None, false, &mut data).await,
+		Command::Launch {
+			instance,
+			last_server,
+			last_client,
+		} => {
+			let side = if last_server {
+				Some(Side::Server)
+			} else if last_client {
+				Some(Side::Client)
+			} else {
+				None
+			};
+			instance::launch_last(instance, side, &mut data).await
+		}
 		Command::Version => {
 			print_version();
 			Ok(())
 		}
 		Command::Files { command } => files::run(command, &mut data).await,
+		Command::Java { command } => java::run(command, &mut data).await,
 		Command::Package { command } => package::run(command, &mut data).await,
+		Command::Repo { command } => repo::run(command).await,
 		Command::Instance { command } => instance::run(command, &mut data).await,
+		Command::Profile { command } => profile::run(command, &mut data).await,
 		Command::Plugin { command } => plugin::run(command, &mut data).await,
 		Command::Config { command } => config::run(command, &mut data).await,
+		Command::Serve => daemon::serve(&mut data).await,
+		Command::Daemon { command } => daemon::run(command, &mut data).await,
+		Command::Complete { shell } => complete::run(shell, &data.paths),
 		Command::External(args) => call_plugin_subcommand(args, &mut data).await,
 	};
 
-	if let Err(e) = &res {
+	if let Some(e) = res.as_ref().err().filter(|e| !e.is::<ExitStatus>()) {
 		// Don't use the existing process or section
-		data.output.end_process();
-		data.output.end_section();
-		data.output.display(
-			MessageContents::Error(format!("{e:?}")),
-			MessageLevel::Important,
-		);
+		data.output.reset_sections();
+		let version_not_found = e.chain().find_map(|x| x.downcast_ref::<VersionNotFound>());
+		let interrupted = e
+			.chain()
+			.find_map(|x| x.downcast_ref::<CreationInterrupted>());
+		if let Some(error) = interrupted {
+			data.output.display(
+				MessageContents::Notice(error.to_string()),
+				MessageLevel::Important,
+			);
+			data.output.display(
+				MessageContents::Error(format!("{e:?}")),
+				MessageLevel::Debug,
+			);
+		} else if let Some(error) = version_not_found {
+			display_version_not_found(error, &mut data.output);
+			data.output.display(
+				MessageContents::Error(format!("{e:?}")),
+				MessageLevel::Debug,
+			);
+		} else {
+			data.output.display(
+				MessageContents::Error(format!("{e:?}")),
+				MessageLevel::Important,
+			);
+		}
 	}
 
 	res
 }
 
-/// Get the log level based on the debug options
-fn get_log_level(cli: &Cli) -> MessageLevel {
-	if cli.trace {
-		MessageLevel::Trace
-	} else if cli.debug {
-		MessageLevel::Debug
+/// Run an update, letting Ctrl-C stop instance creation once the current step is finished instead of
+/// killing mcvm partway through writing files. Pressing it again stops the update right away
+async fn catch_interrupts<T>(update: impl Future<Output = anyhow::Result<T>>) -> anyhow::Result<T> {
+	let interrupts = async {
+		if tokio::signal::ctrl_c().await.is_ok() {
+			eprintln!(
+				"{}",
+				cformat!("<y>Stopping after the current step. Press Ctrl-C again to stop now")
+			);
+			request_interrupt();
+			if tokio::signal::ctrl_c().await.is_ok() {
+				return;
+			}
+		}
+		// Without the signal handler, the update can only end by itself
+		std::future::pending::<()>().await;
+	};
+
+	tokio::select! {
+		result = update => result,
+		_ = interrupts => Err(ExitStatus(130).into()),
+	}
+}
+
+/// Display a missing Minecraft version along with the versions the user might have meant
+fn display_version_not_found(error: &VersionNotFound, o: &mut impl MCVMOutput) {
+	o.display(
+		MessageContents::Error(error.message()),
+		MessageLevel::Important,
+	);
+	if error.snapshot_on_release_channel || error.suggestions.is_empty() {
+		if let Some(hint) = error.hint() {
+			o.display(MessageContents::Notice(hint), MessageLevel::Important);
+		}
+		return;
+	}
+
+	o.display(
+		MessageContents::Notice("Did you mean one of these versions?".into()),
+		MessageLevel::Important,
+	);
+	for suggestion in &error.suggestions {
+		o.display(
+			MessageContents::ListItem(Box::new(MessageContents::Simple(suggestion.clone()))),
+			MessageLevel::Important,
+		);
+	}
+}
+
+/// Get the verbosity based on the quiet, verbose, and debug options
+fn get_verbosity(cli: &Cli) -> Verbosity {
+	if cli.quiet {
+		Verbosity::Quiet
+	} else if cli.trace || cli.verbose >= 3 {
+		Verbosity::Trace
+	} else if cli.debug || cli.verbose == 2 {
+		Verbosity::Debug
+	} else if cli.verbose == 1 {
+		Verbosity::Verbose
 	} else {
-		MessageLevel::Important
+		Verbosity::Normal
 	}
 }
 
 /// Data passed to commands
-pub struct CmdData {
+pub(crate) struct CmdData {
 	pub paths: Paths,
 	pub config: Later<Config>,
 	pub output: TerminalOutput,
@@ -167,19 +417,8 @@ impl CmdData {
 	/// Ensure that the config is loaded
 	pub async fn ensure_config(&mut self, show_warnings: bool) -> anyhow::Result<()> {
 		if self.config.is_empty() {
-			let plugins = PluginManager::load(&self.paths, &mut self.output)
-				.context("Failed to load plugins configuration")?;
-
-			self.config.fill(
-				Config::load(
-					&Config::get_path(&self.paths),
-					plugins,
-					show_warnings,
-					&self.paths,
-					&mut self.output,
-				)
-				.context("Failed to load config")?,
-			);
+			let config = self.load_config(show_warnings)?;
+			self.config.fill(config);
 		}
 
 		// Update the translation map from plugins
@@ -201,6 +440,21 @@ impl CmdData {
 		Ok(())
 	}
 
+	/// Load the config and plugins from their files, without storing them
+	pub fn load_config(&mut self, show_warnings: bool) -> anyhow::Result<Config> {
+		let plugins = PluginManager::load(&self.paths, &mut self.output)
+			.context("Failed to load plugins configuration")?;
+
+		Config::load(
+			&Config::get_path(&self.paths),
+			plugins,
+			show_warnings,
+			&self.paths,
+			&mut self.output,
+		)
+		.context("Failed to load config")
+	}
+
 	/// Get the raw deserialized config
 	pub fn get_raw_config(&self) -> anyhow::Result<ConfigDeser> {
 		let config =
@@ -228,15 +482,10 @@ async fn call_plugin_subcommand(args: Vec<String>, data: &mut CmdData) -> anyhow
 		.first()
 		.context("Subcommand does not have first argument")?;
 
-	{
-		let lock = config.plugins.get_lock()?;
-		let exists = lock
-			.manager
-			.iter_plugins()
-			.any(|x| x.get_manifest().subcommands.contains_key(subcommand));
-		if !exists {
-			bail!("Subcommand '{subcommand}' does not exist");
-		}
+	let subcommands = get_plugin_subcommands(&data.paths, &mut data.output)
+		.context("Failed to get plugin subcommands")?;
+	if !subcommands.iter().any(|x| x.matches(subcommand)) {
+		bail!("Subcommand '{subcommand}' does not exist");
 	}
 
 	let results = config
@@ -249,3 +498,53 @@ async fn call_plugin_subcommand(args: Vec<String>, data: &mut CmdData) -> anyhow
 
 	Ok(())
 }
+
+/// Get the CLI command with the subcommands that plugins provide added to it
+async fn get_command_with_plugins() -> anyhow::Result<ClapCommand> {
+	let paths = Paths::new()
+		.await
+		.context("Failed to set up system paths")?;
+	command_with_plugins(&paths)
+}
+
+/// Get the CLI command with the subcommands that plugins provide added to it
+pub fn command_with_plugins(paths: &Paths) -> anyhow::Result<ClapCommand> {
+	let subcommands = get_plugin_subcommands(paths, &mut output::NoOp)?;
+	Ok(add_plugin_subcommands(Cli::command(), &subcommands))
+}
+
+/// Add plugin subcommands to the CLI command. They take all of their arguments
+/// as-is, and can't replace the built-in subcommands
+fn add_plugin_subcommands(
+	mut cmd: ClapCommand,
+	subcommands: &[SubcommandDescription],
+) -> ClapCommand {
+	for subcommand in subcommands {
+		if cmd.find_subcommand(&subcommand.name).is_some() {
+			continue;
+		}
+		let aliases = subcommand
+			.aliases
+			.iter()
+			.filter(|x| cmd.find_subcommand(x).is_none())
+			.cloned()
+			.collect::<Vec<_>>();
+		let plugin_cmd = ClapCommand::new(subcommand.name.clone())
+			.about(subcommand.about.clone())
+			.visible_aliases(aliases)
+			.after_help(format!(
+				"Run `mcvm {} --help` for the plugin's own help",
+				subcommand.name
+			))
+			.disable_help_flag(true)
+			.arg(
+				Arg::new("args")
+					.num_args(0..)
+					.trailing_var_arg(true)
+					.allow_hyphen_values(true),
+			);
+		cmd = cmd.subcommand(plugin_cmd);
+	}
+
+	cmd
+}
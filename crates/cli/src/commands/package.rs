@@ -1,22 +1,37 @@
+use std::path::{Path, PathBuf};
 use std::{collections::HashMap, sync::Arc};
 
 use super::CmdData;
 use itertools::Itertools;
+use mcvm::api::{self, PackagePreview};
+use mcvm::core::io::update::UpdateManager;
+use mcvm::core::net::download;
+use mcvm::core::net::game_files::version_manifest::{self, make_version_list};
+use mcvm::io::lock::{InstalledPackage, Lockfile};
 use mcvm::parse::lex::Token;
+use mcvm::pkg::eval::cases::EvalCaseStatus;
+use mcvm::pkg::search::{update_search_index, SearchFilters};
+use mcvm::pkg_crate::metadata::PackageMetadata;
+use mcvm::pkg_crate::properties::PackageProperties;
+use mcvm::pkg_crate::resolve::InstallReason;
 use mcvm::pkg_crate::{PackageContentType, PkgRequest, PkgRequestSource};
 use mcvm::shared::id::{InstanceID, ProfileID};
 use mcvm::shared::util::print::ReplPrinter;
+use mcvm::shared::Side;
 
 use anyhow::{bail, Context};
 use clap::Subcommand;
 use color_print::{cformat, cprint, cprintln};
 use mcvm::shared::pkg::PackageID;
-use reqwest::Client;
+use serde::Serialize;
 
-use crate::output::HYPHEN_POINT;
+use crate::output::{terminal_width, wrap_text, HYPHEN_POINT};
+
+/// Indentation for the fields of `package info`
+const INFO_INDENT: &str = "   ";
 
 #[derive(Debug, Subcommand)]
-pub enum PackageSubcommand {
+pub(crate) enum PackageSubcommand {
 	#[command(about = "List all installed packages across all profiles")]
 	#[clap(alias = "ls")]
 	List {
@@ -26,6 +41,13 @@ pub enum PackageSubcommand {
 		/// An instance to filter packages from
 		#[arg(short, long)]
 		instance: Option<String>,
+		/// List every package available from the configured repositories instead
+		/// of only the installed ones
+		#[arg(short, long, conflicts_with = "instance")]
+		available: bool,
+		/// List the files that each package put into the instance
+		#[arg(long, requires = "instance")]
+		files: bool,
 	},
 	#[command(
 		about = "Sync package indexes with ones from package repositories",
@@ -37,6 +59,16 @@ cached locally, but all currently cached package scripts will be removed"
 		#[arg(short, long)]
 		filter: Vec<String>,
 	},
+	#[command(
+		about = "Download packages again, replacing their cached copies",
+		long_about = "Download packages again from their repositories, even if the cached copies
+look current. Useful when a repository changes a package without changing its index."
+	)]
+	Refresh {
+		/// The packages to refresh
+		#[arg(required = true)]
+		packages: Vec<String>,
+	},
 	#[command(
 		about = "Print the contents of a package to standard out",
 		long_about = "Print the contents of any package to standard out.
@@ -50,8 +82,25 @@ This package does not need to be installed, it just has to be in the index."
 		/// The package to print
 		package: String,
 	},
-	#[command(about = "Print information about a specific package")]
+	#[command(
+		about = "Print information about a specific package",
+		long_about = "Print the metadata and properties of any package in the index.
+With --preview, the package is also evaluated for every instance of a profile to show
+the addons and dependencies that installing it would produce."
+	)]
 	Info {
+		/// Whether to describe each of the features of the package
+		#[arg(short, long)]
+		features: bool,
+		/// Whether to print the information as JSON
+		#[arg(long)]
+		json: bool,
+		/// Whether to show what installing the package on a profile would produce
+		#[arg(long, requires = "profile")]
+		preview: bool,
+		/// The profile to preview the package on
+		#[arg(long, requires = "preview")]
+		profile: Option<String>,
 		/// The package to get info about
 		package: String,
 	},
@@ -64,10 +113,70 @@ This package does not need to be installed, it just has to be in the index."
 	},
 	#[command(about = "Browse packages from the remote repositories")]
 	Browse {},
+	#[command(
+		about = "Search for packages in the configured repositories",
+		long_about = "Search for packages by their ID, name, and description. The search index is
+built from the cached repository indexes the first time you search, and is rebuilt
+for a repository when its index changes. Filters are only applied to packages that
+declare the property that is being filtered."
+	)]
+	Search {
+		/// The words to search for. Leave empty to list every package
+		query: Vec<String>,
+		/// Only show packages that support this modloader or plugin loader
+		#[arg(short, long)]
+		loader: Option<String>,
+		/// Only show packages that support this side (client or server)
+		#[arg(short, long)]
+		side: Option<String>,
+		/// Only show packages that support this Minecraft version
+		#[arg(short, long)]
+		mc_version: Option<String>,
+		/// The page of results to show
+		#[arg(short, long, default_value_t = 1)]
+		page: usize,
+		/// The number of results on each page
+		#[arg(long, default_value_t = 20)]
+		per_page: usize,
+		/// Whether to remove formatting and warnings from the output
+		#[arg(short, long)]
+		raw: bool,
+	},
+	#[command(
+		about = "Remove packages that are no longer needed",
+		long_about = "Remove the packages that were installed as dependencies of other packages
+but are no longer required by any configured package. Packages from local files and
+packages installed before mcvm recorded why they were installed are never removed."
+	)]
+	Autoremove {
+		/// The profile to remove unneeded packages from
+		#[arg(short, long)]
+		profile: String,
+		/// Only print the packages that would be removed
+		#[arg(long)]
+		dry_run: bool,
+	},
+	#[command(
+		about = "Test a package against a directory of cases",
+		long_about = "Evaluate a package with the input of every JSON case file in a directory
+and compare the addons, relations, notices, and variables that it produces with the
+expected result in each case. Use --update to write the current results into the cases
+as their new expected results."
+	)]
+	Test {
+		/// The package to test
+		package: String,
+		/// The directory containing the case files
+		#[arg(long)]
+		cases: PathBuf,
+		/// Replace the expected results of the cases instead of checking them
+		#[arg(long)]
+		update: bool,
+	},
 }
 
 #[derive(Debug, Subcommand)]
-pub enum RepoSubcommand {
+pub(crate) enum RepoSubcommand {
 	#[command(about = "List all configured package repositories")]
 	#[clap(alias = "ls")]
 	List {
@@ -84,37 +193,214 @@ pub enum RepoSubcommand {
 
 pub async fn run(subcommand: PackageSubcommand, data: &mut CmdData) -> anyhow::Result<()> {
 	match subcommand {
-		PackageSubcommand::List { raw, instance } => list(data, raw, instance).await,
+		PackageSubcommand::List {
+			raw,
+			instance,
+			available,
+			files,
+		} => list(data, raw, instance, available, files).await,
 		PackageSubcommand::Sync { filter } => sync(data, filter).await,
+		PackageSubcommand::Refresh { packages } => refresh(data, packages).await,
 		PackageSubcommand::Cat { raw, package } => cat(data, &package, raw).await,
-		PackageSubcommand::Info { package } => info(data, &package).await,
+		PackageSubcommand::Info {
+			features,
+			json,
+			preview: _,
+			profile,
+			package,
+		} => info(data, &package, features, json, profile).await,
 		PackageSubcommand::Repository { command } => repo(command, data).await,
 		PackageSubcommand::Browse {} => browse(data).await,
+		PackageSubcommand::Search {
+			query,
+			loader,
+			side,
+			mc_version,
+			page,
+			per_page,
+			raw,
+		} => {
+			let filters = SearchArgs {
+				loader,
+				side,
+				mc_version,
+			};
+			search(data, &query.join(" "), filters, page, per_page, raw).await
+		}
+		PackageSubcommand::Autoremove { profile, dry_run } => {
+			autoremove(data, &profile, dry_run).await
+		}
+		PackageSubcommand::Test {
+			package,
+			cases,
+			update,
+		} => test(data, &package, &cases, update).await,
+	}
+}
+
+async fn test(data: &mut CmdData, package: &str, cases: &Path, update: bool) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+	let config = data.config.get_mut();
+
+	let results = api::test_package(
+		config,
+		package,
+		cases,
+		update,
+		&data.paths,
+		&mut data.output,
+	)
+	.await?;
+	if results.is_empty() {
+		bail!("No case files were found in {}", cases.display());
+	}
+
+	let mut failed = 0;
+	for result in &results {
+		match &result.status {
+			EvalCaseStatus::Passed => cprintln!("<g>PASS</> {}", result.name),
+			EvalCaseStatus::Updated => cprintln!("<y>UPDATED</> {}", result.name),
+			EvalCaseStatus::MissingSnapshot => {
+				cprintln!("<r>FAIL</> {} <k!>(no expected result)", result.name)
+			}
+			EvalCaseStatus::Failed(differences) => {
+				cprintln!("<r>FAIL</> {}", result.name);
+				for difference in differences {
+					println!("{HYPHEN_POINT}{difference}");
+				}
+			}
+		}
+		if result.is_failure() {
+			failed += 1;
+		}
+	}
+
+	if failed > 0 {
+		bail!("{failed} of {} cases failed", results.len());
+	}
+	if !update {
+		cprintln!("<s,g>All {} cases passed", results.len());
+	}
+
+	Ok(())
+}
+
+async fn autoremove(data: &mut CmdData, profile: &str, dry_run: bool) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+	let config = data.config.get();
+
+	let removed =
+		api::autoremove_packages(config, profile, dry_run, &data.paths, &mut data.output)?;
+	if removed.is_empty() {
+		cprintln!("<s>No packages need to be removed");
+		return Ok(());
+	}
+
+	for (instance, packages) in removed {
+		if dry_run {
+			cprintln!("<s>Would remove from instance <b>{}</b>:", instance);
+		} else {
+			cprintln!("<s>Removed from instance <b>{}</b>:", instance);
+		}
+		for package in packages {
+			cprintln!("{}<b!>{}</>", HYPHEN_POINT, package);
+		}
+	}
+
+	Ok(())
+}
+
+/// Describe why an installed package is on an instance
+fn describe_install_reason(package: &InstalledPackage) -> String {
+	let parents = package.required_by.iter().join(", ");
+	let out = match package.reason {
+		Some(InstallReason::Explicit) => "explicit".into(),
+		Some(InstallReason::Dependency) => format!("dependency of {parents}"),
+		Some(InstallReason::Bundled) => format!("bundled by {parents}"),
+		None => "unknown reason".into(),
+	};
+	if package.local {
+		format!("{out}, local")
+	} else {
+		out
 	}
 }
 
-async fn list(data: &mut CmdData, raw: bool, instance: Option<String>) -> anyhow::Result<()> {
+async fn list(
+	data: &mut CmdData,
+	raw: bool,
+	instance: Option<String>,
+	available: bool,
+	files: bool,
+) -> anyhow::Result<()> {
 	data.ensure_config(!raw).await?;
 	let config = data.config.get_mut();
 
-	if let Some(instance_id) = instance {
+	if available {
+		let client = config.client.clone();
+		let packages = config
+			.packages
+			.get_all_available_packages(&data.paths, &client, &mut data.output)
+			.await
+			.context("Failed to get list of available packages")?;
+		if !raw {
+			cprintln!("<s>Available packages:");
+		}
+		for pkg in packages.iter().map(|x| &x.id).sorted().dedup() {
+			if raw {
+				println!("{pkg}");
+			} else {
+				cprintln!("{}<b!>{}</>", HYPHEN_POINT, pkg);
+			}
+		}
+	} else if let Some(instance_id) = instance {
 		let instance_id = InstanceID::from(instance_id);
 		let instance = config
 			.instances
-			.get(&instance_id)
+			.get_mut(&instance_id)
 			.with_context(|| format!("Unknown instance '{instance_id}'"))?;
+		instance.ensure_dirs_set(&data.paths);
+		let game_dir = instance.get_dirs().get().game_dir.clone();
+		let lock = Lockfile::open(&data.paths).context("Failed to open lockfile")?;
+		let installed = lock.get_instance_packages(&instance_id);
+		let not_installed = instance
+			.get_configured_packages()
+			.iter()
+			.filter(|x| !installed.iter().any(|y| y.id == x.id));
 		if !raw {
 			cprintln!("<s>Packages in instance <b>{}</b>:", instance_id);
 		}
-		for pkg in instance
-			.get_configured_packages()
-			.iter()
-			.sorted_by_key(|x| &x.id)
-		{
+		for pkg in &installed {
+			if raw {
+				println!("{}", pkg.id);
+			} else {
+				cprintln!(
+					"{}<b!>{}</> <k!>({})",
+					HYPHEN_POINT,
+					pkg.id,
+					describe_install_reason(pkg)
+				);
+			}
+			if files {
+				for file in lock
+					.get_package_files(&instance_id, &pkg.id)
+					.iter()
+					.sorted()
+				{
+					let file = file.strip_prefix(&game_dir).unwrap_or(file);
+					if raw {
+						println!("\t{}", file.display());
+					} else {
+						cprintln!("{}{}<k!>{}", INFO_INDENT, HYPHEN_POINT, file.display());
+					}
+				}
+			}
+		}
+		for pkg in not_installed.sorted_by_key(|x| &x.id) {
 			if raw {
 				println!("{}", pkg.id);
 			} else {
-				cprintln!("{}<b!>{}</>", HYPHEN_POINT, pkg.id);
+				cprintln!("{}<b!>{}</> <k!>(not installed)", HYPHEN_POINT, pkg.id);
 			}
 		}
 	} else {
@@ -150,7 +436,7 @@ async fn sync(data: &mut CmdData, filter: Vec<String>) -> anyhow::Result<()> {
 	let config = data.config.get_mut();
 
 	let mut printer = ReplPrinter::new(true);
-	let client = Client::new();
+	let client = config.client.clone();
 	for repo in config.packages.repos.iter_mut() {
 		// Skip repositories not in the filter
 		if !filter.is_empty() && !filter.contains(&repo.id) {
@@ -158,7 +444,7 @@ async fn sync(data: &mut CmdData, filter: Vec<String>) -> anyhow::Result<()> {
 		}
 
 		printer.print(&cformat!("Syncing repository <b>{}</b>...", repo.id));
-		match repo.sync(&data.paths, &client).await {
+		match repo.sync(&data.paths, &client, &mut data.output).await {
 			Ok(..) => {
 				printer.print(&cformat!("<g>Synced repository <b!>{}</b!>", repo.id));
 			}
@@ -173,10 +459,26 @@ async fn sync(data: &mut CmdData, filter: Vec<String>) -> anyhow::Result<()> {
 		};
 		cprintln!();
 	}
+
+	let unverified: Vec<_> = config
+		.packages
+		.repos
+		.iter()
+		.filter(|x| !x.is_verified() && (filter.is_empty() || filter.contains(&x.id)))
+		.map(|x| x.id.as_str())
+		.collect();
+	if !unverified.is_empty() {
+		cprintln!(
+			"<y>Warning: The following repositories do not have a public key and are unverified: {}",
+			unverified.join(", ")
+		);
+	}
+
 	printer.print(&cformat!("<s>Updating packages..."));
-	config
+	let update = config
 		.packages
-		.update_cached_packages(&data.paths, &client, &mut data.output)
+		.update_cached_packages(&data.paths, &client, &mut data.output);
+	download::with_limits(config.prefs.download_limits(), update)
 		.await
 		.context("Failed to update cached packages")?;
 	printer.println(&cformat!("<s>Validating packages..."));
@@ -198,13 +500,37 @@ async fn sync(data: &mut CmdData, filter: Vec<String>) -> anyhow::Result<()> {
 	Ok(())
 }
 
+async fn refresh(data: &mut CmdData, packages: Vec<String>) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+	let config = data.config.get_mut();
+	let client = config.client.clone();
+
+	for package in packages {
+		let req = Arc::new(PkgRequest::parse_and_validate(
+			package,
+			PkgRequestSource::UserRequire,
+		)?);
+		config
+			.packages
+			.refresh(&req, &data.paths, &client, &mut data.output)
+			.await
+			.with_context(|| format!("Failed to refresh package '{req}'"))?;
+		cprintln!("<g>Refreshed package <b>{}</>", req);
+	}
+
+	Ok(())
+}
+
 async fn cat(data: &mut CmdData, id: &str, raw: bool) -> anyhow::Result<()> {
 	data.ensure_config(!raw).await?;
 	let config = data.config.get_mut();
 
-	let client = Client::new();
+	let client = config.client.clone();
 
-	let req = Arc::new(PkgRequest::parse(id, PkgRequestSource::UserRequire));
+	let req = Arc::new(PkgRequest::parse_and_validate(
+		id,
+		PkgRequestSource::UserRequire,
+	)?);
 	let contents = config
 		.packages
 		.load(&req, &data.paths, &client, &mut data.output)
@@ -307,26 +633,92 @@ fn pretty_print_package_script(contents: &str) -> anyhow::Result<()> {
 	Ok(())
 }
 
-async fn info(data: &mut CmdData, id: &str) -> anyhow::Result<()> {
-	data.ensure_config(true).await?;
+/// Information about a package, printed by `package info --json`
+#[derive(Serialize)]
+struct PackageInfo {
+	id: String,
+	repository: Option<String>,
+	shadowed: Vec<String>,
+	metadata: PackageMetadata,
+	properties: PackageProperties,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	preview: Option<Vec<PackagePreview>>,
+}
+
+async fn info(
+	data: &mut CmdData,
+	id: &str,
+	features: bool,
+	json: bool,
+	profile: Option<String>,
+) -> anyhow::Result<()> {
+	data.ensure_config(!json).await?;
 	let config = data.config.get_mut();
 
-	let client = Client::new();
+	let client = config.client.clone();
 
-	let req = Arc::new(PkgRequest::parse(id, PkgRequestSource::UserRequire));
+	let req = Arc::new(PkgRequest::parse_and_validate(
+		id,
+		PkgRequestSource::UserRequire,
+	)?);
+	let sources = config
+		.packages
+		.get_package_sources(&req, &data.paths, &client, &mut data.output)
+		.await
+		.context("Failed to find the repositories of the package")?;
 	let metadata = config
 		.packages
 		.get_metadata(&req, &data.paths, &client, &mut data.output)
+		.await;
+	let metadata = match metadata {
+		Ok(metadata) => metadata.clone(),
+		// Packages that aren't in any repository can still come from plugins, so the
+		// registry is only blamed once that lookup has failed too
+		Err(e) if sources.repository.is_none() => {
+			let repos = config.packages.get_repos().iter().map(|x| &x.id).join(", ");
+			return Err(e.context(format!(
+				"Package '{id}' was not found in any repository ({repos}). Try running `mcvm package sync`"
+			)));
+		}
+		Err(e) => return Err(e.context("Failed to get metadata from the registry")),
+	};
+	let properties = config
+		.packages
+		.get_properties(&req, &data.paths, &client, &mut data.output)
 		.await
-		.context("Failed to get metadata from the registry")?;
+		.context("Failed to get properties from the registry")?
+		.clone();
+	let preview = if let Some(profile) = &profile {
+		let preview = api::preview_package(config, id, profile, &data.paths, &mut data.output)
+			.await
+			.with_context(|| format!("Failed to preview package on profile '{profile}'"))?;
+		Some(preview)
+	} else {
+		None
+	};
+
+	if json {
+		let info = PackageInfo {
+			id: id.into(),
+			repository: sources.repository,
+			shadowed: sources.shadowed,
+			metadata,
+			properties,
+			preview,
+		};
+		println!("{}", serde_json::to_string_pretty(&info)?);
+		return Ok(());
+	}
+
 	if let Some(name) = &metadata.name {
 		cprintln!("<s><g>Package</g> <b>{}</b>", name);
 	} else {
 		cprintln!("<s><g>Package</g> <b>{}</b>", id);
 	}
 	if let Some(description) = &metadata.description {
-		if !description.is_empty() {
-			cprintln!("   <s>{}", description);
+		let width = terminal_width().saturating_sub(INFO_INDENT.len());
+		for line in wrap_text(description, width) {
+			cprintln!("{}<s>{}", INFO_INDENT, line);
 		}
 	}
 	if let Some(long_description) = &metadata.long_description {
@@ -335,6 +727,15 @@ async fn info(data: &mut CmdData, id: &str) -> anyhow::Result<()> {
 		}
 	}
 	cprintln!("   <s>ID:</s> <g>{}", id);
+	if let Some(repository) = &sources.repository {
+		cprintln!("   <s>Repository:</s> <m>{}", repository);
+	}
+	if !sources.shadowed.is_empty() {
+		cprintln!(
+			"   <s>Shadows:</s> <m>{}</> <k!>(lower-priority definitions that are not used)",
+			sources.shadowed.join(", ")
+		);
+	}
 	if let Some(authors) = &metadata.authors {
 		if !authors.is_empty() {
 			cprintln!("   <s>Authors:</s> <g>{}", authors.join(", "));
@@ -384,9 +785,158 @@ async fn info(data: &mut CmdData, id: &str) -> anyhow::Result<()> {
 		}
 	}
 
+	print_support(&properties);
+	if features {
+		print_features(&properties);
+	} else if let Some(features) = &properties.features {
+		if !features.is_empty() {
+			cprintln!("   <s>Features:</s> {}", features.join(", "));
+		}
+	}
+
+	if let (Some(profile), Some(preview)) = (profile, preview) {
+		print_preview(&profile, &preview);
+	}
+
 	Ok(())
 }
 
+/// Print which versions, loaders, and sides a package supports
+fn print_support(properties: &PackageProperties) {
+	if let Some(versions) = &properties.supported_versions {
+		cprintln!(
+			"   <s>Supported Versions:</s> <c>{}",
+			versions.iter().join(", ")
+		);
+	}
+	if let Some(modloaders) = &properties.supported_modloaders {
+		cprintln!(
+			"   <s>Supported Modloaders:</s> <c>{}",
+			modloaders.iter().map(get_match_name).join(", ")
+		);
+	}
+	if let Some(plugin_loaders) = &properties.supported_plugin_loaders {
+		cprintln!(
+			"   <s>Supported Plugin Loaders:</s> <c>{}",
+			plugin_loaders.iter().map(get_match_name).join(", ")
+		);
+	}
+	if let Some(sides) = &properties.supported_sides {
+		cprintln!("   <s>Supported Sides:</s> <c>{}", sides.iter().join(", "));
+	}
+}
+
+/// Get the name of a loader match as it is written in packages
+fn get_match_name(matcher: &impl Serialize) -> String {
+	match serde_json::to_value(matcher) {
+		Ok(serde_json::Value::String(name)) => name,
+		_ => "unknown".into(),
+	}
+}
+
+/// Print what installing a package on the instances of a profile would produce
+fn print_preview(profile: &str, preview: &[PackagePreview]) {
+	cprintln!("<s>Installing on profile <b>{}</b> would produce:", profile);
+	for instance in preview {
+		cprintln!("   <s>{}</> <k!>({})", instance.instance, instance.side);
+		if let Some(error) = &instance.error {
+			cprintln!("      <r>{}", error);
+			continue;
+		}
+		if instance.addons.is_empty() {
+			cprintln!("      <s>Addons:</s> None");
+		} else {
+			cprintln!("      <s>Addons:");
+			for addon in &instance.addons {
+				let version = addon
+					.version
+					.as_ref()
+					.map(|x| cformat!(" <k!>({})", x))
+					.unwrap_or_default();
+				cprintln!(
+					"      {}<b!>{}</> <k!>[{}]</> {}{}",
+					HYPHEN_POINT,
+					addon.id,
+					addon.kind,
+					addon.file_name,
+					version
+				);
+			}
+		}
+		if !instance.dependencies.is_empty() {
+			cprintln!(
+				"      <s>Dependencies:</s> <g>{}",
+				instance
+					.dependencies
+					.iter()
+					.map(|x| x.iter().join(" | "))
+					.join(", ")
+			);
+		}
+		if !instance.recommendations.is_empty() {
+			cprintln!(
+				"      <s>Recommendations:</s> <g>{}",
+				instance.recommendations.iter().join(", ")
+			);
+		}
+		if !instance.conflicts.is_empty() {
+			cprintln!(
+				"      <s>Conflicts:</s> <y>{}",
+				instance.conflicts.iter().join(", ")
+			);
+		}
+		for notice in &instance.notices {
+			cprintln!("      <s>Notice:</s> {}", notice);
+		}
+	}
+}
+
+/// Print the features and feature groups of a package
+fn print_features(properties: &PackageProperties) {
+	let default_features = properties.default_features.clone().unwrap_or_default();
+	let features = properties.features.clone().unwrap_or_default();
+	if features.is_empty() {
+		cprintln!("   <s>Features:</s> None");
+		return;
+	}
+
+	cprintln!("   <s>Features:");
+	for feature in features {
+		let default = if default_features.contains(&feature) {
+			" (default)"
+		} else {
+			""
+		};
+		let description = properties
+			.feature_descriptions
+			.as_ref()
+			.and_then(|x| x.get(&feature));
+		if let Some(description) = description {
+			cprintln!(
+				"   {}<b!>{}</><s>{}</> - {}",
+				HYPHEN_POINT,
+				feature,
+				default,
+				description
+			);
+		} else {
+			cprintln!("   {}<b!>{}</><s>{}", HYPHEN_POINT, feature, default);
+		}
+	}
+
+	if let Some(groups) = &properties.feature_groups {
+		cprintln!("   <s>Feature groups:");
+		for (group, group_features) in groups.iter().sorted_by_key(|x| x.0) {
+			cprintln!(
+				"   {}<b!>{}</> - {}",
+				HYPHEN_POINT,
+				group,
+				group_features.join(", ")
+			);
+		}
+	}
+}
+
 async fn repo(subcommand: RepoSubcommand, data: &mut CmdData) -> anyhow::Result<()> {
 	match subcommand {
 		RepoSubcommand::List { raw } => repo_list(data, raw).await,
@@ -431,7 +981,7 @@ async fn repo_info(data: &mut CmdData, repo_id: String) -> anyhow::Result<()> {
 	};
 
 	// Get the repo package count and metadata
-	let client = Client::new();
+	let client = config.client.clone();
 
 	let pkg_count = repo
 		.get_package_count(&data.paths, &client, &mut data.output)
@@ -480,7 +1030,7 @@ async fn browse(data: &mut CmdData) -> anyhow::Result<()> {
 	data.ensure_config(true).await?;
 	let config = data.config.get_mut();
 
-	let client = Client::new();
+	let client = config.client.clone();
 	let mut packages = config
 		.packages
 		.get_all_available_packages(&data.paths, &client, &mut data.output)
@@ -493,7 +1043,7 @@ async fn browse(data: &mut CmdData) -> anyhow::Result<()> {
 			inquire::Select::new("Browse packages. Press Escape to exit.", packages.clone());
 		let package = select.prompt_skippable()?;
 		if let Some(package) = package {
-			info(data, &package.id).await?;
+			info(data, &package.id, false, false, None).await?;
 			inquire::Confirm::new("Press Escape to return to browse page").prompt_skippable()?;
 		} else {
 			break;
@@ -502,3 +1052,100 @@ async fn browse(data: &mut CmdData) -> anyhow::Result<()> {
 
 	Ok(())
 }
+
+/// Filters for `package search` as they were given on the command line
+struct SearchArgs {
+	loader: Option<String>,
+	side: Option<String>,
+	mc_version: Option<String>,
+}
+
+async fn search(
+	data: &mut CmdData,
+	query: &str,
+	args: SearchArgs,
+	page: usize,
+	per_page: usize,
+	raw: bool,
+) -> anyhow::Result<()> {
+	data.ensure_config(!raw).await?;
+	let config = data.config.get_mut();
+
+	let mut filters = SearchFilters {
+		loader: args.loader.as_deref().map(str::parse).transpose()?,
+		..Default::default()
+	};
+	if let Some(side) = &args.side {
+		let side = Side::parse_from_str(side)
+			.with_context(|| format!("Unknown side '{side}'. Use client or server"))?;
+		filters.side = Some(side);
+	}
+	if let Some(version) = args.mc_version {
+		let manager = UpdateManager::new(false, true);
+		let manifest =
+			version_manifest::get(&data.paths.core, &manager, &config.client, &mut data.output)
+				.await
+				.context("Failed to get version manifest")?;
+		filters.version_list = make_version_list(&manifest);
+		filters.version = Some(version);
+	}
+
+	let client = config.client.clone();
+	if !raw {
+		cprintln!("<s>Loading search index...");
+	}
+	let index = update_search_index(&mut config.packages, &data.paths, &client, &mut data.output)
+		.await
+		.context("Failed to update search index")?;
+	let results = index.search(query, &filters);
+
+	let per_page = per_page.max(1);
+	let page_count = results.len().div_ceil(per_page).max(1);
+	if page == 0 || page > page_count {
+		bail!("Page {page} does not exist. There are {page_count} pages of results");
+	}
+	let page_results = results.iter().skip((page - 1) * per_page).take(per_page);
+
+	if raw {
+		for entry in page_results {
+			println!("{}", entry.id);
+		}
+		return Ok(());
+	}
+
+	if results.is_empty() {
+		cprintln!("<s>No packages were found");
+		return Ok(());
+	}
+
+	cprintln!(
+		"<s>{:<30} {:<12} {}",
+		"PACKAGE",
+		"REPOSITORY",
+		"DESCRIPTION"
+	);
+	let description_width = terminal_width().saturating_sub(44).max(20);
+	for entry in page_results {
+		let description = entry.description.as_deref().unwrap_or_default();
+		// Only the first line of the description is shown to keep one package per line
+		let lines = wrap_text(description, description_width);
+		let mut description = lines.first().cloned().unwrap_or_default();
+		if lines.len() > 1 {
+			description.push_str("...");
+		}
+		cprintln!(
+			"<b!>{:<30}</> <m>{:<12}</> {}",
+			entry.id,
+			entry.repository,
+			description
+		);
+	}
+	cprintln!(
+		"<k!>Page {} of {} ({} results). Use --page to see more",
+		page,
+		page_count,
+		results.len()
+	);
+
+	Ok(())
+}
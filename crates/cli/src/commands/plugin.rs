@@ -1,47 +1,143 @@
-use anyhow::Context;
+use anyhow::{bail, Context};
 use clap::Subcommand;
-use color_print::cprintln;
-use itertools::Itertools;
+use color_print::{cformat, cprintln};
+use mcvm::config::plugin::{
+	get_installed_plugins, install_plugin, read_plugin_manifest, PluginsConfig,
+};
+use mcvm::config::preferences::ProxyConfig;
+use mcvm::core::net::download::create_client;
 
 use super::CmdData;
 use crate::output::HYPHEN_POINT;
 
 #[derive(Debug, Subcommand)]
-pub enum PluginSubcommand {
-	#[command(about = "List all enabled plugins")]
+pub(crate) enum PluginSubcommand {
+	#[command(about = "List all installed plugins")]
 	#[clap(alias = "ls")]
 	List {
 		/// Whether to remove formatting and warnings from the output
 		#[arg(short, long)]
 		raw: bool,
+		/// Only list enabled plugins
+		#[arg(short, long)]
+		enabled: bool,
 	},
 	#[command(about = "Print useful information about a plugin")]
 	Info { plugin: String },
+	#[command(about = "Enable an installed plugin")]
+	Enable { plugin: String },
+	#[command(about = "Disable a plugin so that it isn't loaded")]
+	Disable { plugin: String },
+	#[command(about = "Install a plugin from a manifest file, plugin directory, or manifest URL")]
+	Install {
+		/// The path or URL to install the plugin from
+		source: String,
+		/// The ID to install the plugin as. Defaults to the name of the source
+		#[arg(short, long)]
+		id: Option<String>,
+		/// Don't enable the plugin after installing it
+		#[arg(long)]
+		no_enable: bool,
+	},
 }
 
 pub async fn run(command: PluginSubcommand, data: &mut CmdData) -> anyhow::Result<()> {
 	match command {
-		PluginSubcommand::List { raw } => list(data, raw).await,
+		PluginSubcommand::List { raw, enabled } => list(data, raw, enabled).await,
 		PluginSubcommand::Info { plugin } => info(data, plugin).await,
+		PluginSubcommand::Enable { plugin } => set_enabled(data, plugin, true).await,
+		PluginSubcommand::Disable { plugin } => set_enabled(data, plugin, false).await,
+		PluginSubcommand::Install {
+			source,
+			id,
+			no_enable,
+		} => install(data, source, id, no_enable).await,
 	}
 }
 
-async fn list(data: &mut CmdData, raw: bool) -> anyhow::Result<()> {
-	data.ensure_config(!raw).await?;
-	let config = data.config.get_mut();
+async fn list(data: &mut CmdData, raw: bool, enabled_only: bool) -> anyhow::Result<()> {
+	let config = PluginsConfig::open(&data.paths)?;
+	let plugins = get_installed_plugins(&data.paths)?;
 
-	let lock = config.plugins.get_lock()?;
-	for plugin in lock.manager.iter_plugins().sorted_by_key(|x| x.get_id()) {
+	for plugin in plugins {
+		let enabled = config.is_enabled(&plugin);
+		if enabled_only && !enabled {
+			continue;
+		}
 		if raw {
-			println!("{}", plugin.get_id());
+			println!("{plugin}");
+			continue;
+		}
+
+		let manifest = read_plugin_manifest(&plugin, &data.paths).ok();
+		let name = manifest.as_ref().and_then(|(x, _)| x.name.clone());
+		let state = if !enabled {
+			cformat!("<k!>disabled")
+		} else if manifest
+			.as_ref()
+			.is_some_and(|(x, _)| !x.is_protocol_supported())
+		{
+			cformat!("<y>unsupported")
+		} else {
+			cformat!("<g>enabled")
+		};
+		if let Some(name) = name {
+			cprintln!("{}<s>{}</> - {} [{}]", HYPHEN_POINT, plugin, name, state);
 		} else {
-			cprintln!("{}<s>{}", HYPHEN_POINT, plugin.get_id());
+			cprintln!("{}<s>{}</> [{}]", HYPHEN_POINT, plugin, state);
 		}
 	}
 
 	Ok(())
 }
 
+async fn set_enabled(data: &mut CmdData, plugin: String, enabled: bool) -> anyhow::Result<()> {
+	if !get_installed_plugins(&data.paths)?.contains(&plugin) {
+		bail!("Plugin '{plugin}' is not installed");
+	}
+
+	let mut config = PluginsConfig::open(&data.paths)?;
+	config.set_enabled(&plugin, enabled);
+	config.write(&data.paths)?;
+
+	if enabled {
+		cprintln!("<g>Plugin <b>{}</> enabled.", plugin);
+	} else {
+		cprintln!("<g>Plugin <b>{}</> disabled.", plugin);
+	}
+
+	Ok(())
+}
+
+async fn install(
+	data: &mut CmdData,
+	source: String,
+	id: Option<String>,
+	no_enable: bool,
+) -> anyhow::Result<()> {
+	// Plugins are installed before the full config is loaded, so read just the network settings from it
+	let config = data.get_raw_config()?;
+	let proxy = config
+		.preferences
+		.proxy
+		.as_ref()
+		.map(ProxyConfig::to_settings);
+	let client = create_client(proxy.as_ref(), config.preferences.get_timeouts())?;
+	let id = install_plugin(&source, id.as_deref(), &data.paths, &client)
+		.await
+		.context("Failed to install plugin")?;
+
+	if !no_enable {
+		let mut config = PluginsConfig::open(&data.paths)?;
+		config.set_enabled(&id, true);
+		config.write(&data.paths)?;
+	}
+
+	cprintln!("<g>Plugin <b>{}</> installed.", id);
+
+	Ok(())
+}
+
 async fn info(data: &mut CmdData, plugin: String) -> anyhow::Result<()> {
 	data.ensure_config(true).await?;
 	let config = data.config.get_mut();
@@ -0,0 +1,682 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use clap::Subcommand;
+use color_print::cprintln;
+use itertools::Itertools;
+use mcvm::api::{self, UpdateOptions};
+use mcvm::config::instance::InstanceConfig;
+use mcvm::config::modifications::{apply_modifications_and_write, ConfigModification};
+use mcvm::config::Config;
+use mcvm::core::net::stats::TransferStats;
+use mcvm::core::util::versions::MinecraftVersionDeser;
+use mcvm::instance::update::packages::PackageFailures;
+use mcvm::instance::update::pending::PendingOperations;
+use mcvm::instance::update::InstanceUpdateContext;
+use mcvm::io::lock::Lockfile;
+use mcvm::io::paths::Paths;
+use mcvm::shared::id::InstanceID;
+use mcvm::shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm::shared::pkg::PackageStability;
+use mcvm::shared::Side;
+
+use super::{catch_interrupts, CmdData, ExitStatus};
+use crate::output::HYPHEN_POINT;
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum ProfileSubcommand {
+	#[command(about = "List all profiles")]
+	#[clap(alias = "ls")]
+	List {
+		/// Whether to remove formatting and warnings from the output
+		#[arg(short, long)]
+		raw: bool,
+	},
+	#[command(
+		about = "Print useful information about a profile",
+		long_about = "Print the version, modloader, and instances of a profile. Profiles that use a
+custom version JSON instead of a version from the manifest are marked as custom."
+	)]
+	Info {
+		/// The profile to print information about
+		profile: String,
+	},
+	#[command(
+		about = "Update all of the instances that use a profile",
+		long_about = "Update all of the instances that use one or more profiles. If a profile's version
+is a pattern like 'latest' or '1.20.*', the newest matching version is resolved and
+any change since the last update is reported. Use --instance to only update some of
+the instances, leaving the others and their lockfile entries alone."
+	)]
+	Update {
+		/// Whether to force update files that have already been downloaded
+		#[arg(short, long)]
+		force: bool,
+		/// Whether to skip updating packages
+		#[arg(short = 'P', long)]
+		skip_packages: bool,
+		/// Whether to skip backing up world data when the Minecraft version changes
+		#[arg(long, conflicts_with = "dry_run")]
+		no_snapshot: bool,
+		/// Whether to fail instead of changing the Minecraft version, Paper build,
+		/// or package versions that are recorded in the lockfile
+		#[arg(long)]
+		frozen: bool,
+		/// Whether to only print what the update would change, without changing anything.
+		/// Exits with code 2 if there are pending changes and 0 if there are none
+		#[arg(long, conflicts_with_all = ["force", "frozen"])]
+		dry_run: bool,
+		/// The maximum number of Minecraft versions to create instances for at the same time
+		#[arg(short, long)]
+		jobs: Option<usize>,
+		/// The number of seconds that the update can take before it is cancelled
+		#[arg(long)]
+		timeout: Option<u64>,
+		/// Whether to show the changes made to options files like options.txt and server.properties
+		#[arg(long)]
+		show_diffs: bool,
+		/// Whether to stop at the first package that fails to install instead of
+		/// skipping it and installing the rest
+		#[arg(long, conflicts_with = "dry_run")]
+		fail_fast: bool,
+		/// Use this package stability for every package instead of the configured ones,
+		/// without changing the config. Can be `stable`, or `latest` (also called `dev`)
+		#[arg(long, value_parser = parse_stability)]
+		stability: Option<PackageStability>,
+		/// Whether to make the whitelist and ops of servers match their config exactly,
+		/// removing players that were added in-game
+		#[arg(long, conflicts_with = "dry_run")]
+		sync_exact: bool,
+		/// Whether to also print the summary of what the update downloaded as JSON
+		/// on the last line of the output
+		#[arg(long, conflicts_with = "dry_run")]
+		json: bool,
+		/// Whether to fail instead of warning about problems with the configuration,
+		/// such as unknown package features
+		#[arg(long)]
+		strict: bool,
+		/// Update every profile in the config
+		#[arg(long, conflicts_with = "profiles")]
+		all: bool,
+		/// Only update this instance of the profiles, skipping the rest. Can be used more than once
+		#[arg(short, long = "instance", value_name = "INSTANCE")]
+		instances: Vec<String>,
+		/// The profiles to update
+		#[arg(required_unless_present = "all")]
+		profiles: Vec<String>,
+	},
+	#[command(
+		about = "Roll back the instances of a profile to their previous update",
+		long_about = "Restore the package versions, Paper build, and Minecraft version
+that the instances of a profile had before their most recent update."
+	)]
+	Rollback {
+		/// Whether to roll back even if the Minecraft version would change.
+		/// Worlds that were opened in a newer version may not load in an older one
+		#[arg(short, long)]
+		force: bool,
+		/// The profile to roll back
+		profile: String,
+	},
+	#[command(
+		about = "Add a new instance that uses a profile",
+		long_about = "Add a new instance that uses a profile to the config file. The instance
+can be copied from a preset in the instance_presets section of your preferences."
+	)]
+	AddInstance {
+		/// The profile for the instance to use
+		profile: String,
+		/// The ID of the new instance
+		id: String,
+		/// The type of the new instance
+		#[arg(long = "type")]
+		side: Side,
+		/// The name of a preset in your preferences to copy the instance configuration from
+		#[arg(long)]
+		preset: Option<String>,
+	},
+	#[command(about = "Change the ID of a profile, updating everything that uses it")]
+	Rename {
+		/// The current ID of the profile
+		old: String,
+		/// The new ID of the profile
+		new: String,
+	},
+	#[command(about = "Remove an instance that uses a profile")]
+	RemoveInstance {
+		/// The profile that the instance uses
+		profile: String,
+		/// The ID of the instance to remove
+		id: String,
+		/// Whether to also delete the files of the instance. You will be asked for confirmation
+		#[arg(long)]
+		delete_files: bool,
+	},
+}
+
+pub async fn run(command: ProfileSubcommand, data: &mut CmdData) -> anyhow::Result<()> {
+	match command {
+		ProfileSubcommand::List { raw } => list(data, raw).await,
+		ProfileSubcommand::Info { profile } => info(data, &profile).await,
+		ProfileSubcommand::Update {
+			force,
+			skip_packages,
+			no_snapshot,
+			frozen,
+			dry_run,
+			jobs,
+			timeout,
+			show_diffs,
+			fail_fast,
+			stability,
+			sync_exact,
+			json,
+			strict,
+			all,
+			instances,
+			profiles,
+		} => {
+			let scope = get_update_scope(data, profiles, all, instances)?;
+			if dry_run {
+				plan_update(data, scope, skip_packages, stability).await
+			} else {
+				let options = UpdateOptions {
+					force,
+					skip_packages,
+					frozen,
+					jobs,
+					timeout: timeout.map(Duration::from_secs),
+					show_diffs,
+					fail_fast,
+					stability,
+					sync_exact,
+					no_snapshot,
+					strict,
+					..Default::default()
+				};
+				update(data, scope, options, json).await
+			}
+		}
+		ProfileSubcommand::Rollback { force, profile } => rollback(data, profile, force).await,
+		ProfileSubcommand::AddInstance {
+			profile,
+			id,
+			side,
+			preset,
+		} => add_instance(data, profile, id, side, preset).await,
+		ProfileSubcommand::RemoveInstance {
+			profile,
+			id,
+			delete_files,
+		} => remove_instance(data, profile, id, delete_files).await,
+		ProfileSubcommand::Rename { old, new } => rename(data, old, new).await,
+	}
+}
+
+/// Parse a package stability for a one-off update
+fn parse_stability(string: &str) -> anyhow::Result<PackageStability> {
+	match string {
+		"dev" => Ok(PackageStability::Latest),
+		other => PackageStability::parse_from_str(other)
+			.with_context(|| format!("Unknown stability '{other}'. Use stable, latest, or dev")),
+	}
+}
+
+async fn list(data: &mut CmdData, raw: bool) -> anyhow::Result<()> {
+	let config = data.get_raw_config()?;
+
+	if !raw {
+		cprintln!("<s>Profiles:");
+	}
+	for id in config.profiles.keys().sorted() {
+		if raw {
+			println!("{id}");
+		} else {
+			cprintln!("{}<s>{}", HYPHEN_POINT, id);
+		}
+	}
+
+	Ok(())
+}
+
+async fn info(data: &mut CmdData, id: &str) -> anyhow::Result<()> {
+	let config = data.get_raw_config()?;
+	let profile = config
+		.profiles
+		.get(id)
+		.with_context(|| format!("Profile '{id}' does not exist"))?;
+	let common = &profile.instance.common;
+
+	cprintln!("<s><g>Profile <b>{}", id);
+	match &common.version {
+		Some(MinecraftVersionDeser::Custom { custom }) => {
+			cprintln!("   <s>Version:</s> <y>Custom</> <k!>(not from the version manifest)");
+			cprintln!("   {}<s>JSON:</s> <m!>{}", HYPHEN_POINT, custom.json);
+			if let Some(jar) = &custom.jar {
+				cprintln!("   {}<s>JAR:</s> <m!>{}", HYPHEN_POINT, jar);
+			}
+		}
+		Some(version) => {
+			let version =
+				version.to_mc_version_with_channel(common.version_channel.unwrap_or_default());
+			cprintln!("   <s>Version:</s> <g>{}", version);
+		}
+		None => cprintln!("   <s>Version:</s> <k!>None"),
+	}
+	if let Some(modloader) = &common.modloader {
+		cprintln!("   <s>Modloader:</s> <g>{}", modloader);
+	}
+
+	let instances = config
+		.instances
+		.iter()
+		.filter(|(_, x)| x.common.from.as_deref() == Some(id))
+		.map(|x| x.0)
+		.sorted();
+	cprintln!("   <s>Instances:");
+	for instance in instances {
+		cprintln!("   {}<b!>{}", HYPHEN_POINT, instance);
+	}
+
+	Ok(())
+}
+
+/// Get the profiles that an update includes, along with the instances of each one that it is filtered to
+fn get_update_scope(
+	data: &mut CmdData,
+	profiles: Vec<String>,
+	all: bool,
+	instances: Vec<String>,
+) -> anyhow::Result<Vec<(String, Option<Vec<InstanceID>>)>> {
+	let raw_config = data.get_raw_config()?;
+	let profiles: Vec<String> = if all {
+		// Profiles that are only used by other profiles have no instances of their own to update
+		let (profiles, unused): (Vec<_>, Vec<_>) = raw_config
+			.profiles
+			.keys()
+			.sorted()
+			.map(|x| x.to_string())
+			.partition(|profile| {
+				raw_config
+					.instances
+					.values()
+					.any(|x| x.common.from.as_deref() == Some(profile.as_str()))
+			});
+		for profile in unused {
+			data.output.display(
+				MessageContents::Notice(format!(
+					"Skipping profile '{profile}' since no instances use it directly"
+				)),
+				MessageLevel::Important,
+			);
+		}
+		profiles
+	} else {
+		for profile in &profiles {
+			if !raw_config.profiles.contains_key(profile.as_str()) {
+				bail!("Profile '{profile}' does not exist");
+			}
+		}
+		profiles.into_iter().unique().collect()
+	};
+	if profiles.is_empty() {
+		bail!("There are no profiles to update");
+	}
+
+	if instances.is_empty() {
+		return Ok(profiles.into_iter().map(|x| (x, None)).collect());
+	}
+	for instance in &instances {
+		let Some(config) = raw_config.instances.get(instance.as_str()) else {
+			bail!("Instance '{instance}' does not exist");
+		};
+		let from = config.common.from.as_deref();
+		if !profiles.iter().any(|x| Some(x.as_str()) == from) {
+			bail!("Instance '{instance}' does not use any of the profiles being updated");
+		}
+	}
+	// Each profile is only given the selected instances that use it
+	let scope = profiles
+		.into_iter()
+		.map(|profile| {
+			let filter = instances
+				.iter()
+				.filter(|x| {
+					raw_config
+						.instances
+						.get(x.as_str())
+						.is_some_and(|x| x.common.from.as_deref() == Some(profile.as_str()))
+				})
+				.map(|x| InstanceID::from(x.as_str()))
+				.collect();
+			(profile, Some(filter))
+		})
+		.collect();
+
+	Ok(scope)
+}
+
+async fn update(
+	data: &mut CmdData,
+	scope: Vec<(String, Option<Vec<InstanceID>>)>,
+	mut options: UpdateOptions,
+	json: bool,
+) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+	let config = data.config.get_mut();
+	let stats = Arc::new(TransferStats::new());
+	options.stats = Some(stats.clone());
+
+	let updates = async {
+		// Packages that fail in one profile don't stop the other profiles from being updated
+		let mut failures = PackageFailures::default();
+		for (profile, instance_filter) in &scope {
+			if scope.len() > 1 {
+				cprintln!("<s>Updating profile <b>{}", profile);
+			}
+			options.instance_filter = instance_filter.clone();
+			let profile_result =
+				api::update_profile(config, profile, &options, &data.paths, &mut data.output).await;
+			match profile_result.map_err(|e| e.downcast::<PackageFailures>()) {
+				Ok(()) => {}
+				Err(Ok(profile_failures)) => failures.extend(profile_failures),
+				Err(Err(e)) => {
+					return Err(e.context(format!("Failed to update profile '{profile}'")));
+				}
+			}
+		}
+		if !failures.is_empty() {
+			return Err(failures.into());
+		}
+		Ok(())
+	};
+	let result = catch_interrupts(updates).await;
+
+	if json {
+		println!("{}", serde_json::to_string(&stats.summary())?);
+	}
+
+	result
+}
+
+async fn plan_update(
+	data: &mut CmdData,
+	scope: Vec<(String, Option<Vec<InstanceID>>)>,
+	skip_packages: bool,
+	stability: Option<PackageStability>,
+) -> anyhow::Result<()> {
+	data.ensure_config(true).await?;
+	let config = data.config.get_mut();
+	let mut options = UpdateOptions {
+		skip_packages,
+		stability,
+		..Default::default()
+	};
+	let mut plans = Vec::new();
+	for (profile, instance_filter) in scope {
+		options.instance_filter = instance_filter;
+		plans.extend(
+			api::plan_profile_update(config, &profile, &options, &data.paths, &mut data.output)
+				.await?,
+		);
+	}
+
+	for plan in &plans {
+		cprintln!("<s>Instance <b>{}</> ({}):", plan.instance, plan.version);
+		if plan.first_update {
+			cprintln!("{}<y>Instance has not been updated before", HYPHEN_POINT);
+		}
+		for change in &plan.changes {
+			println!("{HYPHEN_POINT}{change}");
+		}
+		for file in &plan.written_files {
+			cprintln!("{}<g>+ {}", HYPHEN_POINT, file.display());
+		}
+		for file in &plan.removed_files {
+			cprintln!("{}<r>- {}", HYPHEN_POINT, file.display());
+		}
+		for file in &plan.options_files {
+			cprintln!(
+				"{}<k!>~ {} (rewritten every update)",
+				HYPHEN_POINT,
+				file.display()
+			);
+		}
+		if !plan.download.is_empty() {
+			cprintln!("{}<c>Would download {}", HYPHEN_POINT, plan.download);
+		}
+		if !plan.has_changes() {
+			cprintln!("{}<g>Up to date", HYPHEN_POINT);
+		}
+	}
+
+	if plans.iter().any(|x| x.has_changes()) {
+		// Distinguish pending changes from errors so that scripts can check for drift
+		return Err(ExitStatus(2).into());
+	}
+
+	Ok(())
+}
+
+async fn rollback(data: &mut CmdData, profile: String, force: bool) -> anyhow::Result<()> {
+	let raw_config = data.get_raw_config()?;
+	if !raw_config.profiles.contains_key(profile.as_str()) {
+		bail!("Profile '{profile}' does not exist");
+	}
+	let ids: Vec<InstanceID> = raw_config
+		.instances
+		.iter()
+		.filter(|(_, instance)| instance.common.from.as_deref() == Some(profile.as_str()))
+		.map(|(id, _)| id.clone())
+		.sorted()
+		.collect();
+	if ids.is_empty() {
+		bail!("Profile '{profile}' is not used by any instances");
+	}
+
+	data.ensure_config(true).await?;
+	let config = data.config.get_mut();
+	let mut lock = Lockfile::open(&data.paths).context("Failed to open lockfile")?;
+
+	// Make sure every instance can be rolled back before changing anything
+	for id in &ids {
+		let record = lock.get_rollback_record(id).with_context(|| {
+			format!("Instance '{id}' does not have a previous update to roll back to")
+		})?;
+		let current_version = lock.get_instance_version(id);
+		if !force && current_version != Some(record.version.as_str()) {
+			bail!(
+				"Rolling back instance '{id}' would change its Minecraft version from {} to {}, which can break existing worlds. Use --force to roll back anyway",
+				current_version.unwrap_or("unknown"),
+				record.version
+			);
+		}
+	}
+
+	let client = config.client.clone();
+	let result = rollback_instances(
+		ids,
+		config,
+		&mut lock,
+		&client,
+		&data.paths,
+		&mut data.output,
+	)
+	.await;
+	// Instances that were rolled back before a failure are still recorded
+	lock.finish(&data.paths)
+		.context("Failed to finish using lockfile")?;
+	result?;
+
+	cprintln!("<g>Profile rolled back.");
+
+	Ok(())
+}
+
+/// Roll back each instance to the previous update recorded in the lockfile
+async fn rollback_instances(
+	ids: Vec<InstanceID>,
+	config: &mut Config,
+	lock: &mut Lockfile,
+	client: &reqwest::Client,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<()> {
+	for id in ids {
+		let instance = config
+			.instances
+			.get_mut(&id)
+			.with_context(|| format!("Unknown instance '{id}'"))?;
+
+		// The history is only changed once the rollback succeeds, so that a failed
+		// rollback can be tried again
+		let record = lock.get_rollback_record(&id).cloned().with_context(|| {
+			format!("Instance '{id}' does not have a previous update to roll back to")
+		})?;
+		let history_len = lock.get_instance_history(&id).len();
+		instance.pin_to_update(record);
+
+		let mut ctx = InstanceUpdateContext {
+			packages: &mut config.packages,
+			users: &config.users,
+			plugins: &config.plugins,
+			prefs: &config.prefs,
+			paths,
+			lock,
+			client,
+			strict: false,
+			show_diffs: false,
+			fail_fast: true,
+			output: o,
+			pending: PendingOperations::new(),
+			stability: None,
+			sync_exact: false,
+			no_snapshot: false,
+		};
+
+		instance
+			.update(true, false, &mut ctx)
+			.await
+			.with_context(|| format!("Failed to roll back instance '{id}'"))?;
+		lock.finish_instance_rollback(&id, history_len);
+		// In case some of the pins couldn't be applied, the real state is recorded
+		lock.record_instance_update(&id)?;
+	}
+
+	Ok(())
+}
+
+async fn add_instance(
+	data: &mut CmdData,
+	profile: String,
+	id: String,
+	side: Side,
+	preset: Option<String>,
+) -> anyhow::Result<()> {
+	let mut raw_config = data.get_raw_config()?;
+	if !raw_config.profiles.contains_key(profile.as_str()) {
+		bail!("Profile '{profile}' does not exist");
+	}
+
+	let mut instance = if let Some(preset) = preset {
+		raw_config
+			.preferences
+			.instance_presets
+			.get(&preset)
+			.with_context(|| format!("Instance preset '{preset}' does not exist"))?
+			.clone()
+	} else {
+		InstanceConfig {
+			side: None,
+			name: None,
+			description: None,
+			icon: None,
+			whitelist: None,
+			ops: None,
+			user: None,
+			common: Default::default(),
+			window: Default::default(),
+		}
+	};
+	instance.side = Some(side);
+	instance.common.from = Some(profile);
+
+	apply_modifications_and_write(
+		&mut raw_config,
+		vec![ConfigModification::AddInstance(id.into(), instance)],
+		&data.paths,
+	)
+	.context("Failed to write modified config")?;
+
+	cprintln!("<g>Instance added.");
+
+	Ok(())
+}
+
+async fn rename(data: &mut CmdData, old: String, new: String) -> anyhow::Result<()> {
+	let mut raw_config = data.get_raw_config()?;
+	apply_modifications_and_write(
+		&mut raw_config,
+		vec![ConfigModification::RenameProfile(
+			old.clone().into(),
+			new.clone().into(),
+		)],
+		&data.paths,
+	)
+	.context("Failed to write modified config")?;
+
+	cprintln!("<g>Profile '{}' renamed to '{}'.", old, new);
+
+	Ok(())
+}
+
+async fn remove_instance(
+	data: &mut CmdData,
+	profile: String,
+	id: String,
+	delete_files: bool,
+) -> anyhow::Result<()> {
+	let mut raw_config = data.get_raw_config()?;
+	let id = InstanceID::from(id);
+	let instance = raw_config
+		.instances
+		.get(&id)
+		.with_context(|| format!("Instance '{id}' does not exist"))?;
+	if instance.common.from.as_deref() != Some(profile.as_str()) {
+		bail!("Instance '{id}' does not use the profile '{profile}'");
+	}
+
+	apply_modifications_and_write(
+		&mut raw_config,
+		vec![ConfigModification::RemoveInstance(id.clone())],
+		&data.paths,
+	)
+	.context("Failed to write modified config")?;
+
+	let mut lock = Lockfile::open(&data.paths).context("Failed to open lockfile")?;
+	lock.remove_instance(&id);
+	lock.finish(&data.paths)
+		.context("Failed to finish using lockfile")?;
+
+	cprintln!("<g>Instance removed.");
+
+	if delete_files {
+		let inst_dir = data.paths.instances.join(&*id);
+		if inst_dir.exists() {
+			let confirmed = inquire::Confirm::new(&format!(
+				"Delete all of the files in {}? This cannot be undone.",
+				inst_dir.display()
+			))
+			.with_default(false)
+			.prompt()?;
+			if confirmed {
+				std::fs::remove_dir_all(&inst_dir)
+					.context("Failed to delete instance directory")?;
+				cprintln!("<g>Instance files deleted.");
+			}
+		}
+	}
+
+	Ok(())
+}
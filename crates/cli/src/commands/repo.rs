@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Subcommand;
+use color_print::cprintln;
+use mcvm::pkg::author::{self, REPO_API_DIR};
+use mcvm::pkg_crate::repo::RepoMetadata;
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum RepoSubcommand {
+	#[command(
+		about = "Create a new package repository",
+		long_about = "Create the layout of a new package repository in a directory, with
+an empty index. The whole directory can be hosted on a static web server, or added
+to a config as a local repository."
+	)]
+	Init {
+		/// The directory to create the repository in
+		dir: PathBuf,
+		/// The display name of the repository
+		#[arg(long)]
+		name: Option<String>,
+		/// A short description of the repository
+		#[arg(long)]
+		description: Option<String>,
+	},
+	#[command(
+		about = "Add a package file to a repository",
+		long_about = "Validate a package file and copy it into a repository, replacing any
+package with the same ID. Files must be named <id>.json for declarative packages or
+<id>.pkg.txt for script packages. Run `mcvm repo index` afterwards to publish it."
+	)]
+	Add {
+		/// The package file to add
+		package: PathBuf,
+		/// The directory of the repository
+		#[arg(short, long, default_value = ".")]
+		dir: PathBuf,
+	},
+	#[command(
+		about = "Generate the index of a repository",
+		long_about = "Validate every package in a repository and write its index.json.
+The same packages always produce the same index, so it can be committed to git.
+With --key, the index is also signed and the signature is written to index.json.sig."
+	)]
+	Index {
+		/// The directory of the repository
+		#[arg(short, long, default_value = ".")]
+		dir: PathBuf,
+		/// A file containing a hex-encoded 32 byte ed25519 secret key to sign the index with
+		#[arg(short, long)]
+		key: Option<PathBuf>,
+	},
+}
+
+pub async fn run(command: RepoSubcommand) -> anyhow::Result<()> {
+	match command {
+		RepoSubcommand::Init {
+			dir,
+			name,
+			description,
+		} => init(&dir, name, description),
+		RepoSubcommand::Add { package, dir } => add(&dir, &package),
+		RepoSubcommand::Index { dir, key } => index(&dir, key.as_deref()),
+	}
+}
+
+fn init(dir: &Path, name: Option<String>, description: Option<String>) -> anyhow::Result<()> {
+	let metadata = RepoMetadata {
+		name,
+		description,
+		mcvm_version: None,
+	};
+	author::init_repo(dir, metadata).context("Failed to create repository")?;
+
+	cprintln!("<g>Created a repository in {}.", dir.display());
+	cprintln!(
+		"Host the directory so that <b>{}</> is served, or add <b>{}</> to your config as a local repository.",
+		REPO_API_DIR,
+		author::get_index_path(dir).display()
+	);
+
+	Ok(())
+}
+
+fn add(dir: &Path, package: &Path) -> anyhow::Result<()> {
+	let added = author::add_package(dir, package).context("Failed to add package")?;
+
+	if added.replaced {
+		cprintln!("<g>Replaced package <b>{}</>.", added.id);
+	} else {
+		cprintln!("<g>Added package <b>{}</>.", added.id);
+	}
+	cprintln!("<s>SHA-256:</> {}", added.hash);
+	cprintln!("Run <b>mcvm repo index</> to update the index.");
+
+	Ok(())
+}
+
+fn index(dir: &Path, key: Option<&Path>) -> anyhow::Result<()> {
+	let key = key
+		.map(|path| {
+			let key = std::fs::read_to_string(path).context("Failed to read signing key")?;
+			author::parse_signing_key(&key)
+		})
+		.transpose()?;
+
+	let count = author::generate_index(dir, key.as_ref()).context("Failed to generate index")?;
+
+	cprintln!(
+		"<g>Wrote index with {} packages to {}.",
+		count,
+		author::get_index_path(dir).display()
+	);
+	if let Some(key) = key {
+		cprintln!(
+			"Signed the index. Users can verify it with the public key <b>{}</>.",
+			author::get_public_key(&key)
+		);
+	}
+
+	Ok(())
+}
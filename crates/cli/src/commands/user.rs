@@ -8,10 +8,9 @@ use mcvm::core::user::UserKind;
 
 use clap::Subcommand;
 use color_print::{cprint, cprintln};
-use reqwest::Client;
 
 #[derive(Debug, Subcommand)]
-pub enum UserSubcommand {
+pub(crate) enum UserSubcommand {
 	#[command(about = "List all users")]
 	#[clap(alias = "ls")]
 	List {
@@ -62,15 +61,16 @@ async fn list(data: &mut CmdData, raw: bool) -> anyhow::Result<()> {
 		cprintln!("<s>Users:");
 	}
 	for (id, user) in config.users.iter_users().sorted_by_key(|x| x.0) {
-		cprint!("{}", HYPHEN_POINT);
 		if raw {
 			println!("{id}");
 		} else {
+			cprint!("{}", HYPHEN_POINT);
 			match user.get_kind() {
 				UserKind::Microsoft { .. } => {
 					cprint!("<s><g>{}</g>", id)
 				}
 				UserKind::Demo => cprint!("<s><c!>{}</c!>", id),
+				UserKind::Unverified => cprint!("<s><k!>(offline) {}</k!>", id),
 				UserKind::Unknown(other) => cprint!("<s><k!>({other}) {}</k!>", id),
 			}
 			if let Some(chosen) = config.users.get_chosen_user() {
@@ -104,6 +104,7 @@ async fn status(data: &mut CmdData) -> anyhow::Result<()> {
 			match user.get_kind() {
 				UserKind::Microsoft { .. } => cprint!("<s,g!>{}", user.get_id()),
 				UserKind::Demo => cprint!("<s,c!>{}", user.get_id()),
+				UserKind::Unverified => cprint!("<s,k!>(offline) {}", user.get_id()),
 				UserKind::Unknown(other) => cprint!("<s,k!>({other}) {}", user.get_id()),
 			}
 
@@ -143,7 +144,7 @@ async fn auth(data: &mut CmdData, user: Option<String>) -> anyhow::Result<()> {
 		config.users.choose_user(&user)?;
 	}
 
-	let client = Client::new();
+	let client = config.client.clone();
 	config
 		.users
 		.authenticate(&data.paths.core, &client, &mut data.output)
@@ -4,14 +4,15 @@ mod secrets;
 
 use std::process::ExitCode;
 
-use commands::run_cli;
+use commands::{run_cli, ExitStatus};
 
 #[tokio::main]
 async fn main() -> ExitCode {
-	let result = run_cli().await;
-	if result.is_err() {
-		return ExitCode::FAILURE;
+	match run_cli().await {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(e) => match e.downcast_ref::<ExitStatus>() {
+			Some(status) => ExitCode::from(status.0),
+			None => ExitCode::FAILURE,
+		},
 	}
-
-	ExitCode::SUCCESS
 }
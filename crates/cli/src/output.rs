@@ -8,7 +8,8 @@ use mcvm::io::paths::Paths;
 use mcvm::pkg_crate::{PkgRequest, PkgRequestSource};
 use mcvm::shared::lang::translate::{TranslationKey, TranslationMap};
 use mcvm::shared::output::{
-	default_special_ms_auth, MCVMOutput, Message, MessageContents, MessageLevel,
+	default_special_ms_auth, DiffChange, MCVMOutput, Message, MessageContents, MessageLevel,
+	OutputFrame, SectionStack, Verbosity,
 };
 use mcvm::shared::util::print::ReplPrinter;
 use mcvm::shared::util::utc_timestamp;
@@ -28,11 +29,10 @@ pub const VERSION: &str = "\u{1F4C5}";
 pub const LOADER: &str = "\u{1F4E5}";
 
 /// Terminal MCVMOutput
-pub struct TerminalOutput {
+pub(crate) struct TerminalOutput {
 	printer: ReplPrinter,
-	level: MessageLevel,
-	in_process: bool,
-	indent_level: u8,
+	verbosity: Verbosity,
+	stack: SectionStack,
 	log_file: File,
 	latest_log_file: File,
 	translation_map: Option<TranslationMap>,
@@ -40,8 +40,10 @@ pub struct TerminalOutput {
 
 impl MCVMOutput for TerminalOutput {
 	fn display_text(&mut self, text: String, level: MessageLevel) {
-		let _ = self.log_message(&text, level);
-		self.display_text_impl(text, level);
+		self.display_message(Message {
+			contents: MessageContents::Simple(text),
+			level,
+		});
 	}
 
 	fn display_message(&mut self, message: Message) {
@@ -49,34 +51,32 @@ impl MCVMOutput for TerminalOutput {
 			&Self::format_message_log(message.contents.clone()),
 			message.level,
 		);
-		self.display_text_impl(self.format_message(message.contents), message.level);
+		if self
+			.verbosity
+			.should_display(&message.contents, message.level)
+		{
+			self.display_text_impl(self.format_message(message.contents));
+		}
 	}
 
 	fn start_process(&mut self) {
-		if self.in_process {
+		if self.in_process() {
 			self.printer.newline();
-		} else {
-			self.in_process = true;
 		}
+		self.stack.push(OutputFrame::Process);
 	}
 
 	fn end_process(&mut self) {
-		if self.in_process {
-			self.printer.newline();
-		}
-		self.in_process = false;
+		self.end_frame(OutputFrame::Process);
 	}
 
 	fn start_section(&mut self) {
-		self.indent_level += 1;
-		self.printer.indent(self.indent_level.into());
+		self.stack.push(OutputFrame::Section);
+		self.printer.indent(self.stack.indent());
 	}
 
 	fn end_section(&mut self) {
-		if self.indent_level != 0 {
-			self.indent_level -= 1;
-			self.printer.indent(self.indent_level.into());
-		}
+		self.end_frame(OutputFrame::Section);
 	}
 
 	fn prompt_yes_no(&mut self, default: bool, message: MessageContents) -> anyhow::Result<bool> {
@@ -129,9 +129,8 @@ impl TerminalOutput {
 			.context("Failed to open latest.txt log file")?;
 		Ok(Self {
 			printer: ReplPrinter::new(true),
-			level: MessageLevel::Important,
-			in_process: false,
-			indent_level: 0,
+			verbosity: Verbosity::Normal,
+			stack: SectionStack::new(),
 			log_file: file,
 			latest_log_file: latest_file,
 			translation_map: None,
@@ -139,12 +138,8 @@ impl TerminalOutput {
 	}
 
 	/// Display text
-	fn display_text_impl(&mut self, text: String, level: MessageLevel) {
-		if !level.at_least(&self.level) {
-			return;
-		}
-
-		if self.in_process {
+	fn display_text_impl(&mut self, text: String) {
+		if self.in_process() {
 			self.printer.print(&text);
 		} else {
 			self.printer.print(&text);
@@ -152,6 +147,39 @@ impl TerminalOutput {
 		}
 	}
 
+	/// Whether messages replace the current line. Quiet output only shows errors,
+	/// which always get their own line
+	fn in_process(&self) -> bool {
+		self.stack.in_process() && self.verbosity != Verbosity::Quiet
+	}
+
+	/// End the innermost frame of a kind, along with anything that was left open inside of it
+	fn end_frame(&mut self, frame: OutputFrame) {
+		let was_in_process = self.in_process();
+		let ended = self.stack.pop(frame);
+		self.finish_frames(was_in_process, &ended);
+	}
+
+	/// End every section and process that is still open, such as when a command fails
+	/// partway through one
+	pub fn reset_sections(&mut self) {
+		let was_in_process = self.in_process();
+		let ended = self.stack.clear();
+		self.finish_frames(was_in_process, &ended);
+	}
+
+	/// Move past the line of a process that was ended and go back to the indent of the
+	/// frames that are still open
+	fn finish_frames(&mut self, was_in_process: bool, ended: &[OutputFrame]) {
+		if ended.is_empty() {
+			return;
+		}
+		if was_in_process && ended.contains(&OutputFrame::Process) {
+			self.printer.newline();
+		}
+		self.printer.indent(self.stack.indent());
+	}
+
 	/// Formatting for messages
 	fn format_message(&self, contents: MessageContents) -> String {
 		match contents {
@@ -215,6 +243,8 @@ impl TerminalOutput {
 				);
 				cformat!("<s>[</><g>{}</g><k!>{}</><s>]</>", full, empty)
 			}
+			MessageContents::DiffLine(DiffChange::Added, line) => cformat!("<g>+ {}", line),
+			MessageContents::DiffLine(DiffChange::Removed, line) => cformat!("<r>- {}", line),
 			contents => contents.default_format(),
 		}
 	}
@@ -265,9 +295,14 @@ impl TerminalOutput {
 		Ok(())
 	}
 
-	/// Set the log level of the output
-	pub fn set_log_level(&mut self, level: MessageLevel) {
-		self.level = level;
+	/// Set how much the output shows
+	pub fn set_verbosity(&mut self, verbosity: Verbosity) {
+		self.verbosity = verbosity;
+	}
+
+	/// Check whether plain messages at a level are shown by the output
+	pub fn shows_level(&self, level: MessageLevel) -> bool {
+		self.verbosity != Verbosity::Quiet && level.at_least(&self.verbosity.get_level())
 	}
 
 	/// Set the translation map of the output
@@ -335,9 +370,59 @@ fn add_period(string: String) -> String {
 	}
 }
 
+/// Wrap text into lines that are at most `width` characters long, breaking at spaces.
+/// Words that are longer than the width are put on their own line
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+	let width = width.max(1);
+	let mut out = Vec::new();
+	for paragraph in text.lines() {
+		let mut line = String::new();
+		let mut line_len = 0;
+		for word in paragraph.split_whitespace() {
+			let word_len = word.chars().count();
+			if line_len > 0 && line_len + 1 + word_len > width {
+				out.push(std::mem::take(&mut line));
+				line_len = 0;
+			}
+			if line_len > 0 {
+				line.push(' ');
+				line_len += 1;
+			}
+			line.push_str(word);
+			line_len += word_len;
+		}
+		out.push(line);
+	}
+
+	out
+}
+
+/// Get the width of the terminal in characters
+pub fn terminal_width() -> usize {
+	termimad::terminal_size().0.into()
+}
+
 /// Get whether icons are enabled
 pub fn icons_enabled() -> bool {
 	let out = std::env::var("MCVM_CLI_ICONS").unwrap_or("0".into());
 	let out: u8 = out.parse().unwrap_or(0);
 	out != 0
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_wrap_text() {
+		assert_eq!(
+			wrap_text("The quick brown fox jumps over the lazy dog", 15),
+			vec!["The quick brown", "fox jumps over", "the lazy dog"]
+		);
+		assert_eq!(
+			wrap_text("short\n\nsupercalifragilistic word", 10),
+			vec!["short", "", "supercalifragilistic", "word"]
+		);
+		assert_eq!(wrap_text("", 10), Vec::<String>::new());
+	}
+}
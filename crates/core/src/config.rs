@@ -1,5 +1,9 @@
+use std::time::Duration;
+
 use mcvm_auth::mc::ClientId;
 
+use crate::net::game_files::cache::DEFAULT_METADATA_FRESHNESS;
+
 use crate::util::secrets::get_ms_client_id;
 
 macro_rules! builder_method {
@@ -25,6 +29,8 @@ pub struct Configuration {
 	/// Whether to use file copies instead of hardlinks. Useful if you
 	/// are on a filesystem that doesn't like hardlinks
 	pub(crate) disable_hardlinks: bool,
+	/// How long downloaded metadata like the version manifest is used before checking for changes
+	pub(crate) metadata_freshness: Duration,
 	/// Launcher branding
 	pub(crate) branding: BrandingProperties,
 }
@@ -44,6 +50,7 @@ impl Configuration {
 			allow_offline: false,
 			censor_secrets: true,
 			disable_hardlinks: false,
+			metadata_freshness: DEFAULT_METADATA_FRESHNESS,
 			branding: BrandingProperties::default(),
 		}
 	}
@@ -98,6 +105,12 @@ impl ConfigBuilder {
 		"Set whether to disable the use of hardlinks"
 	);
 
+	builder_method!(
+		metadata_freshness,
+		Duration,
+		"Set how long downloaded metadata is used before checking for changes"
+	);
+
 	builder_method!(branding, BrandingProperties, "Set the branding properties");
 }
 
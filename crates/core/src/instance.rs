@@ -1,20 +1,21 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context};
-use mcvm_shared::output::MCVMOutput;
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
 use mcvm_shared::Side;
 
 use crate::config::BrandingProperties;
 use crate::io::files::paths::Paths;
-use crate::io::files::update_hardlink;
+use crate::io::files::update_hardlink_or_copy;
 use crate::io::java::classpath::Classpath;
 use crate::io::java::install::{JavaInstallParameters, JavaInstallation};
 use crate::io::persistent::PersistentData;
 use crate::io::update::UpdateManager;
-use crate::launch::{LaunchConfiguration, LaunchParameters};
+use crate::launch::{LaunchCommand, LaunchConfiguration, LaunchParameters};
 use crate::net::game_files::client_meta::ClientMeta;
 use crate::net::game_files::version_manifest::VersionManifestAndList;
-use crate::net::game_files::{game_jar, libraries};
+use crate::net::game_files::{assets, game_jar, libraries, log_config};
+use crate::net::stats::{self, TransferPhase};
 use crate::user::UserManager;
 use crate::util::versions::VersionName;
 use crate::version::{ClientAssetsAndLibraries, ClientAssetsAndLibsParameters};
@@ -31,6 +32,7 @@ pub struct Instance<'params> {
 	jar_path: PathBuf,
 	classpath: Classpath,
 	main_class: String,
+	game_assets_dir: PathBuf,
 }
 
 impl<'params> Instance<'params> {
@@ -56,9 +58,10 @@ impl<'params> Instance<'params> {
 			req_client: params.req_client,
 		};
 		let java =
-			JavaInstallation::install(config.launch.java.clone(), *java_vers, java_params, o)
-				.await
-				.context("Failed to install or update Java")?;
+			JavaInstallation::install(config.launch.java.clone(), *java_vers, java_params, o);
+		let java = stats::phase(TransferPhase::Java, java)
+			.await
+			.context("Failed to install or update Java")?;
 
 		let is_valid = java
 			.verify()
@@ -67,8 +70,6 @@ impl<'params> Instance<'params> {
 			bail!("Java installation is invalid");
 		}
 
-		params.persistent.dump(params.paths).await?;
-
 		// Get the game jar
 		let mut jar_path = if let Some(jar_path) = &config.jar_path {
 			jar_path.clone()
@@ -113,7 +114,7 @@ impl<'params> Instance<'params> {
 							.await
 							.context("Failed to copy server.jar")?;
 					} else {
-						update_hardlink(&jar_path, &new_jar_path)
+						update_hardlink_or_copy(&jar_path, &new_jar_path)
 							.context("Failed to hardlink server.jar")?;
 					}
 					params.update_manager.add_file(new_jar_path.clone());
@@ -127,13 +128,13 @@ impl<'params> Instance<'params> {
 		}
 
 		// Load assets and libs for client
+		let mut game_assets_dir = params.paths.assets.clone();
 		if let Side::Client = config.side.get_side() {
 			let sub_params = ClientAssetsAndLibsParameters {
 				client_meta: params.client_meta,
 				version: params.version,
 				paths: params.paths,
 				req_client: params.req_client,
-				version_manifest: params.version_manifest,
 				update_manager: params.update_manager,
 			};
 			params
@@ -141,19 +142,45 @@ impl<'params> Instance<'params> {
 				.load(sub_params, o)
 				.await
 				.context("Failed to load client assets and libraries")?;
+
+			game_assets_dir = assets::get_instance_assets(
+				params.client_meta,
+				params.paths,
+				&config.path,
+				params.update_manager,
+			)
+			.context("Failed to prepare assets for instance")?;
+
+			if config.launch.use_log4j_config {
+				let result = log_config::get(
+					params.client_meta.logging.client.as_ref(),
+					params.paths,
+					params.update_manager,
+					params.req_client,
+				)
+				.await
+				.context("Failed to get logging configuration")?;
+				params.update_manager.add_result(result);
+			}
 		}
 
 		// Classpath
-		let mut classpath = Classpath::new();
-		if let Side::Client = config.side.get_side() {
-			let lib_classpath = libraries::get_classpath(params.client_meta, params.paths)
-				.context("Failed to extract classpath from game library list")?;
-			classpath.extend(lib_classpath);
-		}
-		for lib in &config.additional_libs {
-			classpath.add_path(lib)?;
+		let game_libs = if let Side::Client = config.side.get_side() {
+			libraries::get_classpath(params.client_meta, params.paths)
+				.context("Failed to extract classpath from game library list")?
+		} else {
+			Classpath::new()
+		};
+		let classpath = build_classpath(&config.additional_libs, game_libs, &jar_path)?;
+		for conflict in classpath.get_conflicts() {
+			o.display(
+				MessageContents::Warning(format!(
+					"Library '{}' is on the classpath twice. Using version {} instead of {}",
+					conflict.artifact, conflict.kept_version, conflict.skipped_version
+				)),
+				MessageLevel::Extra,
+			);
 		}
-		classpath.add_path(&jar_path)?;
 
 		// Main class
 		let main_class = if let Some(main_class) = &config.main_class {
@@ -184,6 +211,7 @@ impl<'params> Instance<'params> {
 			jar_path,
 			classpath,
 			main_class,
+			game_assets_dir,
 		})
 	}
 
@@ -201,11 +229,34 @@ impl<'params> Instance<'params> {
 		&mut self,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<InstanceHandle> {
-		let params = LaunchParameters {
+		self.classpath
+			.validate()
+			.context("Classpath for the instance is invalid")?;
+		let handle = crate::launch::launch(self.get_launch_parameters(), o)
+			.await
+			.context("Failed to run launch routine")?;
+		Ok(handle)
+	}
+
+	/// Get the command that launching the instance would run, without running it.
+	/// This may still authenticate the chosen user
+	pub async fn get_launch_command(
+		&mut self,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<LaunchCommand> {
+		crate::launch::get_instance_launch_command(&mut self.get_launch_parameters(), o)
+			.await
+			.context("Failed to create launch command")
+	}
+
+	/// Get the parameters for the launch routine
+	fn get_launch_parameters(&mut self) -> LaunchParameters<'_> {
+		LaunchParameters {
 			version: self.params.version,
 			version_manifest: self.params.version_manifest,
 			side: &self.config.side,
 			launch_dir: &self.config.path,
+			game_assets_dir: &self.game_assets_dir,
 			java: &self.java,
 			classpath: &self.classpath,
 			main_class: &self.main_class,
@@ -216,11 +267,7 @@ impl<'params> Instance<'params> {
 			users: self.params.users,
 			censor_secrets: self.params.censor_secrets,
 			branding: self.params.branding,
-		};
-		let handle = crate::launch::launch(params, o)
-			.await
-			.context("Failed to run launch routine")?;
-		Ok(handle)
+		}
 	}
 
 	/// Get the JAR path of the instance
@@ -229,6 +276,24 @@ impl<'params> Instance<'params> {
 	}
 }
 
+/// Build the classpath of an instance. The additional libraries, like the ones from modloaders,
+/// come first, then the libraries of the Minecraft version, and then the game JAR. When a library
+/// is included twice with different versions, the version from the additional libraries is used
+fn build_classpath(
+	additional_libs: &[PathBuf],
+	game_libs: Classpath,
+	jar_path: &Path,
+) -> anyhow::Result<Classpath> {
+	let mut classpath = Classpath::new();
+	for lib in additional_libs {
+		classpath.add_path(lib)?;
+	}
+	classpath.extend(game_libs);
+	classpath.add_path(jar_path)?;
+
+	Ok(classpath)
+}
+
 /// Configuration for an instance
 pub struct InstanceConfiguration {
 	/// Configuration for the instance side
@@ -241,7 +306,7 @@ pub struct InstanceConfiguration {
 	pub jar_path: Option<PathBuf>,
 	/// Java main class override
 	pub main_class: Option<String>,
-	/// Additional libraries to add to the classpath.
+	/// Additional libraries to add to the classpath, before the libraries of the Minecraft version.
 	/// These must be absolute paths to Java libraries already installed on the
 	/// system, and will not be installed automatically
 	pub additional_libs: Vec<PathBuf>,
@@ -338,12 +403,17 @@ impl InstanceKind {
 pub struct ClientWindowConfig {
 	/// The resolution of the window
 	pub resolution: Option<WindowResolution>,
+	/// Whether the window should be fullscreen. Leaves the game's own setting alone if not set
+	pub fullscreen: Option<bool>,
 }
 
 impl ClientWindowConfig {
 	/// Construct a new ClientWindowConfig with default settings
 	pub fn new() -> Self {
-		Self { resolution: None }
+		Self {
+			resolution: None,
+			fullscreen: None,
+		}
 	}
 }
 
@@ -378,3 +448,26 @@ pub(crate) struct InstanceParameters<'a> {
 	pub disable_hardlinks: bool,
 	pub branding: &'a BrandingProperties,
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_classpath_order() {
+		let libraries = PathBuf::from("libraries");
+		let loader_asm = libraries.join("org/ow2/asm/asm/9.6/asm-9.6.jar");
+		let loader = libraries.join("net/fabricmc/fabric-loader/0.15.0/fabric-loader-0.15.0.jar");
+		let mut game_libs = Classpath::new();
+		let vanilla_asm = libraries.join("org/ow2/asm/asm/9.3/asm-9.3.jar");
+		let lwjgl = libraries.join("org/lwjgl/lwjgl/3.3.1/lwjgl-3.3.1.jar");
+		game_libs.add_path(&vanilla_asm).unwrap();
+		game_libs.add_path(&lwjgl).unwrap();
+		let jar = PathBuf::from("client.jar");
+
+		let classpath =
+			build_classpath(&[loader_asm.clone(), loader.clone()], game_libs, &jar).unwrap();
+		assert_eq!(classpath.get_paths(), [loader_asm, loader, lwjgl, jar]);
+		assert_eq!(classpath.get_conflicts().len(), 1);
+	}
+}
@@ -6,6 +6,17 @@ use std::io::{BufWriter, Write};
 use std::path::Path;
 
 use anyhow::ensure;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Held while writing files that are shared between versions, like assets and libraries,
+/// so that versions that are installed at the same time don't write the same file at once
+static SHARED_FILES_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Wait until no other version is writing shared files, and keep them from doing so
+/// until the returned guard is dropped
+pub async fn lock_shared_files() -> MutexGuard<'static, ()> {
+	SHARED_FILES_LOCK.lock().await
+}
 
 /// Create a directory that may already exist without an error
 pub fn create_dir(path: &Path) -> std::io::Result<()> {
@@ -43,6 +54,17 @@ pub fn update_hardlink(path: &Path, link: &Path) -> std::io::Result<()> {
 	Ok(())
 }
 
+/// Creates a new hardlink if it does not exist, copying the file instead if it can't be linked.
+/// Hardlinks can fail on filesystems that don't support them, such as FAT32 and some network
+/// drives on Windows, or when the link is on a different drive
+pub fn update_hardlink_or_copy(path: &Path, link: &Path) -> std::io::Result<()> {
+	if !link.exists() && fs::hard_link(path, link).is_err() {
+		fs::copy(path, link)?;
+	}
+
+	Ok(())
+}
+
 /// Creates a new hardlink if it does not exist
 pub async fn update_hardlink_async(path: &Path, link: &Path) -> std::io::Result<()> {
 	if !link.exists() {
@@ -52,11 +74,22 @@ pub async fn update_hardlink_async(path: &Path, link: &Path) -> std::io::Result<
 	Ok(())
 }
 
-/// Cross platform - create a directory soft link
+/// Check whether an IO error was caused by trying to link a file across filesystems
+pub fn is_cross_device_error(err: &std::io::Error) -> bool {
+	// ERROR_NOT_SAME_DEVICE on Windows and EXDEV everywhere else
+	let code = if cfg!(windows) { 17 } else { 18 };
+	err.raw_os_error() == Some(code)
+}
+
+/// Cross platform - create a directory soft link. Symlinks need elevated rights or developer mode
+/// on Windows, so the directory is copied instead when the symlink can't be created
 #[cfg(target_os = "windows")]
 pub fn dir_symlink(path: &Path, target: &Path) -> std::io::Result<()> {
-	std::os::windows::fs::symlink_dir(path, target)?;
-	Ok(())
+	if std::os::windows::fs::symlink_dir(path, target).is_ok() {
+		return Ok(());
+	}
+
+	copy_dir_recursive(path, target).map_err(std::io::Error::other)
 }
 
 /// Cross platform - create a directory soft link
@@ -108,6 +141,37 @@ pub async fn copy_dir_contents_async(src: &Path, dest: &Path) -> anyhow::Result<
 	Ok(())
 }
 
+/// Move a directory to another location. If it can't simply be renamed, such as when the
+/// destination is on another drive or already exists, its contents are copied into the destination
+/// and the original is removed
+pub fn move_dir(src: &Path, dest: &Path) -> anyhow::Result<()> {
+	create_leading_dirs(dest)?;
+	if fs::rename(src, dest).is_ok() {
+		return Ok(());
+	}
+
+	copy_dir_recursive(src, dest)?;
+	fs::remove_dir_all(src)?;
+
+	Ok(())
+}
+
+/// Copy a directory and all of its subdirectories into another directory
+fn copy_dir_recursive(src: &Path, dest: &Path) -> anyhow::Result<()> {
+	create_dir(dest)?;
+	for entry in fs::read_dir(src)? {
+		let entry = entry?;
+		let dest_path = dest.join(entry.file_name());
+		if entry.file_type()?.is_dir() {
+			copy_dir_recursive(&entry.path(), &dest_path)?;
+		} else {
+			fs::copy(entry.path(), dest_path)?;
+		}
+	}
+
+	Ok(())
+}
+
 /// Simple tweak of std::fs::write to use a BufWriter
 pub fn write_buffered<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> std::io::Result<()> {
 	fn inner(path: &Path, contents: &[u8]) -> std::io::Result<()> {
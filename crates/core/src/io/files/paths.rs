@@ -1,7 +1,16 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use directories::{BaseDirs, ProjectDirs};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// The environment variable that overrides the data directory
+pub const DATA_DIR_ENV: &str = "MCVM_DATA_DIR";
+/// The name of the file in the internal directory that marks that the cache layout is being used.
+/// Once it exists, the old locations in the data directory are never used again
+pub const LAYOUT_MARKER_FILE: &str = "cache_layout";
+/// The directories of downloaded files that are stored in the cache directory instead of
+/// the internal directory, unless the legacy layout is being used
+pub const CACHE_DIRS: [&str; 5] = ["assets", "libraries", "natives", "jars", "versions"];
 
 /// Store for all of the paths that are used throughout the application
 #[derive(Debug, Clone)]
@@ -12,12 +21,18 @@ pub struct Paths {
 	pub project: ProjectDirs,
 	/// Holds data
 	pub data: PathBuf,
+	/// Holds cached files that can be downloaded again
+	pub cache: PathBuf,
 	/// Holds internal data
 	pub internal: PathBuf,
 	/// Holds game assets
 	pub assets: PathBuf,
 	/// Holds game libraries
 	pub libraries: PathBuf,
+	/// Holds native library jars
+	pub natives: PathBuf,
+	/// Holds the version manifest and version metadata
+	pub versions: PathBuf,
 	/// Holds Java installations
 	pub java: PathBuf,
 	/// Holds game jar files
@@ -30,6 +45,9 @@ pub struct Paths {
 	pub launch_logs: PathBuf,
 	/// Used for runtime info like PIDs
 	pub run: PathBuf,
+	/// Whether downloaded files are still stored in the internal directory because
+	/// the data directory hasn't been migrated to the cache layout yet
+	pub legacy_layout: bool,
 }
 
 impl Paths {
@@ -45,31 +63,69 @@ impl Paths {
 	/// Create the directories on an existing set of paths
 	pub fn create_dirs(&self) -> anyhow::Result<()> {
 		std::fs::create_dir_all(&self.data)?;
-		std::fs::create_dir_all(self.project.cache_dir())?;
+		std::fs::create_dir_all(&self.cache)?;
 		std::fs::create_dir_all(self.project.config_dir())?;
 		std::fs::create_dir_all(&self.internal)?;
 		std::fs::create_dir_all(&self.assets)?;
+		std::fs::create_dir_all(&self.libraries)?;
+		std::fs::create_dir_all(&self.natives)?;
+		std::fs::create_dir_all(&self.versions)?;
 		std::fs::create_dir_all(&self.java)?;
 		std::fs::create_dir_all(&self.jars)?;
 		std::fs::create_dir_all(&self.auth)?;
 		std::fs::create_dir_all(&self.logs)?;
 		std::fs::create_dir_all(&self.launch_logs)?;
 		std::fs::create_dir_all(&self.run)?;
+		if !self.legacy_layout {
+			let marker = self.internal.join(LAYOUT_MARKER_FILE);
+			if !marker.exists() {
+				std::fs::write(marker, "").context("Failed to write layout marker")?;
+			}
+		}
 		Ok(())
 	}
 
 	/// Create the paths without creating any directories
 	pub fn new_no_create() -> anyhow::Result<Self> {
+		Self::resolve(None)
+	}
+
+	/// Create the paths without creating any directories, using a data directory from the user's
+	/// preferences. All paths are created through this function so that the overrides are always
+	/// used. The MCVM_DATA_DIR environment variable takes priority over the preference, and when either
+	/// is used, cached files are stored in a `cache` directory inside of it
+	pub fn resolve(data_dir_preference: Option<PathBuf>) -> anyhow::Result<Self> {
 		let base = BaseDirs::new().ok_or(anyhow!("Failed to create base directories"))?;
-		let project = ProjectDirs::from("", "mcvm", "mcvm")
-			.ok_or(anyhow!("Failed to create project directories"))?;
+		let project = get_project_dirs()?;
+
+		// Relative paths from the environment are resolved now so that the paths are always absolute
+		let data_dir_override = std::env::var_os(DATA_DIR_ENV)
+			.filter(|x| !x.is_empty())
+			.map(|x| match std::env::current_dir() {
+				Ok(current_dir) => current_dir.join(x),
+				Err(..) => PathBuf::from(x),
+			})
+			.or(data_dir_preference);
+		let (data, cache) = match data_dir_override {
+			Some(data) => {
+				let cache = data.join("cache");
+				(data, cache)
+			}
+			None => (
+				project.data_dir().to_owned(),
+				project.cache_dir().to_owned(),
+			),
+		};
 
-		let data = project.data_dir().to_owned();
 		let internal = data.join("internal");
-		let assets = internal.join("assets");
-		let libraries = internal.join("libraries");
+		let legacy_layout = uses_legacy_layout(&internal);
+		let downloads = if legacy_layout { &internal } else { &cache };
+		let assets = downloads.join("assets");
+		let libraries = downloads.join("libraries");
+		let natives = downloads.join("natives");
+		let versions = downloads.join("versions");
+		let jars = downloads.join("jars");
 		let java = internal.join("java");
-		let jars = internal.join("jars");
 		let auth = internal.join("auth");
 		let logs = data.join("logs");
 		let launch_logs = logs.join("launch");
@@ -82,15 +138,85 @@ impl Paths {
 			base,
 			project,
 			data,
+			cache,
 			internal,
 			assets,
 			libraries,
+			natives,
+			versions,
 			java,
 			jars,
 			auth,
 			logs,
 			launch_logs,
 			run,
+			legacy_layout,
 		})
 	}
+
+	/// Move downloaded files from the internal directory to the cache directory and mark the
+	/// data directory as migrated. Returns the names of the directories that were moved.
+	/// The paths have to be resolved again afterwards to use the new locations
+	pub fn migrate_layout(&self) -> anyhow::Result<Vec<&'static str>> {
+		let mut moved = Vec::new();
+		for dir in CACHE_DIRS {
+			let src = self.internal.join(dir);
+			if !src.exists() {
+				continue;
+			}
+			super::move_dir(&src, &self.cache.join(dir))
+				.with_context(|| format!("Failed to move the {dir} directory"))?;
+			moved.push(dir);
+		}
+
+		std::fs::create_dir_all(&self.internal)?;
+		std::fs::write(self.internal.join(LAYOUT_MARKER_FILE), "")
+			.context("Failed to write layout marker")?;
+
+		Ok(moved)
+	}
+}
+
+/// Get the default project-specific directories, before any overrides
+pub fn get_project_dirs() -> anyhow::Result<ProjectDirs> {
+	ProjectDirs::from("", "mcvm", "mcvm").ok_or(anyhow!("Failed to create project directories"))
+}
+
+/// Check whether downloaded files should still be stored in the internal directory.
+/// This is the case when files from before the cache layout exist and haven't been migrated
+fn uses_legacy_layout(internal: &Path) -> bool {
+	if internal.join(LAYOUT_MARKER_FILE).exists() {
+		return false;
+	}
+	CACHE_DIRS.iter().any(|x| internal.join(x).exists())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_layout_migration() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let internal = dir.join("internal");
+		assert!(!uses_legacy_layout(&internal));
+
+		std::fs::create_dir_all(internal.join("jars")).unwrap();
+		std::fs::write(internal.join("jars").join("1.20.jar"), "jar").unwrap();
+		assert!(uses_legacy_layout(&internal));
+
+		let mut paths = Paths::resolve(Some(dir.to_path_buf())).unwrap();
+		paths.data = dir.to_path_buf();
+		paths.cache = dir.join("cache");
+		paths.internal = internal.clone();
+		assert_eq!(paths.migrate_layout().unwrap(), vec!["jars"]);
+		assert!(!uses_legacy_layout(&internal));
+		assert!(!internal.join("jars").exists());
+		assert!(dir.join("cache").join("jars").join("1.20.jar").exists());
+
+		// Old directories that show up again don't switch back to the legacy layout
+		std::fs::create_dir_all(internal.join("assets")).unwrap();
+		assert!(!uses_legacy_layout(&internal));
+	}
 }
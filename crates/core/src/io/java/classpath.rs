@@ -1,6 +1,6 @@
 use std::path::{Path, PathBuf};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 
 /// The separator for entries in the classpath
 #[cfg(target_os = "linux")]
@@ -12,36 +12,59 @@ pub const CLASSPATH_SEP: char = ':';
 /// The separator for entries in the classpath
 pub const CLASSPATH_SEP: char = ';';
 
-/// A utility for working with Java classpaths
+/// A utility for working with Java classpaths.
+///
+/// Entries are kept in the order they are added, and are deduplicated as they are added.
+/// Libraries stored in the Maven layout (`group/artifact/version/artifact-version.jar`)
+/// are only added once per artifact and classifier. If another version of a library
+/// that is already on the classpath is added, the one that was added first is kept and the
+/// conflict is recorded. Because of this, entries that should take priority have to be added first.
+/// Instances add the libraries of their modloader, then the libraries of the Minecraft version,
+/// and then the game JAR
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Classpath {
-	string: String,
+	entries: Vec<String>,
+	conflicts: Vec<ClasspathConflict>,
 }
 
 impl Classpath {
 	/// Create a new empty classpath
 	pub fn new() -> Self {
 		Self {
-			string: String::new(),
+			entries: Vec::new(),
+			conflicts: Vec::new(),
 		}
 	}
 
-	/// Append the classpath separator to the end of the string.
-	/// This can create invalid classpaths, so don't use it unless
-	/// you know what you are doing.
-	pub fn add_sep(&mut self) {
-		self.string.push(CLASSPATH_SEP);
+	/// Appends a string to the end of the classpath. The string can contain
+	/// multiple entries separated by the classpath separator
+	pub fn add(&mut self, string: &str) {
+		for entry in string.split(CLASSPATH_SEP) {
+			self.add_entry(entry);
+		}
 	}
 
-	/// Appends a string to the end of the classpath
-	pub fn add(&mut self, string: &str) {
-		if let Some(last_char) = self.string.chars().last() {
-			if last_char != CLASSPATH_SEP {
-				self.add_sep();
+	/// Add a single entry, skipping it if it is a duplicate
+	fn add_entry(&mut self, entry: &str) {
+		if entry.is_empty() || self.entries.iter().any(|x| x == entry) {
+			return;
+		}
+
+		if let Some(library) = LibraryCoordinate::parse(Path::new(entry)) {
+			let existing = self.entries.iter().find_map(|x| {
+				LibraryCoordinate::parse(Path::new(x)).filter(|x| x.is_same_artifact(&library))
+			});
+			if let Some(existing) = existing {
+				self.conflicts.push(ClasspathConflict {
+					artifact: library.artifact,
+					kept_version: existing.version,
+					skipped_version: library.version,
+				});
+				return;
 			}
 		}
 
-		self.string.push_str(string);
+		self.entries.push(entry.to_string());
 	}
 
 	/// Converts a path to a string and appends it to the classpath
@@ -56,20 +79,111 @@ impl Classpath {
 
 	/// Extends the classpath with another classpath
 	pub fn extend(&mut self, other: Classpath) {
-		self.add(&other.string)
+		for entry in &other.entries {
+			self.add_entry(entry);
+		}
+		self.conflicts.extend(other.conflicts);
 	}
 
 	/// Obtain the classpath as a string
 	pub fn get_str(&self) -> String {
-		self.string.clone()
+		self.entries.join(&CLASSPATH_SEP.to_string())
 	}
 
 	/// Split the classpath into a vector of paths
 	pub fn get_paths(&self) -> Vec<PathBuf> {
-		self.string
-			.split(CLASSPATH_SEP)
-			.map(PathBuf::from)
-			.collect()
+		self.entries.iter().map(PathBuf::from).collect()
+	}
+
+	/// Get the libraries that were skipped because another version of them
+	/// was already on the classpath
+	pub fn get_conflicts(&self) -> &[ClasspathConflict] {
+		&self.conflicts
+	}
+
+	/// Check that every entry of the classpath exists. Wildcard entries
+	/// are checked by the directory that they are in
+	pub fn validate(&self) -> anyhow::Result<()> {
+		let missing: Vec<_> = self
+			.entries
+			.iter()
+			.filter(|entry| {
+				let path = match entry.strip_suffix('*') {
+					Some(dir) => Path::new(dir),
+					None => Path::new(entry.as_str()),
+				};
+				!path.exists()
+			})
+			.map(String::as_str)
+			.collect();
+		if !missing.is_empty() {
+			bail!(
+				"Files on the classpath do not exist:\n{}",
+				missing.join("\n")
+			);
+		}
+
+		Ok(())
+	}
+}
+
+/// A library that was left out of a classpath because another version of it was added first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClasspathConflict {
+	/// The name of the library's artifact
+	pub artifact: String,
+	/// The version of the library that is on the classpath
+	pub kept_version: String,
+	/// The version of the library that was left out
+	pub skipped_version: String,
+}
+
+/// The Maven coordinate of a library on the classpath, parsed from its path
+#[derive(Debug)]
+struct LibraryCoordinate {
+	/// The directory of the artifact, which contains a directory for every version.
+	/// Since it includes the group, this identifies the library as long as all of
+	/// the libraries are stored in the same place
+	artifact_dir: PathBuf,
+	artifact: String,
+	version: String,
+	classifier: String,
+}
+
+impl LibraryCoordinate {
+	/// Parse the coordinate of a library from its path. Returns None
+	/// if the path isn't in the Maven layout
+	fn parse(path: &Path) -> Option<Self> {
+		let file_name = path.file_name()?.to_str()?;
+		let version_dir = path.parent()?;
+		let version = version_dir.file_name()?.to_str()?;
+		let artifact_dir = version_dir.parent()?;
+		let artifact = artifact_dir.file_name()?.to_str()?;
+		// There has to be a group
+		artifact_dir.parent()?.file_name()?;
+
+		let rest = file_name
+			.strip_prefix(artifact)?
+			.strip_prefix('-')?
+			.strip_prefix(version)?
+			.strip_suffix(".jar")?;
+		let classifier = if rest.is_empty() {
+			""
+		} else {
+			rest.strip_prefix('-')?
+		};
+
+		Some(Self {
+			artifact_dir: artifact_dir.to_owned(),
+			artifact: artifact.to_string(),
+			version: version.to_string(),
+			classifier: classifier.to_string(),
+		})
+	}
+
+	/// Check whether this is the same artifact as another one, ignoring the version
+	fn is_same_artifact(&self, other: &Self) -> bool {
+		self.artifact_dir == other.artifact_dir && self.classifier == other.classifier
 	}
 }
 
@@ -77,10 +191,25 @@ impl Classpath {
 mod tests {
 	use super::*;
 
+	/// Get the path of a library in the Maven layout as a string
+	fn lib(group: &str, artifact: &str, version: &str, classifier: Option<&str>) -> String {
+		let file_name = match classifier {
+			Some(classifier) => format!("{artifact}-{version}-{classifier}.jar"),
+			None => format!("{artifact}-{version}.jar"),
+		};
+		let path = PathBuf::from("libraries")
+			.join(group.replace('.', "/"))
+			.join(artifact)
+			.join(version)
+			.join(file_name);
+		path.to_str().unwrap().to_string()
+	}
+
 	#[test]
 	fn test_classpath() {
 		let mut classpath = Classpath::new();
 		assert_eq!(classpath.get_str(), String::new());
+		assert!(classpath.get_paths().is_empty());
 		classpath.add("foo");
 		assert_eq!(classpath.get_str(), "foo".to_string());
 		classpath.add("bar");
@@ -105,4 +234,89 @@ mod tests {
 			format!("foo{0}bar{0}baz{0}hello{0}world", CLASSPATH_SEP)
 		);
 	}
+
+	#[test]
+	fn test_classpath_dedup() {
+		let loader_asm = lib("org.ow2.asm", "asm", "9.6", None);
+		let vanilla_asm = lib("org.ow2.asm", "asm", "9.3", None);
+		let lwjgl = lib("org.lwjgl", "lwjgl", "3.3.1", None);
+		let lwjgl_natives = lib("org.lwjgl", "lwjgl", "3.3.1", Some("natives-linux"));
+		let other_asm = lib("com.example", "asm", "1.0", None);
+
+		let mut loader = Classpath::new();
+		loader.add(&loader_asm);
+		let mut vanilla = Classpath::new();
+		vanilla.add(&vanilla_asm);
+		vanilla.add(&lwjgl);
+		vanilla.add(&lwjgl_natives);
+		vanilla.add(&lwjgl);
+		vanilla.add(&other_asm);
+
+		let mut classpath = Classpath::new();
+		classpath.extend(loader);
+		classpath.extend(vanilla);
+		classpath.add("client.jar");
+		classpath.add("client.jar");
+
+		assert_eq!(
+			classpath.get_paths(),
+			[
+				&loader_asm,
+				&lwjgl,
+				&lwjgl_natives,
+				&other_asm,
+				"client.jar"
+			]
+			.map(PathBuf::from)
+		);
+		assert_eq!(
+			classpath.get_conflicts(),
+			[ClasspathConflict {
+				artifact: "asm".into(),
+				kept_version: "9.6".into(),
+				skipped_version: "9.3".into(),
+			}]
+		);
+	}
+
+	#[test]
+	fn test_library_coordinate_parsing() {
+		let coordinate = LibraryCoordinate::parse(Path::new(&lib(
+			"net.fabricmc",
+			"sponge-mixin",
+			"0.12.5+mixin.0.8.5",
+			None,
+		)))
+		.unwrap();
+		assert_eq!(coordinate.artifact, "sponge-mixin");
+		assert_eq!(coordinate.version, "0.12.5+mixin.0.8.5");
+		assert_eq!(coordinate.classifier, "");
+
+		let coordinate = LibraryCoordinate::parse(Path::new(&lib(
+			"org.lwjgl",
+			"lwjgl",
+			"3.3.1",
+			Some("natives-windows"),
+		)))
+		.unwrap();
+		assert_eq!(coordinate.classifier, "natives-windows");
+
+		assert!(LibraryCoordinate::parse(Path::new("versions/1.20.1/1.20.1.jar")).is_none());
+		assert!(LibraryCoordinate::parse(Path::new("client.jar")).is_none());
+	}
+
+	#[test]
+	fn test_classpath_validate() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let jar = dir.join("client.jar");
+		std::fs::write(&jar, "").unwrap();
+
+		let mut classpath = Classpath::new();
+		classpath.add_path(&jar).unwrap();
+		classpath.add(&format!("{}/*", dir.to_str().unwrap()));
+		assert!(classpath.validate().is_ok());
+		classpath.add_path(&dir.join("missing.jar")).unwrap();
+		assert!(classpath.validate().is_err());
+	}
 }
@@ -12,6 +12,7 @@ use anyhow::{bail, Context};
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
 use mcvm_shared::translate;
 use tar::Archive;
+use tokio::sync::Mutex;
 use zip::ZipArchive;
 
 use crate::io::files::{self, paths::Paths};
@@ -22,6 +23,10 @@ use mcvm_shared::util::preferred_archive_extension;
 
 use super::JavaMajorVersion;
 
+/// Held while installing Java so that multiple cores updating at the same time don't
+/// download the same installation or overwrite each other's persistent data
+static INSTALL_LOCK: Mutex<()> = Mutex::const_new(());
+
 /// Type of Java installation
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum JavaInstallationKind {
@@ -86,14 +91,22 @@ impl JavaInstallation {
 
 		let vers_str = major_version.to_string();
 
+		// Another core may have installed Java since our persistent data was loaded
+		let _lock = INSTALL_LOCK.lock().await;
+		params
+			.persistent
+			.reload(params.paths)
+			.context("Failed to reload persistent data")?;
+
 		let path = match &kind {
-			JavaInstallationKind::Auto => install_auto(&vers_str, params, o).await?,
+			JavaInstallationKind::Auto => install_auto(&vers_str, &mut params, o).await?,
 			JavaInstallationKind::System => system::install(&vers_str)?,
 			JavaInstallationKind::Adoptium => install_adoptium(&vers_str, &mut params, o).await?,
 			JavaInstallationKind::Zulu => install_zulu(&vers_str, &mut params, o).await?,
 			JavaInstallationKind::GraalVM => install_graalvm(&vers_str, &mut params, o).await?,
 			JavaInstallationKind::Custom { path } => path.clone(),
 		};
+		params.persistent.dump(params.paths).await?;
 
 		o.display(
 			MessageContents::Success(translate!(o, FinishCheckingForJavaUpdates)),
@@ -162,22 +175,22 @@ pub(crate) struct JavaInstallParameters<'a> {
 
 async fn install_auto(
 	major_version: &str,
-	mut params: JavaInstallParameters<'_>,
+	params: &mut JavaInstallParameters<'_>,
 	o: &mut impl MCVMOutput,
 ) -> anyhow::Result<PathBuf> {
 	let out = system::install(major_version);
 	if let Ok(out) = out {
 		return Ok(out);
 	}
-	let out = install_adoptium(major_version, &mut params, o).await;
+	let out = install_adoptium(major_version, params, o).await;
 	if let Ok(out) = out {
 		return Ok(out);
 	}
-	let out = install_graalvm(major_version, &mut params, o).await;
+	let out = install_graalvm(major_version, params, o).await;
 	if let Ok(out) = out {
 		return Ok(out);
 	}
-	let out = install_zulu(major_version, &mut params, o).await;
+	let out = install_zulu(major_version, params, o).await;
 	if let Ok(out) = out {
 		return Ok(out);
 	}
@@ -41,8 +41,10 @@ struct PersistentDataJava {
 	graalvm: HashMap<String, PersistentDataJavaVersion>,
 }
 
-/// Used as a function argument
-pub(crate) enum PersistentDataJavaInstallation {
+/// A vendor of Java installations that MCVM downloads
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum PersistentDataJavaInstallation {
 	/// Adoptium Java
 	Adoptium,
 	/// Zulu Java
@@ -51,6 +53,43 @@ pub(crate) enum PersistentDataJavaInstallation {
 	GraalVM,
 }
 
+impl PersistentDataJavaInstallation {
+	/// All of the vendors
+	pub const ALL: [Self; 3] = [Self::Adoptium, Self::Zulu, Self::GraalVM];
+
+	/// Parse a vendor from its name
+	pub fn parse_from_str(string: &str) -> Option<Self> {
+		match string {
+			"adoptium" => Some(Self::Adoptium),
+			"zulu" => Some(Self::Zulu),
+			"graalvm" => Some(Self::GraalVM),
+			_ => None,
+		}
+	}
+
+	/// Get the name of the vendor
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Adoptium => "adoptium",
+			Self::Zulu => "zulu",
+			Self::GraalVM => "graalvm",
+		}
+	}
+}
+
+/// A Java installation that was downloaded by MCVM
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManagedJavaInstallation {
+	/// The vendor of the installation
+	pub vendor: PersistentDataJavaInstallation,
+	/// The major Java version of the installation
+	pub major_version: String,
+	/// The full version of the installation
+	pub version: String,
+	/// The path to the installation directory
+	pub path: PathBuf,
+}
+
 impl PersistentDataContents {
 	/// Fix changes in persistent data format
 	pub fn fix(&mut self) {}
@@ -69,6 +108,13 @@ impl PersistentData {
 		Ok(Self { contents })
 	}
 
+	/// Read the persistent data file again, replacing any changes that haven't been written
+	pub(crate) fn reload(&mut self, paths: &Paths) -> anyhow::Result<()> {
+		*self = Self::open(paths)?;
+
+		Ok(())
+	}
+
 	/// Get the path to the persistent data file
 	pub fn get_path(paths: &Paths) -> PathBuf {
 		paths.internal.join("core_persistent.json")
@@ -83,6 +129,18 @@ impl PersistentData {
 		Ok(())
 	}
 
+	/// Get the map of major versions to installations for a vendor
+	fn get_java_vendor(
+		&mut self,
+		installation: PersistentDataJavaInstallation,
+	) -> &mut HashMap<String, PersistentDataJavaVersion> {
+		match installation {
+			PersistentDataJavaInstallation::Adoptium => &mut self.contents.java.adoptium,
+			PersistentDataJavaInstallation::Zulu => &mut self.contents.java.zulu,
+			PersistentDataJavaInstallation::GraalVM => &mut self.contents.java.graalvm,
+		}
+	}
+
 	/// Updates a Java installation with a new version. Returns true if the version has changed.
 	pub(crate) fn update_java_installation(
 		&mut self,
@@ -91,11 +149,7 @@ impl PersistentData {
 		version: &str,
 		path: &Path,
 	) -> anyhow::Result<bool> {
-		let installation = match installation {
-			PersistentDataJavaInstallation::Adoptium => &mut self.contents.java.adoptium,
-			PersistentDataJavaInstallation::Zulu => &mut self.contents.java.zulu,
-			PersistentDataJavaInstallation::GraalVM => &mut self.contents.java.graalvm,
-		};
+		let installation = self.get_java_vendor(installation);
 		let path_str = path.to_string_lossy().to_string();
 		if let Some(current_version) = installation.get_mut(major_version) {
 			if current_version.version == version {
@@ -137,4 +191,77 @@ impl PersistentData {
 		let version = installation.get(version)?;
 		Some(PathBuf::from(version.path.clone()))
 	}
+
+	/// Get all of the Java installations that have been downloaded, sorted by vendor and major version
+	pub fn get_managed_java_installations(&self) -> Vec<ManagedJavaInstallation> {
+		let mut out = Vec::new();
+		for vendor in PersistentDataJavaInstallation::ALL {
+			let installation = match vendor {
+				PersistentDataJavaInstallation::Adoptium => &self.contents.java.adoptium,
+				PersistentDataJavaInstallation::Zulu => &self.contents.java.zulu,
+				PersistentDataJavaInstallation::GraalVM => &self.contents.java.graalvm,
+			};
+			for (major_version, version) in installation {
+				out.push(ManagedJavaInstallation {
+					vendor,
+					major_version: major_version.clone(),
+					version: version.version.clone(),
+					path: PathBuf::from(&version.path),
+				});
+			}
+		}
+		out.sort_by_key(|x| {
+			(
+				x.vendor,
+				x.major_version.parse::<u16>().unwrap_or_default(),
+				x.major_version.clone(),
+			)
+		});
+
+		out
+	}
+
+	/// Removes a downloaded Java installation along with its files. Returns the installation
+	/// that was removed, if there was one
+	pub fn remove_java_installation(
+		&mut self,
+		installation: PersistentDataJavaInstallation,
+		major_version: &str,
+	) -> anyhow::Result<Option<ManagedJavaInstallation>> {
+		let Some(removed) = self.get_java_vendor(installation).remove(major_version) else {
+			return Ok(None);
+		};
+		let path = PathBuf::from(removed.path);
+		if path.exists() {
+			fs::remove_dir_all(&path).context("Failed to remove Java installation")?;
+		}
+
+		Ok(Some(ManagedJavaInstallation {
+			vendor: installation,
+			major_version: major_version.to_string(),
+			version: removed.version,
+			path,
+		}))
+	}
+
+	/// Get the names and paths of all of the Java installations that have been downloaded
+	pub fn get_java_installations(&self) -> Vec<(String, PathBuf)> {
+		let installations = [
+			("adoptium", &self.contents.java.adoptium),
+			("zulu", &self.contents.java.zulu),
+			("graalvm", &self.contents.java.graalvm),
+		];
+		let mut out = Vec::new();
+		for (kind, installation) in installations {
+			for (major_version, version) in installation {
+				out.push((
+					format!("{kind} {major_version} ({})", version.version),
+					PathBuf::from(&version.path),
+				));
+			}
+		}
+		out.sort();
+
+		out
+	}
 }
@@ -1,5 +1,8 @@
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::net::game_files::cache::DEFAULT_METADATA_FRESHNESS;
 
 /// Manager for when we are updating profile files.
 /// It will keep track of files we have already downloaded, manage task requirements, etc
@@ -9,6 +12,8 @@ pub struct UpdateManager {
 	pub(crate) force: bool,
 	/// Whether we will prioritize local files instead of remote ones
 	pub(crate) allow_offline: bool,
+	/// How long downloaded metadata like the version manifest is used before checking for changes
+	pub(crate) metadata_freshness: Duration,
 	/// File paths that are added when they have been updated by other functions
 	files: HashSet<PathBuf>,
 }
@@ -19,10 +24,16 @@ impl UpdateManager {
 		Self {
 			force,
 			allow_offline,
+			metadata_freshness: DEFAULT_METADATA_FRESHNESS,
 			files: HashSet::new(),
 		}
 	}
 
+	/// Set how long downloaded metadata is used before checking for changes
+	pub fn set_metadata_freshness(&mut self, freshness: Duration) {
+		self.metadata_freshness = freshness;
+	}
+
 	/// Add a single tracked file to the manager
 	pub fn add_file(&mut self, file: PathBuf) {
 		self.files.insert(file);
@@ -1,106 +1,57 @@
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
-use mcvm_shared::util::{ARCH_STRING, OS_STRING};
 use mcvm_shared::versions::VersionPattern;
 
 use crate::instance::{InstanceKind, WindowResolution};
 use crate::launch::{LaunchParameters, QuickPlayType};
 
 use crate::io::files::paths::Paths;
-use crate::net::game_files::assets::get_virtual_dir_path;
 use crate::net::game_files::client_meta::args::ArgumentItem;
+use crate::net::game_files::client_meta::conditions::{check_rules, FeatureState, RuleState};
+use crate::net::game_files::client_meta::ClientLogInfo;
 use crate::user::UserKind;
 
 /// Process an argument for the client from the client meta
 pub(crate) fn process_arg(arg: &ArgumentItem, params: &LaunchParameters) -> Vec<String> {
-	let mut out = Vec::new();
+	let state = RuleState::current(get_feature_state(params));
+	expand_arg(arg, &state, |arg| process_simple_arg(arg, params))
+}
+
+/// Get the features that argument rules check from the state of the instance
+fn get_feature_state(params: &LaunchParameters) -> FeatureState {
 	let InstanceKind::Client { window } = &params.side else {
 		panic!("Instance is not a client")
 	};
-	match arg {
-		ArgumentItem::Simple(arg) => {
-			let arg = process_simple_arg(arg, params);
-			if let Some(arg) = arg {
-				out.push(arg);
-			}
-		}
-		ArgumentItem::Conditional(arg) => {
-			for rule in &arg.rules {
-				let allowed = rule.action.is_allowed();
+	let quick_play = &params.launch_config.quick_play;
 
-				if let Some(os_name) = &rule.os.name {
-					if allowed != (OS_STRING == os_name.to_string()) {
-						return vec![];
-					}
-				}
-				if let Some(os_arch) = &rule.os.arch {
-					if allowed != (ARCH_STRING == os_arch.to_string()) {
-						return vec![];
-					}
-				}
-
-				if let Some(has_custom_resolution) = &rule.features.has_custom_resolution {
-					if *has_custom_resolution && window.resolution.is_none() {
-						return vec![];
-					}
-				}
-				if let Some(is_demo_user) = &rule.features.is_demo_user {
-					if *is_demo_user {
-						let use_demo = match params.users.get_chosen_user() {
-							Some(user) => matches!(user.kind, UserKind::Demo),
-							None => false,
-						};
-						if !use_demo {
-							return vec![];
-						}
-					}
-				}
-				if let Some(quick_play_support) = &rule.features.has_quick_play_support {
-					if *quick_play_support {
-						let uses_quick_play =
-							!matches!(params.launch_config.quick_play, QuickPlayType::None);
-						if !uses_quick_play {
-							return vec![];
-						}
-					}
-				}
-				if let Some(quick_play_singleplayer) = &rule.features.is_quick_play_singleplayer {
-					if *quick_play_singleplayer {
-						let uses_quick_play =
-							!matches!(params.launch_config.quick_play, QuickPlayType::World { .. });
-						if !uses_quick_play {
-							return vec![];
-						}
-					}
-				}
-				if let Some(quick_play_multiplayer) = &rule.features.is_quick_play_multiplayer {
-					if *quick_play_multiplayer {
-						let uses_quick_play = !matches!(
-							params.launch_config.quick_play,
-							QuickPlayType::Server { .. }
-						);
-						if !uses_quick_play {
-							return vec![];
-						}
-					}
-				}
-				if let Some(quick_play_realms) = &rule.features.is_quick_play_realms {
-					if *quick_play_realms {
-						let uses_quick_play =
-							!matches!(params.launch_config.quick_play, QuickPlayType::Realm { .. });
-						if !uses_quick_play {
-							return vec![];
-						}
-					}
-				}
-			}
+	FeatureState {
+		is_demo_user: params
+			.users
+			.get_chosen_user()
+			.is_some_and(|user| user.is_demo()),
+		has_custom_resolution: window.resolution.is_some(),
+		has_quick_play_support: !matches!(quick_play, QuickPlayType::None),
+		is_quick_play_singleplayer: matches!(quick_play, QuickPlayType::World { .. }),
+		is_quick_play_multiplayer: matches!(quick_play, QuickPlayType::Server { .. }),
+		is_quick_play_realms: matches!(quick_play, QuickPlayType::Realm { .. }),
+	}
+}
 
-			for arg in arg.value.iter() {
-				out.extend(process_simple_arg(arg, params));
+/// Expand an argument item into the arguments it produces, checking its rules
+/// and replacing placeholders in each argument with the given function
+fn expand_arg(
+	arg: &ArgumentItem,
+	state: &RuleState,
+	mut replace: impl FnMut(&str) -> Option<String>,
+) -> Vec<String> {
+	match arg {
+		ArgumentItem::Simple(arg) => replace(arg).into_iter().collect(),
+		ArgumentItem::Conditional(arg) => {
+			if !check_rules(&arg.rules, state) {
+				return Vec::new();
 			}
+			arg.value.iter().filter_map(|x| replace(x)).collect()
 		}
-	};
-
-	out
+	}
 }
 
 /// Process a simple string argument
@@ -133,8 +84,7 @@ pub(crate) fn replace_arg_placeholders(arg: &str, params: &LaunchParameters) ->
 		placeholder!("natives_directory"),
 		params
 			.paths
-			.internal
-			.join("versions")
+			.versions
 			.join(params.version.to_string())
 			.join("natives")
 			.to_str()?,
@@ -143,10 +93,13 @@ pub(crate) fn replace_arg_placeholders(arg: &str, params: &LaunchParameters) ->
 	out = out.replace(placeholder!("version_type"), "mcvm");
 	out = out.replace(placeholder!("game_directory"), params.launch_dir.to_str()?);
 	out = out.replace(placeholder!("assets_root"), params.paths.assets.to_str()?);
-	out = out.replace(placeholder!("assets_index_name"), params.version);
+	out = out.replace(
+		placeholder!("assets_index_name"),
+		&params.client_meta.assets_version,
+	);
 	out = out.replace(
 		placeholder!("game_assets"),
-		get_virtual_dir_path(params.paths).to_str()?,
+		params.game_assets_dir.to_str()?,
 	);
 
 	out = out.replace(placeholder!("clientid"), "mcvm");
@@ -198,7 +151,7 @@ pub(crate) fn replace_arg_placeholders(arg: &str, params: &LaunchParameters) ->
 		Some(user) => {
 			// User type
 			let user_type = match user.get_kind() {
-				UserKind::Microsoft { .. } => "msa",
+				UserKind::Demo | UserKind::Unverified => "legacy",
 				_ => "msa",
 			};
 			out = out.replace(placeholder!("user_type"), user_type);
@@ -299,7 +252,208 @@ pub fn create_quick_play_args(
 }
 
 /// Fill the logging path argument with the correct path
-pub fn fill_logging_path_arg(arg: String, version: &str, paths: &Paths) -> Option<String> {
-	let path = crate::net::game_files::log_config::get_path(version, paths);
-	Some(arg.replace(placeholder!("path"), path.to_str()?))
+pub fn fill_logging_path_arg(log_info: &ClientLogInfo, paths: &Paths) -> Option<String> {
+	let path = crate::net::game_files::log_config::get_path(log_info, paths);
+	Some(
+		log_info
+			.argument
+			.replace(placeholder!("path"), path.to_str()?),
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::net::game_files::client_meta::args::Arguments;
+	use crate::net::game_files::client_meta::LogInfo;
+
+	/// The arguments block from the 1.20.1 version JSON
+	const ARGUMENTS_1_20: &str = r#"{
+		"game": [
+			"--username", "${auth_player_name}",
+			"--version", "${version_name}",
+			"--gameDir", "${game_directory}",
+			"--accessToken", "${auth_access_token}",
+			{"rules": [{"action": "allow", "features": {"is_demo_user": true}}], "value": "--demo"},
+			{"rules": [{"action": "allow", "features": {"has_custom_resolution": true}}], "value": ["--width", "${resolution_width}", "--height", "${resolution_height}"]},
+			{"rules": [{"action": "allow", "features": {"has_quick_plays_support": true}}], "value": ["--quickPlayPath", "${quickPlayPath}"]},
+			{"rules": [{"action": "allow", "features": {"is_quick_play_singleplayer": true}}], "value": ["--quickPlaySingleplayer", "${quickPlaySingleplayer}"]},
+			{"rules": [{"action": "allow", "features": {"is_quick_play_multiplayer": true}}], "value": ["--quickPlayMultiplayer", "${quickPlayMultiplayer}"]},
+			{"rules": [{"action": "allow", "features": {"is_quick_play_realms": true}}], "value": ["--quickPlayRealms", "${quickPlayRealms}"]}
+		],
+		"jvm": [
+			{"rules": [{"action": "allow", "os": {"name": "osx"}}], "value": ["-XstartOnFirstThread"]},
+			{"rules": [{"action": "allow", "os": {"name": "windows"}}], "value": "-XX:HeapDumpPath=MojangTricksIntelDriversForPerformance_javaw.exe_minecraft.exe.heapdump"},
+			{"rules": [{"action": "allow", "os": {"arch": "x86"}}], "value": "-Xss1M"},
+			"-Djava.library.path=${natives_directory}",
+			"-cp", "${classpath}"
+		]
+	}"#;
+
+	fn expand(args: &[ArgumentItem], state: &RuleState) -> Vec<String> {
+		let values = [
+			("${auth_player_name}", "Steve"),
+			("${version_name}", "1.20.1"),
+			("${game_directory}", "game"),
+			("${auth_access_token}", "token"),
+			("${resolution_width}", "854"),
+			("${resolution_height}", "480"),
+			("${quickPlayPath}", "quickPlay/log.json"),
+			("${quickPlaySingleplayer}", "world"),
+			("${natives_directory}", "natives"),
+			("${classpath}", "cp"),
+		];
+		args.iter()
+			.flat_map(|arg| {
+				expand_arg(arg, state, |x| {
+					let mut x = x.to_string();
+					for (placeholder, value) in values {
+						x = x.replace(placeholder, value);
+					}
+					Some(x)
+				})
+			})
+			.collect()
+	}
+
+	fn get_args() -> (Vec<ArgumentItem>, Vec<ArgumentItem>) {
+		let Arguments::New(args) = serde_json::from_str(ARGUMENTS_1_20).unwrap() else {
+			panic!("Arguments should be in the new format");
+		};
+		(args.game, args.jvm)
+	}
+
+	fn state(os: &'static str, arch: &'static str, features: FeatureState) -> RuleState {
+		RuleState { os, arch, features }
+	}
+
+	#[test]
+	fn test_game_arg_features() {
+		let (game, _) = get_args();
+		let base = [
+			"--username",
+			"Steve",
+			"--version",
+			"1.20.1",
+			"--gameDir",
+			"game",
+			"--accessToken",
+			"token",
+		];
+
+		let args = expand(&game, &state("linux", "x86_64", FeatureState::default()));
+		assert_eq!(args, base);
+
+		let features = FeatureState {
+			is_demo_user: true,
+			has_custom_resolution: true,
+			..Default::default()
+		};
+		let args = expand(&game, &state("linux", "x86_64", features));
+		let mut expected = base.to_vec();
+		expected.extend(["--demo", "--width", "854", "--height", "480"]);
+		assert_eq!(args, expected);
+
+		let features = FeatureState {
+			has_quick_play_support: true,
+			is_quick_play_singleplayer: true,
+			..Default::default()
+		};
+		let args = expand(&game, &state("linux", "x86_64", features));
+		let mut expected = base.to_vec();
+		expected.extend([
+			"--quickPlayPath",
+			"quickPlay/log.json",
+			"--quickPlaySingleplayer",
+			"world",
+		]);
+		assert_eq!(args, expected);
+	}
+
+	#[test]
+	fn test_jvm_arg_rules() {
+		let (_, jvm) = get_args();
+		let common = ["-Djava.library.path=natives", "-cp", "cp"];
+
+		let args = expand(&jvm, &state("linux", "x86_64", FeatureState::default()));
+		assert_eq!(args, common);
+
+		let args = expand(&jvm, &state("macos", "aarch64", FeatureState::default()));
+		let mut expected = vec!["-XstartOnFirstThread"];
+		expected.extend(common);
+		assert_eq!(args, expected);
+
+		let args = expand(&jvm, &state("windows", "x86", FeatureState::default()));
+		assert_eq!(args.len(), 5);
+		assert!(args[0].starts_with("-XX:HeapDumpPath"));
+		assert_eq!(args[1], "-Xss1M");
+	}
+
+	#[test]
+	fn test_disallow_rules() {
+		let arg: ArgumentItem = serde_json::from_str(
+			r#"{"rules": [{"action": "allow"}, {"action": "disallow", "os": {"name": "osx"}}], "value": "arg"}"#,
+		)
+		.unwrap();
+		let state = |os| state(os, "x86_64", FeatureState::default());
+		let args = [arg];
+		assert_eq!(expand(&args, &state("linux")), ["arg"]);
+		assert!(expand(&args, &state("macos")).is_empty());
+	}
+
+	#[test]
+	fn test_legacy_arguments() {
+		let args: Arguments =
+			serde_json::from_str(r#""--username ${auth_player_name} --version ${version_name}""#)
+				.unwrap();
+		let Arguments::Old(args) = args else {
+			panic!("Arguments should be in the old format");
+		};
+		let args: Vec<_> = args
+			.split(' ')
+			.map(|x| ArgumentItem::Simple(x.to_string()))
+			.collect();
+		assert_eq!(
+			expand(&args, &state("linux", "x86_64", FeatureState::default())),
+			["--username", "Steve", "--version", "1.20.1"]
+		);
+	}
+
+	/// The logging block from the 1.20.1 version JSON
+	const LOGGING_1_20: &str = r#"{
+		"client": {
+			"argument": "-Dlog4j.configurationFile=${path}",
+			"file": {
+				"id": "client-1.12.xml",
+				"sha1": "bd65e7d2e3c237be76cfbef4c2405033d7f91521",
+				"size": 888,
+				"url": "https://piston-data.mojang.com/v1/objects/bd65e7d2e3c237be76cfbef4c2405033d7f91521/client-1.12.xml"
+			},
+			"type": "log4j2-xml"
+		}
+	}"#;
+
+	#[test]
+	fn test_logging_arg() {
+		let log_info: LogInfo = serde_json::from_str(LOGGING_1_20).unwrap();
+		let log_info = log_info.client.unwrap();
+		assert_eq!(
+			log_info.file.sha1.as_deref(),
+			Some("bd65e7d2e3c237be76cfbef4c2405033d7f91521")
+		);
+
+		let paths = Paths::new_no_create().unwrap();
+		let path = crate::net::game_files::log_config::get_path(&log_info, &paths);
+		assert!(path.ends_with("log_configs/client-1.12.xml"));
+		let arg = fill_logging_path_arg(&log_info, &paths).unwrap();
+		assert_eq!(
+			arg,
+			format!("-Dlog4j.configurationFile={}", path.to_str().unwrap())
+		);
+
+		// Versions without a logging section
+		let log_info: LogInfo = serde_json::from_str("{}").unwrap();
+		assert!(log_info.client.is_none());
+	}
 }
@@ -4,13 +4,16 @@ mod args;
 use anyhow::{anyhow, bail, Context};
 
 use std::collections::HashMap;
+use std::path::Path;
 
+use mcvm_options::client::write_options_txt;
 #[cfg(target_os = "linux")]
 use mcvm_shared::versions::VersionPattern;
 use mcvm_shared::{output::MCVMOutput, skip_none};
 
 pub use args::create_quick_play_args;
 
+use crate::instance::{ClientWindowConfig, InstanceKind, WindowResolution};
 use crate::net::game_files::client_meta::args::Arguments;
 
 use super::{process::LaunchProcessProperties, LaunchParameters};
@@ -37,10 +40,12 @@ pub(crate) async fn get_launch_props(
 	let mut game_args = Vec::new();
 
 	if params.launch_config.use_log4j_config {
-		let logging_arg = params.client_meta.logging.client.argument.clone();
-		let logging_arg = args::fill_logging_path_arg(logging_arg, params.version, params.paths)
-			.ok_or(anyhow!("Failed to convert logging path to a string"))?;
-		jvm_args.push(logging_arg);
+		// Versions without a logging configuration just use the default one
+		if let Some(log_info) = &params.client_meta.logging.client {
+			let logging_arg = args::fill_logging_path_arg(log_info, params.paths)
+				.ok_or(anyhow!("Failed to convert logging path to a string"))?;
+			jvm_args.push(logging_arg);
+		}
 	}
 
 	match &params.client_meta.arguments {
@@ -78,6 +83,21 @@ pub(crate) async fn get_launch_props(
 		}
 	}
 
+	let InstanceKind::Client { window } = &params.side else {
+		panic!("Instance is not a client")
+	};
+	let is_legacy = matches!(params.client_meta.arguments, Arguments::Old(..));
+	game_args.extend(get_window_args(window, is_legacy));
+	// Newer versions add this with an argument rule instead
+	let is_demo = params.users.get_chosen_user().is_some_and(|x| x.is_demo());
+	if is_legacy && is_demo {
+		game_args.push("--demo".into());
+	}
+	if let Some(fullscreen) = window.fullscreen {
+		write_fullscreen_option(&params.launch_dir.join("options.txt"), fullscreen)
+			.context("Failed to write fullscreen setting to options.txt")?;
+	}
+
 	let env_vars =
 		get_additional_environment_variables(params.version, &params.version_manifest.list);
 
@@ -89,6 +109,34 @@ pub(crate) async fn get_launch_props(
 	Ok(props)
 }
 
+/// Get the game arguments that apply the window configuration. The arguments
+/// in newer versions already contain the resolution, but the legacy ones don't
+fn get_window_args(window: &ClientWindowConfig, is_legacy: bool) -> Vec<String> {
+	let mut out = Vec::new();
+	if is_legacy {
+		if let Some(WindowResolution { width, height }) = window.resolution {
+			out.extend([
+				"--width".into(),
+				width.to_string(),
+				"--height".into(),
+				height.to_string(),
+			]);
+		}
+	}
+	if window.fullscreen == Some(true) {
+		out.push("--fullscreen".into());
+	}
+
+	out
+}
+
+/// Set the fullscreen key in options.txt, keeping the rest of the file the same.
+/// Creates the file if it doesn't exist
+fn write_fullscreen_option(path: &Path, fullscreen: bool) -> anyhow::Result<()> {
+	let keys = HashMap::from([("fullscreen".to_string(), fullscreen.to_string())]);
+	write_options_txt(keys, path, &None)
+}
+
 /// Get additional environment variables for the client
 fn get_additional_environment_variables(
 	version: &str,
@@ -115,3 +163,66 @@ fn get_additional_environment_variables(
 
 	env_vars
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_window_args() {
+		let window = ClientWindowConfig {
+			resolution: Some(WindowResolution::new(1280, 720)),
+			fullscreen: Some(true),
+		};
+		assert_eq!(
+			get_window_args(&window, true),
+			["--width", "1280", "--height", "720", "--fullscreen"]
+		);
+		// Modern versions get the resolution from the argument rules
+		assert_eq!(get_window_args(&window, false), ["--fullscreen"]);
+
+		let window = ClientWindowConfig {
+			resolution: None,
+			fullscreen: Some(false),
+		};
+		assert!(get_window_args(&window, true).is_empty());
+		assert!(get_window_args(&window, false).is_empty());
+	}
+
+	#[test]
+	fn test_write_fullscreen_option() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("options.txt");
+
+		// 1.12.2
+		std::fs::write(
+			&path,
+			"version:1343\nfullscreen:false\nkey_key.attack:-100\n",
+		)
+		.unwrap();
+		write_fullscreen_option(&path, true).unwrap();
+		assert_eq!(
+			std::fs::read_to_string(&path).unwrap(),
+			"fullscreen:true\nkey_key.attack:-100\nversion:1343\n"
+		);
+
+		// 1.20.1
+		std::fs::write(
+			&path,
+			"version:3465\nkey_key.attack:key.mouse.left\nfullscreen:true\n",
+		)
+		.unwrap();
+		write_fullscreen_option(&path, false).unwrap();
+		assert_eq!(
+			std::fs::read_to_string(&path).unwrap(),
+			"fullscreen:false\nkey_key.attack:key.mouse.left\nversion:3465\n"
+		);
+
+		std::fs::remove_file(&path).unwrap();
+		write_fullscreen_option(&path, false).unwrap();
+		assert_eq!(
+			std::fs::read_to_string(&path).unwrap(),
+			"fullscreen:false\n"
+		);
+	}
+}
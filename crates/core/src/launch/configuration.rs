@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use crate::io::java::args::MemoryNum;
 use crate::io::java::install::JavaInstallationKind;
@@ -18,12 +19,18 @@ pub struct LaunchConfiguration {
 	pub max_mem: Option<MemoryNum>,
 	/// Environment variables
 	pub env: HashMap<String, String>,
+	/// The directory to run the process in instead of the game directory
+	pub working_dir: Option<PathBuf>,
 	/// Wrapper command
 	pub wrappers: Vec<WrapperCommand>,
 	/// Quick Play options
 	pub quick_play: QuickPlayType,
 	/// Whether or not to use the Log4J configuration
 	pub use_log4j_config: bool,
+	/// Whether to pipe the standard input of the process instead of inheriting it
+	pub pipe_stdin: bool,
+	/// Whether to pipe the standard output and error of the process instead of inheriting them
+	pub pipe_output: bool,
 }
 
 impl LaunchConfiguration {
@@ -36,9 +43,12 @@ impl LaunchConfiguration {
 			min_mem: None,
 			max_mem: None,
 			env: HashMap::new(),
+			working_dir: None,
 			wrappers: Vec::new(),
 			quick_play: QuickPlayType::None,
 			use_log4j_config: false,
+			pipe_stdin: false,
+			pipe_output: false,
 		}
 	}
 
@@ -108,6 +118,12 @@ impl LaunchConfigBuilder {
 		self
 	}
 
+	/// Set the directory to run the process in instead of the game directory
+	pub fn working_dir(mut self, working_dir: PathBuf) -> Self {
+		self.config.working_dir = Some(working_dir);
+		self
+	}
+
 	/// Add a wrapper command that encloses the normal command
 	pub fn wrapper(mut self, wrapper: WrapperCommand) -> Self {
 		self.config.wrappers.push(wrapper);
@@ -125,6 +141,18 @@ impl LaunchConfigBuilder {
 		self.config.use_log4j_config = use_log4j_config;
 		self
 	}
+
+	/// Set whether to pipe the standard input of the process
+	pub fn pipe_stdin(mut self, pipe_stdin: bool) -> Self {
+		self.config.pipe_stdin = pipe_stdin;
+		self
+	}
+
+	/// Set whether to pipe the standard output and error of the process
+	pub fn pipe_output(mut self, pipe_output: bool) -> Self {
+		self.config.pipe_output = pipe_output;
+		self
+	}
 }
 
 impl Default for LaunchConfigBuilder {
@@ -12,9 +12,13 @@ use std::path::Path;
 use anyhow::Context;
 use mcvm_shared::output::MCVMOutput;
 use mcvm_shared::Side;
+#[cfg(target_family = "unix")]
+use nix::sys::signal::{kill, killpg, Signal};
+#[cfg(target_family = "unix")]
+use nix::unistd::Pid;
 
 use self::client::create_quick_play_args;
-use self::process::{launch_game_process, LaunchGameProcessParameters};
+use self::process::{get_game_launch_command, launch_game_process, LaunchGameProcessParameters};
 use crate::config::BrandingProperties;
 use crate::instance::InstanceKind;
 use crate::io::files::paths::Paths;
@@ -30,18 +34,34 @@ pub use self::configuration::{
 	LaunchConfigBuilder, LaunchConfiguration, QuickPlayType, WrapperCommand,
 };
 
-pub use self::process::launch_process;
-pub use self::process::{LaunchProcessParameters, LaunchProcessProperties};
+pub use self::process::{get_launch_command, launch_process};
+pub use self::process::{
+	LaunchCommand, LaunchProcessParameters, LaunchProcessProperties, ScriptKind, ACCESS_TOKEN_VAR,
+};
 
 pub(crate) async fn launch(
 	mut params: LaunchParameters<'_>,
 	o: &mut impl MCVMOutput,
 ) -> anyhow::Result<InstanceHandle> {
+	let command = get_instance_launch_command(&mut params, o).await?;
+	let child = launch_game_process(&command, params.censor_secrets, o)
+		.context("Failed to launch game process")?;
+
+	let mut handle = InstanceHandle::new(child);
+	handle.process_group = command.process_group;
+	Ok(handle)
+}
+
+/// Get the command that launching an instance runs
+pub(crate) async fn get_instance_launch_command(
+	params: &mut LaunchParameters<'_>,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<LaunchCommand> {
 	let command = params.java.get_jvm_path();
 	// Get side-specific launch properties
 	let props = match params.side.get_side() {
-		Side::Client => self::client::get_launch_props(&mut params, o).await,
-		Side::Server => self::server::get_launch_props(&params),
+		Side::Client => self::client::get_launch_props(params, o).await,
+		Side::Server => self::server::get_launch_props(params),
 	}
 	.context("Failed to generate side-specific launch properties")?;
 
@@ -52,7 +72,11 @@ pub(crate) async fn launch(
 
 	let proc_params = LaunchGameProcessParameters {
 		command: command.as_os_str(),
-		cwd: params.launch_dir,
+		cwd: params
+			.launch_config
+			.working_dir
+			.as_deref()
+			.unwrap_or(params.launch_dir),
 		main_class: Some(params.main_class),
 		props,
 		launch_config: params.launch_config,
@@ -60,13 +84,9 @@ pub(crate) async fn launch(
 		version_list: &params.version_manifest.list,
 		side: params.side,
 		user_access_token,
-		censor_secrets: params.censor_secrets,
 	};
 
-	let child = launch_game_process(proc_params, o).context("Failed to launch game process")?;
-
-	let handle = InstanceHandle::new(child);
-	Ok(handle)
+	Ok(get_game_launch_command(proc_params, o))
 }
 
 /// Container struct for parameters for launching an instance
@@ -75,6 +95,7 @@ pub(crate) struct LaunchParameters<'a> {
 	pub version_manifest: &'a VersionManifestAndList,
 	pub side: &'a InstanceKind,
 	pub launch_dir: &'a Path,
+	pub game_assets_dir: &'a Path,
 	pub java: &'a JavaInstallation,
 	pub classpath: &'a Classpath,
 	pub main_class: &'a str,
@@ -146,12 +167,17 @@ impl LaunchConfiguration {
 pub struct InstanceHandle {
 	/// The child process for the launched instance
 	process: std::process::Child,
+	/// Whether the process leads its own process group, which is signalled instead of just the process
+	process_group: bool,
 }
 
 impl InstanceHandle {
 	/// Construct a new InstanceHandle
 	fn new(process: std::process::Child) -> Self {
-		Self { process }
+		Self {
+			process,
+			process_group: false,
+		}
 	}
 
 	/// Waits for the process to complete
@@ -161,12 +187,195 @@ impl InstanceHandle {
 
 	/// Kills the process early
 	pub fn kill(&mut self) -> std::io::Result<()> {
+		// Kill the whole group first so that the game doesn't outlive its wrappers
+		#[cfg(target_family = "unix")]
+		if self.process_group {
+			let _ = send_signal(self.get_pid(), Signal::SIGKILL, true);
+		}
 		self.process.kill()
 	}
 
+	/// Asks the process to exit gracefully. On Unix this sends SIGTERM, and on Windows
+	/// this asks the process tree to close using taskkill. Returns an error if the request
+	/// could not be delivered, such as for Windows console processes that can only be killed
+	pub fn terminate(&mut self) -> std::io::Result<()> {
+		#[cfg(target_family = "unix")]
+		return send_signal(self.get_pid(), Signal::SIGTERM, self.process_group);
+
+		// Without /F, taskkill sends a close request instead of terminating the process
+		#[cfg(target_os = "windows")]
+		{
+			let status = std::process::Command::new("taskkill")
+				.arg("/PID")
+				.arg(self.get_pid().to_string())
+				.arg("/T")
+				.stdout(std::process::Stdio::null())
+				.stderr(std::process::Stdio::null())
+				.status()?;
+			if status.success() {
+				Ok(())
+			} else {
+				Err(std::io::Error::other(format!(
+					"Terminating the process failed with {status}"
+				)))
+			}
+		}
+	}
+
+	/// Gets something that can forward interrupts to the process while it is being waited on
+	pub fn get_interrupter(&self) -> ProcessInterrupter {
+		ProcessInterrupter {
+			pid: self.get_pid(),
+			process_group: self.process_group,
+		}
+	}
+
+	/// Checks if the process has exited without blocking
+	pub fn try_wait(&mut self) -> std::io::Result<Option<std::process::ExitStatus>> {
+		self.process.try_wait()
+	}
+
+	/// Gets the PID of the process
+	pub fn get_pid(&self) -> u32 {
+		self.process.id()
+	}
+
+	/// Takes the piped standard input of the process, if the process was launched with it piped
+	pub fn take_stdin(&mut self) -> Option<std::process::ChildStdin> {
+		self.process.stdin.take()
+	}
+
+	/// Takes the piped standard output and error of the process,
+	/// if the process was launched with them piped
+	pub fn take_output(
+		&mut self,
+	) -> (
+		Option<std::process::ChildStdout>,
+		Option<std::process::ChildStderr>,
+	) {
+		(self.process.stdout.take(), self.process.stderr.take())
+	}
+
 	/// Gets the internal child process for the game, consuming the
 	/// InstanceHandle
 	pub fn get_process(self) -> std::process::Child {
 		self.process
 	}
 }
+
+/// Forwards interrupts like Ctrl+C to a launched process. A process in its own process group
+/// is not in the foreground group of the terminal, so it doesn't get the interrupt by itself
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessInterrupter {
+	#[cfg_attr(target_os = "windows", allow(dead_code))]
+	pid: u32,
+	process_group: bool,
+}
+
+impl ProcessInterrupter {
+	/// Interrupt the process. This does nothing if the process shares our process group,
+	/// since it already got the same interrupt that we did
+	pub fn interrupt(&self) -> std::io::Result<()> {
+		if !self.process_group {
+			return Ok(());
+		}
+
+		#[cfg(target_family = "unix")]
+		return send_signal(self.pid, Signal::SIGINT, true);
+
+		// Processes aren't put in their own group on Windows, so they share our
+		// console and already got the Ctrl+C
+		#[cfg(target_os = "windows")]
+		return Ok(());
+	}
+}
+
+/// Send a signal to a process, or to the process group that it leads
+#[cfg(target_family = "unix")]
+fn send_signal(pid: u32, signal: Signal, process_group: bool) -> std::io::Result<()> {
+	let pid = Pid::from_raw(pid as i32);
+	if process_group {
+		killpg(pid, signal)?;
+	} else {
+		kill(pid, signal)?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[cfg(target_family = "unix")]
+	fn test_terminate() {
+		let process = std::process::Command::new("sleep")
+			.arg("30")
+			.spawn()
+			.unwrap();
+		let mut handle = InstanceHandle::new(process);
+		handle.terminate().unwrap();
+		let status = handle.wait().unwrap();
+		assert!(!status.success());
+	}
+
+	#[test]
+	#[cfg(target_family = "unix")]
+	fn test_forward_interrupt() {
+		use std::os::unix::process::CommandExt;
+
+		let process = std::process::Command::new("sh")
+			.arg("-c")
+			.arg("trap 'exit 3' INT; echo ready; sleep 30 & wait")
+			.stdout(std::process::Stdio::piped())
+			.process_group(0)
+			.spawn()
+			.unwrap();
+		let mut handle = InstanceHandle::new(process);
+		handle.process_group = true;
+		let mut stdout = std::io::BufReader::new(handle.take_output().0.unwrap());
+		let mut line = String::new();
+		std::io::BufRead::read_line(&mut stdout, &mut line).unwrap();
+
+		handle.get_interrupter().interrupt().unwrap();
+		assert_eq!(handle.wait().unwrap().code(), Some(3));
+	}
+
+	#[test]
+	#[cfg(target_os = "linux")]
+	fn test_kill_process_group() {
+		use std::os::unix::process::CommandExt;
+
+		// The shell stands in for a wrapper that starts the real process as a child
+		let process = std::process::Command::new("sh")
+			.arg("-c")
+			.arg("sleep 30 & echo $!; wait")
+			.stdout(std::process::Stdio::piped())
+			.process_group(0)
+			.spawn()
+			.unwrap();
+		let mut handle = InstanceHandle::new(process);
+		handle.process_group = true;
+		let mut stdout = std::io::BufReader::new(handle.take_output().0.unwrap());
+		let mut child_pid = String::new();
+		std::io::BufRead::read_line(&mut stdout, &mut child_pid).unwrap();
+		let child_pid = child_pid.trim();
+
+		handle.terminate().unwrap();
+		handle.wait().unwrap();
+		// Give the signal time to reach the grandchild. It may be left as a zombie if nothing reaps it
+		let is_dead = || {
+			std::fs::read_to_string(format!("/proc/{child_pid}/stat")).map_or(true, |stat| {
+				stat.rsplit(") ").next().is_some_and(|x| x.starts_with('Z'))
+			})
+		};
+		for _ in 0..50 {
+			if is_dead() {
+				return;
+			}
+			std::thread::sleep(std::time::Duration::from_millis(20));
+		}
+		panic!("Child process {child_pid} is still running");
+	}
+}
@@ -1,10 +1,11 @@
 use std::collections::HashMap;
-use std::ffi::OsStr;
-use std::path::Path;
-use std::process::{Child, Command};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
 
 use anyhow::Context;
 use mcvm_auth::mc::AccessToken;
+use mcvm_options::diff::is_secret_key;
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
 use mcvm_shared::translate;
 
@@ -14,11 +15,11 @@ use crate::WrapperCommand;
 
 use super::LaunchConfiguration;
 
-/// Launch the game process
-pub(crate) fn launch_game_process(
+/// Get the command for launching the game process
+pub(crate) fn get_game_launch_command(
 	mut params: LaunchGameProcessParameters<'_>,
 	o: &mut impl MCVMOutput,
-) -> anyhow::Result<std::process::Child> {
+) -> LaunchCommand {
 	// Modify the parameters based on game-specific properties
 
 	// Prepend generated game args to the beginning
@@ -40,68 +41,304 @@ pub(crate) fn launch_game_process(
 		launch_config: params.launch_config,
 	};
 
+	let mut command = get_launch_command(proc_params);
+	command.access_token = params.user_access_token.cloned();
+
+	command
+}
+
+/// Launch the game process from its command
+pub(crate) fn launch_game_process(
+	command: &LaunchCommand,
+	censor_secrets: bool,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<std::process::Child> {
 	o.display(
 		MessageContents::Success(translate!(o, Launch)),
 		MessageLevel::Important,
 	);
 
-	// Get the command and output it
-	let mut cmd = get_process_launch_command(proc_params)
-		.context("Failed to create process launch command")?;
-
-	output_launch_command(&cmd, params.user_access_token, params.censor_secrets, o)?;
+	output_launch_command(command, censor_secrets, o)?;
 
 	// Spawn
-	let child = cmd.spawn().context("Failed to spawn child process")?;
+	let child = command
+		.to_command()
+		.spawn()
+		.context("Failed to spawn child process")?;
 
 	Ok(child)
 }
 
 /// Launch a generic process with the core's config system
 pub fn launch_process(params: LaunchProcessParameters<'_>) -> anyhow::Result<Child> {
-	let mut cmd =
-		get_process_launch_command(params).context("Failed to create process launch command")?;
-
-	cmd.spawn().context("Failed to spawn child process")
+	get_launch_command(params)
+		.to_command()
+		.spawn()
+		.context("Failed to spawn child process")
 }
 
 /// Get the command for launching a generic process using the core's config system
-pub fn get_process_launch_command(params: LaunchProcessParameters<'_>) -> anyhow::Result<Command> {
-	// Create the base command based on wrapper settings
-	let mut cmd = create_wrapped_command(params.command, &params.launch_config.wrappers);
-
-	// Fill out the command properties
-	cmd.current_dir(params.cwd);
-	cmd.envs(params.launch_config.env.clone());
-	cmd.envs(params.props.additional_env_vars);
-
-	// Add the arguments
-	cmd.args(params.launch_config.generate_jvm_args());
-	cmd.args(params.props.jvm_args);
+pub fn get_launch_command(params: LaunchProcessParameters<'_>) -> LaunchCommand {
+	let mut env = params.launch_config.env.clone();
+	env.extend(params.props.additional_env_vars);
+
+	let mut args: Vec<OsString> = Vec::new();
+	args.extend(
+		params
+			.launch_config
+			.generate_jvm_args()
+			.into_iter()
+			.map(OsString::from),
+	);
+	args.extend(params.props.jvm_args.into_iter().map(OsString::from));
 	if let Some(main_class) = params.main_class {
-		cmd.arg(main_class);
+		args.push(main_class.into());
 	}
-	cmd.args(params.props.game_args);
+	args.extend(params.props.game_args.into_iter().map(OsString::from));
+
+	let mut command = LaunchCommand {
+		program: params.command.to_owned(),
+		args,
+		env,
+		cwd: params.cwd.to_owned(),
+		// Wrappers like gamemoderun start the JVM as a child process, so the whole tree is put
+		// in its own process group that can be signalled together
+		process_group: !params.launch_config.wrappers.is_empty(),
+		pipe_stdin: params.launch_config.pipe_stdin,
+		pipe_output: params.launch_config.pipe_output,
+		access_token: None,
+	};
+	for wrapper in &params.launch_config.wrappers {
+		command.wrap(wrapper);
+	}
+
+	command
+}
+
+/// A complete command for launching a process, which can either be run or written out as a script
+#[derive(Debug, Clone)]
+pub struct LaunchCommand {
+	/// The program to run, which is the outermost wrapper if there are any
+	pub program: OsString,
+	/// The arguments to the program
+	pub args: Vec<OsString>,
+	/// Environment variables to set in addition to the ones that are inherited
+	pub env: HashMap<String, String>,
+	/// The directory to run the process in
+	pub cwd: PathBuf,
+	/// Whether to run the process in its own process group. Only used on Unix
+	pub process_group: bool,
+	/// Whether to pipe the stdin of the process
+	pub pipe_stdin: bool,
+	/// Whether to pipe the stdout and stderr of the process
+	pub pipe_output: bool,
+	/// The access token of the user, which is censored when the command is shown
+	/// and left out of scripts
+	pub access_token: Option<AccessToken>,
+}
 
-	Ok(cmd)
+impl LaunchCommand {
+	/// Wrap this command in a wrapper command, which becomes the new outermost command
+	pub fn wrap(&mut self, wrapper: &WrapperCommand) {
+		let mut args: Vec<OsString> = wrapper.args.iter().map(OsString::from).collect();
+		args.push(std::mem::replace(
+			&mut self.program,
+			wrapper.cmd.clone().into(),
+		));
+		args.append(&mut self.args);
+		self.args = args;
+	}
+
+	/// Create the process command
+	pub fn to_command(&self) -> Command {
+		let mut cmd = Command::new(&self.program);
+		#[cfg(target_family = "unix")]
+		if self.process_group {
+			use std::os::unix::process::CommandExt;
+			cmd.process_group(0);
+		}
+
+		cmd.current_dir(&self.cwd);
+		cmd.envs(&self.env);
+		cmd.args(&self.args);
+
+		if self.pipe_stdin {
+			cmd.stdin(Stdio::piped());
+		}
+		if self.pipe_output {
+			cmd.stdout(Stdio::piped());
+			cmd.stderr(Stdio::piped());
+		}
+
+		cmd
+	}
+
+	/// Write a script that runs this command. The access token and the values of the
+	/// given environment variables are left out, and are read from the environment
+	/// of the script instead
+	pub fn to_script(&self, kind: ScriptKind, secret_env: &[String]) -> String {
+		let token = self.access_token.as_ref().map(|x| x.0.as_str());
+		let mut required = Vec::new();
+		if token.is_some() {
+			required.push(ACCESS_TOKEN_VAR);
+		}
+		let mut env: Vec<_> = self.env.iter().collect();
+		env.sort();
+		let mut set_env = Vec::new();
+		for (var, value) in env {
+			if secret_env.contains(var) {
+				required.push(var.as_str());
+			} else {
+				set_env.push((var, value));
+			}
+		}
+
+		let command_line = std::iter::once(&self.program)
+			.chain(self.args.iter())
+			.map(|x| kind.quote(&x.to_string_lossy(), token))
+			.collect::<Vec<_>>();
+
+		let mut out = String::new();
+		match kind {
+			ScriptKind::Shell => {
+				out.push_str("#!/bin/sh\n");
+				out.push_str(
+					"# Launch script exported by MCVM. Secrets are read from the environment\n",
+				);
+				for var in required {
+					out.push_str(&format!(": \"${{{var}:?{var} must be set}}\"\n"));
+				}
+				out.push_str(&format!(
+					"cd {} || exit 1\n",
+					kind.quote(&self.cwd.to_string_lossy(), None)
+				));
+				for (var, value) in set_env {
+					out.push_str(&format!("export {var}={}\n", kind.quote(value, None)));
+				}
+				out.push_str("exec ");
+				out.push_str(&command_line.join(" \\\n\t"));
+				out.push('\n');
+			}
+			ScriptKind::Batch => {
+				out.push_str("@echo off\r\n");
+				out.push_str(
+					"rem Launch script exported by MCVM. Secrets are read from the environment\r\n",
+				);
+				for var in required {
+					out.push_str(&format!(
+						"if not defined {var} (echo {var} must be set & exit /b 1)\r\n"
+					));
+				}
+				out.push_str(&format!(
+					"cd /d {}\r\n",
+					kind.quote(&self.cwd.to_string_lossy(), None)
+				));
+				for (var, value) in set_env {
+					out.push_str(&format!("set \"{var}={}\"\r\n", value.replace('%', "%%")));
+				}
+				out.push_str(&command_line.join(" ^\r\n\t"));
+				out.push_str("\r\n");
+			}
+		}
+
+		out
+	}
+}
+
+/// The environment variable that exported launch scripts read the access token from
+pub const ACCESS_TOKEN_VAR: &str = "MCVM_ACCESS_TOKEN";
+
+/// The kind of script to export a launch command as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptKind {
+	/// A POSIX shell script
+	Shell,
+	/// A Windows batch file
+	Batch,
+}
+
+impl ScriptKind {
+	/// Get the kind of script that is native to the current platform
+	pub fn native() -> Self {
+		if cfg!(target_os = "windows") {
+			Self::Batch
+		} else {
+			Self::Shell
+		}
+	}
+
+	/// Get the kind of script from the extension of the path it is written to,
+	/// using the native kind if the extension isn't known
+	pub fn from_path(path: &Path) -> Self {
+		match path.extension().and_then(|x| x.to_str()) {
+			Some("bat" | "cmd") => Self::Batch,
+			Some("sh") => Self::Shell,
+			_ => Self::native(),
+		}
+	}
+
+	/// Quote an argument for the script, replacing the access token with a reference to its variable
+	fn quote(&self, arg: &str, token: Option<&str>) -> String {
+		let (quote, token_ref): (fn(&str) -> String, String) = match self {
+			Self::Shell => (
+				|x| format!("'{}'", x.replace('\'', "'\\''")),
+				format!("\"${ACCESS_TOKEN_VAR}\""),
+			),
+			Self::Batch => (
+				|x| format!("\"{}\"", x.replace('"', "\"\"").replace('%', "%%")),
+				format!("\"%{ACCESS_TOKEN_VAR}%\""),
+			),
+		};
+		let Some(token) = token.filter(|x| !x.is_empty() && arg.contains(x)) else {
+			return quote(arg);
+		};
+
+		let parts: Vec<_> = arg
+			.split(token)
+			.map(|x| {
+				if x.is_empty() {
+					String::new()
+				} else {
+					quote(x)
+				}
+			})
+			.collect();
+		parts.join(&token_ref)
+	}
 }
 
 /// Display the launch command in our own way,
 /// censoring any credentials if needed
 fn output_launch_command(
-	command: &Command,
-	access_token: Option<&AccessToken>,
+	command: &LaunchCommand,
 	censor_secrets: bool,
 	o: &mut impl MCVMOutput,
 ) -> anyhow::Result<()> {
 	o.end_process();
-	let access_token = if censor_secrets { access_token } else { None };
+	let access_token = if censor_secrets {
+		command.access_token.as_ref()
+	} else {
+		None
+	};
+	const CENSOR_STR: &str = "***";
+	let censor = |arg: &OsStr| {
+		let arg = arg.to_string_lossy().to_string();
+		match &access_token {
+			Some(access_token) => arg.replace(&access_token.0, CENSOR_STR),
+			None => arg,
+		}
+	};
+
+	// Show the whole command line, including any wrappers
+	let command_line = std::iter::once(&command.program)
+		.chain(command.args.iter())
+		.map(|x| quote_arg(&censor(x)))
+		.collect::<Vec<_>>()
+		.join(" ");
 	o.display(
 		MessageContents::Property(
 			"Launch command".into(),
-			Box::new(MessageContents::Simple(
-				command.get_program().to_string_lossy().into(),
-			)),
+			Box::new(MessageContents::Simple(command_line)),
 		),
 		MessageLevel::Debug,
 	);
@@ -111,12 +348,8 @@ fn output_launch_command(
 		MessageLevel::Debug,
 	);
 
-	const CENSOR_STR: &str = "***";
-	for arg in command.get_args() {
-		let mut arg = arg.to_string_lossy().to_string();
-		if let Some(access_token) = &access_token {
-			arg = arg.replace(&access_token.0, CENSOR_STR);
-		}
+	for arg in &command.args {
+		let arg = censor(arg);
 		o.display(
 			MessageContents::ListItem(Box::new(MessageContents::Simple(arg))),
 			MessageLevel::Debug,
@@ -128,49 +361,41 @@ fn output_launch_command(
 		MessageLevel::Debug,
 	);
 
-	for (env, val) in command.get_envs() {
-		let Some(val) = val else { continue };
-		let env = env.to_string_lossy().to_string();
-		let val = val.to_string_lossy().to_string();
-
+	for (env, val) in &command.env {
+		let val = if censor_secrets && is_secret_key(env) {
+			CENSOR_STR.to_string()
+		} else {
+			censor(OsStr::new(val))
+		};
 		o.display(
 			MessageContents::ListItem(Box::new(MessageContents::Property(
-				env,
+				env.clone(),
 				Box::new(MessageContents::Simple(val)),
 			))),
 			MessageLevel::Debug,
 		);
 	}
 
-	if let Some(dir) = command.get_current_dir() {
-		o.display(
-			MessageContents::Property(
-				"Launch command directory".into(),
-				Box::new(MessageContents::Simple(dir.to_string_lossy().into())),
-			),
-			MessageLevel::Debug,
-		);
-	}
+	o.display(
+		MessageContents::Property(
+			"Launch command directory".into(),
+			Box::new(MessageContents::Simple(
+				command.cwd.to_string_lossy().into(),
+			)),
+		),
+		MessageLevel::Debug,
+	);
 
 	Ok(())
 }
 
-/// Creates a command wrapped in multiple other wrappers
-fn create_wrapped_command(command: &OsStr, wrappers: &[WrapperCommand]) -> Command {
-	let mut cmd = Command::new(command);
-	for wrapper in wrappers {
-		cmd = wrap_single(cmd, wrapper);
+/// Quotes an argument for display if it contains whitespace
+fn quote_arg(arg: &str) -> String {
+	if arg.is_empty() || arg.contains(char::is_whitespace) {
+		format!("\"{}\"", arg.replace('"', "\\\""))
+	} else {
+		arg.to_string()
 	}
-	cmd
-}
-
-/// Wraps a single command in a wrapper
-fn wrap_single(command: Command, wrapper: &WrapperCommand) -> Command {
-	let mut new_cmd = Command::new(&wrapper.cmd);
-	new_cmd.args(&wrapper.args);
-	new_cmd.arg(command.get_program());
-	new_cmd.args(command.get_args());
-	new_cmd
 }
 
 /// Container struct for parameters for launching the game process
@@ -187,7 +412,6 @@ pub(crate) struct LaunchGameProcessParameters<'a> {
 	pub version_list: &'a [String],
 	pub side: &'a InstanceKind,
 	pub user_access_token: Option<&'a AccessToken>,
-	pub censor_secrets: bool,
 }
 
 /// Container struct for parameters for launching a generic Java process
@@ -232,13 +456,105 @@ mod tests {
 				args: vec!["foo".into(), "bar".into()],
 			},
 		];
-		let cmd = create_wrapped_command(OsStr::new("run"), &wrappers);
-		dbg!(&cmd);
-		assert_eq!(cmd.get_program(), OsStr::new("world"));
-		let mut args = cmd.get_args();
-		assert_eq!(args.next(), Some(OsStr::new("foo")));
-		assert_eq!(args.next(), Some(OsStr::new("bar")));
-		assert_eq!(args.next(), Some(OsStr::new("hello")));
-		assert_eq!(args.next(), Some(OsStr::new("run")));
+		let mut cmd = test_command(None);
+		for wrapper in &wrappers {
+			cmd.wrap(wrapper);
+		}
+		assert_eq!(cmd.program, OsStr::new("world"));
+		let mut args = cmd.args.iter();
+		assert_eq!(args.next().unwrap(), OsStr::new("foo"));
+		assert_eq!(args.next().unwrap(), OsStr::new("bar"));
+		assert_eq!(args.next().unwrap(), OsStr::new("hello"));
+		assert_eq!(args.next().unwrap(), OsStr::new("java"));
+		assert_eq!(args.next().unwrap(), OsStr::new("-Xmx2G"));
+	}
+
+	#[test]
+	fn test_shell_script() {
+		let cmd = test_command(Some("secret123"));
+		let script = cmd.to_script(ScriptKind::Shell, &["API_TOKEN".into()]);
+		assert!(!script.contains("secret123"));
+		assert!(!script.contains("hidden"));
+		assert!(script.contains(": \"${MCVM_ACCESS_TOKEN:?MCVM_ACCESS_TOKEN must be set}\""));
+		assert!(script.contains(": \"${API_TOKEN:?API_TOKEN must be set}\""));
+		assert!(script.contains("cd '/games/My World' || exit 1"));
+		assert!(script.contains("export GAME_MODE='it'\\''s on'"));
+		assert!(script.contains("'--accessToken' \\\n\t\"$MCVM_ACCESS_TOKEN\""));
+		assert!(script.contains("'token:'\"$MCVM_ACCESS_TOKEN\""));
+		assert!(script.contains("exec 'java' \\\n\t'-Xmx2G'"));
+	}
+
+	#[test]
+	fn test_batch_script() {
+		let cmd = test_command(Some("secret123"));
+		let script = cmd.to_script(ScriptKind::Batch, &[]);
+		assert!(!script.contains("secret123"));
+		assert!(script.contains("if not defined MCVM_ACCESS_TOKEN"));
+		assert!(script.contains("set \"API_TOKEN=hidden\""));
+		assert!(script.contains("\"--accessToken\" ^\r\n\t\"%MCVM_ACCESS_TOKEN%\""));
+		assert!(script.contains("\"100%%\""));
+		assert_eq!(
+			ScriptKind::from_path(Path::new("run.bat")),
+			ScriptKind::Batch
+		);
+		assert_eq!(
+			ScriptKind::from_path(Path::new("run.sh")),
+			ScriptKind::Shell
+		);
+	}
+
+	fn test_command(access_token: Option<&str>) -> LaunchCommand {
+		let args = [
+			"-Xmx2G",
+			"--accessToken",
+			"secret123",
+			"token:secret123",
+			"100%",
+		];
+		LaunchCommand {
+			program: "java".into(),
+			args: args.into_iter().map(OsString::from).collect(),
+			env: HashMap::from([
+				("GAME_MODE".into(), "it's on".into()),
+				("API_TOKEN".into(), "hidden".into()),
+			]),
+			cwd: PathBuf::from("/games/My World"),
+			process_group: false,
+			pipe_stdin: false,
+			pipe_output: false,
+			access_token: access_token.map(|x| AccessToken(x.into())),
+		}
+	}
+
+	#[test]
+	fn test_censored_output() {
+		struct Lines(Vec<String>);
+
+		impl MCVMOutput for Lines {
+			fn display_text(&mut self, text: String, _level: MessageLevel) {
+				self.0.push(text);
+			}
+		}
+
+		let mut cmd = test_command(Some("secret123"));
+		cmd.env
+			.insert("JAVA_OPTS".into(), "-Dtoken=secret123".into());
+		let mut o = Lines(Vec::new());
+		output_launch_command(&cmd, true, &mut o).unwrap();
+		let output = o.0.join("\n");
+		assert!(!output.contains("secret123"));
+		assert!(!output.contains("hidden"));
+		assert!(output.contains("it's on"));
+
+		let mut o = Lines(Vec::new());
+		output_launch_command(&cmd, false, &mut o).unwrap();
+		assert!(o.0.join("\n").contains("hidden"));
+	}
+
+	#[test]
+	fn test_quote_arg() {
+		assert_eq!(quote_arg("-Xmx2G"), "-Xmx2G");
+		assert_eq!(quote_arg("My World"), "\"My World\"");
+		assert_eq!(quote_arg(""), "\"\"");
 	}
 }
@@ -33,7 +33,9 @@ use io::java::JavaMajorVersion;
 use io::{persistent::PersistentData, update::UpdateManager};
 use mcvm_shared::output::{self, MCVMOutput};
 use mcvm_shared::versions::VersionInfo;
+use net::download::{create_client, TimeoutSettings};
 use net::game_files::version_manifest::{make_version_list, VersionEntry, VersionManifestAndList};
+use net::stats::{self, TransferPhase};
 use user::UserManager;
 use util::versions::MinecraftVersion;
 use version::{
@@ -44,7 +46,7 @@ use version::{
 pub use config::{ConfigBuilder, Configuration};
 pub use instance::{ClientWindowConfig, Instance, InstanceConfiguration, InstanceKind};
 pub use io::files::paths::Paths;
-pub use launch::{InstanceHandle, QuickPlayType, WrapperCommand};
+pub use launch::{InstanceHandle, ProcessInterrupter, QuickPlayType, WrapperCommand};
 
 /// Wrapper around all usage of `mcvm_core`
 pub struct MCVMCore {
@@ -72,11 +74,13 @@ impl MCVMCore {
 	pub fn with_config_and_paths(config: Configuration, paths: Paths) -> anyhow::Result<Self> {
 		let persistent =
 			PersistentData::open(&paths).context("Failed to open persistent data file")?;
+		let mut update_manager = UpdateManager::new(config.force_reinstall, config.allow_offline);
+		update_manager.set_metadata_freshness(config.metadata_freshness);
 		let out = Self {
 			paths,
-			req_client: reqwest::Client::new(),
+			req_client: create_client(None, TimeoutSettings::default())?,
 			persistent,
-			update_manager: UpdateManager::new(config.force_reinstall, config.allow_offline),
+			update_manager,
 			versions: VersionRegistry::new(),
 			users: UserManager::new(config.ms_client_id.clone()),
 			config,
@@ -141,24 +145,41 @@ impl MCVMCore {
 		&mut self,
 		version: &MinecraftVersion,
 		o: &mut impl MCVMOutput,
-	) -> anyhow::Result<InstalledVersion> {
-		self.get_version_manifest(o)
-			.await
-			.context("Failed to ensure version manifest exists")?;
-		let version = version
-			.get_version(&self.versions.get_version_manifest().manifest)
-			.context("Version does not exist")?;
-
+	) -> anyhow::Result<InstalledVersion<'_, '_>> {
 		let params = LoadVersionParameters {
 			paths: &self.paths,
 			req_client: &self.req_client,
 			update_manager: &self.update_manager,
 		};
-		let inner = self
-			.versions
-			.get_version(&version, params, o)
-			.await
-			.context("Failed to get or install version")?;
+		let versions = &mut self.versions;
+		let load = async {
+			// Custom versions aren't resolved from the manifest
+			let version = if let MinecraftVersion::Custom(custom) = version {
+				versions
+					.get_custom_version(custom, params.clone(), o)
+					.await
+					.context("Failed to get custom version")?
+			} else {
+				let vm_params = LoadVersionManifestParameters {
+					paths: params.paths,
+					update_manager: params.update_manager,
+					req_client: params.req_client,
+				};
+				let manifest = versions
+					.load_version_manifest(vm_params, o)
+					.await
+					.context("Failed to ensure version manifest exists")?;
+				version
+					.get_version(&manifest.manifest)
+					.context("Version does not exist")?
+			};
+
+			versions
+				.get_version(&version, params, o)
+				.await
+				.context("Failed to get or install version")
+		};
+		let inner = stats::phase(TransferPhase::Manifest, load).await?;
 
 		let params = VersionParameters {
 			paths: &self.paths,
@@ -202,7 +223,8 @@ impl MCVMCore {
 			persistent: &mut self.persistent,
 			req_client: &self.req_client,
 		};
-		let java = JavaInstallation::install(kind, major_version, java_params, o)
+		let java = JavaInstallation::install(kind, major_version, java_params, o);
+		let java = stats::phase(TransferPhase::Java, java)
 			.await
 			.context("Failed to install or update Java")?;
 
@@ -0,0 +1,337 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::io::files::paths::Paths;
+use crate::io::json_from_file;
+
+use super::assets::{get_objects_dir_path, get_virtual_dir_path, AssetIndex, AssetLayout};
+
+/// The objects in an installed asset index, as a map of hashes to sizes
+#[derive(Debug, Clone)]
+pub struct IndexObjects {
+	/// The ID of the index
+	pub id: String,
+	/// The layout of the assets in the index
+	pub layout: AssetLayout,
+	/// The hashes of the objects in the index mapped to their sizes in bytes
+	pub objects: HashMap<String, u64>,
+}
+
+impl IndexObjects {
+	/// Get the objects in an asset index. Objects listed under more than one name are only counted once
+	pub fn from_index(id: String, index: &AssetIndex) -> Self {
+		let objects = index
+			.objects
+			.values()
+			.map(|x| (x.hash.clone(), x.size as u64))
+			.collect();
+		Self {
+			id,
+			layout: index.get_layout(),
+			objects,
+		}
+	}
+
+	/// Get the total size of the objects in bytes
+	pub fn size(&self) -> u64 {
+		self.objects.values().sum()
+	}
+}
+
+/// How much of the object store a single asset index uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexReport {
+	/// The ID of the index
+	pub id: String,
+	/// The layout of the assets in the index
+	pub layout: AssetLayout,
+	/// The number of unique objects in the index
+	pub objects: usize,
+	/// The total size of the objects in bytes
+	pub size: u64,
+	/// The number of objects that at least one other index uses as well
+	pub shared_objects: usize,
+}
+
+/// How the installed asset indexes share the object store
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssetReport {
+	/// The reports for each index, sorted by ID
+	pub indexes: Vec<IndexReport>,
+	/// The number of objects used by any index
+	pub unique_objects: usize,
+	/// The total size of the objects used by any index in bytes
+	pub unique_size: u64,
+	/// The number of objects used by more than one index
+	pub shared_objects: usize,
+	/// The size that would be stored again if indexes didn't share objects, in bytes
+	pub saved_size: u64,
+	/// Objects in the object store that no index uses, sorted by path
+	pub orphans: Vec<PathBuf>,
+	/// The total size of the orphaned objects in bytes
+	pub orphaned_size: u64,
+}
+
+impl AssetReport {
+	/// Create a report from the objects of the installed indexes, and the hashes and sizes
+	/// of the objects that are actually in the object store
+	pub fn new(indexes: &[IndexObjects], stored: &HashMap<String, (PathBuf, u64)>) -> Self {
+		let mut out = Self::default();
+
+		// How many indexes use each object
+		let mut uses: HashMap<&str, (u64, usize)> = HashMap::new();
+		for index in indexes {
+			for (hash, size) in &index.objects {
+				uses.entry(hash).or_insert((*size, 0)).1 += 1;
+			}
+		}
+
+		for (size, count) in uses.values() {
+			out.unique_objects += 1;
+			out.unique_size += size;
+			if *count > 1 {
+				out.shared_objects += 1;
+				out.saved_size += size * (*count as u64 - 1);
+			}
+		}
+
+		out.indexes = indexes
+			.iter()
+			.map(|index| IndexReport {
+				id: index.id.clone(),
+				layout: index.layout,
+				objects: index.objects.len(),
+				size: index.size(),
+				shared_objects: index
+					.objects
+					.keys()
+					.filter(|x| uses.get(x.as_str()).is_some_and(|x| x.1 > 1))
+					.count(),
+			})
+			.collect();
+		out.indexes.sort_by(|a, b| a.id.cmp(&b.id));
+
+		for (hash, (path, size)) in stored {
+			if !uses.contains_key(hash.as_str()) {
+				out.orphans.push(path.clone());
+				out.orphaned_size += size;
+			}
+		}
+		out.orphans.sort();
+
+		out
+	}
+}
+
+/// Read the objects of every asset index under the assets directory. Indexes are read
+/// in parallel since the newer ones are quite large
+pub fn read_installed_indexes(paths: &Paths) -> anyhow::Result<Vec<IndexObjects>> {
+	let indexes_dir = paths.assets.join("indexes");
+	if !indexes_dir.exists() {
+		return Ok(Vec::new());
+	}
+
+	let mut index_paths = Vec::new();
+	for entry in std::fs::read_dir(&indexes_dir).context("Failed to read asset indexes")? {
+		let path = entry?.path();
+		if path.extension().is_some_and(|x| x == "json") {
+			index_paths.push(path);
+		}
+	}
+
+	std::thread::scope(|scope| {
+		let handles: Vec<_> = index_paths
+			.iter()
+			.map(|path| scope.spawn(move || read_index_objects(path)))
+			.collect();
+		handles
+			.into_iter()
+			.map(|x| x.join().expect("Asset index thread panicked"))
+			.collect()
+	})
+}
+
+/// Read the objects of a single asset index file
+fn read_index_objects(path: &Path) -> anyhow::Result<IndexObjects> {
+	let id = path
+		.file_stem()
+		.map(|x| x.to_string_lossy().to_string())
+		.unwrap_or_default();
+	let index: AssetIndex =
+		json_from_file(path).with_context(|| format!("Failed to read asset index '{id}'"))?;
+
+	Ok(IndexObjects::from_index(id, &index))
+}
+
+/// Get the hashes of the objects that are in the object store, along with their paths and sizes
+pub fn read_stored_objects(objects_dir: &Path) -> anyhow::Result<HashMap<String, (PathBuf, u64)>> {
+	let mut out = HashMap::new();
+	if !objects_dir.exists() {
+		return Ok(out);
+	}
+
+	for prefix in std::fs::read_dir(objects_dir).context("Failed to read asset objects")? {
+		let prefix = prefix?;
+		if !prefix.file_type()?.is_dir() {
+			continue;
+		}
+		for entry in std::fs::read_dir(prefix.path())? {
+			let entry = entry?;
+			let meta = entry.metadata()?;
+			if !meta.is_file() {
+				continue;
+			}
+			let hash = entry.file_name().to_string_lossy().to_string();
+			out.insert(hash, (entry.path(), meta.len()));
+		}
+	}
+
+	Ok(out)
+}
+
+/// Create a report on how the installed asset indexes share the object store
+pub fn get_report(paths: &Paths) -> anyhow::Result<AssetReport> {
+	let indexes = read_installed_indexes(paths)?;
+	let stored = read_stored_objects(&get_objects_dir_path(paths))?;
+	Ok(AssetReport::new(&indexes, &stored))
+}
+
+/// Get the virtual asset directories of indexes that aren't installed anymore
+pub fn get_orphaned_virtual_dirs(
+	indexes: &[IndexObjects],
+	paths: &Paths,
+) -> anyhow::Result<Vec<PathBuf>> {
+	let virtual_dir = paths.assets.join("virtual");
+	if !virtual_dir.exists() {
+		return Ok(Vec::new());
+	}
+
+	let installed: HashSet<_> = indexes
+		.iter()
+		.filter(|x| x.layout == AssetLayout::Virtual)
+		.map(|x| get_virtual_dir_path(&x.id, paths))
+		.collect();
+	let mut out = Vec::new();
+	for entry in std::fs::read_dir(&virtual_dir).context("Failed to read virtual assets")? {
+		let path = entry?.path();
+		if path.is_dir() && !installed.contains(&path) {
+			out.push(path);
+		}
+	}
+	out.sort();
+
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const INDEX_A: &str = r#"{
+		"objects": {
+			"icons/icon_16x16.png": {"hash": "aaaa", "size": 100},
+			"minecraft/sounds/a.ogg": {"hash": "bbbb", "size": 1000},
+			"minecraft/sounds/copy_of_a.ogg": {"hash": "bbbb", "size": 1000}
+		}
+	}"#;
+
+	const INDEX_B: &str = r#"{
+		"objects": {
+			"icons/icon_16x16.png": {"hash": "aaaa", "size": 100},
+			"minecraft/sounds/b.ogg": {"hash": "cccc", "size": 2000}
+		}
+	}"#;
+
+	const INDEX_LEGACY: &str = r#"{
+		"virtual": true,
+		"objects": {
+			"sounds/a.ogg": {"hash": "bbbb", "size": 1000},
+			"lang/en_US.lang": {"hash": "dddd", "size": 10}
+		}
+	}"#;
+
+	fn get_indexes() -> Vec<IndexObjects> {
+		[("b", INDEX_B), ("legacy", INDEX_LEGACY), ("a", INDEX_A)]
+			.into_iter()
+			.map(|(id, json)| {
+				let index: AssetIndex = serde_json::from_str(json).unwrap();
+				IndexObjects::from_index(id.into(), &index)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_index_objects() {
+		let index: AssetIndex = serde_json::from_str(INDEX_A).unwrap();
+		let objects = IndexObjects::from_index("a".into(), &index);
+		assert_eq!(objects.objects.len(), 2);
+		assert_eq!(objects.size(), 1100);
+	}
+
+	#[test]
+	fn test_asset_report() {
+		let stored = HashMap::from([
+			("aaaa".to_string(), (PathBuf::from("aa/aaaa"), 100)),
+			("eeee".to_string(), (PathBuf::from("ee/eeee"), 50)),
+			("ffff".to_string(), (PathBuf::from("ff/ffff"), 5)),
+		]);
+		let report = AssetReport::new(&get_indexes(), &stored);
+
+		assert_eq!(report.unique_objects, 4);
+		assert_eq!(report.unique_size, 3110);
+		assert_eq!(report.shared_objects, 2);
+		assert_eq!(report.saved_size, 1100);
+		assert_eq!(
+			report.orphans,
+			vec![PathBuf::from("ee/eeee"), PathBuf::from("ff/ffff")]
+		);
+		assert_eq!(report.orphaned_size, 55);
+
+		let ids: Vec<_> = report.indexes.iter().map(|x| x.id.as_str()).collect();
+		assert_eq!(ids, vec!["a", "b", "legacy"]);
+		assert_eq!(report.indexes[0].shared_objects, 2);
+		assert_eq!(report.indexes[1].shared_objects, 1);
+		assert_eq!(report.indexes[2].layout, AssetLayout::Virtual);
+		assert_eq!(report.indexes[2].size, 1010);
+	}
+
+	#[test]
+	fn test_empty_report() {
+		let report = AssetReport::new(&[], &HashMap::new());
+		assert_eq!(report, AssetReport::default());
+	}
+
+	#[test]
+	fn test_read_installed_indexes() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let paths = Paths {
+			assets: dir.to_path_buf(),
+			..Paths::new_no_create().unwrap()
+		};
+		let indexes_dir = dir.join("indexes");
+		std::fs::create_dir_all(&indexes_dir).unwrap();
+		std::fs::write(indexes_dir.join("a.json"), INDEX_A).unwrap();
+		std::fs::write(indexes_dir.join("legacy.json"), INDEX_LEGACY).unwrap();
+		std::fs::create_dir_all(dir.join("objects/aa")).unwrap();
+		std::fs::write(dir.join("objects/aa/aaaa"), [0; 100]).unwrap();
+		std::fs::write(dir.join("objects/aa/aa00"), [0; 20]).unwrap();
+		std::fs::create_dir_all(dir.join("virtual/legacy")).unwrap();
+		std::fs::create_dir_all(dir.join("virtual/removed")).unwrap();
+
+		let mut indexes = read_installed_indexes(&paths).unwrap();
+		indexes.sort_by(|a, b| a.id.cmp(&b.id));
+		assert_eq!(indexes.len(), 2);
+		assert_eq!(indexes[1].layout, AssetLayout::Virtual);
+
+		let report = get_report(&paths).unwrap();
+		assert_eq!(report.orphans, vec![dir.join("objects/aa/aa00")]);
+		assert_eq!(report.orphaned_size, 20);
+		assert_eq!(
+			get_orphaned_virtual_dirs(&indexes, &paths).unwrap(),
+			vec![dir.join("virtual/removed")]
+		);
+	}
+}
@@ -5,7 +5,6 @@ use std::sync::Arc;
 use anyhow::Context;
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
 use mcvm_shared::translate;
-use mcvm_shared::versions::VersionPattern;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tokio::{sync::Semaphore, task::JoinSet};
@@ -14,7 +13,7 @@ use crate::io::files::{self, paths::Paths};
 use crate::io::update::{UpdateManager, UpdateMethodResult};
 use crate::io::{json_from_file, json_to_file};
 use crate::net::download::{self, get_transfer_limit};
-use crate::util::versions::VersionName;
+use crate::net::stats;
 
 use super::client_meta::ClientMeta;
 
@@ -23,6 +22,37 @@ use super::client_meta::ClientMeta;
 pub struct AssetIndex {
 	/// The map of asset resource locations to index entries
 	pub objects: HashMap<String, IndexEntry>,
+	/// Whether the assets have to be placed by name in a virtual directory. Used by old versions
+	#[serde(default, rename = "virtual")]
+	pub is_virtual: bool,
+	/// Whether the assets have to be placed by name in the resources directory of the instance.
+	/// Used by very old versions
+	#[serde(default)]
+	pub map_to_resources: bool,
+}
+
+impl AssetIndex {
+	/// Get the layout that the game expects the assets in this index to be in
+	pub fn get_layout(&self) -> AssetLayout {
+		if self.map_to_resources {
+			AssetLayout::Resources
+		} else if self.is_virtual {
+			AssetLayout::Virtual
+		} else {
+			AssetLayout::Objects
+		}
+	}
+}
+
+/// Layout of assets that the game expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetLayout {
+	/// The assets are read from the shared object store using the index
+	Objects,
+	/// The assets are read by name from a shared virtual directory
+	Virtual,
+	/// The assets are read by name from the resources directory in the instance
+	Resources,
 }
 
 /// A single asset in the index
@@ -40,29 +70,30 @@ impl IndexEntry {
 	pub fn get_hash_path(&self) -> String {
 		format!("{}/{}", &self.hash[..2], self.hash)
 	}
+
+	/// Get the path to this asset in the objects directory. The path is built from
+	/// components instead of joining the hash path, which always uses forward slashes
+	pub fn get_object_path(&self, objects_dir: &Path) -> PathBuf {
+		objects_dir.join(&self.hash[..2]).join(&self.hash)
+	}
 }
 
 /// Download assets used by the client, such as game resources and icons.
 pub async fn get(
 	client_meta: &ClientMeta,
 	paths: &Paths,
-	version: &VersionName,
-	version_list: &[String],
 	manager: &UpdateManager,
 	client: &Client,
 	o: &mut impl MCVMOutput,
 ) -> anyhow::Result<UpdateMethodResult> {
 	let mut out = UpdateMethodResult::new();
-	let version_string = version.to_string();
-	let indexes_dir = paths.assets.join("indexes");
-	files::create_dir(&indexes_dir)?;
+	files::create_dir(&paths.assets.join("indexes"))?;
 
-	let index_path = indexes_dir.join(version_string + ".json");
+	let index_path = get_index_path(&client_meta.assets_version, paths);
 	let index_url = &client_meta.asset_index.url;
 
-	let (objects_dir, virtual_dir) = create_dirs(paths, version, version_list)
-		.await
-		.context("Failed to create directories for assets")?;
+	let objects_dir = get_objects_dir_path(paths);
+	files::create_dir(&objects_dir)?;
 
 	let index = match download_index(index_url, &index_path, manager, client, false).await {
 		Ok(val) => val,
@@ -89,37 +120,27 @@ pub async fn get(
 		name: String,
 		url: String,
 		path: PathBuf,
-		virtual_path: Option<PathBuf>,
 		size: usize,
 	}
 
 	let mut assets_to_download = Vec::new();
-	for (name, asset) in index.objects {
+	for (name, asset) in &index.objects {
 		let hash_path = asset.get_hash_path();
 		let url = format!("https://resources.download.minecraft.net/{hash_path}");
 
-		let path = objects_dir.join(&hash_path);
-		let virtual_path = virtual_dir.as_ref().map(|x| x.join(&hash_path));
+		let path = asset.get_object_path(&objects_dir);
 		if !manager.should_update_file(&path) {
-			if let Some(virtual_path) = &virtual_path {
-				if !manager.should_update_file(virtual_path) {
-					continue;
-				}
-			} else {
-				continue;
-			}
+			stats::record_cache_hit();
+			continue;
 		}
+		stats::record_cache_miss();
 
 		out.files_updated.insert(path.clone());
 		files::create_leading_dirs(&path)?;
-		if let Some(virtual_path) = &virtual_path {
-			files::create_leading_dirs(virtual_path)?;
-		}
 		let data = AssetData {
-			name,
+			name: name.clone(),
 			url,
 			path,
-			virtual_path,
 			size: asset.size,
 		};
 		assets_to_download.push(data);
@@ -164,15 +185,10 @@ pub async fn get(
 					.await
 					.context("Failed to write asset to file")?;
 			}
-
-			if let Some(virtual_path) = asset.virtual_path {
-				files::update_hardlink_async(&asset.path, &virtual_path)
-					.await
-					.context("Failed to hardlink virtual asset")?;
-			}
+			stats::record_file();
 			Ok::<String, anyhow::Error>(asset.name)
 		};
-		join.spawn(fut);
+		join.spawn(download::inherit_limits(stats::inherit(fut)));
 	}
 
 	if count > 0 {
@@ -213,6 +229,81 @@ pub async fn get(
 	);
 	o.end_process();
 
+	if index.get_layout() == AssetLayout::Virtual {
+		let virtual_dir = get_virtual_dir_path(&client_meta.assets_version, paths);
+		let result = materialize_assets(&index, &objects_dir, &virtual_dir, manager)
+			.context("Failed to create virtual assets directory")?;
+		out.merge(result);
+	}
+
+	Ok(out)
+}
+
+/// Prepare the assets for an instance that has already had its assets downloaded,
+/// returning the directory that the game should read assets from
+pub fn get_instance_assets(
+	client_meta: &ClientMeta,
+	paths: &Paths,
+	game_dir: &Path,
+	manager: &mut UpdateManager,
+) -> anyhow::Result<PathBuf> {
+	let index: AssetIndex = json_from_file(get_index_path(&client_meta.assets_version, paths))
+		.context("Failed to read asset index")?;
+	let layout = index.get_layout();
+	match layout {
+		AssetLayout::Objects => Ok(paths.assets.clone()),
+		AssetLayout::Virtual => Ok(get_virtual_dir_path(&client_meta.assets_version, paths)),
+		AssetLayout::Resources => {
+			let resources_dir = game_dir.join("resources");
+			let result = materialize_assets(
+				&index,
+				&get_objects_dir_path(paths),
+				&resources_dir,
+				manager,
+			)
+			.context("Failed to create resources directory")?;
+			manager.add_result(result);
+			Ok(resources_dir)
+		}
+	}
+}
+
+/// Get the layout of the assets for an asset index that has already been downloaded
+pub fn get_asset_layout(index_id: &str, paths: &Paths) -> anyhow::Result<AssetLayout> {
+	let index: AssetIndex =
+		json_from_file(get_index_path(index_id, paths)).context("Failed to read asset index")?;
+	Ok(index.get_layout())
+}
+
+/// Place the assets from the object store by name in a directory, which older versions use
+/// instead of the object store. Files are hardlinked when possible and copied otherwise, so that
+/// this works on filesystems and platforms where links aren't available
+pub fn materialize_assets(
+	index: &AssetIndex,
+	objects_dir: &Path,
+	target_dir: &Path,
+	manager: &UpdateManager,
+) -> anyhow::Result<UpdateMethodResult> {
+	let mut out = UpdateMethodResult::new();
+	for (name, asset) in &index.objects {
+		let path = target_dir.join(name);
+		if !manager.should_update_file(&path) {
+			continue;
+		}
+
+		let object_path = asset.get_object_path(objects_dir);
+		files::create_leading_dirs(&path)?;
+		if path.exists() {
+			std::fs::remove_file(&path)
+				.with_context(|| format!("Failed to remove existing asset '{name}'"))?;
+		}
+		if std::fs::hard_link(&object_path, &path).is_err() {
+			std::fs::copy(&object_path, &path)
+				.with_context(|| format!("Failed to copy asset '{name}'"))?;
+		}
+		out.files_updated.insert(path);
+	}
+
 	Ok(out)
 }
 
@@ -239,25 +330,101 @@ async fn download_index(
 	Ok(index)
 }
 
-/// Create the directories needed to store assets
-async fn create_dirs(
-	paths: &Paths,
-	version: &VersionName,
-	version_list: &[String],
-) -> anyhow::Result<(PathBuf, Option<PathBuf>)> {
-	let objects_dir = paths.assets.join("objects");
-	files::create_dir(&objects_dir)?;
-	// Apparently this directory name is used for older game versions
-	let virtual_dir =
-		if VersionPattern::Before("13w48b".into()).matches_single(version, version_list) {
-			Some(get_virtual_dir_path(paths))
-		} else {
-			None
-		};
-	Ok((objects_dir, virtual_dir))
+/// Get the path to the asset index with the given ID
+pub fn get_index_path(index_id: &str, paths: &Paths) -> PathBuf {
+	paths
+		.assets
+		.join("indexes")
+		.join(format!("{index_id}.json"))
+}
+
+/// Get the path to the shared asset object store
+pub fn get_objects_dir_path(paths: &Paths) -> PathBuf {
+	paths.assets.join("objects")
+}
+
+/// Get the virtual assets directory path for an asset index
+pub fn get_virtual_dir_path(index_id: &str, paths: &Paths) -> PathBuf {
+	paths.assets.join("virtual").join(index_id)
 }
 
-/// Get the virtual assets directory path
-pub fn get_virtual_dir_path(paths: &Paths) -> PathBuf {
-	paths.assets.join("virtual").join("legacy")
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Part of the pre-1.6 asset index
+	const INDEX_PRE_1_6: &str = r#"{
+		"map_to_resources": true,
+		"objects": {
+			"sound/ambient/cave/cave1.ogg": {"hash": "5be3a4e5d8e1bbc8a5e5c37e5ec7a0d0bf6ab3ae", "size": 66085},
+			"music/calm1.ogg": {"hash": "c3ab9bc2a2d2e1d0a08f6d6a8f0ea1dcb7a8f6c2", "size": 2904411}
+		}
+	}"#;
+
+	/// Part of the legacy asset index, used by 1.7.2
+	const INDEX_LEGACY: &str = r#"{
+		"virtual": true,
+		"objects": {
+			"sounds/ambient/cave/cave1.ogg": {"hash": "5be3a4e5d8e1bbc8a5e5c37e5ec7a0d0bf6ab3ae", "size": 66085},
+			"lang/en_US.lang": {"hash": "0d6cf3e1a5ba9e9a1d7e8d1b3a2b8a4f6e3d7c91", "size": 111233}
+		}
+	}"#;
+
+	#[test]
+	fn test_asset_index_layout() {
+		let index: AssetIndex = serde_json::from_str(INDEX_PRE_1_6).unwrap();
+		assert_eq!(index.get_layout(), AssetLayout::Resources);
+		let index: AssetIndex = serde_json::from_str(INDEX_LEGACY).unwrap();
+		assert_eq!(index.get_layout(), AssetLayout::Virtual);
+		let index: AssetIndex = serde_json::from_str(r#"{"objects": {}}"#).unwrap();
+		assert_eq!(index.get_layout(), AssetLayout::Objects);
+	}
+
+	#[test]
+	fn test_object_path() {
+		let entry = IndexEntry {
+			hash: "bdf48ef6b5d0d23bbb02e17d04865216179f510a".into(),
+			size: 0,
+		};
+		let objects_dir = Path::new("assets").join("objects");
+		assert_eq!(
+			entry.get_hash_path(),
+			"bd/bdf48ef6b5d0d23bbb02e17d04865216179f510a"
+		);
+		assert_eq!(
+			entry.get_object_path(&objects_dir),
+			objects_dir
+				.join("bd")
+				.join("bdf48ef6b5d0d23bbb02e17d04865216179f510a")
+		);
+	}
+
+	#[test]
+	fn test_materialize_assets() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let objects_dir = dir.join("objects");
+
+		for (fixture, target) in [(INDEX_PRE_1_6, "resources"), (INDEX_LEGACY, "virtual")] {
+			let index: AssetIndex = serde_json::from_str(fixture).unwrap();
+			for asset in index.objects.values() {
+				let path = asset.get_object_path(&objects_dir);
+				files::create_leading_dirs(&path).unwrap();
+				std::fs::write(path, &asset.hash).unwrap();
+			}
+
+			let target_dir = dir.join(target);
+			let manager = UpdateManager::new(false, false);
+			let result = materialize_assets(&index, &objects_dir, &target_dir, &manager).unwrap();
+			assert_eq!(result.files_updated.len(), index.objects.len());
+			for (name, asset) in &index.objects {
+				let contents = std::fs::read_to_string(target_dir.join(name)).unwrap();
+				assert_eq!(contents, asset.hash);
+			}
+
+			// Assets that are already in place are not touched again
+			let result = materialize_assets(&index, &objects_dir, &target_dir, &manager).unwrap();
+			assert!(result.files_updated.is_empty());
+		}
+	}
 }
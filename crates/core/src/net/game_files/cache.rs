@@ -0,0 +1,250 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::util::utc_timestamp;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::io::update::UpdateManager;
+use crate::io::{json_from_file, json_to_file};
+use crate::net::download::{self, ProgressiveDownload};
+use crate::net::stats;
+
+/// How long downloaded metadata is used before checking for changes, by default
+pub const DEFAULT_METADATA_FRESHNESS: Duration = Duration::from_secs(60 * 60);
+
+/// Information about when a cached file was downloaded, stored next to it
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CacheMetadata {
+	/// When the file was last downloaded or confirmed to be unchanged, as a UTC timestamp in seconds
+	pub fetched: u64,
+	/// The ETag that the server sent with the file
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub etag: Option<String>,
+	/// The modification date that the server sent with the file
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub last_modified: Option<String>,
+}
+
+impl CacheMetadata {
+	/// Get the path to the metadata for a cached file
+	pub fn get_path(file: &Path) -> PathBuf {
+		file.with_extension("cache.json")
+	}
+
+	/// Read the metadata for a cached file. Missing or invalid metadata is treated as not existing
+	pub fn read(file: &Path) -> Option<Self> {
+		let path = Self::get_path(file);
+		if !path.exists() {
+			return None;
+		}
+		json_from_file(path).ok()
+	}
+
+	/// Write the metadata for a cached file
+	pub fn write(&self, file: &Path) -> anyhow::Result<()> {
+		json_to_file(Self::get_path(file), self).context("Failed to write cache metadata")
+	}
+}
+
+/// What to do to get a cached file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheAction {
+	/// Use the cached file without any network requests
+	UseCached,
+	/// Ask the server if the cached file has changed, and download it if it has
+	Revalidate,
+	/// Download the file without using the cached one
+	Download,
+}
+
+/// Decide what to do to get a cached file, given the current time as a UTC timestamp in seconds
+pub fn get_cache_action(
+	file_exists: bool,
+	metadata: Option<&CacheMetadata>,
+	now: u64,
+	manager: &UpdateManager,
+) -> CacheAction {
+	if !file_exists {
+		return CacheAction::Download;
+	}
+	if manager.allow_offline {
+		return CacheAction::UseCached;
+	}
+	let Some(metadata) = metadata else {
+		// Files from before metadata was stored have an unknown age
+		return CacheAction::Revalidate;
+	};
+	if manager.force {
+		return CacheAction::Revalidate;
+	}
+
+	// A fetch time in the future means that the clock changed, so the age can't be trusted
+	match now.checked_sub(metadata.fetched) {
+		Some(age) if age < manager.metadata_freshness.as_secs() => CacheAction::UseCached,
+		_ => CacheAction::Revalidate,
+	}
+}
+
+/// Get the contents of a cached metadata file, downloading it if it is missing or has changed on the server.
+/// Newly downloaded contents are passed through `process` before they are stored. If the server can't be reached,
+/// the cached file is used with a warning. The `message` is shown along with the download progress
+pub async fn get_cached_file(
+	url: &str,
+	path: &Path,
+	process: impl FnOnce(Vec<u8>) -> anyhow::Result<Vec<u8>>,
+	message: &str,
+	manager: &UpdateManager,
+	client: &Client,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<Vec<u8>> {
+	let now = utc_timestamp()?;
+	let metadata = CacheMetadata::read(path);
+	let action = get_cache_action(path.exists(), metadata.as_ref(), now, manager);
+	let read_cached = || std::fs::read(path).context("Failed to read cached file");
+
+	if action == CacheAction::UseCached {
+		stats::record_cache_hit();
+		return read_cached();
+	}
+
+	let validators = match action {
+		CacheAction::Revalidate => metadata.clone().unwrap_or_default(),
+		_ => CacheMetadata::default(),
+	};
+	let response = download::conditional(
+		url,
+		client,
+		validators.etag.as_deref(),
+		validators.last_modified.as_deref(),
+	)
+	.await;
+	let response = match response {
+		Ok(response) => response,
+		Err(e) if path.exists() => {
+			o.display(
+				MessageContents::Warning(format!(
+					"Failed to check for changes to {}, so the cached copy will be used: {e:#}",
+					path.display()
+				)),
+				MessageLevel::Important,
+			);
+			stats::record_cache_hit();
+			return read_cached();
+		}
+		Err(e) => return Err(e),
+	};
+
+	if response.status() == StatusCode::NOT_MODIFIED && path.exists() {
+		let metadata = CacheMetadata {
+			fetched: now,
+			..validators
+		};
+		metadata.write(path)?;
+		stats::record_cache_hit();
+		return read_cached();
+	}
+
+	let get_header = |name| {
+		response
+			.headers()
+			.get(name)
+			.and_then(|x| x.to_str().ok())
+			.map(String::from)
+	};
+	stats::record_cache_miss();
+	let metadata = CacheMetadata {
+		fetched: now,
+		etag: get_header(reqwest::header::ETAG),
+		last_modified: get_header(reqwest::header::LAST_MODIFIED),
+	};
+
+	let mut download = ProgressiveDownload::from_response(response, Cursor::new(Vec::new()));
+	while !download.is_finished() {
+		download.poll_download().await?;
+		o.display(
+			MessageContents::Associated(
+				Box::new(download.get_progress()),
+				Box::new(MessageContents::Simple(message.into())),
+			),
+			MessageLevel::Important,
+		);
+	}
+	let bytes = process(download.finish())?;
+
+	std::fs::write(path, &bytes).context("Failed to write downloaded file")?;
+	stats::record_file();
+	metadata.write(path)?;
+
+	Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_cache_action() {
+		let mut manager = UpdateManager::new(false, false);
+		manager.set_metadata_freshness(Duration::from_secs(100));
+		let metadata = CacheMetadata {
+			fetched: 1000,
+			etag: Some("\"abc\"".into()),
+			last_modified: None,
+		};
+
+		assert_eq!(
+			get_cache_action(false, Some(&metadata), 1050, &manager),
+			CacheAction::Download
+		);
+		assert_eq!(
+			get_cache_action(true, Some(&metadata), 1050, &manager),
+			CacheAction::UseCached
+		);
+		assert_eq!(
+			get_cache_action(true, Some(&metadata), 1100, &manager),
+			CacheAction::Revalidate
+		);
+		assert_eq!(
+			get_cache_action(true, None, 1050, &manager),
+			CacheAction::Revalidate
+		);
+		// Clock went backwards
+		assert_eq!(
+			get_cache_action(true, Some(&metadata), 900, &manager),
+			CacheAction::Revalidate
+		);
+
+		let mut manager = UpdateManager::new(true, false);
+		manager.set_metadata_freshness(Duration::from_secs(100));
+		assert_eq!(
+			get_cache_action(true, Some(&metadata), 1050, &manager),
+			CacheAction::Revalidate
+		);
+
+		let manager = UpdateManager::new(false, true);
+		assert_eq!(
+			get_cache_action(true, None, 1_000_000, &manager),
+			CacheAction::UseCached
+		);
+		assert_eq!(
+			get_cache_action(false, None, 1_000_000, &manager),
+			CacheAction::Download
+		);
+	}
+
+	#[test]
+	fn test_metadata_path() {
+		assert_eq!(
+			CacheMetadata::get_path(Path::new("versions/manifest.json")),
+			PathBuf::from("versions/manifest.cache.json")
+		);
+		assert_eq!(
+			CacheMetadata::get_path(Path::new("versions/1.20.1/1.20.1.json")),
+			PathBuf::from("versions/1.20.1/1.20.1.cache.json")
+		);
+	}
+}
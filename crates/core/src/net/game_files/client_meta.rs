@@ -1,7 +1,8 @@
 use std::io::{Cursor, Read};
+use std::path::PathBuf;
 
 use anyhow::{bail, Context};
-use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::output::MCVMOutput;
 use mcvm_shared::translate;
 use mcvm_shared::util::DeserListOrSingle;
 use reqwest::Client;
@@ -10,10 +11,12 @@ use zip::ZipArchive;
 
 use crate::io::files::{self, paths::Paths};
 use crate::io::java::JavaMajorVersion;
-use crate::io::json_from_file;
 use crate::io::update::UpdateManager;
-use crate::net::download::ProgressiveDownload;
+use crate::net::download;
+use crate::util::versions::{CustomVersion, VersionName, VersionNotFound};
 
+use super::cache::get_cached_file;
+use super::sha1_hex;
 use super::version_manifest::VersionManifest;
 
 /// The client metadata, which is used for information about
@@ -40,7 +43,8 @@ pub struct ClientMeta {
 	/// Java main class for the client
 	#[serde(rename = "mainClass")]
 	pub main_class: String,
-	/// Logging information
+	/// Logging information. Old versions don't have this
+	#[serde(default)]
 	pub logging: LogInfo,
 }
 
@@ -49,6 +53,12 @@ pub struct ClientMeta {
 pub struct AssetIndexInfo {
 	/// The URL to the assets index for this version
 	pub url: String,
+	/// The size of the assets index in bytes
+	#[serde(default)]
+	pub size: Option<u64>,
+	/// The total size of all of the assets in the index in bytes
+	#[serde(default, rename = "totalSize")]
+	pub total_size: Option<u64>,
 }
 
 /// Download information for different files
@@ -56,15 +66,22 @@ pub struct AssetIndexInfo {
 pub struct Downloads {
 	/// Download info for the client.jar
 	pub client: DownloadInfo,
-	/// Download info for the server.jar
+	/// Download info for the server.jar. Some custom versions don't have one
+	#[serde(default)]
 	pub server: DownloadInfo,
 }
 
 /// Information for the downloading of a specific file
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct DownloadInfo {
-	/// The URL to the file
+	/// The URL to the file, or a local path for custom versions
 	pub url: String,
+	/// The size of the file in bytes
+	#[serde(default)]
+	pub size: Option<u64>,
+	/// The SHA-1 hash of the file
+	#[serde(default)]
+	pub sha1: Option<String>,
 }
 
 /// Information about Java for this version
@@ -76,21 +93,33 @@ pub struct JavaInfo {
 }
 
 /// Information about logging for this version
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct LogInfo {
 	/// Client logging
-	pub client: ClientLogInfo,
+	#[serde(default)]
+	pub client: Option<ClientLogInfo>,
 }
 
 /// Information about logging for the client
 #[derive(Deserialize, Debug, Clone)]
 pub struct ClientLogInfo {
 	/// The JVM argument to use for specifying the path of the log.
-	/// It contains a token '${file}' that should be replaced with the path
+	/// It contains a token '${path}' that should be replaced with the path
 	/// to the file.
 	pub argument: String,
 	/// Download for the logging configuration file
-	pub file: DownloadInfo,
+	pub file: LogFileInfo,
+}
+
+/// Download information for a logging configuration file
+#[derive(Deserialize, Debug, Clone)]
+pub struct LogFileInfo {
+	/// The ID of the file, which is also its file name
+	pub id: String,
+	/// The URL to the file
+	pub url: String,
+	/// The SHA-1 hash of the file
+	pub sha1: Option<String>,
 }
 
 /// Game arguments in the client meta
@@ -180,6 +209,12 @@ pub mod libraries {
 		pub path: String,
 		/// URL to download the artifact from
 		pub url: String,
+		/// The size of the artifact in bytes
+		#[serde(default)]
+		pub size: Option<u64>,
+		/// The SHA-1 hash of the artifact
+		#[serde(default)]
+		pub sha1: Option<String>,
 	}
 
 	/// Extraction rules for a library
@@ -234,6 +269,101 @@ pub mod conditions {
 		}
 	}
 
+	impl Rule {
+		/// Check if all of the conditions of this rule match
+		pub fn matches(&self, state: &RuleState) -> bool {
+			if let Some(os_name) = &self.os.name {
+				if os_name.to_string() != state.os {
+					return false;
+				}
+			}
+			if let Some(os_arch) = &self.os.arch {
+				if os_arch.to_string() != state.arch {
+					return false;
+				}
+			}
+
+			let features = &self.features;
+			let state = &state.features;
+			let checks = [
+				(features.is_demo_user, state.is_demo_user),
+				(features.has_custom_resolution, state.has_custom_resolution),
+				(
+					features.has_quick_play_support,
+					state.has_quick_play_support,
+				),
+				(
+					features.is_quick_play_singleplayer,
+					state.is_quick_play_singleplayer,
+				),
+				(
+					features.is_quick_play_multiplayer,
+					state.is_quick_play_multiplayer,
+				),
+				(features.is_quick_play_realms, state.is_quick_play_realms),
+			];
+			checks
+				.into_iter()
+				.all(|(expected, actual)| expected.map_or(true, |x| x == actual))
+		}
+	}
+
+	/// Check whether a list of rules allows something. Like the official launcher, the action
+	/// of the last rule that matches is used, and nothing is allowed if no rules match
+	pub fn check_rules(rules: &[Rule], state: &RuleState) -> bool {
+		if rules.is_empty() {
+			return true;
+		}
+
+		let mut allowed = false;
+		for rule in rules {
+			if rule.matches(state) {
+				allowed = rule.action.is_allowed();
+			}
+		}
+
+		allowed
+	}
+
+	/// The system and instance state that rules are checked against
+	#[derive(Debug, Clone)]
+	pub struct RuleState {
+		/// The operating system, in the format of [mcvm_shared::util::OS_STRING]
+		pub os: &'static str,
+		/// The architecture, in the format of [mcvm_shared::util::ARCH_STRING]
+		pub arch: &'static str,
+		/// The enabled features
+		pub features: FeatureState,
+	}
+
+	impl RuleState {
+		/// Create the state for the running system
+		pub fn current(features: FeatureState) -> Self {
+			Self {
+				os: mcvm_shared::util::OS_STRING,
+				arch: mcvm_shared::util::ARCH_STRING,
+				features,
+			}
+		}
+	}
+
+	/// Which features are enabled for an instance when checking rules
+	#[derive(Debug, Default, Clone)]
+	pub struct FeatureState {
+		/// Whether the user is a demo user
+		pub is_demo_user: bool,
+		/// Whether a custom window resolution is set
+		pub has_custom_resolution: bool,
+		/// Whether any kind of Quick Play is used
+		pub has_quick_play_support: bool,
+		/// Whether Quick Play into a world is used
+		pub is_quick_play_singleplayer: bool,
+		/// Whether Quick Play into a server is used
+		pub is_quick_play_multiplayer: bool,
+		/// Whether Quick Play into a Realm is used
+		pub is_quick_play_realms: bool,
+	}
+
 	/// Features that can be checked for a conditional argument rule
 	#[derive(Deserialize, Debug, Default, Clone)]
 	pub struct RuleFeatures {
@@ -298,6 +428,9 @@ pub mod conditions {
 		X86_64,
 		/// ARM architecture
 		Arm,
+		/// 64-bit ARM architecture
+		#[serde(alias = "aarch64")]
+		Arm64,
 	}
 
 	impl Display for OSArch {
@@ -309,6 +442,7 @@ pub mod conditions {
 					Self::X86 => "x86",
 					Self::X86_64 => "x86_64",
 					Self::Arm => "arm",
+					Self::Arm64 => "aarch64",
 				}
 			)
 		}
@@ -331,67 +465,204 @@ pub async fn get(
 		.iter()
 		.find(|x| x.id == version_string);
 	let Some(entry) = entry else {
-		bail!("Minecraft version does not exist or was not found in the manifest");
+		return Err(VersionNotFound::new(version, version_manifest).into());
 	};
 
-	let client_meta_name: String = version_string.clone() + ".json";
-	let version_dir = paths.internal.join("versions").join(version_string);
-	files::create_dir(&version_dir).context("Failed to create versions directory")?;
-	let path = version_dir.join(client_meta_name);
+	let path = get_path(version, paths);
+	files::create_dir(
+		path.parent()
+			.expect("Client meta path should have a parent"),
+	)
+	.context("Failed to create versions directory")?;
+
+	let message: String = translate!(o, DownloadingClientMeta);
+	let mut bytes = get_cached_file(
+		&entry.url,
+		&path,
+		|bytes| {
+			if entry.is_zipped {
+				unzip_client_meta(bytes)
+			} else {
+				Ok(bytes)
+			}
+		},
+		&message,
+		manager,
+		client,
+		o,
+	)
+	.await
+	.context("Failed to get client meta contents")?;
+
+	// Parsing must come after the file is written since simd json overwrites the slice
+	simd_json::from_slice(&mut bytes).context("Failed to parse client meta")
+}
 
-	let meta = if manager.allow_offline && path.exists() {
-		json_from_file(path).context("Failed to read client meta contents from file")?
+/// Gets the client meta for a custom version from its version JSON, which can be a path or a URL.
+/// The JSON is stored with the other versions, with its game JAR downloads replaced by the custom
+/// JAR if there is one. Returns the ID of the version from the JSON along with its meta
+pub async fn get_custom(
+	custom: &CustomVersion,
+	version_manifest: &VersionManifest,
+	paths: &Paths,
+	manager: &UpdateManager,
+	client: &Client,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<(VersionName, ClientMeta)> {
+	let bytes = if download::is_url(&custom.json) {
+		// The ID isn't known until the JSON is downloaded, so it is cached by its URL instead
+		let url_hash = sha1_hex(&custom.json);
+		let path = paths
+			.versions
+			.join("custom")
+			.join(format!("{url_hash}.json"));
+		files::create_leading_dirs(&path).context("Failed to create custom versions directory")?;
+		let message: String = translate!(o, DownloadingClientMeta);
+		get_cached_file(&custom.json, &path, Ok, &message, manager, client, o)
+			.await
+			.context("Failed to download custom version JSON")?
 	} else {
-		let mut download = ProgressiveDownload::bytes(&entry.url, client).await?;
-
-		while !download.is_finished() {
-			download.poll_download().await?;
-			o.display(
-				MessageContents::Associated(
-					Box::new(download.get_progress()),
-					Box::new(MessageContents::Simple(translate!(
-						o,
-						DownloadingClientMeta
-					))),
-				),
-				MessageLevel::Important,
-			);
+		std::fs::read(&custom.json)
+			.with_context(|| format!("Failed to read custom version JSON '{}'", custom.json))?
+	};
+
+	let mut json: serde_json::Value =
+		serde_json::from_slice(&bytes).context("Custom version JSON is not valid JSON")?;
+	let id = json
+		.get("id")
+		.and_then(|x| x.as_str())
+		.context("Custom version JSON is missing its id")?
+		.to_string();
+	check_custom_version_id(&id, version_manifest)?;
+	if let Some(jar) = &custom.jar {
+		set_custom_jar(&mut json, jar);
+	}
+
+	let path = get_path(&id, paths);
+	files::create_leading_dirs(&path).context("Failed to create versions directory")?;
+	let contents = serde_json::to_vec(&json).context("Failed to serialize custom version JSON")?;
+	std::fs::write(&path, contents).context("Failed to store custom version JSON")?;
+	let meta = serde_json::from_value(json).context("Failed to parse custom version JSON")?;
+
+	Ok((id.into(), meta))
+}
+
+/// Checks that the ID of a custom version can be used. Custom versions are stored alongside the
+/// ones from the manifest, so they can't share an ID with them
+fn check_custom_version_id(id: &str, version_manifest: &VersionManifest) -> anyhow::Result<()> {
+	if id.is_empty() || id.contains(['/', '\\']) || id == "." || id == ".." {
+		bail!("Custom version has an invalid ID '{id}'");
+	}
+	if id.contains('*') || id == "latest" || id == "latest_snapshot" {
+		bail!("Custom version ID '{id}' can't be a version pattern");
+	}
+	if version_manifest.versions.iter().any(|x| x.id == id) {
+		bail!("Custom version has the same ID '{id}' as a version in the manifest. Give it a different id in its version JSON");
+	}
+
+	Ok(())
+}
+
+/// Replace the game JAR downloads in a version JSON with a custom JAR. The hash and size
+/// in the JSON are for the JAR that is being replaced, so they are removed
+fn set_custom_jar(json: &mut serde_json::Value, jar: &str) {
+	let Some(json) = json.as_object_mut() else {
+		return;
+	};
+	let downloads = json
+		.entry("downloads")
+		.or_insert_with(|| serde_json::json!({}));
+	let Some(downloads) = downloads.as_object_mut() else {
+		return;
+	};
+	for side in ["client", "server"] {
+		let download = downloads
+			.entry(side)
+			.or_insert_with(|| serde_json::json!({}));
+		if let Some(download) = download.as_object_mut() {
+			download.insert("url".into(), jar.into());
+			download.remove("sha1");
+			download.remove("size");
 		}
-		let mut bytes = download.finish();
-
-		// Unzip if we need to
-		if entry.is_zipped {
-			let mut zip =
-				ZipArchive::new(Cursor::new(&bytes)).context("Failed to open zip archive")?;
-			if !zip.is_empty() {
-				let mut out = None;
-				for i in 0..zip.len() {
-					let mut file = zip.by_index(i).expect("Index should exist");
-					if file.is_file() {
-						let mut buf = Vec::with_capacity(
-							file.size().try_into().expect("Stop using 32 pointer width"),
-						);
-						file.read_to_end(&mut buf)
-							.context("Failed to read zip file")?;
-						out = Some(buf);
-					}
-				}
-				if let Some(out) = out {
-					bytes = out;
-				} else {
-					bail!("No files found for use in zip file");
-				}
-			} else {
-				bail!("Zipped client meta has no files inside")
-			}
+	}
+}
+
+/// Get the path to the stored client meta of a Minecraft version
+pub fn get_path(version: &str, paths: &Paths) -> PathBuf {
+	paths.versions.join(version).join(format!("{version}.json"))
+}
+
+/// Read the client meta of a Minecraft version that has already been downloaded, without
+/// going to the network. Returns None if it hasn't been downloaded
+pub fn read_stored(version: &str, paths: &Paths) -> anyhow::Result<Option<ClientMeta>> {
+	let path = get_path(version, paths);
+	if !path.exists() {
+		return Ok(None);
+	}
+	let mut bytes = std::fs::read(path).context("Failed to read client meta")?;
+	let meta = simd_json::from_slice(&mut bytes).context("Failed to parse client meta")?;
+
+	Ok(Some(meta))
+}
+
+/// Get the client meta out of a zip file
+fn unzip_client_meta(bytes: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+	let mut zip = ZipArchive::new(Cursor::new(&bytes)).context("Failed to open zip archive")?;
+	if zip.is_empty() {
+		bail!("Zipped client meta has no files inside")
+	}
+
+	let mut out = None;
+	for i in 0..zip.len() {
+		let mut file = zip.by_index(i).expect("Index should exist");
+		if file.is_file() {
+			let mut buf =
+				Vec::with_capacity(file.size().try_into().expect("Stop using 32 pointer width"));
+			file.read_to_end(&mut buf)
+				.context("Failed to read zip file")?;
+			out = Some(buf);
 		}
+	}
 
-		std::fs::write(path, &bytes).context("Failed to write client meta to a file")?;
+	out.context("No files found for use in zip file")
+}
 
-		// Parsing must come second since simd json overwrites the slice
+#[cfg(test)]
+mod tests {
+	use super::*;
 
-		simd_json::from_slice(&mut bytes).context("Failed to parse client meta")?
-	};
+	#[test]
+	fn test_custom_version_id() {
+		let manifest: VersionManifest = serde_json::from_value(serde_json::json!({
+			"latest": { "release": "1.20.1", "snapshot": "1.20.1" },
+			"versions": [{ "id": "1.20.1", "type": "release", "url": "" }]
+		}))
+		.unwrap();
+
+		assert!(check_custom_version_id("1.20.1-custom", &manifest).is_ok());
+		assert!(check_custom_version_id("1.20.1", &manifest).is_err());
+		assert!(check_custom_version_id("1.20.*", &manifest).is_err());
+		assert!(check_custom_version_id("latest", &manifest).is_err());
+		assert!(check_custom_version_id("../foo", &manifest).is_err());
+		assert!(check_custom_version_id("", &manifest).is_err());
+	}
 
-	Ok(meta)
+	#[test]
+	fn test_set_custom_jar() {
+		let mut json = serde_json::json!({
+			"id": "modified",
+			"downloads": {
+				"client": { "url": "https://example.com/client.jar", "sha1": "abc", "size": 1024 }
+			}
+		});
+		set_custom_jar(&mut json, "/home/user/modified.jar");
+
+		let downloads: Downloads = serde_json::from_value(json["downloads"].clone()).unwrap();
+		assert_eq!(downloads.client.url, "/home/user/modified.jar");
+		// The hash and size of the replaced JAR would never match the custom one
+		assert_eq!(downloads.client.sha1, None);
+		assert_eq!(downloads.client.size, None);
+		assert_eq!(downloads.server.url, "/home/user/modified.jar");
+		assert_eq!(downloads.server.sha1, None);
+	}
 }
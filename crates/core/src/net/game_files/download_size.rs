@@ -0,0 +1,215 @@
+use std::path::Path;
+
+use anyhow::Context;
+use mcvm_shared::Side;
+
+use crate::io::files::paths::Paths;
+use crate::io::json_from_file;
+use crate::io::update::UpdateManager;
+
+use super::assets::{self, AssetIndex};
+use super::client_meta::ClientMeta;
+use super::libraries::{self, LibraryFile};
+
+/// The total size of the files that an update would download
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DownloadSize {
+	/// The number of files that would be downloaded
+	pub files: usize,
+	/// The total size of the files whose sizes are known, in bytes
+	pub bytes: u64,
+	/// The number of files whose sizes aren't known
+	pub unknown: usize,
+}
+
+impl DownloadSize {
+	/// Add a file to the total
+	pub fn add_file(&mut self, size: Option<u64>) {
+		self.files += 1;
+		match size {
+			Some(size) => self.bytes += size,
+			None => self.unknown += 1,
+		}
+	}
+
+	/// Add another total to this one
+	pub fn merge(&mut self, other: Self) {
+		self.files += other.files;
+		self.bytes += other.bytes;
+		self.unknown += other.unknown;
+	}
+
+	/// Check whether nothing would be downloaded
+	pub fn is_empty(&self) -> bool {
+		self.files == 0
+	}
+
+	/// Check whether this download is over a limit in bytes. Any download is over a limit of zero
+	pub fn exceeds(&self, limit: u64) -> bool {
+		!self.is_empty() && (limit == 0 || self.bytes > limit)
+	}
+}
+
+impl std::fmt::Display for DownloadSize {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{:.1} MiB in {} files",
+			self.bytes as f64 / 1024.0 / 1024.0,
+			self.files
+		)?;
+		if self.unknown > 0 {
+			write!(f, " ({} of unknown size)", self.unknown)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Get the size of the assets in an index that aren't downloaded yet
+pub fn get_assets_size(
+	index: &AssetIndex,
+	objects_dir: &Path,
+	manager: &UpdateManager,
+) -> DownloadSize {
+	let mut out = DownloadSize::default();
+	for asset in index.objects.values() {
+		if manager.should_update_file(&asset.get_object_path(objects_dir)) {
+			out.add_file(Some(asset.size as u64));
+		}
+	}
+
+	out
+}
+
+/// Get the size of the library files that aren't downloaded yet
+pub fn get_libraries_size(libraries: &[LibraryFile], manager: &UpdateManager) -> DownloadSize {
+	let mut out = DownloadSize::default();
+	for library in libraries {
+		if manager.should_update_file(&library.path) {
+			out.add_file(library.size);
+		}
+	}
+
+	out
+}
+
+/// Estimate how much would be downloaded to set up an instance of a version using the sizes in
+/// its client meta. If the asset index hasn't been downloaded yet, every asset is counted, even though
+/// some of them may already be stored for other versions
+pub fn estimate_version_size(
+	client_meta: &ClientMeta,
+	version: &str,
+	side: Side,
+	paths: &Paths,
+	manager: &UpdateManager,
+) -> anyhow::Result<DownloadSize> {
+	let mut out = DownloadSize::default();
+
+	let jar_path = crate::io::minecraft::game_jar::get_path(side, version, None, paths);
+	if manager.should_update_file(&jar_path) {
+		let download = match side {
+			Side::Client => &client_meta.downloads.client,
+			Side::Server => &client_meta.downloads.server,
+		};
+		out.add_file(download.size);
+	}
+
+	// Servers don't need assets or libraries
+	if let Side::Server = side {
+		return Ok(out);
+	}
+
+	let index_path = assets::get_index_path(&client_meta.assets_version, paths);
+	if index_path.exists() {
+		let index: AssetIndex =
+			json_from_file(&index_path).context("Failed to read asset index")?;
+		out.merge(get_assets_size(
+			&index,
+			&assets::get_objects_dir_path(paths),
+			manager,
+		));
+	} else {
+		out.add_file(client_meta.asset_index.size);
+		if let Some(total_size) = client_meta.asset_index.total_size {
+			out.bytes += total_size;
+		} else {
+			out.unknown += 1;
+		}
+	}
+
+	let libraries =
+		libraries::get_files(client_meta, paths).context("Failed to get library files")?;
+	out.merge(get_libraries_size(&libraries, manager));
+
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_assets_size() {
+		let index: AssetIndex = serde_json::from_str(
+			r#"{
+				"objects": {
+					"minecraft/sounds/ambient/cave/cave1.ogg": {"hash": "5be3a4e5d8e1bbc8a5e5c37e5ec7a0d0bf6ab3ae", "size": 66085},
+					"minecraft/lang/en_us.json": {"hash": "0d6cf3e1a5ba9e9a1d7e8d1b3a2b8a4f6e3d7c91", "size": 111233},
+					"icons/icon_16x16.png": {"hash": "bdf48ef6b5d0d23bbb02e17d04865216179f510a", "size": 3665}
+				}
+			}"#,
+		)
+		.unwrap();
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let objects_dir = dir.join("objects");
+
+		let manager = UpdateManager::new(false, false);
+		let size = get_assets_size(&index, &objects_dir, &manager);
+		assert_eq!(size.files, 3);
+		assert_eq!(size.bytes, 66085 + 111233 + 3665);
+
+		// Assets that are already stored aren't counted
+		let stored = &index.objects["minecraft/lang/en_us.json"];
+		let path = stored.get_object_path(&objects_dir);
+		std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+		std::fs::write(&path, "{}").unwrap();
+		let size = get_assets_size(&index, &objects_dir, &manager);
+		assert_eq!(size.files, 2);
+		assert_eq!(size.bytes, 66085 + 3665);
+	}
+
+	#[test]
+	fn test_libraries_size() {
+		let dir = tempfile::tempdir().unwrap();
+		let library = |name: &str, size: Option<u64>| LibraryFile {
+			path: dir.path().join(name),
+			size,
+			is_native: false,
+		};
+		let libraries = vec![
+			library("lwjgl.jar", Some(548)),
+			library("gson.jar", Some(1000)),
+			library("unknown.jar", None),
+		];
+
+		let manager = UpdateManager::new(false, false);
+		let size = get_libraries_size(&libraries, &manager);
+		assert_eq!(
+			size,
+			DownloadSize {
+				files: 3,
+				bytes: 1548,
+				unknown: 1
+			}
+		);
+		assert!(!size.is_empty());
+		assert!(get_libraries_size(&[], &manager).is_empty());
+
+		assert!(size.exceeds(0));
+		assert!(size.exceeds(1000));
+		assert!(!size.exceeds(1548));
+		assert!(!DownloadSize::default().exceeds(0));
+	}
+}
@@ -1,9 +1,9 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
 use mcvm_shared::translate;
 use reqwest::Client;
@@ -14,11 +14,14 @@ use crate::io::files::{self, paths::Paths};
 use crate::io::java::classpath::Classpath;
 use crate::io::update::{UpdateManager, UpdateMethodResult};
 use crate::net::download::{self, get_transfer_limit};
+use crate::net::stats;
 use mcvm_shared::skip_none;
 use mcvm_shared::util;
 
+use super::client_meta::conditions::{check_rules, FeatureState, RuleState};
 use super::client_meta::libraries::ExtractionRules;
 use super::client_meta::{libraries::Library, ClientMeta};
+use super::sha1_matches;
 
 /// Downloads base client libraries.
 /// Returns a set of files to be added to the update manager.
@@ -31,15 +34,11 @@ pub async fn get(
 	o: &mut impl MCVMOutput,
 ) -> anyhow::Result<UpdateMethodResult> {
 	let mut out = UpdateMethodResult::new();
-	let libraries_path = paths.internal.join("libraries");
+	let libraries_path = paths.libraries.clone();
 	files::create_dir(&libraries_path)?;
-	let natives_path = paths
-		.internal
-		.join("versions")
-		.join(version)
-		.join("natives");
+	let natives_path = get_natives_dir(version, paths);
 	files::create_dir(&natives_path)?;
-	let natives_jars_path = paths.internal.join("natives");
+	let natives_jars_path = paths.natives.clone();
 
 	let mut natives = Vec::new();
 
@@ -49,7 +48,10 @@ pub async fn get(
 
 	for lib in libraries {
 		if !lib.natives.is_empty() {
-			let key = skip_none!(get_natives_classifier_key(&lib.natives));
+			let key = skip_none!(get_natives_classifier_key(
+				&lib.natives,
+				LibraryTarget::current()
+			));
 
 			let classifier = lib
 				.downloads
@@ -61,6 +63,7 @@ pub async fn get(
 
 			natives.push((path.clone(), &lib.name, &lib.extract));
 			if !manager.should_update_file(&path) {
+				stats::record_cache_hit();
 				continue;
 			}
 			libs_to_download.push((lib.name.clone(), classifier.clone(), path));
@@ -69,6 +72,7 @@ pub async fn get(
 		if let Some(artifact) = &lib.downloads.artifact {
 			let path = libraries_path.join(artifact.path.clone());
 			if !manager.should_update_file(&path) {
+				stats::record_cache_hit();
 				continue;
 			}
 			libs_to_download.push((lib.name.clone(), artifact.clone(), path));
@@ -94,6 +98,7 @@ pub async fn get(
 	// Used to limit the number of open file descriptors
 	let sem = Arc::new(Semaphore::new(get_transfer_limit()));
 	for (name, library, path) in libs_to_download {
+		stats::record_cache_miss();
 		let client = client.clone();
 		let sem = sem.clone();
 		let path_clone = path.clone();
@@ -105,13 +110,19 @@ pub async fn get(
 			let response = download::bytes(library.url, &client)
 				.await
 				.context("Failed to download library")?;
+			if let Some(sha1) = &library.sha1 {
+				if !sha1_matches(&response, sha1) {
+					bail!("Library {name} does not match its expected hash");
+				}
+			}
 			tokio::fs::write(&path_clone, response)
 				.await
 				.context("Failed to write library file")?;
+			stats::record_file();
 
 			Ok::<String, anyhow::Error>(name)
 		};
-		join.spawn(fut);
+		join.spawn(download::inherit_limits(stats::inherit(fut)));
 		out.files_updated.insert(path.clone());
 	}
 
@@ -168,14 +179,33 @@ pub async fn get(
 
 /// Gets the classpath from Minecraft libraries
 pub fn get_classpath(client_meta: &ClientMeta, paths: &Paths) -> anyhow::Result<Classpath> {
-	let natives_jars_path = paths.internal.join("natives");
-	let libraries_path = paths.internal.join("libraries");
-
 	let mut classpath = Classpath::new();
-	let libraries = get_list(client_meta);
-	for lib in libraries {
+	for file in get_files(client_meta, paths)? {
+		classpath.add_path(&file.path)?;
+	}
+	Ok(classpath)
+}
+
+/// A library file that is installed for a version
+#[derive(Debug, Clone)]
+pub struct LibraryFile {
+	/// The path to the file
+	pub path: PathBuf,
+	/// The size of the file in bytes, if the meta has it
+	pub size: Option<u64>,
+	/// Whether this is a jar of natives that get extracted
+	pub is_native: bool,
+}
+
+/// Gets the files of the Minecraft libraries that are installed for a version
+pub fn get_files(client_meta: &ClientMeta, paths: &Paths) -> anyhow::Result<Vec<LibraryFile>> {
+	let mut out = Vec::new();
+	for lib in get_list(client_meta) {
 		if !lib.natives.is_empty() {
-			let key = skip_none!(get_natives_classifier_key(&lib.natives));
+			let key = skip_none!(get_natives_classifier_key(
+				&lib.natives,
+				LibraryTarget::current()
+			));
 
 			let classifier = lib
 				.downloads
@@ -183,47 +213,140 @@ pub fn get_classpath(client_meta: &ClientMeta, paths: &Paths) -> anyhow::Result<
 				.get(&key)
 				.ok_or(anyhow!("Native lib artifact does not exist"))?;
 
-			let path = natives_jars_path.join(classifier.path.clone());
-			classpath.add_path(&path)?;
-
+			out.push(LibraryFile {
+				path: paths.natives.join(&classifier.path),
+				size: classifier.size,
+				is_native: true,
+			});
 			continue;
 		}
 		if let Some(artifact) = &lib.downloads.artifact {
-			let path = libraries_path.join(artifact.path.clone());
-			classpath.add_path(&path)?;
-			continue;
+			out.push(LibraryFile {
+				path: paths.libraries.join(&artifact.path),
+				size: artifact.size,
+				is_native: false,
+			});
 		}
 	}
-	Ok(classpath)
+	Ok(out)
 }
 
-/// Get the key for the natives classifier
-fn get_natives_classifier_key(classifiers: &HashMap<String, String>) -> Option<String> {
-	let key = classifiers
-		.get(&format!("natives-{}", util::OS_STRING))
-		.or_else(|| classifiers.get(util::OS_STRING))?;
-	let key = key.replace("${arch}", util::TARGET_BITS_STR);
+/// Gets the directory that natives are extracted to for a version
+pub fn get_natives_dir(version: &str, paths: &Paths) -> PathBuf {
+	paths.versions.join(version).join("natives")
+}
 
-	Some(key)
+/// The operating system and architecture that libraries are selected for
+#[derive(Debug, Clone, Copy)]
+struct LibraryTarget {
+	/// The OS, in the format of [util::OS_STRING]
+	os: &'static str,
+	/// The architecture, in the format of [util::ARCH_STRING]
+	arch: &'static str,
+	/// The pointer width that replaces `${arch}` in classifiers
+	bits: &'static str,
 }
 
-/// Checks the rules of a game library to see if it should be installed
-fn is_allowed(lib: &Library) -> bool {
-	for rule in &lib.rules {
-		let allowed = rule.action.is_allowed();
-		if let Some(os_name) = &rule.os.name {
-			if allowed != (os_name.to_string() == util::OS_STRING) {
-				return false;
-			}
+impl LibraryTarget {
+	/// The target of the running system
+	fn current() -> Self {
+		Self {
+			os: util::OS_STRING,
+			arch: util::ARCH_STRING,
+			bits: util::TARGET_BITS_STR,
 		}
-		if let Some(os_arch) = &rule.os.arch {
-			if allowed != (os_arch.to_string() == util::ARCH_STRING) {
-				return false;
-			}
+	}
+
+	/// Names that version JSONs use for this OS
+	fn os_names(&self) -> &'static [&'static str] {
+		match self.os {
+			"macos" => &["osx", "macos"],
+			"linux" => &["linux"],
+			"windows" => &["windows"],
+			_ => &[],
 		}
 	}
 
-	true
+	/// Names that version JSONs use for this architecture
+	fn arch_names(&self) -> &'static [&'static str] {
+		match self.arch {
+			"aarch64" => &["arm64", "aarch64"],
+			"x86_64" => &["x86_64", "x64", "amd64"],
+			"x86" => &["x86"],
+			"arm" => &["arm32", "arm"],
+			_ => &[],
+		}
+	}
+
+	/// Check if an architecture name from a version JSON refers to this architecture
+	fn is_arch(&self, arch: &str) -> bool {
+		self.arch_names().contains(&arch)
+	}
+}
+
+/// Get the key for the natives classifier. Keys specific to the
+/// architecture, like `osx-arm64`, are preferred over the key for just the OS
+fn get_natives_classifier_key(
+	classifiers: &HashMap<String, String>,
+	target: LibraryTarget,
+) -> Option<String> {
+	let os_names = target.os_names();
+	let arch_key = os_names.iter().find_map(|os| {
+		target
+			.arch_names()
+			.iter()
+			.find_map(|arch| classifiers.get(&format!("{os}-{arch}")))
+	});
+	let key = arch_key.or_else(|| os_names.iter().find_map(|os| classifiers.get(*os)))?;
+	let key = key.replace("${arch}", target.bits);
+
+	Some(key)
+}
+
+/// Checks the rules of a game library to see if it should be installed
+fn is_allowed(lib: &Library, target: LibraryTarget) -> bool {
+	let state = RuleState {
+		os: target.os,
+		arch: target.arch,
+		features: FeatureState::default(),
+	};
+	check_rules(&lib.rules, &state)
+}
+
+/// Splits the natives classifier of a library that ships its natives as a regular artifact,
+/// like `org.lwjgl:lwjgl:3.3.1:natives-macos-arm64`, into the library without the classifier
+/// and the architecture of the natives, if one is given
+fn parse_natives_artifact(name: &str) -> Option<(&str, Option<&str>)> {
+	let (base, classifier) = name.rsplit_once(':')?;
+	let classifier = classifier.strip_prefix("natives-")?;
+	// Strip the OS
+	let arch = classifier.split_once('-').map(|x| x.1);
+	Some((base, arch))
+}
+
+/// Filters libraries down to the ones that should be installed on a target.
+/// Natives that are regular artifacts are only checked against the OS by their rules,
+/// so we also need to pick the ones for the architecture using their classifiers
+fn filter_libraries(libraries: &[Library], target: LibraryTarget) -> Vec<&Library> {
+	let allowed: Vec<_> = libraries
+		.iter()
+		.filter(|lib| is_allowed(lib, target))
+		.collect();
+
+	allowed
+		.iter()
+		.filter(|lib| match parse_natives_artifact(&lib.name) {
+			None => true,
+			Some((_, Some(arch))) => target.is_arch(arch),
+			// Natives without an architecture are for x86_64, so we skip them
+			// when a variant for our architecture is available
+			Some((base, None)) => !allowed.iter().any(|other| {
+				parse_natives_artifact(&other.name)
+					.is_some_and(|(x, arch)| x == base && arch.is_some_and(|x| target.is_arch(x)))
+			}),
+		})
+		.copied()
+		.collect()
 }
 
 /// Extract the files of a native library into the natives directory.
@@ -278,5 +401,171 @@ fn extract_native(
 
 /// Gets the list of allowed libraries from the client meta
 pub fn get_list(client_meta: &ClientMeta) -> impl Iterator<Item = &Library> {
-	client_meta.libraries.iter().filter(|lib| is_allowed(lib))
+	filter_libraries(&client_meta.libraries, LibraryTarget::current()).into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn get_names(libraries: &[Library], os: &'static str, arch: &'static str) -> Vec<String> {
+		let target = LibraryTarget {
+			os,
+			arch,
+			bits: "64",
+		};
+		filter_libraries(libraries, target)
+			.into_iter()
+			.map(|x| x.name.clone())
+			.collect()
+	}
+
+	/// Natives in the classifier layout, from the 1.16.5 version JSON
+	fn classifier_libraries() -> Vec<Library> {
+		serde_json::from_str(
+			r#"[
+				{
+					"name": "org.lwjgl:lwjgl:3.2.2",
+					"downloads": {
+						"artifact": {"path": "org/lwjgl/lwjgl/3.2.2/lwjgl-3.2.2.jar", "url": ""},
+						"classifiers": {
+							"natives-linux": {"path": "org/lwjgl/lwjgl/3.2.2/lwjgl-3.2.2-natives-linux.jar", "url": ""},
+							"natives-macos": {"path": "org/lwjgl/lwjgl/3.2.2/lwjgl-3.2.2-natives-macos.jar", "url": ""},
+							"natives-windows": {"path": "org/lwjgl/lwjgl/3.2.2/lwjgl-3.2.2-natives-windows.jar", "url": ""}
+						}
+					},
+					"natives": {"linux": "natives-linux", "osx": "natives-macos", "windows": "natives-windows"}
+				},
+				{
+					"name": "com.mojang:text2speech:1.11.3",
+					"downloads": {
+						"classifiers": {
+							"natives-windows-32": {"path": "text2speech-natives-windows-32.jar", "url": ""},
+							"natives-windows-64": {"path": "text2speech-natives-windows-64.jar", "url": ""}
+						}
+					},
+					"natives": {"windows": "natives-windows-${arch}"}
+				},
+				{
+					"name": "ca.weblite:java-objc-bridge:1.0.0",
+					"natives": {"osx": "natives-osx", "osx-arm64": "natives-osx-arm64"},
+					"rules": [{"action": "allow", "os": {"name": "osx"}}]
+				}
+			]"#,
+		)
+		.unwrap()
+	}
+
+	/// Natives in the rules layout, from the 1.20.1 version JSON
+	fn rules_libraries() -> Vec<Library> {
+		let natives = [
+			("linux", "linux"),
+			("macos", "osx"),
+			("macos-arm64", "osx"),
+			("windows", "windows"),
+			("windows-arm64", "windows"),
+			("windows-x86", "windows"),
+		];
+		let mut libraries: Vec<Library> = natives
+			.iter()
+			.map(|(classifier, os)| {
+				serde_json::from_value(serde_json::json!({
+					"name": format!("org.lwjgl:lwjgl:3.3.1:natives-{classifier}"),
+					"downloads": {"artifact": {"path": format!("lwjgl-3.3.1-natives-{classifier}.jar"), "url": ""}},
+					"rules": [{"action": "allow", "os": {"name": os}}]
+				}))
+				.unwrap()
+			})
+			.collect();
+		libraries.push(
+			serde_json::from_value(serde_json::json!({
+				"name": "org.lwjgl:lwjgl:3.3.1",
+				"downloads": {"artifact": {"path": "lwjgl-3.3.1.jar", "url": ""}}
+			}))
+			.unwrap(),
+		);
+		libraries.push(
+			serde_json::from_value(serde_json::json!({
+				"name": "com.example:arm-only:1.0",
+				"rules": [{"action": "allow", "os": {"arch": "arm64"}}]
+			}))
+			.unwrap(),
+		);
+
+		libraries
+	}
+
+	#[test]
+	fn test_classifier_natives() {
+		let libraries = classifier_libraries();
+		let key = |lib: usize, os, arch| {
+			let target = LibraryTarget {
+				os,
+				arch,
+				bits: "64",
+			};
+			get_natives_classifier_key(&libraries[lib].natives, target)
+		};
+
+		assert_eq!(key(0, "linux", "x86_64").as_deref(), Some("natives-linux"));
+		assert_eq!(key(0, "linux", "aarch64").as_deref(), Some("natives-linux"));
+		assert_eq!(key(0, "macos", "aarch64").as_deref(), Some("natives-macos"));
+		assert_eq!(
+			key(1, "windows", "x86_64").as_deref(),
+			Some("natives-windows-64")
+		);
+		assert_eq!(key(1, "linux", "x86_64"), None);
+		assert_eq!(key(2, "macos", "x86_64").as_deref(), Some("natives-osx"));
+		assert_eq!(
+			key(2, "macos", "aarch64").as_deref(),
+			Some("natives-osx-arm64")
+		);
+
+		assert_eq!(get_names(&libraries, "linux", "x86_64").len(), 2);
+		assert_eq!(get_names(&libraries, "macos", "aarch64").len(), 3);
+	}
+
+	#[test]
+	fn test_rules_natives() {
+		let libraries = rules_libraries();
+
+		assert_eq!(
+			get_names(&libraries, "linux", "x86_64"),
+			[
+				"org.lwjgl:lwjgl:3.3.1:natives-linux",
+				"org.lwjgl:lwjgl:3.3.1"
+			]
+		);
+		// There are no natives for ARM on Linux, so the generic ones are still used
+		assert_eq!(
+			get_names(&libraries, "linux", "aarch64"),
+			[
+				"org.lwjgl:lwjgl:3.3.1:natives-linux",
+				"org.lwjgl:lwjgl:3.3.1",
+				"com.example:arm-only:1.0"
+			]
+		);
+		assert_eq!(
+			get_names(&libraries, "macos", "x86_64"),
+			[
+				"org.lwjgl:lwjgl:3.3.1:natives-macos",
+				"org.lwjgl:lwjgl:3.3.1"
+			]
+		);
+		assert_eq!(
+			get_names(&libraries, "macos", "aarch64"),
+			[
+				"org.lwjgl:lwjgl:3.3.1:natives-macos-arm64",
+				"org.lwjgl:lwjgl:3.3.1",
+				"com.example:arm-only:1.0"
+			]
+		);
+		assert_eq!(
+			get_names(&libraries, "windows", "x86"),
+			[
+				"org.lwjgl:lwjgl:3.3.1:natives-windows-x86",
+				"org.lwjgl:lwjgl:3.3.1"
+			]
+		);
+	}
 }
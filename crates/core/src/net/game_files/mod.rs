@@ -1,7 +1,13 @@
+/// Reporting how installed asset indexes share the object store
+pub mod asset_report;
 /// Downloading game assets
 pub mod assets;
+/// Caching downloaded metadata files
+pub mod cache;
 /// Structure for the client metadata file
 pub mod client_meta;
+/// Estimating how much an update will download
+pub mod download_size;
 /// Downloading game Java libraries
 pub mod libraries;
 /// Downloading and using the version manifest
@@ -14,13 +20,20 @@ use mcvm_shared::util::cap_first_letter;
 use mcvm_shared::Side;
 
 use reqwest::Client;
+use sha1::{Digest, Sha1};
 
-use super::download;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use super::{download, stats};
 
 /// Downloading the game JAR file
 pub mod game_jar {
 	use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel, OutputProcess};
 
+	use anyhow::{bail, Context};
+
 	use self::download::ProgressiveDownload;
 
 	use super::{client_meta::ClientMeta, *};
@@ -37,9 +50,20 @@ pub mod game_jar {
 	) -> anyhow::Result<()> {
 		let side_str = side.to_string();
 		let path = crate::io::minecraft::game_jar::get_path(side, version, None, paths);
-		if !manager.should_update_file(&path) {
+		let download = match side {
+			Side::Client => &client_meta.downloads.client,
+			Side::Server => &client_meta.downloads.server,
+		};
+		// Custom versions can use a JAR from a local path, which is copied every time in case it changed
+		let is_local = !download.url.is_empty() && !download::is_url(&download.url);
+		if !is_local && !manager.should_update_file(&path) {
+			stats::record_cache_hit();
 			return Ok(());
 		}
+		stats::record_cache_miss();
+		if download.url.is_empty() {
+			bail!("Version {version} does not have a {side_str} JAR");
+		}
 
 		let process = OutputProcess::new(o);
 		let download_message = translate!(process.0, StartDownloadingGameJar, "side" = &side_str);
@@ -48,21 +72,32 @@ pub mod game_jar {
 			MessageLevel::Important,
 		);
 
-		let download = match side {
-			Side::Client => &client_meta.downloads.client,
-			Side::Server => &client_meta.downloads.server,
-		};
+		if is_local {
+			crate::io::files::create_leading_dirs(&path)?;
+			std::fs::copy(&download.url, &path)
+				.with_context(|| format!("Failed to copy game JAR from '{}'", download.url))?;
+			stats::record_file();
+		} else {
+			let mut progress = ProgressiveDownload::file(&download.url, &path, client).await?;
+			while !progress.is_finished() {
+				progress.poll_download().await?;
+				process.0.display(
+					MessageContents::Associated(
+						Box::new(progress.get_progress()),
+						Box::new(MessageContents::Simple(download_message.clone())),
+					),
+					MessageLevel::Important,
+				);
+			}
+		}
 
-		let mut download = ProgressiveDownload::file(&download.url, path, client).await?;
-		while !download.is_finished() {
-			download.poll_download().await?;
-			process.0.display(
-				MessageContents::Associated(
-					Box::new(download.get_progress()),
-					Box::new(MessageContents::Simple(download_message.clone())),
-				),
-				MessageLevel::Important,
-			);
+		if let Some(sha1) = &download.sha1 {
+			let matches =
+				file_matches_sha1(&path, sha1).context("Failed to check game JAR hash")?;
+			if !matches {
+				let _ = std::fs::remove_file(&path);
+				bail!("The {side_str} JAR for version {version} does not match the hash in its version JSON");
+			}
 		}
 
 		let side_str = cap_first_letter(&side_str);
@@ -84,31 +119,89 @@ pub mod game_jar {
 pub mod log_config {
 	use std::path::PathBuf;
 
-	use super::{client_meta::ClientMeta, *};
+	use anyhow::{bail, Context};
+
+	use crate::io::update::UpdateMethodResult;
 
-	/// Get the logging configuration file and returns the path to it
+	use super::{client_meta::ClientLogInfo, *};
+
+	/// Get the logging configuration file for a version, if it has one
 	pub async fn get(
-		client_meta: &ClientMeta,
-		version: &str,
+		log_info: Option<&ClientLogInfo>,
 		paths: &Paths,
 		manager: &UpdateManager,
 		client: &Client,
-	) -> anyhow::Result<()> {
-		let path = get_path(version, paths);
+	) -> anyhow::Result<UpdateMethodResult> {
+		let mut out = UpdateMethodResult::new();
+		let Some(log_info) = log_info else {
+			return Ok(out);
+		};
+		let path = get_path(log_info, paths);
 
-		if !manager.should_update_file(&path) {
-			return Ok(());
+		let hash = log_info.file.sha1.as_deref();
+		let hash_matches = hash.map_or(true, |x| file_matches_sha1(&path, x).unwrap_or(false));
+		if !manager.should_update_file(&path) && hash_matches {
+			return Ok(out);
 		}
 
-		let url = &client_meta.logging.client.file.url;
-		download::file(url, &path, client).await?;
+		let contents = download::bytes(&log_info.file.url, client)
+			.await
+			.context("Failed to download logging configuration")?;
+		if let Some(hash) = hash {
+			if !sha1_matches(&contents, hash) {
+				bail!("Logging configuration file does not match its expected hash");
+			}
+		}
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)
+				.context("Failed to create logging configuration directory")?;
+		}
+		std::fs::write(&path, contents).context("Failed to write logging configuration")?;
+		out.files_updated.insert(path);
 
-		Ok(())
+		Ok(out)
+	}
+
+	/// Get the path to a logging config file. These are shared between versions and
+	/// stored by their ID
+	pub fn get_path(log_info: &ClientLogInfo, paths: &Paths) -> PathBuf {
+		paths.assets.join("log_configs").join(&log_info.file.id)
 	}
+}
+
+/// Get the hex-encoded SHA-1 hash of some data
+pub(crate) fn sha1_hex(data: impl AsRef<[u8]>) -> String {
+	hex::encode(Sha1::digest(data))
+}
+
+/// Check if some data matches a hex-encoded SHA-1 hash
+pub(crate) fn sha1_matches(data: impl AsRef<[u8]>, hash: &str) -> bool {
+	hash.eq_ignore_ascii_case(&sha1_hex(data))
+}
+
+/// Check if a file matches a hex-encoded SHA-1 hash, without reading all of it into memory
+pub(crate) fn file_matches_sha1(path: &Path, hash: &str) -> anyhow::Result<bool> {
+	let mut file = BufReader::new(File::open(path)?);
+	let mut hasher = Sha1::new();
+	std::io::copy(&mut file, &mut hasher)?;
+	Ok(hash.eq_ignore_ascii_case(&hex::encode(hasher.finalize())))
+}
 
-	/// Get the path to the logging config file
-	pub fn get_path(version: &str, paths: &Paths) -> PathBuf {
-		let version_dir = paths.internal.join("versions").join(version);
-		version_dir.join("logging.xml")
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_sha1_matches() {
+		let hash = "2AAE6C35C94FCFB415DBE95F408B9CE91EE846ED";
+		assert!(sha1_matches("hello world", hash));
+		assert!(sha1_matches("hello world", &hash.to_lowercase()));
+		assert!(!sha1_matches("hello world!", hash));
+
+		let temp_dir = tempfile::tempdir().unwrap();
+		let path = temp_dir.path().join("file");
+		std::fs::write(&path, "hello world").unwrap();
+		assert!(file_matches_sha1(&path, hash).unwrap());
+		assert!(file_matches_sha1(&temp_dir.path().join("missing"), hash).is_err());
 	}
 }
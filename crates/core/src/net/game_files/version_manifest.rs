@@ -4,12 +4,14 @@ use mcvm_shared::translate;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
+use std::path::PathBuf;
+
 use crate::io::files::{self, paths::Paths};
 use crate::io::update::UpdateManager;
-use crate::io::{json_from_file, json_to_file};
-use crate::net::download::ProgressiveDownload;
 use crate::util::versions::VersionName;
 
+use super::cache::get_cached_file;
+
 /// JSON format for the version manifest that contains all available Minecraft versions
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct VersionManifest {
@@ -66,7 +68,7 @@ pub async fn get(
 	client: &Client,
 	o: &mut impl MCVMOutput,
 ) -> anyhow::Result<VersionManifest> {
-	let manifest = get_contents(paths, manager, client, false, o).await;
+	let manifest = get_contents(paths, manager, client, o).await;
 	let manifest = match manifest {
 		Ok(manifest) => manifest,
 		Err(err) => {
@@ -82,7 +84,12 @@ pub async fn get(
 				MessageContents::StartProcess("Redownloading".into()),
 				MessageLevel::Important,
 			);
-			get_contents(paths, manager, client, true, o)
+			// Remove the cached copy so that it has to be downloaded again
+			let path = get_path(paths);
+			if path.exists() {
+				std::fs::remove_file(path).context("Failed to remove cached manifest")?;
+			}
+			get_contents(paths, manager, client, o)
 				.await
 				.context("Failed to download manifest contents")?
 		}
@@ -116,45 +123,35 @@ pub async fn get_with_output(
 	Ok(manifest)
 }
 
+/// Get the path to the cached version manifest
+fn get_path(paths: &Paths) -> PathBuf {
+	paths.versions.join("manifest.json")
+}
+
 /// Obtain the version manifest contents
 async fn get_contents(
 	paths: &Paths,
 	manager: &UpdateManager,
 	client: &Client,
-	force: bool,
 	o: &mut impl MCVMOutput,
 ) -> anyhow::Result<VersionManifest> {
-	let mut path = paths.internal.join("versions");
-	files::create_dir(&path)?;
-	path.push("manifest.json");
-	if manager.allow_offline && !force && path.exists() {
-		return json_from_file(path).context("Failed to read manifest contents from file");
-	}
+	files::create_dir(&paths.versions)?;
+	let path = get_path(paths);
 
-	let mut download = ProgressiveDownload::bytes(
+	let message: String = translate!(o, StartDownloadingVersionManifest);
+	let mut bytes = get_cached_file(
 		"https://piston-meta.mojang.com/mc/game/version_manifest_v2.json",
+		&path,
+		Ok,
+		&message,
+		manager,
 		client,
+		o,
 	)
-	.await?;
-
-	while !download.is_finished() {
-		download.poll_download().await?;
-		o.display(
-			MessageContents::Associated(
-				Box::new(download.get_progress()),
-				Box::new(MessageContents::Simple(translate!(
-					o,
-					StartDownloadingVersionManifest
-				))),
-			),
-			MessageLevel::Important,
-		);
-	}
-	let manifest = download.finish_json()?;
+	.await
+	.context("Failed to get manifest contents")?;
 
-	json_to_file(path, &manifest).context("Failed to write manifest to a file")?;
-
-	Ok(manifest)
+	simd_json::from_slice(&mut bytes).context("Failed to parse manifest contents")
 }
 
 /// Make an ordered list of versions from the manifest to use for matching
@@ -4,6 +4,14 @@ use mcvm_shared::util::{ARCH_STRING, OS_STRING, PREFERRED_ARCHIVE};
 use anyhow::{anyhow, Context};
 use reqwest::Client;
 
+/// Get the architecture name used by Java distribution APIs, which call x86_64 `x64`
+fn get_java_arch() -> &'static str {
+	match ARCH_STRING {
+		"x86_64" => "x64",
+		other => other,
+	}
+}
+
 /// Downloading Adoptium JDK
 pub mod adoptium {
 	use anyhow::bail;
@@ -45,11 +53,7 @@ pub mod adoptium {
 
 	/// Get the arch argument for the API
 	fn get_arch_arg() -> &'static str {
-		if cfg!(target_arch = "x86_64") {
-			"x64"
-		} else {
-			ARCH_STRING
-		}
+		get_java_arch()
 	}
 
 	/// A single package info for Adoptium
@@ -99,7 +103,8 @@ pub mod zulu {
 	/// Gets the URL to the JSON file for a major Java version
 	fn json_url(major_version: &str) -> String {
 		format!(
-			"https://api.azul.com/metadata/v1/zulu/packages/?java_version={major_version}&os={OS_STRING}&arch={ARCH_STRING}&archive_type={PREFERRED_ARCHIVE}&java_package_type=jre&latest=true&java_package_features=headfull&release_status=ga&availability_types=CA&certifications=tck&page=1&page_size=100"
+			"https://api.azul.com/metadata/v1/zulu/packages/?java_version={major_version}&os={OS_STRING}&arch={}&archive_type={PREFERRED_ARCHIVE}&java_package_type=jre&latest=true&java_package_features=headfull&release_status=ga&availability_types=CA&certifications=tck&page=1&page_size=100",
+			get_java_arch()
 		)
 	}
 
@@ -147,7 +152,7 @@ pub mod graalvm {
 		format!(
 			"https://download.oracle.com/graalvm/{major_version}/latest/graalvm-jdk-{major_version}_{}-{}_bin{}",
 			OS_STRING,
-			ARCH_STRING,
+			get_java_arch(),
 			preferred_archive_extension()
 		)
 	}
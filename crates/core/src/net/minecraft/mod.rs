@@ -1,5 +1,6 @@
+use anyhow::Context;
 use mcvm_auth::mc::{call_mc_api, Keypair};
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 
 /// Struct for a Minecraft Profile from the Minecraft Services API
@@ -104,3 +105,61 @@ pub async fn get_user_certificate(
 
 	Ok(response)
 }
+
+/// The public profile of any player, from the Mojang API
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerProfile {
+	/// The current username of the player
+	pub name: String,
+	/// The UUID of the player, without hyphens
+	#[serde(rename = "id")]
+	pub uuid: String,
+}
+
+/// Look up the profile of the player with a username. Returns None if no player has the name
+pub async fn get_player_by_name(
+	name: &str,
+	client: &Client,
+) -> anyhow::Result<Option<PlayerProfile>> {
+	get_player_profile(
+		&format!("https://api.mojang.com/users/profiles/minecraft/{name}"),
+		client,
+	)
+	.await
+}
+
+/// Look up the profile of the player with a UUID. Returns None if no player has the UUID
+pub async fn get_player_by_uuid(
+	uuid: &str,
+	client: &Client,
+) -> anyhow::Result<Option<PlayerProfile>> {
+	get_player_profile(
+		&format!("https://sessionserver.mojang.com/session/minecraft/profile/{uuid}"),
+		client,
+	)
+	.await
+}
+
+/// Get a player profile from one of the profile endpoints, which respond with
+/// an empty body or 404 when the player doesn't exist
+async fn get_player_profile(url: &str, client: &Client) -> anyhow::Result<Option<PlayerProfile>> {
+	let response = client
+		.get(url)
+		.send()
+		.await
+		.context("Failed to send request")?;
+	if matches!(
+		response.status(),
+		StatusCode::NOT_FOUND | StatusCode::NO_CONTENT
+	) {
+		return Ok(None);
+	}
+	let response = response
+		.error_for_status()
+		.context("Server reported an error")?
+		.json()
+		.await
+		.context("Failed to parse player profile")?;
+
+	Ok(Some(response))
+}
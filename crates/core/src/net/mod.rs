@@ -7,3 +7,4 @@ pub mod minecraft;
 
 // Re-export
 pub use mcvm_net::download;
+pub use mcvm_net::stats;
@@ -12,8 +12,14 @@ use mcvm_auth::mc::{
 	ClientId, RefreshToken,
 };
 
+use super::uuid::offline_uuid;
 use super::{CustomAuthFunction, User, UserKind};
 
+/// The name used for offline users that don't have one configured
+pub const DEFAULT_OFFLINE_NAME: &str = "Player";
+/// The placeholder access token given to the game for offline users
+const OFFLINE_ACCESS_TOKEN: &str = "0";
+
 impl User {
 	/// Authenticate the user
 	pub(crate) async fn authenticate(
@@ -48,7 +54,7 @@ impl User {
 					*xbox_uid = user_data.xbox_uid;
 				}
 			}
-			UserKind::Demo => {}
+			UserKind::Demo | UserKind::Unverified => self.create_offline_session(),
 			UserKind::Unknown(other) => {
 				if let Some(func) = params.custom_auth_fn {
 					o.display(
@@ -76,6 +82,17 @@ impl User {
 		Ok(())
 	}
 
+	/// Fill in the name, UUID, and a dummy access token for a user without an account.
+	/// These users never get a keypair since they can't sign chat messages
+	fn create_offline_session(&mut self) {
+		let name = self
+			.name
+			.get_or_insert_with(|| DEFAULT_OFFLINE_NAME.to_string());
+		self.uuid = Some(offline_uuid(name));
+		self.access_token = Some(AccessToken(OFFLINE_ACCESS_TOKEN.to_string()));
+		self.keypair = None;
+	}
+
 	/// Checks if the user still has valid authentication. This does not mean that they are
 	/// authenticated yet. To check if the user is authenticated and ready to be used, use the is_authenticated
 	/// function instead.
@@ -88,7 +105,7 @@ impl User {
 
 				db.get_valid_user(&self.id).is_some()
 			}
-			UserKind::Demo => true,
+			UserKind::Demo | UserKind::Unverified => true,
 			UserKind::Unknown(..) => true,
 		}
 	}
@@ -96,8 +113,9 @@ impl User {
 	/// Checks if this user is currently authenticated and ready to be used
 	pub fn is_authenticated(&self) -> bool {
 		match &self.kind {
-			UserKind::Microsoft { .. } => self.access_token.is_some() && self.uuid.is_some(),
-			UserKind::Demo => true,
+			UserKind::Microsoft { .. } | UserKind::Demo | UserKind::Unverified => {
+				self.access_token.is_some() && self.uuid.is_some()
+			}
 			UserKind::Unknown(..) => true,
 		}
 	}
@@ -181,7 +199,7 @@ async fn update_microsoft_user_auth(
 		// Get the access token using the refresh token
 		let oauth_client =
 			auth::create_client(params.client_id).context("Failed to create OAuth client")?;
-		let token = auth::refresh_microsoft_token(&oauth_client, &refresh_token)
+		let token = auth::refresh_microsoft_token(&oauth_client, &refresh_token, params.req_client)
 			.await
 			.context("Failed to get refreshed token")?;
 
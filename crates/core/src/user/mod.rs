@@ -44,6 +44,8 @@ pub enum UserKind {
 	},
 	/// A demo user
 	Demo,
+	/// A user that plays offline with just a username and no account
+	Unverified,
 	/// An unknown user kind
 	Unknown(String),
 }
@@ -71,6 +73,11 @@ impl User {
 		self.name.as_ref()
 	}
 
+	/// Set the name of this user. Only used for users that don't get their name from authentication
+	pub fn set_name(&mut self, name: &str) {
+		self.name = Some(name.to_string());
+	}
+
 	/// Checks if this user is a Microsoft user
 	pub fn is_microsoft(&self) -> bool {
 		matches!(self.kind, UserKind::Microsoft { .. })
@@ -81,6 +88,11 @@ impl User {
 		matches!(self.kind, UserKind::Demo)
 	}
 
+	/// Checks if this user plays without an authenticated account
+	pub fn is_offline(&self) -> bool {
+		matches!(self.kind, UserKind::Demo | UserKind::Unverified)
+	}
+
 	/// Gets the kind of this user
 	pub fn get_kind(&self) -> &UserKind {
 		&self.kind
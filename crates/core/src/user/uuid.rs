@@ -1,4 +1,5 @@
 use anyhow::ensure;
+use md5::{Digest, Md5};
 
 /// Converts a hexadecimal uuid to the hyphenated form
 pub fn hyphenate_uuid(uuid: &str) -> anyhow::Result<String> {
@@ -14,6 +15,18 @@ pub fn hyphenate_uuid(uuid: &str) -> anyhow::Result<String> {
 	Ok(out)
 }
 
+/// Gets the UUID that the game uses for a player in offline mode, which is a version 3 UUID
+/// of the bytes of `OfflinePlayer:<name>`. Returned without hyphens, like other UUIDs
+pub fn offline_uuid(name: &str) -> String {
+	let mut hash: [u8; 16] = Md5::digest(format!("OfflinePlayer:{name}").as_bytes()).into();
+	// Set the version to 3
+	hash[6] = (hash[6] & 0x0f) | 0x30;
+	// Set the variant to IETF
+	hash[8] = (hash[8] & 0x3f) | 0x80;
+
+	hash.iter().map(|x| format!("{x:02x}")).collect()
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -26,4 +39,14 @@ mod tests {
 			"8b447756-e180-44d3-bfbf-dbc8df722db4".to_string()
 		);
 	}
+
+	#[test]
+	fn test_offline_uuid() {
+		assert_eq!(offline_uuid("Notch"), "b50ad385829d3141a2167e7d7539ba7f");
+		assert_eq!(offline_uuid("jeb_"), "a762f5604fce3236812ab80efff0b62b");
+		assert_eq!(
+			hyphenate_uuid(&offline_uuid("Steve")).unwrap(),
+			"5627dd98-e6be-3c21-b8a8-e92344183641"
+		);
+	}
 }
@@ -1,10 +1,14 @@
 use std::{fmt::Display, sync::Arc};
 
+use anyhow::bail;
+
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::net::game_files::version_manifest::VersionManifest;
+use mcvm_shared::util::levenshtein;
+
+use crate::net::game_files::version_manifest::{VersionManifest, VersionType};
 
 /// Matches for the latest Minecraft version.
 /// We have to separate this so that deserialization works
@@ -29,19 +33,70 @@ pub enum MinecraftVersionDeser {
 	Latest(MinecraftLatestVersion),
 	/// A generic version
 	Version(VersionName),
+	/// A version that isn't in the version manifest
+	Custom {
+		/// The files for the version
+		custom: CustomVersion,
+	},
+}
+
+/// A version of the game that isn't in the version manifest, such as a modified client,
+/// that is loaded from its own version JSON
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct CustomVersion {
+	/// A path or URL to the version JSON, which has the same format as the ones from Mojang
+	pub json: String,
+	/// A path or URL to the game JAR. Defaults to the download listed in the version JSON
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub jar: Option<String>,
 }
 
 impl MinecraftVersionDeser {
 	/// Convert to a Minecraft version
 	pub fn to_mc_version(&self) -> MinecraftVersion {
+		self.to_mc_version_with_channel(VersionChannel::Release)
+	}
+
+	/// Convert to a Minecraft version, with the latest version and wildcards
+	/// following the given channel
+	pub fn to_mc_version_with_channel(&self, channel: VersionChannel) -> MinecraftVersion {
 		match self {
+			Self::Version(version) if version.contains('*') => {
+				MinecraftVersion::Wildcard(version.clone(), channel)
+			}
 			Self::Version(version) => MinecraftVersion::Version(version.clone()),
-			Self::Latest(MinecraftLatestVersion::Release) => MinecraftVersion::Latest,
+			Self::Latest(MinecraftLatestVersion::Release) => match channel {
+				VersionChannel::Release => MinecraftVersion::Latest,
+				VersionChannel::Snapshot => MinecraftVersion::LatestSnapshot,
+			},
 			Self::Latest(MinecraftLatestVersion::Snapshot) => MinecraftVersion::LatestSnapshot,
+			Self::Custom { custom } => MinecraftVersion::Custom(custom.clone()),
+		}
+	}
+
+	/// Checks if this is a pattern that can resolve to a different version over time
+	pub fn is_floating(&self) -> bool {
+		match self {
+			Self::Version(version) => version.contains('*'),
+			Self::Latest(..) => true,
+			Self::Custom { .. } => false,
 		}
 	}
 }
 
+/// Which kinds of versions to track when resolving the latest version or a wildcard
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum VersionChannel {
+	/// Only release versions
+	#[default]
+	Release,
+	/// Release and snapshot versions
+	Snapshot,
+}
+
 /// User-supplied Minecraft version pattern
 #[derive(Debug, Clone)]
 pub enum MinecraftVersion {
@@ -51,6 +106,10 @@ pub enum MinecraftVersion {
 	Latest,
 	/// The latest release or development version available
 	LatestSnapshot,
+	/// The newest version matching a wildcard pattern such as `1.20.*` in a channel
+	Wildcard(VersionName, VersionChannel),
+	/// A version loaded from a custom version JSON instead of the manifest
+	Custom(CustomVersion),
 }
 
 impl MinecraftVersion {
@@ -60,21 +119,186 @@ impl MinecraftVersion {
 			Self::Version(version) => Ok(version.clone()),
 			Self::Latest => Ok(manifest.latest.release.clone()),
 			Self::LatestSnapshot => Ok(manifest.latest.snapshot.clone()),
+			Self::Wildcard(pattern, channel) => {
+				// The manifest is ordered from newest to oldest
+				let version = manifest
+					.versions
+					.iter()
+					.filter(|x| {
+						*channel == VersionChannel::Snapshot || matches!(x.ty, VersionType::Release)
+					})
+					.find(|x| matches_wildcard(pattern, &x.id));
+				let Some(version) = version else {
+					return Err(VersionNotFound::for_wildcard(pattern, *channel, manifest).into());
+				};
+				Ok(version.id.clone().into())
+			}
+			Self::Custom(custom) => bail!(
+				"The custom version from '{}' is not in the version manifest. Its ID is only known once it is loaded",
+				custom.json
+			),
+		}
+	}
+
+	/// Checks if this version is loaded from a custom version JSON
+	pub fn is_custom(&self) -> bool {
+		matches!(self, Self::Custom(..))
+	}
+}
+
+/// Check if a version matches a wildcard pattern, where `*` matches any sequence of characters.
+/// A pattern ending in `.*` also matches the version without that suffix, so `1.20.*` matches `1.20`
+pub fn matches_wildcard(pattern: &str, version: &str) -> bool {
+	if let Some(base) = pattern.strip_suffix(".*") {
+		if version == base {
+			return true;
+		}
+	}
+
+	let mut parts = pattern.split('*');
+	let first = parts.next().unwrap_or_default();
+	let Some(mut rest) = version.strip_prefix(first) else {
+		return false;
+	};
+	let parts: Vec<_> = parts.collect();
+	let Some((last, middle)) = parts.split_last() else {
+		// No wildcards in the pattern
+		return rest.is_empty();
+	};
+	for part in middle {
+		let Some(pos) = rest.find(part) else {
+			return false;
+		};
+		rest = &rest[pos + part.len()..];
+	}
+
+	rest.ends_with(last)
+}
+
+/// The maximum number of similar versions to suggest when a version is not found
+const MAX_VERSION_SUGGESTIONS: usize = 5;
+
+/// Error for when a requested Minecraft version does not exist in the version manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionNotFound {
+	/// The version or wildcard pattern that was requested
+	pub version: String,
+	/// Similar versions from the manifest, most similar first
+	pub suggestions: Vec<String>,
+	/// Whether the request only matches snapshots while the channel only allows releases
+	pub snapshot_on_release_channel: bool,
+}
+
+impl VersionNotFound {
+	/// Create the error for a version that is not in the manifest
+	pub fn new(version: &str, manifest: &VersionManifest) -> Self {
+		Self {
+			version: version.into(),
+			suggestions: suggest_versions(version, manifest),
+			snapshot_on_release_channel: false,
+		}
+	}
+
+	/// Create the error for a wildcard pattern that did not match any version in the channel
+	pub fn for_wildcard(
+		pattern: &str,
+		channel: VersionChannel,
+		manifest: &VersionManifest,
+	) -> Self {
+		let snapshot_on_release_channel = channel == VersionChannel::Release
+			&& manifest
+				.versions
+				.iter()
+				.any(|x| matches_wildcard(pattern, &x.id));
+		Self {
+			version: pattern.into(),
+			suggestions: Vec::new(),
+			snapshot_on_release_channel,
+		}
+	}
+
+	/// Get the main error message, without the suggestions
+	pub fn message(&self) -> String {
+		if self.snapshot_on_release_channel {
+			format!(
+				"'{}' only matches snapshot versions, but the version channel is set to releases",
+				self.version
+			)
+		} else {
+			format!(
+				"Minecraft version '{}' does not exist or was not found in the manifest",
+				self.version
+			)
+		}
+	}
+
+	/// Get a hint for fixing the error, if there is one
+	pub fn hint(&self) -> Option<String> {
+		if self.snapshot_on_release_channel {
+			Some(
+				"Set the version_channel of the instance or profile to 'snapshot' to use it".into(),
+			)
+		} else if self.suggestions.is_empty() {
+			None
+		} else {
+			Some(format!("Did you mean {}?", self.suggestions.join(", ")))
+		}
+	}
+}
+
+impl Display for VersionNotFound {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.message())?;
+		if let Some(hint) = self.hint() {
+			write!(f, ". {hint}")?;
+		}
+
+		Ok(())
+	}
+}
+
+impl std::error::Error for VersionNotFound {}
+
+/// Find versions in the manifest that are similar to a version that does not exist.
+/// Versions that share a prefix with it come first, and then versions with a small edit distance
+fn suggest_versions(version: &str, manifest: &VersionManifest) -> Vec<String> {
+	if version.is_empty() {
+		return Vec::new();
+	}
+	let max_distance = (version.chars().count() / 3).max(2);
+
+	let mut prefix_matches = Vec::new();
+	let mut close_matches = Vec::new();
+	// The manifest is ordered from newest to oldest, and the sorts are stable, so newer versions come first
+	for entry in &manifest.versions {
+		let candidate = entry.id.as_str();
+		let distance = levenshtein(version, candidate);
+		if candidate.starts_with(version) || version.starts_with(candidate) {
+			prefix_matches.push((distance, candidate));
+		} else if distance <= max_distance {
+			close_matches.push((distance, candidate));
 		}
 	}
+	prefix_matches.sort_by_key(|x| x.0);
+	close_matches.sort_by_key(|x| x.0);
+
+	prefix_matches
+		.into_iter()
+		.chain(close_matches)
+		.take(MAX_VERSION_SUGGESTIONS)
+		.map(|x| x.1.to_string())
+		.collect()
 }
 
 impl Display for MinecraftVersion {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(
-			f,
-			"{}",
-			match self {
-				Self::Version(version) => version,
-				Self::Latest => "Latest",
-				Self::LatestSnapshot => "Latest Snaphot",
-			}
-		)
+		match self {
+			Self::Version(version) => write!(f, "{version}"),
+			Self::Latest => write!(f, "Latest"),
+			Self::LatestSnapshot => write!(f, "Latest Snaphot"),
+			Self::Wildcard(pattern, ..) => write!(f, "{pattern}"),
+			Self::Custom(custom) => write!(f, "Custom ({})", custom.json),
+		}
 	}
 }
 
@@ -112,5 +336,151 @@ mod tests {
 				.version,
 			MinecraftVersionDeser::Latest(MinecraftLatestVersion::Snapshot)
 		);
+
+		let custom = serde_json::from_str::<Test>(
+			r#"{"version": {"custom": {"json": "b1.7.3-fixed.json", "jar": "https://example.com/b1.7.3.jar"}}}"#,
+		)
+		.unwrap()
+		.version;
+		assert_eq!(
+			custom,
+			MinecraftVersionDeser::Custom {
+				custom: CustomVersion {
+					json: "b1.7.3-fixed.json".into(),
+					jar: Some("https://example.com/b1.7.3.jar".into()),
+				}
+			}
+		);
+		assert!(!custom.is_floating());
+		assert!(custom.to_mc_version().is_custom());
+		assert!(MinecraftVersionDeser::Version("1.20.*".into()).is_floating());
+		assert!(!MinecraftVersionDeser::Version("1.20.1".into()).is_floating());
+	}
+
+	fn fixture_manifest() -> VersionManifest {
+		let versions = [
+			("23w45a", "snapshot"),
+			("1.20.2", "release"),
+			("1.20.2-pre1", "snapshot"),
+			("1.20.1", "release"),
+			("1.20", "release"),
+			("1.19.4", "release"),
+		];
+		serde_json::from_value(serde_json::json!({
+			"latest": {"release": "1.20.2", "snapshot": "23w45a"},
+			"versions": versions.iter().map(|(id, ty)| serde_json::json!({
+				"id": id,
+				"type": ty,
+				"url": "",
+			})).collect::<Vec<_>>(),
+		}))
+		.unwrap()
+	}
+
+	#[test]
+	fn test_version_channels() {
+		let manifest = fixture_manifest();
+		let latest = MinecraftVersionDeser::Latest(MinecraftLatestVersion::Release);
+		let version = latest.to_mc_version_with_channel(VersionChannel::Release);
+		assert_eq!(&*version.get_version(&manifest).unwrap(), "1.20.2");
+		let version = latest.to_mc_version_with_channel(VersionChannel::Snapshot);
+		assert_eq!(&*version.get_version(&manifest).unwrap(), "23w45a");
+
+		let latest = MinecraftVersionDeser::Latest(MinecraftLatestVersion::Snapshot);
+		let version = latest.to_mc_version_with_channel(VersionChannel::Release);
+		assert_eq!(&*version.get_version(&manifest).unwrap(), "23w45a");
+	}
+
+	#[test]
+	fn test_version_wildcards() {
+		let manifest = fixture_manifest();
+		let get = |pattern: &str, channel| {
+			MinecraftVersionDeser::Version(pattern.into())
+				.to_mc_version_with_channel(channel)
+				.get_version(&manifest)
+				.ok()
+				.map(|x| x.to_string())
+		};
+		assert_eq!(
+			get("1.20.*", VersionChannel::Release).as_deref(),
+			Some("1.20.2")
+		);
+		assert_eq!(
+			get("1.19.*", VersionChannel::Release).as_deref(),
+			Some("1.19.4")
+		);
+		assert_eq!(get("1.18.*", VersionChannel::Release), None);
+		assert_eq!(get("*-pre*", VersionChannel::Release), None);
+		assert_eq!(
+			get("*-pre*", VersionChannel::Snapshot).as_deref(),
+			Some("1.20.2-pre1")
+		);
+
+		assert!(matches_wildcard("1.20.*", "1.20"));
+		assert!(matches_wildcard("1.20.*", "1.20.1"));
+		assert!(!matches_wildcard("1.20.*", "1.2"));
+		assert!(!matches_wildcard("1.20.*", "1.200"));
+		assert!(matches_wildcard("1.*.1", "1.20.1"));
+		assert!(!matches_wildcard("1.*.1", "1.20.2"));
+	}
+
+	#[test]
+	fn test_version_suggestions() {
+		let versions = [
+			"1.20.4", "1.20.3", "1.20.2", "23w31a", "1.20.1", "1.20", "1.19.4", "1.19.3", "1.19.2",
+			"1.19.1", "1.19", "1.18.2", "1.12.2", "1.2.5",
+		];
+		let manifest: VersionManifest = serde_json::from_value(serde_json::json!({
+			"latest": {"release": "1.20.4", "snapshot": "1.20.4"},
+			"versions": versions.iter().map(|id| serde_json::json!({
+				"id": id,
+				"url": "",
+			})).collect::<Vec<_>>(),
+		}))
+		.unwrap();
+		let suggest = |version| VersionNotFound::new(version, &manifest).suggestions;
+
+		assert_eq!(
+			suggest("1.20.11"),
+			vec!["1.20.1", "1.20", "1.20.4", "1.20.3", "1.20.2"]
+		);
+		assert_eq!(
+			suggest("1.19.5"),
+			vec!["1.19", "1.19.4", "1.19.3", "1.19.2", "1.19.1"]
+		);
+		assert_eq!(
+			suggest("1.12.3"),
+			vec!["1.19.3", "1.12.2", "1.20.3", "1.19.4", "1.19.2"]
+		);
+		assert_eq!(
+			suggest("1.2.6"),
+			vec!["1.2.5", "1.20.4", "1.20.3", "1.20.2", "1.20.1"]
+		);
+		assert_eq!(suggest("23w31b"), vec!["23w31a"]);
+		assert!(suggest("something else").is_empty());
+		assert!(suggest("").is_empty());
+
+		let error = VersionNotFound::new("23w31b", &manifest);
+		assert_eq!(
+			error.to_string(),
+			"Minecraft version '23w31b' does not exist or was not found in the manifest. Did you mean 23w31a?"
+		);
+	}
+
+	#[test]
+	fn test_snapshot_on_release_channel() {
+		let manifest = fixture_manifest();
+		let error = MinecraftVersion::Wildcard("*-pre*".into(), VersionChannel::Release)
+			.get_version(&manifest)
+			.unwrap_err();
+		let error = error.downcast_ref::<VersionNotFound>().unwrap();
+		assert!(error.snapshot_on_release_channel);
+		assert!(error.hint().unwrap().contains("version_channel"));
+
+		let error = MinecraftVersion::Wildcard("1.18.*".into(), VersionChannel::Release)
+			.get_version(&manifest)
+			.unwrap_err();
+		let error = error.downcast_ref::<VersionNotFound>().unwrap();
+		assert!(!error.snapshot_on_release_channel);
 	}
 }
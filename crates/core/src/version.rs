@@ -9,14 +9,15 @@ use mcvm_shared::versions::VersionInfo;
 
 use crate::config::BrandingProperties;
 use crate::instance::{Instance, InstanceConfiguration, InstanceParameters};
-use crate::io::files::paths::Paths;
+use crate::io::files::{self, paths::Paths};
 use crate::io::persistent::PersistentData;
 use crate::io::update::UpdateManager;
 use crate::net::game_files::client_meta::{self, ClientMeta};
 use crate::net::game_files::version_manifest::{self, VersionEntry, VersionManifestAndList};
 use crate::net::game_files::{assets, libraries};
+use crate::net::stats::{self, TransferPhase};
 use crate::user::UserManager;
-use crate::util::versions::VersionName;
+use crate::util::versions::{CustomVersion, VersionName};
 
 /// An installed version of the game. This cannot be constructed directly,
 /// only from the MCVMCore struct by using the `install_version()` method
@@ -51,7 +52,7 @@ impl<'inner, 'params> InstalledVersion<'inner, 'params> {
 		&mut self,
 		config: InstanceConfiguration,
 		o: &mut impl MCVMOutput,
-	) -> anyhow::Result<Instance> {
+	) -> anyhow::Result<Instance<'_>> {
 		let params = InstanceParameters {
 			version: &self.inner.version,
 			version_manifest: &self.inner.version_manifest,
@@ -85,7 +86,6 @@ impl<'inner, 'params> InstalledVersion<'inner, 'params> {
 			version: &self.inner.version,
 			paths: self.params.paths,
 			req_client: self.params.req_client,
-			version_manifest: &self.inner.version_manifest,
 			update_manager: self.params.update_manager,
 		};
 		self.inner.client_assets_and_libs.load(params, o).await
@@ -189,6 +189,46 @@ impl VersionRegistry {
 			.expect("Version should exist in map"))
 	}
 
+	/// Load a custom version from its version JSON. The JSON is loaded again every time, since it
+	/// can change without its ID changing. Returns the ID of the version
+	pub async fn get_custom_version(
+		&mut self,
+		custom: &CustomVersion,
+		params: LoadVersionParameters<'_>,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<VersionName> {
+		let vm_params = LoadVersionManifestParameters {
+			paths: params.paths,
+			req_client: params.req_client,
+			update_manager: params.update_manager,
+		};
+		self.load_version_manifest(vm_params, o)
+			.await
+			.context("Failed to get version manifest")?;
+		let version_manifest = self.version_manifest.get();
+
+		let (version, client_meta) = client_meta::get_custom(
+			custom,
+			&version_manifest.manifest,
+			params.paths,
+			params.update_manager,
+			params.req_client,
+			o,
+		)
+		.await
+		.context("Failed to load custom version")?;
+
+		let installed_version = InstalledVersionInner {
+			version: version.clone(),
+			version_manifest: version_manifest.clone(),
+			client_meta,
+			client_assets_and_libs: ClientAssetsAndLibraries::new(),
+		};
+		self.versions.insert(version.clone(), installed_version);
+
+		Ok(version)
+	}
+
 	/// Load the version manifest
 	pub async fn load_version_manifest(
 		&mut self,
@@ -216,11 +256,6 @@ impl VersionRegistry {
 		Ok(self.version_manifest.get())
 	}
 
-	/// Get the version manifest, panicking if it does not exist
-	pub fn get_version_manifest(&self) -> &Arc<VersionManifestAndList> {
-		self.version_manifest.get()
-	}
-
 	/// Add additional versions to the manifest. Must be called before the manifest is obtained.
 	pub fn add_additional_versions(&mut self, versions: Vec<VersionEntry>) {
 		self.additional_versions.extend(versions);
@@ -274,29 +309,30 @@ impl ClientAssetsAndLibraries {
 		if self.loaded {
 			return Ok(());
 		}
-		let result = assets::get(
+		let _lock = files::lock_shared_files().await;
+		let assets = assets::get(
 			params.client_meta,
 			params.paths,
-			params.version,
-			&params.version_manifest.list,
 			params.update_manager,
 			params.req_client,
 			o,
-		)
-		.await
-		.context("Failed to get game assets")?;
+		);
+		let result = stats::phase(TransferPhase::Assets, assets)
+			.await
+			.context("Failed to get game assets")?;
 		params.update_manager.add_result(result);
 
-		let result = libraries::get(
+		let libraries = libraries::get(
 			params.client_meta,
 			params.paths,
 			params.version,
 			params.update_manager,
 			params.req_client,
 			o,
-		)
-		.await
-		.context("Failed to get game libraries")?;
+		);
+		let result = stats::phase(TransferPhase::Libraries, libraries)
+			.await
+			.context("Failed to get game libraries")?;
 		params.update_manager.add_result(result);
 
 		self.loaded = true;
@@ -310,6 +346,5 @@ pub(crate) struct ClientAssetsAndLibsParameters<'a> {
 	pub version: &'a VersionName,
 	pub paths: &'a Paths,
 	pub req_client: &'a reqwest::Client,
-	pub version_manifest: &'a VersionManifestAndList,
 	pub update_manager: &'a mut UpdateManager,
 }
@@ -1,6 +1,7 @@
 use std::fmt::Display;
+use std::path::Path;
 
-use anyhow::{anyhow, Context};
+use anyhow::{bail, Context};
 use mcvm_core::io::java::classpath::Classpath;
 use mcvm_core::io::java::maven::MavenLibraryParts;
 use mcvm_core::io::json_from_file;
@@ -39,6 +40,65 @@ impl Display for Mode {
 	}
 }
 
+/// The maximum number of nearby versions to list when a loader version can't be found
+const MAX_NEARBY_VERSIONS: usize = 5;
+
+/// A requested version of the Fabric/Quilt loader
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum LoaderVersion {
+	/// The newest loader version
+	#[default]
+	Latest,
+	/// The newest loader version that starts with a prefix, written like `0.15.*`
+	Prefix(String),
+	/// A single loader version
+	Exact(String),
+}
+
+impl LoaderVersion {
+	/// Parse a loader version from a string
+	pub fn parse(text: &str) -> anyhow::Result<Self> {
+		if text == "latest" {
+			return Ok(Self::Latest);
+		}
+		if text.is_empty() || text.contains(char::is_whitespace) {
+			bail!("Invalid loader version '{text}'");
+		}
+
+		match text.strip_suffix(".*") {
+			Some(prefix) if !prefix.is_empty() && !prefix.contains('*') => {
+				Ok(Self::Prefix(prefix.into()))
+			}
+			Some(..) => bail!("Invalid loader version '{text}'"),
+			None if text.contains('*') => {
+				bail!("Invalid loader version '{text}'. Wildcards can only be used at the end, like '0.15.*'")
+			}
+			None => Ok(Self::Exact(text.into())),
+		}
+	}
+
+	/// Check if a loader version matches this request
+	pub fn matches(&self, version: &str) -> bool {
+		match self {
+			Self::Latest => true,
+			Self::Prefix(prefix) => version
+				.strip_prefix(prefix.as_str())
+				.is_some_and(|rest| rest.is_empty() || rest.starts_with('.')),
+			Self::Exact(exact) => version == exact,
+		}
+	}
+}
+
+impl Display for LoaderVersion {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Latest => write!(f, "latest"),
+			Self::Prefix(prefix) => write!(f, "{prefix}.*"),
+			Self::Exact(exact) => write!(f, "{exact}"),
+		}
+	}
+}
+
 /// Install Fabric/Quilt using the core and information about the version.
 /// First, create the core and the version you want. Then, get the version info from the version.
 /// Finally, run this function. Returns the classpath and main class to add to the instance you are launching
@@ -46,12 +106,14 @@ pub async fn install_from_core(
 	core: &mut MCVMCore,
 	version_info: &VersionInfo,
 	mode: Mode,
+	loader_version: &LoaderVersion,
 	side: Side,
 	o: &mut impl MCVMOutput,
 ) -> anyhow::Result<(Classpath, String)> {
 	let meta = get_meta(
 		&version_info.version,
 		&mode,
+		loader_version,
 		core.get_paths(),
 		core.get_update_manager(),
 		core.get_client(),
@@ -103,6 +165,17 @@ pub struct FabricQuiltMeta {
 	pub intermediary: MainLibrary,
 }
 
+impl FabricQuiltMeta {
+	/// Get the version of the loader, which is the last part of its Maven identifier
+	pub fn get_loader_version(&self) -> &str {
+		self.loader
+			.maven
+			.rsplit(':')
+			.next()
+			.unwrap_or(&self.loader.maven)
+	}
+}
+
 /// Metadata for the launcher
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct LauncherMeta {
@@ -167,14 +240,29 @@ impl MainClass {
 	}
 }
 
-/// Get the Fabric/Quilt metadata file
+/// Get the Fabric/Quilt metadata for the requested loader version
 pub async fn get_meta(
 	version: &str,
 	mode: &Mode,
+	loader_version: &LoaderVersion,
 	paths: &Paths,
 	manager: &UpdateManager,
 	client: &Client,
 ) -> anyhow::Result<FabricQuiltMeta> {
+	let list = get_meta_list(version, mode, paths, manager, client).await?;
+	let meta = select_meta(&list, loader_version, *mode)?;
+
+	Ok(meta.clone())
+}
+
+/// Get the Fabric/Quilt metadata for every loader version of a Minecraft version, from newest to oldest
+pub async fn get_meta_list(
+	version: &str,
+	mode: &Mode,
+	paths: &Paths,
+	manager: &UpdateManager,
+	client: &Client,
+) -> anyhow::Result<Vec<FabricQuiltMeta>> {
 	let meta_url = match mode {
 		Mode::Fabric => format!("https://meta.fabricmc.net/v2/versions/loader/{version}"),
 		Mode::Quilt => format!("https://meta.quiltmc.org/v3/versions/loader/{version}"),
@@ -202,11 +290,136 @@ pub async fn get_meta(
 		out
 	};
 
-	let meta = meta
-		.first()
-		.ok_or(anyhow!("Could not find a valid {mode} version"))?;
+	Ok(meta)
+}
 
-	Ok(meta.clone())
+/// Select the newest metadata from a list that matches a loader version request.
+/// Fails with the nearby versions if none of them match
+pub fn select_meta<'a>(
+	list: &'a [FabricQuiltMeta],
+	loader_version: &LoaderVersion,
+	mode: Mode,
+) -> anyhow::Result<&'a FabricQuiltMeta> {
+	if let Some(meta) = list
+		.iter()
+		.find(|x| loader_version.matches(x.get_loader_version()))
+	{
+		return Ok(meta);
+	}
+	if list.is_empty() {
+		bail!("Could not find a valid {mode} version");
+	}
+
+	let versions: Vec<_> = list.iter().map(|x| x.get_loader_version()).collect();
+	let requested = match loader_version {
+		LoaderVersion::Prefix(prefix) | LoaderVersion::Exact(prefix) => prefix.as_str(),
+		LoaderVersion::Latest => "",
+	};
+	bail!(
+		"{mode} loader version '{loader_version}' is not available for this Minecraft version. Nearby versions: {}",
+		get_nearby_versions(&versions, requested).join(", ")
+	);
+}
+
+/// A version of the Fabric installer
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct InstallerVersion {
+	/// The version number
+	pub version: String,
+	/// Whether this version is a stable release
+	#[serde(default)]
+	pub stable: bool,
+}
+
+/// Get every version of the Fabric installer, from newest to oldest
+pub async fn get_installer_versions(client: &Client) -> anyhow::Result<Vec<InstallerVersion>> {
+	download::json("https://meta.fabricmc.net/v2/versions/installer", client)
+		.await
+		.context("Failed to download Fabric installer versions")
+}
+
+/// Select the newest installer version that matches a request. The latest version is the newest
+/// stable one. Fails with the nearby versions if none of them match
+pub fn select_installer_version<'a>(
+	list: &'a [InstallerVersion],
+	installer_version: &LoaderVersion,
+) -> anyhow::Result<&'a str> {
+	let found = list.iter().find(|x| match installer_version {
+		LoaderVersion::Latest => x.stable,
+		_ => installer_version.matches(&x.version),
+	});
+	if let Some(version) = found {
+		return Ok(&version.version);
+	}
+	if list.is_empty() {
+		bail!("Could not find a valid Fabric installer version");
+	}
+
+	let versions: Vec<_> = list.iter().map(|x| x.version.as_str()).collect();
+	let requested = match installer_version {
+		LoaderVersion::Prefix(prefix) | LoaderVersion::Exact(prefix) => prefix.as_str(),
+		LoaderVersion::Latest => "",
+	};
+	bail!(
+		"Fabric installer version '{installer_version}' does not exist. Nearby versions: {}",
+		get_nearby_versions(&versions, requested).join(", ")
+	);
+}
+
+/// Get the file name of the standalone Fabric server launcher, which is named after the versions it uses
+pub fn get_server_launcher_file_name(
+	mc_version: &str,
+	loader_version: &str,
+	installer_version: &str,
+) -> String {
+	format!(
+		"fabric-server-mc.{mc_version}-loader.{loader_version}-launcher.{installer_version}.jar"
+	)
+}
+
+/// Check whether a file name is one of a standalone Fabric server launcher
+pub fn is_server_launcher_file_name(file_name: &str) -> bool {
+	file_name.starts_with("fabric-server-mc.") && file_name.ends_with(".jar")
+}
+
+/// Download the standalone Fabric server launcher built by an installer version. The launcher
+/// downloads the server and libraries itself, so that the server can be started with `java -jar`
+pub async fn download_server_launcher(
+	mc_version: &str,
+	loader_version: &str,
+	installer_version: &str,
+	path: &Path,
+	client: &Client,
+) -> anyhow::Result<()> {
+	let url = format!("https://meta.fabricmc.net/v2/versions/loader/{mc_version}/{loader_version}/{installer_version}/server/jar");
+	download::file(url, path, client)
+		.await
+		.context("Failed to download Fabric server launcher")
+}
+
+/// Get the versions that share the most leading components with a requested version,
+/// from a list of versions ordered from newest to oldest
+fn get_nearby_versions<'a>(versions: &[&'a str], requested: &str) -> Vec<&'a str> {
+	let requested: Vec<_> = requested.split('.').collect();
+	let shared_components = |version: &str| {
+		version
+			.split('.')
+			.zip(&requested)
+			.take_while(|(left, right)| left == *right)
+			.count()
+	};
+	let most_shared = versions
+		.iter()
+		.map(|x| shared_components(x))
+		.max()
+		.unwrap_or_default();
+
+	versions
+		.iter()
+		.filter(|x| shared_components(x) == most_shared)
+		.take(MAX_NEARBY_VERSIONS)
+		.copied()
+		.collect()
 }
 
 /// Download files for Quilt/Fabric that are common for both client and server
@@ -226,6 +439,8 @@ pub async fn download_files(
 		MessageLevel::Important,
 	);
 
+	// The libraries are shared with other versions that may be installed at the same time
+	let _lock = files::lock_shared_files().await;
 	let libs = meta.launcher_meta.libraries.common.clone();
 	let paths_clone = paths.clone();
 
@@ -290,6 +505,7 @@ pub async fn download_side_specific_files(
 		Side::Server => &meta.launcher_meta.libraries.server,
 	};
 
+	let _lock = files::lock_shared_files().await;
 	download_libraries(libs, paths, client, manager.force_reinstall()).await?;
 
 	Ok(())
@@ -415,3 +631,97 @@ pub fn get_classpath(
 
 	Ok(out)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn create_meta(version: &str) -> FabricQuiltMeta {
+		serde_json::from_value(serde_json::json!({
+			"launcherMeta": {
+				"libraries": { "common": [], "client": [], "server": [] },
+				"mainClass": "net.fabricmc.loader.launch.knot.KnotClient"
+			},
+			"loader": { "maven": format!("net.fabricmc:fabric-loader:{version}") },
+			"intermediary": { "maven": "net.fabricmc:intermediary:1.20.1" }
+		}))
+		.unwrap()
+	}
+
+	#[test]
+	fn test_loader_version_parse() {
+		assert_eq!(
+			LoaderVersion::parse("latest").unwrap(),
+			LoaderVersion::Latest
+		);
+		assert_eq!(
+			LoaderVersion::parse("0.15.*").unwrap(),
+			LoaderVersion::Prefix("0.15".into())
+		);
+		assert_eq!(
+			LoaderVersion::parse("0.15.11").unwrap(),
+			LoaderVersion::Exact("0.15.11".into())
+		);
+		assert!(LoaderVersion::parse("").is_err());
+		assert!(LoaderVersion::parse("0.*.1").is_err());
+		assert!(LoaderVersion::parse(".*").is_err());
+		assert!(LoaderVersion::parse("0.15 .1").is_err());
+
+		let prefix = LoaderVersion::Prefix("0.15".into());
+		assert!(prefix.matches("0.15.11"));
+		assert!(prefix.matches("0.15"));
+		assert!(!prefix.matches("0.150.1"));
+	}
+
+	#[test]
+	fn test_select_meta() {
+		let list: Vec<_> = ["0.16.0", "0.15.11", "0.15.10", "0.14.25"]
+			.into_iter()
+			.map(create_meta)
+			.collect();
+		let select = |version: &str| {
+			select_meta(&list, &LoaderVersion::parse(version).unwrap(), Mode::Fabric)
+				.map(|x| x.get_loader_version().to_string())
+		};
+
+		assert_eq!(select("latest").unwrap(), "0.16.0");
+		assert_eq!(select("0.15.*").unwrap(), "0.15.11");
+		assert_eq!(select("0.15.10").unwrap(), "0.15.10");
+
+		let err = select("0.15.12").unwrap_err().to_string();
+		assert!(err.ends_with("Nearby versions: 0.15.11, 0.15.10"));
+		let err = select("1.*").unwrap_err().to_string();
+		assert!(err.ends_with("Nearby versions: 0.16.0, 0.15.11, 0.15.10, 0.14.25"));
+	}
+
+	#[test]
+	fn test_select_installer_version() {
+		let list: Vec<_> = [("1.1.0", false), ("1.0.1", true), ("1.0.0", true)]
+			.into_iter()
+			.map(|(version, stable)| InstallerVersion {
+				version: version.into(),
+				stable,
+			})
+			.collect();
+		let select = |version: &str| {
+			select_installer_version(&list, &LoaderVersion::parse(version).unwrap())
+				.map(String::from)
+		};
+
+		// Unstable versions are only used when they are asked for
+		assert_eq!(select("latest").unwrap(), "1.0.1");
+		assert_eq!(select("1.1.*").unwrap(), "1.1.0");
+		assert_eq!(select("1.0.0").unwrap(), "1.0.0");
+
+		let err = select("1.0.2").unwrap_err().to_string();
+		assert!(err.ends_with("Nearby versions: 1.0.1, 1.0.0"));
+		assert_eq!(
+			get_server_launcher_file_name("1.20.1", "0.15.11", "1.0.1"),
+			"fabric-server-mc.1.20.1-loader.0.15.11-launcher.1.0.1.jar"
+		);
+		assert!(is_server_launcher_file_name(
+			"fabric-server-mc.1.20.1-loader.0.15.11-launcher.1.0.1.jar"
+		));
+		assert!(!is_server_launcher_file_name("server.jar"));
+	}
+}
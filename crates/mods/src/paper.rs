@@ -26,7 +26,8 @@ pub enum Mode {
 }
 
 impl Mode {
-	fn to_str(self) -> &'static str {
+	/// Get the ID of the project in the PaperMC API
+	pub fn to_str(self) -> &'static str {
 		match self {
 			Self::Paper => "paper",
 			Self::Folia => "folia",
@@ -80,7 +81,7 @@ pub async fn install_from_core(
 	.context(format!("Failed to download {mode} JAR file"))?;
 
 	Ok((
-		get_local_jar_path(mode, &version_info.version, core.get_paths()),
+		get_local_jar_path(mode, &version_info.version, build_num, core.get_paths()),
 		PAPER_SERVER_MAIN_CLASS.into(),
 	))
 }
@@ -109,7 +110,7 @@ pub async fn install_velocity(paths: &Paths, client: &Client) -> anyhow::Result<
 	.context("Failed to download Velocity JAR")?;
 
 	Ok((
-		get_local_jar_path(Mode::Velocity, &version, paths),
+		get_local_jar_path(Mode::Velocity, &version, build_num, paths),
 		VELOCITY_MAIN_CLASS.into(),
 	))
 }
@@ -200,15 +201,55 @@ pub async fn download_server_jar(
 	let num_str = build_num.to_string();
 	let url = format!("https://api.papermc.io/v2/projects/{}/versions/{version}/builds/{num_str}/downloads/{file_name}", mode.to_str());
 
-	let file_path = get_local_jar_path(mode, version, paths);
+	let file_path = get_local_jar_path(mode, version, build_num, paths);
 	download::file(&url, &file_path, client)
 		.await
-		.context("Failed to download {mode} JAR")?;
+		.with_context(|| format!("Failed to download {mode} JAR"))?;
 
 	Ok(())
 }
 
-/// Get the path to the stored JAR file
-pub fn get_local_jar_path(mode: Mode, version: &str, paths: &Paths) -> PathBuf {
-	mcvm_core::io::minecraft::game_jar::get_path(Side::Server, version, Some(mode.to_str()), paths)
+/// Get the path to the stored JAR file. Every build is stored separately so that
+/// instances on the same version and build can share one file
+pub fn get_local_jar_path(mode: Mode, version: &str, build_num: u16, paths: &Paths) -> PathBuf {
+	let specifier = format!("{}-{build_num}", mode.to_str());
+	mcvm_core::io::minecraft::game_jar::get_path(Side::Server, version, Some(&specifier), paths)
+}
+
+/// Parse the name of a stored JAR file into its mode, Minecraft version, and build number.
+/// Files stored by older versions don't have a build number
+pub fn parse_local_jar_name(file_name: &str) -> Option<(Mode, &str, Option<u16>)> {
+	let name = file_name.strip_suffix(".jar")?;
+	let (version, specifier) = name.rsplit_once("_server_")?;
+	let (project, build_num) = match specifier.split_once('-') {
+		Some((project, build_num)) => (project, Some(build_num.parse().ok()?)),
+		None => (specifier, None),
+	};
+	let mode = [Mode::Paper, Mode::Folia, Mode::Velocity]
+		.into_iter()
+		.find(|x| x.to_str() == project)?;
+
+	Some((mode, version, build_num))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_local_jar_name() {
+		let paths = Paths::new_no_create().unwrap();
+		let path = get_local_jar_path(Mode::Folia, "1.20.4", 12, &paths);
+		let file_name = path.file_name().unwrap().to_str().unwrap();
+		let (mode, version, build_num) = parse_local_jar_name(file_name).unwrap();
+		assert!(matches!(mode, Mode::Folia));
+		assert_eq!(version, "1.20.4");
+		assert_eq!(build_num, Some(12));
+
+		let (_, version, build_num) = parse_local_jar_name("1.19_server_paper.jar").unwrap();
+		assert_eq!(version, "1.19");
+		assert_eq!(build_num, None);
+		assert!(parse_local_jar_name("1.19_server.jar").is_none());
+		assert!(parse_local_jar_name("1.19_server_spongevanilla.jar").is_none());
+	}
 }
@@ -77,8 +77,11 @@ pub async fn get_newest_version(
 }
 
 /// Information about a version of a Sponge project, from their API
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Version {
+	/// The version string of the Sponge build
+	#[serde(default)]
+	pub version: String,
 	artifacts: HashMap<String, Artifact>,
 }
 
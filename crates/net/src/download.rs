@@ -1,12 +1,17 @@
 use std::fs::File;
+use std::future::Future;
 use std::io::{BufWriter, Cursor, Write};
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{ensure, Context};
 use mcvm_shared::output::MessageContents;
 use reqwest::{IntoUrl, Url};
 use serde::de::DeserializeOwned;
 
+use crate::stats;
+
 /// Re-export of reqwest::Client for users of this download module
 pub use reqwest::Client;
 
@@ -17,15 +22,171 @@ const FD_SENSIBLE_LIMIT: usize = 128;
 #[cfg(not(target_os = "windows"))]
 const FD_SENSIBLE_LIMIT: usize = 128;
 
-/// Get the sensible limit for asynchronous transfers
+tokio::task_local! {
+	/// The download limits of the current task
+	static LIMITS: DownloadLimits;
+}
+
+/// Limits for downloads, which apply to the transfers made while running in [with_limits]
+#[derive(Debug, Clone, Default)]
+pub struct DownloadLimits {
+	/// The limit for concurrent transfers
+	transfer_limit: Option<usize>,
+	/// The download rate limit in bytes per second
+	rate_limit: Option<u64>,
+	/// The token bucket shared by all downloads so that the rate limit applies to their total
+	rate_limiter: Arc<Mutex<Option<TokenBucket>>>,
+}
+
+impl DownloadLimits {
+	/// Create new DownloadLimits. None will use the default concurrency limit and no rate limit
+	pub fn new(transfer_limit: Option<usize>, rate_limit: Option<u64>) -> Self {
+		Self {
+			transfer_limit: transfer_limit.filter(|x| *x > 0),
+			rate_limit: rate_limit.filter(|x| *x > 0),
+			rate_limiter: Arc::new(Mutex::new(None)),
+		}
+	}
+
+	/// Get the limit for concurrent asynchronous transfers. The MCVM_DOWNLOAD_CONCURRENCY
+	/// environment variable takes precedence over the configured limit
+	pub fn get_transfer_limit(&self) -> usize {
+		if let Some(limit) = get_env_limit("MCVM_DOWNLOAD_CONCURRENCY")
+			.or_else(|| get_env_limit("MCVM_TRANSFER_LIMIT"))
+		{
+			return limit as usize;
+		}
+		self.transfer_limit.unwrap_or(FD_SENSIBLE_LIMIT)
+	}
+
+	/// Get the download rate limit in bytes per second, if there is one. The MCVM_DOWNLOAD_RATE_LIMIT
+	/// environment variable takes precedence over the configured limit
+	pub fn get_rate_limit(&self) -> Option<u64> {
+		get_env_limit("MCVM_DOWNLOAD_RATE_LIMIT").or(self.rate_limit)
+	}
+
+	/// Wait until an amount of bytes is allowed by the shared rate limiter
+	async fn throttle(&self, amount: usize, rate: u64) {
+		let delay = {
+			let mut limiter = self.rate_limiter.lock().unwrap_or_else(|e| e.into_inner());
+			let now = Instant::now();
+			let bucket = limiter.get_or_insert_with(|| TokenBucket::new(rate, now));
+			if bucket.get_rate() != rate {
+				bucket.set_rate(rate);
+			}
+			bucket.take(amount as u64, now)
+		};
+		if !delay.is_zero() {
+			tokio::time::sleep(delay).await;
+		}
+	}
+}
+
+/// Run a future with download limits applied to all of the transfers it makes
+pub async fn with_limits<F: Future>(limits: DownloadLimits, fut: F) -> F::Output {
+	LIMITS.scope(limits, fut).await
+}
+
+/// Make a future that will be spawned as its own task use the same download limits
+/// as the current task
+pub fn inherit_limits<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+	let limits = current_limits();
+	LIMITS.scope(limits, fut)
+}
+
+/// Get the download limits of the current task, or the default ones if there are none
+fn current_limits() -> DownloadLimits {
+	LIMITS.try_with(|x| x.clone()).unwrap_or_default()
+}
+
+/// Get the limit for concurrent asynchronous transfers in the current task
 pub fn get_transfer_limit() -> usize {
-	if let Ok(env) = std::env::var("MCVM_TRANSFER_LIMIT") {
-		env.parse().unwrap_or_default()
-	} else {
-		FD_SENSIBLE_LIMIT
+	current_limits().get_transfer_limit()
+}
+
+/// Get the download rate limit in bytes per second in the current task, if there is one
+pub fn get_rate_limit() -> Option<u64> {
+	current_limits().get_rate_limit()
+}
+
+/// Read a positive limit from an environment variable
+fn get_env_limit(var: &str) -> Option<u64> {
+	std::env::var(var)
+		.ok()
+		.and_then(|x| x.parse().ok())
+		.filter(|x| *x > 0)
+}
+
+/// Token bucket that paces transfers to a rate in bytes per second.
+/// Bursts of up to a second's worth of bytes are allowed, and transfers larger than
+/// the available tokens borrow against future ones
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+	rate: u64,
+	/// The number of available tokens, which is negative when the bucket is in debt
+	tokens: f64,
+	last_update: Instant,
+}
+
+impl TokenBucket {
+	/// Create a new full TokenBucket with a rate in bytes per second
+	pub fn new(rate: u64, now: Instant) -> Self {
+		let rate = rate.max(1);
+		Self {
+			rate,
+			tokens: rate as f64,
+			last_update: now,
+		}
+	}
+
+	/// Get the rate of the bucket in bytes per second
+	pub fn get_rate(&self) -> u64 {
+		self.rate
+	}
+
+	/// Change the rate of the bucket
+	pub fn set_rate(&mut self, rate: u64) {
+		self.rate = rate.max(1);
+	}
+
+	/// Take tokens for an amount of bytes, returning how long to wait before transferring them
+	pub fn take(&mut self, amount: u64, now: Instant) -> Duration {
+		let elapsed = now.saturating_duration_since(self.last_update);
+		self.last_update = self.last_update.max(now);
+
+		let rate = self.rate as f64;
+		self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(rate);
+		self.tokens -= amount as f64;
+		if self.tokens >= 0.0 {
+			Duration::ZERO
+		} else {
+			Duration::from_secs_f64(-self.tokens / rate)
+		}
 	}
 }
 
+/// Read the body of a response chunk by chunk, respecting the download rate limit
+async fn read_body(mut response: reqwest::Response) -> anyhow::Result<bytes::Bytes> {
+	let limits = current_limits();
+	let Some(rate) = limits.get_rate_limit() else {
+		let bytes = response
+			.bytes()
+			.await
+			.context("Failed to convert download to raw bytes")?;
+		stats::record_bytes(bytes.len() as u64);
+		return Ok(bytes);
+	};
+
+	let mut out = Vec::with_capacity(response.content_length().unwrap_or_default() as usize);
+	while let Some(chunk) = response.chunk().await.context("Failed to download chunk")? {
+		limits.throttle(chunk.len(), rate).await;
+		stats::record_bytes(chunk.len() as u64);
+		out.extend_from_slice(&chunk);
+	}
+
+	Ok(out.into())
+}
+
 /// The User-Agent header for requests
 fn user_agent() -> String {
 	let version = env!("CARGO_PKG_VERSION");
@@ -46,28 +207,44 @@ pub async fn download(url: impl IntoUrl, client: &Client) -> anyhow::Result<reqw
 	Ok(resp)
 }
 
+/// Downloads data from a remote location, asking the server to respond with 304 Not Modified
+/// instead if the data still matches the ETag or modification date from an earlier response
+pub async fn conditional(
+	url: impl IntoUrl,
+	client: &Client,
+	etag: Option<&str>,
+	last_modified: Option<&str>,
+) -> anyhow::Result<reqwest::Response> {
+	let mut request = client.get(url).header("User-Agent", user_agent());
+	if let Some(etag) = etag {
+		request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+	}
+	if let Some(last_modified) = last_modified {
+		request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+	}
+	let resp = request
+		.send()
+		.await
+		.context("Failed to send request")?
+		.error_for_status()
+		.context("Server reported an error")?;
+
+	Ok(resp)
+}
+
 /// Downloads and returns text
 pub async fn text(url: impl IntoUrl, client: &Client) -> anyhow::Result<String> {
-	let text = download(url, client)
-		.await
-		.context("Failed to download")?
-		.text()
-		.await
-		.context("Failed to convert download to text")?;
+	let bytes = bytes(url, client).await?;
+	let text = String::from_utf8(bytes.into()).context("Failed to convert download to text")?;
 
 	Ok(text)
 }
 
 /// Downloads and returns bytes
 pub async fn bytes(url: impl IntoUrl, client: &Client) -> anyhow::Result<bytes::Bytes> {
-	let bytes = download(url, client)
-		.await
-		.context("Failed to download")?
-		.bytes()
-		.await
-		.context("Failed to convert download to raw bytes")?;
+	let response = download(url, client).await.context("Failed to download")?;
 
-	Ok(bytes)
+	read_body(response).await
 }
 
 /// Downloads and puts the contents in a file
@@ -85,18 +262,17 @@ pub async fn file(
 			path.as_ref().display()
 		)
 	})?;
+	stats::record_file();
 
 	Ok(())
 }
 
 /// Downloads and deserializes the contents into JSON
 pub async fn json<T: DeserializeOwned>(url: impl IntoUrl, client: &Client) -> anyhow::Result<T> {
-	download(url, client)
-		.await
-		.context("Failed to download JSON data")?
-		.json()
+	let bytes = bytes(url, client)
 		.await
-		.context("Failed to parse JSON")
+		.context("Failed to download JSON data")?;
+	serde_json::from_slice(&bytes).context("Failed to parse JSON")
 }
 
 /// A persistent single download that can be used to track progress
@@ -145,10 +321,15 @@ impl<W: Write> ProgressiveDownload<W> {
 			.await
 			.context("Failed to download chunk")?;
 		if let Some(bytes) = chunk {
+			let limits = current_limits();
+			if let Some(rate) = limits.get_rate_limit() {
+				limits.throttle(bytes.len(), rate).await;
+			}
 			self.writer
 				.write_all(&bytes)
 				.context("Failed to write downloaded bytes")?;
 			self.bytes_downloaded += bytes.len();
+			stats::record_bytes(bytes.len() as u64);
 		} else {
 			self.finished = true;
 			// Ensure that we downloaded the correct amount
@@ -174,10 +355,11 @@ impl ProgressiveDownload<BufWriter<File>> {
 		path: impl AsRef<Path>,
 		client: &Client,
 	) -> anyhow::Result<Self> {
-		let file = BufWriter::new(File::create(path).context("Failed to open file")?);
 		let response = download(url, client)
 			.await
 			.context("Failed to get response")?;
+		let file = BufWriter::new(File::create(path).context("Failed to open file")?);
+		stats::record_file();
 
 		Ok(Self::from_response(response, file))
 	}
@@ -215,3 +397,134 @@ pub fn validate_url(url: &str) -> anyhow::Result<()> {
 
 	Ok(())
 }
+
+/// Checks if a source that can be either a local path or a URL is a URL
+pub fn is_url(source: &str) -> bool {
+	source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Settings for sending requests through an HTTP proxy
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxySettings {
+	/// The URL of the proxy server
+	pub url: String,
+	/// The username to authenticate with the proxy using
+	pub username: Option<String>,
+	/// The password to authenticate with the proxy using
+	pub password: Option<String>,
+	/// Hosts that should be connected to directly instead of through the proxy
+	pub no_proxy: Vec<String>,
+}
+
+/// Timeouts for requests, so that a stuck connection can't hang forever
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeoutSettings {
+	/// How long to wait for a connection to a server
+	pub connect: Duration,
+	/// How long to wait for a whole request, including downloading the response
+	pub total: Duration,
+}
+
+impl TimeoutSettings {
+	/// The default connection timeout
+	pub const DEFAULT_CONNECT: Duration = Duration::from_secs(30);
+	/// The default total request timeout
+	pub const DEFAULT_TOTAL: Duration = Duration::from_secs(600);
+}
+
+impl Default for TimeoutSettings {
+	fn default() -> Self {
+		Self {
+			connect: Self::DEFAULT_CONNECT,
+			total: Self::DEFAULT_TOTAL,
+		}
+	}
+}
+
+/// Create the client that should be used for all requests. If no proxy settings are given,
+/// the standard HTTP_PROXY, HTTPS_PROXY, and NO_PROXY environment variables are respected
+pub fn create_client(
+	proxy: Option<&ProxySettings>,
+	timeouts: TimeoutSettings,
+) -> anyhow::Result<Client> {
+	let mut builder = Client::builder()
+		.connect_timeout(timeouts.connect)
+		.timeout(timeouts.total);
+	if let Some(settings) = proxy {
+		let mut proxy = reqwest::Proxy::all(&settings.url)
+			.with_context(|| format!("Invalid proxy URL '{}'", settings.url))?;
+		if let Some(username) = &settings.username {
+			proxy = proxy.basic_auth(username, settings.password.as_deref().unwrap_or_default());
+		}
+		if !settings.no_proxy.is_empty() {
+			proxy = proxy.no_proxy(reqwest::NoProxy::from_string(&settings.no_proxy.join(",")));
+		}
+		builder = builder.proxy(proxy);
+	}
+
+	builder.build().context("Failed to create HTTP client")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_token_bucket_pacing() {
+		let start = Instant::now();
+		let mut bucket = TokenBucket::new(1000, start);
+
+		// A full bucket allows a second's worth of bytes immediately
+		assert_eq!(bucket.take(1000, start), Duration::ZERO);
+		// Going into debt waits until the debt is paid off
+		assert_eq!(bucket.take(500, start), Duration::from_millis(500));
+		assert_eq!(bucket.take(500, start), Duration::from_secs(1));
+		// Time passing pays off the debt
+		let now = start + Duration::from_secs(1);
+		assert_eq!(bucket.take(0, now), Duration::ZERO);
+		assert_eq!(bucket.take(250, now), Duration::from_millis(250));
+
+		// Idle time only refills up to the burst size
+		let now = now + Duration::from_secs(60);
+		assert_eq!(bucket.take(1000, now), Duration::ZERO);
+		assert_eq!(bucket.take(2000, now), Duration::from_secs(2));
+
+		// Times from before the last update don't refill the bucket
+		assert_eq!(bucket.take(0, start), Duration::from_secs(2));
+	}
+
+	#[test]
+	fn test_token_bucket_rate_change() {
+		let start = Instant::now();
+		let mut bucket = TokenBucket::new(100, start);
+		assert_eq!(bucket.take(100, start), Duration::ZERO);
+		bucket.set_rate(400);
+		assert_eq!(bucket.take(100, start), Duration::from_millis(250));
+		assert_eq!(
+			bucket.take(0, start + Duration::from_millis(250)),
+			Duration::ZERO
+		);
+	}
+
+	#[tokio::test]
+	async fn test_scoped_limits() {
+		assert_eq!(get_rate_limit(), None);
+
+		let limits = DownloadLimits::new(Some(4), Some(1000));
+		with_limits(limits, async {
+			assert_eq!(get_transfer_limit(), 4);
+			assert_eq!(get_rate_limit(), Some(1000));
+
+			// Spawned tasks only have the limits if they inherit them
+			let inherited = tokio::spawn(inherit_limits(async { get_rate_limit() }));
+			assert_eq!(inherited.await.unwrap(), Some(1000));
+			let detached = tokio::spawn(async { get_rate_limit() });
+			assert_eq!(detached.await.unwrap(), None);
+		})
+		.await;
+
+		// The limits don't leak out of the scope
+		assert_eq!(get_transfer_limit(), FD_SENSIBLE_LIMIT);
+		assert_eq!(get_rate_limit(), None);
+	}
+}
@@ -7,3 +7,5 @@ pub mod download;
 pub mod modrinth;
 /// Interacting with the Smithed API
 pub mod smithed;
+/// Statistics about the transfers made during an update
+pub mod stats;
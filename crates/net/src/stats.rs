@@ -0,0 +1,376 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+tokio::task_local! {
+	/// The statistics that the current task records into, along with the phase it is in
+	static CURRENT: (Arc<TransferStats>, TransferPhase);
+}
+
+/// A part of an update that statistics are recorded for separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferPhase {
+	/// The version manifest and client metadata
+	Manifest,
+	/// Java installations
+	Java,
+	/// Game assets
+	Assets,
+	/// Game libraries
+	Libraries,
+	/// Modloaders and server JARs
+	Loaders,
+	/// Packages and their addons
+	Packages,
+	/// Everything that isn't part of another phase, like the game JAR
+	Other,
+}
+
+impl TransferPhase {
+	/// All of the phases, in the order they are shown
+	pub const ALL: [Self; 7] = [
+		Self::Manifest,
+		Self::Java,
+		Self::Assets,
+		Self::Libraries,
+		Self::Loaders,
+		Self::Packages,
+		Self::Other,
+	];
+
+	/// Get the phase as a string
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Manifest => "manifest",
+			Self::Java => "java",
+			Self::Assets => "assets",
+			Self::Libraries => "libraries",
+			Self::Loaders => "loaders",
+			Self::Packages => "packages",
+			Self::Other => "other",
+		}
+	}
+
+	fn index(&self) -> usize {
+		*self as usize
+	}
+}
+
+/// Counters for one phase. Downloads run concurrently, so these are all atomics
+#[derive(Debug, Default)]
+struct PhaseCounters {
+	bytes: AtomicU64,
+	files: AtomicU64,
+	cache_hits: AtomicU64,
+	cache_misses: AtomicU64,
+	micros: AtomicU64,
+}
+
+/// Statistics about the transfers of an update, which are shared by all of its tasks
+#[derive(Debug)]
+pub struct TransferStats {
+	phases: [PhaseCounters; TransferPhase::ALL.len()],
+	start: Instant,
+}
+
+impl Default for TransferStats {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl TransferStats {
+	/// Create new empty statistics. The total time is measured from now
+	pub fn new() -> Self {
+		Self {
+			phases: Default::default(),
+			start: Instant::now(),
+		}
+	}
+
+	fn phase(&self, phase: TransferPhase) -> &PhaseCounters {
+		&self.phases[phase.index()]
+	}
+
+	/// Record downloaded bytes
+	pub fn add_bytes(&self, phase: TransferPhase, bytes: u64) {
+		self.phase(phase).bytes.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	/// Record a file that was written
+	pub fn add_file(&self, phase: TransferPhase) {
+		self.phase(phase).files.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Record a file that was already present and didn't need to be downloaded
+	pub fn add_cache_hit(&self, phase: TransferPhase) {
+		self.phase(phase).cache_hits.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Record a file that had to be downloaded
+	pub fn add_cache_miss(&self, phase: TransferPhase) {
+		self.phase(phase)
+			.cache_misses
+			.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Record time spent in a phase
+	pub fn add_time(&self, phase: TransferPhase, time: Duration) {
+		self.phase(phase)
+			.micros
+			.fetch_add(time.as_micros() as u64, Ordering::Relaxed);
+	}
+
+	/// Get a summary of the statistics so far
+	pub fn summary(&self) -> TransferSummary {
+		let mut out = TransferSummary {
+			wall_time_ms: self.start.elapsed().as_millis() as u64,
+			..Default::default()
+		};
+		for phase in TransferPhase::ALL {
+			let counters = self.phase(phase);
+			let summary = PhaseSummary {
+				phase,
+				bytes_downloaded: counters.bytes.load(Ordering::Relaxed),
+				files_written: counters.files.load(Ordering::Relaxed),
+				cache_hits: counters.cache_hits.load(Ordering::Relaxed),
+				cache_misses: counters.cache_misses.load(Ordering::Relaxed),
+				wall_time_ms: counters.micros.load(Ordering::Relaxed) / 1000,
+			};
+			out.bytes_downloaded += summary.bytes_downloaded;
+			out.files_written += summary.files_written;
+			out.cache_hits += summary.cache_hits;
+			out.cache_misses += summary.cache_misses;
+			out.phases.push(summary);
+		}
+
+		out
+	}
+}
+
+/// A summary of [TransferStats]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TransferSummary {
+	/// The total number of bytes downloaded
+	pub bytes_downloaded: u64,
+	/// The total number of files written
+	pub files_written: u64,
+	/// The total number of files that were already present
+	pub cache_hits: u64,
+	/// The total number of files that had to be downloaded
+	pub cache_misses: u64,
+	/// How long the whole update took, in milliseconds
+	pub wall_time_ms: u64,
+	/// The statistics for each phase
+	pub phases: Vec<PhaseSummary>,
+}
+
+impl std::fmt::Display for TransferSummary {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"Downloaded {:.1} MiB and wrote {} files in {:.1}s. {} files were already present and {} had to be downloaded.",
+			self.bytes_downloaded as f64 / 1024.0 / 1024.0,
+			self.files_written,
+			self.wall_time_ms as f64 / 1000.0,
+			self.cache_hits,
+			self.cache_misses,
+		)?;
+		let mut phases = self.phases.iter().filter(|x| x.wall_time_ms > 0).peekable();
+		if phases.peek().is_some() {
+			write!(f, " Time per phase:")?;
+			for (i, phase) in phases.enumerate() {
+				let separator = if i == 0 { "" } else { "," };
+				write!(
+					f,
+					"{separator} {} {:.1}s",
+					phase.phase.as_str(),
+					phase.wall_time_ms as f64 / 1000.0
+				)?;
+			}
+			write!(f, ".")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// The statistics for one phase in a [TransferSummary]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PhaseSummary {
+	/// The phase
+	pub phase: TransferPhase,
+	/// The number of bytes downloaded
+	pub bytes_downloaded: u64,
+	/// The number of files written
+	pub files_written: u64,
+	/// The number of files that were already present
+	pub cache_hits: u64,
+	/// The number of files that had to be downloaded
+	pub cache_misses: u64,
+	/// The time spent in the phase in milliseconds. Phases that run at the same time
+	/// for different instances have their times added together
+	pub wall_time_ms: u64,
+}
+
+impl PhaseSummary {
+	/// Checks whether nothing happened in this phase
+	pub fn is_empty(&self) -> bool {
+		self.bytes_downloaded == 0
+			&& self.files_written == 0
+			&& self.cache_hits == 0
+			&& self.cache_misses == 0
+			&& self.wall_time_ms == 0
+	}
+}
+
+/// Record the transfers made by a future into statistics. Tasks that the future spawns
+/// only record into them if they are wrapped with [inherit]
+pub async fn track<F: Future>(stats: Arc<TransferStats>, future: F) -> F::Output {
+	CURRENT.scope((stats, TransferPhase::Other), future).await
+}
+
+/// Run a future as a phase of the tracked transfers, measuring the time it takes.
+/// Does nothing special if transfers aren't being tracked
+pub async fn phase<F: Future>(phase: TransferPhase, future: F) -> F::Output {
+	let Some((stats, _)) = current() else {
+		return future.await;
+	};
+	let start = Instant::now();
+	let out = CURRENT.scope((stats.clone(), phase), future).await;
+	stats.add_time(phase, start.elapsed());
+	out
+}
+
+/// Wrap a future that will be spawned as a new task so that it records into the
+/// same statistics and phase as the current task
+pub fn inherit<F: Future>(future: F) -> impl Future<Output = F::Output> {
+	let current = current();
+	async move {
+		match current {
+			Some(current) => CURRENT.scope(current, future).await,
+			None => future.await,
+		}
+	}
+}
+
+fn current() -> Option<(Arc<TransferStats>, TransferPhase)> {
+	CURRENT.try_with(|x| x.clone()).ok()
+}
+
+fn with_current(f: impl FnOnce(&TransferStats, TransferPhase)) {
+	let _ = CURRENT.try_with(|(stats, phase)| f(stats, *phase));
+}
+
+/// Record downloaded bytes in the current task
+pub fn record_bytes(bytes: u64) {
+	with_current(|stats, phase| stats.add_bytes(phase, bytes));
+}
+
+/// Record a written file in the current task
+pub fn record_file() {
+	with_current(|stats, phase| stats.add_file(phase));
+}
+
+/// Record a file that was already present in the current task
+pub fn record_cache_hit() {
+	with_current(|stats, phase| stats.add_cache_hit(phase));
+}
+
+/// Record a file that had to be downloaded in the current task
+pub fn record_cache_miss() {
+	with_current(|stats, phase| stats.add_cache_miss(phase));
+}
+
+#[cfg(test)]
+mod tests {
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+	use tokio::net::TcpListener;
+	use tokio::task::JoinSet;
+
+	use super::*;
+	use crate::download;
+
+	/// Serve a fixed body for every request until the listener is dropped
+	async fn serve_fixture(body: &'static [u8]) -> String {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		tokio::spawn(async move {
+			loop {
+				let Ok((mut stream, _)) = listener.accept().await else {
+					return;
+				};
+				tokio::spawn(async move {
+					// Requests from the client are small and have no body
+					let mut buf = [0; 4096];
+					let _ = stream.read(&mut buf).await;
+					let header = format!(
+						"HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+						body.len()
+					);
+					let _ = stream.write_all(header.as_bytes()).await;
+					let _ = stream.write_all(body).await;
+				});
+			}
+		});
+		format!("http://{addr}")
+	}
+
+	#[tokio::test]
+	async fn test_synthetic_update() {
+		const BODY: &[u8] = b"{\"id\": \"fixture\"}";
+		let url = serve_fixture(BODY).await;
+		let client = download::Client::new();
+		let dir = tempfile::tempdir().unwrap();
+
+		let stats = Arc::new(TransferStats::new());
+		track(stats.clone(), async {
+			phase(TransferPhase::Manifest, async {
+				let _: serde_json::Value = download::json(&url, &client).await.unwrap();
+				record_cache_miss();
+			})
+			.await;
+
+			// Concurrent downloads in spawned tasks
+			phase(TransferPhase::Assets, async {
+				let mut join = JoinSet::new();
+				for i in 0..16 {
+					let url = format!("{url}/{i}");
+					let path = dir.path().join(i.to_string());
+					let client = client.clone();
+					join.spawn(inherit(async move {
+						download::file(url, path, &client).await.unwrap();
+					}));
+				}
+				while let Some(result) = join.join_next().await {
+					result.unwrap();
+				}
+				record_cache_hit();
+			})
+			.await;
+		})
+		.await;
+		// Transfers outside of the tracked future aren't recorded
+		download::bytes(&url, &client).await.unwrap();
+
+		let summary = stats.summary();
+		let len = BODY.len() as u64;
+		assert_eq!(summary.bytes_downloaded, len * 17);
+		assert_eq!(summary.files_written, 16);
+		assert_eq!(summary.cache_hits, 1);
+		assert_eq!(summary.cache_misses, 1);
+
+		let manifest = &summary.phases[TransferPhase::Manifest.index()];
+		assert_eq!(manifest.bytes_downloaded, len);
+		assert_eq!(manifest.files_written, 0);
+		let assets = &summary.phases[TransferPhase::Assets.index()];
+		assert_eq!(assets.bytes_downloaded, len * 16);
+		assert_eq!(assets.files_written, 16);
+		assert!(summary.phases[TransferPhase::Java.index()].is_empty());
+		assert!(summary.phases[TransferPhase::Other.index()].is_empty());
+	}
+}
@@ -12,6 +12,7 @@ use mcvm_shared::util::ToInt;
 
 use mcvm_shared::versions::{VersionInfo, VersionPattern};
 
+use super::keybinds::resolve_keybind;
 use super::{ClientOptions, CloudRenderMode, FullscreenResolution, GraphicsMode};
 
 const SEP: char = ':';
@@ -75,7 +76,7 @@ fn add_data_version_field(options: &mut HashMap<String, String>, data_version: &
 }
 
 /// Creates the string for the list of resource packs
-fn write_resource_packs(resource_packs: &[String]) -> String {
+pub(super) fn write_resource_packs(resource_packs: &[String]) -> String {
 	let names = resource_packs.iter().map(|x| format!("\"{x}\","));
 	let mut names_joined = String::new();
 	for name in names {
@@ -101,6 +102,17 @@ fn convert_fov(fov: u8) -> f32 {
 	(fov as f32 - 70.0) / 40.0
 }
 
+/// Converts a language code to the format used by the version. Before 16w32a the region
+/// part of the code was uppercase (`en_US`), and afterwards the whole code is lowercase (`en_us`)
+fn convert_language(language: &str, before_16w32a: bool) -> String {
+	match language.split_once('_') {
+		Some((lang, region)) if before_16w32a => {
+			format!("{}_{}", lang.to_lowercase(), region.to_uppercase())
+		}
+		_ => language.to_lowercase(),
+	}
+}
+
 /// Converts mouse sensitivity from the 0-200% integer value to 0-1
 fn convert_mouse_sensitivity(sensitivity: i16) -> f32 {
 	(sensitivity as f32) / 2.0 / 100.0
@@ -108,8 +120,10 @@ fn convert_mouse_sensitivity(sensitivity: i16) -> f32 {
 
 macro_rules! match_keybind {
 	($out:ident, $option:expr, $key:expr, $before_1_13:expr) => {
-		if let Some(value) = $option {
-			$out.insert($key.into(), value.get_keycode($before_1_13));
+		if let Some(keybind) = &$option {
+			let keybind = resolve_keybind(keybind)
+				.with_context(|| format!("Invalid keybind for '{}'", $key))?;
+			$out.insert($key.into(), keybind.get_keycode($before_1_13));
 		}
 	};
 
@@ -134,6 +148,7 @@ pub fn create_keys(
 	let after_13w47a = VersionPattern::After("13w47a".into()).matches_info(version_info);
 	let after_14w25a = VersionPattern::After("14w25a".into()).matches_info(version_info);
 	let after_14w28a = VersionPattern::After("14w28a".into()).matches_info(version_info);
+	let after_1_9 = VersionPattern::After("1.9".into()).matches_info(version_info);
 	let after_17w06a = VersionPattern::After("17w06a".into()).matches_info(version_info);
 	let after_17w47a = VersionPattern::After("17w47a".into()).matches_info(version_info);
 	let after_18w15a = VersionPattern::After("18w15a".into()).matches_info(version_info);
@@ -153,6 +168,7 @@ pub fn create_keys(
 	let before_13w42a = VersionPattern::Before("13w42a".into()).matches_info(version_info);
 	let before_14w03a = VersionPattern::Before("14w03a".into()).matches_info(version_info);
 	let before_15w31a = VersionPattern::Before("15w31a".into()).matches_info(version_info);
+	let before_16w32a = VersionPattern::Before("16w32a".into()).matches_info(version_info);
 	let before_1_13 = VersionPattern::Before("1.13".into()).matches_info(version_info);
 	let before_20w27a = VersionPattern::Before("20w27a".into()).matches_info(version_info);
 	let before_21w43a = VersionPattern::Before("21w43a".into()).matches_info(version_info);
@@ -224,7 +240,17 @@ pub fn create_keys(
 			out.insert("graphicsMode".into(), value.to_int().to_string());
 		}
 	}
-	match_key!(out, options.video.smooth_lighting, "ao");
+	if let Some(value) = options.video.smooth_lighting {
+		// Smooth lighting used to have a minimum and maximum level
+		let value = if after_22w11a {
+			value.to_string()
+		} else if value {
+			"2".into()
+		} else {
+			"0".into()
+		};
+		out.insert("ao".into(), value);
+	}
 	match_key!(out, options.video.biome_blend, "biomeBlendRadius", after_18w15a);
 	if let Some(value) = &options.video.clouds {
 		if after_14w25a {
@@ -239,7 +265,9 @@ pub fn create_keys(
 	if let Some(value) = &options.resource_packs {
 		out.insert("resourcePacks".into(), write_resource_packs(value));
 	}
-	match_key!(out, &options.language, "lang");
+	if let Some(value) = &options.language {
+		out.insert("lang".into(), convert_language(value, before_16w32a));
+	}
 	match_key_int!(out, &options.chat.visibility, "chatVisibility");
 	match_key!(out, options.chat.opacity, "chatOpacity");
 	match_key!(out, options.chat.line_spacing, "chatLineSpacing");
@@ -259,6 +287,7 @@ pub fn create_keys(
 	match_key!(out, options.video.mipmap_levels, "mipmapLevels");
 	match_key!(out, options.use_native_transport, "useNativeTransport");
 	match_key!(out, &options.main_hand, "mainHand");
+	match_key_int!(out, &options.video.attack_indicator, "attackIndicator", after_1_9);
 	if after_17w06a {
 		match_key_int!(out, &options.chat.narrator_mode, "narrator");
 		match_key!(out, &options.tutorial_step, "tutorialStep");
@@ -395,9 +424,41 @@ pub fn create_keys(
 	Ok(out)
 }
 
+/// Convert the keys of an existing options.txt back into options. Only the options that
+/// are written the same way in every version are read, and everything else is left out
+pub fn read_client_options(keys: &HashMap<String, String>) -> ClientOptions {
+	fn get<T: std::str::FromStr>(keys: &HashMap<String, String>, key: &str) -> Option<T> {
+		keys.get(key).and_then(|x| x.parse().ok())
+	}
+
+	let mut out = ClientOptions::default();
+
+	out.control.auto_jump = get(keys, "autoJump");
+	out.control.invert_mouse_y = get(keys, "invertYMouse");
+	out.control.toggle_crouch = get(keys, "toggleCrouch");
+	out.control.toggle_sprint = get(keys, "toggleSprint");
+	out.control.mouse_sensitivity =
+		get::<f32>(keys, "mouseSensitivity").map(|x| (x * 2.0 * 100.0).round() as i16);
+	out.video.fullscreen = get(keys, "fullscreen");
+	out.video.vsync = get(keys, "enableVsync");
+	out.video.view_bobbing = get(keys, "bobView");
+	out.video.entity_shadows = get(keys, "entityShadows");
+	out.video.brightness = get(keys, "gamma");
+	out.video.render_distance = get(keys, "renderDistance");
+	out.video.simulation_distance = get(keys, "simulationDistance");
+	out.video.gui_scale = get(keys, "guiScale");
+	out.video.max_fps = get(keys, "maxFps");
+	out.video.fov = get::<f32>(keys, "fov").map(|x| (x * 40.0 + 70.0).round() as u8);
+	out.sound.show_subtitles = get(keys, "showSubtitles");
+	out.language = keys.get("lang").map(|x| x.to_lowercase());
+
+	out
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::client::ParticlesMode;
 	use crate::read::parse_options_str;
 
 	#[test]
@@ -411,4 +472,205 @@ mod tests {
 		};
 		create_keys(&options.client.unwrap(), &info).unwrap();
 	}
+
+	/// Versions in release order, including every version that the writer checks against
+	const TEST_VERSIONS: &[&str] = &[
+		"12w50a",
+		"13w36a",
+		"13w42a",
+		"13w47a",
+		"14w03a",
+		"14w25a",
+		"14w28a",
+		"1.8",
+		"15w31a",
+		"1.9",
+		"16w32a",
+		"17w06a",
+		"1.12",
+		"17w47a",
+		"18w15a",
+		"18w21a",
+		"1.13-pre2",
+		"1.13",
+		"1.15.2-pre1",
+		"1.16",
+		"20w27a",
+		"1.16.4-rc1",
+		"21w13a",
+		"21w37a",
+		"21w38a",
+		"21w42a",
+		"21w43a",
+		"1.18-pre2",
+		"1.18.2-pre1",
+		"22w11a",
+		"22w15a",
+		"1.19",
+		"1.19.4",
+		"1.20",
+	];
+
+	#[test]
+	fn test_versioned_keys() {
+		let options = parse_options_str(include_str!("fixtures/versioned_options.json")).unwrap();
+		let options = options.client.unwrap();
+		let expected = [
+			("1.8", include_str!("fixtures/expected_1.8.txt")),
+			("1.12", include_str!("fixtures/expected_1.12.txt")),
+			("1.16", include_str!("fixtures/expected_1.16.txt")),
+			("1.20", include_str!("fixtures/expected_1.20.txt")),
+		];
+		for (version, expected) in expected {
+			let info = VersionInfo {
+				version: version.to_string(),
+				versions: TEST_VERSIONS.iter().map(|x| x.to_string()).collect(),
+			};
+			let keys = create_keys(&options, &info).unwrap();
+			let expected = read_options_file(expected, SEP).unwrap();
+			assert_eq!(keys, expected, "Keys for {version} did not match");
+		}
+	}
+
+	#[test]
+	fn test_enum_representation() {
+		let options = parse_options_str(
+			r#"{
+				"client": {
+					"video": {
+						"graphics_mode": "fancy",
+						"particles": "decreased",
+						"attack_indicator": "hotbar"
+					},
+					"chat": {
+						"visibility": "commands_only",
+						"narrator_mode": "chat"
+					}
+				}
+			}"#,
+		)
+		.unwrap();
+		let options = options.client.unwrap();
+
+		// Enum options are always written as integers, and attackIndicator only exists since 1.9
+		let expected = [
+			("1.8", "particles", Some("1")),
+			("1.8", "attackIndicator", None),
+			("1.12", "graphicsMode", None),
+			("1.12", "particles", Some("1")),
+			("1.12", "chatVisibility", Some("1")),
+			("1.12", "narrator", Some("2")),
+			("1.12", "attackIndicator", Some("2")),
+			("1.16", "graphicsMode", None),
+			("1.16", "particles", Some("1")),
+			("1.16", "chatVisibility", Some("1")),
+			("1.16", "narrator", Some("2")),
+			("1.16", "attackIndicator", Some("2")),
+			("1.20", "graphicsMode", Some("1")),
+			("1.20", "particles", Some("1")),
+			("1.20", "chatVisibility", Some("1")),
+			("1.20", "narrator", Some("2")),
+			("1.20", "attackIndicator", Some("2")),
+		];
+		for (version, key, value) in expected {
+			let info = VersionInfo {
+				version: version.to_string(),
+				versions: TEST_VERSIONS.iter().map(|x| x.to_string()).collect(),
+			};
+			let keys = create_keys(&options, &info).unwrap();
+			assert_eq!(
+				keys.get(key).map(String::as_str),
+				value,
+				"Key {key} for {version} did not match"
+			);
+		}
+	}
+
+	#[test]
+	fn test_enum_numbers() {
+		let options = parse_options_str(
+			r#"{"client": {"video": {"graphics_mode": 2, "particles": "minimal"}}}"#,
+		)
+		.unwrap();
+		let options = options.client.unwrap();
+		assert_eq!(options.video.graphics_mode, Some(EnumOrNumber::Num(2)));
+		assert_eq!(
+			options.video.particles,
+			Some(EnumOrNumber::Enum(ParticlesMode::Minimal))
+		);
+
+		// Numbers given by the user are written as-is
+		let info = VersionInfo {
+			version: "1.20".to_string(),
+			versions: TEST_VERSIONS.iter().map(|x| x.to_string()).collect(),
+		};
+		let keys = create_keys(&options, &info).unwrap();
+		assert_eq!(keys.get("graphicsMode").unwrap(), "2");
+		assert_eq!(keys.get("particles").unwrap(), "2");
+	}
+
+	#[test]
+	fn test_invalid_keybind() {
+		let options =
+			parse_options_str(r#"{"client": {"control": {"keys": {"attack": "mouse9"}}}}"#)
+				.unwrap();
+		let info = VersionInfo {
+			version: "1.20".to_string(),
+			versions: TEST_VERSIONS.iter().map(|x| x.to_string()).collect(),
+		};
+		let error = create_keys(&options.client.unwrap(), &info).unwrap_err();
+		assert!(format!("{error:#}").contains("'mouse9' is not a known key"));
+	}
+
+	#[test]
+	fn test_read_client_options() {
+		let keys = HashMap::from([
+			("fov".to_string(), "0.5".to_string()),
+			("mouseSensitivity".to_string(), "0.5".to_string()),
+			("renderDistance".to_string(), "16".to_string()),
+			("enableVsync".to_string(), "false".to_string()),
+			("lang".to_string(), "en_US".to_string()),
+			("maxFps".to_string(), "not a number".to_string()),
+		]);
+		let options = read_client_options(&keys);
+		assert_eq!(options.video.fov, Some(90));
+		assert_eq!(options.control.mouse_sensitivity, Some(100));
+		assert_eq!(options.video.render_distance, Some(16));
+		assert_eq!(options.video.vsync, Some(false));
+		assert_eq!(options.video.max_fps, None);
+		assert_eq!(options.language.as_deref(), Some("en_us"));
+
+		// Reading the keys back gives the same values
+		let info = VersionInfo {
+			version: "1.20".to_string(),
+			versions: TEST_VERSIONS.iter().map(|x| x.to_string()).collect(),
+		};
+		let written = create_keys(&options, &info).unwrap();
+		assert_eq!(written.get("fov").unwrap(), "0.5");
+		assert_eq!(written.get("mouseSensitivity").unwrap(), "0.5");
+	}
+
+	#[test]
+	fn test_convert_language() {
+		assert_eq!(convert_language("en_us", true), "en_US");
+		assert_eq!(convert_language("en_US", false), "en_us");
+		assert_eq!(convert_language("lolcat", true), "lolcat");
+	}
+
+	#[test]
+	fn test_data_version() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let path = dir.join("options.txt");
+
+		write_options_txt(HashMap::new(), &path, &Some(3465)).unwrap();
+		let written = read_options_txt(&path).unwrap();
+		assert_eq!(written.get("version").unwrap(), "3465");
+
+		std::fs::remove_file(&path).unwrap();
+		let keys = HashMap::from([("version".to_string(), "1343".to_string())]);
+		write_options_txt(keys, &path, &Some(3465)).unwrap();
+		let written = read_options_txt(&path).unwrap();
+		assert_eq!(written.get("version").unwrap(), "1343");
+	}
 }
@@ -1,12 +1,17 @@
 use std::fmt::Display;
 
-use mcvm_shared::util::ToInt;
+use anyhow::bail;
+use mcvm_shared::util::{levenshtein, ToInt};
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator};
+
+use super::KeyOptions;
+use crate::read::EnumOrString;
 
 /// Version-agnostic keybinds
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Copy)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Copy, EnumIter)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum Keybind {
@@ -415,4 +420,211 @@ impl Keybind {
 			self.to_string()
 		}
 	}
+
+	/// Get the name of this keybind as it is written in the config
+	pub fn get_name(&self) -> String {
+		serde_json::to_value(self)
+			.ok()
+			.and_then(|x| x.as_str().map(String::from))
+			.unwrap_or_default()
+	}
+}
+
+/// Other names that people commonly use for keys, in the compacted form from `compact_key_name`
+const KEYBIND_ALIASES: &[(&str, Keybind)] = &[
+	("lshift", Keybind::LeftShift),
+	("shift", Keybind::LeftShift),
+	("rshift", Keybind::RightShift),
+	("lctrl", Keybind::LeftControl),
+	("lcontrol", Keybind::LeftControl),
+	("ctrl", Keybind::LeftControl),
+	("control", Keybind::LeftControl),
+	("rctrl", Keybind::RightControl),
+	("rcontrol", Keybind::RightControl),
+	("lalt", Keybind::LeftAlt),
+	("lmenu", Keybind::LeftAlt),
+	("alt", Keybind::LeftAlt),
+	("ralt", Keybind::RightAlt),
+	("rmenu", Keybind::RightAlt),
+	("altgr", Keybind::RightAlt),
+	("lwin", Keybind::LeftSystem),
+	("lmeta", Keybind::LeftSystem),
+	("lsuper", Keybind::LeftSystem),
+	("win", Keybind::LeftSystem),
+	("super", Keybind::LeftSystem),
+	("meta", Keybind::LeftSystem),
+	("cmd", Keybind::LeftSystem),
+	("command", Keybind::LeftSystem),
+	("rwin", Keybind::RightSystem),
+	("rmeta", Keybind::RightSystem),
+	("rsuper", Keybind::RightSystem),
+	("esc", Keybind::Escape),
+	("return", Keybind::Enter),
+	("back", Keybind::Backspace),
+	("del", Keybind::Delete),
+	("ins", Keybind::Insert),
+	("pgup", Keybind::PageUp),
+	("prior", Keybind::PageUp),
+	("pgdn", Keybind::PageDown),
+	("pgdown", Keybind::PageDown),
+	("next", Keybind::PageDown),
+	("caps", Keybind::CapsLock),
+	("capital", Keybind::CapsLock),
+	("grave", Keybind::GraveAccent),
+	("backtick", Keybind::GraveAccent),
+	("tilde", Keybind::GraveAccent),
+	("lbracket", Keybind::LeftBracket),
+	("rbracket", Keybind::RightBracket),
+	("equals", Keybind::Equal),
+	("spacebar", Keybind::Space),
+	("prtsc", Keybind::PrintScreen),
+	("sysrq", Keybind::PrintScreen),
+	("scroll", Keybind::ScrollLock),
+	("lmb", Keybind::MouseLeft),
+	("mouse1", Keybind::MouseLeft),
+	("rmb", Keybind::MouseRight),
+	("mouse2", Keybind::MouseRight),
+	("mmb", Keybind::MouseMiddle),
+	("mouse3", Keybind::MouseMiddle),
+	("none", Keybind::Unbound),
+];
+
+/// Parse a keybind from the config, accepting config names, Minecraft key IDs, and common aliases
+pub fn parse_keybind(string: &str) -> anyhow::Result<Keybind> {
+	let compact = compact_key_name(string);
+	let found = Keybind::iter().find(|keybind| {
+		compact == compact_key_name(&keybind.get_name())
+			|| compact == compact_key_name(&keybind.to_string())
+	});
+	if let Some(keybind) = found {
+		return Ok(keybind);
+	}
+	if let Some((_, keybind)) = KEYBIND_ALIASES.iter().find(|x| x.0 == compact) {
+		return Ok(*keybind);
+	}
+
+	match suggest_keybind(&compact) {
+		Some(suggestion) => bail!(
+			"'{string}' is not a known key. Did you mean '{}'?",
+			suggestion.get_name()
+		),
+		None => bail!("'{string}' is not a known key"),
+	}
+}
+
+/// Get the keybind that is actually used for a configured keybind
+pub fn resolve_keybind(keybind: &EnumOrString<Keybind>) -> anyhow::Result<Keybind> {
+	match keybind {
+		EnumOrString::Enum(keybind) => Ok(*keybind),
+		EnumOrString::String(string) => parse_keybind(string),
+	}
+}
+
+/// Check configured keybinds, returning a message for every one that is not valid
+pub fn validate_keybinds(keys: &KeyOptions) -> Vec<String> {
+	let Ok(serde_json::Value::Object(keys)) = serde_json::to_value(keys) else {
+		return Vec::new();
+	};
+	keys.iter()
+		.filter_map(|(action, key)| {
+			let error = parse_keybind(key.as_str()?).err()?;
+			Some(format!("Invalid keybind for '{action}': {error}"))
+		})
+		.collect()
+}
+
+/// Lowercases a key name and removes prefixes and separators so that
+/// the different ways of writing the same key compare equal
+fn compact_key_name(name: &str) -> String {
+	let name = name.to_lowercase();
+	let name = name
+		.strip_prefix("key.keyboard.")
+		.or_else(|| name.strip_prefix("key."))
+		.unwrap_or(&name);
+	name.chars()
+		.filter(|x| !matches!(x, '_' | '.' | '-' | ' '))
+		.collect()
+}
+
+/// Find the keybind closest to a misspelled compacted key name
+fn suggest_keybind(compact: &str) -> Option<Keybind> {
+	let names = Keybind::iter()
+		.flat_map(|x| [(x.get_name(), x), (x.to_string(), x)])
+		.chain(
+			KEYBIND_ALIASES
+				.iter()
+				.map(|(alias, x)| (alias.to_string(), *x)),
+		);
+	let (distance, keybind) = names
+		.map(|(name, keybind)| (levenshtein(compact, &compact_key_name(&name)), keybind))
+		.min_by_key(|x| x.0)?;
+
+	// Don't suggest something completely different
+	if distance <= 2 && distance < compact.len() {
+		Some(keybind)
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_all_keybinds_parse() {
+		for keybind in Keybind::iter() {
+			assert_eq!(parse_keybind(&keybind.get_name()).unwrap(), keybind);
+			assert_eq!(parse_keybind(&keybind.to_string()).unwrap(), keybind);
+		}
+	}
+
+	#[test]
+	fn test_aliases() {
+		assert_eq!(parse_keybind("LSHIFT").unwrap(), Keybind::LeftShift);
+		assert_eq!(parse_keybind("left_shift").unwrap(), Keybind::LeftShift);
+		assert_eq!(parse_keybind("LeftShift").unwrap(), Keybind::LeftShift);
+		assert_eq!(
+			parse_keybind("key.keyboard.leftshift").unwrap(),
+			Keybind::LeftShift
+		);
+		assert_eq!(parse_keybind("mouse4").unwrap(), Keybind::Mouse4);
+		assert_eq!(parse_keybind("key.mouse.4").unwrap(), Keybind::Mouse4);
+		assert_eq!(parse_keybind("ESC").unwrap(), Keybind::Escape);
+		assert_eq!(parse_keybind("keypad_0").unwrap(), Keybind::Numpad0);
+		assert_eq!(parse_keybind("0").unwrap(), Keybind::Num0);
+		for (alias, _) in KEYBIND_ALIASES {
+			assert_eq!(compact_key_name(alias), *alias);
+		}
+	}
+
+	#[test]
+	fn test_suggestions() {
+		let error = parse_keybind("left_shfit").unwrap_err().to_string();
+		assert!(error.contains("Did you mean 'left_shift'?"), "{error}");
+		let error = parse_keybind("key.keyboard.spcae").unwrap_err().to_string();
+		assert!(error.contains("Did you mean 'space'?"), "{error}");
+		let error = parse_keybind("jetpack").unwrap_err().to_string();
+		assert!(!error.contains("Did you mean"), "{error}");
+	}
+
+	#[test]
+	fn test_levenshtein() {
+		assert_eq!(levenshtein("", "abc"), 3);
+		assert_eq!(levenshtein("kitten", "sitting"), 3);
+		assert_eq!(levenshtein("same", "same"), 0);
+	}
+
+	#[test]
+	fn test_validate_keybinds() {
+		let keys = KeyOptions {
+			attack: Some(EnumOrString::Enum(Keybind::MouseLeft)),
+			sneak: Some(EnumOrString::String("LSHIFT".into())),
+			jump: Some(EnumOrString::String("spcae".into())),
+			..Default::default()
+		};
+		let problems = validate_keybinds(&keys);
+		assert_eq!(problems.len(), 1);
+		assert!(problems[0].contains("'jump'"));
+	}
 }
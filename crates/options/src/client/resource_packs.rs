@@ -0,0 +1,432 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::Context;
+use mcvm_shared::versions::{VersionInfo, VersionPattern};
+use serde::Deserialize;
+use zip::ZipArchive;
+
+use super::file::write_resource_packs;
+
+/// The key for the enabled resource packs in options.txt
+pub const RESOURCE_PACKS_KEY: &str = "resourcePacks";
+/// The key for the enabled resource packs that the game considers incompatible
+pub const INCOMPATIBLE_RESOURCE_PACKS_KEY: &str = "incompatibleResourcePacks";
+/// The directory in the game directory that the game loads resource packs from
+pub const RESOURCE_PACKS_DIR: &str = "resourcepacks";
+/// Prefix for packs from the resource pack directory, used since 17w43a
+const FILE_PREFIX: &str = "file/";
+
+/// The first release of every resource pack format, in order. Snapshots use the
+/// format of the latest release before them
+const PACK_FORMATS: [(&str, i32); 21] = [
+	("1.6.1", 1),
+	("1.9", 2),
+	("1.11", 3),
+	("1.13", 4),
+	("1.15", 5),
+	("1.16.2", 6),
+	("1.17", 7),
+	("1.18", 8),
+	("1.19", 9),
+	("1.19.3", 12),
+	("1.19.4", 13),
+	("1.20", 15),
+	("1.20.2", 18),
+	("1.20.3", 22),
+	("1.20.5", 32),
+	("1.21", 34),
+	("1.21.2", 42),
+	("1.21.4", 46),
+	("1.21.5", 55),
+	("1.21.6", 63),
+	("1.21.7", 64),
+];
+
+/// The resource pack keys for options.txt, along with problems that the user should know about
+#[derive(Debug, Default)]
+pub struct ResourcePackKeys {
+	/// The keys to write to options.txt
+	pub keys: HashMap<String, String>,
+	/// Warnings about the resource packs
+	pub warnings: Vec<String>,
+}
+
+/// Check the configured resource packs in created keys against the packs in the game directory
+/// and the files of installed resource pack addons, and create the final resource pack keys.
+/// Packs that are enabled in the existing options.txt but aren't configured are kept, and packs
+/// that don't support the format of the Minecraft version are listed as incompatible so that the
+/// game loads them anyways. Does nothing if resource packs aren't configured
+pub fn resolve_resource_packs(
+	keys: &HashMap<String, String>,
+	existing_keys: &HashMap<String, String>,
+	game_dir: &Path,
+	addon_files: &[String],
+	version_info: &VersionInfo,
+) -> ResourcePackKeys {
+	let mut out = ResourcePackKeys::default();
+	let Some(configured) = keys.get(RESOURCE_PACKS_KEY) else {
+		return out;
+	};
+	let configured = read_resource_packs(configured);
+
+	let uses_prefix = VersionPattern::After("17w43a".into()).matches_info(version_info);
+	let pack_dir = game_dir.join(RESOURCE_PACKS_DIR);
+	let addon_files: HashSet<_> = addon_files
+		.iter()
+		.filter_map(|x| Path::new(x).file_name())
+		.collect();
+	let is_available = |file_name: &str| {
+		pack_dir.join(file_name).exists() || addon_files.contains(std::ffi::OsStr::new(file_name))
+	};
+
+	for pack in &configured {
+		if let Some(file_name) = get_pack_file_name(pack, uses_prefix) {
+			if !is_available(file_name) {
+				out.warnings.push(format!(
+					"Resource pack '{pack}' is not in the {RESOURCE_PACKS_DIR} directory and is not installed by a package"
+				));
+			}
+		}
+	}
+
+	// Keep the packs that were enabled in game, unless their files were removed
+	let preserved = existing_keys
+		.get(RESOURCE_PACKS_KEY)
+		.map(|x| read_resource_packs(x))
+		.unwrap_or_default()
+		.into_iter()
+		.filter(|x| !configured.contains(x))
+		.filter(|x| get_pack_file_name(x, uses_prefix).map_or(true, is_available));
+	let packs = merge_resource_packs(configured.clone(), preserved.collect(), uses_prefix);
+
+	if let Some(format) = get_pack_format(version_info) {
+		let mut incompatible = Vec::new();
+		for pack in &packs {
+			let Some(file_name) = get_pack_file_name(pack, uses_prefix) else {
+				continue;
+			};
+			let path = pack_dir.join(file_name);
+			if !path.exists() {
+				continue;
+			}
+			match read_supported_formats(&path) {
+				Ok(Some((min, max))) => {
+					if format < min || format > max {
+						incompatible.push(pack.clone());
+						out.warnings.push(format!(
+							"Resource pack '{pack}' was made for a different version of Minecraft and may not work correctly"
+						));
+					}
+				}
+				Ok(None) => {}
+				Err(e) => out.warnings.push(format!(
+					"Failed to read the metadata of resource pack '{pack}': {e:?}"
+				)),
+			}
+		}
+		out.keys.insert(
+			INCOMPATIBLE_RESOURCE_PACKS_KEY.into(),
+			write_resource_packs(&incompatible),
+		);
+	}
+
+	out.keys
+		.insert(RESOURCE_PACKS_KEY.into(), write_resource_packs(&packs));
+
+	out
+}
+
+/// Read a list of resource packs from options.txt
+pub fn read_resource_packs(value: &str) -> Vec<String> {
+	// The lists that MCVM writes have a trailing comma, which isn't valid JSON
+	let value = value.trim();
+	let value = match value.strip_suffix(",]") {
+		Some(start) => format!("{start}]"),
+		None => value.to_string(),
+	};
+
+	serde_json::from_str(&value).unwrap_or_default()
+}
+
+/// Add packs that should be kept to the configured packs. Since 17w43a the list goes from
+/// the lowest to the highest priority, and before that it went from the highest to the lowest.
+/// The kept packs are placed right below the configured packs from the resource pack directory
+fn merge_resource_packs(
+	mut configured: Vec<String>,
+	preserved: Vec<String>,
+	uses_prefix: bool,
+) -> Vec<String> {
+	if uses_prefix {
+		let index = configured
+			.iter()
+			.position(|x| x.starts_with(FILE_PREFIX))
+			.unwrap_or(configured.len());
+		configured.splice(index..index, preserved);
+	} else {
+		configured.extend(preserved);
+	}
+
+	configured
+}
+
+/// Get the name of the file in the resource pack directory that a pack refers to, if it refers to one.
+/// Since 17w43a, packs that don't start with `file/` are built into the game or mods
+fn get_pack_file_name(pack: &str, uses_prefix: bool) -> Option<&str> {
+	if uses_prefix {
+		pack.strip_prefix(FILE_PREFIX)
+	} else {
+		Some(pack)
+	}
+}
+
+/// Get the resource pack format of a Minecraft version, if it is known
+fn get_pack_format(version_info: &VersionInfo) -> Option<i32> {
+	PACK_FORMATS
+		.iter()
+		.rev()
+		.find(|(version, _)| VersionPattern::After(version.to_string()).matches_info(version_info))
+		.map(|(_, format)| *format)
+}
+
+/// The pack.mcmeta file of a resource pack
+#[derive(Deserialize)]
+struct PackMeta {
+	pack: PackSection,
+}
+
+#[derive(Deserialize)]
+struct PackSection {
+	pack_format: Option<i32>,
+	supported_formats: Option<FormatRange>,
+	min_format: Option<FormatVersion>,
+	max_format: Option<FormatVersion>,
+}
+
+/// The supported_formats field, added in 1.20.2
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FormatRange {
+	Single(i32),
+	List(Vec<i32>),
+	Object {
+		min_inclusive: i32,
+		max_inclusive: i32,
+	},
+}
+
+/// The min_format and max_format fields, added in 1.21.9, which can also have a minor version
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FormatVersion {
+	Major(i32),
+	List(Vec<i32>),
+}
+
+impl FormatVersion {
+	fn major(&self) -> Option<i32> {
+		match self {
+			Self::Major(major) => Some(*major),
+			Self::List(list) => list.first().copied(),
+		}
+	}
+}
+
+/// Read the inclusive range of formats that a resource pack directory or zip supports.
+/// Returns None if the pack doesn't say
+fn read_supported_formats(path: &Path) -> anyhow::Result<Option<(i32, i32)>> {
+	let contents = if path.is_dir() {
+		std::fs::read_to_string(path.join("pack.mcmeta")).context("Failed to read pack.mcmeta")?
+	} else {
+		let file = File::open(path).context("Failed to open resource pack")?;
+		let mut zip = ZipArchive::new(BufReader::new(file)).context("Failed to open zip file")?;
+		let mut file = zip
+			.by_name("pack.mcmeta")
+			.context("Failed to find pack.mcmeta")?;
+		let mut contents = String::new();
+		file.read_to_string(&mut contents)
+			.context("Failed to read pack.mcmeta")?;
+		contents
+	};
+	let meta: PackMeta = serde_json::from_str(contents.trim_start_matches('\u{feff}'))
+		.context("Failed to parse pack.mcmeta")?;
+
+	let mut formats: Vec<i32> = meta.pack.pack_format.into_iter().collect();
+	match meta.pack.supported_formats {
+		Some(FormatRange::Single(format)) => formats.push(format),
+		Some(FormatRange::List(list)) => formats.extend(list),
+		Some(FormatRange::Object {
+			min_inclusive,
+			max_inclusive,
+		}) => formats.extend([min_inclusive, max_inclusive]),
+		None => {}
+	}
+	formats.extend(meta.pack.min_format.as_ref().and_then(FormatVersion::major));
+	formats.extend(meta.pack.max_format.as_ref().and_then(FormatVersion::major));
+
+	Ok(formats
+		.iter()
+		.min()
+		.copied()
+		.zip(formats.iter().max().copied()))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Write;
+
+	use zip::write::SimpleFileOptions;
+	use zip::ZipWriter;
+
+	use super::*;
+
+	const TEST_VERSIONS: [&str; 21] = [
+		"1.5.2", "1.6.1", "1.8", "1.9", "1.11", "1.12.2", "17w43a", "1.13", "1.15", "1.16.2",
+		"1.17", "1.18", "1.19", "1.19.3", "1.19.4", "1.20", "1.20.1", "1.20.2", "1.20.3", "1.20.5",
+		"1.21",
+	];
+
+	fn version_info(version: &str) -> VersionInfo {
+		VersionInfo {
+			version: version.to_string(),
+			versions: TEST_VERSIONS.iter().map(|x| x.to_string()).collect(),
+		}
+	}
+
+	/// Copy the fixture resource pack directory and add a zipped pack to it
+	fn create_game_dir() -> tempfile::TempDir {
+		let dir = tempfile::tempdir().unwrap();
+		let pack_dir = dir.path().join(RESOURCE_PACKS_DIR);
+		let fixtures =
+			Path::new(env!("CARGO_MANIFEST_DIR")).join("src/client/fixtures/resourcepacks");
+		for pack in std::fs::read_dir(fixtures).unwrap() {
+			let pack = pack.unwrap();
+			let target = pack_dir.join(pack.file_name());
+			std::fs::create_dir_all(&target).unwrap();
+			std::fs::copy(pack.path().join("pack.mcmeta"), target.join("pack.mcmeta")).unwrap();
+		}
+
+		let file = File::create(pack_dir.join("Zipped.zip")).unwrap();
+		let mut zip = ZipWriter::new(file);
+		zip.start_file("pack.mcmeta", SimpleFileOptions::default())
+			.unwrap();
+		zip.write_all(br#"{"pack": {"pack_format": 15, "description": "Zipped"}}"#)
+			.unwrap();
+		zip.finish().unwrap();
+
+		dir
+	}
+
+	fn keys(packs: &str) -> HashMap<String, String> {
+		HashMap::from([(RESOURCE_PACKS_KEY.to_string(), packs.to_string())])
+	}
+
+	#[test]
+	fn test_read_resource_packs() {
+		assert_eq!(
+			read_resource_packs(r#"["vanilla","file/a, b.zip",]"#),
+			["vanilla", "file/a, b.zip"]
+		);
+		assert_eq!(read_resource_packs(r#"["vanilla"]"#), ["vanilla"]);
+		assert!(read_resource_packs("[]").is_empty());
+		assert!(read_resource_packs("garbage").is_empty());
+	}
+
+	#[test]
+	fn test_pack_format() {
+		assert_eq!(get_pack_format(&version_info("1.8")), Some(1));
+		assert_eq!(get_pack_format(&version_info("1.13")), Some(4));
+		assert_eq!(get_pack_format(&version_info("1.20.1")), Some(15));
+		assert_eq!(get_pack_format(&version_info("1.21")), Some(34));
+		assert_eq!(get_pack_format(&version_info("1.5.2")), None);
+	}
+
+	#[test]
+	fn test_resolve_resource_packs() {
+		let game_dir = create_game_dir();
+		let configured = keys(
+			r#"["vanilla","file/Modern","file/Legacy","file/Zipped.zip","file/Missing","file/Addon.zip",]"#,
+		);
+		let existing = keys(r#"["vanilla","programmer_art","file/Removed","file/Ranged"]"#);
+		let addon_files = vec!["resourcepacks/Addon.zip".to_string()];
+
+		let out = resolve_resource_packs(
+			&configured,
+			&existing,
+			game_dir.path(),
+			&addon_files,
+			&version_info("1.20.1"),
+		);
+		assert_eq!(
+			read_resource_packs(&out.keys[RESOURCE_PACKS_KEY]),
+			[
+				"vanilla",
+				"programmer_art",
+				"file/Ranged",
+				"file/Modern",
+				"file/Legacy",
+				"file/Zipped.zip",
+				"file/Missing",
+				"file/Addon.zip"
+			]
+		);
+		assert_eq!(
+			read_resource_packs(&out.keys[INCOMPATIBLE_RESOURCE_PACKS_KEY]),
+			["file/Legacy"]
+		);
+		assert_eq!(out.warnings.len(), 2);
+		assert!(out.warnings[0].contains("file/Missing"));
+		assert!(out.warnings[1].contains("file/Legacy"));
+
+		// The ranged pack supports 1.21 too, but the others don't
+		let out = resolve_resource_packs(
+			&configured,
+			&existing,
+			game_dir.path(),
+			&addon_files,
+			&version_info("1.21"),
+		);
+		assert_eq!(
+			read_resource_packs(&out.keys[INCOMPATIBLE_RESOURCE_PACKS_KEY]),
+			["file/Modern", "file/Legacy", "file/Zipped.zip"]
+		);
+	}
+
+	#[test]
+	fn test_resolve_resource_packs_before_prefix() {
+		let game_dir = create_game_dir();
+		let configured = keys(r#"["Legacy","Modern",]"#);
+		let existing = keys(r#"["Ranged","Legacy"]"#);
+
+		let out = resolve_resource_packs(
+			&configured,
+			&existing,
+			game_dir.path(),
+			&[],
+			&version_info("1.8"),
+		);
+		assert_eq!(
+			read_resource_packs(&out.keys[RESOURCE_PACKS_KEY]),
+			["Legacy", "Modern", "Ranged"]
+		);
+		assert_eq!(
+			read_resource_packs(&out.keys[INCOMPATIBLE_RESOURCE_PACKS_KEY]),
+			["Modern", "Ranged"]
+		);
+	}
+
+	#[test]
+	fn test_unconfigured_resource_packs() {
+		let out = resolve_resource_packs(
+			&HashMap::new(),
+			&keys(r#"["vanilla"]"#),
+			Path::new("/nonexistent"),
+			&[],
+			&version_info("1.20.1"),
+		);
+		assert!(out.keys.is_empty());
+		assert!(out.warnings.is_empty());
+	}
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use mcvm_shared::output::{DiffChange, MCVMOutput, MessageContents, MessageLevel};
+
+/// Parts of option keys that mark their values as secret
+const SECRET_KEY_PARTS: [&str; 3] = ["password", "secret", "token"];
+
+/// Diffs with more changed lines than this only show the keys that changed
+pub const MAX_DIFF_LINES: usize = 30;
+
+/// What secret values are replaced with in diffs
+const REDACTED: &str = "<redacted>";
+
+/// A line that changed between two versions of an options file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineChange {
+	/// Whether the line was added or removed
+	pub change: DiffChange,
+	/// The contents of the line
+	pub line: String,
+}
+
+/// Compute the lines that changed between two versions of an options file.
+/// The order of lines doesn't matter in options files, so lines that only moved aren't included.
+/// Changes are sorted by key so that the old and new values of a key are next to each other
+pub fn diff_lines(old: &str, new: &str, sep: char) -> Vec<LineChange> {
+	let mut old_counts: HashMap<&str, usize> = HashMap::new();
+	for line in old.lines() {
+		*old_counts.entry(line).or_default() += 1;
+	}
+	let mut new_counts: HashMap<&str, usize> = HashMap::new();
+	for line in new.lines() {
+		*new_counts.entry(line).or_default() += 1;
+	}
+
+	let removed = get_unmatched_lines(old, &new_counts, DiffChange::Removed);
+	let added = get_unmatched_lines(new, &old_counts, DiffChange::Added);
+
+	removed
+		.into_iter()
+		.chain(added)
+		.sorted_by(|a, b| {
+			get_key(&a.line, sep)
+				.cmp(get_key(&b.line, sep))
+				.then_with(|| is_added(a.change).cmp(&is_added(b.change)))
+		})
+		.collect()
+}
+
+/// Get the lines of a file that don't have a matching line in the other file
+fn get_unmatched_lines(
+	contents: &str,
+	other_counts: &HashMap<&str, usize>,
+	change: DiffChange,
+) -> Vec<LineChange> {
+	let mut other_counts = other_counts.clone();
+	let mut out = Vec::new();
+	for line in contents.lines() {
+		match other_counts.get_mut(line) {
+			Some(count) if *count > 0 => *count -= 1,
+			_ => out.push(LineChange {
+				change,
+				line: line.to_string(),
+			}),
+		}
+	}
+
+	out
+}
+
+fn is_added(change: DiffChange) -> bool {
+	change == DiffChange::Added
+}
+
+/// Check whether the value of an option key should be hidden
+pub fn is_secret_key(key: &str) -> bool {
+	let key = key.to_lowercase();
+	SECRET_KEY_PARTS.iter().any(|x| key.contains(x))
+}
+
+/// Get the key of an options file line
+fn get_key(line: &str, sep: char) -> &str {
+	line.split_once(sep).map_or(line, |x| x.0).trim()
+}
+
+/// Hide the value of a line if its key is secret
+pub fn redact_line(line: &str, sep: char) -> String {
+	match line.split_once(sep) {
+		Some((key, _)) if is_secret_key(key) => format!("{key}{sep}{REDACTED}"),
+		_ => line.to_string(),
+	}
+}
+
+/// Show the changes made to an options file. Small diffs show every changed line with secret values redacted,
+/// while large ones only list the keys that changed
+pub fn display_diff(
+	file_name: &str,
+	old: &str,
+	new: &str,
+	sep: char,
+	level: MessageLevel,
+	o: &mut impl MCVMOutput,
+) {
+	let changes = diff_lines(old, new, sep);
+	if changes.is_empty() {
+		return;
+	}
+
+	o.display(
+		MessageContents::Simple(format!("Changes to {file_name}:")),
+		level,
+	);
+	if changes.len() > MAX_DIFF_LINES {
+		let keys = changes
+			.iter()
+			.map(|x| get_key(&x.line, sep))
+			.unique()
+			.join(", ");
+		o.display(
+			MessageContents::Property(
+				"Changed keys".into(),
+				Box::new(MessageContents::Simple(keys)),
+			),
+			level,
+		);
+	} else {
+		for change in changes {
+			o.display(
+				MessageContents::DiffLine(change.change, redact_line(&change.line, sep)),
+				level,
+			);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use mcvm_shared::output::{Buffered, NoOp, OutputEvent, SharedOutput};
+
+	use super::*;
+
+	#[test]
+	fn test_diff_lines() {
+		let old = "fov:0.0\nrenderDistance:12\nlang:en_us\n";
+		let new = "lang:en_us\nfov:0.0\nrenderDistance:8\nsimulationDistance:8\n";
+		let changes = diff_lines(old, new, ':');
+		let added = |line: &str| LineChange {
+			change: DiffChange::Added,
+			line: line.into(),
+		};
+		let removed = |line: &str| LineChange {
+			change: DiffChange::Removed,
+			line: line.into(),
+		};
+		assert_eq!(
+			changes,
+			vec![
+				removed("renderDistance:12"),
+				added("renderDistance:8"),
+				added("simulationDistance:8"),
+			]
+		);
+
+		assert!(diff_lines(old, old, ':').is_empty());
+		assert_eq!(diff_lines("", "a=b", '=').len(), 1);
+		// Duplicate lines are counted
+		assert_eq!(diff_lines("a=b\na=b", "a=b", '='), vec![removed("a=b")]);
+	}
+
+	#[test]
+	fn test_redaction() {
+		assert_eq!(
+			redact_line("rcon.password=hunter2", '='),
+			"rcon.password=<redacted>"
+		);
+		assert_eq!(redact_line("motd=Hello=World", '='), "motd=Hello=World");
+		assert_eq!(
+			redact_line("management-server-secret=abc", '='),
+			"management-server-secret=<redacted>"
+		);
+		assert!(!is_secret_key("max-players"));
+		// Environment variables are checked the same way
+		assert!(is_secret_key("GITHUB_TOKEN"));
+		assert!(is_secret_key("db_password"));
+		assert!(!is_secret_key("MESA_GL_VERSION_OVERRIDE"));
+	}
+
+	fn get_displayed(old: &str, new: &str) -> Vec<String> {
+		let mut noop = NoOp;
+		let shared = SharedOutput::new(&mut noop);
+		let mut o = Buffered::new(&shared);
+		display_diff(
+			"server.properties",
+			old,
+			new,
+			'=',
+			MessageLevel::Debug,
+			&mut o,
+		);
+		o.into_events()
+			.into_iter()
+			.filter_map(|x| match x {
+				OutputEvent::Message(message) => Some(message.contents.default_format()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_display_diff() {
+		assert_eq!(
+			get_displayed("rcon.password=a\nmotd=Hi\n", "rcon.password=b\nmotd=Hey\n"),
+			vec![
+				"Changes to server.properties:",
+				"- motd=Hi",
+				"+ motd=Hey",
+				"- rcon.password=<redacted>",
+				"+ rcon.password=<redacted>",
+			]
+		);
+		assert!(get_displayed("motd=Hi", "motd=Hi").is_empty());
+
+		let old = (0..MAX_DIFF_LINES).map(|i| format!("key{i}=a")).join("\n");
+		let new = (0..MAX_DIFF_LINES).map(|i| format!("key{i}=b")).join("\n");
+		let displayed = get_displayed(&old, &new);
+		assert_eq!(displayed.len(), 2);
+		assert!(displayed[1].starts_with("Changed keys: key0, key1, key10"));
+		assert!(!displayed[1].contains("=a"));
+	}
+}
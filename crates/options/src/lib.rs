@@ -7,6 +7,8 @@
 
 /// Options management for the client
 pub mod client;
+/// Showing the changes made to options files
+pub mod diff;
 /// Common utilties for reading and parsing options-related files
 mod read;
 /// Options management for the server
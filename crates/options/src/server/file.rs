@@ -31,7 +31,7 @@ pub fn write_server_properties(
 }
 
 /// Collect a hashmap from an existing server.properties file so we can compare with it
-fn read_server_properties(path: &Path) -> anyhow::Result<HashMap<String, String>> {
+pub fn read_server_properties(path: &Path) -> anyhow::Result<HashMap<String, String>> {
 	if path.exists() {
 		let contents = std::fs::read_to_string(path).context("Failed to read options.txt")?;
 		read_options_file(&contents, SEP)
@@ -82,6 +82,11 @@ pub fn create_keys(
 	let mut out = HashMap::new();
 
 	let after_18w42a = VersionPattern::After("18w42a".into()).matches_info(version_info);
+	let after_1_16 = VersionPattern::After("1.16".into()).matches_info(version_info);
+	let after_1_18 = VersionPattern::After("1.18".into()).matches_info(version_info);
+	let after_1_19 = VersionPattern::After("1.19".into()).matches_info(version_info);
+	let after_1_20_2 = VersionPattern::After("1.20.2".into()).matches_info(version_info);
+	let after_1_20_5 = VersionPattern::After("1.20.5".into()).matches_info(version_info);
 
 	match_key!(out, options.allow_flight, "allow-flight");
 	match_key!(out, options.world.allow_nether, "allow-nether");
@@ -94,7 +99,7 @@ pub fn create_keys(
 	match_key!(out, options.rcon.enable, "enable-rcon");
 	match_key!(out, options.enable_status, "enable-status");
 	match_key!(out, options.query.enable, "enable-query");
-	match_key!(out, options.enforce_secure_profile, "enforce-secure-profile");
+	match_key!(out, options.enforce_secure_profile, "enforce-secure-profile", after_1_19);
 	match_key!(out, options.whitelist.enforce, "enforce-whitelist");
 	match_key!( out, options.entity_broadcast_range, "entity-broadcast-range-percentage");
 	match_key!(out, options.gamemode.force, "force-gamemode");
@@ -110,7 +115,7 @@ pub fn create_keys(
 		);
 	}
 	match_key!(out, options.hardcore, "hardcore");
-	match_key!(out, options.hide_online_players, "hide-online-players");
+	match_key!(out, options.hide_online_players, "hide-online-players", after_1_18);
 	if let Some(value) = &options.datapacks.initial_disabled {
 		out.insert("initial-disabled-packs".into(), write_datapacks(value));
 	}
@@ -120,7 +125,8 @@ pub fn create_keys(
 	match_key!(out, &options.world.name, WORLD_NAME_KEY);
 	match_key!(out, &options.world.seed, "level-seed");
 	match_key!(out, &options.world.r#type, "level-type");
-	match_key!( out, options.max_chained_neighbor_updates, "max-chained-neighbor-updates");
+	match_key!(out, options.log_ips, "log-ips", after_1_20_2);
+	match_key!( out, options.max_chained_neighbor_updates, "max-chained-neighbor-updates", after_1_19);
 	match_key!(out, options.max_players, "max-players");
 	match_key!(out, options.max_tick_time, "max-tick-time");
 	match_key!(out, options.world.max_build_height, "max-build-height");
@@ -139,8 +145,10 @@ pub fn create_keys(
 	match_key!(out, options.rate_limit, "rate-limit");
 	match_key!(out, &options.rcon.password, "rcon.password");
 	match_key!(out, options.rcon.port, "rcon.port");
+	match_key!(out, &options.region_file_compression, "region-file-compression", after_1_20_5);
 	match_key!(out, &options.resource_pack.uri, "resource-pack");
 	match_key!(out, &options.resource_pack.prompt, "resource-pack-prompt");
+	match_key!(out, &options.resource_pack.sha1, "resource-pack-sha1");
 	match_key!( out, &options.resource_pack.required, "require-resource-pack");
 	match_key!(out, &options.ip, "server-ip");
 	match_key!(out, options.port, "server-port");
@@ -150,6 +158,7 @@ pub fn create_keys(
 	match_key!(out, options.spawn_monsters, "spawn-monsters");
 	match_key!(out, options.spawn_npcs, "spawn-npcs");
 	match_key!(out, options.spawn_protection, "spawn-protection");
+	match_key!(out, options.sync_chunk_writes, "sync-chunk-writes", after_1_16);
 	match_key!(out, options.use_native_transport, "use-native-transport");
 	match_key!(out, options.view_distance, "view-distance");
 	match_key!(out, options.whitelist.enable, "white-list");
@@ -187,4 +196,68 @@ mod tests {
 		};
 		create_keys(&options.server.unwrap(), &info).unwrap();
 	}
+
+	fn get_test_version_info(version: &str) -> VersionInfo {
+		let versions = ["1.16", "1.18", "1.19", "1.20.2", "1.20.5", "1.21"];
+		VersionInfo {
+			version: version.to_string(),
+			versions: versions.iter().map(|x| x.to_string()).collect(),
+		}
+	}
+
+	fn get_modern_options() -> ServerOptions {
+		let options = parse_options_str(
+			r#"{
+				"server": {
+					"enforce_secure_profile": false,
+					"log_ips": false,
+					"hide_online_players": true,
+					"max_chained_neighbor_updates": 500000,
+					"region_file_compression": "lz4",
+					"sync_chunk_writes": false
+				}
+			}"#,
+		)
+		.unwrap();
+		options.server.unwrap()
+	}
+
+	#[test]
+	fn test_modern_keys_round_trip() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let path = dir.join("server.properties");
+		std::fs::write(&path, "future-key=something\nhide-online-players=false\n").unwrap();
+
+		let keys = create_keys(&get_modern_options(), &get_test_version_info("1.21")).unwrap();
+		write_server_properties(keys.clone(), &path).unwrap();
+		let read = read_server_properties(&path).unwrap();
+
+		for (key, value) in &keys {
+			assert_eq!(read.get(key), Some(value), "Key {key} did not round trip");
+		}
+		assert_eq!(read.get("enforce-secure-profile").unwrap(), "false");
+		assert_eq!(read.get("log-ips").unwrap(), "false");
+		assert_eq!(read.get("hide-online-players").unwrap(), "true");
+		assert_eq!(read.get("max-chained-neighbor-updates").unwrap(), "500000");
+		assert_eq!(read.get("region-file-compression").unwrap(), "lz4");
+		assert_eq!(read.get("sync-chunk-writes").unwrap(), "false");
+		assert_eq!(read.get("future-key").unwrap(), "something");
+	}
+
+	#[test]
+	fn test_modern_keys_version_gates() {
+		let options = get_modern_options();
+
+		let keys = create_keys(&options, &get_test_version_info("1.19")).unwrap();
+		assert!(keys.contains_key("enforce-secure-profile"));
+		assert!(keys.contains_key("max-chained-neighbor-updates"));
+		assert!(!keys.contains_key("log-ips"));
+		assert!(!keys.contains_key("region-file-compression"));
+
+		let keys = create_keys(&options, &get_test_version_info("1.16")).unwrap();
+		assert!(keys.contains_key("sync-chunk-writes"));
+		assert!(!keys.contains_key("hide-online-players"));
+		assert!(!keys.contains_key("enforce-secure-profile"));
+	}
 }
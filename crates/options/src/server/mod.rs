@@ -1,11 +1,22 @@
 /// Writing to the server.properties file
 mod file;
+/// Hashing and copying local resource packs
+mod resource_pack;
+/// Writing to Sponge's global config
+mod sponge;
 
 use mcvm_shared::util::{DefaultExt, ToInt};
 
 pub use file::create_keys;
 pub use file::get_world_name;
+pub use file::read_server_properties;
 pub use file::write_server_properties;
+pub use resource_pack::create_resource_pack_keys;
+pub use resource_pack::ResourcePackKeys;
+pub use resource_pack::RESOURCE_PACK_DIR;
+pub use sponge::create_sponge_keys;
+pub use sponge::write_sponge_config;
+pub use sponge::SPONGE_GLOBAL_CONFIG_PATH;
 
 use std::collections::HashMap;
 use std::fmt::Display;
@@ -41,6 +52,8 @@ pub mod deser {
 		pub world: WorldOptions,
 		#[serde(skip_serializing_if = "DefaultExt::is_default")]
 		pub resource_pack: ResourcePackOptions,
+		#[serde(skip_serializing_if = "DefaultExt::is_default")]
+		pub sponge: SpongeOptions,
 		#[serde(skip_serializing_if = "HashMap::is_empty")]
 		pub custom: HashMap<String, String>,
 		#[serde(skip_serializing_if = "Option::is_none")]
@@ -66,6 +79,8 @@ pub mod deser {
 		#[serde(skip_serializing_if = "Option::is_none")]
 		pub hide_online_players: Option<bool>,
 		#[serde(skip_serializing_if = "Option::is_none")]
+		pub log_ips: Option<bool>,
+		#[serde(skip_serializing_if = "Option::is_none")]
 		pub max_chained_neighbor_updates: Option<i32>,
 		#[serde(skip_serializing_if = "Option::is_none")]
 		pub max_players: Option<u32>,
@@ -90,6 +105,8 @@ pub mod deser {
 		#[serde(skip_serializing_if = "Option::is_none")]
 		pub rate_limit: Option<i16>,
 		#[serde(skip_serializing_if = "Option::is_none")]
+		pub region_file_compression: Option<EnumOrString<RegionFileCompression>>,
+		#[serde(skip_serializing_if = "Option::is_none")]
 		pub ip: Option<String>,
 		#[serde(skip_serializing_if = "Option::is_none")]
 		pub port: Option<u16>,
@@ -201,6 +218,26 @@ pub mod deser {
 		pub sha1: Option<String>,
 		#[serde(skip_serializing_if = "Option::is_none")]
 		pub required: Option<bool>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub path: Option<String>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub copy: Option<bool>,
+	}
+
+	#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+	#[cfg_attr(feature = "schema", derive(JsonSchema))]
+	#[serde(default)]
+	pub struct SpongeOptions {
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub ip_forwarding: Option<bool>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub auto_save_interval: Option<u32>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub keep_spawn_loaded: Option<bool>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub generate_spawn_on_load: Option<bool>,
+		#[serde(skip_serializing_if = "HashMap::is_empty")]
+		pub custom: HashMap<String, String>,
 	}
 
 	#[derive(Deserialize, Serialize, Debug, Clone)]
@@ -309,4 +346,27 @@ pub mod deser {
 			self.clone() as i32
 		}
 	}
+
+	#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+	#[cfg_attr(feature = "schema", derive(JsonSchema))]
+	#[serde(rename_all = "snake_case")]
+	pub enum RegionFileCompression {
+		Deflate,
+		Lz4,
+		None,
+	}
+
+	impl Display for RegionFileCompression {
+		fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+			write!(
+				f,
+				"{}",
+				match self {
+					Self::Deflate => "deflate",
+					Self::Lz4 => "lz4",
+					Self::None => "none",
+				}
+			)
+		}
+	}
 }
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use sha1::{Digest, Sha1};
+
+use super::ResourcePackOptions;
+
+/// The directory in the game directory that local resource packs are copied to
+pub const RESOURCE_PACK_DIR: &str = "resource_pack";
+/// The key of the resource pack hash property
+const SHA1_KEY: &str = "resource-pack-sha1";
+
+/// The keys for a local resource pack, along with problems that the user should know about
+#[derive(Debug, Default)]
+pub struct ResourcePackKeys {
+	/// The keys to write to server.properties
+	pub keys: HashMap<String, String>,
+	/// Warnings about the resource pack
+	pub warnings: Vec<String>,
+}
+
+/// Create the keys for the local resource pack file in the options, if there is one.
+/// The file is hashed every time, so a changed pack always gets a new hash.
+/// Relative paths are relative to the game directory
+pub fn create_resource_pack_keys(
+	options: &ResourcePackOptions,
+	game_dir: &Path,
+) -> anyhow::Result<ResourcePackKeys> {
+	let mut out = ResourcePackKeys::default();
+	let Some(path) = &options.path else {
+		return Ok(out);
+	};
+
+	let path = game_dir.join(path);
+	let hash = hash_file(&path)
+		.with_context(|| format!("Failed to hash resource pack {}", path.display()))?;
+
+	let copied_path = if options.copy.unwrap_or_default() {
+		Some(copy_pack(&path, &hash, game_dir).context("Failed to copy resource pack")?)
+	} else {
+		None
+	};
+
+	if options.uri.is_none() {
+		let location = copied_path.as_ref().unwrap_or(&path);
+		out.warnings.push(format!(
+			"The resource pack at {} has to be hosted somewhere that players can download it from. Set the resource_pack.uri option to its URL",
+			location.display()
+		));
+	}
+
+	out.keys.insert(SHA1_KEY.into(), hash);
+
+	Ok(out)
+}
+
+/// Get the hex SHA-1 hash of a file
+fn hash_file(path: &Path) -> anyhow::Result<String> {
+	let file = File::open(path).context("Failed to open file")?;
+	let mut hasher = Sha1::new();
+	std::io::copy(&mut BufReader::new(file), &mut hasher).context("Failed to read file")?;
+	Ok(hex::encode(hasher.finalize()))
+}
+
+/// Copy a resource pack into the game directory, unless the copy there is already the same
+fn copy_pack(path: &Path, hash: &str, game_dir: &Path) -> anyhow::Result<PathBuf> {
+	let file_name = path
+		.file_name()
+		.context("Resource pack path has no file name")?;
+	let dir = game_dir.join(RESOURCE_PACK_DIR);
+	let target = dir.join(file_name);
+	if target == path {
+		return Ok(target);
+	}
+	if target.exists() && hash_file(&target).is_ok_and(|x| x == hash) {
+		return Ok(target);
+	}
+
+	std::fs::create_dir_all(&dir).context("Failed to create resource pack directory")?;
+	std::fs::copy(path, &target)
+		.with_context(|| format!("Failed to copy to {}", target.display()))?;
+
+	Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_resource_pack_hash() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		std::fs::write(dir.join("pack.zip"), "hello").unwrap();
+		let options = ResourcePackOptions {
+			uri: Some("https://example.com/pack.zip".into()),
+			path: Some("pack.zip".into()),
+			..Default::default()
+		};
+
+		let keys = create_resource_pack_keys(&options, dir).unwrap();
+		assert_eq!(
+			keys.keys.get(SHA1_KEY).unwrap(),
+			"aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+		);
+		assert!(keys.warnings.is_empty());
+
+		// Changing the pack changes the hash
+		std::fs::write(dir.join("pack.zip"), "hello world").unwrap();
+		let keys = create_resource_pack_keys(&options, dir).unwrap();
+		assert_eq!(
+			keys.keys.get(SHA1_KEY).unwrap(),
+			"2aae6c35c94fcfb415dbe95f408b9ce91ee846ed"
+		);
+	}
+
+	#[test]
+	fn test_resource_pack_copy() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let source_dir = tempfile::tempdir().unwrap();
+		let source = source_dir.path().join("pack.zip");
+		std::fs::write(&source, "hello").unwrap();
+		let options = ResourcePackOptions {
+			path: Some(source.to_string_lossy().to_string()),
+			copy: Some(true),
+			..Default::default()
+		};
+
+		let keys = create_resource_pack_keys(&options, dir).unwrap();
+		let copied = dir.join(RESOURCE_PACK_DIR).join("pack.zip");
+		assert_eq!(std::fs::read_to_string(&copied).unwrap(), "hello");
+		// Without a URI, the user is told to host the pack
+		assert_eq!(keys.warnings.len(), 1);
+
+		std::fs::write(&source, "changed").unwrap();
+		create_resource_pack_keys(&options, dir).unwrap();
+		assert_eq!(std::fs::read_to_string(&copied).unwrap(), "changed");
+	}
+
+	#[test]
+	fn test_no_resource_pack_path() {
+		let options = ResourcePackOptions {
+			sha1: Some("abc".into()),
+			..Default::default()
+		};
+		let keys = create_resource_pack_keys(&options, Path::new("/nonexistent")).unwrap();
+		assert!(keys.keys.is_empty());
+		assert!(keys.warnings.is_empty());
+	}
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use itertools::Itertools;
+
+use super::SpongeOptions;
+
+/// The path to Sponge's global config file, relative to the server directory
+pub const SPONGE_GLOBAL_CONFIG_PATH: &str = "config/sponge/global.conf";
+
+/// The comment that starts the options written by mcvm in the config
+const BLOCK_START: &str = "# Start of options managed by mcvm";
+/// The comment that ends the options written by mcvm in the config
+const BLOCK_END: &str = "# End of options managed by mcvm";
+
+/// Write Sponge options to a list of keys, which are paths in the global config
+pub fn create_sponge_keys(options: &SpongeOptions) -> HashMap<String, String> {
+	let mut out = HashMap::new();
+	if let Some(value) = options.ip_forwarding {
+		// The BungeeCord module has to be enabled for forwarding to do anything
+		out.insert("sponge.modules.bungeecord".into(), value.to_string());
+		out.insert("sponge.bungeecord.ip-forwarding".into(), value.to_string());
+	}
+	if let Some(value) = options.auto_save_interval {
+		out.insert("sponge.world.auto-save-interval".into(), value.to_string());
+	}
+	if let Some(value) = options.keep_spawn_loaded {
+		out.insert("sponge.world.keep-spawn-loaded".into(), value.to_string());
+	}
+	if let Some(value) = options.generate_spawn_on_load {
+		out.insert(
+			"sponge.world.generate-spawn-on-load".into(),
+			value.to_string(),
+		);
+	}
+	out.extend(options.custom.clone());
+
+	out
+}
+
+/// Write keys to Sponge's global config. The keys are written in a block at the end of the file,
+/// which replaces the block from the last time. Since HOCON uses the last value of a key,
+/// these override the values that Sponge generated without changing the rest of the file
+pub fn write_sponge_config(keys: HashMap<String, String>, path: &Path) -> anyhow::Result<()> {
+	let existing = if path.exists() {
+		std::fs::read_to_string(path).context("Failed to read existing Sponge config")?
+	} else {
+		String::new()
+	};
+
+	let contents = replace_managed_block(&existing, &keys);
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent).context("Failed to create Sponge config directory")?;
+	}
+	std::fs::write(path, contents).context("Failed to write Sponge config")?;
+
+	Ok(())
+}
+
+/// Replace the block of options managed by mcvm in the contents of a config file
+fn replace_managed_block(contents: &str, keys: &HashMap<String, String>) -> String {
+	let mut out = String::new();
+	let mut in_block = false;
+	for line in contents.lines() {
+		match line.trim() {
+			BLOCK_START => in_block = true,
+			BLOCK_END => in_block = false,
+			_ if !in_block => {
+				out.push_str(line);
+				out.push('\n');
+			}
+			_ => {}
+		}
+	}
+
+	if keys.is_empty() {
+		return out;
+	}
+	if !out.is_empty() && !out.ends_with("\n\n") {
+		out.push('\n');
+	}
+	out.push_str(BLOCK_START);
+	out.push('\n');
+	for (key, value) in keys.iter().sorted_by_key(|x| x.0) {
+		out.push_str(&format!("{key}={value}\n"));
+	}
+	out.push_str(BLOCK_END);
+	out.push('\n');
+
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_managed_block() {
+		let options = SpongeOptions {
+			ip_forwarding: Some(true),
+			auto_save_interval: Some(900),
+			..Default::default()
+		};
+		let keys = create_sponge_keys(&options);
+		let generated = "sponge {\n    world {\n        auto-save-interval=0\n    }\n}\n";
+
+		let written = replace_managed_block(generated, &keys);
+		assert_eq!(
+			written,
+			format!(
+				"{generated}\n{BLOCK_START}\nsponge.bungeecord.ip-forwarding=true\nsponge.modules.bungeecord=true\nsponge.world.auto-save-interval=900\n{BLOCK_END}\n"
+			)
+		);
+
+		// Writing again replaces the old block instead of adding another one
+		assert_eq!(replace_managed_block(&written, &keys), written);
+		assert_eq!(
+			replace_managed_block(&written, &HashMap::new()),
+			format!("{generated}\n")
+		);
+	}
+}
@@ -144,7 +144,7 @@ impl ConditionKind {
 			"defined" => Some(Self::Defined(Later::Empty)),
 			"os" => Some(Self::OS(Later::Empty)),
 			"stability" => Some(Self::Stability(Later::Empty)),
-			"language" => Some(Self::Language(Later::Empty)),
+			"language" | "lang" => Some(Self::Language(Later::Empty)),
 			_ => None,
 		}
 	}
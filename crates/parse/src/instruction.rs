@@ -1,9 +1,10 @@
 use std::fmt::Display;
 
 use anyhow::bail;
+use mcvm_shared::lang::Language;
 use mcvm_shared::later::Later;
 use mcvm_shared::modifications::{ModloaderMatch, PluginLoaderMatch};
-use mcvm_shared::pkg::PackageAddonHashes;
+use mcvm_shared::pkg::{ConfigFilePolicy, PackageAddonHashes};
 use mcvm_shared::util::yes_no;
 use mcvm_shared::versions::VersionPattern;
 use mcvm_shared::Side;
@@ -76,6 +77,10 @@ pub enum InstrKind {
 	Features(Vec<String>),
 	/// Set the package default features property
 	DefaultFeatures(Vec<String>),
+	/// Describe one of the package's features
+	FeatureDescription(Later<String>, Later<String>),
+	/// Declare a group of the package's features
+	FeatureGroup(Later<String>, Vec<String>),
 	/// Set the package content versions property
 	ContentVersions(Vec<String>),
 	/// Set the package Modrinth ID property
@@ -117,6 +122,17 @@ pub enum InstrKind {
 		/// The addon's hashes
 		hashes: PackageAddonHashes<Value>,
 	},
+	/// Install a configuration file into the instance
+	ConfigFile {
+		/// How to combine the file with an existing one
+		policy: Later<ConfigFilePolicy>,
+		/// The path to the file, relative to the game directory
+		path: Value,
+		/// The contents of the file, or the URL to download them from
+		value: Value,
+		/// Whether the value is a URL instead of the contents
+		url: bool,
+	},
 	/// Set a variable to a value
 	Set(Later<String>, Value),
 	/// Require a package
@@ -137,6 +153,8 @@ pub enum InstrKind {
 	Fail(Option<FailReason>),
 	/// Present a notice to the user
 	Notice(Value),
+	/// Present a notice to the user in their language, with a message for each language
+	NoticeLocalized(Vec<(Language, Value)>),
 	/// Run a command
 	Cmd(Vec<Value>),
 	/// Call another routine
@@ -180,6 +198,8 @@ impl Display for InstrKind {
 				Self::Categories(..) => "categories",
 				Self::Features(..) => "features",
 				Self::DefaultFeatures(..) => "default_features",
+				Self::FeatureDescription(..) => "feature_description",
+				Self::FeatureGroup(..) => "feature_group",
 				Self::ContentVersions(..) => "content_versions",
 				Self::ModrinthID(..) => "modrinth_id",
 				Self::CurseForgeID(..) => "curseforge_id",
@@ -193,6 +213,8 @@ impl Display for InstrKind {
 				Self::Tags(..) => "tags",
 				Self::OpenSource(..) => "open_source",
 				Self::Addon { .. } => "addon",
+				Self::ConfigFile { url: false, .. } => "config_file",
+				Self::ConfigFile { url: true, .. } => "config_file_url",
 				Self::Set(..) => "set",
 				Self::Require(..) => "require",
 				Self::Refuse(..) => "refuse",
@@ -203,6 +225,7 @@ impl Display for InstrKind {
 				Self::Finish() => "finish",
 				Self::Fail(..) => "fail",
 				Self::Notice(..) => "notice",
+				Self::NoticeLocalized(..) => "notice_localized",
 				Self::Cmd(..) => "cmd",
 				Self::Call(..) => "call",
 				Self::Custom(..) => "custom",
@@ -238,6 +261,8 @@ impl Instruction {
 			"categories" => Ok(InstrKind::Categories(Vec::new())),
 			"features" => Ok(InstrKind::Features(Vec::new())),
 			"default_features" => Ok(InstrKind::DefaultFeatures(Vec::new())),
+			"feature_description" => Ok(InstrKind::FeatureDescription(Later::Empty, Later::Empty)),
+			"feature_group" => Ok(InstrKind::FeatureGroup(Later::Empty, Vec::new())),
 			"content_versions" => Ok(InstrKind::ContentVersions(Vec::new())),
 			"modrinth_id" => Ok(InstrKind::ModrinthID(Later::Empty)),
 			"curseforge_id" => Ok(InstrKind::CurseForgeID(Later::Empty)),
@@ -249,6 +274,18 @@ impl Instruction {
 			"supported_architectures" => Ok(InstrKind::SupportedArchitectures(Vec::new())),
 			"tags" => Ok(InstrKind::Tags(Vec::new())),
 			"open_source" => Ok(InstrKind::OpenSource(Later::Empty)),
+			"config_file" => Ok(InstrKind::ConfigFile {
+				policy: Later::Empty,
+				path: Value::None,
+				value: Value::None,
+				url: false,
+			}),
+			"config_file_url" => Ok(InstrKind::ConfigFile {
+				policy: Later::Empty,
+				path: Value::None,
+				value: Value::None,
+				url: true,
+			}),
 			"set" => Ok(InstrKind::Set(Later::Empty, Value::None)),
 			"finish" => Ok(InstrKind::Finish()),
 			"fail" => Ok(InstrKind::Fail(None)),
@@ -258,6 +295,7 @@ impl Instruction {
 			"compat" => Ok(InstrKind::Compat(Value::None, Value::None)),
 			"extend" => Ok(InstrKind::Extend(Value::None)),
 			"notice" => Ok(InstrKind::Notice(Value::None)),
+			"notice_localized" => Ok(InstrKind::NoticeLocalized(Vec::new())),
 			"call" => Ok(InstrKind::Call(Later::Empty)),
 			"custom" => Ok(InstrKind::Custom(Later::Empty)),
 			string => bail!("Unknown instruction '{string}' {}", pos),
@@ -308,9 +346,22 @@ impl Instruction {
 			InstrKind::SupportedOperatingSystems(val) => !val.is_empty(),
 			InstrKind::SupportedArchitectures(val) => !val.is_empty(),
 			InstrKind::OpenSource(val) => val.is_full(),
+			InstrKind::FeatureDescription(feature, description) => {
+				feature.is_full() && description.is_full()
+			}
+			InstrKind::FeatureGroup(group, list) => group.is_full() && !list.is_empty(),
 			InstrKind::Compat(val1, val2) => val1.is_some() && val2.is_some(),
 			InstrKind::Set(var, val) => var.is_full() && val.is_some(),
+			InstrKind::ConfigFile {
+				policy,
+				path,
+				value,
+				..
+			} => policy.is_full() && path.is_some() && value.is_some(),
 			InstrKind::Cmd(list) => !list.is_empty(),
+			InstrKind::NoticeLocalized(list) => {
+				matches!(list.last(), Some((_, message)) if message.is_some())
+			}
 			InstrKind::Fail(..) | InstrKind::Finish() => true,
 			InstrKind::If { .. } | InstrKind::Addon { .. } | InstrKind::Require(..) => {
 				unimplemented!()
@@ -368,6 +419,16 @@ impl Instruction {
 				| InstrKind::Tags(list)
 				| InstrKind::Gallery(list) => list.push(parse_string(tok, pos)?),
 				InstrKind::Cmd(list) => list.push(parse_arg(tok, pos)?),
+				InstrKind::NoticeLocalized(list) => match list.last_mut() {
+					Some((_, message @ Value::None)) => *message = parse_arg(tok, pos)?,
+					_ => match tok {
+						Token::Ident(name) => match Language::parse_from_str(name) {
+							Some(lang) => list.push((lang, Value::None)),
+							None => bail!("Unknown language '{name}' {}", pos.clone()),
+						},
+						_ => unexpected_token!(tok, pos),
+					},
+				},
 				InstrKind::Recommend(inverted, val) => match tok {
 					Token::Bang => {
 						if *inverted || val.is_some() {
@@ -430,6 +491,22 @@ impl Instruction {
 						unexpected_token!(tok, pos);
 					}
 				}
+				InstrKind::FeatureDescription(feature, description) => {
+					if feature.is_empty() {
+						feature.fill(parse_string(tok, pos)?);
+					} else if description.is_empty() {
+						description.fill(parse_string(tok, pos)?);
+					} else {
+						unexpected_token!(tok, pos);
+					}
+				}
+				InstrKind::FeatureGroup(group, list) => {
+					if group.is_empty() {
+						group.fill(parse_string(tok, pos)?);
+					} else {
+						list.push(parse_string(tok, pos)?);
+					}
+				}
 				InstrKind::Set(var, val) => {
 					if var.is_full() {
 						if let Value::None = val {
@@ -444,6 +521,30 @@ impl Instruction {
 						}
 					}
 				}
+				InstrKind::ConfigFile {
+					policy,
+					path,
+					value,
+					..
+				} => {
+					if policy.is_empty() {
+						match tok {
+							Token::Ident(name) => match ConfigFilePolicy::parse_from_str(name) {
+								Some(val) => policy.fill(val),
+								None => {
+									bail!("Unknown config file policy '{name}' {}", pos.clone())
+								}
+							},
+							_ => unexpected_token!(tok, pos),
+						}
+					} else if let Value::None = path {
+						*path = parse_arg(tok, pos)?;
+					} else if let Value::None = value {
+						*value = parse_arg(tok, pos)?;
+					} else {
+						unexpected_token!(tok, pos);
+					}
+				}
 				InstrKind::Fail(reason) => match tok {
 					Token::Ident(name) => {
 						if reason.is_none() {
@@ -5,6 +5,7 @@ use anyhow::bail;
 
 /// Create a list of tokens from package text contents that we will
 /// then use for parsing
+#[allow(clippy::collapsible_match)]
 pub fn lex(text: &str) -> anyhow::Result<Vec<(Token, TextPos)>> {
 	let mut tokens: Vec<(Token, TextPos)> = Vec::new();
 
@@ -729,6 +729,8 @@ pub fn lex_and_parse(text: &str) -> anyhow::Result<Parsed> {
 
 #[cfg(test)]
 mod tests {
+	use mcvm_shared::lang::Language;
+	use mcvm_shared::pkg::ConfigFilePolicy;
 	use mcvm_shared::{later::Later, modifications::ModloaderMatch};
 
 	use super::*;
@@ -801,6 +803,84 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn test_language_condition_parse() {
+		for text in [
+			"@install { if language german {} }",
+			"@install { if lang german {} }",
+		] {
+			let parsed = lex_and_parse(text).unwrap();
+			let block = parsed
+				.blocks
+				.get(parsed.routines.get(INSTALL_ROUTINE).unwrap())
+				.unwrap();
+			let instr = block.contents.first().unwrap();
+			let InstrKind::If { condition, .. } = &instr.kind else {
+				panic!("Instruction is not an if");
+			};
+			assert_eq!(
+				condition.kind,
+				ConditionKind::Language(Later::Full(Language::German))
+			);
+		}
+
+		assert!(lex_and_parse("@install { if lang not_a_language {} }").is_err());
+	}
+
+	#[test]
+	fn test_localized_notice_parse() {
+		let text = r#"@install { notice_localized american_english "Hello" german $message; }"#;
+		let parsed = lex_and_parse(text).unwrap();
+		let block = parsed
+			.blocks
+			.get(parsed.routines.get(INSTALL_ROUTINE).unwrap())
+			.unwrap();
+		let instr = block.contents.first().unwrap();
+		let InstrKind::NoticeLocalized(messages) = &instr.kind else {
+			panic!("Instruction is not a localized notice");
+		};
+		assert_eq!(messages.len(), 2);
+		assert!(matches!(
+			&messages[0],
+			(Language::AmericanEnglish, Value::Literal(text)) if text == "Hello"
+		));
+		assert!(matches!(
+			&messages[1],
+			(Language::German, Value::Var(var)) if var == "message"
+		));
+
+		assert!(lex_and_parse("@install { notice_localized german; }").is_err());
+		assert!(lex_and_parse(r#"@install { notice_localized "Hello"; }"#).is_err());
+	}
+
+	#[test]
+	fn test_config_file_parse() {
+		let text = r#"@install { config_file_url json_merge "config/foo.json" $url; }"#;
+		let parsed = lex_and_parse(text).unwrap();
+		let block = parsed
+			.blocks
+			.get(parsed.routines.get(INSTALL_ROUTINE).unwrap())
+			.unwrap();
+		let instr = block.contents.first().unwrap();
+		let InstrKind::ConfigFile {
+			policy,
+			path,
+			value,
+			url,
+		} = &instr.kind
+		else {
+			panic!("Instruction is not a config file");
+		};
+		assert_eq!(policy.get(), &ConfigFilePolicy::JsonMerge);
+		assert!(matches!(path, Value::Literal(path) if path == "config/foo.json"));
+		assert!(matches!(value, Value::Var(var) if var == "url"));
+		assert!(url);
+
+		assert!(lex_and_parse(r#"@install { config_file "foo.txt" "a"; }"#).is_err());
+		assert!(lex_and_parse(r#"@install { config_file replace "foo.txt" "a"; }"#).is_err());
+		assert!(lex_and_parse(r#"@install { config_file create "foo.txt"; }"#).is_err());
+	}
+
 	#[test]
 	fn test_if_else() {
 		let text = r#"@install {
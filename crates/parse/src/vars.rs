@@ -137,6 +137,11 @@ impl HashMapVariableStore {
 	pub fn new() -> Self {
 		Self(HashMap::new())
 	}
+
+	/// Iterate over the variables and their values
+	pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+		self.0.iter()
+	}
 }
 
 impl VariableStore for HashMapVariableStore {
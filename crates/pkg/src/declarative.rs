@@ -5,7 +5,7 @@ use mcvm_parse::conditions::{ArchCondition, OSCondition};
 use mcvm_shared::addon::AddonKind;
 use mcvm_shared::lang::Language;
 use mcvm_shared::modifications::{ModloaderMatch, PluginLoaderMatch};
-use mcvm_shared::pkg::{PackageAddonOptionalHashes, PackageStability};
+use mcvm_shared::pkg::{ConfigFilePolicy, PackageAddonOptionalHashes, PackageStability};
 use mcvm_shared::util::DeserListOrSingle;
 use mcvm_shared::versions::VersionPattern;
 use mcvm_shared::Side;
@@ -37,6 +37,9 @@ pub struct DeclarativePackage {
 	/// Changes to conditionally apply to the package
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub conditional_rules: Vec<DeclarativeConditionalRule>,
+	/// Configuration files that the package installs
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub config_files: Vec<DeclarativeConfigFile>,
 }
 
 /// Package relationships for declarative packages
@@ -207,6 +210,29 @@ pub struct DeclarativeAddonVersion {
 	pub hashes: PackageAddonOptionalHashes,
 }
 
+/// Configuration file in a declarative package
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct DeclarativeConfigFile {
+	/// The path to the file, relative to the game directory
+	pub path: String,
+	/// The contents of the file
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub contents: Option<String>,
+	/// The URL to download the contents of the file from
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub url: Option<String>,
+	/// How to combine the file with an existing one
+	#[serde(default)]
+	pub policy: ConfigFilePolicy,
+	/// Conditions for this file to be installed
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub conditions: Vec<DeclarativeConditionSet>,
+}
+
 /// Properties for declarative addon versions that can be changed with patches
 #[derive(Deserialize, Serialize, Debug, Default, Clone)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -319,4 +345,29 @@ mod tests {
 
 		assert_eq!(pkg.meta.name, Some("Test Package".into()));
 	}
+
+	#[test]
+	fn test_config_file_deser() {
+		let contents = r#"
+			{
+				"config_files": [
+					{
+						"path": "config/foo.json",
+						"contents": "{}",
+						"policy": "json_merge"
+					},
+					{
+						"path": "config/bar.toml",
+						"url": "example.com"
+					}
+				]
+			}
+		"#;
+
+		let pkg = deserialize_declarative_package(contents).unwrap();
+
+		assert_eq!(pkg.config_files.len(), 2);
+		assert_eq!(pkg.config_files[0].policy, ConfigFilePolicy::JsonMerge);
+		assert_eq!(pkg.config_files[1].policy, ConfigFilePolicy::Create);
+	}
 }
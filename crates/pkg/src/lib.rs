@@ -35,23 +35,35 @@ pub use mcvm_shared::pkg::{PkgRequest, PkgRequestSource};
 
 /// Parses and validates a package
 pub fn parse_and_validate(contents: &str, content_type: PackageContentType) -> anyhow::Result<()> {
+	parse_and_evaluate(contents, content_type)?;
+
+	Ok(())
+}
+
+/// Parses and validates a package, returning its metadata and properties
+pub fn parse_and_evaluate(
+	contents: &str,
+	content_type: PackageContentType,
+) -> anyhow::Result<(PackageMetadata, PackageProperties)> {
 	match content_type {
 		PackageContentType::Script => {
 			let parsed = parse::parse::lex_and_parse(contents).context("Parsing failed")?;
-			metadata::eval_metadata(&parsed).context("Metadata evaluation failed")?;
-			properties::eval_properties(&parsed).context("Properties evaluation failed")?;
+			let metadata =
+				metadata::eval_metadata(&parsed).context("Metadata evaluation failed")?;
+			let properties =
+				properties::eval_properties(&parsed).context("Properties evaluation failed")?;
+			Ok((metadata, properties))
 		}
 		PackageContentType::Declarative => {
 			let contents = deserialize_declarative_package(contents).context("Parsing failed")?;
 			validate_declarative_package(&contents).context("Package was invalid")?;
+			Ok((contents.meta, contents.properties))
 		}
 	}
-
-	Ok(())
 }
 
 /// Content type of a package
-#[derive(Deserialize, Serialize, Debug, Copy, Clone, Default)]
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum PackageContentType {
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use anyhow::{bail, ensure};
 use mcvm_parse::conditions::{ArchCondition, OSCondition};
 use mcvm_shared::modifications::{ModloaderMatch, PluginLoaderMatch};
@@ -19,6 +21,12 @@ pub struct PackageProperties {
 	/// Features enabled by default
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub default_features: Option<Vec<String>>,
+	/// Descriptions for the available features
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub feature_descriptions: Option<HashMap<String, String>>,
+	/// Named groups of features that are expanded when they are configured
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub feature_groups: Option<HashMap<String, Vec<String>>>,
 	/// List of available content versions in order
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub content_versions: Option<Vec<String>>,
@@ -61,8 +69,8 @@ impl PackageProperties {
 	/// Check the validity of the properties
 	pub fn check_validity(&self) -> anyhow::Result<()> {
 		// Validate features
-		if let Some(default_features) = &self.default_features {
-			if let Some(features) = &self.features {
+		if let Some(features) = &self.features {
+			if let Some(default_features) = &self.default_features {
 				for feature in default_features {
 					ensure!(
 						features.contains(feature),
@@ -70,15 +78,66 @@ impl PackageProperties {
 					);
 				}
 			}
+			if let Some(descriptions) = &self.feature_descriptions {
+				for feature in descriptions.keys() {
+					ensure!(
+						features.contains(feature),
+						"Described feature '{feature}' does not exist"
+					);
+				}
+			}
+			if let Some(groups) = &self.feature_groups {
+				for (group, group_features) in groups {
+					ensure!(
+						!features.contains(group),
+						"Feature group '{group}' has the same name as a feature"
+					);
+					for feature in group_features {
+						ensure!(
+							features.contains(feature),
+							"Feature '{feature}' in group '{group}' does not exist"
+						);
+					}
+				}
+			}
 		}
 
 		Ok(())
 	}
 
+	/// Check whether a feature or feature group is declared by these properties
+	pub fn has_feature(&self, feature: &str) -> bool {
+		let is_feature = self
+			.features
+			.as_ref()
+			.is_some_and(|x| x.iter().any(|x| x == feature));
+		let is_group = self
+			.feature_groups
+			.as_ref()
+			.is_some_and(|x| x.contains_key(feature));
+		is_feature || is_group
+	}
+
+	/// Expand any feature groups in a list of features into the features they contain
+	pub fn expand_feature_groups(&self, features: &[String]) -> Vec<String> {
+		let mut out = Vec::with_capacity(features.len());
+		for feature in features {
+			if let Some(group) = self.feature_groups.as_ref().and_then(|x| x.get(feature)) {
+				out.extend(group.iter().cloned());
+			} else {
+				out.push(feature.clone());
+			}
+		}
+
+		out
+	}
+
 	/// Check if all properties are empty
 	pub fn is_empty(&self) -> bool {
 		self.features.is_none()
 			&& self.default_features.is_none()
+			&& self.feature_descriptions.is_none()
+			&& self.feature_groups.is_none()
 			&& self.modrinth_id.is_none()
 			&& self.curseforge_id.is_none()
 			&& self.smithed_id.is_none()
@@ -104,6 +163,16 @@ pub fn eval_properties(parsed: &Parsed) -> anyhow::Result<PackageProperties> {
 				match &instr.kind {
 					InstrKind::Features(list) => out.features = Some(list.clone()),
 					InstrKind::DefaultFeatures(list) => out.default_features = Some(list.clone()),
+					InstrKind::FeatureDescription(feature, description) => {
+						out.feature_descriptions
+							.get_or_insert_with(HashMap::new)
+							.insert(feature.get_clone(), description.get_clone());
+					}
+					InstrKind::FeatureGroup(group, list) => {
+						out.feature_groups
+							.get_or_insert_with(HashMap::new)
+							.insert(group.get_clone(), list.clone());
+					}
 					InstrKind::ContentVersions(list) => out.content_versions = Some(list.clone()),
 					InstrKind::ModrinthID(id) => out.modrinth_id = Some(id.get_clone()),
 					InstrKind::CurseForgeID(id) => out.curseforge_id = Some(id.get_clone()),
@@ -140,3 +209,38 @@ pub fn eval_properties(parsed: &Parsed) -> anyhow::Result<PackageProperties> {
 		Ok(PackageProperties::default())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::parse::parse::lex_and_parse;
+
+	#[test]
+	fn test_feature_properties() {
+		let text = r#"@properties {
+			features "shaders" "particles" "sounds";
+			default_features "sounds";
+			feature_description "shaders" "Better looking shaders";
+			feature_group "all-visuals" "shaders" "particles";
+		}"#;
+		let parsed = lex_and_parse(text).unwrap();
+		let properties = eval_properties(&parsed).unwrap();
+		assert_eq!(
+			properties.feature_descriptions.as_ref().unwrap()["shaders"],
+			"Better looking shaders"
+		);
+		assert!(properties.has_feature("all-visuals"));
+		assert!(!properties.has_feature("music"));
+		assert_eq!(
+			properties.expand_feature_groups(&["all-visuals".into(), "sounds".into()]),
+			vec!["shaders", "particles", "sounds"]
+		);
+
+		let text = r#"@properties {
+			features "shaders";
+			feature_group "all-visuals" "shaders" "particles";
+		}"#;
+		let parsed = lex_and_parse(text).unwrap();
+		assert!(eval_properties(&parsed).is_err());
+	}
+}
@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use std::collections::{HashMap, HashSet};
 
+use crate::properties::PackageProperties;
 use crate::PackageContentType;
 
 /// JSON format for a repository index
@@ -49,6 +50,18 @@ pub struct RepoPkgEntry {
 	/// Flags for this package
 	#[serde(default)]
 	pub flags: HashSet<PackageFlag>,
+	/// The hex SHA-256 hash of the package file. Cached copies that don't match it are downloaded again
+	#[serde(default)]
+	pub hash: Option<String>,
+	/// The display name of the package, so that it can be searched for without being downloaded
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub name: Option<String>,
+	/// The short description of the package, used for searching
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub description: Option<String>,
+	/// The properties of the package, used to filter search results
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub properties: Option<PackageProperties>,
 }
 
 /// Flags that can be applied to packages by repositories to provide information about them
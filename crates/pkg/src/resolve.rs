@@ -1,9 +1,12 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 
 use anyhow::{bail, Context};
 use itertools::Itertools;
 use mcvm_shared::pkg::{ArcPkgReq, PackageID};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 use crate::properties::PackageProperties;
 use crate::{ConfiguredPackage, PackageEvalRelationsResult, PackageEvaluator};
@@ -11,6 +14,7 @@ use crate::{ConfiguredPackage, PackageEvalRelationsResult, PackageEvaluator};
 use crate::{PkgRequest, PkgRequestSource};
 
 /// Find all package dependencies from a set of required packages
+#[allow(clippy::collapsible_match)]
 pub async fn resolve<'a, E: PackageEvaluator<'a>>(
 	packages: &[E::ConfiguredPackage],
 	mut evaluator: E,
@@ -21,6 +25,7 @@ pub async fn resolve<'a, E: PackageEvaluator<'a>>(
 		tasks: VecDeque::new(),
 		constraints: Vec::new(),
 		constant_input: constant_eval_input,
+		required_by: HashMap::new(),
 	};
 
 	// Create the initial EvalPackage from the installed packages
@@ -81,9 +86,14 @@ pub async fn resolve<'a, E: PackageEvaluator<'a>>(
 		}
 	}
 
+	let relations = PackageRelations {
+		reasons: resolver.get_install_reasons(),
+		required_by: resolver.required_by.clone(),
+	};
 	let out = ResolutionResult {
 		packages: resolver.collect_packages(),
 		unfulfilled_recommendations,
+		relations,
 	};
 
 	Ok(out)
@@ -95,6 +105,72 @@ pub struct ResolutionResult {
 	pub packages: Vec<ArcPkgReq>,
 	/// Package recommendations that were not satisfied
 	pub unfulfilled_recommendations: Vec<RecommendedPackage>,
+	/// Why each of the packages is installed
+	pub relations: PackageRelations,
+}
+
+/// Why a package was installed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum InstallReason {
+	/// The package was configured by the user
+	Explicit,
+	/// The package was required by another package
+	Dependency,
+	/// The package was bundled by another package
+	Bundled,
+}
+
+/// How the resolved packages relate to each other
+#[derive(Debug, Clone, Default)]
+pub struct PackageRelations {
+	/// Why each package was installed
+	pub reasons: HashMap<PackageID, InstallReason>,
+	/// The packages that depend on, bundle, or need compatibility with each package
+	pub required_by: HashMap<PackageID, Vec<PackageID>>,
+}
+
+impl PackageRelations {
+	/// Get why a package was installed. Packages that weren't resolved count as dependencies
+	pub fn get_reason(&self, package: &str) -> InstallReason {
+		self.reasons
+			.get(package)
+			.copied()
+			.unwrap_or(InstallReason::Dependency)
+	}
+
+	/// Get the packages that required a package
+	pub fn get_required_by(&self, package: &str) -> Vec<PackageID> {
+		self.required_by.get(package).cloned().unwrap_or_default()
+	}
+}
+
+/// Find the packages that can be reached from a set of root packages by following the packages
+/// that they require. `required_by` maps each package to the packages that required it
+pub fn get_reachable_packages(
+	roots: impl IntoIterator<Item = PackageID>,
+	required_by: &HashMap<PackageID, Vec<PackageID>>,
+) -> HashSet<PackageID> {
+	let mut requires: HashMap<&PackageID, Vec<&PackageID>> = HashMap::new();
+	for (package, parents) in required_by {
+		for parent in parents {
+			requires.entry(parent).or_default().push(package);
+		}
+	}
+
+	let mut out = HashSet::new();
+	let mut queue: VecDeque<PackageID> = roots.into_iter().collect();
+	while let Some(package) = queue.pop_front() {
+		if !out.insert(package.clone()) {
+			continue;
+		}
+		if let Some(children) = requires.get(&package) {
+			queue.extend(children.iter().map(|x| (*x).clone()));
+		}
+	}
+
+	out
 }
 
 /// Recommended package that has a PkgRequest instead of a String
@@ -179,6 +255,7 @@ async fn resolve_eval_package<'a, E: PackageEvaluator<'a>>(
 			bail!("Package '{req}' has been explicitly required by this package. This means it must be required by the user in their config.");
 		}
 		resolver.check_constraints(&req)?;
+		resolver.add_required_by(&req.id, &package.id);
 		if !resolver.is_required(&req) {
 			resolver.constraints.push(Constraint {
 				kind: ConstraintKind::Require(req.clone()),
@@ -196,6 +273,7 @@ async fn resolve_eval_package<'a, E: PackageEvaluator<'a>>(
 			PkgRequestSource::Bundled(package.clone()),
 		));
 		resolver.check_constraints(&req)?;
+		resolver.add_required_by(&req.id, &package.id);
 		resolver.remove_require_constraint(&req);
 		resolver.constraints.push(Constraint {
 			kind: ConstraintKind::Bundle(req.clone()),
@@ -267,6 +345,8 @@ struct Resolver<'a, E: PackageEvaluator<'a>> {
 	tasks: VecDeque<Task<'a, E>>,
 	constraints: Vec<Constraint>,
 	constant_input: E::EvalInput<'a>,
+	/// The packages that required each package
+	required_by: HashMap<PackageID, Vec<PackageID>>,
 }
 
 impl<'a, E> Resolver<'a, E>
@@ -368,12 +448,25 @@ where
 		Ok(())
 	}
 
+	/// Remember that a package was required by another package
+	pub fn add_required_by(&mut self, package: &PackageID, parent: &PackageID) {
+		let parents = self.required_by.entry(package.clone()).or_default();
+		if !parents.contains(parent) {
+			parents.push(parent.clone());
+		}
+	}
+
 	/// Checks compat constraints to see if new constraints are needed
 	pub fn check_compats(&mut self) {
 		let mut constraints_to_add = Vec::new();
+		let mut relations = Vec::new();
 		for constraint in &self.constraints {
 			if let ConstraintKind::Compat(package, compat_package) = &constraint.kind {
-				if self.is_required(package) && !self.is_required(compat_package) {
+				if !self.is_required(package) {
+					continue;
+				}
+				relations.push((compat_package.id.clone(), package.id.clone()));
+				if !self.is_required(compat_package) {
 					constraints_to_add.push(Constraint {
 						kind: ConstraintKind::Require(compat_package.clone()),
 					});
@@ -385,6 +478,30 @@ where
 			}
 		}
 		self.constraints.extend(constraints_to_add);
+		for (package, parent) in relations {
+			self.add_required_by(&package, &parent);
+		}
+	}
+
+	/// Get why each of the required packages is installed
+	pub fn get_install_reasons(&self) -> HashMap<PackageID, InstallReason> {
+		let mut out = HashMap::new();
+		for constraint in &self.constraints {
+			let (dest, reason) = match &constraint.kind {
+				ConstraintKind::UserRequire(dest) => (dest, InstallReason::Explicit),
+				ConstraintKind::Bundle(dest) => (dest, InstallReason::Bundled),
+				ConstraintKind::Require(dest) => (dest, InstallReason::Dependency),
+				_ => continue,
+			};
+			// Packages that the user configured are always explicit, even if something else
+			// also requires them
+			let current = out.entry(dest.id.clone()).or_insert(reason);
+			if reason == InstallReason::Explicit {
+				*current = reason;
+			}
+		}
+
+		out
 	}
 
 	/// Collect all needed packages for final output
@@ -432,3 +549,254 @@ enum Task<'a, E: PackageEvaluator<'a>> {
 fn package_context_error_message(package: &PkgRequest) -> String {
 	format!("In package '{}'", package.debug_sources())
 }
+
+#[cfg(test)]
+mod tests {
+	use async_trait::async_trait;
+
+	use super::*;
+	use crate::RequiredPackage;
+
+	/// The relationships of a test package
+	#[derive(Default, Clone)]
+	struct TestRelations {
+		deps: Vec<&'static str>,
+		bundled: Vec<&'static str>,
+		compats: Vec<(&'static str, &'static str)>,
+	}
+
+	impl PackageEvalRelationsResult for TestRelations {
+		fn get_deps(&self) -> Vec<Vec<RequiredPackage>> {
+			let deps = self.deps.iter().map(|x| RequiredPackage {
+				value: (*x).into(),
+				explicit: false,
+			});
+			vec![deps.collect()]
+		}
+
+		fn get_conflicts(&self) -> Vec<PackageID> {
+			Vec::new()
+		}
+
+		fn get_recommendations(&self) -> Vec<crate::RecommendedPackage> {
+			Vec::new()
+		}
+
+		fn get_bundled(&self) -> Vec<PackageID> {
+			self.bundled.iter().map(|x| (*x).into()).collect()
+		}
+
+		fn get_compats(&self) -> Vec<(PackageID, PackageID)> {
+			let compats = self.compats.iter();
+			compats.map(|(x, y)| ((*x).into(), (*y).into())).collect()
+		}
+
+		fn get_extensions(&self) -> Vec<PackageID> {
+			Vec::new()
+		}
+	}
+
+	/// Evaluates packages from a fixed set of relationships
+	struct TestEvaluator {
+		packages: HashMap<PackageID, TestRelations>,
+		properties: PackageProperties,
+	}
+
+	#[async_trait]
+	impl<'a> PackageEvaluator<'a> for TestEvaluator {
+		type CommonInput = ();
+		type EvalInput<'b> = ();
+		type EvalRelationsResult<'b> = TestRelations;
+		type ConfiguredPackage = TestConfiguredPackage;
+
+		async fn eval_package_relations(
+			&mut self,
+			pkg: &ArcPkgReq,
+			_: &Self::EvalInput<'a>,
+			_: &Self::CommonInput,
+		) -> anyhow::Result<Self::EvalRelationsResult<'a>> {
+			Ok(self.packages.get(&pkg.id).cloned().unwrap_or_default())
+		}
+
+		async fn get_package_properties<'b>(
+			&'b mut self,
+			_: &ArcPkgReq,
+			_: &Self::CommonInput,
+		) -> anyhow::Result<&'b PackageProperties> {
+			Ok(&self.properties)
+		}
+	}
+
+	#[derive(Clone)]
+	struct TestConfiguredPackage(ArcPkgReq);
+
+	impl ConfiguredPackage for TestConfiguredPackage {
+		type EvalInput<'a> = ();
+
+		fn get_package(&self) -> ArcPkgReq {
+			self.0.clone()
+		}
+
+		fn override_configured_package_input(
+			&self,
+			_: &PackageProperties,
+			_: &mut Self::EvalInput<'_>,
+		) -> anyhow::Result<()> {
+			Ok(())
+		}
+	}
+
+	fn resolve_test_packages(
+		user_packages: &[&str],
+		packages: Vec<(&str, TestRelations)>,
+	) -> PackageRelations {
+		let user_packages: Vec<_> = user_packages
+			.iter()
+			.map(|x| {
+				let req = PkgRequest::parse(*x, PkgRequestSource::UserRequire);
+				TestConfiguredPackage(Arc::new(req))
+			})
+			.collect();
+		let evaluator = TestEvaluator {
+			packages: packages.into_iter().map(|(x, y)| (x.into(), y)).collect(),
+			properties: PackageProperties::default(),
+		};
+
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let result = runtime
+			.block_on(resolve(&user_packages, evaluator, (), &()))
+			.unwrap();
+		result.relations
+	}
+
+	#[test]
+	fn test_install_reasons() {
+		// a depends on b and c, and bundles d. c depends on e, and also bundles b,
+		// and a needs f for compatibility whenever e is installed
+		let relations = resolve_test_packages(
+			&["a", "c"],
+			vec![
+				(
+					"a",
+					TestRelations {
+						deps: vec!["b", "c"],
+						bundled: vec!["d"],
+						compats: vec![("e", "f")],
+					},
+				),
+				(
+					"c",
+					TestRelations {
+						deps: vec!["e"],
+						bundled: vec!["b"],
+						..Default::default()
+					},
+				),
+			],
+		);
+
+		assert_eq!(relations.get_reason("a"), InstallReason::Explicit);
+		// Packages that the user configured stay explicit when another package depends on them
+		assert_eq!(relations.get_reason("c"), InstallReason::Explicit);
+		// Bundling a package that is already a dependency makes it bundled
+		assert_eq!(relations.get_reason("b"), InstallReason::Bundled);
+		assert_eq!(relations.get_reason("d"), InstallReason::Bundled);
+		assert_eq!(relations.get_reason("e"), InstallReason::Dependency);
+		assert_eq!(relations.get_reason("f"), InstallReason::Dependency);
+		assert_eq!(relations.reasons.len(), 6);
+		assert_eq!(relations.get_reason("unknown"), InstallReason::Dependency);
+	}
+
+	#[test]
+	fn test_required_by() {
+		let relations = resolve_test_packages(
+			&["a", "c"],
+			vec![
+				(
+					"a",
+					TestRelations {
+						deps: vec!["b", "c"],
+						bundled: vec!["d"],
+						compats: vec![("e", "f")],
+					},
+				),
+				(
+					"c",
+					TestRelations {
+						deps: vec!["e"],
+						bundled: vec!["b"],
+						..Default::default()
+					},
+				),
+				// d is bundled by a and also depended on by e
+				(
+					"e",
+					TestRelations {
+						deps: vec!["d"],
+						..Default::default()
+					},
+				),
+			],
+		);
+
+		let required_by = |x| {
+			let parents = relations.get_required_by(x);
+			parents
+				.iter()
+				.map(ToString::to_string)
+				.sorted()
+				.collect_vec()
+		};
+		assert!(required_by("a").is_empty());
+		assert_eq!(required_by("b"), ["a", "c"]);
+		assert_eq!(required_by("c"), ["a"]);
+		assert_eq!(required_by("d"), ["a", "e"]);
+		assert_eq!(required_by("e"), ["c"]);
+		// Compatibility packages are required by the package that they are compatible with
+		assert_eq!(required_by("f"), ["e"]);
+		assert!(required_by("unknown").is_empty());
+
+		// Removing a still keeps the packages that c needs
+		let reachable = get_reachable_packages(["c".into()], &relations.required_by);
+		let expected: HashSet<PackageID> = ["b", "c", "e", "d", "f"].map(Into::into).into();
+		assert_eq!(reachable, expected);
+	}
+
+	#[test]
+	fn test_reachable_packages() {
+		// a -> b -> c, a -> d, e -> d, f is orphaned and g is only required by f
+		let required_by: HashMap<PackageID, Vec<PackageID>> = [
+			("b", vec!["a"]),
+			("c", vec!["b"]),
+			("d", vec!["a", "e"]),
+			("g", vec!["f"]),
+		]
+		.into_iter()
+		.map(|(id, parents)| (id.into(), parents.into_iter().map(Into::into).collect()))
+		.collect();
+
+		let reachable = get_reachable_packages(["a".into()], &required_by);
+		let expected: HashSet<PackageID> = ["a", "b", "c", "d"].map(Into::into).into();
+		assert_eq!(reachable, expected);
+
+		// Removing a keeps d alive through e, but not b and c
+		let reachable = get_reachable_packages(["e".into()], &required_by);
+		let expected: HashSet<PackageID> = ["e", "d"].map(Into::into).into();
+		assert_eq!(reachable, expected);
+
+		assert!(get_reachable_packages([], &required_by).is_empty());
+	}
+
+	#[test]
+	fn test_reachable_packages_cycle() {
+		let required_by: HashMap<PackageID, Vec<PackageID>> = [
+			("a".into(), vec!["b".into()]),
+			("b".into(), vec!["a".into()]),
+		]
+		.into();
+
+		let reachable = get_reachable_packages(["a".into()], &required_by);
+		assert_eq!(reachable.len(), 2);
+		assert!(get_reachable_packages(["c".into()], &required_by).len() == 1);
+	}
+}
@@ -7,7 +7,9 @@ use mcvm_parse::FailReason;
 
 use anyhow::{anyhow, bail, Context};
 use mcvm_shared::addon::AddonKind;
-use mcvm_shared::pkg::{PackageAddonOptionalHashes, PackageID};
+use mcvm_shared::lang::Language;
+use mcvm_shared::later::Later;
+use mcvm_shared::pkg::{ConfigFilePolicy, PackageAddonOptionalHashes, PackageID};
 use serde::{Deserialize, Serialize};
 
 use crate::{RecommendedPackage, RequiredPackage};
@@ -84,6 +86,13 @@ pub trait ScriptEvaluator {
 		addon: AddonInstructionData,
 	) -> anyhow::Result<()>;
 
+	/// Add a configuration file
+	fn add_config_file(
+		&mut self,
+		shared: &mut Self::Shared<'_>,
+		config_file: ConfigFileInstructionData,
+	) -> anyhow::Result<()>;
+
 	/// Run a custom instruction
 	fn run_custom(&mut self, shared: &mut Self::Shared<'_>, custom: String) -> anyhow::Result<()>;
 }
@@ -204,6 +213,7 @@ pub fn eval_instr<E: ScriptEvaluator>(
 						.with_context(|| "Failed to set variable".to_string())?;
 				}
 				InstrKind::Finish() => out.finish = true,
+				#[allow(unused_assignments)]
 				InstrKind::Fail(reason) => {
 					out.finish = true;
 					let reason = reason.as_ref().unwrap_or(&FailReason::None).clone();
@@ -264,6 +274,12 @@ pub fn eval_instr<E: ScriptEvaluator>(
 					let notice = notice.get(e.get_variable_store(shared))?;
 					e.add_notice(shared, notice)?;
 				}
+				InstrKind::NoticeLocalized(messages) => {
+					if let Some(notice) = select_localized_notice(e, shared, messages)? {
+						let notice = notice.get(e.get_variable_store(shared))?;
+						e.add_notice(shared, notice)?;
+					}
+				}
 				InstrKind::Cmd(command) => {
 					if let EvalReason::Install = config.reason {
 						let command = get_value_vec(command, e.get_variable_store(shared))?;
@@ -299,6 +315,29 @@ pub fn eval_instr<E: ScriptEvaluator>(
 						e.add_addon(shared, data)?;
 					}
 				}
+				InstrKind::ConfigFile {
+					policy,
+					path,
+					value,
+					url,
+				} => {
+					if let EvalReason::Install = config.reason {
+						let path = path.get(e.get_variable_store(shared))?;
+						let value = value.get(e.get_variable_store(shared))?;
+						let (contents, url) = if *url {
+							(None, Some(value))
+						} else {
+							(Some(value), None)
+						};
+						let data = ConfigFileInstructionData {
+							path,
+							contents,
+							url,
+							policy: *policy.get(),
+						};
+						e.add_config_file(shared, data)?;
+					}
+				}
 				_ => bail!("Instruction is not allowed in this routine context"),
 			},
 		}
@@ -330,6 +369,26 @@ impl Default for EvalResult {
 	}
 }
 
+/// Select the message of a localized notice that matches the user's language.
+/// Falls back to American English and then to the first message
+fn select_localized_notice<'a, E: ScriptEvaluator>(
+	e: &mut E,
+	shared: &mut E::Shared<'_>,
+	messages: &'a [(Language, Value)],
+) -> anyhow::Result<Option<&'a Value>> {
+	for (lang, message) in messages {
+		let condition = ConditionKind::Language(Later::Full(*lang));
+		if e.eval_condition(shared, &condition)? {
+			return Ok(Some(message));
+		}
+	}
+
+	let english = messages
+		.iter()
+		.find(|(lang, _)| *lang == Language::AmericanEnglish);
+	Ok(english.or(messages.first()).map(|(_, message)| message))
+}
+
 /// Utility function to convert a vec of values to a vec of strings
 fn get_value_vec(vec: &[Value], vars: &impl VariableStore) -> anyhow::Result<Vec<String>> {
 	let out = vec.iter().map(|x| x.get(vars));
@@ -355,3 +414,17 @@ pub struct AddonInstructionData {
 	/// The addon's hashes
 	pub hashes: PackageAddonOptionalHashes,
 }
+
+/// Data for implementing the config file instruction
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConfigFileInstructionData {
+	/// The path to the file, relative to the game directory
+	pub path: String,
+	/// The contents of the file; may not exist
+	pub contents: Option<String>,
+	/// The URL to download the contents of the file from; may not exist
+	pub url: Option<String>,
+	/// How to combine the file with an existing one
+	#[serde(default)]
+	pub policy: ConfigFilePolicy,
+}
@@ -1,15 +1,20 @@
 /// Output back to the main MCVM process
 pub mod output;
 
+use std::cell::OnceCell;
 use std::env::Args;
 use std::marker::PhantomData;
 use std::path::PathBuf;
 
-use anyhow::Context;
+use anyhow::{bail, Context};
+use mcvm_core::net::download::Client;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-use crate::hooks::{Hook, CONFIG_DIR_ENV, CUSTOM_CONFIG_ENV, DATA_DIR_ENV, PLUGIN_STATE_ENV};
+use crate::context::{InstalledPackageInfo, InstanceInfo, NetworkInfo, PluginContextData};
+use crate::hooks::{
+	Hook, CONFIG_DIR_ENV, CUSTOM_CONFIG_ENV, DATA_DIR_ENV, PLUGIN_CONTEXT_ENV, PLUGIN_STATE_ENV,
+};
 use crate::output::OutputAction;
 
 use self::output::PluginOutput;
@@ -59,6 +64,7 @@ impl CustomPlugin {
 		let ctx = StoredHookContext {
 			custom_config,
 			output: PluginOutput::new(settings.use_base64),
+			context: OnceCell::new(),
 		};
 		Ok(Self {
 			name: name.into(),
@@ -76,6 +82,11 @@ impl CustomPlugin {
 
 	hook_interface!(on_load, "on_load", OnLoad, |_| Ok(()));
 	hook_interface!(subcommand, "subcommand", Subcommand);
+	hook_interface!(
+		describe_subcommands,
+		"describe_subcommands",
+		DescribeSubcommands
+	);
 	hook_interface!(
 		modify_instance_config,
 		"modify_instance_config",
@@ -83,6 +94,11 @@ impl CustomPlugin {
 	);
 	hook_interface!(add_versions, "add_versions", AddVersions);
 	hook_interface!(on_instance_setup, "on_instance_setup", OnInstanceSetup);
+	hook_interface!(
+		on_instance_prepare_launch,
+		"on_instance_prepare_launch",
+		OnInstancePrepareLaunch
+	);
 	hook_interface!(on_instance_launch, "on_instance_launch", OnInstanceLaunch);
 	hook_interface!(
 		while_instance_launch,
@@ -103,6 +119,17 @@ impl CustomPlugin {
 		AddInstanceTransferFormat
 	);
 	hook_interface!(export_instance, "export_instance", ExportInstance);
+	hook_interface!(snapshot_instance, "snapshot_instance", SnapshotInstance);
+	hook_interface!(backup_instance, "backup_instance", BackupInstance);
+	hook_interface!(rename_instance, "rename_instance", RenameInstance);
+	hook_interface!(
+		add_package_repositories,
+		"add_package_repositories",
+		AddPackageRepositories
+	);
+	hook_interface!(provide_package, "provide_package", ProvidePackage);
+	hook_interface!(on_profile_update, "on_profile_update", OnProfileUpdate);
+	hook_interface!(ping, "ping", Ping);
 
 	/// Handle a hook
 	fn handle_hook<H: Hook>(
@@ -158,6 +185,8 @@ impl CustomPlugin {
 struct StoredHookContext {
 	custom_config: Option<String>,
 	output: PluginOutput,
+	/// The instance information shared by MCVM, which is read the first time it is needed
+	context: OnceCell<PluginContextData>,
 }
 
 /// Argument passed to every hook
@@ -188,6 +217,70 @@ impl<'ctx, H: Hook> HookContext<'ctx, H> {
 		get_env_path(CONFIG_DIR_ENV).context("Failed to get directory from environment variable")
 	}
 
+	/// Get information about an instance, such as its side, directories, and Minecraft version.
+	/// Use this instead of building the instance directories yourself
+	pub fn get_instance_info(&self, instance: &str) -> anyhow::Result<&InstanceInfo> {
+		self.get_context_data()?
+			.instances
+			.get(instance)
+			.with_context(|| format!("Instance '{instance}' does not exist"))
+	}
+
+	/// List the IDs of all of the user's instances, in sorted order
+	pub fn list_instances(&self) -> anyhow::Result<Vec<&str>> {
+		let mut out: Vec<_> = self
+			.get_context_data()?
+			.instances
+			.keys()
+			.map(String::as_str)
+			.collect();
+		out.sort();
+
+		Ok(out)
+	}
+
+	/// Get the packages that are installed on an instance according to the lockfile
+	pub fn get_installed_packages(
+		&self,
+		instance: &str,
+	) -> anyhow::Result<&[InstalledPackageInfo]> {
+		let data = self.get_context_data()?;
+		if !data.instances.contains_key(instance) {
+			bail!("Instance '{instance}' does not exist");
+		}
+
+		Ok(data
+			.packages
+			.get(instance)
+			.map(Vec::as_slice)
+			.unwrap_or_default())
+	}
+
+	/// Create an HTTP client that follows the user's network preferences, such as their proxy.
+	/// Use this instead of creating a client yourself
+	pub fn create_client(&self) -> anyhow::Result<Client> {
+		match self.get_context_data() {
+			Ok(data) => data.network.create_client(),
+			// Without shared information, we can still respect the standard proxy environment variables
+			Err(..) => NetworkInfo::default().create_client(),
+		}
+	}
+
+	/// Get the instance information that MCVM shares with plugins
+	fn get_context_data(&self) -> anyhow::Result<&PluginContextData> {
+		if let Some(data) = self.ctx.context.get() {
+			return Ok(data);
+		}
+
+		let path = get_env_path(PLUGIN_CONTEXT_ENV)
+			.context("MCVM did not share instance information with this hook")?;
+		let file = std::fs::File::open(path).context("Failed to open instance information")?;
+		let data = serde_json::from_reader(std::io::BufReader::new(file))
+			.context("Failed to read instance information")?;
+
+		Ok(self.ctx.context.get_or_init(|| data))
+	}
+
 	/// Get the persistent plugin state, kept the same for this entire hook handler,
 	/// along with a default state
 	pub fn get_persistent_state(
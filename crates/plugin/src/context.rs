@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use mcvm_core::net::download::{create_client, Client, ProxySettings, TimeoutSettings};
+use mcvm_pkg::resolve::InstallReason;
+use mcvm_shared::addon::AddonKind;
+use mcvm_shared::Side;
+use serde::{Deserialize, Serialize};
+
+/// The newest version of the instance information that is shared with plugins.
+/// Every field has a default, so plugins can read information from older and newer versions
+pub const CONTEXT_VERSION: u16 = 2;
+
+/// Information about the user's instances that MCVM shares with plugins.
+/// It is written to the file at the path in the [crate::hooks::PLUGIN_CONTEXT_ENV] environment variable
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PluginContextData {
+	/// The version of this data
+	pub version: u16,
+	/// The instances, keyed by their IDs
+	pub instances: HashMap<String, InstanceInfo>,
+	/// The packages installed on each instance according to the lockfile, keyed by instance ID
+	pub packages: HashMap<String, Vec<InstalledPackageInfo>>,
+	/// The network preferences that requests from plugins should follow
+	pub network: NetworkInfo,
+}
+
+/// Information about a single instance
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct InstanceInfo {
+	/// The ID of the instance
+	pub id: String,
+	/// The side of the instance
+	pub side: Option<Side>,
+	/// Path to the instance's dir
+	pub dir: String,
+	/// Path to the instance's game dir, which is different from the instance dir on clients
+	pub game_dir: String,
+	/// The profile that the instance derives from
+	pub profile: Option<String>,
+	/// The Minecraft version that the instance was last updated to. If the instance
+	/// hasn't been updated yet, this is the version from its config, which may be something like "latest"
+	pub version: Option<String>,
+}
+
+/// A package that is installed on an instance
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct InstalledPackageInfo {
+	/// The ID of the package
+	pub id: String,
+	/// Why the package was installed, if it is known
+	pub reason: Option<InstallReason>,
+	/// The addons that the package installed
+	pub addons: Vec<InstalledAddonInfo>,
+}
+
+/// An addon that a package installed on an instance
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct InstalledAddonInfo {
+	/// The ID of the addon
+	pub id: String,
+	/// The kind of the addon
+	pub kind: AddonKind,
+	/// The version of the addon, if it has one
+	pub version: Option<String>,
+	/// Paths to the files of the addon in the instance
+	pub files: Vec<String>,
+}
+
+impl Default for InstalledAddonInfo {
+	fn default() -> Self {
+		Self {
+			id: String::new(),
+			kind: AddonKind::Mod,
+			version: None,
+			files: Vec::new(),
+		}
+	}
+}
+
+/// The user's network preferences
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NetworkInfo {
+	/// The proxy to send requests through
+	pub proxy: Option<ProxyInfo>,
+	/// How long to wait for a connection to a server, in seconds
+	pub connect_timeout: u64,
+	/// How long to wait for a whole request, in seconds
+	pub request_timeout: u64,
+}
+
+impl Default for NetworkInfo {
+	fn default() -> Self {
+		Self::new(None, TimeoutSettings::default())
+	}
+}
+
+impl NetworkInfo {
+	/// Create the network information from the settings used to create a client
+	pub fn new(proxy: Option<&ProxySettings>, timeouts: TimeoutSettings) -> Self {
+		Self {
+			proxy: proxy.map(|x| ProxyInfo {
+				url: x.url.clone(),
+				username: x.username.clone(),
+				password: x.password.clone(),
+				no_proxy: x.no_proxy.clone(),
+			}),
+			connect_timeout: timeouts.connect.as_secs(),
+			request_timeout: timeouts.total.as_secs(),
+		}
+	}
+
+	/// Create a client that follows these preferences
+	pub fn create_client(&self) -> anyhow::Result<Client> {
+		let proxy = self.proxy.as_ref().map(|x| ProxySettings {
+			url: x.url.clone(),
+			username: x.username.clone(),
+			password: x.password.clone(),
+			no_proxy: x.no_proxy.clone(),
+		});
+		let timeouts = TimeoutSettings {
+			connect: Duration::from_secs(self.connect_timeout),
+			total: Duration::from_secs(self.request_timeout),
+		};
+
+		create_client(proxy.as_ref(), timeouts)
+	}
+}
+
+/// An HTTP proxy
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ProxyInfo {
+	/// The URL of the proxy server
+	pub url: String,
+	/// The username to authenticate with the proxy using
+	pub username: Option<String>,
+	/// The password to authenticate with the proxy using
+	pub password: Option<String>,
+	/// Hosts that should be connected to directly instead of through the proxy
+	pub no_proxy: Vec<String>,
+}
+
+impl PluginContextData {
+	/// Create new empty data at the newest version
+	pub fn new() -> Self {
+		Self {
+			version: CONTEXT_VERSION,
+			..Default::default()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_context_defaults() {
+		let data: PluginContextData = serde_json::from_str(
+			r#"{
+				"instances": {
+					"client": {
+						"id": "client",
+						"side": "client",
+						"unknown": true
+					}
+				},
+				"packages": {
+					"client": [
+						{
+							"id": "sodium",
+							"addons": [{ "id": "sodium", "kind": "mod" }]
+						}
+					]
+				}
+			}"#,
+		)
+		.unwrap();
+
+		assert_eq!(data.version, 0);
+		let instance = &data.instances["client"];
+		assert_eq!(instance.side, Some(Side::Client));
+		assert!(instance.version.is_none());
+		let package = &data.packages["client"][0];
+		assert!(package.reason.is_none());
+		assert!(matches!(package.addons[0].kind, AddonKind::Mod));
+		assert!(package.addons[0].files.is_empty());
+		assert!(data.network.proxy.is_none());
+		assert_eq!(
+			data.network.request_timeout,
+			TimeoutSettings::DEFAULT_TOTAL.as_secs()
+		);
+	}
+}
@@ -1,14 +1,18 @@
+use std::collections::{HashMap, HashSet};
 use std::io::{BufRead, BufReader};
 use std::ops::Deref;
 use std::path::Path;
 use std::process::{Child, ChildStdout, Command};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, bail, Context};
 use mcvm_core::net::minecraft::MinecraftUserProfile;
 use mcvm_core::{net::game_files::version_manifest::VersionEntry, Paths};
+use mcvm_pkg::repo::PackageFlag;
 use mcvm_pkg::script_eval::AddonInstructionData;
-use mcvm_pkg::{RecommendedPackage, RequiredPackage};
+use mcvm_pkg::{PackageContentType, RecommendedPackage, RequiredPackage};
 use mcvm_shared::lang::translate::LanguageMap;
 use mcvm_shared::pkg::PackageID;
 use mcvm_shared::{output::MCVMOutput, versions::VersionInfo, Side};
@@ -19,13 +23,15 @@ use crate::output::OutputAction;
 /// The environment variable for custom config passed to a hook
 pub static CUSTOM_CONFIG_ENV: &str = "MCVM_CUSTOM_CONFIG";
 /// The environment variable for the data directory passed to a hook
-pub static DATA_DIR_ENV: &str = "MCVM_DATA_DIR";
+pub static DATA_DIR_ENV: &str = mcvm_core::io::files::paths::DATA_DIR_ENV;
 /// The environment variable for the config directory passed to a hook
 pub static CONFIG_DIR_ENV: &str = "MCVM_CONFIG_DIR";
 /// The environment variable for the plugin state passed to a hook
 pub static PLUGIN_STATE_ENV: &str = "MCVM_PLUGIN_STATE";
 /// The environment variable for the version of MCVM
 pub static MCVM_VERSION_ENV: &str = "MCVM_VERSION";
+/// The environment variable for the path to the instance information that is shared with plugins
+pub static PLUGIN_CONTEXT_ENV: &str = "MCVM_PLUGIN_CONTEXT";
 
 /// Trait for a hook that can be called
 pub trait Hook {
@@ -60,6 +66,7 @@ pub trait Hook {
 		state: Arc<Mutex<serde_json::Value>>,
 		paths: &Paths,
 		mcvm_version: Option<&str>,
+		context_path: Option<&Path>,
 		plugin_id: &str,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<HookHandle<Self>>
@@ -82,6 +89,9 @@ pub trait Hook {
 		if let Some(mcvm_version) = mcvm_version {
 			cmd.env(MCVM_VERSION_ENV, mcvm_version);
 		}
+		if let Some(context_path) = context_path {
+			cmd.env(PLUGIN_CONTEXT_ENV, context_path);
+		}
 		if let Some(working_dir) = working_dir {
 			cmd.current_dir(working_dir);
 		}
@@ -169,42 +179,7 @@ impl<H: Hook> HookHandle<H> {
 				}
 				let line = line_buf.trim_end_matches("\r\n").trim_end_matches('\n');
 
-				let action = OutputAction::deserialize(line, self.use_base64)
-					.context("Failed to deserialize plugin action")?;
-				match action {
-					OutputAction::SetResult(new_result) => {
-						*result = Some(
-							serde_json::from_str(&new_result)
-								.context("Failed to deserialize hook result")?,
-						);
-					}
-					OutputAction::SetState(new_state) => {
-						let state = self
-							.plugin_state
-							.as_mut()
-							.context("Hook handle does not have a reference to persistent state")?;
-						let mut lock = state.lock().map_err(|x| anyhow!("{x}"))?;
-						*lock = new_state;
-					}
-					OutputAction::Text(text, level) => {
-						o.display_text(text, level);
-					}
-					OutputAction::Message(message) => {
-						o.display_message(message);
-					}
-					OutputAction::StartProcess => {
-						o.start_process();
-					}
-					OutputAction::EndProcess => {
-						o.end_process();
-					}
-					OutputAction::StartSection => {
-						o.start_section();
-					}
-					OutputAction::EndSection => {
-						o.end_section();
-					}
-				}
+				Self::handle_line(line, self.use_base64, &self.plugin_state, result, o)?;
 
 				Ok(false)
 			}
@@ -223,26 +198,134 @@ impl<H: Hook> HookHandle<H> {
 			}
 		}
 
+		match self.inner {
+			HookHandleInner::Constant(result) => Ok(result),
+			HookHandleInner::Process { child, result, .. } => Self::finish(child, result),
+		}
+	}
+
+	/// Get the result of the hook by waiting for it, killing the hook process
+	/// and failing if it doesn't finish within the timeout
+	pub fn result_timeout(
+		self,
+		timeout: Duration,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<H::Result> {
+		let deadline = Instant::now() + timeout;
 		match self.inner {
 			HookHandleInner::Constant(result) => Ok(result),
 			HookHandleInner::Process {
-				mut child, result, ..
+				mut child,
+				stdout,
+				mut result,
+				..
 			} => {
-				let cmd_result = child.wait()?;
-
-				if !cmd_result.success() {
-					if let Some(exit_code) = cmd_result.code() {
-						bail!("Hook returned a non-zero exit code of {}", exit_code);
-					} else {
-						bail!("Hook returned a non-zero exit code");
+				// Read on another thread so that a hung plugin can't block us
+				let (sender, receiver) = mpsc::channel();
+				std::thread::spawn(move || {
+					for line in stdout.lines() {
+						let Ok(line) = line else {
+							break;
+						};
+						if sender.send(line).is_err() {
+							break;
+						}
+					}
+				});
+
+				loop {
+					let remaining = deadline.saturating_duration_since(Instant::now());
+					match receiver.recv_timeout(remaining) {
+						Ok(line) => {
+							let line = line.trim_end_matches('\r');
+							Self::handle_line(
+								line,
+								self.use_base64,
+								&self.plugin_state,
+								&mut result,
+								o,
+							)?;
+						}
+						Err(RecvTimeoutError::Disconnected) => break,
+						Err(RecvTimeoutError::Timeout) => {
+							let _ = child.kill();
+							let _ = child.wait();
+							bail!(
+								"Hook timed out after {} seconds in plugin '{}'",
+								timeout.as_secs(),
+								self.plugin_id
+							);
+						}
 					}
 				}
 
-				let result = result.context("Plugin hook did not return a result")?;
+				Self::finish(child, result)
+			}
+		}
+	}
 
-				Ok(result)
+	/// Handle a single line of output from a hook process
+	fn handle_line(
+		line: &str,
+		use_base64: bool,
+		plugin_state: &Option<Arc<Mutex<serde_json::Value>>>,
+		result: &mut Option<H::Result>,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<()> {
+		let action = OutputAction::deserialize(line, use_base64)
+			.context("Failed to deserialize plugin action")?;
+		match action {
+			OutputAction::SetResult(new_result) => {
+				*result = Some(
+					serde_json::from_str(&new_result)
+						.context("Failed to deserialize hook result")?,
+				);
+			}
+			OutputAction::SetState(new_state) => {
+				let state = plugin_state
+					.as_ref()
+					.context("Hook handle does not have a reference to persistent state")?;
+				let mut lock = state.lock().map_err(|x| anyhow!("{x}"))?;
+				*lock = new_state;
+			}
+			OutputAction::Text(text, level) => {
+				o.display_text(text, level);
+			}
+			OutputAction::Message(message) => {
+				o.display_message(message);
+			}
+			OutputAction::StartProcess => {
+				o.start_process();
+			}
+			OutputAction::EndProcess => {
+				o.end_process();
+			}
+			OutputAction::StartSection => {
+				o.start_section();
+			}
+			OutputAction::EndSection => {
+				o.end_section();
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Wait for a finished hook process and get its result
+	fn finish(mut child: Child, result: Option<H::Result>) -> anyhow::Result<H::Result> {
+		let cmd_result = child.wait()?;
+
+		if !cmd_result.success() {
+			if let Some(exit_code) = cmd_result.code() {
+				bail!("Hook returned a non-zero exit code of {}", exit_code);
+			} else {
+				bail!("Hook returned a non-zero exit code");
 			}
 		}
+
+		let result = result.context("Plugin hook did not return a result")?;
+
+		Ok(result)
 	}
 
 	/// Get the result of the hook by killing it
@@ -313,6 +396,33 @@ def_hook!(
 	}
 );
 
+def_hook!(
+	DescribeSubcommands,
+	"describe_subcommands",
+	"Hook for describing the subcommands a plugin provides, for help and shell completions",
+	(),
+	Vec<SubcommandDescription>,
+);
+
+/// Description of a subcommand provided by a plugin
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct SubcommandDescription {
+	/// The name of the subcommand
+	pub name: String,
+	/// Other names that the subcommand can be run with
+	pub aliases: Vec<String>,
+	/// A short description of the subcommand
+	pub about: String,
+}
+
+impl SubcommandDescription {
+	/// Check whether the subcommand can be run with a name
+	pub fn matches(&self, name: &str) -> bool {
+		self.name == name || self.aliases.iter().any(|x| x == name)
+	}
+}
+
 def_hook!(
 	ModifyInstanceConfig,
 	"modify_instance_config",
@@ -359,6 +469,10 @@ pub struct OnInstanceSetupArg {
 	pub version_info: VersionInfo,
 	/// Custom config on the instance
 	pub custom_config: serde_json::Map<String, serde_json::Value>,
+	/// Whether changes to files should be shown to the user instead of only being logged
+	pub show_diffs: bool,
+	/// Whether to fail on problems with the configuration that would otherwise be warnings
+	pub strict: bool,
 }
 
 def_hook!(
@@ -369,6 +483,28 @@ def_hook!(
 	(),
 );
 
+def_hook!(
+	OnInstancePrepareLaunch,
+	"on_instance_prepare_launch",
+	"Hook for adding arguments, environment variables, and classpath entries to an instance before it is launched",
+	InstanceLaunchArg,
+	OnInstancePrepareLaunchResult,
+);
+
+/// Result from the OnInstancePrepareLaunch hook
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct OnInstancePrepareLaunchResult {
+	/// Additional JVM args to add to the launch command
+	pub jvm_args: Vec<String>,
+	/// Additional game args to add to the launch command
+	pub game_args: Vec<String>,
+	/// Additional environment variables to set for the instance process
+	pub env: HashMap<String, String>,
+	/// Additional paths to add to the classpath
+	pub classpath: Vec<String>,
+}
+
 def_hook!(
 	WhileInstanceLaunch,
 	"while_instance_launch",
@@ -385,7 +521,7 @@ def_hook!(
 	(),
 );
 
-/// Argument for the OnInstanceLaunch and WhileInstanceLaunch hooks
+/// Argument for the OnInstancePrepareLaunch, OnInstanceLaunch, and WhileInstanceLaunch hooks
 #[derive(Serialize, Deserialize, Default)]
 #[serde(default)]
 pub struct InstanceLaunchArg {
@@ -403,6 +539,9 @@ pub struct InstanceLaunchArg {
 	pub custom_config: serde_json::Map<String, serde_json::Value>,
 	/// The PID of the instance process
 	pub pid: Option<u32>,
+	/// Environment variables from the launch config of the instance.
+	/// Only filled if the instance allows sharing them with hooks
+	pub env: HashMap<String, String>,
 }
 
 def_hook!(
@@ -549,4 +688,194 @@ pub struct ExportInstanceArg {
 	pub game_dir: String,
 	/// The desired path for the resulting instance
 	pub result_path: String,
+	/// The description of the instance
+	pub description: Option<String>,
+	/// The path to the PNG icon of the instance, which can be used as the icon of the exported pack
+	pub icon: Option<String>,
 }
+
+def_hook!(
+	SnapshotInstance,
+	"snapshot_instance",
+	"Hook for backing up an instance's world data before its Minecraft version is changed",
+	SnapshotInstanceArg,
+	Option<String>,
+);
+
+/// Argument provided to the snapshot_instance hook
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SnapshotInstanceArg {
+	/// The ID of the instance
+	pub id: String,
+	/// The side of the instance
+	pub side: Option<Side>,
+	/// Path to the instance's dir
+	pub dir: String,
+	/// Path to the instance's game dir
+	pub game_dir: String,
+	/// Paths to the existing world data, relative to the instance's dir
+	pub world_paths: Vec<String>,
+	/// The Minecraft version the instance is being changed from
+	pub old_version: String,
+	/// The Minecraft version the instance is being changed to
+	pub new_version: String,
+	/// Custom config on the instance
+	pub custom_config: serde_json::Map<String, serde_json::Value>,
+}
+
+def_hook!(
+	BackupInstance,
+	"backup_instance",
+	"Hook for creating a backup of an instance, such as on a schedule",
+	BackupInstanceArg,
+	Option<String>,
+);
+
+/// Argument provided to the backup_instance hook
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct BackupInstanceArg {
+	/// The ID of the instance
+	pub id: String,
+	/// The side of the instance
+	pub side: Option<Side>,
+	/// Path to the instance's dir
+	pub dir: String,
+	/// Path to the instance's game dir
+	pub game_dir: String,
+	/// The backup group to store the backup in, if any
+	pub group: Option<String>,
+	/// Custom config on the instance
+	pub custom_config: serde_json::Map<String, serde_json::Value>,
+}
+
+def_hook!(
+	RenameInstance,
+	"rename_instance",
+	"Hook for moving a plugin's data for an instance when the instance is renamed",
+	RenameInstanceArg,
+	(),
+);
+
+/// Argument provided to the rename_instance hook
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct RenameInstanceArg {
+	/// The old ID of the instance
+	pub old_id: String,
+	/// The new ID of the instance
+	pub new_id: String,
+	/// Path to the instance's new dir
+	pub dir: String,
+}
+
+def_hook!(
+	AddPackageRepositories,
+	"add_package_repositories",
+	"Hook for adding extra package repositories",
+	(),
+	Vec<PluginPackageRepository>,
+);
+
+/// A package repository added by a plugin
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct PluginPackageRepository {
+	/// The ID of the repository
+	pub id: String,
+	/// The URL to the repository
+	pub url: Option<String>,
+	/// The path to the repository on the local filesystem
+	pub path: Option<String>,
+}
+
+def_hook!(
+	ProvidePackage,
+	"provide_package",
+	"Hook for providing the contents of packages in a namespace that the plugin claims",
+	ProvidePackageArg,
+	ProvidePackageResult,
+);
+
+/// Argument provided to the provide_package hook
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ProvidePackageArg {
+	/// The ID of the requested package
+	pub id: String,
+}
+
+/// Result from the provide_package hook
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ProvidePackageResult {
+	/// The contents of the package, or None if the plugin doesn't have it
+	pub contents: Option<String>,
+	/// The content type of the package
+	pub content_type: PackageContentType,
+	/// Flags for the package
+	pub flags: HashSet<PackageFlag>,
+}
+
+def_hook!(
+	OnProfileUpdate,
+	"on_profile_update",
+	"Hook for doing work after all of the instances of a profile have been updated",
+	OnProfileUpdateArg,
+	(),
+);
+
+/// Argument provided to the on_profile_update hook
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct OnProfileUpdateArg {
+	/// The ID of the profile
+	pub profile: String,
+	/// The Minecraft version of the profile's instances before the update,
+	/// or None if they had never been updated
+	pub old_version: Option<String>,
+	/// The Minecraft version of the profile's instances after the update
+	pub new_version: Option<String>,
+	/// The changes to the packages installed on each instance
+	pub packages: Vec<ProfilePackageChange>,
+	/// The IDs of the instances that were updated
+	pub instances: Vec<String>,
+}
+
+/// A change to a package on an instance during a profile update
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ProfilePackageChange {
+	/// The ID of the instance
+	pub instance: String,
+	/// The ID of the package
+	pub package: String,
+	/// What happened to the package
+	pub kind: PackageChangeKind,
+	/// The versions of the package's addons before the update
+	pub old_version: Option<String>,
+	/// The versions of the package's addons after the update
+	pub new_version: Option<String>,
+}
+
+/// What happened to a package during an update
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageChangeKind {
+	/// The package was installed
+	#[default]
+	Added,
+	/// The package was removed
+	Removed,
+	/// The package's addons changed versions
+	Upgraded,
+}
+
+def_hook!(
+	Ping,
+	"ping",
+	"Hook for checking that a plugin is working, used by the health check",
+	(),
+	(),
+);
@@ -3,6 +3,8 @@
 //! This library is used by both MCVM to load plugins, and as a framework for defining
 //! Rust plugins for MCVM to use
 
+use std::path::PathBuf;
+
 use anyhow::{bail, Context};
 use hooks::{Hook, HookHandle, OnLoad};
 use mcvm_core::Paths;
@@ -12,6 +14,8 @@ use plugin::Plugin;
 /// API for Rust-based plugins to use
 #[cfg(feature = "api")]
 pub mod api;
+/// Information about the user's instances that is shared with plugins
+pub mod context;
 /// Plugin hooks and their definitions
 pub mod hooks;
 /// Serialized output format for plugins
@@ -24,6 +28,7 @@ pub mod plugin;
 pub struct PluginManager {
 	plugins: Vec<Plugin>,
 	mcvm_version: Option<&'static str>,
+	context_path: Option<PathBuf>,
 }
 
 impl Default for PluginManager {
@@ -38,6 +43,7 @@ impl PluginManager {
 		Self {
 			plugins: Vec::new(),
 			mcvm_version: None,
+			context_path: None,
 		}
 	}
 
@@ -46,6 +52,16 @@ impl PluginManager {
 		self.mcvm_version = Some(version);
 	}
 
+	/// Set the path to the file with the instance information that is shared with plugins
+	pub fn set_context_path(&mut self, path: PathBuf) {
+		self.context_path = Some(path);
+	}
+
+	/// Check whether the manager has any plugins
+	pub fn has_plugins(&self) -> bool {
+		!self.plugins.is_empty()
+	}
+
 	/// Add a plugin to the manager
 	pub fn add_plugin(
 		&mut self,
@@ -55,7 +71,7 @@ impl PluginManager {
 	) -> anyhow::Result<()> {
 		// Call the on_load hook
 		let result = plugin
-			.call_hook(&OnLoad, &(), paths, self.mcvm_version, o)
+			.call_hook(&OnLoad, &(), paths, self.mcvm_version, None, o)
 			.context("Failed to call on_load hook of plugin")?;
 		if let Some(result) = result {
 			result.result(o)?;
@@ -77,7 +93,14 @@ impl PluginManager {
 		let mut out = Vec::new();
 		for plugin in &self.plugins {
 			let result = plugin
-				.call_hook(&hook, arg, paths, self.mcvm_version, o)
+				.call_hook(
+					&hook,
+					arg,
+					paths,
+					self.mcvm_version,
+					self.context_path.as_deref(),
+					o,
+				)
 				.context("Plugin hook failed")?;
 			out.extend(result);
 		}
@@ -97,7 +120,14 @@ impl PluginManager {
 		for plugin in &self.plugins {
 			if plugin.get_id() == plugin_id {
 				let result = plugin
-					.call_hook(&hook, arg, paths, self.mcvm_version, o)
+					.call_hook(
+						&hook,
+						arg,
+						paths,
+						self.mcvm_version,
+						self.context_path.as_deref(),
+						o,
+					)
 					.context("Plugin hook failed")?;
 				return Ok(result);
 			}
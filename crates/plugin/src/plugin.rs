@@ -1,16 +1,20 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::Mutex;
 
 use anyhow::Context;
 use mcvm_core::Paths;
 use mcvm_shared::output::MCVMOutput;
+use mcvm_shared::Side;
 use serde::{Deserialize, Deserializer};
 
 use crate::hooks::{Hook, HookHandle};
 
+/// The newest plugin protocol version that is supported
+pub const PROTOCOL_VERSION: u16 = 1;
+
 /// A plugin
 #[derive(Debug)]
 pub struct Plugin {
@@ -48,6 +52,17 @@ impl Plugin {
 		&self.manifest
 	}
 
+	/// Check whether the plugin claims the namespace of a package ID. A package is in
+	/// a namespace if its ID is the namespace or starts with the namespace followed by a hyphen
+	pub fn claims_package(&self, package: &str) -> bool {
+		self.manifest.package_namespaces.iter().any(|namespace| {
+			package == namespace
+				|| package
+					.strip_prefix(namespace.as_str())
+					.is_some_and(|x| x.starts_with('-'))
+		})
+	}
+
 	/// Call a hook on the plugin
 	pub fn call_hook<H: Hook>(
 		&self,
@@ -55,6 +70,7 @@ impl Plugin {
 		arg: &H::Arg,
 		paths: &Paths,
 		mcvm_version: Option<&str>,
+		context_path: Option<&Path>,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<Option<HookHandle<H>>> {
 		let Some(handler) = self.manifest.hooks.get(hook.get_name()) else {
@@ -72,6 +88,7 @@ impl Plugin {
 					self.state.clone(),
 					paths,
 					mcvm_version,
+					context_path,
 					&self.id,
 					o,
 				)
@@ -123,6 +140,11 @@ pub struct PluginManifest {
 	pub protocol_version: Option<u16>,
 	/// Whether to disable base64 encoding in the protocol
 	pub raw_transfer: bool,
+	/// Package ID namespaces that the plugin provides packages for through the provide_package hook
+	pub package_namespaces: Vec<String>,
+	/// Files in the game directory of an instance on each side that the on_instance_setup
+	/// hook rewrites on every update
+	pub setup_files: HashMap<Side, Vec<String>>,
 }
 
 impl PluginManifest {
@@ -130,6 +152,29 @@ impl PluginManifest {
 	pub fn new() -> Self {
 		Self::default()
 	}
+
+	/// Check whether the protocol version of the plugin is supported by this version of the plugin system
+	pub fn is_protocol_supported(&self) -> bool {
+		match self.protocol_version {
+			Some(version) => version <= PROTOCOL_VERSION,
+			None => true,
+		}
+	}
+
+	/// Get the executables that are run by the hooks of the plugin, without duplicates
+	pub fn get_executables(&self) -> Vec<&str> {
+		let mut out: Vec<&str> = self
+			.hooks
+			.values()
+			.filter_map(|x| match x {
+				HookHandler::Execute { executable, .. } => Some(executable.as_str()),
+				_ => None,
+			})
+			.collect();
+		out.sort();
+		out.dedup();
+		out
+	}
 }
 
 /// A handler for a single hook that a plugin uses
@@ -17,6 +17,9 @@ macro_rules! define_translations {
 		}
 
 		impl TranslationKey {
+			/// Every translation key
+			pub const ALL: &'static [Self] = &[$(Self::$key,)*];
+
 			/// Get the default translation for this key
 			pub fn get_default(&self) -> &'static str {
 				match self {
@@ -47,19 +50,33 @@ define_translations! {
 	StartUpdatingPackages, "When starting to update packages on a profile", "Updating packages";
 	FinishUpdatingPackages, "When finishing updating packages on a profile", "All packages installed";
 	StartUpdatingProfileVersion, "When starting to update a profile's version", "Updating profile version";
+	InstanceVersionChanged, "When the resolved Minecraft version of an instance changes since its last update", "Minecraft version of '%inst' changed from %old to %new";
 	FinishUpdatingProfileVersion, "When finishing updating a profile's version", "Profile version updated";
+	SnapshotCreated, "When a snapshot of world data is created before changing versions", "Backed up world data from version %version as '%id'";
+	InstanceCrashed, "When an instance exits abnormally and a crash bundle is created", "Instance '%inst' crashed with exit code %code. Crash information was saved to %path";
+	StartCopyingInstanceFiles, "When starting to copy the files of an instance to a clone", "Copying %count files";
+	CopiedInstanceFile, "When a file is copied to a cloned instance", "Copied %file";
+	FinishCopyingInstanceFiles, "When finishing copying the files of an instance to a clone", "Instance files copied";
+	PluginRenameFailed, "When a plugin fails to move its data for a renamed instance", "A plugin failed to move its data for instance '%inst': %error";
 	StartUpdatingProxy, "When starting to update a proxy", "Checking for proxy updates";
 	FinishUpdatingProxy, "When finishing updating a proxy", "Proxy updated";
 	StartRunningCommands, "When starting to run package commands", "Running commands";
 	FinishRunningCommands, "When finishing running package commands", "Finished running commands";
 	StartUpdatingInstance, "When starting to update an instance", "Updating instance %inst";
 	PreparingLaunch, "When preparing to launch the game", "Preparing to launch";
+	PluginProtocolTooNew, "When a plugin is skipped because it needs a newer plugin protocol", "Skipping plugin '%plugin' since it uses protocol version %version, but only versions up to %supported are supported";
+	PluginEnvConflict, "When two plugins set the same environment variable for a launch", "Plugin '%plugin' overrides the environment variable '%var' that was set by plugin '%other'";
 	Launch, "When launching the game", "Launching!";
 	CoreRepoName, "Name of the core repo", "Core";
 	CoreRepoDescription, "Description of the core repo", "The built-in set of packages";
 	RepoVersionWarning, "Warning when a remote repo version is too high", "Minimum MCVM version for repository %repo is higher than current installation";
+	CoreOverridesFailed, "Warning when the core package overrides could not be updated or loaded", "Failed to use core package overrides, so the bundled core packages will be used: %error";
+	RepoIndexRollback, "Warning when a verified repo index is older than one that was previously verified", "The index for repository %repo was replaced by an older one. It may have been rolled back by an attacker";
 	OverwriteAddonFilePrompt, "Prompt when an addon file would be overwriten", "The existing file '%file' has the same path as an addon. Overwrite it?";
 	CustomInstructionsWarning, "When a package uses unrecognized custom instructions", "Package uses custom instructions that MCVM does not recognize";
+	UnknownPackageFeature, "When a package is configured with a feature that it doesn't have", "Package '%pkg' does not have the feature '%feature', so it will be ignored";
+	DatapackSkipped, "When a datapack can't be installed on a client because no worlds were selected", "Skipping datapack '%addon' since no worlds or datapack folder were configured for it";
+	PerformingFirstUpdate, "When an instance is updated for the first time before launching", "Performing first update of instance '%inst'";
 	Redownloading, "When something is being redownloaded", "Redownloading";
 	AssetIndexFailed, "When the asset index is unable to be obtained", "Failed to obtain asset index";
 	StartDownloadingAssets, "When starting to download assets", "Downloading %count assets";
@@ -190,7 +190,8 @@ impl Display for ServerType {
 pub enum PluginLoaderMatch {
 	/// The default game with no plugin support
 	Vanilla,
-	/// Matches any server that can load Bukkit plugins
+	/// Matches any server that can load Bukkit plugins. Folia is not included
+	/// since most Bukkit plugins don't work on it
 	Bukkit,
 	/// Matches Paper server
 	Paper,
@@ -239,7 +240,8 @@ impl PluginLoaderMatch {
 				other,
 				ServerType::Paper
 					| ServerType::CraftBukkit
-					| ServerType::Spigot | ServerType::Glowstone
+					| ServerType::Spigot
+					| ServerType::Glowstone
 					| ServerType::Pufferfish
 					| ServerType::Purpur
 			),
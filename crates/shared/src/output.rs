@@ -1,7 +1,10 @@
-use anyhow::bail;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{anyhow, bail};
 use serde::{Deserialize, Serialize};
 
-use crate::{lang::translate::TranslationKey, pkg::PkgRequest};
+use crate::lang::translate::{TranslationKey, TranslationMap};
+use crate::pkg::PkgRequest;
 
 /// Trait for a type that can output information about MCVM processes
 pub trait MCVMOutput {
@@ -127,9 +130,32 @@ pub enum MessageContents {
 		/// The total amount that needs to be completed
 		total: u32,
 	},
+	/// A line that was added or removed in a diff
+	DiffLine(DiffChange, String),
+}
+
+/// How a line in a diff was changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DiffChange {
+	/// The line was added
+	Added,
+	/// The line was removed
+	Removed,
 }
 
 impl MessageContents {
+	/// Checks whether this message is an error, including errors that are associated with something
+	pub fn is_error(&self) -> bool {
+		match self {
+			Self::Error(..) => true,
+			Self::Property(_, contents) | Self::ListItem(contents) | Self::Package(_, contents) => {
+				contents.is_error()
+			}
+			Self::Associated(_, contents) => contents.is_error(),
+			_ => false,
+		}
+	}
+
 	/// Message formatting for the default implementation
 	pub fn default_format(self) -> String {
 		match self {
@@ -153,6 +179,8 @@ impl MessageContents {
 			}
 			MessageContents::ListItem(item) => format!(" - {}", item.default_format()),
 			MessageContents::Progress { current, total } => format!("{current}/{total}"),
+			MessageContents::DiffLine(DiffChange::Added, line) => format!("+ {line}"),
+			MessageContents::DiffLine(DiffChange::Removed, line) => format!("- {line}"),
 		}
 	}
 }
@@ -187,6 +215,47 @@ impl MessageLevel {
 	}
 }
 
+/// How much an output should show to the user
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verbosity {
+	/// Only show errors
+	Quiet,
+	/// Show important messages
+	#[default]
+	Normal,
+	/// Show extra messages as well
+	Verbose,
+	/// Show debug messages as well
+	Debug,
+	/// Show every message
+	Trace,
+}
+
+impl Verbosity {
+	/// Get the lowest level of message that is shown
+	pub fn get_level(&self) -> MessageLevel {
+		match self {
+			Self::Quiet | Self::Normal => MessageLevel::Important,
+			Self::Verbose => MessageLevel::Extra,
+			Self::Debug => MessageLevel::Debug,
+			Self::Trace => MessageLevel::Trace,
+		}
+	}
+
+	/// Checks whether a message with some contents and level should be shown
+	pub fn should_display(&self, contents: &MessageContents, level: MessageLevel) -> bool {
+		if !level.at_least(&self.get_level()) {
+			return false;
+		}
+		if let Self::Quiet = self {
+			return contents.is_error();
+		}
+
+		true
+	}
+}
+
 /// Dummy MCVMOutput that doesn't print anything
 pub struct NoOp;
 
@@ -207,6 +276,265 @@ impl MCVMOutput for Simple {
 	}
 }
 
+/// A level of hierarchy that an output can be in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFrame {
+	/// A process started with [MCVMOutput::start_process]
+	Process,
+	/// A section started with [MCVMOutput::start_section]
+	Section,
+}
+
+/// The processes and sections that an output is in, from outermost to innermost.
+/// Ending a frame also ends every frame that was started inside of it and never ended,
+/// so that a task that fails partway through doesn't leave the output indented
+#[derive(Clone, Debug, Default)]
+pub struct SectionStack {
+	frames: Vec<OutputFrame>,
+}
+
+impl SectionStack {
+	/// Create a new empty SectionStack
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Start a new frame
+	pub fn push(&mut self, frame: OutputFrame) {
+		self.frames.push(frame);
+	}
+
+	/// End the innermost frame of a kind, along with any frames inside of it. Returns the frames
+	/// that were ended, innermost first. Nothing is ended if no frame of that kind is open
+	pub fn pop(&mut self, frame: OutputFrame) -> Vec<OutputFrame> {
+		let Some(position) = self.frames.iter().rposition(|x| *x == frame) else {
+			return Vec::new();
+		};
+		let mut out = self.frames.split_off(position);
+		out.reverse();
+		out
+	}
+
+	/// End every frame. Returns the frames that were ended, innermost first
+	pub fn clear(&mut self) -> Vec<OutputFrame> {
+		let mut out = std::mem::take(&mut self.frames);
+		out.reverse();
+		out
+	}
+
+	/// Get the number of sections that are open, which is the indent level
+	pub fn indent(&self) -> usize {
+		self.frames
+			.iter()
+			.filter(|x| **x == OutputFrame::Section)
+			.count()
+	}
+
+	/// Checks whether any process is open
+	pub fn in_process(&self) -> bool {
+		self.frames.contains(&OutputFrame::Process)
+	}
+
+	/// Checks whether no frames are open
+	pub fn is_empty(&self) -> bool {
+		self.frames.is_empty()
+	}
+}
+
+/// Something that was sent to a [Buffered] output
+#[derive(Clone, Debug)]
+pub enum OutputEvent {
+	/// A message was displayed
+	Message(Message),
+	/// A process was started
+	StartProcess,
+	/// A process was ended
+	EndProcess,
+	/// A section was started
+	StartSection,
+	/// A section was ended
+	EndSection,
+}
+
+/// An output that is shared by tasks that run at the same time. Each task records its messages
+/// with a [Buffered] output, and prompts are sent to the shared output one at a time
+pub struct SharedOutput<'o, O: MCVMOutput> {
+	output: Mutex<&'o mut O>,
+	translations: TranslationMap,
+}
+
+impl<'o, O> SharedOutput<'o, O>
+where
+	O: MCVMOutput,
+{
+	/// Create a new SharedOutput, taking the translations of the output
+	pub fn new(output: &'o mut O) -> Self {
+		let translations = TranslationKey::ALL
+			.iter()
+			.map(|key| (*key, output.translate(*key).to_string()))
+			.collect();
+		Self {
+			output: Mutex::new(output),
+			translations,
+		}
+	}
+
+	/// Lock the output so that it can be used directly
+	pub fn lock(&self) -> anyhow::Result<MutexGuard<'_, &'o mut O>> {
+		self.output.lock().map_err(|x| anyhow!("{x}"))
+	}
+
+	/// Get back the output that was shared
+	pub fn into_inner(self) -> anyhow::Result<&'o mut O> {
+		self.output.into_inner().map_err(|x| anyhow!("{x}"))
+	}
+}
+
+/// MCVMOutput that records everything sent to it so that it can be shown later with [replay_output].
+/// Tasks that run concurrently can each use one of these so that their output isn't mixed together.
+/// Translations come from a [SharedOutput]. Prompts are passed to the shared output after
+/// showing everything that was recorded so far, so that the user sees what they are answering.
+/// The recorded events are always balanced, so sections and processes that are never ended
+/// are ended when the events are taken
+pub struct Buffered<'a, 'o, O: MCVMOutput> {
+	shared: &'a SharedOutput<'o, O>,
+	events: Vec<OutputEvent>,
+	stack: SectionStack,
+	/// The number of events that are not inside of any section or process
+	complete: usize,
+}
+
+impl<'a, 'o, O> Buffered<'a, 'o, O>
+where
+	O: MCVMOutput,
+{
+	/// Create a new Buffered output that belongs to a shared output
+	pub fn new(shared: &'a SharedOutput<'o, O>) -> Self {
+		Self {
+			shared,
+			events: Vec::new(),
+			stack: SectionStack::new(),
+			complete: 0,
+		}
+	}
+
+	/// Get the recorded events, ending any sections and processes that are still open
+	pub fn into_events(mut self) -> Vec<OutputEvent> {
+		for frame in self.stack.clear() {
+			self.events.push(OutputEvent::end(frame));
+		}
+		self.events
+	}
+
+	/// Send the events that are not inside of an open section or process to another output
+	/// and forget them. Flushing like this keeps the output of a task together, even when
+	/// many tasks flush to the same output
+	pub fn flush(&mut self, o: &mut impl MCVMOutput) {
+		let events: Vec<_> = self.events.drain(..self.complete).collect();
+		self.complete = 0;
+		replay_output(events, o);
+	}
+
+	/// Lock the shared output for a prompt, showing every recorded event on it first.
+	/// Sections and processes that are still open stay open on the shared output
+	fn start_prompt(&mut self) -> anyhow::Result<MutexGuard<'a, &'o mut O>> {
+		let mut output = self.shared.lock()?;
+		replay_output(std::mem::take(&mut self.events), &mut **output);
+		self.complete = 0;
+		Ok(output)
+	}
+
+	fn push(&mut self, event: OutputEvent) {
+		self.events.push(event);
+		if self.stack.is_empty() {
+			self.complete = self.events.len();
+		}
+	}
+
+	fn end(&mut self, frame: OutputFrame) {
+		for frame in self.stack.pop(frame) {
+			self.push(OutputEvent::end(frame));
+		}
+	}
+}
+
+impl OutputEvent {
+	/// Get the event that ends a frame
+	fn end(frame: OutputFrame) -> Self {
+		match frame {
+			OutputFrame::Process => Self::EndProcess,
+			OutputFrame::Section => Self::EndSection,
+		}
+	}
+}
+
+impl<'a, 'o, O> MCVMOutput for Buffered<'a, 'o, O>
+where
+	O: MCVMOutput,
+{
+	fn display_text(&mut self, text: String, level: MessageLevel) {
+		self.display_message(Message {
+			contents: MessageContents::Simple(text),
+			level,
+		});
+	}
+
+	fn display_message(&mut self, message: Message) {
+		self.push(OutputEvent::Message(message));
+	}
+
+	fn start_process(&mut self) {
+		self.stack.push(OutputFrame::Process);
+		self.push(OutputEvent::StartProcess);
+	}
+
+	fn end_process(&mut self) {
+		self.end(OutputFrame::Process);
+	}
+
+	fn start_section(&mut self) {
+		self.stack.push(OutputFrame::Section);
+		self.push(OutputEvent::StartSection);
+	}
+
+	fn end_section(&mut self) {
+		self.end(OutputFrame::Section);
+	}
+
+	fn prompt_yes_no(&mut self, default: bool, message: MessageContents) -> anyhow::Result<bool> {
+		self.start_prompt()?.prompt_yes_no(default, message)
+	}
+
+	fn prompt_password(&mut self, message: MessageContents) -> anyhow::Result<String> {
+		self.start_prompt()?.prompt_password(message)
+	}
+
+	fn prompt_new_password(&mut self, message: MessageContents) -> anyhow::Result<String> {
+		self.start_prompt()?.prompt_new_password(message)
+	}
+
+	fn translate(&self, key: TranslationKey) -> &str {
+		self.shared
+			.translations
+			.get(&key)
+			.map(String::as_str)
+			.unwrap_or(key.get_default())
+	}
+}
+
+/// Send events that were recorded by a [Buffered] output to another output
+pub fn replay_output(events: Vec<OutputEvent>, o: &mut impl MCVMOutput) {
+	for event in events {
+		match event {
+			OutputEvent::Message(message) => o.display_message(message),
+			OutputEvent::StartProcess => o.start_process(),
+			OutputEvent::EndProcess => o.end_process(),
+			OutputEvent::StartSection => o.start_section(),
+			OutputEvent::EndSection => o.end_section(),
+		}
+	}
+}
+
 /// RAII struct that opens and closes an output process
 pub struct OutputProcess<'a, O: MCVMOutput>(pub &'a mut O);
 
@@ -230,6 +558,29 @@ where
 	}
 }
 
+/// RAII struct that opens and closes an output section
+pub struct OutputSection<'a, O: MCVMOutput>(pub &'a mut O);
+
+impl<'a, O> OutputSection<'a, O>
+where
+	O: MCVMOutput,
+{
+	/// Create a new OutputSection from an MCVMOutput
+	pub fn new(o: &'a mut O) -> Self {
+		o.start_section();
+		Self(o)
+	}
+}
+
+impl<'a, O> Drop for OutputSection<'a, O>
+where
+	O: MCVMOutput,
+{
+	fn drop(&mut self) {
+		self.0.end_section();
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -240,4 +591,237 @@ mod tests {
 		assert!(MessageLevel::Debug.at_least(&MessageLevel::Debug));
 		assert!(!MessageLevel::Debug.at_least(&MessageLevel::Extra));
 	}
+
+	#[test]
+	fn test_buffered_output() {
+		let mut noop = NoOp;
+		let shared = SharedOutput::new(&mut noop);
+		let mut o = Buffered::new(&shared);
+		o.start_section();
+		o.display(
+			MessageContents::Simple("Hello".into()),
+			MessageLevel::Important,
+		);
+		o.display_text("World".into(), MessageLevel::Debug);
+		o.end_section();
+
+		let mut replayed = Buffered::new(&shared);
+		replay_output(o.into_events(), &mut replayed);
+		let events = replayed.into_events();
+		assert_eq!(events.len(), 4);
+		assert!(matches!(events[0], OutputEvent::StartSection));
+		assert!(matches!(
+			&events[1],
+			OutputEvent::Message(Message {
+				contents: MessageContents::Simple(text),
+				level: MessageLevel::Important,
+			}) if text == "Hello"
+		));
+		assert!(matches!(
+			&events[2],
+			OutputEvent::Message(Message {
+				level: MessageLevel::Debug,
+				..
+			})
+		));
+		assert!(matches!(events[3], OutputEvent::EndSection));
+	}
+
+	#[test]
+	fn test_verbosity_filtering() {
+		let error = MessageContents::Error("Oh no".into());
+		let package_error =
+			MessageContents::ListItem(Box::new(MessageContents::Error("Oh no".into())));
+		let simple = MessageContents::Simple("Hello".into());
+
+		assert!(Verbosity::Quiet.should_display(&error, MessageLevel::Important));
+		assert!(Verbosity::Quiet.should_display(&package_error, MessageLevel::Important));
+		assert!(!Verbosity::Quiet.should_display(&simple, MessageLevel::Important));
+		assert!(!Verbosity::Quiet.should_display(&error, MessageLevel::Debug));
+		assert!(Verbosity::Normal.should_display(&simple, MessageLevel::Important));
+		assert!(!Verbosity::Normal.should_display(&simple, MessageLevel::Extra));
+		assert!(Verbosity::Verbose.should_display(&simple, MessageLevel::Extra));
+		assert!(!Verbosity::Verbose.should_display(&simple, MessageLevel::Debug));
+		assert!(Verbosity::Debug.should_display(&simple, MessageLevel::Debug));
+		assert!(!Verbosity::Debug.should_display(&simple, MessageLevel::Trace));
+		assert!(Verbosity::Trace.should_display(&simple, MessageLevel::Trace));
+	}
+
+	#[test]
+	fn test_section_stack() {
+		let mut stack = SectionStack::new();
+		stack.push(OutputFrame::Section);
+		stack.push(OutputFrame::Process);
+		stack.push(OutputFrame::Section);
+		assert_eq!(stack.indent(), 2);
+		assert!(stack.in_process());
+
+		// Ending the process ends the section that was left open inside of it
+		assert_eq!(
+			stack.pop(OutputFrame::Process),
+			vec![OutputFrame::Section, OutputFrame::Process]
+		);
+		assert_eq!(stack.indent(), 1);
+		assert!(!stack.in_process());
+		assert!(stack.pop(OutputFrame::Process).is_empty());
+		assert_eq!(stack.pop(OutputFrame::Section), vec![OutputFrame::Section]);
+		assert!(stack.is_empty());
+	}
+
+	#[test]
+	fn test_buffered_output_balanced() {
+		let mut noop = NoOp;
+		let shared = SharedOutput::new(&mut noop);
+		let mut o = Buffered::new(&shared);
+		o.start_section();
+		o.start_process();
+		// A task that fails here never ends its process or section
+		let events = o.into_events();
+		assert!(matches!(
+			events.as_slice(),
+			[
+				OutputEvent::StartSection,
+				OutputEvent::StartProcess,
+				OutputEvent::EndProcess,
+				OutputEvent::EndSection
+			]
+		));
+	}
+
+	#[test]
+	fn test_output_section_guard() {
+		fn fail(o: &mut impl MCVMOutput) -> Result<(), ()> {
+			let section = OutputSection::new(o);
+			section
+				.0
+				.display_text("Working".into(), MessageLevel::Important);
+			Err(())
+		}
+
+		let mut noop = NoOp;
+		let shared = SharedOutput::new(&mut noop);
+		let mut o = Buffered::new(&shared);
+		assert!(fail(&mut o).is_err());
+		assert!(o.stack.is_empty());
+		assert!(matches!(o.events.last(), Some(OutputEvent::EndSection)));
+	}
+
+	/// Output that records each line along with the indent it was printed at
+	#[derive(Default)]
+	struct Recorder {
+		stack: SectionStack,
+		lines: Vec<(usize, String)>,
+	}
+
+	impl MCVMOutput for Recorder {
+		fn display_text(&mut self, text: String, _level: MessageLevel) {
+			self.lines.push((self.stack.indent(), text));
+		}
+
+		fn start_process(&mut self) {
+			self.stack.push(OutputFrame::Process);
+		}
+
+		fn end_process(&mut self) {
+			self.stack.pop(OutputFrame::Process);
+		}
+
+		fn start_section(&mut self) {
+			self.stack.push(OutputFrame::Section);
+		}
+
+		fn end_section(&mut self) {
+			self.stack.pop(OutputFrame::Section);
+		}
+	}
+
+	/// Output that answers every prompt with the opposite of its default
+	#[derive(Default)]
+	struct Answerer {
+		lines: Vec<String>,
+	}
+
+	impl MCVMOutput for Answerer {
+		fn display_text(&mut self, text: String, _level: MessageLevel) {
+			self.lines.push(text);
+		}
+
+		fn prompt_yes_no(
+			&mut self,
+			default: bool,
+			message: MessageContents,
+		) -> anyhow::Result<bool> {
+			self.lines.push(message.default_format());
+			Ok(!default)
+		}
+	}
+
+	#[test]
+	fn test_buffered_output_prompts() {
+		let mut answerer = Answerer::default();
+		let shared = SharedOutput::new(&mut answerer);
+		let mut o = Buffered::new(&shared);
+		o.start_section();
+		o.display_text("Before".into(), MessageLevel::Important);
+		let answer = o
+			.prompt_yes_no(true, MessageContents::Simple("Continue?".into()))
+			.unwrap();
+		assert!(!answer);
+		o.display_text("After".into(), MessageLevel::Important);
+
+		// Everything up to the prompt was shown, and the rest is still recorded
+		let events = o.into_events();
+		assert!(matches!(
+			events.as_slice(),
+			[OutputEvent::Message(..), OutputEvent::EndSection]
+		));
+		let answerer = shared.into_inner().unwrap();
+		assert_eq!(answerer.lines, vec!["Before", "Continue?"]);
+	}
+
+	#[test]
+	fn test_concurrent_sections_stress() {
+		const TASKS: usize = 16;
+		const ITERATIONS: usize = 200;
+
+		let mut noop = NoOp;
+		let shared = SharedOutput::new(&mut noop);
+		let recorder = std::sync::Mutex::new(Recorder::default());
+		std::thread::scope(|scope| {
+			for task in 0..TASKS {
+				let shared = &shared;
+				let recorder = &recorder;
+				scope.spawn(move || {
+					let mut o = Buffered::new(shared);
+					for i in 0..ITERATIONS {
+						o.start_section();
+						o.display_text(format!("{task} header {i}"), MessageLevel::Important);
+						o.start_section();
+						o.start_process();
+						o.display_text(format!("{task} progress {i}"), MessageLevel::Important);
+						o.end_process();
+						o.display_text(format!("{task} nested {i}"), MessageLevel::Important);
+						o.end_section();
+						// Flushing in the middle of a section doesn't split it
+						o.flush(&mut *recorder.lock().unwrap());
+						o.display_text(format!("{task} footer {i}"), MessageLevel::Important);
+						o.end_section();
+						o.flush(&mut *recorder.lock().unwrap());
+					}
+				});
+			}
+		});
+
+		let recorder = recorder.into_inner().unwrap();
+		assert!(recorder.stack.is_empty());
+		assert_eq!(recorder.lines.len(), TASKS * ITERATIONS * 4);
+		for block in recorder.lines.chunks(4) {
+			let task = block[0].1.split(' ').next().unwrap();
+			let indents: Vec<_> = block.iter().map(|x| x.0).collect();
+			assert_eq!(indents, vec![1, 2, 2, 1]);
+			for (_, line) in block {
+				assert_eq!(line.split(' ').next().unwrap(), task);
+			}
+		}
+	}
 }
@@ -1,3 +1,4 @@
+use anyhow::bail;
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -19,6 +20,10 @@ pub struct PkgRequest {
 	pub source: PkgRequestSource,
 	/// The ID of the package to request
 	pub id: PackageID,
+	/// The repository or plugin namespace that the package was explicitly requested from,
+	/// written as `namespace:package`
+	#[serde(default)]
+	pub namespace: Option<Arc<str>>,
 	/// The requested content version of the package
 	pub content_version: VersionPattern,
 }
@@ -88,6 +93,7 @@ impl PkgRequest {
 		Self {
 			id: id.into(),
 			source,
+			namespace: None,
 			content_version,
 		}
 	}
@@ -98,10 +104,21 @@ impl PkgRequest {
 		Self::new(id, source, VersionPattern::Any)
 	}
 
-	/// Parse the package name and content version from a string
+	/// Parse the namespace, package name, and content version from a string
 	pub fn parse(string: impl AsRef<str>, source: PkgRequestSource) -> Self {
-		let string = string.as_ref();
-		if let Some(index) = string.find('@') {
+		let mut string = string.as_ref();
+		// The namespace can only come before the content version
+		let id_end = string.find('@').unwrap_or(string.len());
+		let namespace = match string[..id_end].find(':') {
+			Some(index) => {
+				let namespace = &string[..index];
+				string = &string[index + 1..];
+				Some(Arc::from(namespace))
+			}
+			None => None,
+		};
+
+		let mut out = if let Some(index) = string.find('@') {
 			let (id, mut version) = string.split_at(index);
 			if index + 1 < string.len() {
 				// Cut off the at symbol
@@ -112,7 +129,28 @@ impl PkgRequest {
 			}
 		} else {
 			PkgRequest::new(string, source, VersionPattern::Any)
+		};
+		out.namespace = namespace;
+		out
+	}
+
+	/// Parse a request from a string like `parse`, and check that its namespace and ID are valid
+	pub fn parse_and_validate(
+		string: impl AsRef<str>,
+		source: PkgRequestSource,
+	) -> anyhow::Result<Self> {
+		let string = string.as_ref();
+		let out = Self::parse(string, source);
+		if let Some(namespace) = &out.namespace {
+			if namespace.is_empty() || !is_valid_identifier(namespace) {
+				bail!("Invalid namespace '{namespace}' in package '{string}'");
+			}
 		}
+		if out.id.is_empty() || !is_valid_package_id(&out.id) {
+			bail!("Invalid package ID '{}' in package '{string}'", out.id);
+		}
+
+		Ok(out)
 	}
 
 	/// Create a dependency list for debugging
@@ -123,24 +161,24 @@ impl PkgRequest {
 	/// Recursive inner function for debugging sources
 	fn debug_sources_inner(&self, list: String) -> String {
 		match &self.source {
-			PkgRequestSource::UserRequire => format!("{}{list}", self.id),
+			PkgRequestSource::UserRequire => format!("{self}{list}"),
 			PkgRequestSource::Dependency(source) => {
-				format!("{} -> {}", source.debug_sources_inner(list), self.id)
+				format!("{} -> {self}", source.debug_sources_inner(list))
 			}
 			PkgRequestSource::Refused(source) => {
-				format!("{} =X=> {}", source.debug_sources_inner(list), self.id)
+				format!("{} =X=> {self}", source.debug_sources_inner(list))
 			}
 			PkgRequestSource::Bundled(bundler) => {
-				format!("{} => {}", bundler.debug_sources_inner(list), self.id)
+				format!("{} => {self}", bundler.debug_sources_inner(list))
 			}
-			PkgRequestSource::Repository => format!("Repository -> {}{list}", self.id),
+			PkgRequestSource::Repository => format!("Repository -> {self}{list}"),
 		}
 	}
 }
 
 impl PartialEq for PkgRequest {
 	fn eq(&self, other: &Self) -> bool {
-		self.id == other.id
+		self.id == other.id && self.namespace == other.namespace
 	}
 }
 
@@ -149,12 +187,17 @@ impl Eq for PkgRequest {}
 impl Hash for PkgRequest {
 	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
 		self.id.hash(state);
+		self.namespace.hash(state);
 	}
 }
 
 impl Display for PkgRequest {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(f, "{}", self.id)
+		if let Some(namespace) = &self.namespace {
+			write!(f, "{namespace}:{}", self.id)
+		} else {
+			write!(f, "{}", self.id)
+		}
 	}
 }
 
@@ -184,6 +227,32 @@ impl PackageStability {
 	}
 }
 
+/// How a configuration file that a package installs is combined with the file already in the instance
+#[derive(Deserialize, Serialize, Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigFilePolicy {
+	/// Only write the file if it doesn't exist yet
+	#[default]
+	Create,
+	/// Always replace the file
+	Overwrite,
+	/// Merge the keys of a JSON file into the existing one
+	JsonMerge,
+}
+
+impl ConfigFilePolicy {
+	/// Parse a ConfigFilePolicy from a string
+	pub fn parse_from_str(string: &str) -> Option<Self> {
+		match string {
+			"create" => Some(Self::Create),
+			"overwrite" => Some(Self::Overwrite),
+			"json_merge" => Some(Self::JsonMerge),
+			_ => None,
+		}
+	}
+}
+
 /// The maximum length for a package identifier
 pub const MAX_PACKAGE_ID_LENGTH: usize = 32;
 
@@ -262,4 +331,46 @@ mod tests {
 		let debug = req.debug_sources();
 		assert_eq!(debug, "Repository -> baz -> bar -> foo");
 	}
+
+	#[test]
+	fn test_request_parse() {
+		let parse = |string| PkgRequest::parse_and_validate(string, PkgRequestSource::UserRequire);
+
+		let req = parse("sodium").unwrap();
+		assert_eq!(&*req.id, "sodium");
+		assert_eq!(req.namespace, None);
+		assert_eq!(req.content_version, VersionPattern::Any);
+		assert_eq!(req.to_string(), "sodium");
+
+		let req = parse("modrinth:sodium").unwrap();
+		assert_eq!(&*req.id, "sodium");
+		assert_eq!(req.namespace.as_deref(), Some("modrinth"));
+		assert_eq!(req.to_string(), "modrinth:sodium");
+
+		let req = parse("my-repo:sodium@1.2").unwrap();
+		assert_eq!(&*req.id, "sodium");
+		assert_eq!(req.namespace.as_deref(), Some("my-repo"));
+		assert_eq!(req.content_version, VersionPattern::from("1.2"));
+
+		// Colons in the content version are not a namespace
+		let req = parse("sodium@1:2").unwrap();
+		assert_eq!(req.namespace, None);
+		assert_eq!(req.content_version, VersionPattern::from("1:2"));
+
+		assert!(parse(":sodium").is_err());
+		assert!(parse("modrinth:").is_err());
+		assert!(parse("a:b:c").is_err());
+		assert!(parse("my repo:sodium").is_err());
+		assert!(parse("modrinth:Sodium").is_err());
+		assert!(parse("sodium_mod").is_err());
+	}
+
+	#[test]
+	fn test_request_namespace_identity() {
+		let parse = |string| PkgRequest::parse(string, PkgRequestSource::UserRequire);
+		assert_eq!(parse("foo"), parse("foo@2"));
+		assert_ne!(parse("foo"), parse("repo:foo"));
+		assert_ne!(parse("repo:foo"), parse("other:foo"));
+		assert_eq!(parse("repo:foo"), parse("repo:foo"));
+	}
 }
@@ -210,6 +210,22 @@ pub fn yes_no(string: &str) -> Option<bool> {
 	}
 }
 
+/// Edit distance between two strings
+pub fn levenshtein(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut previous: Vec<usize> = (0..=b.len()).collect();
+	for (i, a_char) in a.chars().enumerate() {
+		let mut current = vec![i + 1];
+		for (j, b_char) in b.iter().enumerate() {
+			let substitution = previous[j] + usize::from(a_char != *b_char);
+			current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+		}
+		previous = current;
+	}
+
+	previous[b.len()]
+}
+
 /// Checks if a string is a valid identifier
 pub fn is_valid_identifier(id: &str) -> bool {
 	for c in id.chars() {
@@ -390,6 +406,15 @@ mod tests {
 		assert!(!is_valid_identifier("hello world"));
 	}
 
+	#[test]
+	fn test_levenshtein() {
+		assert_eq!(levenshtein("1.20.11", "1.20.1"), 1);
+		assert_eq!(levenshtein("1.20.1", "1.20.11"), 1);
+		assert_eq!(levenshtein("1.19", "1.91"), 2);
+		assert_eq!(levenshtein("", "abc"), 3);
+		assert_eq!(levenshtein("same", "same"), 0);
+	}
+
 	#[test]
 	fn test_deser_list_or_single_iter() {
 		let item = DeserListOrSingle::Single(7);
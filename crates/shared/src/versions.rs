@@ -1,5 +1,8 @@
+use std::collections::HashSet;
 use std::fmt::Display;
+use std::str::FromStr;
 
+use anyhow::bail;
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -20,6 +23,8 @@ pub enum VersionPattern {
 	Range(String, String),
 	/// Matches any version
 	Any,
+	/// Matches any version that matches at least one of the patterns
+	AnyOf(Vec<VersionPattern>),
 }
 
 impl VersionPattern {
@@ -32,7 +37,7 @@ impl VersionPattern {
 			},
 			Self::Latest(found) => match found {
 				Some(found) => vec![found.clone()],
-				None => match versions.get(versions.len()).cloned() {
+				None => match versions.last().cloned() {
 					Some(version) => vec![version],
 					None => vec![],
 				},
@@ -53,6 +58,24 @@ impl VersionPattern {
 				None => vec![],
 			},
 			Self::Any => versions.to_vec(),
+			Self::AnyOf(patterns) => {
+				let matches: HashSet<_> = patterns
+					.iter()
+					.flat_map(|x| x.get_matches(versions))
+					.collect();
+				// Keep the order of the version list
+				let mut out: Vec<_> = versions
+					.iter()
+					.filter(|x| matches.contains(*x))
+					.cloned()
+					.collect();
+				for version in matches {
+					if !versions.contains(&version) {
+						out.push(version);
+					}
+				}
+				out
+			}
 		}
 	}
 
@@ -115,6 +138,7 @@ impl VersionPattern {
 				}
 			}
 			Self::Any => versions.contains(&version.to_string()),
+			Self::AnyOf(patterns) => patterns.iter().any(|x| x.matches_single(version, versions)),
 		}
 	}
 
@@ -140,18 +164,43 @@ impl VersionPattern {
 			.collect()
 	}
 
-	/// Creates a version pattern by parsing a string
+	/// Creates a version pattern by parsing a string. Malformed patterns
+	/// are treated as a single version instead of failing
 	pub fn from(text: &str) -> Self {
+		text.parse()
+			.unwrap_or_else(|_| Self::Single(unescape(text)))
+	}
+
+	/// Parses a pattern that is not a union of multiple patterns
+	fn parse_single(text: &str) -> anyhow::Result<Self> {
 		match text {
-			"latest" => Self::Latest(None),
-			"*" => Self::Any,
+			"latest" => Ok(Self::Latest(None)),
+			"*" => Ok(Self::Any),
+			text if SPACED_VERSIONS.contains(&text) => Ok(Self::Single(text.to_string())),
 			text => {
+				let range_split = split_unescaped(text, RANGE_SEPARATOR);
+				if range_split.len() > 2 {
+					bail!("Version range '{text}' has more than two ends");
+				}
+				if let [start, end] = range_split[..] {
+					let (start, end) = (start.trim(), end.trim());
+					if start.is_empty() || end.is_empty() {
+						bail!("Version range '{text}' is missing one of its ends");
+					}
+					return Ok(Self::Range(unescape(start), unescape(end)));
+				}
+
 				if let Some(last) = text.chars().last() {
 					// Check for escape
-					if !text.chars().nth(text.len() - 2).is_some_and(|x| x == '\\') {
+					let escaped = text
+						.len()
+						.checked_sub(2)
+						.and_then(|x| text.chars().nth(x))
+						.is_some_and(|x| x == '\\');
+					if !escaped {
 						match last {
-							'-' => return Self::Before(text[..text.len() - 1].to_string()),
-							'+' => return Self::After(text[..text.len() - 1].to_string()),
+							'-' => return Ok(Self::Before(text[..text.len() - 1].to_string())),
+							'+' => return Ok(Self::After(text[..text.len() - 1].to_string())),
 							_ => {}
 						}
 					}
@@ -167,11 +216,11 @@ impl VersionPattern {
 						let end = range_split
 							.get(1)
 							.expect("Second element in range split should exist");
-						return Self::Range(start.to_string(), end.to_string());
+						return Ok(Self::Range(start.to_string(), end.to_string()));
 					}
 				}
 
-				Self::Single(text.replace('\\', ""))
+				Ok(Self::Single(unescape(text)))
 			}
 		}
 	}
@@ -179,7 +228,12 @@ impl VersionPattern {
 	/// Checks that a string contains no pattern-special characters
 	#[cfg(test)]
 	pub fn validate(text: &str) -> bool {
-		if text.contains('*') || text.contains("..") || text == "latest" {
+		if text.contains('*')
+			|| text.contains("..")
+			|| text.contains(UNION_SEPARATOR)
+			|| text.contains(RANGE_SEPARATOR)
+			|| text == "latest"
+		{
 			return false;
 		}
 		if let Some(last) = text.chars().last() {
@@ -191,18 +245,92 @@ impl VersionPattern {
 	}
 }
 
+/// The separator between the patterns of a union, like `1.18.2 | 1.19.4`
+const UNION_SEPARATOR: &str = "|";
+/// The separator between the ends of a spaced range, like `1.19 - 1.20.1`
+const RANGE_SEPARATOR: &str = " - ";
+/// Versions from the version manifest that contain the range separator. These are kept
+/// as single versions instead of being parsed as ranges. Any other version like this has to be escaped
+const SPACED_VERSIONS: &[&str] = &["1.14.3 - Combat Test"];
+
+/// Splits a string at every separator that is not escaped with a backslash
+fn split_unescaped<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+	let mut out = Vec::new();
+	let mut start = 0;
+	for (pos, _) in text.match_indices(separator) {
+		if text[..pos].ends_with('\\') {
+			continue;
+		}
+		out.push(&text[start..pos]);
+		start = pos + separator.len();
+	}
+	out.push(&text[start..]);
+
+	out
+}
+
+/// Removes the escape backslashes from a version
+fn unescape(text: &str) -> String {
+	text.replace('\\', "")
+}
+
+/// Escapes the characters in a single version that would otherwise be parsed as a pattern
+fn escape(version: &str) -> String {
+	if version == "latest" || version == "*" {
+		return format!("\\{version}");
+	}
+	let mut out = version
+		.replace(UNION_SEPARATOR, "\\|")
+		.replace(RANGE_SEPARATOR, "\\ - ")
+		.replace("..", "\\..");
+	if out.ends_with('-') || out.ends_with('+') {
+		out.insert(out.len() - 1, '\\');
+	}
+
+	out
+}
+
+impl FromStr for VersionPattern {
+	type Err = anyhow::Error;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let alternatives = split_unescaped(text, UNION_SEPARATOR);
+		if alternatives.len() == 1 {
+			return Self::parse_single(text);
+		}
+
+		let patterns = alternatives
+			.into_iter()
+			.map(|x| {
+				let x = x.trim();
+				if x.is_empty() {
+					bail!("Version pattern '{text}' has an empty alternative");
+				}
+				Self::parse_single(x)
+			})
+			.collect::<anyhow::Result<_>>()?;
+
+		Ok(Self::AnyOf(patterns))
+	}
+}
+
 impl Display for VersionPattern {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		write!(
 			f,
 			"{}",
 			match self {
-				Self::Single(version) => version.to_string(),
+				Self::Single(version) => escape(version),
 				Self::Latest(..) => "latest".into(),
 				Self::Before(version) => version.to_string() + "-",
 				Self::After(version) => version.to_string() + "+",
 				Self::Range(start, end) => start.to_string() + ".." + end,
 				Self::Any => "*".into(),
+				Self::AnyOf(patterns) => patterns
+					.iter()
+					.map(|x| x.to_string())
+					.collect::<Vec<_>>()
+					.join(" | "),
 			}
 		)
 	}
@@ -239,6 +367,8 @@ pub struct VersionInfo {
 
 #[cfg(test)]
 mod tests {
+	use serde::de::value::StrDeserializer;
+
 	use super::*;
 
 	#[test]
@@ -308,6 +438,14 @@ mod tests {
 			VersionPattern::from("1.17.1..1.19.3"),
 			VersionPattern::Range("1.17.1".into(), "1.19.3".into())
 		);
+		assert_eq!(
+			VersionPattern::from("2"),
+			VersionPattern::Single("2".into())
+		);
+		assert_eq!(
+			VersionPattern::from("+"),
+			VersionPattern::After(String::new())
+		);
 	}
 
 	#[test]
@@ -322,6 +460,186 @@ mod tests {
 		);
 	}
 
+	/// Releases interleaved with snapshots, like the list from the version manifest
+	fn snapshot_versions() -> Vec<String> {
+		[
+			"1.18.2",
+			"22w11a",
+			"22w12a",
+			"1.19-pre1",
+			"1.19",
+			"1.19.1",
+			"22w42a",
+			"1.19.3",
+			"1.19.4",
+			"23w12a",
+			"1.20",
+			"1.20.1",
+		]
+		.into_iter()
+		.map(String::from)
+		.collect()
+	}
+
+	#[test]
+	fn test_version_pattern_parse_range_union() {
+		assert_eq!(
+			"1.19 - 1.20.1".parse::<VersionPattern>().unwrap(),
+			VersionPattern::Range("1.19".into(), "1.20.1".into())
+		);
+		assert_eq!(
+			"1.18.2 | 1.19.4".parse::<VersionPattern>().unwrap(),
+			VersionPattern::AnyOf(vec![
+				VersionPattern::Single("1.18.2".into()),
+				VersionPattern::Single("1.19.4".into())
+			])
+		);
+		assert_eq!(
+			"1.18.2- | 1.19 - 1.19.1 | 1.20+|latest"
+				.parse::<VersionPattern>()
+				.unwrap(),
+			VersionPattern::AnyOf(vec![
+				VersionPattern::Before("1.18.2".into()),
+				VersionPattern::Range("1.19".into(), "1.19.1".into()),
+				VersionPattern::After("1.20".into()),
+				VersionPattern::Latest(None),
+			])
+		);
+		// Dashes inside of versions are not ranges
+		assert_eq!(
+			"1.19-pre1".parse::<VersionPattern>().unwrap(),
+			VersionPattern::Single("1.19-pre1".into())
+		);
+		assert_eq!(
+			"1.19-pre1 - 1.19".parse::<VersionPattern>().unwrap(),
+			VersionPattern::Range("1.19-pre1".into(), "1.19".into())
+		);
+		assert_eq!(
+			"1.18.2 \\| 1.19.4".parse::<VersionPattern>().unwrap(),
+			VersionPattern::Single("1.18.2 | 1.19.4".into())
+		);
+
+		assert!("1.18.2 |".parse::<VersionPattern>().is_err());
+		assert!("| 1.18.2".parse::<VersionPattern>().is_err());
+		assert!("1.18.2 || 1.19".parse::<VersionPattern>().is_err());
+		assert!(" - 1.19".parse::<VersionPattern>().is_err());
+		assert!("1.18 - 1.19 - 1.20".parse::<VersionPattern>().is_err());
+		assert_eq!(
+			VersionPattern::from("1.18.2 |"),
+			VersionPattern::Single("1.18.2 |".into())
+		);
+		let deserializer = StrDeserializer::<serde::de::value::Error>::new("1.18.2 |");
+		assert_eq!(
+			VersionPattern::deserialize(deserializer).unwrap(),
+			VersionPattern::Single("1.18.2 |".into())
+		);
+
+		// Versions from the manifest that contain the separator are not ranges
+		assert_eq!(
+			"1.14.3 - Combat Test".parse::<VersionPattern>().unwrap(),
+			VersionPattern::Single("1.14.3 - Combat Test".into())
+		);
+		assert_eq!(
+			"1.14.3 - Combat Test | 1.14.4"
+				.parse::<VersionPattern>()
+				.unwrap(),
+			VersionPattern::AnyOf(vec![
+				VersionPattern::Single("1.14.3 - Combat Test".into()),
+				VersionPattern::Single("1.14.4".into())
+			])
+		);
+		// They have to be escaped to be used as the end of a range
+		assert!("1.14.3 - Combat Test - 1.14.4"
+			.parse::<VersionPattern>()
+			.is_err());
+		assert_eq!(
+			"1.14.3\\ - Combat Test - 1.14.4"
+				.parse::<VersionPattern>()
+				.unwrap(),
+			VersionPattern::Range("1.14.3 - Combat Test".into(), "1.14.4".into())
+		);
+	}
+
+	#[test]
+	fn test_version_pattern_match_range_union() {
+		let versions = snapshot_versions();
+
+		let range = VersionPattern::from("1.19-pre1 - 1.19.3");
+		assert_eq!(
+			range.get_matches(&versions),
+			vec!["1.19-pre1", "1.19", "1.19.1", "22w42a", "1.19.3"]
+		);
+		assert!(range.matches_single("22w42a", &versions));
+		assert!(!range.matches_single("22w12a", &versions));
+		assert!(!range.matches_single("1.19.2", &versions));
+		// Ranges with unknown ends match nothing
+		let unknown = VersionPattern::from("1.19 - 1.19.2");
+		assert!(unknown.get_matches(&versions).is_empty());
+		assert!(!unknown.matches_single("1.19.1", &versions));
+
+		let union = VersionPattern::from("1.20+ | 1.18.2 | 1.19.2 | 22w11a - 22w12a");
+		assert_eq!(
+			union.get_matches(&versions),
+			vec!["1.18.2", "22w11a", "22w12a", "1.20", "1.20.1"]
+		);
+		assert_eq!(union.get_match(&versions), Some("1.20.1".into()));
+		assert!(union.matches_single("22w12a", &versions));
+		assert!(union.matches_single("1.20", &versions));
+		assert!(!union.matches_single("1.19", &versions));
+		// Single versions match even if they aren't in the list
+		assert!(union.matches_single("1.19.2", &versions));
+		assert!(!union.matches_single("1.19.5", &versions));
+
+		assert_eq!(
+			VersionPattern::Latest(None).get_match(&versions),
+			Some("1.20.1".into())
+		);
+		assert!(VersionPattern::from("1.19.2 | 1.19.5")
+			.get_matches(&versions)
+			.is_empty());
+	}
+
+	#[test]
+	fn test_version_pattern_display_round_trip() {
+		let patterns = [
+			"1.19.2",
+			"latest",
+			"*",
+			"1.19.2-",
+			"1.19.2+",
+			"1.17.1..1.19.3",
+			"1.19 - 1.20.1",
+			"1.18.2 | 1.19.4",
+			"1.18.2- | 1.19 - 1.19.1 | 1.20+",
+		];
+		for pattern in patterns {
+			let parsed = VersionPattern::from(pattern);
+			assert_eq!(
+				VersionPattern::from(&parsed.to_string()),
+				parsed,
+				"{pattern}"
+			);
+		}
+
+		let singles = [
+			"1.19.5+",
+			"1.17.1..1.19.3",
+			"a | b",
+			"a - b",
+			"latest",
+			"*",
+			"1.14.3 - Combat Test",
+		];
+		for single in singles {
+			let pattern = VersionPattern::Single(single.into());
+			assert_eq!(
+				VersionPattern::from(&pattern.to_string()),
+				pattern,
+				"{single}"
+			);
+		}
+	}
+
 	#[test]
 	fn test_version_pattern_validation() {
 		assert!(VersionPattern::validate("hello"));
@@ -330,5 +648,7 @@ mod tests {
 		assert!(!VersionPattern::validate("foo+"));
 		assert!(!VersionPattern::validate("f*o"));
 		assert!(!VersionPattern::validate("f..o"));
+		assert!(!VersionPattern::validate("f|o"));
+		assert!(!VersionPattern::validate("f - o"));
 	}
 }
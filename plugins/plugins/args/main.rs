@@ -1,6 +1,7 @@
 use std::str::FromStr;
 
 use anyhow::bail;
+use mcvm::config::jvm_presets::get_builtin_preset;
 use mcvm_plugin::api::{CustomPlugin, MCVMOutput, MessageContents, MessageLevel};
 use mcvm_plugin::hooks::ModifyInstanceConfigResult;
 
@@ -68,38 +69,7 @@ impl ArgsPreset {
 	pub fn generate_args(&self) -> Vec<String> {
 		match self {
 			Self::None => vec![],
-			Self::Aikars => {
-				let (
-					new_size_percent,
-					max_new_size_percent,
-					heap_region_size,
-					reserve_percent,
-					ihop,
-				) = ("40", "50", "16M", "15", "20");
-
-				vec![
-					"-XX:+UseG1GC".to_string(),
-					"-XX:+ParallelRefProcEnabled".to_string(),
-					"-XX:MaxGCPauseMillis=200".to_string(),
-					"-XX:+UnlockExperimentalVMOptions".to_string(),
-					"-XX:+DisableExplicitGC".to_string(),
-					"-XX:+AlwaysPreTouch".to_string(),
-					format!("-XX:G1NewSizePercent={new_size_percent}"),
-					format!("-XX:G1MaxNewSizePercent={max_new_size_percent}"),
-					format!("-XX:G1HeapRegionSize={heap_region_size}"),
-					format!("-XX:G1ReservePercent={reserve_percent}"),
-					"-XX:G1HeapWastePercent=5".to_string(),
-					"-XX:G1MixedGCCountTarget=4".to_string(),
-					format!("-XX:InitiatingHeapOccupancyPercent={ihop}"),
-					"-XX:G1MixedGCLiveThresholdPercent=90".to_string(),
-					"-XX:G1RSetUpdatingPauseTimePercent=5".to_string(),
-					"-XX:SurvivorRatio=32".to_string(),
-					"-XX:+PerfDisableSharedMem".to_string(),
-					"-XX:MaxTenuringThreshold=1".to_string(),
-					"-Dusing.aikars.flags=https://mcflags.emc.gs".to_string(),
-					"-Daikars.new.flags=true".to_string(),
-				]
-			}
+			Self::Aikars => get_builtin_preset("aikar").unwrap_or_default(),
 			Self::Krusic => vec![
 				"-XX:+UnlockExperimentalVMOptions".to_string(),
 				"-XX:+DisableExplicitGC".to_string(),
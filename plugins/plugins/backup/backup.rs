@@ -1,11 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File};
 use std::io::{BufReader, BufWriter, Read};
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, bail, Context};
+use mcvm::instance::read_world_name;
+use mcvm::instance::snapshot::find_world_paths;
+use mcvm::instance::DEFAULT_WORLD_NAME;
+use mcvm::util::hash::hash_file_sha256;
 use mcvm_core::io::json_to_file;
+use mcvm_plugin::context::InstanceInfo;
 use mcvm_shared::util::utc_timestamp;
+use mcvm_shared::Side;
 use rand::Rng;
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
@@ -16,6 +22,8 @@ use zip::{ZipArchive, ZipWriter};
 pub const INDEX_NAME: &str = "index.json";
 /// ID of the default group
 pub const DEFAULT_GROUP: &str = "default";
+/// ID of the group for backups created before an instance's Minecraft version is changed
+pub const VERSION_CHANGE_GROUP: &str = "version_change";
 
 /// Settings for backups
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
@@ -94,6 +102,7 @@ pub struct Index {
 	/// The config for the backups
 	pub config: Config,
 	/// The instance ID for this index
+	#[allow(dead_code)]
 	pub inst_id: String,
 }
 
@@ -151,23 +160,47 @@ impl Index {
 			.context("Backup does not exist")
 	}
 
-	/// Create a new backup
+	/// Create a new backup, returning its ID
 	pub fn create_backup(
 		&mut self,
 		source: BackupSource,
 		group_id: Option<&str>,
 		instance_dir: &Path,
-	) -> anyhow::Result<()> {
+		side: Option<Side>,
+	) -> anyhow::Result<String> {
 		let group_id = group_id.unwrap_or(DEFAULT_GROUP);
 
 		let group_config = self.config.get_group_config(group_id)?;
 
+		self.create_backup_with_config(source, group_id, &group_config, None, instance_dir, side)
+	}
+
+	/// Create a new backup using an already consolidated group config, returning its ID
+	pub fn create_backup_with_config(
+		&mut self,
+		source: BackupSource,
+		group_id: &str,
+		group_config: &GroupConfig,
+		tag: Option<String>,
+		instance_dir: &Path,
+		side: Option<Side>,
+	) -> anyhow::Result<String> {
 		let backup_id = generate_random_id();
 		let backup_path =
 			self.get_backup_path(group_id, &backup_id, group_config.common.storage_type);
 
+		// Back up the worlds if the group doesn't say what to back up
+		let default_paths;
+		let include_paths = if group_config.common.paths.is_empty() {
+			default_paths = get_default_paths(instance_dir, side);
+			&default_paths
+		} else {
+			&group_config.common.paths
+		};
+
 		let mut readers = Vec::new();
-		for path in &group_config.common.paths {
+		let mut file_paths = Vec::new();
+		for path in include_paths {
 			let paths = get_instance_file_paths(path, instance_dir)
 				.context("Failed to get recursive file paths")?;
 			for path in paths {
@@ -175,23 +208,30 @@ impl Index {
 					.with_context(|| format!("Failed to open backed up file with path {path}"))?;
 				let file = BufReader::new(file);
 				readers.push((path.clone(), file));
+				file_paths.push(path);
 			}
 		}
-		write_backup_files(&backup_path, &group_config, readers)?;
+		write_backup_files(&backup_path, group_config, readers)?;
+		let checksums =
+			Checksums::compute(&backup_path, group_config.common.storage_type, file_paths)
+				.context("Failed to compute backup checksums")?;
 
 		let now = utc_timestamp()?;
 		// Add the backup entry to the group
 		let group_entry = self.contents.groups.entry(group_id.into()).or_default();
 		group_entry.backups.push(Entry {
-			id: backup_id,
+			id: backup_id.clone(),
 			date: now,
 			source,
 			storage_type: group_config.common.storage_type,
+			tag,
+			side,
+			checksums: Some(checksums),
 		});
 
-		self.remove_old_backups(group_id, &group_config)?;
+		self.remove_old_backups(group_id, group_config)?;
 
-		Ok(())
+		Ok(backup_id)
 	}
 
 	/// Remove a backup
@@ -244,30 +284,46 @@ impl Index {
 		Ok(())
 	}
 
-	/// Restores a backup
+	/// Restores a backup. The index itself is not changed. The backup is verified first,
+	/// and isn't restored if it is corrupted unless `force` is set
 	pub fn restore_backup(
 		&self,
 		group_id: &str,
 		backup_id: &str,
-		instance_dir: &Path,
+		target: &RestoreTarget,
+		force: bool,
 	) -> anyhow::Result<()> {
-		let group_entry = self
-			.contents
-			.groups
-			.get(group_id)
-			.context("Group does not exist")?;
-		let backup = group_entry
-			.backups
-			.iter()
-			.find(|x| x.id == backup_id)
-			.ok_or(anyhow!("Backup with ID was not found"))?;
+		let backup = self.get_backup(group_id, backup_id)?;
+		target.check_side(backup)?;
+
+		let verification = self
+			.verify_backup(group_id, backup_id)
+			.context("Failed to verify backup")?;
+		if !force && !verification.is_ok() {
+			bail!("Backup '{backup_id}' failed verification, so it was not restored. Use --force to restore it anyway. {verification}");
+		}
+
+		if let RestoreTarget::Dir(dir) = target {
+			fs::create_dir_all(dir).context("Failed to create restore directory")?;
+		}
 
 		let backup_path = self.get_backup_path(group_id, backup_id, backup.storage_type);
-		restore_backup_files(&backup_path, backup.storage_type, instance_dir)?;
+		restore_backup_files(&backup_path, backup.storage_type, target.get_dir())?;
 
 		Ok(())
 	}
 
+	/// Checks the stored data of a backup against its checksums without changing anything
+	pub fn verify_backup(&self, group_id: &str, backup_id: &str) -> anyhow::Result<Verification> {
+		let backup = self.get_backup(group_id, backup_id)?;
+		let Some(checksums) = &backup.checksums else {
+			return Ok(Verification::Unknown);
+		};
+		let backup_path = self.get_backup_path(group_id, backup_id, backup.storage_type);
+
+		checksums.verify(&backup_path)
+	}
+
 	/// Gets the backup directory for a group
 	fn get_group_dir(&self, group_id: &str) -> PathBuf {
 		self.dir.join(group_id)
@@ -310,6 +366,203 @@ pub struct Entry {
 	pub source: BackupSource,
 	/// How the backup is stored on the filesystem
 	pub storage_type: StorageType,
+	/// An optional label for the backup, such as the Minecraft version it was taken from
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tag: Option<String>,
+	/// The side of the instance that the backup was created from. Backups created by older
+	/// versions of the plugin don't have this
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub side: Option<Side>,
+	/// Checksums of the stored data. Backups created by older versions of the plugin don't have these
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub checksums: Option<Checksums>,
+}
+
+/// SHA-256 checksums of the stored data of a backup
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Checksums {
+	/// The checksum of the whole archive
+	Archive {
+		/// The checksum of the archive file
+		sha256: String,
+	},
+	/// The checksums of each file in a folder backup
+	Folder {
+		/// Checksums of the files, keyed by their paths in the backup
+		files: BTreeMap<String, String>,
+	},
+}
+
+impl Checksums {
+	/// Compute the checksums of a stored backup. Takes the paths of the files in the backup
+	pub fn compute(
+		backup_path: &Path,
+		storage_type: StorageType,
+		file_paths: Vec<String>,
+	) -> anyhow::Result<Self> {
+		match storage_type {
+			StorageType::Archive => Ok(Self::Archive {
+				sha256: hash_file_sha256(backup_path)?,
+			}),
+			StorageType::Folder => {
+				let mut files = BTreeMap::new();
+				for path in file_paths {
+					let hash = hash_file_sha256(&backup_path.join(&path))
+						.with_context(|| format!("Failed to hash backed up file {path}"))?;
+					files.insert(path, hash);
+				}
+				Ok(Self::Folder { files })
+			}
+		}
+	}
+
+	/// Check a stored backup against these checksums
+	pub fn verify(&self, backup_path: &Path) -> anyhow::Result<Verification> {
+		let mut corrupted = Vec::new();
+		let mut missing = Vec::new();
+		let mut check = |path: &Path, name: &str, expected: &str| -> anyhow::Result<()> {
+			if !path.exists() {
+				missing.push(name.to_string());
+			} else if hash_file_sha256(path)? != expected {
+				corrupted.push(name.to_string());
+			}
+			Ok(())
+		};
+
+		match self {
+			Self::Archive { sha256 } => {
+				let name = backup_path
+					.file_name()
+					.map(|x| x.to_string_lossy().to_string())
+					.unwrap_or_default();
+				check(backup_path, &name, sha256)?;
+			}
+			Self::Folder { files } => {
+				for (path, sha256) in files {
+					check(&backup_path.join(path), path, sha256)?;
+				}
+			}
+		}
+
+		if corrupted.is_empty() && missing.is_empty() {
+			Ok(Verification::Ok)
+		} else {
+			Ok(Verification::Failed { corrupted, missing })
+		}
+	}
+}
+
+/// The result of verifying a backup
+#[derive(Debug, PartialEq)]
+pub enum Verification {
+	/// The stored data matches the checksums
+	Ok,
+	/// The backup has no checksums to verify against
+	Unknown,
+	/// Some of the stored data is different from when it was created
+	Failed {
+		/// Files that don't match their checksums
+		corrupted: Vec<String>,
+		/// Files that no longer exist
+		missing: Vec<String>,
+	},
+}
+
+impl Verification {
+	/// Checks whether the backup can be trusted. Backups without checksums are trusted
+	pub fn is_ok(&self) -> bool {
+		!matches!(self, Self::Failed { .. })
+	}
+}
+
+impl std::fmt::Display for Verification {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Ok => write!(f, "OK"),
+			Self::Unknown => write!(f, "Unknown, since the backup has no checksums"),
+			Self::Failed { corrupted, missing } => {
+				write!(f, "Failed")?;
+				if !corrupted.is_empty() {
+					write!(f, ". Corrupted files: {}", corrupted.join(", "))?;
+				}
+				if !missing.is_empty() {
+					write!(f, ". Missing files: {}", missing.join(", "))?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+
+/// Where to restore a backup to
+#[derive(Debug, PartialEq)]
+pub enum RestoreTarget {
+	/// The directory of an instance
+	Instance {
+		/// The ID of the instance
+		id: String,
+		/// The instance's directory
+		dir: PathBuf,
+		/// The side of the instance
+		side: Option<Side>,
+	},
+	/// A plain directory, which is created if it doesn't exist
+	Dir(PathBuf),
+}
+
+impl RestoreTarget {
+	/// Create a target for an instance, which must have been created already
+	pub fn instance(info: &InstanceInfo) -> anyhow::Result<Self> {
+		let dir = PathBuf::from(&info.dir);
+		if !dir.exists() {
+			bail!(
+				"Instance '{}' has not been created yet. Update it before restoring a backup into it",
+				info.id
+			);
+		}
+
+		Ok(Self::Instance {
+			id: info.id.clone(),
+			dir,
+			side: info.side,
+		})
+	}
+
+	/// Create a target for a directory. Relative paths are resolved from the current directory
+	pub fn dir(path: &Path) -> anyhow::Result<Self> {
+		let dir = if path.is_absolute() {
+			path.to_owned()
+		} else {
+			std::env::current_dir()
+				.context("Failed to get current directory")?
+				.join(path)
+		};
+
+		Ok(Self::Dir(dir))
+	}
+
+	/// Get the directory that the backup will be restored into
+	pub fn get_dir(&self) -> &Path {
+		match self {
+			Self::Instance { dir, .. } | Self::Dir(dir) => dir,
+		}
+	}
+
+	/// Check that a backup can be restored here. Backups can't be restored onto
+	/// an instance of a different side than they were created from
+	pub fn check_side(&self, backup: &Entry) -> anyhow::Result<()> {
+		let Self::Instance { id, side, .. } = self else {
+			return Ok(());
+		};
+		if let (Some(backup_side), Some(side)) = (backup.side, side) {
+			if backup_side != *side {
+				bail!("Backup '{}' was created from a {backup_side} instance and can't be restored onto {side} instance '{id}'", backup.id);
+			}
+		}
+
+		Ok(())
+	}
 }
 
 /// Where a backup was created from
@@ -342,15 +595,28 @@ pub fn get_backup_directory(base_dir: &Path, inst_id: &str) -> PathBuf {
 /// Generates a random backup ID
 pub fn generate_random_id() -> String {
 	let mut rng = rand::thread_rng();
-	let num = rng.gen_range(0..std::u64::MAX);
+	let num = rng.gen_range(0..u64::MAX);
 	format!("{num:x}")
 }
 
+/// Get the world directories of an instance, which are backed up when a group doesn't set any paths.
+/// Servers use the level-name from their server.properties
+fn get_default_paths(instance_dir: &Path, side: Option<Side>) -> Vec<String> {
+	let side = side.unwrap_or(if instance_dir.join(".minecraft").exists() {
+		Side::Client
+	} else {
+		Side::Server
+	});
+	let world_name = read_world_name(instance_dir).unwrap_or_else(|| DEFAULT_WORLD_NAME.into());
+
+	find_world_paths(instance_dir, side, &world_name)
+}
+
 /// Gets all file paths from a user-provided path recursively
 fn get_instance_file_paths(path: &str, instance_dir: &Path) -> anyhow::Result<Vec<String>> {
 	// Handle glob patterns
 	if path.contains('*') {
-		let glob = format!("{}/{path}", instance_dir.to_string_lossy());
+		let glob = get_glob_pattern(path, instance_dir);
 		let glob = glob::glob(&glob);
 
 		if let Ok(glob) = glob {
@@ -359,12 +625,12 @@ fn get_instance_file_paths(path: &str, instance_dir: &Path) -> anyhow::Result<Ve
 				let path = path?;
 				let rel = path.strip_prefix(instance_dir)?;
 				if path.is_dir() {
-					let rel_str = format!("{}", rel.to_string_lossy());
+					let rel_str = get_archive_path(rel);
 					let recursive_paths = get_instance_file_paths(&rel_str, instance_dir)
 						.context("Failed to read subdirectory")?;
 					out.extend(recursive_paths);
 				} else {
-					out.push(rel.to_string_lossy().to_string());
+					out.push(get_archive_path(rel));
 				}
 			}
 
@@ -381,7 +647,7 @@ fn get_instance_file_paths(path: &str, instance_dir: &Path) -> anyhow::Result<Ve
 			let entry = entry?;
 			let sub_path = entry.path();
 			let rel = sub_path.strip_prefix(instance_dir)?;
-			let rel_str = rel.to_string_lossy().to_string();
+			let rel_str = get_archive_path(rel);
 			if sub_path.is_dir() {
 				let recursive_paths = get_instance_file_paths(&rel_str, instance_dir)
 					.context("Failed to read subdirectory")?;
@@ -395,6 +661,22 @@ fn get_instance_file_paths(path: &str, instance_dir: &Path) -> anyhow::Result<Ve
 	}
 }
 
+/// Gets the glob pattern for a user-provided path in the instance directory.
+/// The instance directory is escaped so that characters like brackets in it aren't treated as part of the pattern
+fn get_glob_pattern(path: &str, instance_dir: &Path) -> String {
+	let instance_dir = glob::Pattern::escape(&instance_dir.to_string_lossy());
+	format!("{instance_dir}{}{path}", std::path::MAIN_SEPARATOR)
+}
+
+/// Converts a relative path in the instance to the path used in backups.
+/// Backups always use forward slashes so that archives made on Windows can be restored anywhere
+fn get_archive_path(rel: &Path) -> String {
+	rel.components()
+		.map(|x| x.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
 /// Writes backup files to the stored format. Takes the path to the backup file / directory.
 /// Readers are pairs of relative file paths and readers for files.
 fn write_backup_files<R: Read>(
@@ -455,3 +737,197 @@ fn restore_backup_files(
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_glob_pattern() {
+		let instance_dir = Path::new("instances").join("[test]");
+		let pattern = get_glob_pattern("world/*.dat", &instance_dir);
+		let expected = glob::Pattern::new(&pattern).unwrap();
+		assert!(expected.matches_path(&instance_dir.join("world").join("level.dat")));
+		assert!(!expected.matches_path(
+			&Path::new("instances")
+				.join("t")
+				.join("world")
+				.join("level.dat")
+		));
+	}
+
+	#[test]
+	fn test_default_paths() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let instance_dir = temp_dir.path();
+		fs::create_dir_all(instance_dir.join("world")).unwrap();
+		fs::write(instance_dir.join("world/level.dat"), "").unwrap();
+		assert_eq!(get_default_paths(instance_dir, None), vec!["world"]);
+
+		fs::write(
+			instance_dir.join("server.properties"),
+			"level-name=survival\nmotd=Hello\n",
+		)
+		.unwrap();
+		for dir in ["survival", "survival_nether"] {
+			fs::create_dir_all(instance_dir.join(dir)).unwrap();
+			fs::write(instance_dir.join(dir).join("level.dat"), "").unwrap();
+		}
+		// Empty worlds aren't backed up
+		fs::create_dir_all(instance_dir.join("survival_the_end")).unwrap();
+		assert_eq!(
+			get_default_paths(instance_dir, Some(Side::Server)),
+			vec!["survival", "survival_nether"]
+		);
+
+		fs::create_dir_all(instance_dir.join(".minecraft/saves/New World")).unwrap();
+		assert_eq!(
+			get_default_paths(instance_dir, None),
+			vec![".minecraft/saves"]
+		);
+	}
+
+	#[test]
+	fn test_restore_target_paths() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path().join("copy");
+		let mut info = InstanceInfo {
+			id: "copy".into(),
+			side: Some(Side::Server),
+			dir: dir.to_string_lossy().to_string(),
+			..Default::default()
+		};
+		assert!(RestoreTarget::instance(&info).is_err());
+
+		fs::create_dir_all(&dir).unwrap();
+		let target = RestoreTarget::instance(&info).unwrap();
+		assert_eq!(target.get_dir(), dir);
+		info.side = None;
+		assert_eq!(
+			RestoreTarget::instance(&info).unwrap(),
+			RestoreTarget::Instance {
+				id: "copy".into(),
+				dir: dir.clone(),
+				side: None
+			}
+		);
+
+		let target = RestoreTarget::dir(&dir).unwrap();
+		assert_eq!(target, RestoreTarget::Dir(dir.clone()));
+		let target = RestoreTarget::dir(Path::new("inspect")).unwrap();
+		assert_eq!(
+			target.get_dir(),
+			std::env::current_dir().unwrap().join("inspect")
+		);
+	}
+
+	#[test]
+	fn test_restore_side_check() {
+		let backup = |side| Entry {
+			id: "abc".into(),
+			date: 0,
+			source: BackupSource::User,
+			storage_type: StorageType::Archive,
+			tag: None,
+			side,
+			checksums: None,
+		};
+		let instance = |side| RestoreTarget::Instance {
+			id: "test".into(),
+			dir: PathBuf::from("test"),
+			side,
+		};
+
+		let server = instance(Some(Side::Server));
+		assert!(server.check_side(&backup(Some(Side::Server))).is_ok());
+		assert!(server.check_side(&backup(Some(Side::Client))).is_err());
+		assert!(instance(Some(Side::Client))
+			.check_side(&backup(Some(Side::Server)))
+			.is_err());
+		// Old backups without a side can be restored anywhere
+		assert!(server.check_side(&backup(None)).is_ok());
+		// Directories don't have a side
+		assert!(RestoreTarget::Dir(PathBuf::from("test"))
+			.check_side(&backup(Some(Side::Client)))
+			.is_ok());
+	}
+
+	#[test]
+	fn test_corrupted_backup() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let instance_dir = dir.join("instance");
+		fs::create_dir_all(instance_dir.join("world/region")).unwrap();
+		fs::write(instance_dir.join("world/level.dat"), "level").unwrap();
+		fs::write(instance_dir.join("world/region/r.0.0.mca"), "region").unwrap();
+
+		for storage_type in [StorageType::Folder, StorageType::Archive] {
+			let config = Config {
+				common: CommonConfig {
+					storage_type,
+					..Default::default()
+				},
+				..Default::default()
+			};
+			let backups_dir = dir.join(format!("backups_{storage_type:?}"));
+			let mut index = Index::open(&backups_dir, "test", &config).unwrap();
+			let id = index
+				.create_backup(BackupSource::User, None, &instance_dir, None)
+				.unwrap();
+			assert_eq!(
+				index.verify_backup(DEFAULT_GROUP, &id).unwrap(),
+				Verification::Ok
+			);
+
+			// Corrupt the stored data
+			let backup_path = index.get_backup_path(DEFAULT_GROUP, &id, storage_type);
+			let expected = match storage_type {
+				StorageType::Folder => {
+					fs::write(backup_path.join("world/level.dat"), "corrupted").unwrap();
+					fs::remove_file(backup_path.join("world/region/r.0.0.mca")).unwrap();
+					Verification::Failed {
+						corrupted: vec!["world/level.dat".into()],
+						missing: vec!["world/region/r.0.0.mca".into()],
+					}
+				}
+				StorageType::Archive => {
+					let mut contents = fs::read(&backup_path).unwrap();
+					let last = contents.len() - 1;
+					contents[last] ^= 0xff;
+					fs::write(&backup_path, contents).unwrap();
+					Verification::Failed {
+						corrupted: vec![format!("{id}.zip")],
+						missing: Vec::new(),
+					}
+				}
+			};
+			assert_eq!(index.verify_backup(DEFAULT_GROUP, &id).unwrap(), expected);
+
+			// Corrupted backups aren't restored over existing data
+			let target = RestoreTarget::Dir(dir.join("restored"));
+			assert!(index
+				.restore_backup(DEFAULT_GROUP, &id, &target, false)
+				.is_err());
+			assert!(!dir.join("restored").exists());
+
+			// Backups from before checksums were stored are unknown
+			index
+				.contents
+				.groups
+				.get_mut(DEFAULT_GROUP)
+				.unwrap()
+				.backups[0]
+				.checksums = None;
+			assert_eq!(
+				index.verify_backup(DEFAULT_GROUP, &id).unwrap(),
+				Verification::Unknown
+			);
+		}
+	}
+
+	#[test]
+	fn test_archive_path() {
+		let rel = Path::new("world").join("region").join("r.0.0.mca");
+		assert_eq!(get_archive_path(&rel), "world/region/r.0.0.mca");
+	}
+}
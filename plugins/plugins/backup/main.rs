@@ -1,20 +1,34 @@
+#![allow(unexpected_cfgs)]
+
 mod backup;
 
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use anyhow::Context;
-use backup::{get_backup_directory, BackupAutoHook, Config, Index, DEFAULT_GROUP};
+use anyhow::{bail, Context};
+use backup::{
+	get_backup_directory, BackupAutoHook, Config, Index, RestoreTarget, Verification,
+	DEFAULT_GROUP, VERSION_CHANGE_GROUP,
+};
 use clap::Parser;
 use color_print::cprintln;
 use mcvm_plugin::api::{CustomPlugin, HookContext};
 use mcvm_plugin::api::{MCVMOutput, MessageContents, MessageLevel};
-use mcvm_plugin::hooks::{self, Hook};
+use mcvm_plugin::hooks::{self, Hook, SubcommandDescription};
+use mcvm_shared::Side;
 
 use crate::backup::BackupSource;
 
 fn main() -> anyhow::Result<()> {
 	let mut plugin = CustomPlugin::new("backup")?;
+	plugin.describe_subcommands(|_, _| {
+		Ok(vec![SubcommandDescription {
+			name: "backup".into(),
+			aliases: vec!["back".into()],
+			about: "Manage backups for instances".into(),
+		}])
+	})?;
+
 	plugin.subcommand(|ctx, args| {
 		let Some(subcommand) = args.first() else {
 			return Ok(());
@@ -41,7 +55,23 @@ fn main() -> anyhow::Result<()> {
 				instance,
 				group,
 				backup,
-			} => restore(&ctx, &instance, group.as_deref(), &backup),
+				target_instance,
+				target_dir,
+				force,
+			} => restore(
+				&ctx,
+				&instance,
+				group.as_deref(),
+				&backup,
+				target_instance.as_deref(),
+				target_dir.as_deref(),
+				force,
+			),
+			Subcommand::Verify {
+				instance,
+				group,
+				backup,
+			} => verify(&ctx, &instance, group.as_deref(), backup.as_deref()),
 			Subcommand::Info {
 				instance,
 				group,
@@ -55,14 +85,70 @@ fn main() -> anyhow::Result<()> {
 
 	plugin.on_instance_launch(|ctx, arg| {
 		let inst_dir = PathBuf::from(&arg.dir);
-		check_auto_hook(ctx, BackupAutoHook::OnLaunch, &arg.id, &inst_dir)?;
+		check_auto_hook(ctx, BackupAutoHook::OnLaunch, &arg.id, &inst_dir, arg.side)?;
 
 		Ok(())
 	})?;
 
 	plugin.on_instance_stop(|ctx, arg| {
 		let inst_dir = PathBuf::from(&arg.dir);
-		check_auto_hook(ctx, BackupAutoHook::OnStop, &arg.id, &inst_dir)?;
+		check_auto_hook(ctx, BackupAutoHook::OnStop, &arg.id, &inst_dir, arg.side)?;
+
+		Ok(())
+	})?;
+
+	plugin.snapshot_instance(|ctx, arg| {
+		let inst_dir = PathBuf::from(&arg.dir);
+		let mut index = get_index(&ctx, &arg.id)?;
+
+		// Use the version change group if it is configured, and fall back to backing up the worlds
+		let config_group = if index.config.groups.contains_key(VERSION_CHANGE_GROUP) {
+			VERSION_CHANGE_GROUP
+		} else {
+			DEFAULT_GROUP
+		};
+		let mut group_config = index.config.get_group_config(config_group)?;
+		if group_config.common.paths.is_empty() {
+			group_config.common.paths = arg.world_paths;
+		}
+
+		let backup_id = index.create_backup_with_config(
+			BackupSource::Auto,
+			VERSION_CHANGE_GROUP,
+			&group_config,
+			Some(arg.old_version),
+			&inst_dir,
+			arg.side,
+		)?;
+		index.finish()?;
+
+		Ok(Some(backup_id))
+	})?;
+
+	plugin.backup_instance(|ctx, arg| {
+		let inst_dir = PathBuf::from(&arg.dir);
+		let mut index = get_index(&ctx, &arg.id)?;
+		let backup_id = index.create_backup(
+			BackupSource::Auto,
+			arg.group.as_deref(),
+			&inst_dir,
+			arg.side,
+		)?;
+		index.finish()?;
+
+		Ok(Some(backup_id))
+	})?;
+
+	plugin.rename_instance(|ctx, arg| {
+		let backups_dir = get_backups_dir(&ctx)?;
+		let old_dir = get_backup_directory(&backups_dir, &arg.old_id);
+		let new_dir = get_backup_directory(&backups_dir, &arg.new_id);
+		if old_dir.exists() {
+			if new_dir.exists() {
+				bail!("Backups already exist for instance '{}'", arg.new_id);
+			}
+			std::fs::rename(old_dir, new_dir).context("Failed to move backups")?;
+		}
 
 		Ok(())
 	})?;
@@ -106,7 +192,11 @@ enum Subcommand {
 		/// The backup to remove
 		backup: String,
 	},
-	#[command(about = "Restore an existing backup")]
+	#[command(
+		about = "Restore an existing backup",
+		long_about = "Restore an existing backup into the instance it was created from, another
+instance, or a plain directory. The backups of the instance are not changed."
+	)]
 	Restore {
 		/// The instance the backup is in
 		instance: String,
@@ -114,6 +204,28 @@ enum Subcommand {
 		group: Option<String>,
 		/// The backup to restore
 		backup: String,
+		/// Restore into this instance instead, which must be of the same side
+		#[arg(long, conflicts_with = "target_dir")]
+		target_instance: Option<String>,
+		/// Unpack the backup into this directory instead
+		#[arg(long)]
+		target_dir: Option<PathBuf>,
+		/// Restore the backup even if it fails verification
+		#[arg(short, long)]
+		force: bool,
+	},
+	#[command(
+		about = "Check that backups haven't been corrupted",
+		long_about = "Check the stored data of backups against the checksums recorded when they were
+created, without changing anything. Backups created before checksums were recorded can't be checked."
+	)]
+	Verify {
+		/// The instance the backups are in
+		instance: String,
+		/// The group the backups are in
+		group: Option<String>,
+		/// The backup to verify. All of the backups in the group are verified if this is not set
+		backup: Option<String>,
 	},
 	#[command(about = "Print information about a specific backup")]
 	Info {
@@ -162,9 +274,10 @@ fn create(
 
 	let mut index = get_index(ctx, instance)?;
 
-	let inst_dir = ctx.get_data_dir()?.join("instances").join(instance);
+	let info = ctx.get_instance_info(instance)?;
+	let inst_dir = PathBuf::from(&info.dir);
 
-	index.create_backup(BackupSource::User, Some(group), &inst_dir)?;
+	index.create_backup(BackupSource::User, Some(group), &inst_dir, info.side)?;
 
 	index.finish()?;
 
@@ -196,17 +309,68 @@ fn restore(
 	instance: &str,
 	group: Option<&str>,
 	backup: &str,
+	target_instance: Option<&str>,
+	target_dir: Option<&Path>,
+	force: bool,
 ) -> anyhow::Result<()> {
 	let group = group.unwrap_or(DEFAULT_GROUP);
 
 	let index = get_index(ctx, instance)?;
 
-	let inst_dir = ctx.get_data_dir()?.join("instances").join(instance);
+	let target = if let Some(dir) = target_dir {
+		RestoreTarget::dir(dir)?
+	} else {
+		RestoreTarget::instance(ctx.get_instance_info(target_instance.unwrap_or(instance))?)?
+	};
 
-	index.restore_backup(group, backup, &inst_dir)?;
-	index.finish()?;
+	index.restore_backup(group, backup, &target, force)?;
 
-	cprintln!("<g>Backup restored.");
+	cprintln!("<g>Backup restored to {}.", target.get_dir().display());
+
+	Ok(())
+}
+
+fn verify(
+	ctx: &HookContext<'_, hooks::Subcommand>,
+	instance: &str,
+	group: Option<&str>,
+	backup: Option<&str>,
+) -> anyhow::Result<()> {
+	let group = group.unwrap_or(DEFAULT_GROUP);
+
+	let index = get_index(ctx, instance)?;
+	let backups: Vec<_> = if let Some(backup) = backup {
+		vec![backup.to_string()]
+	} else {
+		index
+			.contents
+			.groups
+			.get(group)
+			.context("Group does not exist")?
+			.backups
+			.iter()
+			.map(|x| x.id.clone())
+			.collect()
+	};
+
+	let mut failed = 0;
+	for backup in backups {
+		let verification = index
+			.verify_backup(group, &backup)
+			.with_context(|| format!("Failed to verify backup '{backup}'"))?;
+		match verification {
+			Verification::Ok => cprintln!("<k!> - </>{backup}: <g>{verification}"),
+			Verification::Unknown => cprintln!("<k!> - </>{backup}: <y>{verification}"),
+			Verification::Failed { .. } => {
+				failed += 1;
+				cprintln!("<k!> - </>{backup}: <r>{verification}");
+			}
+		}
+	}
+
+	if failed > 0 {
+		bail!("{failed} backups failed verification");
+	}
 
 	Ok(())
 }
@@ -229,6 +393,12 @@ fn info(
 		instance
 	);
 	cprintln!("<k!> - </>Date created: <c>{}", backup.date);
+	if let Some(tag) = &backup.tag {
+		cprintln!("<k!> - </>Tag: <c>{}", tag);
+	}
+	if let Some(side) = &backup.side {
+		cprintln!("<k!> - </>Side: <c>{}", side);
+	}
 
 	Ok(())
 }
@@ -248,7 +418,7 @@ fn get_backup_config<H: Hook>(instance: &str, ctx: &HookContext<'_, H>) -> anyho
 	let config = ctx.get_custom_config().unwrap_or("{}");
 	let mut config: HashMap<String, Config> =
 		serde_json::from_str(config).context("Failed to deserialize custom config")?;
-	let config = config.remove(&instance.to_string()).unwrap_or_default();
+	let config = config.remove(instance).unwrap_or_default();
 	Ok(config)
 }
 
@@ -257,6 +427,7 @@ fn check_auto_hook<H: Hook>(
 	hook: BackupAutoHook,
 	instance: &str,
 	inst_dir: &Path,
+	side: Option<Side>,
 ) -> anyhow::Result<()> {
 	let mut index = get_index(&ctx, instance)?;
 	let groups = index.config.groups.clone();
@@ -277,7 +448,7 @@ fn check_auto_hook<H: Hook>(
 		if let Some(on) = &group.on {
 			#[allow(irrefutable_let_patterns)]
 			if on == &hook {
-				index.create_backup(BackupSource::Auto, Some(&group_id), inst_dir)?;
+				index.create_backup(BackupSource::Auto, Some(&group_id), inst_dir, side)?;
 			}
 		}
 	}
@@ -5,9 +5,18 @@ use clap::Parser;
 use color_print::{cprint, cprintln};
 use docs::Docs;
 use mcvm_plugin::api::CustomPlugin;
+use mcvm_plugin::hooks::SubcommandDescription;
 
 fn main() -> anyhow::Result<()> {
 	let mut plugin = CustomPlugin::new("docs")?;
+	plugin.describe_subcommands(|_, _| {
+		Ok(vec![SubcommandDescription {
+			name: "docs".into(),
+			aliases: Vec::new(),
+			about: "View documentation".into(),
+		}])
+	})?;
+
 	plugin.subcommand(|_, args| {
 		let Some(subcommand) = args.first() else {
 			return Ok(());
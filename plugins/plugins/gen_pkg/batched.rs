@@ -47,7 +47,7 @@ pub struct BatchedPackageConfig {
 }
 
 /// Generate a lot of packages
-pub async fn batched_gen(mut config: BatchedConfig, filter: Vec<String>) {
+pub async fn batched_gen(mut config: BatchedConfig, filter: Vec<String>, client: &Client) {
 	// Read config dir for additional packages
 	if let Some(config_dir) = config.config_dir {
 		let config_dir = PathBuf::from(config_dir);
@@ -75,8 +75,6 @@ pub async fn batched_gen(mut config: BatchedConfig, filter: Vec<String>) {
 		config.packages.extend(additional_pkgs);
 	}
 
-	let client = Client::new();
-
 	println!("Requesting API...");
 
 	// Collect Modrinth projects
@@ -93,7 +91,7 @@ pub async fn batched_gen(mut config: BatchedConfig, filter: Vec<String>) {
 		})
 		.map(|x| x.id.clone())
 		.collect();
-	let modrinth_projects = mcvm_net::modrinth::get_multiple_projects(&modrinth_ids, &client)
+	let modrinth_projects = mcvm_net::modrinth::get_multiple_projects(&modrinth_ids, client)
 		.await
 		.expect("Failed to get Modrinth projects");
 
@@ -3,7 +3,9 @@ use std::fs::File;
 use std::io::stdout;
 
 use clap::Parser;
+use mcvm_core::net::download::Client;
 use mcvm_plugin::api::CustomPlugin;
+use mcvm_plugin::hooks::SubcommandDescription;
 use serde::{Deserialize, Serialize};
 use serde_json::ser::PrettyFormatter;
 use serde_json::Serializer;
@@ -17,11 +19,27 @@ pub mod smithed;
 
 fn main() -> anyhow::Result<()> {
 	let mut plugin = CustomPlugin::new("stats")?;
-	plugin.subcommand(|_, args| {
+	plugin.describe_subcommands(|_, _| {
+		Ok(vec![
+			SubcommandDescription {
+				name: "gen-pkg".into(),
+				aliases: Vec::new(),
+				about: "Generate a single package".into(),
+			},
+			SubcommandDescription {
+				name: "gen-pkg-batched".into(),
+				aliases: Vec::new(),
+				about: "Generate lots of packages".into(),
+			},
+		])
+	})?;
+
+	plugin.subcommand(|ctx, args| {
 		let Some(subcommand) = args.first() else {
 			return Ok(());
 		};
 		let subcommand = subcommand.to_owned();
+		let client = ctx.create_client()?;
 
 		// Trick the parser to give it the right bin name
 		let it = std::iter::once(format!("mcvm {subcommand}")).chain(args.into_iter().skip(1));
@@ -36,14 +54,14 @@ fn main() -> anyhow::Result<()> {
 					)
 					.expect("Failed to deserialize config")
 				});
-				gen(cli.source, config, &cli.id).await;
+				gen(cli.source, config, &cli.id, &client).await;
 			} else if subcommand == "gen-pkg-batched" {
 				let cli = GenPkgBatched::parse_from(it);
 				let config = serde_json::from_reader(
 					File::open(cli.config_path).expect("Failed to open config file"),
 				)
 				.expect("Failed to deserialize config");
-				batched::batched_gen(config, cli.filter).await;
+				batched::batched_gen(config, cli.filter, &client).await;
 			}
 
 			Ok::<(), anyhow::Error>(())
@@ -115,11 +133,22 @@ impl PackageGenerationConfig {
 }
 
 /// Generates a package from a source and config
-pub async fn gen(source: PackageSource, config: Option<PackageGenerationConfig>, id: &str) {
+pub async fn gen(
+	source: PackageSource,
+	config: Option<PackageGenerationConfig>,
+	id: &str,
+	client: &Client,
+) {
 	let config = config.unwrap_or_default();
 	let mut pkg = match source {
 		PackageSource::Smithed => {
-			smithed::gen(id, config.relation_substitutions, &config.force_extensions).await
+			smithed::gen(
+				id,
+				config.relation_substitutions,
+				&config.force_extensions,
+				client,
+			)
+			.await
 		}
 		PackageSource::Modrinth => {
 			modrinth::gen(
@@ -128,6 +157,7 @@ pub async fn gen(source: PackageSource, config: Option<PackageGenerationConfig>,
 				&config.force_extensions,
 				config.make_fabriclike.unwrap_or_default(),
 				config.make_forgelike.unwrap_or_default(),
+				client,
 			)
 			.await
 		}
@@ -14,6 +14,7 @@ use mcvm::shared::util::DeserListOrSingle;
 use mcvm::shared::versions::VersionPattern;
 
 use mcvm::shared::Side;
+use mcvm_core::net::download::Client;
 use mcvm_net::modrinth::{
 	self, DependencyType, KnownLoader, Loader, Member, Project, ProjectType, ReleaseChannel,
 	SideSupport, Version,
@@ -25,17 +26,17 @@ pub async fn gen(
 	force_extensions: &[String],
 	make_fabriclike: bool,
 	make_forgelike: bool,
+	client: &Client,
 ) -> DeclarativePackage {
-	let client = mcvm_core::net::download::Client::new();
-	let project = modrinth::get_project(id, &client)
+	let project = modrinth::get_project(id, client)
 		.await
 		.expect("Failed to get Modrinth project");
 
-	let versions = modrinth::get_multiple_versions(&project.versions, &client)
+	let versions = modrinth::get_multiple_versions(&project.versions, client)
 		.await
 		.expect("Failed to get Modrinth project versions");
 
-	let members = modrinth::get_project_team(id, &client)
+	let members = modrinth::get_project_team(id, client)
 		.await
 		.expect("Failed to get project team members from Modrinth");
 
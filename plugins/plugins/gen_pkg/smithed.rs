@@ -17,8 +17,9 @@ pub async fn gen(
 	id: &str,
 	relation_substitutions: HashMap<String, String>,
 	force_extensions: &[String],
+	client: &Client,
 ) -> DeclarativePackage {
-	let pack = mcvm_net::smithed::get_pack(id, &Client::new())
+	let pack = mcvm_net::smithed::get_pack(id, client)
 		.await
 		.expect("Failed to get pack");
 
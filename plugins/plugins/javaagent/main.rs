@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use mcvm_plugin::api::CustomPlugin;
+use mcvm_plugin::hooks::OnInstancePrepareLaunchResult;
+use serde::Deserialize;
+
+fn main() -> anyhow::Result<()> {
+	let mut plugin = CustomPlugin::new("javaagent")?;
+	plugin.on_instance_prepare_launch(|_, arg| {
+		let Some(config) = arg.custom_config.get("javaagent") else {
+			return Ok(OnInstancePrepareLaunchResult::default());
+		};
+		let config: AgentConfig =
+			serde_json::from_value(config.clone()).context("Invalid javaagent configuration")?;
+
+		let mut arg = format!("-javaagent:{}", config.path);
+		if let Some(options) = &config.options {
+			arg.push('=');
+			arg.push_str(options);
+		}
+
+		Ok(OnInstancePrepareLaunchResult {
+			jvm_args: vec![arg],
+			env: config.env,
+			classpath: config.classpath,
+			..Default::default()
+		})
+	})?;
+
+	Ok(())
+}
+
+/// Configuration for the agent on an instance
+#[derive(Deserialize)]
+struct AgentConfig {
+	/// Path to the agent jar
+	path: String,
+	/// Options string to pass to the agent
+	#[serde(default)]
+	options: Option<String>,
+	/// Environment variables that the agent needs
+	#[serde(default)]
+	env: HashMap<String, String>,
+	/// Extra jars that the agent needs on the classpath
+	#[serde(default)]
+	classpath: Vec<String>,
+}
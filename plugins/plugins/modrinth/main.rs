@@ -2,10 +2,19 @@ use anyhow::Context;
 use clap::Parser;
 use mcvm_core::net::download::Client;
 use mcvm_plugin::api::CustomPlugin;
+use mcvm_plugin::hooks::SubcommandDescription;
 
 fn main() -> anyhow::Result<()> {
 	let mut plugin = CustomPlugin::new("modrinth")?;
-	plugin.subcommand(|_, args| {
+	plugin.describe_subcommands(|_, _| {
+		Ok(vec![SubcommandDescription {
+			name: "modrinth".into(),
+			aliases: vec!["mr".into()],
+			about: "Use the Modrinth API".into(),
+		}])
+	})?;
+
+	plugin.subcommand(|ctx, args| {
 		let Some(subcommand) = args.first() else {
 			return Ok(());
 		};
@@ -15,12 +24,13 @@ fn main() -> anyhow::Result<()> {
 		// Trick the parser to give it the right bin name
 		let it = std::iter::once(format!("mcvm {subcommand}")).chain(args.into_iter().skip(1));
 		let cli = Cli::parse_from(it);
+		let client = ctx.create_client()?;
 
 		let runtime = tokio::runtime::Runtime::new()?;
 		runtime.block_on(async {
 			match cli.subcommand {
-				Subcommand::GetProject { project } => get_modrinth_project(project).await,
-				Subcommand::GetVersion { version } => get_modrinth_version(version).await,
+				Subcommand::GetProject { project } => get_modrinth_project(project, &client).await,
+				Subcommand::GetVersion { version } => get_modrinth_version(version, &client).await,
 			}
 		})?;
 
@@ -50,10 +60,8 @@ enum Subcommand {
 	},
 }
 
-async fn get_modrinth_project(project: String) -> anyhow::Result<()> {
-	let client = Client::new();
-
-	let project = mcvm_net::modrinth::get_project_raw(&project, &client)
+async fn get_modrinth_project(project: String, client: &Client) -> anyhow::Result<()> {
+	let project = mcvm_net::modrinth::get_project_raw(&project, client)
 		.await
 		.context("Failed to get project")?;
 	let project_pretty = mcvm::core::util::json::format_json(&project);
@@ -69,10 +77,8 @@ async fn get_modrinth_project(project: String) -> anyhow::Result<()> {
 	Ok(())
 }
 
-async fn get_modrinth_version(version: String) -> anyhow::Result<()> {
-	let client = Client::new();
-
-	let version = mcvm_net::modrinth::get_version_raw(&version, &client)
+async fn get_modrinth_version(version: String, client: &Client) -> anyhow::Result<()> {
+	let version = mcvm_net::modrinth::get_version_raw(&version, client)
 		.await
 		.context("Failed to get project version")?;
 	let version_pretty = mcvm::core::util::json::format_json(&version);
@@ -1,25 +1,36 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use mcvm_core::Paths;
-use mcvm_options::{
-	client::write_options_txt, read_options, server::write_server_properties, Options,
+use mcvm_options::client::{
+	read_options_txt, resolve_resource_packs, validate_keybinds, write_options_txt, ClientOptions,
 };
+use mcvm_options::diff::display_diff;
+use mcvm_options::server::{
+	create_resource_pack_keys, create_sponge_keys, write_server_properties, write_sponge_config,
+	ServerOptions, SPONGE_GLOBAL_CONFIG_PATH,
+};
+use mcvm_options::{read_options, Options};
 use mcvm_plugin::{
-	api::{CustomPlugin, HookContext},
-	hooks::Hook,
+	api::{CustomPlugin, HookContext, MessageLevel},
+	hooks::{Hook, OnInstanceSetupArg},
 };
+use mcvm_shared::addon::AddonKind;
+use mcvm_shared::output::{MCVMOutput, MessageContents};
 use mcvm_shared::Side;
 
 fn main() -> anyhow::Result<()> {
 	let mut plugin = CustomPlugin::new("options")?;
-	plugin.on_instance_setup(|ctx, arg| {
+	plugin.on_instance_setup(|mut ctx, arg| {
 		// Consolidate the options from all the sources
 		let mut keys = HashMap::new();
+		let mut sponge_keys = HashMap::new();
 		if let Some(global_options) = get_global_options(&ctx)? {
 			match arg.side.unwrap() {
 				Side::Client => {
 					if let Some(global_options) = &global_options.client {
+						check_keybinds(global_options, arg.strict, &mut ctx)?;
 						let global_keys =
 							mcvm_options::client::create_keys(global_options, &arg.version_info)
 								.context("Failed to create keys for global options")?;
@@ -32,6 +43,12 @@ fn main() -> anyhow::Result<()> {
 							mcvm_options::server::create_keys(global_options, &arg.version_info)
 								.context("Failed to create keys for global options")?;
 						keys.extend(global_keys);
+						keys.extend(get_resource_pack_keys(
+							global_options,
+							&arg.game_dir,
+							&mut ctx,
+						)?);
+						sponge_keys.extend(create_sponge_keys(&global_options.sponge));
 					}
 				}
 			}
@@ -41,6 +58,7 @@ fn main() -> anyhow::Result<()> {
 			match arg.side.unwrap() {
 				Side::Client => {
 					let options = serde_json::from_value(options.clone())?;
+					check_keybinds(&options, arg.strict, &mut ctx)?;
 					let override_keys =
 						mcvm_options::client::create_keys(&options, &arg.version_info)
 							.context("Failed to create keys for override options")?;
@@ -52,15 +70,28 @@ fn main() -> anyhow::Result<()> {
 						mcvm_options::server::create_keys(&options, &arg.version_info)
 							.context("Failed to create keys for override options")?;
 					keys.extend(override_keys);
+					keys.extend(get_resource_pack_keys(&options, &arg.game_dir, &mut ctx)?);
+					sponge_keys.extend(create_sponge_keys(&options.sponge));
 				}
 			}
 		}
 
 		// Write the options
 		if !keys.is_empty() {
+			let (file_name, sep) = match arg.side.unwrap() {
+				Side::Client => ("options.txt", ':'),
+				Side::Server => ("server.properties", '='),
+			};
+			let options_path = PathBuf::from(&arg.game_dir).join(file_name);
+			let old_contents = std::fs::read_to_string(&options_path).unwrap_or_default();
 			match arg.side.unwrap() {
 				Side::Client => {
-					let options_path = PathBuf::from(arg.game_dir).join("options.txt");
+					keys.extend(get_client_resource_pack_keys(
+						&keys,
+						&options_path,
+						&arg,
+						&mut ctx,
+					));
 					let paths = Paths::new()?;
 					let data_version =
 						mcvm_core::io::minecraft::get_data_version(&arg.version_info, &paths);
@@ -68,11 +99,32 @@ fn main() -> anyhow::Result<()> {
 						.context("Failed to write options.txt")?;
 				}
 				Side::Server => {
-					let options_path = PathBuf::from(arg.game_dir).join("server.properties");
 					write_server_properties(keys, &options_path)
 						.context("Failed to write server.properties")?;
 				}
 			}
+
+			let new_contents = std::fs::read_to_string(&options_path)
+				.with_context(|| format!("Failed to read written {file_name}"))?;
+			let level = if arg.show_diffs {
+				MessageLevel::Important
+			} else {
+				MessageLevel::Debug
+			};
+			display_diff(
+				file_name,
+				&old_contents,
+				&new_contents,
+				sep,
+				level,
+				ctx.get_output(),
+			);
+		}
+
+		if !sponge_keys.is_empty() {
+			let config_path = PathBuf::from(&arg.game_dir).join(SPONGE_GLOBAL_CONFIG_PATH);
+			write_sponge_config(sponge_keys, &config_path)
+				.context("Failed to write Sponge config")?;
 		}
 
 		Ok(())
@@ -81,6 +133,74 @@ fn main() -> anyhow::Result<()> {
 	Ok(())
 }
 
+/// Warn about invalid keybinds, or fail if strict
+fn check_keybinds<H: Hook>(
+	options: &ClientOptions,
+	strict: bool,
+	ctx: &mut HookContext<'_, H>,
+) -> anyhow::Result<()> {
+	let problems = validate_keybinds(&options.control.keys);
+	if strict && !problems.is_empty() {
+		bail!("{}", problems.join("\n"));
+	}
+	for problem in problems {
+		ctx.get_output().display(
+			MessageContents::Warning(format!("{problem}. It will not be written")),
+			MessageLevel::Important,
+		);
+	}
+
+	Ok(())
+}
+
+/// Hash the local resource pack of server options, showing any warnings
+fn get_resource_pack_keys<H: Hook>(
+	options: &ServerOptions,
+	game_dir: &str,
+	ctx: &mut HookContext<'_, H>,
+) -> anyhow::Result<HashMap<String, String>> {
+	let out = create_resource_pack_keys(&options.resource_pack, Path::new(game_dir))
+		.context("Failed to create resource pack keys")?;
+	for warning in out.warnings {
+		ctx.get_output()
+			.display(MessageContents::Warning(warning), MessageLevel::Important);
+	}
+
+	Ok(out.keys)
+}
+
+/// Check the configured client resource packs and find the incompatible ones, showing any warnings
+fn get_client_resource_pack_keys<H: Hook>(
+	keys: &HashMap<String, String>,
+	options_path: &Path,
+	arg: &OnInstanceSetupArg,
+	ctx: &mut HookContext<'_, H>,
+) -> HashMap<String, String> {
+	let existing_keys = read_options_txt(options_path).unwrap_or_default();
+	// Packs installed by packages count as present even if they haven't been downloaded yet
+	let addon_files: Vec<_> = ctx
+		.get_installed_packages(&arg.id)
+		.unwrap_or_default()
+		.iter()
+		.flat_map(|x| &x.addons)
+		.filter(|x| matches!(x.kind, AddonKind::ResourcePack))
+		.flat_map(|x| x.files.clone())
+		.collect();
+	let out = resolve_resource_packs(
+		keys,
+		&existing_keys,
+		Path::new(&arg.game_dir),
+		&addon_files,
+		&arg.version_info,
+	);
+	for warning in out.warnings {
+		ctx.get_output()
+			.display(MessageContents::Warning(warning), MessageLevel::Important);
+	}
+
+	out.keys
+}
+
 fn get_global_options<H: Hook>(ctx: &HookContext<'_, H>) -> anyhow::Result<Option<Options>> {
 	let config_file = ctx.get_config_dir()?.join("options.json");
 	read_options(&config_file)
@@ -8,12 +8,20 @@ use color_print::cprintln;
 use itertools::Itertools;
 use mcvm_core::io::{json_from_file, json_to_file};
 use mcvm_plugin::api::{CustomPlugin, HookContext};
-use mcvm_plugin::hooks::{Hook, Subcommand};
+use mcvm_plugin::hooks::{Hook, Subcommand, SubcommandDescription};
 use mcvm_shared::util::utc_timestamp;
 use serde::{Deserialize, Serialize};
 
 fn main() -> anyhow::Result<()> {
 	let mut plugin = CustomPlugin::new("stats")?;
+	plugin.describe_subcommands(|_, _| {
+		Ok(vec![SubcommandDescription {
+			name: "stats".into(),
+			aliases: Vec::new(),
+			about: "View playtime stats".into(),
+		}])
+	})?;
+
 	plugin.subcommand(|ctx, args| {
 		let Some(subcommand) = args.first() else {
 			return Ok(());
@@ -80,6 +88,16 @@ fn main() -> anyhow::Result<()> {
 		}
 	})?;
 
+	plugin.rename_instance(|ctx, arg| {
+		let mut stats = Stats::open(&ctx).context("Failed to open stats")?;
+		if let Some(instance) = stats.instances.remove(&arg.old_id) {
+			stats.instances.insert(arg.new_id, instance);
+			stats.write(&ctx).context("Failed to write stats")?;
+		}
+
+		Ok(())
+	})?;
+
 	Ok(())
 }
 
@@ -114,7 +132,15 @@ fn update_playtime<H: Hook>(ctx: &mut HookContext<'_, H>, instance: &str) -> any
 struct Cli {}
 
 fn print_stats(ctx: HookContext<'_, Subcommand>) -> anyhow::Result<()> {
-	let stats = Stats::open(&ctx).context("Failed to open stats")?;
+	let mut stats = Stats::open(&ctx).context("Failed to open stats")?;
+	// Show instances that haven't been launched yet, but not ones that have been removed
+	let instances = ctx.list_instances()?;
+	stats
+		.instances
+		.retain(|id, _| instances.contains(&id.as_str()));
+	for instance in instances {
+		stats.instances.entry(instance.into()).or_default();
+	}
 
 	#[derive(PartialEq, Eq, PartialOrd, Ord)]
 	struct Ordering {
@@ -131,8 +157,13 @@ fn print_stats(ctx: HookContext<'_, Subcommand>) -> anyhow::Result<()> {
 			playtime: Reverse(stats.playtime),
 			instance_id: inst_id.clone(),
 		}) {
+		let version = ctx
+			.get_instance_info(&instance)?
+			.version
+			.as_deref()
+			.unwrap_or("unknown version");
 		cprintln!(
-			"<k!> - </><b,s>{instance}</> - Launched <m>{}</> times for a total of <m!>{}</>",
+			"<k!> - </><b,s>{instance}</> <k!>({version})</> - Launched <m>{}</> times for a total of <m!>{}</>",
 			stats.launches,
 			format_time(stats.playtime)
 		);
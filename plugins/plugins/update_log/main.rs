@@ -0,0 +1,55 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use anyhow::Context;
+use mcvm_plugin::api::CustomPlugin;
+use mcvm_plugin::hooks::{OnProfileUpdateArg, PackageChangeKind};
+use mcvm_shared::util::utc_timestamp;
+
+fn main() -> anyhow::Result<()> {
+	let mut plugin = CustomPlugin::new("update_log")?;
+	plugin.on_profile_update(|ctx, arg| {
+		let dir = ctx.get_data_dir()?.join("logs");
+		std::fs::create_dir_all(&dir).context("Failed to create log directory")?;
+		let mut file = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(dir.join("profile_updates.log"))
+			.context("Failed to open update log")?;
+		file.write_all(format_summary(&arg, utc_timestamp()?).as_bytes())
+			.context("Failed to write to update log")?;
+
+		Ok(())
+	})?;
+
+	Ok(())
+}
+
+/// Format the summary of a profile update as an entry in the log
+fn format_summary(arg: &OnProfileUpdateArg, timestamp: u64) -> String {
+	let version = |x: &Option<String>| x.clone().unwrap_or_else(|| "none".into());
+	let mut out = format!(
+		"[{timestamp}] Updated profile '{}' ({} -> {}) on instances {}\n",
+		arg.profile,
+		version(&arg.old_version),
+		version(&arg.new_version),
+		arg.instances.join(", ")
+	);
+	for change in &arg.packages {
+		let line = match change.kind {
+			PackageChangeKind::Added => format!("+ {}", version(&change.new_version)),
+			PackageChangeKind::Removed => format!("- {}", version(&change.old_version)),
+			PackageChangeKind::Upgraded => format!(
+				"~ {} -> {}",
+				version(&change.old_version),
+				version(&change.new_version)
+			),
+		};
+		out.push_str(&format!(
+			"  {}:{} {line}\n",
+			change.instance, change.package
+		));
+	}
+
+	out
+}
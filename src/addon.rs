@@ -1,14 +1,22 @@
 use anyhow::{bail, Context};
 use mcvm_shared::addon::{Addon, AddonKind};
 use mcvm_shared::pkg::PackageAddonOptionalHashes;
+use mcvm_shared::util::utc_timestamp;
 use reqwest::Client;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
+use crate::io::lock::AddonProvenance;
 use crate::io::paths::Paths;
-use crate::util::hash::{get_best_hash, hash_file_with_best_hash};
-use mcvm_core::io::files::{create_leading_dirs, update_hardlink};
+use crate::util::hash::{get_best_hash, hash_file_sha256, hash_file_with_best_hash};
+use mcvm_core::io::files::{
+	create_leading_dirs, is_cross_device_error, update_hardlink, update_hardlink_or_copy,
+};
 use mcvm_core::net::download;
 use mcvm_shared::modifications::{Modloader, ServerType};
 
+use std::fs::File;
 use std::future::Future;
 use std::path::{Path, PathBuf};
 
@@ -82,6 +90,124 @@ pub fn is_stored_addon_path(path: &Path, paths: &Paths) -> bool {
 	path.starts_with(&paths.addons)
 }
 
+/// How addon files are put into instances
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AddonLinkMode {
+	/// Hard link the stored addon, falling back to copying it if the instance is on another filesystem
+	#[default]
+	Hardlink,
+	/// Copy the stored addon
+	Copy,
+}
+
+/// What to do when addons from different packages would be put at the same file in an instance
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AddonConflictPolicy {
+	/// Fail the update
+	#[default]
+	Error,
+	/// Keep the addon from the package that was installed first and show a warning
+	FirstWins,
+	/// Keep the addon from the package that was installed first if both have the same hash,
+	/// and fail the update otherwise
+	AllowIdentical,
+}
+
+/// Put a stored addon file into an instance at the link path. The previous mode is how the
+/// existing file at the link path was created, if there is one. Returns how the file was placed.
+pub fn link_addon_file(
+	addon_path: &Path,
+	link: &Path,
+	mode: AddonLinkMode,
+	previous_mode: Option<AddonLinkMode>,
+) -> anyhow::Result<AddonLinkMode> {
+	if mode == AddonLinkMode::Copy {
+		copy_addon_file(addon_path, link, previous_mode)?;
+		return Ok(AddonLinkMode::Copy);
+	}
+
+	// We have to remove the current link since it doesnt let us update it in place
+	if link.exists() {
+		std::fs::remove_file(link).context("Failed to remove instance addon file")?;
+	}
+	match update_hardlink(addon_path, link) {
+		Ok(()) => Ok(AddonLinkMode::Hardlink),
+		// Some filesystems, like FAT32 drives on Windows, don't support hard links at all
+		Err(e) if is_cross_device_error(&e) || e.kind() == std::io::ErrorKind::Unsupported => {
+			copy_addon_file(addon_path, link, None)?;
+			Ok(AddonLinkMode::Copy)
+		}
+		Err(e) => Err(e).context("Failed to create hard link"),
+	}
+}
+
+/// Copy a stored addon file into an instance, skipping the copy if an existing copy is up to date
+fn copy_addon_file(
+	addon_path: &Path,
+	dest: &Path,
+	previous_mode: Option<AddonLinkMode>,
+) -> anyhow::Result<()> {
+	if dest.exists() {
+		// Only files we copied before can be checked, as hard links always look up to date
+		if previous_mode == Some(AddonLinkMode::Copy) && is_copy_up_to_date(addon_path, dest) {
+			return Ok(());
+		}
+		std::fs::remove_file(dest).context("Failed to remove instance addon file")?;
+	}
+
+	std::fs::copy(addon_path, dest).context("Failed to copy addon")?;
+	// Keep the modification time of the stored addon so we can tell when the copy is stale
+	let modified = std::fs::metadata(addon_path)
+		.and_then(|x| x.modified())
+		.context("Failed to get modification time of addon")?;
+	let file = File::options()
+		.write(true)
+		.open(dest)
+		.context("Failed to open copied addon")?;
+	file.set_modified(modified)
+		.context("Failed to set modification time of copied addon")?;
+
+	Ok(())
+}
+
+/// Check whether an addon file in an instance still matches the stored addon it was created from.
+/// Hard linked files are also checked against the known hashes of the addon, if there are any
+pub fn is_addon_file_intact(
+	addon_path: &Path,
+	link: &Path,
+	mode: AddonLinkMode,
+	hashes: &PackageAddonOptionalHashes,
+) -> bool {
+	match mode {
+		AddonLinkMode::Copy => is_copy_up_to_date(addon_path, link),
+		// Hard links share their contents with the stored addon, unless the file was replaced
+		AddonLinkMode::Hardlink => {
+			let same_size = matches!((std::fs::metadata(addon_path), std::fs::metadata(link)), (Ok(x), Ok(y)) if x.len() == y.len());
+			if !same_size {
+				return false;
+			}
+			match get_best_hash(hashes) {
+				Some(hash) => hash_file_with_best_hash(link, hash).unwrap_or(false),
+				None => true,
+			}
+		}
+	}
+}
+
+/// Check whether a copy of an addon has the same size and modification time as the original
+fn is_copy_up_to_date(addon_path: &Path, copy: &Path) -> bool {
+	let (Ok(original), Ok(copy)) = (std::fs::metadata(addon_path), std::fs::metadata(copy)) else {
+		return false;
+	};
+
+	original.len() == copy.len()
+		&& matches!((original.modified(), copy.modified()), (Ok(x), Ok(y)) if x == y)
+}
+
 /// The location of an addon
 #[derive(Debug, Clone)]
 pub enum AddonLocation {
@@ -106,11 +232,45 @@ impl AddonRequest {
 		Self { addon, location }
 	}
 
+	/// Get where the addon is located
+	pub fn get_location(&self) -> &AddonLocation {
+		&self.location
+	}
+
 	/// Get a unique identifier for this addon
 	pub fn get_unique_id(&self, instance_id: &str) -> String {
 		self.addon.get_unique_id(instance_id)
 	}
 
+	/// Get where this addon came from, to record once it has been put into an instance. The previous record
+	/// is kept if this version of the addon is still from the same place, so the file doesn't need to be hashed again
+	pub fn get_provenance(
+		&self,
+		paths: &Paths,
+		instance_id: &str,
+		previous: Option<&AddonProvenance>,
+	) -> anyhow::Result<AddonProvenance> {
+		let (url, path) = match &self.location {
+			AddonLocation::Remote(url) => (Some(url.clone()), None),
+			AddonLocation::Local(path) => (None, Some(path.to_string_lossy().to_string())),
+		};
+		// Addons without a version are stored in the same file every time, so it may have changed
+		if let Some(previous) = previous {
+			if self.addon.version.is_some() && previous.url == url && previous.path == path {
+				return Ok(previous.clone());
+			}
+		}
+
+		let sha256 = hash_file_sha256(&self.addon.get_path(paths, instance_id))
+			.context("Failed to hash stored addon file")?;
+		Ok(AddonProvenance {
+			url,
+			path,
+			sha256,
+			installed: utc_timestamp()?,
+		})
+	}
+
 	/// Get the addon and store it
 	pub async fn acquire(
 		&self,
@@ -146,7 +306,7 @@ impl AddonRequest {
 						.context("Failed to download addon")?;
 				}
 				AddonLocation::Local(actual_path) => {
-					update_hardlink(&actual_path, &path)
+					update_hardlink_or_copy(&actual_path, &path)
 						.context("Failed to hardlink local addon")?;
 				}
 			}
@@ -227,4 +387,113 @@ mod tests {
 		};
 		assert_eq!(addon.split_filename(), ("FooBar", ".baz.jar"));
 	}
+
+	#[test]
+	fn test_copy_addon_file() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let stored = dir.join("stored.jar");
+		let link = dir.join("instance.jar");
+		std::fs::write(&stored, "first").unwrap();
+
+		let mode = link_addon_file(&stored, &link, AddonLinkMode::Copy, None).unwrap();
+		assert_eq!(mode, AddonLinkMode::Copy);
+		assert_eq!(std::fs::read_to_string(&link).unwrap(), "first");
+		assert!(is_copy_up_to_date(&stored, &link));
+
+		// Changes to the stored addon should be picked up by the next copy
+		std::fs::write(&stored, "second!").unwrap();
+		assert!(!is_copy_up_to_date(&stored, &link));
+		link_addon_file(
+			&stored,
+			&link,
+			AddonLinkMode::Copy,
+			Some(AddonLinkMode::Copy),
+		)
+		.unwrap();
+		assert_eq!(std::fs::read_to_string(&link).unwrap(), "second!");
+	}
+
+	#[test]
+	fn test_hardlinked_addon_hash() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let stored = dir.join("stored.jar");
+		let link = dir.join("instance.jar");
+		std::fs::write(&stored, "sodium").unwrap();
+		std::fs::write(&link, "sodium").unwrap();
+		let hashes = PackageAddonOptionalHashes {
+			sha256: Some(hash_file_sha256(&stored).unwrap()),
+			sha512: None,
+		};
+
+		assert!(is_addon_file_intact(
+			&stored,
+			&link,
+			AddonLinkMode::Hardlink,
+			&hashes
+		));
+		// A replaced file with the same size is only caught by the hash
+		std::fs::write(&link, "iodine").unwrap();
+		assert!(is_addon_file_intact(
+			&stored,
+			&link,
+			AddonLinkMode::Hardlink,
+			&PackageAddonOptionalHashes::default()
+		));
+		assert!(!is_addon_file_intact(
+			&stored,
+			&link,
+			AddonLinkMode::Hardlink,
+			&hashes
+		));
+	}
+
+	#[test]
+	fn test_addon_provenance() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let mut paths = Paths::new_no_create().unwrap();
+		paths.addons = dir.join("addons");
+
+		let request = |url: &str| {
+			let addon = Addon {
+				kind: AddonKind::Mod,
+				id: "sodium".into(),
+				file_name: "sodium-0.5.3.jar".into(),
+				pkg_id: PackageID::from("sodium"),
+				version: Some("0.5.3".into()),
+				hashes: PackageAddonOptionalHashes::default(),
+			};
+			AddonRequest::new(addon, AddonLocation::Remote(url.into()))
+		};
+		let stored = request("").addon.get_path(&paths, "inst");
+		create_leading_dirs(&stored).unwrap();
+		std::fs::write(&stored, "sodium").unwrap();
+
+		let first = request("example.com/sodium.jar")
+			.get_provenance(&paths, "inst", None)
+			.unwrap();
+		assert_eq!(first.url.as_deref(), Some("example.com/sodium.jar"));
+		assert_eq!(
+			first.sha256,
+			"ee9d62778c8b664aa8501af83ec4738e01d20f2cdca133208c7bf66cbcaa37b8"
+		);
+
+		// The same version from the same place keeps its record
+		let mut previous = first.clone();
+		previous.installed = 0;
+		let kept = request("example.com/sodium.jar")
+			.get_provenance(&paths, "inst", Some(&previous))
+			.unwrap();
+		assert_eq!(kept, previous);
+
+		// A different source is recorded again
+		let moved = request("mirror.example.com/sodium.jar")
+			.get_provenance(&paths, "inst", Some(&previous))
+			.unwrap();
+		assert_eq!(moved.url.as_deref(), Some("mirror.example.com/sodium.jar"));
+		assert_eq!(moved.sha256, first.sha256);
+		assert_ne!(moved.installed, 0);
+	}
 }
@@ -0,0 +1,1088 @@
+//! These functions wrap the lower level modules of the library so that you don't have to
+//! wire up plugins, the lockfile, and update contexts yourself. Nothing here prints to the terminal;
+//! all feedback is sent through the [MCVMOutput] that you pass in.
+//!
+//! ```no_run
+//! use mcvm::api::{self, UpdateOptions};
+//! use mcvm::core::auth_crate::mc::ClientId;
+//! use mcvm::instance::launch::LaunchSettings;
+//! use mcvm::io::paths::Paths;
+//! use mcvm::shared::output::Simple;
+//! use mcvm::shared::output::MessageLevel;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let paths = Paths::new().await?;
+//! let mut o = Simple(MessageLevel::Important);
+//! let mut config = api::load_config(&paths, &mut o)?;
+//!
+//! api::update_profile(&mut config, "survival", &UpdateOptions::default(), &paths, &mut o).await?;
+//!
+//! let settings = LaunchSettings {
+//!     ms_client_id: ClientId::new("my-client-id".into()),
+//!     offline_auth: false,
+//!     pipe_stdin: false,
+//!     mirror_output: false,
+//!     window: Default::default(),
+//!     update_timeout: None,
+//!     verify: true,
+//!     download_limit: None,
+//!     user: None,
+//! };
+//! let handle = api::launch_instance(&mut config, "survival-client", settings, &paths, &mut o).await?;
+//! let status = handle.wait(&config.plugins, &paths, &mut o)?;
+//! println!("Game exited with {status}");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use mcvm_core::io::java::install::JavaInstallationKind;
+use mcvm_core::io::java::JavaMajorVersion;
+use mcvm_core::io::persistent::{
+	ManagedJavaInstallation, PersistentData, PersistentDataJavaInstallation,
+};
+use mcvm_core::io::update::UpdateManager;
+use mcvm_core::launch::ScriptKind;
+use mcvm_core::net::download;
+use mcvm_core::net::game_files::asset_report::{self, AssetReport};
+use mcvm_core::net::game_files::assets::get_objects_dir_path;
+use mcvm_core::net::game_files::version_manifest::{self, make_version_list};
+use mcvm_core::net::stats::{self, TransferStats};
+use mcvm_mods::paper;
+use mcvm_options::diff::is_secret_key;
+use mcvm_pkg::{PkgRequest, PkgRequestSource};
+use mcvm_shared::addon::AddonKind;
+use mcvm_shared::id::{InstanceID, ProfileID};
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::pkg::{PackageID, PackageStability};
+use mcvm_shared::{translate, Side};
+use serde::Serialize;
+
+use crate::config::plugin::PluginManager;
+use crate::config::Config;
+use crate::instance::launch::{InstanceHandle, LargeDownload, LaunchSettings};
+use crate::instance::update::packages::PackageFailures;
+use crate::instance::update::pending::PendingOperations;
+use crate::instance::update::plan::UpdatePlan;
+use crate::instance::update::{self, InstanceUpdateContext, DEFAULT_UPDATE_JOBS};
+use crate::io::lock::Lockfile;
+use crate::io::paths::Paths;
+use crate::pkg::eval::cases::{run_eval_cases, EvalCaseResult};
+use crate::pkg::eval::{EvalConstants, EvalInput, EvalParameters, Routine};
+use crate::profile::update::ProfileUpdateSnapshot;
+
+/// Load plugins and the user's config from the standard location
+pub fn load_config(paths: &Paths, o: &mut impl MCVMOutput) -> anyhow::Result<Config> {
+	let plugins = PluginManager::load(paths, o).context("Failed to load plugins configuration")?;
+	Config::load(&Config::get_path(paths), plugins, true, paths, o).context("Failed to load config")
+}
+
+/// Options for updating instances
+#[derive(Debug, Clone, Default)]
+pub struct UpdateOptions {
+	/// Whether to force files to be redownloaded
+	pub force: bool,
+	/// Whether to skip updating packages
+	pub skip_packages: bool,
+	/// Whether to fail instead of warning when the package configuration has problems
+	pub strict: bool,
+	/// Whether to refuse any changes to the versions recorded in the lockfile
+	pub frozen: bool,
+	/// The maximum number of Minecraft versions whose instances are created at the same time.
+	/// Defaults to [DEFAULT_UPDATE_JOBS]
+	pub jobs: Option<usize>,
+	/// How long the whole update can take before it is cancelled
+	pub timeout: Option<Duration>,
+	/// Whether to show the changes made to options files instead of only logging them
+	pub show_diffs: bool,
+	/// Whether to stop at the first package that fails to install. Otherwise, failed packages
+	/// are rolled back, the rest are installed, and the failures are returned as a
+	/// [PackageFailures] error once the lockfile has been written
+	pub fail_fast: bool,
+	/// The stability to use for every package instead of the configured ones.
+	/// This only applies to this update and isn't saved to the config
+	pub stability: Option<PackageStability>,
+	/// Whether to make the whitelist.json and ops.json of servers match their config exactly,
+	/// removing players that were added in-game
+	pub sync_exact: bool,
+	/// Whether to skip backing up the worlds of instances whose Minecraft version changes,
+	/// even if the `snapshot_on_version_change` preference is enabled
+	pub no_snapshot: bool,
+	/// Statistics to record the downloads of the update into, so that they can be read
+	/// afterwards. A summary of them is shown at the end of the update either way
+	pub stats: Option<Arc<TransferStats>>,
+	/// When updating a profile, only update the instances in this list. Instances of the
+	/// profile that aren't in the list are skipped and their lockfile entries are left alone.
+	/// Every instance in the list has to use the profile
+	pub instance_filter: Option<Vec<InstanceID>>,
+}
+
+/// Update a single instance
+pub async fn update_instance(
+	config: &mut Config,
+	instance: &str,
+	options: &UpdateOptions,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<()> {
+	update_instances(config, &[InstanceID::from(instance)], options, paths, o).await
+}
+
+/// Update all of the instances that derive from a profile
+pub async fn update_profile(
+	config: &mut Config,
+	profile: &str,
+	options: &UpdateOptions,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<()> {
+	let profile = ProfileID::from(profile);
+	let ids = get_profile_update_instances(config, &profile, options, o)?;
+	if ids.is_empty() {
+		o.display(
+			MessageContents::Notice(format!(
+				"Skipping profile '{profile}' since none of its instances were selected"
+			)),
+			MessageLevel::Important,
+		);
+		return Ok(());
+	}
+
+	let lock = Lockfile::open(paths).context("Failed to open lockfile")?;
+	let snapshot = ProfileUpdateSnapshot::new(profile, &ids, &lock);
+	let result = update_instances(config, &ids, options, paths, o).await;
+	// The lockfile is also written when only some packages failed
+	let written = match &result {
+		Ok(()) => true,
+		Err(e) => e.is::<PackageFailures>(),
+	};
+	if written {
+		// The update itself is already done, so a failing hook shouldn't hide its result
+		let hook_result = Lockfile::open(paths)
+			.context("Failed to open lockfile")
+			.and_then(|lock| snapshot.call_hook(&lock, &config.plugins, paths, o));
+		if let Err(e) = hook_result {
+			o.display(
+				MessageContents::Warning(format!("Failed to tell plugins about the update: {e:#}")),
+				MessageLevel::Important,
+			);
+		}
+	}
+
+	result
+}
+
+/// Get the instances of a profile that an update includes, sorted by ID. Instances that
+/// are left out by the instance filter of the options are shown as skipped
+fn get_profile_update_instances(
+	config: &Config,
+	profile: &ProfileID,
+	options: &UpdateOptions,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<Vec<InstanceID>> {
+	let ids: Vec<_> = config
+		.instances
+		.iter()
+		.filter(|(_, x)| x.get_config().profile.as_ref() == Some(profile))
+		.map(|(id, _)| id.clone())
+		.sorted()
+		.collect();
+	if ids.is_empty() {
+		anyhow::bail!("No instances use the profile '{profile}'");
+	}
+
+	let Some(filter) = &options.instance_filter else {
+		return Ok(ids);
+	};
+	if let Some(unknown) = filter.iter().find(|x| !ids.contains(x)) {
+		anyhow::bail!("Instance '{unknown}' does not use the profile '{profile}'");
+	}
+	let (selected, skipped): (Vec<_>, Vec<_>) = ids.into_iter().partition(|x| filter.contains(x));
+	for id in skipped {
+		o.display(
+			MessageContents::Notice(format!("Skipping instance '{id}' since it wasn't selected")),
+			MessageLevel::Important,
+		);
+	}
+
+	Ok(selected)
+}
+
+/// Work out what updating all of the instances that derive from a profile would change,
+/// without installing anything or writing the lockfile
+pub async fn plan_profile_update(
+	config: &mut Config,
+	profile: &str,
+	options: &UpdateOptions,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<Vec<UpdatePlan>> {
+	let profile = ProfileID::from(profile);
+	let ids = get_profile_update_instances(config, &profile, options, o)?;
+
+	// The lockfile is only read, so changes to it are thrown away
+	let mut lock = Lockfile::open(paths).context("Failed to open lockfile")?;
+	let mut out = Vec::new();
+	for id in ids {
+		let instance = config
+			.instances
+			.get_mut(&id)
+			.with_context(|| format!("Unknown instance '{id}'"))?;
+
+		let mut ctx = InstanceUpdateContext {
+			packages: &mut config.packages,
+			users: &config.users,
+			plugins: &config.plugins,
+			prefs: &config.prefs,
+			paths,
+			lock: &mut lock,
+			client: &config.client,
+			strict: options.strict,
+			show_diffs: options.show_diffs,
+			fail_fast: options.fail_fast,
+			output: o,
+			pending: PendingOperations::new(),
+			stability: options.stability,
+			sync_exact: options.sync_exact,
+			no_snapshot: options.no_snapshot,
+		};
+
+		let plan = instance
+			.plan_update(!options.skip_packages, &mut ctx)
+			.await
+			.with_context(|| format!("Failed to plan update for instance '{id}'"))?;
+		out.push(plan);
+	}
+
+	Ok(out)
+}
+
+/// What installing a package on one instance would produce
+#[derive(Debug, Clone, Serialize)]
+pub struct PackagePreview {
+	/// The instance that the package was evaluated for
+	pub instance: InstanceID,
+	/// The side of the instance
+	pub side: Side,
+	/// The addons that the package would install
+	pub addons: Vec<AddonPreview>,
+	/// The packages that would be installed as dependencies. Each entry is satisfied by any one
+	/// of the packages in it
+	pub dependencies: Vec<Vec<PackageID>>,
+	/// The packages that the package recommends
+	pub recommendations: Vec<PackageID>,
+	/// The packages that the package conflicts with
+	pub conflicts: Vec<PackageID>,
+	/// Notices that the package would show
+	pub notices: Vec<String>,
+	/// Why the package couldn't be evaluated for this instance, such as when it doesn't
+	/// support the instance's Minecraft version
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+/// An addon in a [PackagePreview]
+#[derive(Debug, Clone, Serialize)]
+pub struct AddonPreview {
+	/// The ID of the addon
+	pub id: String,
+	/// What kind of addon it is
+	pub kind: AddonKind,
+	/// The file name of the addon
+	pub file_name: String,
+	/// The version of the addon
+	pub version: Option<String>,
+}
+
+/// Work out which addons and dependencies a package would produce on each of the instances
+/// that derive from a profile, without installing anything. The package is evaluated with its
+/// default features
+pub async fn preview_package(
+	config: &mut Config,
+	package: &str,
+	profile: &str,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<Vec<PackagePreview>> {
+	let profile = ProfileID::from(profile);
+	let ids: Vec<_> = config
+		.instances
+		.iter()
+		.filter(|(_, x)| x.get_config().profile.as_ref() == Some(&profile))
+		.map(|(id, _)| id.clone())
+		.sorted()
+		.collect();
+	if ids.is_empty() {
+		bail!("No instances use the profile '{profile}'");
+	}
+
+	let mut manager = UpdateManager::new(false, true);
+	manager.set_metadata_freshness(config.prefs.metadata_freshness);
+	let manifest = version_manifest::get(&paths.core, &manager, &config.client, o)
+		.await
+		.context("Failed to get version manifest")?;
+	let version_list = make_version_list(&manifest);
+
+	let req = Arc::new(PkgRequest::parse_and_validate(
+		package,
+		PkgRequestSource::UserRequire,
+	)?);
+	let properties = config
+		.packages
+		.get_properties(&req, paths, &config.client, o)
+		.await?
+		.clone();
+	let features = properties.default_features.clone().unwrap_or_default();
+
+	let mut out = Vec::new();
+	for id in ids {
+		let instance = config
+			.instances
+			.get(&id)
+			.with_context(|| format!("Unknown instance '{id}'"))?;
+		let instance_config = instance.get_config();
+		let version = instance_config
+			.version
+			.get_version(&manifest)
+			.with_context(|| format!("Failed to get the Minecraft version of instance '{id}'"))?;
+		let constants = EvalConstants {
+			version: version.to_string(),
+			modifications: instance_config.modifications.clone(),
+			version_list: version_list.clone(),
+			language: config.prefs.language,
+			profile_stability: instance_config.package_stability,
+		};
+		let side = instance.get_side();
+		let mut params = EvalParameters::new(side);
+		params.stability = instance_config.package_stability;
+		params.perms = instance_config.package_permissions;
+		params.features = features.clone();
+		// Use the settings of the package if it is already configured on the instance
+		if let Some(package_config) = instance.get_package_config(&req.id) {
+			package_config.apply_to_params(&properties, false, &mut params)?;
+		}
+		params.pinned_addon_versions = instance.get_pinned_addon_versions(&req.id);
+		let input = EvalInput {
+			constants: &constants,
+			params,
+		};
+
+		let eval = config
+			.packages
+			.eval(
+				&req,
+				paths,
+				Routine::Install,
+				input,
+				&config.client,
+				&config.plugins,
+				o,
+			)
+			.await;
+		let preview = match eval {
+			Ok(eval) => PackagePreview {
+				instance: id,
+				side,
+				addons: eval
+					.addon_reqs
+					.iter()
+					.map(|x| AddonPreview {
+						id: x.addon.id.clone(),
+						kind: x.addon.kind,
+						file_name: x.addon.file_name.clone(),
+						version: x.addon.version.clone(),
+					})
+					.collect(),
+				dependencies: eval
+					.deps
+					.iter()
+					.map(|x| x.iter().map(|x| x.value.clone()).collect())
+					.collect(),
+				recommendations: eval
+					.recommendations
+					.iter()
+					.filter(|x| !x.invert)
+					.map(|x| x.value.clone())
+					.collect(),
+				conflicts: eval.conflicts.clone(),
+				notices: eval.notices.clone(),
+				error: None,
+			},
+			Err(e) => PackagePreview {
+				instance: id,
+				side,
+				addons: Vec::new(),
+				dependencies: Vec::new(),
+				recommendations: Vec::new(),
+				conflicts: Vec::new(),
+				notices: Vec::new(),
+				error: Some(format!("{e:#}")),
+			},
+		};
+		out.push(preview);
+	}
+
+	Ok(out)
+}
+
+/// Run a package against the case files in a directory, comparing each evaluation with the
+/// expected result in the case. With `update`, the expected results are replaced instead
+pub async fn test_package(
+	config: &mut Config,
+	package: &str,
+	cases_dir: &Path,
+	update: bool,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<Vec<EvalCaseResult>> {
+	let req = Arc::new(PkgRequest::parse_and_validate(
+		package,
+		PkgRequestSource::UserRequire,
+	)?);
+	let pkg = config
+		.packages
+		.ensure_package_contents(&req, paths, &config.client, o)
+		.await?;
+
+	run_eval_cases(
+		pkg,
+		cases_dir,
+		update,
+		paths,
+		&config.client,
+		&config.plugins,
+	)
+	.await
+}
+
+/// Update a list of instances, sharing the lockfile between them. The lockfile is only
+/// written once every instance has been updated, with packages that failed to install
+/// left at their previous versions
+pub async fn update_instances(
+	config: &mut Config,
+	instances: &[InstanceID],
+	options: &UpdateOptions,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<()> {
+	let ids: Vec<_> = instances.iter().unique().collect();
+	if let Some(id) = ids.iter().find(|x| !config.instances.contains_key(**x)) {
+		anyhow::bail!("Unknown instance '{id}'");
+	}
+	let mut selected: Vec<_> = config
+		.instances
+		.iter_mut()
+		.filter_map(|(id, instance)| Some((ids.iter().position(|x| *x == id)?, instance)))
+		.sorted_by_key(|x| x.0)
+		.map(|x| x.1)
+		.collect();
+
+	let mut lock = Lockfile::open(paths).context("Failed to open lockfile")?;
+	lock.set_frozen(options.frozen);
+	let pending = PendingOperations::new();
+	let mut ctx = InstanceUpdateContext {
+		packages: &mut config.packages,
+		users: &config.users,
+		plugins: &config.plugins,
+		prefs: &config.prefs,
+		paths,
+		lock: &mut lock,
+		client: &config.client,
+		strict: options.strict,
+		show_diffs: options.show_diffs,
+		fail_fast: options.fail_fast,
+		output: o,
+		pending: pending.clone(),
+		stability: options.stability,
+		sync_exact: options.sync_exact,
+		no_snapshot: options.no_snapshot,
+	};
+
+	let stats = options.stats.clone().unwrap_or_default();
+	let limits = config.prefs.download_limits();
+	let update = update::update_instances(
+		&mut selected,
+		!options.skip_packages,
+		options.force,
+		options.jobs.unwrap_or(DEFAULT_UPDATE_JOBS),
+		&mut ctx,
+	);
+	let update = stats::track(stats.clone(), download::with_limits(limits, update));
+	let result = run_with_timeout(update, options.timeout, &pending).await;
+	if result.as_ref().is_err_and(|e| !e.is::<PackageFailures>()) {
+		return result;
+	}
+
+	lock.finish(paths)
+		.context("Failed to finish using lockfile")?;
+
+	o.display(
+		MessageContents::Simple(stats.summary().to_string()),
+		MessageLevel::Important,
+	);
+
+	if let Err(e) = config.update_plugin_context(paths) {
+		o.display(
+			MessageContents::Warning(format!(
+				"Failed to share instance information with plugins: {e:#}"
+			)),
+			MessageLevel::Important,
+		);
+	}
+
+	result
+}
+
+/// Remove the packages that were only installed as dependencies and are no longer needed
+/// from all of the instances that derive from a profile. Returns the packages that were removed
+/// for each instance. When `dry_run` is set, nothing is removed
+pub fn autoremove_packages(
+	config: &Config,
+	profile: &str,
+	dry_run: bool,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<Vec<(InstanceID, Vec<PackageID>)>> {
+	let profile = ProfileID::from(profile);
+	let instances: Vec<_> = config
+		.instances
+		.iter()
+		.filter(|(_, x)| x.get_config().profile.as_ref() == Some(&profile))
+		.sorted_by(|a, b| a.0.cmp(b.0))
+		.collect();
+	if instances.is_empty() {
+		anyhow::bail!("No instances use the profile '{profile}'");
+	}
+
+	let mut lock = Lockfile::open(paths).context("Failed to open lockfile")?;
+	let mut out = Vec::new();
+	for (id, instance) in instances {
+		let unneeded = instance.get_unneeded_packages(&lock);
+		if unneeded.is_empty() {
+			continue;
+		}
+		if !dry_run {
+			let ignore = instance.load_ignore(paths, o);
+			for package in &unneeded {
+				instance
+					.remove_installed_package(package, &mut lock, paths, &ignore)
+					.with_context(|| format!("Failed to remove package '{package}'"))?;
+			}
+		}
+		out.push((id.clone(), unneeded));
+	}
+
+	if !dry_run {
+		lock.finish(paths)
+			.context("Failed to finish using lockfile")?;
+	}
+
+	Ok(out)
+}
+
+/// Remove stored Paper and Folia JARs for builds that no instance uses anymore, including ones in their
+/// update history. Returns the paths of the removed files. When `dry_run` is set, nothing is removed
+pub fn clean_server_jars(paths: &Paths, dry_run: bool) -> anyhow::Result<Vec<PathBuf>> {
+	let jars_dir = &paths.core.jars;
+	if !jars_dir.exists() {
+		return Ok(Vec::new());
+	}
+
+	let lock = Lockfile::open(paths).context("Failed to open lockfile")?;
+	let used = lock.get_used_server_builds();
+	let mut out = Vec::new();
+	for entry in std::fs::read_dir(jars_dir).context("Failed to read stored JARs")? {
+		let entry = entry?;
+		let file_name = entry.file_name();
+		let Some((mode, version, build_num)) =
+			file_name.to_str().and_then(paper::parse_local_jar_name)
+		else {
+			continue;
+		};
+		// Velocity isn't tied to instances
+		if let paper::Mode::Velocity = mode {
+			continue;
+		}
+		// JARs stored by older versions of mcvm aren't used anymore
+		let in_use = build_num.is_some_and(|x| used.contains(&(version.to_string(), x)));
+		if in_use {
+			continue;
+		}
+
+		let path = entry.path();
+		if !dry_run {
+			std::fs::remove_file(&path)
+				.with_context(|| format!("Failed to remove {}", path.display()))?;
+		}
+		out.push(path);
+	}
+	out.sort();
+
+	Ok(out)
+}
+
+/// Report how the installed asset indexes share the asset object store, and which objects
+/// are left over from indexes that were removed
+pub fn get_asset_report(paths: &Paths) -> anyhow::Result<AssetReport> {
+	asset_report::get_report(&paths.core).context("Failed to read installed assets")
+}
+
+/// Remove asset objects that no installed index uses, along with the virtual asset directories of
+/// indexes that were removed. Returns the paths of the removed files and directories. When `dry_run`
+/// is set, nothing is removed
+pub fn clean_assets(paths: &Paths, dry_run: bool) -> anyhow::Result<Vec<PathBuf>> {
+	let indexes = asset_report::read_installed_indexes(&paths.core)
+		.context("Failed to read asset indexes")?;
+	let stored = asset_report::read_stored_objects(&get_objects_dir_path(&paths.core))
+		.context("Failed to read asset objects")?;
+	let report = AssetReport::new(&indexes, &stored);
+	let virtual_dirs = asset_report::get_orphaned_virtual_dirs(&indexes, &paths.core)?;
+
+	if !dry_run {
+		for path in &report.orphans {
+			std::fs::remove_file(path)
+				.with_context(|| format!("Failed to remove {}", path.display()))?;
+		}
+		for path in &virtual_dirs {
+			std::fs::remove_dir_all(path)
+				.with_context(|| format!("Failed to remove {}", path.display()))?;
+		}
+	}
+
+	let mut out = report.orphans;
+	out.extend(virtual_dirs);
+	Ok(out)
+}
+
+/// A Java installation that was downloaded by mcvm, along with the instances that launch with it
+#[derive(Debug, Clone, Serialize)]
+pub struct JavaRuntime {
+	/// The installation
+	#[serde(flatten)]
+	pub installation: ManagedJavaInstallation,
+	/// The configured instances that launch with the installation, sorted by ID
+	pub required_by: Vec<InstanceID>,
+}
+
+/// List the Java installations that mcvm downloaded, and which configured instances need them.
+/// Instances that haven't been created yet aren't included, since their Java version isn't known
+pub fn list_java_runtimes(config: &Config, paths: &Paths) -> anyhow::Result<Vec<JavaRuntime>> {
+	let data = PersistentData::open(&paths.core).context("Failed to open persistent data")?;
+	let lock = Lockfile::open(paths).context("Failed to open lockfile")?;
+
+	let mut requirements = Vec::new();
+	for (id, instance) in config.instances.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+		let major_version = instance
+			.get_java_major_version(paths, &lock)
+			.with_context(|| format!("Failed to get the Java version of instance '{id}'"))?;
+		if let Some(major_version) = major_version {
+			requirements.push((id, instance, major_version));
+		}
+	}
+
+	let out = data
+		.get_managed_java_installations()
+		.into_iter()
+		.map(|installation| JavaRuntime {
+			required_by: requirements
+				.iter()
+				.filter(|(_, instance, major)| {
+					instance.uses_java_installation(*major, &installation)
+				})
+				.map(|(id, ..)| (*id).clone())
+				.collect(),
+			installation,
+		})
+		.collect();
+
+	Ok(out)
+}
+
+/// Download a Java installation ahead of time so that updating instances that need it doesn't have to.
+/// If the latest version is already installed, nothing is downloaded. Returns the path to the installation
+pub async fn install_java_runtime(
+	major_version: JavaMajorVersion,
+	vendor: PersistentDataJavaInstallation,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<PathBuf> {
+	let kind = match vendor {
+		PersistentDataJavaInstallation::Adoptium => JavaInstallationKind::Adoptium,
+		PersistentDataJavaInstallation::Zulu => JavaInstallationKind::Zulu,
+		PersistentDataJavaInstallation::GraalVM => JavaInstallationKind::GraalVM,
+	};
+	let mut core = mcvm_core::MCVMCore::with_config_and_paths(
+		mcvm_core::Configuration::new(),
+		paths.core.clone(),
+	)
+	.context("Failed to initialize core")?;
+	let java = core.get_java_installation(major_version, kind, o).await?;
+
+	Ok(java.get_path().to_owned())
+}
+
+/// Remove the downloaded Java installations of a major version, or only the one from a vendor.
+/// Unless `force` is set, installations that a configured instance launches with are not removed.
+/// Returns the installations that were removed
+pub async fn remove_java_runtime(
+	config: &Config,
+	major_version: JavaMajorVersion,
+	vendor: Option<PersistentDataJavaInstallation>,
+	force: bool,
+	paths: &Paths,
+) -> anyhow::Result<Vec<ManagedJavaInstallation>> {
+	let major_string = major_version.to_string();
+	let runtimes: Vec<_> = list_java_runtimes(config, paths)?
+		.into_iter()
+		.filter(|x| x.installation.major_version == major_string)
+		.filter(|x| vendor.map_or(true, |vendor| x.installation.vendor == vendor))
+		.collect();
+	if runtimes.is_empty() {
+		bail!("No downloaded Java {major_version} installation was found");
+	}
+
+	if !force {
+		let required_by: Vec<_> = runtimes
+			.iter()
+			.flat_map(|x| x.required_by.iter())
+			.unique()
+			.map(|x| x.to_string())
+			.collect();
+		if !required_by.is_empty() {
+			bail!(
+				"Java {major_version} is needed by the instances {}. Use --force to remove it anyway",
+				required_by.join(", ")
+			);
+		}
+	}
+
+	let mut data = PersistentData::open(&paths.core).context("Failed to open persistent data")?;
+	let mut out = Vec::new();
+	for runtime in runtimes {
+		let removed = data
+			.remove_java_installation(runtime.installation.vendor, &major_string)
+			.context("Failed to remove Java installation")?;
+		out.extend(removed);
+	}
+	data.dump(&paths.core)
+		.await
+		.context("Failed to write persistent data")?;
+
+	Ok(out)
+}
+
+/// Run an update, cancelling it if it doesn't finish within the timeout. Cancelling drops the update,
+/// which aborts any tasks it was running
+async fn run_with_timeout<T>(
+	update: impl Future<Output = anyhow::Result<T>>,
+	timeout: Option<Duration>,
+	pending: &PendingOperations,
+) -> anyhow::Result<T> {
+	let Some(timeout) = timeout else {
+		return update.await;
+	};
+
+	let mut update = pin!(update);
+	match tokio::time::timeout(timeout, &mut update).await {
+		Ok(result) => result,
+		Err(..) => {
+			// The pending operations have to be read before the update is dropped, which finishes them
+			let operations = pending.get();
+			let operations = if operations.is_empty() {
+				String::new()
+			} else {
+				format!(
+					". Operations that were still running:\n{}",
+					operations.iter().map(|x| format!(" - {x}")).join("\n")
+				)
+			};
+			bail!(
+				"Update timed out after {} seconds{operations}",
+				timeout.as_secs()
+			);
+		}
+	}
+}
+
+/// Launch an instance, performing its first update if it hasn't had one yet.
+/// The returned handle owns the game process. If the settings have a download limit and
+/// the launch would download more than it, this fails with a [LargeDownload] error before anything is downloaded
+pub async fn launch_instance(
+	config: &mut Config,
+	instance: &str,
+	settings: LaunchSettings,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<InstanceHandle> {
+	let instance_id = InstanceID::from(instance);
+	check_download_limit(config, &instance_id, &settings, paths, o).await?;
+	ensure_first_update(config, &instance_id, &settings, paths, o).await?;
+
+	let limits = config.prefs.download_limits();
+	let instance = config
+		.instances
+		.get_mut(&instance_id)
+		.context("Instance does not exist")?;
+	let launch = instance.launch(
+		paths,
+		&mut config.users,
+		&config.plugins,
+		&config.client,
+		settings,
+		o,
+	);
+	download::with_limits(limits, launch)
+		.await
+		.context("Instance failed to launch")
+}
+
+/// Write a script that launches an instance the same way as [launch_instance], without launching it.
+/// The instance is still updated first. The kind of script is picked from the extension of the path.
+/// The access token and environment variables that look like secrets are left out of the script
+/// and must be set in its environment instead
+pub async fn export_launch_script(
+	config: &mut Config,
+	instance: &str,
+	settings: LaunchSettings,
+	script_path: &Path,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<()> {
+	let instance_id = InstanceID::from(instance);
+	check_download_limit(config, &instance_id, &settings, paths, o).await?;
+	ensure_first_update(config, &instance_id, &settings, paths, o).await?;
+
+	let instance = config
+		.instances
+		.get_mut(&instance_id)
+		.context("Instance does not exist")?;
+	let command = instance
+		.get_launch_command(
+			paths,
+			&mut config.users,
+			&config.plugins,
+			&config.client,
+			settings,
+			o,
+		)
+		.await?;
+
+	let secret_env: Vec<_> = command
+		.env
+		.keys()
+		.filter(|x| is_secret_key(x))
+		.cloned()
+		.collect();
+	let script = command.to_script(ScriptKind::from_path(script_path), &secret_env);
+	std::fs::write(script_path, script).context("Failed to write launch script")?;
+	#[cfg(target_family = "unix")]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		std::fs::set_permissions(script_path, std::fs::Permissions::from_mode(0o755))
+			.context("Failed to make launch script executable")?;
+	}
+
+	Ok(())
+}
+
+/// Fail with a [LargeDownload] error if launching an instance would download more than the limit in the settings
+async fn check_download_limit(
+	config: &mut Config,
+	instance_id: &InstanceID,
+	settings: &LaunchSettings,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<()> {
+	let instance = config
+		.instances
+		.get_mut(instance_id)
+		.context("Instance does not exist")?;
+	if let Some(limit) = settings.download_limit {
+		let size = instance
+			.estimate_download_size(
+				paths,
+				&config.users,
+				&config.plugins,
+				&config.client,
+				&config.prefs,
+				o,
+			)
+			.await
+			.context("Failed to estimate download size")?;
+		if size.exceeds(limit) {
+			return Err(LargeDownload { size, limit }.into());
+		}
+	}
+
+	Ok(())
+}
+
+/// Update an instance if it hasn't been updated before
+async fn ensure_first_update(
+	config: &mut Config,
+	instance_id: &InstanceID,
+	settings: &LaunchSettings,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<()> {
+	let instance = config
+		.instances
+		.get_mut(instance_id)
+		.context("Instance does not exist")?;
+	let limits = config.prefs.download_limits();
+	let mut lock = Lockfile::open(paths).context("Failed to open lockfile")?;
+	if !lock.has_instance_done_first_update(instance_id) {
+		let pending = PendingOperations::new();
+		o.display(
+			MessageContents::StartProcess(translate!(
+				o,
+				PerformingFirstUpdate,
+				"inst" = &instance_id
+			)),
+			MessageLevel::Important,
+		);
+
+		let mut ctx = InstanceUpdateContext {
+			packages: &mut config.packages,
+			users: &config.users,
+			plugins: &config.plugins,
+			prefs: &config.prefs,
+			paths,
+			lock: &mut lock,
+			client: &config.client,
+			strict: false,
+			show_diffs: false,
+			fail_fast: true,
+			output: o,
+			pending: pending.clone(),
+			stability: None,
+			sync_exact: false,
+			no_snapshot: false,
+		};
+
+		let update = download::with_limits(limits, instance.update(true, false, &mut ctx));
+		run_with_timeout(update, settings.update_timeout, &pending)
+			.await
+			.context("Failed to perform first update for instance")?;
+
+		// Since the update was successful, we can mark the instance as ready
+		lock.update_instance_has_done_first_update(instance_id);
+		lock.finish(paths)
+			.context("Failed to finish using lockfile")?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use mcvm_shared::output;
+
+	use super::*;
+
+	#[test]
+	fn test_profile_update_instance_filter() {
+		let deser = serde_json::from_value(serde_json::json!({
+			"profiles": {
+				"survival": { "version": "1.20.1" },
+				"empty": { "version": "1.20.1" }
+			},
+			"instances": {
+				"client": { "type": "client", "from": "survival" },
+				"server": { "type": "server", "from": "survival" },
+				"other": { "type": "client", "version": "1.20.1" }
+			}
+		}))
+		.unwrap();
+		let mut o = output::Simple(output::MessageLevel::Debug);
+		let config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			true,
+			&Paths::new_no_create().unwrap(),
+			&mut o,
+		)
+		.unwrap();
+		let profile = ProfileID::from("survival");
+
+		let mut options = UpdateOptions::default();
+		let ids = get_profile_update_instances(&config, &profile, &options, &mut o).unwrap();
+		assert_eq!(
+			ids,
+			[InstanceID::from("client"), InstanceID::from("server")]
+		);
+
+		options.instance_filter = Some(vec!["server".into()]);
+		let ids = get_profile_update_instances(&config, &profile, &options, &mut o).unwrap();
+		assert_eq!(ids, [InstanceID::from("server")]);
+
+		options.instance_filter = Some(Vec::new());
+		let ids = get_profile_update_instances(&config, &profile, &options, &mut o).unwrap();
+		assert!(ids.is_empty());
+
+		// Instances that don't use the profile can't be selected
+		options.instance_filter = Some(vec!["server".into(), "other".into()]);
+		assert!(get_profile_update_instances(&config, &profile, &options, &mut o).is_err());
+		options.instance_filter = Some(vec!["missing".into()]);
+		assert!(get_profile_update_instances(&config, &profile, &options, &mut o).is_err());
+
+		// Profiles without any instances are an error, with or without a filter
+		let empty = ProfileID::from("empty");
+		options.instance_filter = None;
+		assert!(get_profile_update_instances(&config, &empty, &options, &mut o).is_err());
+		options.instance_filter = Some(Vec::new());
+		assert!(get_profile_update_instances(&config, &empty, &options, &mut o).is_err());
+	}
+
+	#[test]
+	fn test_run_with_timeout_cancels() {
+		use std::sync::atomic::{AtomicBool, Ordering};
+		use std::sync::Arc;
+
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let pending = PendingOperations::new();
+		let finished = Arc::new(AtomicBool::new(false));
+
+		let update = {
+			let pending = pending.clone();
+			let finished = finished.clone();
+			async move {
+				let _operation = pending.start("Downloading assets");
+				let mut tasks = tokio::task::JoinSet::new();
+				tasks.spawn(async move {
+					tokio::time::sleep(Duration::from_millis(200)).await;
+					finished.store(true, Ordering::SeqCst);
+				});
+				while tasks.join_next().await.is_some() {}
+				Ok(())
+			}
+		};
+		let result = runtime.block_on(run_with_timeout(
+			update,
+			Some(Duration::from_millis(20)),
+			&pending,
+		));
+		let error = format!("{:#}", result.unwrap_err());
+		assert!(error.contains("timed out"));
+		assert!(error.contains(" - Downloading assets"));
+		assert!(pending.get().is_empty());
+
+		// The task that the update spawned was aborted along with it
+		runtime.block_on(async { tokio::time::sleep(Duration::from_millis(400)).await });
+		assert!(!finished.load(Ordering::SeqCst));
+
+		let result = runtime.block_on(run_with_timeout(
+			async { Ok(1) },
+			Some(Duration::from_secs(1)),
+			&pending,
+		));
+		assert_eq!(result.unwrap(), 1);
+	}
+}
@@ -17,6 +17,7 @@ use crate::pkg::eval::EvalPermissions;
 use crate::pkg::reg::PkgRegistry;
 use crate::pkg::repo::PkgRepo;
 
+use super::daemon::DaemonConfig;
 use super::instance::{read_instance_config, ClientWindowConfig, InstanceConfig, LaunchConfig};
 use super::package::{FullPackageConfig, PackageConfigDeser};
 use super::plugin::{PluginConfig, PluginManager};
@@ -51,7 +52,7 @@ impl ConfigBuilder {
 	}
 
 	/// Create a UserBuilder
-	pub fn user(&mut self, id: String, kind: UserBuilderKind) -> UserBuilder {
+	pub fn user(&mut self, id: String, kind: UserBuilderKind) -> UserBuilder<'_> {
 		UserBuilder::with_parent(id, kind, Some(self))
 	}
 
@@ -97,6 +98,9 @@ impl ConfigBuilder {
 			}
 		}
 
+		self.packages.set_plugins(self.plugins.clone());
+		let client = self.preferences.create_client()?;
+
 		Ok(Config {
 			users: self.users,
 			instances: self.instances,
@@ -104,6 +108,8 @@ impl ConfigBuilder {
 			packages: self.packages,
 			plugins: self.plugins,
 			prefs: self.preferences,
+			daemon: DaemonConfig::default(),
+			client,
 		})
 	}
 }
@@ -180,6 +186,11 @@ impl<'parent> InstanceBuilder<'parent> {
 		let config = InstanceConfig {
 			side: Some(side),
 			name: None,
+			description: None,
+			icon: None,
+			whitelist: None,
+			ops: None,
+			user: None,
 			common: Default::default(),
 			window: Default::default(),
 		};
@@ -193,6 +204,30 @@ impl<'parent> InstanceBuilder<'parent> {
 		self
 	}
 
+	/// Set the description of the instance
+	pub fn description(&mut self, description: String) -> &mut Self {
+		self.config.description = Some(description);
+		self
+	}
+
+	/// Set the path to the icon of the instance
+	pub fn icon(&mut self, icon: String) -> &mut Self {
+		self.config.icon = Some(icon);
+		self
+	}
+
+	/// Set the players to whitelist on the server
+	pub fn whitelist(&mut self, players: Vec<String>) -> &mut Self {
+		self.config.whitelist = Some(players);
+		self
+	}
+
+	/// Set the players to make operators on the server
+	pub fn ops(&mut self, players: Vec<String>) -> &mut Self {
+		self.config.ops = Some(players);
+		self
+	}
+
 	/// Set the modloader of the instance
 	pub fn modloader(&mut self, modloader: Modloader) -> &mut Self {
 		self.config.common.modloader = Some(modloader);
@@ -213,7 +248,13 @@ impl<'parent> InstanceBuilder<'parent> {
 
 	/// Set the default package stability of the instance
 	pub fn package_stability(&mut self, package_stability: PackageStability) -> &mut Self {
-		self.config.common.package_stability = Some(package_stability);
+		self.config.common.default_package_stability = Some(package_stability);
+		self
+	}
+
+	/// Set the default permissions of packages on the instance
+	pub fn package_permissions(&mut self, permissions: EvalPermissions) -> &mut Self {
+		self.config.common.default_package_permissions = Some(permissions);
 		self
 	}
 
@@ -279,6 +320,7 @@ impl<'parent> InstanceBuilder<'parent> {
 			self.id.clone(),
 			self.config,
 			&HashMap::new(),
+			&HashMap::new(),
 			plugins,
 			paths,
 			o,
@@ -304,9 +346,10 @@ where
 			id: data.id,
 			features: Default::default(),
 			use_default_features: true,
-			permissions: Default::default(),
+			permissions: None,
 			stability: Default::default(),
 			worlds: Default::default(),
+			repository: None,
 		};
 
 		Self { config, parent }
@@ -326,7 +369,7 @@ where
 
 	/// Set the permissions of the package
 	pub fn permissions(&mut self, permissions: EvalPermissions) -> &mut Self {
-		self.config.permissions = permissions;
+		self.config.permissions = Some(permissions);
 		self
 	}
 
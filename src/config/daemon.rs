@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use mcvm_shared::id::InstanceID;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Configuration for the daemon that supervises instances
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(default)]
+pub struct DaemonConfig {
+	/// The instances to supervise
+	pub instances: HashMap<InstanceID, SupervisedInstanceConfig>,
+	/// Actions to run on instances at scheduled times
+	#[serde(skip_serializing_if = "HashMap::is_empty")]
+	pub schedules: HashMap<String, ScheduleConfig>,
+}
+
+/// Configuration for a single instance supervised by the daemon
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(default)]
+pub struct SupervisedInstanceConfig {
+	/// When the instance should be restarted after it exits
+	pub restart: RestartPolicy,
+	/// Whether to start the instance as soon as the daemon starts
+	pub autostart: bool,
+	/// The delay in seconds before the first restart after a crash
+	pub initial_backoff: u64,
+	/// The maximum delay in seconds between restarts when the instance is crash looping
+	pub max_backoff: u64,
+	/// How long in seconds the instance has to stay up for its crash count to be reset
+	pub stable_time: u64,
+}
+
+impl Default for SupervisedInstanceConfig {
+	fn default() -> Self {
+		Self {
+			restart: RestartPolicy::default(),
+			autostart: true,
+			initial_backoff: 1,
+			max_backoff: 300,
+			stable_time: 60,
+		}
+	}
+}
+
+/// When a supervised instance should be restarted
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+	/// Always restart the instance when it exits, unless it was stopped on purpose
+	Always,
+	/// Only restart the instance when it exits with an error
+	#[default]
+	OnFailure,
+	/// Never restart the instance
+	Never,
+}
+
+/// Configuration for an action that the daemon runs on a schedule
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct ScheduleConfig {
+	/// The cron expression for when the schedule runs
+	pub cron: String,
+	/// The instance to run the action on
+	pub instance: InstanceID,
+	/// The action to run
+	pub action: ScheduleAction,
+	/// The time zone to evaluate the cron expression in. Defaults to the local time zone of the system
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub timezone: Option<String>,
+}
+
+/// An action that can be run on a schedule
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduleAction {
+	/// Back up the instance using a backup plugin
+	Backup {
+		/// The backup group to store the backup in
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		group: Option<String>,
+	},
+	/// Restart the instance
+	Restart,
+	/// Run a command on the instance's console
+	Command {
+		/// The command to run
+		command: String,
+	},
+}
+
+impl std::fmt::Display for ScheduleAction {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Backup { group: None } => write!(f, "backup"),
+			Self::Backup { group: Some(group) } => write!(f, "backup to group '{group}'"),
+			Self::Restart => write!(f, "restart"),
+			Self::Command { command } => write!(f, "run '{command}'"),
+		}
+	}
+}
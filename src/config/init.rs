@@ -0,0 +1,239 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::bail;
+use mcvm_core::io::json_to_file_pretty;
+use mcvm_shared::modifications::Modloader;
+use mcvm_shared::Side;
+use serde_json::json;
+
+use super::user::UserVariant;
+
+/// The modloaders that can be chosen when creating a new config
+pub const INIT_MODLOADERS: [Modloader; 5] = [
+	Modloader::Vanilla,
+	Modloader::Fabric,
+	Modloader::Quilt,
+	Modloader::Forge,
+	Modloader::NeoForged,
+];
+
+/// Options for creating a new config for a first-time setup
+#[derive(Clone)]
+pub struct InitOptions {
+	/// The ID of the user to create
+	pub user_id: String,
+	/// The kind of user to create
+	pub user: UserVariant,
+	/// The Minecraft version for the profile
+	pub version: String,
+	/// Which instances to create
+	pub sides: InitSides,
+	/// The modloader for the profile
+	pub modloader: Modloader,
+}
+
+/// Which sides to create instances for in a new config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InitSides {
+	/// Only a client
+	#[default]
+	Client,
+	/// Only a server
+	Server,
+	/// A client and a server
+	Both,
+}
+
+impl InitSides {
+	/// All of the choices for sides
+	pub const ALL: [Self; 3] = [Self::Client, Self::Server, Self::Both];
+
+	/// Get the name of this choice
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Client => "client",
+			Self::Server => "server",
+			Self::Both => "both",
+		}
+	}
+
+	/// Get the sides that instances are created for
+	pub fn get_sides(&self) -> Vec<Side> {
+		match self {
+			Self::Client => vec![Side::Client],
+			Self::Server => vec![Side::Server],
+			Self::Both => vec![Side::Client, Side::Server],
+		}
+	}
+}
+
+impl FromStr for InitSides {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.into_iter()
+			.find(|x| x.as_str() == s)
+			.ok_or_else(|| anyhow::anyhow!("Unknown side '{s}'. Use client, server, or both"))
+	}
+}
+
+/// Which kind of user to create in a new config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InitUserType {
+	/// A Microsoft account
+	#[default]
+	Microsoft,
+	/// A demo user
+	Demo,
+	/// A user that plays offline without an account
+	Unverified,
+}
+
+impl InitUserType {
+	/// All of the choices for user types
+	pub const ALL: [Self; 3] = [Self::Microsoft, Self::Demo, Self::Unverified];
+
+	/// Get the name of this choice
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Microsoft => "microsoft",
+			Self::Demo => "demo",
+			Self::Unverified => "unverified",
+		}
+	}
+
+	/// Get the user config for this choice. The username is only used by unverified users
+	pub fn get_variant(&self, username: String) -> UserVariant {
+		match self {
+			Self::Microsoft => UserVariant::Microsoft {},
+			Self::Demo => UserVariant::Demo {},
+			Self::Unverified => UserVariant::Unverified { username },
+		}
+	}
+}
+
+impl FromStr for InitUserType {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.into_iter()
+			.find(|x| x.as_str() == s)
+			.ok_or_else(|| {
+				anyhow::anyhow!("Unknown user type '{s}'. Use microsoft, demo, or unverified")
+			})
+	}
+}
+
+/// Parse one of the modloaders that can be chosen when creating a new config
+pub fn parse_init_modloader(string: &str) -> anyhow::Result<Modloader> {
+	let modloader = match string {
+		"vanilla" | "none" => Modloader::Vanilla,
+		"fabric" => Modloader::Fabric,
+		"quilt" => Modloader::Quilt,
+		"forge" => Modloader::Forge,
+		"neoforged" => Modloader::NeoForged,
+		other => {
+			bail!("Unknown modloader '{other}'. Use vanilla, fabric, quilt, forge, or neoforged")
+		}
+	};
+
+	Ok(modloader)
+}
+
+/// Get the ID of the profile that a new config creates
+pub fn get_profile_id(options: &InitOptions) -> String {
+	options.version.clone()
+}
+
+/// Generate a new config from init options
+pub fn generate_config(options: &InitOptions) -> serde_json::Value {
+	let profile_id = get_profile_id(options);
+	let instances: serde_json::Map<_, _> = options
+		.sides
+		.get_sides()
+		.into_iter()
+		.map(|side| {
+			(
+				side.to_string(),
+				json!({
+					"from": profile_id,
+					"type": side.to_string(),
+				}),
+			)
+		})
+		.collect();
+
+	json!({
+		"users": {
+			&options.user_id: options.user,
+		},
+		"default_user": options.user_id,
+		"profiles": {
+			&profile_id: {
+				"version": options.version,
+				"modloader": options.modloader,
+			}
+		},
+		"instances": instances,
+	})
+}
+
+/// Generate a new config from init options and write it, replacing the existing config
+pub fn write_config(options: &InitOptions, path: &Path) -> anyhow::Result<()> {
+	json_to_file_pretty(path, &generate_config(options))
+}
+
+#[cfg(test)]
+mod tests {
+	use mcvm_shared::output;
+
+	use super::*;
+	use crate::config::plugin::PluginManager;
+	use crate::config::Config;
+	use crate::io::paths::Paths;
+
+	#[test]
+	fn test_generated_configs_load() {
+		for (modloader, user_type) in INIT_MODLOADERS
+			.into_iter()
+			.zip(InitUserType::ALL.into_iter().cycle())
+		{
+			for sides in InitSides::ALL {
+				let options = InitOptions {
+					user_id: "me".into(),
+					user: user_type.get_variant("Steve".into()),
+					version: "1.20.4".into(),
+					sides,
+					modloader: modloader.clone(),
+				};
+				let deser = serde_json::from_value(generate_config(&options)).unwrap();
+				let config = Config::load_from_deser(
+					deser,
+					PluginManager::new(),
+					true,
+					&Paths::new_no_create().unwrap(),
+					&mut output::Simple(output::MessageLevel::Debug),
+				)
+				.unwrap();
+				assert_eq!(config.instances.len(), sides.get_sides().len());
+				assert!(config.users.get_user("me").is_some());
+			}
+		}
+	}
+
+	#[test]
+	fn test_parse_options() {
+		assert_eq!(InitSides::from_str("both").unwrap(), InitSides::Both);
+		assert!(InitSides::from_str("proxy").is_err());
+		assert_eq!(
+			InitUserType::from_str("unverified").unwrap(),
+			InitUserType::Unverified
+		);
+		assert!(InitUserType::from_str("mojang").is_err());
+		assert_eq!(parse_init_modloader("quilt").unwrap(), Modloader::Quilt);
+		assert!(parse_init_modloader("rift").is_err());
+	}
+}
@@ -1,13 +1,19 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use anyhow::{bail, ensure, Context};
+use itertools::Itertools;
 use mcvm_core::io::java::args::MemoryNum;
 use mcvm_core::io::java::install::JavaInstallationKind;
-use mcvm_core::util::versions::MinecraftVersionDeser;
+use mcvm_core::net::download::is_url;
+use mcvm_core::util::versions::{
+	CustomVersion, MinecraftVersion, MinecraftVersionDeser, VersionChannel,
+};
+use mcvm_mods::fabric_quilt::LoaderVersion;
 use mcvm_plugin::hooks::ModifyInstanceConfig;
 use mcvm_shared::id::{InstanceID, ProfileID};
 use mcvm_shared::modifications::{ClientType, Modloader, ServerType};
-use mcvm_shared::output::MCVMOutput;
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
 use mcvm_shared::pkg::PackageStability;
 use mcvm_shared::util::{merge_options, DefaultExt};
 use mcvm_shared::Side;
@@ -15,10 +21,13 @@ use mcvm_shared::Side;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::instance::launch::{LaunchOptions, WrapperCommand};
+use crate::instance::icon::check_icon;
+use crate::instance::launch::{InstanceLogConfig, LaunchOptions, WrapperConfig};
 use crate::instance::{InstKind, Instance, InstanceStoredConfig};
 use crate::io::paths::Paths;
+use crate::pkg::eval::EvalPermissions;
 
+use super::jvm_presets;
 use super::package::{PackageConfig, PackageConfigDeser, PackageConfigSource};
 use super::plugin::PluginManager;
 use super::profile::{GameModifications, ProfileConfig};
@@ -33,6 +42,26 @@ pub struct InstanceConfig {
 	/// The display name of this instance
 	#[serde(default)]
 	pub name: Option<String>,
+	/// A short description of this instance
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub description: Option<String>,
+	/// Path to a PNG image to use as the icon of this instance
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub icon: Option<String>,
+	/// Usernames or UUIDs of the players to add to the whitelist of a server
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub whitelist: Option<Vec<String>>,
+	/// Usernames or UUIDs of the players to make operators on a server
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub ops: Option<Vec<String>>,
+	/// The ID of the user to launch this client with instead of the default user
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub user: Option<String>,
 	/// The common config of this instance
 	#[serde(flatten)]
 	pub common: CommonInstanceConfig,
@@ -52,10 +81,23 @@ pub struct CommonInstanceConfig {
 	pub from: Option<String>,
 	/// The Minecraft version
 	pub version: Option<MinecraftVersionDeser>,
+	/// Which kinds of versions the latest version and version wildcards will resolve to
+	#[serde(default)]
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub version_channel: Option<VersionChannel>,
 	/// Configured modloader
 	#[serde(default)]
 	#[serde(skip_serializing_if = "DefaultExt::is_default")]
 	pub modloader: Option<Modloader>,
+	/// The Fabric or Quilt loader version to install. Can be a version, a prefix like `0.15.*`, or `latest`
+	#[serde(default)]
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub loader_version: Option<String>,
+	/// The version of the Fabric installer that builds the standalone server launcher.
+	/// Can be a version, a prefix like `1.0.*`, or `latest`
+	#[serde(default)]
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub installer_version: Option<String>,
 	/// Configured client type
 	#[serde(default)]
 	#[serde(skip_serializing_if = "DefaultExt::is_default")]
@@ -66,8 +108,13 @@ pub struct CommonInstanceConfig {
 	pub server_type: Option<ServerType>,
 	/// Default stability setting of packages on this instance
 	#[serde(default)]
+	#[serde(alias = "package_stability")]
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub default_package_stability: Option<PackageStability>,
+	/// Default permissions of packages on this instance that don't set their own
+	#[serde(default)]
 	#[serde(skip_serializing_if = "DefaultExt::is_default")]
-	pub package_stability: Option<PackageStability>,
+	pub default_package_permissions: Option<EvalPermissions>,
 	/// Launch configuration
 	#[serde(skip_serializing_if = "DefaultExt::is_default")]
 	pub launch: LaunchConfig,
@@ -88,10 +135,18 @@ impl CommonInstanceConfig {
 	pub fn merge(&mut self, other: Self) -> &mut Self {
 		self.from = other.from.or(self.from.clone());
 		self.version = other.version.or(self.version.clone());
+		self.version_channel = other.version_channel.or(self.version_channel);
 		self.modloader = other.modloader.or(self.modloader.clone());
+		self.loader_version = other.loader_version.or(self.loader_version.clone());
+		self.installer_version = other.installer_version.or(self.installer_version.clone());
 		self.client_type = other.client_type.or(self.client_type.clone());
 		self.server_type = other.server_type.or(self.server_type.clone());
-		self.package_stability = other.package_stability.or(self.package_stability);
+		self.default_package_stability = other
+			.default_package_stability
+			.or(self.default_package_stability);
+		self.default_package_permissions = other
+			.default_package_permissions
+			.or(self.default_package_permissions);
 		self.launch.merge(other.launch);
 		self.datapack_folder = other.datapack_folder.or(self.datapack_folder.clone());
 		self.packages.extend(other.packages);
@@ -172,10 +227,6 @@ fn default_java() -> String {
 	"auto".into()
 }
 
-fn default_flags_preset() -> String {
-	"none".into()
-}
-
 /// Options for the Minecraft QuickPlay feature
 #[derive(Deserialize, Serialize, Debug, PartialEq, Default, Clone)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -219,17 +270,28 @@ pub struct LaunchConfig {
 	/// The java installation to use
 	#[serde(default = "default_java")]
 	pub java: String,
-	/// The preset for flags
-	#[serde(default = "default_flags_preset")]
-	pub preset: String,
+	/// The name of a preset of JVM arguments to use along with the explicit ones
+	#[serde(default)]
+	#[serde(alias = "preset")]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub jvm_preset: Option<String>,
 	/// Environment variables
 	#[serde(default)]
 	#[serde(skip_serializing_if = "HashMap::is_empty")]
 	pub env: HashMap<String, String>,
-	/// A wrapper command
+	/// The directory to launch the game process in, relative to the game directory.
+	/// Defaults to the game directory
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub working_dir: Option<String>,
+	/// Whether plugin hooks that run around the launch are also given the environment variables
+	#[serde(default)]
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub share_env_with_hooks: bool,
+	/// Wrapper commands to run the game with
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Option::is_none")]
-	pub wrapper: Option<WrapperCommand>,
+	pub wrapper: Option<WrapperConfig>,
 	/// QuickPlay options
 	#[serde(default)]
 	#[serde(skip_serializing_if = "DefaultExt::is_default")]
@@ -238,11 +300,19 @@ pub struct LaunchConfig {
 	#[serde(default)]
 	#[serde(skip_serializing_if = "DefaultExt::is_default")]
 	pub use_log4j_config: bool,
+	/// Options for capturing the output of the game into log files
+	#[serde(default)]
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub log: InstanceLogConfig,
 }
 
 impl LaunchConfig {
-	/// Parse and finalize this LaunchConfig into LaunchOptions
-	pub fn to_options(self) -> anyhow::Result<LaunchOptions> {
+	/// Parse and finalize this LaunchConfig into LaunchOptions, expanding the JVM argument preset
+	/// using the user's presets and the built-in ones
+	pub fn to_options(
+		self,
+		jvm_presets: &HashMap<String, Vec<String>>,
+	) -> anyhow::Result<LaunchOptions> {
 		let min_mem = match &self.memory {
 			LaunchMemory::None => None,
 			LaunchMemory::Single(string) => MemoryNum::parse(string),
@@ -261,16 +331,35 @@ impl LaunchConfig {
 				);
 			}
 		}
+
+		let mut preset_args = match &self.jvm_preset {
+			Some(preset) => jvm_presets::get_preset(preset, jvm_presets)?,
+			None => Vec::new(),
+		};
+		// The memory settings are added to the end of the arguments later, so they replace the preset's
+		if min_mem.is_some() || max_mem.is_some() {
+			preset_args.retain(|x| {
+				!matches!(jvm_presets::get_flag_key(x).as_deref(), Some("Xms" | "Xmx"))
+			});
+		}
+
 		Ok(LaunchOptions {
-			jvm_args: self.args.jvm.parse(),
+			jvm_args: jvm_presets::merge_jvm_args(preset_args, self.args.jvm.parse()),
 			game_args: self.args.game.parse(),
 			min_mem,
 			max_mem,
 			java: JavaInstallationKind::parse(&self.java),
 			env: self.env,
-			wrapper: self.wrapper,
+			working_dir: self.working_dir,
+			share_env_with_hooks: self.share_env_with_hooks,
+			wrappers: self
+				.wrapper
+				.map(|x| x.get_commands())
+				.transpose()?
+				.unwrap_or_default(),
 			quick_play: self.quick_play,
 			use_log4j_config: self.use_log4j_config,
+			log: self.log,
 		})
 	}
 
@@ -282,16 +371,23 @@ impl LaunchConfig {
 			self.memory = other.memory;
 		}
 		self.java = other.java;
-		if other.preset != "none" {
-			self.preset = other.preset;
+		if other.jvm_preset.is_some() {
+			self.jvm_preset = other.jvm_preset;
 		}
 		self.env.extend(other.env);
+		if other.working_dir.is_some() {
+			self.working_dir = other.working_dir;
+		}
+		self.share_env_with_hooks |= other.share_env_with_hooks;
 		if other.wrapper.is_some() {
 			self.wrapper = other.wrapper;
 		}
 		if !matches!(other.quick_play, QuickPlay::None) {
 			self.quick_play = other.quick_play;
 		}
+		if !other.log.is_default() {
+			self.log = other.log;
+		}
 
 		self
 	}
@@ -306,11 +402,14 @@ impl Default for LaunchConfig {
 			},
 			memory: LaunchMemory::default(),
 			java: default_java(),
-			preset: default_flags_preset(),
+			jvm_preset: None,
 			env: HashMap::new(),
+			working_dir: None,
+			share_env_with_hooks: false,
 			wrapper: None,
 			quick_play: QuickPlay::default(),
 			use_log4j_config: false,
+			log: InstanceLogConfig::default(),
 		}
 	}
 }
@@ -333,12 +432,16 @@ pub struct ClientWindowConfig {
 	/// The resolution of the window
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub resolution: Option<WindowResolution>,
+	/// Whether the window should be fullscreen
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub fullscreen: Option<bool>,
 }
 
 impl ClientWindowConfig {
 	/// Merge two ClientWindowConfigs
 	pub fn merge(&mut self, other: Self) -> &mut Self {
 		self.resolution = merge_options(self.resolution, other.resolution);
+		self.fullscreen = merge_options(self.fullscreen, other.fullscreen);
 		self
 	}
 }
@@ -353,6 +456,11 @@ pub fn merge_instance_configs(
 	let mut out = preset.clone();
 	out.common.merge(config.common);
 	out.name = config.name.or(out.name);
+	out.description = config.description.or(out.description);
+	out.icon = config.icon.or(out.icon);
+	out.whitelist = config.whitelist.or(out.whitelist);
+	out.ops = config.ops.or(out.ops);
+	out.user = config.user.or(out.user);
 	out.side = config.side.or(out.side);
 	out.window.merge(config.window);
 
@@ -364,6 +472,7 @@ pub fn read_instance_config(
 	id: InstanceID,
 	mut config: InstanceConfig,
 	profiles: &HashMap<ProfileID, ProfileConfig>,
+	jvm_presets: &HashMap<String, Vec<String>>,
 	plugins: &PluginManager,
 	paths: &Paths,
 	o: &mut impl MCVMOutput,
@@ -391,6 +500,15 @@ pub fn read_instance_config(
 
 	let side = config.side.context("Instance type was not specified")?;
 
+	if let Some(EvalPermissions::Elevated) = config.common.default_package_permissions {
+		o.display(
+			MessageContents::Warning(format!(
+				"Instance '{id}' gives elevated permissions to every package that doesn't set its own. These packages can run commands on your computer, so only give elevated permissions to the packages that you trust and need them"
+			)),
+			MessageLevel::Important,
+		);
+	}
+
 	// Consolidate all of the package configs into the instance package config list
 	let packages = consolidate_package_configs(profile, &config, side);
 
@@ -405,12 +523,33 @@ pub fn read_instance_config(
 		config.common.server_type.clone().unwrap_or_default(),
 	);
 
+	let loader_version = match &config.common.loader_version {
+		Some(loader_version) => LoaderVersion::parse(loader_version)?,
+		None => LoaderVersion::Latest,
+	};
+	let installer_version = config
+		.common
+		.installer_version
+		.as_deref()
+		.map(LoaderVersion::parse)
+		.transpose()
+		.context("Invalid installer version")?;
+
 	let version = config
 		.common
 		.version
 		.clone()
-		.context("Instance is missing a Minecraft version")?
-		.to_mc_version();
+		.context("Instance is missing a Minecraft version")?;
+	if let MinecraftVersionDeser::Custom { .. } = &version {
+		if config.common.version_channel.is_some() {
+			bail!("Instance '{id}' uses a custom version, which can't be combined with a version_channel since it never resolves to a different version");
+		}
+	}
+	let mut version =
+		version.to_mc_version_with_channel(config.common.version_channel.unwrap_or_default());
+	if let MinecraftVersion::Custom(custom) = &mut version {
+		expand_custom_version_paths(custom);
+	}
 
 	// Apply plugins
 	let results = plugins
@@ -426,14 +565,73 @@ pub fn read_instance_config(
 			.merge(Args::List(result.additional_jvm_args));
 	}
 
+	if side == Side::Client && (config.whitelist.is_some() || config.ops.is_some()) {
+		o.display(
+			MessageContents::Warning(format!(
+				"Instance '{id}' is a client, so its whitelist and ops are ignored"
+			)),
+			MessageLevel::Important,
+		);
+	}
+	if side == Side::Server && config.user.is_some() {
+		o.display(
+			MessageContents::Warning(format!(
+				"Instance '{id}' is a server, so its user is ignored"
+			)),
+			MessageLevel::Important,
+		);
+	}
+
+	// A broken icon shouldn't stop the instance from loading
+	let icon = config.icon.and_then(|icon| {
+		let icon = PathBuf::from(shellexpand::tilde(&icon).to_string());
+		match check_icon(&icon, side) {
+			Ok(warning) => {
+				if let Some(warning) = warning {
+					o.display(
+						MessageContents::Warning(format!("Icon of instance '{id}': {warning}")),
+						MessageLevel::Important,
+					);
+				}
+				Some(icon)
+			}
+			Err(e) => {
+				o.display(
+					MessageContents::Warning(format!(
+						"Ignoring invalid icon '{}' of instance '{id}': {e:#}",
+						icon.display()
+					)),
+					MessageLevel::Important,
+				);
+				None
+			}
+		}
+	});
+
 	let stored_config = InstanceStoredConfig {
 		name: config.name,
+		description: config.description,
+		icon,
+		whitelist: config.whitelist,
+		ops: config.ops,
+		user: config.user.filter(|_| side == Side::Client),
+		profile: config.common.from.clone().map(ProfileID::from),
 		version,
 		modifications: game_modifications,
-		launch: config.common.launch.to_options()?,
+		loader_version,
+		installer_version,
+		launch: config
+			.common
+			.launch
+			.to_options(jvm_presets)
+			.context("Invalid launch configuration")?,
 		datapack_folder: config.common.datapack_folder,
 		packages,
-		package_stability: config.common.package_stability.unwrap_or_default(),
+		package_stability: config.common.default_package_stability.unwrap_or_default(),
+		package_permissions: config
+			.common
+			.default_package_permissions
+			.unwrap_or_default(),
 		plugin_config: config.common.plugin_config,
 	};
 
@@ -442,6 +640,19 @@ pub fn read_instance_config(
 	Ok(instance)
 }
 
+/// Expand the home directory in the local paths of a custom version
+fn expand_custom_version_paths(custom: &mut CustomVersion) {
+	let expand = |source: &mut String| {
+		if !is_url(source) {
+			*source = shellexpand::tilde(source).to_string();
+		}
+	};
+	expand(&mut custom.json);
+	if let Some(jar) = &mut custom.jar {
+		expand(jar);
+	}
+}
+
 /// Checks if an instance ID is valid
 pub fn is_valid_instance_id(id: &str) -> bool {
 	for c in id.chars() {
@@ -471,43 +682,80 @@ fn consolidate_package_configs(
 	instance: &InstanceConfig,
 	side: Side,
 ) -> Vec<PackageConfig> {
-	let stability = instance.common.package_stability.unwrap_or_default();
+	let stability = instance
+		.common
+		.default_package_stability
+		.unwrap_or_default();
+	let permissions = instance
+		.common
+		.default_package_permissions
+		.unwrap_or_default();
 	// We use a map so that we can override packages from more general sources
 	// with those from more specific ones
 	let mut map = HashMap::new();
 	if let Some(profile) = profile {
 		for pkg in profile.packages.iter_global() {
-			let pkg = pkg
-				.clone()
-				.to_package_config(stability, PackageConfigSource::Profile);
+			let pkg =
+				pkg.clone()
+					.to_package_config(stability, permissions, PackageConfigSource::Profile);
 			map.insert(pkg.id.clone(), pkg);
 		}
 		for pkg in profile.packages.iter_side(side) {
-			let pkg = pkg
-				.clone()
-				.to_package_config(stability, PackageConfigSource::Profile);
+			let pkg =
+				pkg.clone()
+					.to_package_config(stability, permissions, PackageConfigSource::Profile);
 			map.insert(pkg.id.clone(), pkg);
 		}
 	}
 	for pkg in &instance.common.packages {
-		let pkg = pkg
-			.clone()
-			.to_package_config(stability, PackageConfigSource::Instance);
+		// Just listing the ID of a package that the profile already configures shouldn't
+		// reset its features and other settings
+		if let PackageConfigDeser::Basic(id) = pkg {
+			if let Some(existing) = map.get_mut(id) {
+				existing.source = PackageConfigSource::Instance;
+				continue;
+			}
+		}
+		let pkg =
+			pkg.clone()
+				.to_package_config(stability, permissions, PackageConfigSource::Instance);
 		map.insert(pkg.id.clone(), pkg);
 	}
 
-	let mut out = Vec::new();
-	for pkg in map.values() {
-		out.push(pkg.clone());
-	}
-
-	out
+	map.into_values()
+		.sorted_by(|x, y| x.id.cmp(&y.id))
+		.collect()
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	#[test]
+	fn test_jvm_preset_expansion() {
+		let user_presets = HashMap::from([(
+			"big".to_string(),
+			vec!["-Xmx8G".to_string(), "-XX:+UseG1GC".to_string()],
+		)]);
+		let config: LaunchConfig = serde_json::from_str(
+			r#"{
+				"preset": "big",
+				"args": { "jvm": ["-XX:+UseZGC"] },
+				"memory": "2G"
+			}"#,
+		)
+		.unwrap();
+		let options = config.to_options(&user_presets).unwrap();
+		// The preset's memory argument is replaced by the memory setting
+		assert_eq!(options.jvm_args, vec!["-XX:+UseZGC"]);
+
+		let config = LaunchConfig {
+			jvm_preset: Some("missing".into()),
+			..Default::default()
+		};
+		assert!(config.to_options(&user_presets).is_err());
+	}
+
 	#[test]
 	fn test_quickplay_deser() {
 		#[derive(Deserialize)]
@@ -535,4 +783,59 @@ mod tests {
 			}
 		);
 	}
+
+	#[test]
+	fn test_custom_version_channel() {
+		let config: InstanceConfig = serde_json::from_str(
+			r#"{
+				"type": "client",
+				"version": { "custom": { "json": "custom.json" } },
+				"version_channel": "snapshot"
+			}"#,
+		)
+		.unwrap();
+		let paths = Paths::new_no_create().unwrap();
+		let result = read_instance_config(
+			"custom".into(),
+			config,
+			&HashMap::new(),
+			&HashMap::new(),
+			&PluginManager::new(),
+			&paths,
+			&mut mcvm_shared::output::NoOp,
+		);
+		assert!(result.is_err());
+	}
+
+	#[test]
+	fn test_consolidate_package_configs() {
+		let profile: ProfileConfig = serde_json::from_str(
+			r#"{
+			"version": "1.20.1",
+			"packages": [
+				"fabric-api",
+				{ "id": "sodium", "features": ["extra"] }
+			]
+		}"#,
+		)
+		.unwrap();
+		let instance: InstanceConfig = serde_json::from_str(
+			r#"{
+			"type": "client",
+			"packages": [
+				"sodium",
+				{ "id": "fabric-api", "stability": "latest" },
+				"lithium"
+			]
+		}"#,
+		)
+		.unwrap();
+
+		let packages = consolidate_package_configs(Some(&profile), &instance, Side::Client);
+		let ids: Vec<_> = packages.iter().map(|x| x.id.to_string()).collect();
+		assert_eq!(ids, vec!["fabric-api", "lithium", "sodium"]);
+		assert_eq!(packages[0].stability, PackageStability::Latest);
+		assert_eq!(packages[2].features, vec!["extra".to_string()]);
+		assert_eq!(packages[2].source, PackageConfigSource::Instance);
+	}
 }
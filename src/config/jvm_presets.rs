@@ -0,0 +1,237 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::bail;
+
+/// The names of the JVM argument presets that are built in
+pub const BUILTIN_PRESETS: [&str; 3] = ["aikar", "client-default", "zgc"];
+
+/// The key that collects every flag that enables a garbage collector,
+/// since only one collector can be used at a time
+const GC_KEY: &str = "gc";
+
+/// Get the arguments of a built-in JVM argument preset
+pub fn get_builtin_preset(name: &str) -> Option<Vec<String>> {
+	let args: &[&str] = match name {
+		// https://docs.papermc.io/paper/aikars-flags
+		"aikar" | "aikars" => &[
+			"-XX:+UseG1GC",
+			"-XX:+ParallelRefProcEnabled",
+			"-XX:MaxGCPauseMillis=200",
+			"-XX:+UnlockExperimentalVMOptions",
+			"-XX:+DisableExplicitGC",
+			"-XX:+AlwaysPreTouch",
+			"-XX:G1NewSizePercent=30",
+			"-XX:G1MaxNewSizePercent=40",
+			"-XX:G1HeapRegionSize=8M",
+			"-XX:G1ReservePercent=20",
+			"-XX:G1HeapWastePercent=5",
+			"-XX:G1MixedGCCountTarget=4",
+			"-XX:InitiatingHeapOccupancyPercent=15",
+			"-XX:G1MixedGCLiveThresholdPercent=90",
+			"-XX:G1RSetUpdatingPauseTimePercent=5",
+			"-XX:SurvivorRatio=32",
+			"-XX:+PerfDisableSharedMem",
+			"-XX:MaxTenuringThreshold=1",
+			"-Dusing.aikars.flags=https://mcflags.emc.gs",
+			"-Daikars.new.flags=true",
+		],
+		// The arguments that the official launcher uses
+		"client-default" => &[
+			"-XX:+UnlockExperimentalVMOptions",
+			"-XX:+UseG1GC",
+			"-XX:G1NewSizePercent=20",
+			"-XX:G1ReservePercent=20",
+			"-XX:MaxGCPauseMillis=50",
+			"-XX:G1HeapRegionSize=32M",
+		],
+		"zgc" => &[
+			"-XX:+UnlockExperimentalVMOptions",
+			"-XX:+UseZGC",
+			"-XX:+DisableExplicitGC",
+			"-XX:+AlwaysPreTouch",
+		],
+		_ => return None,
+	};
+
+	Some(args.iter().map(|x| x.to_string()).collect())
+}
+
+/// Get the arguments of a JVM argument preset. User presets take priority over the built-in ones,
+/// and the `none` preset has no arguments
+pub fn get_preset(
+	name: &str,
+	user_presets: &HashMap<String, Vec<String>>,
+) -> anyhow::Result<Vec<String>> {
+	if let Some(args) = user_presets.get(name) {
+		return Ok(args.clone());
+	}
+	if name == "none" {
+		return Ok(Vec::new());
+	}
+	let Some(args) = get_builtin_preset(name) else {
+		bail!(
+			"Unknown JVM argument preset '{name}'. The built-in presets are {}",
+			BUILTIN_PRESETS.join(", ")
+		);
+	};
+
+	Ok(args)
+}
+
+/// Get the key that a JVM argument sets, so that arguments that set the same option can be found.
+/// Returns None for arguments that can't conflict with each other
+pub fn get_flag_key(arg: &str) -> Option<String> {
+	if let Some(option) = arg.strip_prefix("-XX:") {
+		if let Some(name) = option.strip_prefix('+') {
+			if name.starts_with("Use") && name.ends_with("GC") {
+				return Some(GC_KEY.into());
+			}
+			return Some(name.into());
+		}
+		if let Some(name) = option.strip_prefix('-') {
+			return Some(name.into());
+		}
+		let name = option.split_once('=').map_or(option, |x| x.0);
+		return Some(name.into());
+	}
+	if let Some(property) = arg.strip_prefix("-D") {
+		let name = property.split_once('=').map_or(property, |x| x.0);
+		return Some(format!("D{name}"));
+	}
+	for prefix in ["-Xms", "-Xmx", "-Xmn", "-Xss"] {
+		if arg.starts_with(prefix) {
+			return Some(prefix[1..].into());
+		}
+	}
+
+	None
+}
+
+/// Get the name of the boolean option that a `-XX:+Name` or `-XX:-Name` argument turns on or off
+fn get_boolean_option(arg: &str) -> Option<&str> {
+	let option = arg.strip_prefix("-XX:")?;
+	option
+		.strip_prefix('+')
+		.or_else(|| option.strip_prefix('-'))
+}
+
+/// Merge the arguments from a preset with explicit arguments. Preset arguments that set the same option as
+/// an explicit one are left out, so the explicit ones always win. The explicit arguments come after the preset
+pub fn merge_jvm_args(preset: Vec<String>, explicit: Vec<String>) -> Vec<String> {
+	let explicit_keys: HashSet<_> = explicit.iter().filter_map(|x| get_flag_key(x)).collect();
+	// Turning a collector off has a different key than turning it on, so the option names are compared too
+	let explicit_options: HashSet<_> = explicit
+		.iter()
+		.filter_map(|x| get_boolean_option(x))
+		.collect();
+	let mut out: Vec<_> = preset
+		.iter()
+		.filter(|x| !explicit.contains(x))
+		.filter(|x| get_flag_key(x).map_or(true, |key| !explicit_keys.contains(&key)))
+		.filter(|x| get_boolean_option(x).map_or(true, |x| !explicit_options.contains(x)))
+		.cloned()
+		.collect();
+	out.extend(explicit);
+
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use mcvm_core::io::java::args::MemoryNum;
+	use mcvm_core::launch::LaunchConfiguration;
+
+	use super::*;
+
+	#[test]
+	fn test_flag_keys() {
+		assert_eq!(get_flag_key("-XX:+UseG1GC"), Some("gc".into()));
+		assert_eq!(get_flag_key("-XX:+UseZGC"), Some("gc".into()));
+		assert_eq!(get_flag_key("-XX:-UseG1GC"), Some("UseG1GC".into()));
+		assert_eq!(
+			get_flag_key("-XX:MaxGCPauseMillis=200"),
+			Some("MaxGCPauseMillis".into())
+		);
+		assert_eq!(
+			get_flag_key("-XX:+AlwaysPreTouch"),
+			get_flag_key("-XX:-AlwaysPreTouch")
+		);
+		assert_eq!(get_flag_key("-Dfoo=bar"), Some("Dfoo".into()));
+		assert_eq!(get_flag_key("-Xmx4G"), Some("Xmx".into()));
+		assert_eq!(get_flag_key("--add-modules"), None);
+	}
+
+	#[test]
+	fn test_conflict_resolution() {
+		let preset = get_builtin_preset("aikar").unwrap();
+		let explicit = vec![
+			"-XX:+UseZGC".to_string(),
+			"-XX:MaxGCPauseMillis=100".to_string(),
+			"-XX:-AlwaysPreTouch".to_string(),
+		];
+		let merged = merge_jvm_args(preset.clone(), explicit.clone());
+
+		assert!(!merged.contains(&"-XX:+UseG1GC".to_string()));
+		assert!(!merged.contains(&"-XX:MaxGCPauseMillis=200".to_string()));
+		assert!(!merged.contains(&"-XX:+AlwaysPreTouch".to_string()));
+		assert!(merged.contains(&"-XX:+ParallelRefProcEnabled".to_string()));
+		assert_eq!(merged[merged.len() - explicit.len()..], explicit);
+		// Only one collector is ever selected
+		let gc_count = merged
+			.iter()
+			.filter(|x| get_flag_key(x).as_deref() == Some("gc"))
+			.count();
+		assert_eq!(gc_count, 1);
+
+		// Turning off the preset's collector removes it
+		let merged = merge_jvm_args(preset.clone(), vec!["-XX:-UseG1GC".into()]);
+		assert!(!merged.contains(&"-XX:+UseG1GC".to_string()));
+		assert_eq!(merged.last().map(String::as_str), Some("-XX:-UseG1GC"));
+		assert_eq!(merged.len(), preset.len());
+		let merged = merge_jvm_args(
+			vec!["-XX:-UseG1GC".into(), "-XX:+UseZGC".into()],
+			vec!["-XX:+UseG1GC".into()],
+		);
+		assert_eq!(merged, vec!["-XX:+UseG1GC"]);
+
+		// Duplicates of explicit arguments are only passed once
+		let merged = merge_jvm_args(preset.clone(), vec!["-XX:+UseG1GC".into()]);
+		assert_eq!(merged.len(), preset.len());
+	}
+
+	#[test]
+	fn test_user_presets() {
+		let user_presets = HashMap::from([
+			("mine".to_string(), vec!["-XX:+UseSerialGC".to_string()]),
+			("zgc".to_string(), vec!["-XX:+UseShenandoahGC".to_string()]),
+		]);
+		assert_eq!(
+			get_preset("mine", &user_presets).unwrap(),
+			vec!["-XX:+UseSerialGC"]
+		);
+		assert_eq!(
+			get_preset("zgc", &user_presets).unwrap(),
+			vec!["-XX:+UseShenandoahGC"]
+		);
+		assert!(get_preset("none", &user_presets).unwrap().is_empty());
+		assert!(get_preset("client-default", &user_presets).is_ok());
+		assert!(get_preset("unknown", &user_presets).is_err());
+	}
+
+	#[test]
+	fn test_memory_args_last() {
+		let jvm_args = merge_jvm_args(
+			get_builtin_preset("aikar").unwrap(),
+			vec!["-Dfoo=bar".into()],
+		);
+		let config = LaunchConfiguration {
+			jvm_args: jvm_args.clone(),
+			min_mem: MemoryNum::parse("2G"),
+			max_mem: MemoryNum::parse("4G"),
+			..Default::default()
+		};
+		let args = config.generate_jvm_args();
+		assert_eq!(args[..jvm_args.len()], jvm_args);
+		assert_eq!(args[jvm_args.len()..], ["-Xms2g", "-Xmx4g"]);
+	}
+}
@@ -1,8 +1,14 @@
 /// Easy programatic creation of config
 #[cfg(feature = "builder")]
 pub mod builder;
+/// Configuring the instance supervisor daemon
+pub mod daemon;
+/// Generating a new config during first-time setup
+pub mod init;
 /// Configuring instances
 pub mod instance;
+/// Presets of JVM arguments for launching instances
+pub mod jvm_presets;
 /// Configuring profile modifications
 pub mod modifications;
 /// Configuring packages
@@ -16,6 +22,7 @@ pub mod profile;
 /// Configuring users
 pub mod user;
 
+use self::daemon::{DaemonConfig, ScheduleAction};
 use self::instance::{read_instance_config, InstanceConfig};
 use self::plugin::PluginManager;
 use self::preferences::PrefDeser;
@@ -24,18 +31,22 @@ use self::user::UserConfig;
 use anyhow::{bail, Context};
 use mcvm_core::auth_crate::mc::ClientId;
 use mcvm_core::io::{json_from_file, json_to_file_pretty};
+use mcvm_core::net::download::Client;
 use mcvm_core::user::UserManager;
+use mcvm_plugin::context::{NetworkInfo, PluginContextData};
 use mcvm_shared::id::{InstanceID, ProfileID};
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
 use mcvm_shared::translate;
-use mcvm_shared::util::is_valid_identifier;
-use preferences::ConfigPreferences;
+use mcvm_shared::util::{is_valid_identifier, DefaultExt};
+use preferences::{add_plugin_repos, ConfigPreferences};
 use profile::consolidate_profile_configs;
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use super::instance::Instance;
+use crate::daemon::schedule::Schedule;
+use crate::io::lock::Lockfile;
 use crate::io::paths::Paths;
 use crate::pkg::reg::PkgRegistry;
 
@@ -61,6 +72,10 @@ pub struct Config {
 	pub plugins: PluginManager,
 	/// Global user preferences
 	pub prefs: ConfigPreferences,
+	/// Configuration for the daemon
+	pub daemon: DaemonConfig,
+	/// The client used for network requests, which respects the configured proxy
+	pub client: Client,
 }
 
 /// Deserialization struct for user configuration
@@ -68,18 +83,32 @@ pub struct Config {
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(default)]
 pub struct ConfigDeser {
-	users: HashMap<String, UserConfig>,
-	default_user: Option<String>,
-	instances: HashMap<InstanceID, InstanceConfig>,
-	instance_groups: HashMap<Arc<str>, Vec<InstanceID>>,
-	profiles: HashMap<ProfileID, ProfileConfig>,
-	preferences: PrefDeser,
+	/// The configured users
+	pub users: HashMap<String, UserConfig>,
+	/// The ID of the user to use by default
+	pub default_user: Option<String>,
+	/// The configured instances
+	pub instances: HashMap<InstanceID, InstanceConfig>,
+	/// Named groups of instances
+	pub instance_groups: HashMap<Arc<str>, Vec<InstanceID>>,
+	/// The configured profiles
+	pub profiles: HashMap<ProfileID, ProfileConfig>,
+	/// Global user preferences
+	pub preferences: PrefDeser,
+	/// Configuration for the daemon
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub daemon: DaemonConfig,
 }
 
 impl Config {
 	/// Get the config path
 	pub fn get_path(paths: &Paths) -> PathBuf {
-		paths.project.config_dir().join("mcvm.json")
+		Self::get_path_in(paths.project.config_dir())
+	}
+
+	/// Get the config path in a config directory
+	pub(crate) fn get_path_in(config_dir: &Path) -> PathBuf {
+		config_dir.join("mcvm.json")
 	}
 
 	/// Open the config from a file
@@ -105,7 +134,7 @@ impl Config {
 	}
 
 	/// Create the Config struct from deserialized config
-	fn load_from_deser(
+	pub(crate) fn load_from_deser(
 		config: ConfigDeser,
 		plugins: PluginManager,
 		show_warnings: bool,
@@ -115,10 +144,14 @@ impl Config {
 		let mut users = UserManager::new(ClientId::new("".into()));
 		let mut instances = HashMap::with_capacity(config.instances.len());
 		// Preferences
-		let (prefs, repositories) =
+		let (prefs, mut repositories) =
 			ConfigPreferences::read(&config.preferences).context("Failed to read preferences")?;
+		let client = prefs.create_client()?;
+		add_plugin_repos(&mut repositories, &plugins, paths, o)
+			.context("Failed to add repositories from plugins")?;
 
-		let packages = PkgRegistry::new(repositories, prefs.package_caching_strategy.clone());
+		let mut packages = PkgRegistry::new(repositories, prefs.package_caching_strategy.clone());
+		packages.set_plugins(plugins.clone());
 
 		// Users
 		for (user_id, user_config) in config.users.iter() {
@@ -126,12 +159,6 @@ impl Config {
 				bail!("Invalid user ID '{user_id}'");
 			}
 			let user = user_config.to_user(user_id);
-			// Disabled until we can verify game ownership.
-			// We don't want to be a cracked launcher.
-			if user.is_demo() {
-				bail!("Unverified and Demo users are currently disabled");
-			}
-
 			users.add_user(user);
 		}
 
@@ -159,18 +186,49 @@ impl Config {
 		let profiles =
 			consolidate_profile_configs(config.profiles).context("Failed to merge profiles")?;
 
+		// Daemon
+		for instance_id in config.daemon.instances.keys() {
+			if !config.instances.contains_key(instance_id) {
+				bail!("Instance '{instance_id}' supervised by the daemon does not exist");
+			}
+		}
+		for (schedule_id, schedule) in &config.daemon.schedules {
+			Schedule::from_config(schedule)
+				.with_context(|| format!("Invalid schedule '{schedule_id}'"))?;
+			if !config.instances.contains_key(&schedule.instance) {
+				bail!(
+					"Instance '{}' used by schedule '{schedule_id}' does not exist",
+					schedule.instance
+				);
+			}
+			let needs_supervision = !matches!(schedule.action, ScheduleAction::Backup { .. });
+			if needs_supervision && !config.daemon.instances.contains_key(&schedule.instance) {
+				bail!(
+					"Instance '{}' used by schedule '{schedule_id}' must be supervised by the daemon",
+					schedule.instance
+				);
+			}
+		}
+
 		// Instances
 		for (instance_id, instance_config) in config.instances {
 			let instance = read_instance_config(
 				instance_id.clone(),
 				instance_config,
 				&profiles,
+				&prefs.jvm_presets,
 				&plugins,
 				paths,
 				o,
 			)
 			.with_context(|| format!("Failed to read config for instance {instance_id}"))?;
 
+			if let Some(user) = &instance.config.user {
+				if !users.user_exists(user) {
+					bail!("User '{user}' of instance '{instance_id}' does not exist");
+				}
+			}
+
 			if show_warnings
 				&& !profile::can_install_client_type(&instance.config.modifications.client_type)
 			{
@@ -207,6 +265,8 @@ impl Config {
 			packages,
 			plugins,
 			prefs,
+			daemon: config.daemon,
+			client,
 		})
 	}
 
@@ -219,7 +279,35 @@ impl Config {
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<Self> {
 		let obj = Self::open(path)?;
-		Self::load_from_deser(obj, plugins, show_warnings, paths, o)
+		let config = Self::load_from_deser(obj, plugins, show_warnings, paths, o)?;
+		if let Err(e) = config.update_plugin_context(paths) {
+			o.display(
+				MessageContents::Warning(format!(
+					"Failed to share instance information with plugins: {e:#}"
+				)),
+				MessageLevel::Important,
+			);
+		}
+
+		Ok(config)
+	}
+
+	/// Give plugins the current information about the instances and their installed packages.
+	/// This should be done again after the lockfile changes
+	pub fn update_plugin_context(&self, paths: &Paths) -> anyhow::Result<()> {
+		let lock = Lockfile::open(paths).context("Failed to open lockfile")?;
+		let mut data = PluginContextData::new();
+		data.network = NetworkInfo::new(self.prefs.proxy.as_ref(), self.prefs.timeouts);
+		for (id, instance) in &self.instances {
+			data.instances
+				.insert(id.to_string(), instance.get_plugin_info(&lock, paths));
+			let packages = instance
+				.get_plugin_package_info(&lock)
+				.with_context(|| format!("Failed to get packages of instance '{id}'"))?;
+			data.packages.insert(id.to_string(), packages);
+		}
+
+		self.plugins.set_context(&data, paths)
 	}
 }
 
@@ -272,4 +360,101 @@ mod tests {
 		)
 		.unwrap();
 	}
+
+	#[test]
+	fn test_invalid_instance_icon() {
+		let deser = serde_json::from_value(serde_json::json!({
+			"instances": {
+				"server": {
+					"type": "server",
+					"version": "1.20.1",
+					"description": "A server",
+					"icon": "/mcvm/does/not/exist.png"
+				}
+			}
+		}))
+		.unwrap();
+		let config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			true,
+			&Paths::new_no_create().unwrap(),
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+
+		let instance = config.instances.get("server").unwrap();
+		assert_eq!(
+			instance.get_config().description.as_deref(),
+			Some("A server")
+		);
+		assert_eq!(instance.get_config().icon, None);
+	}
+
+	#[test]
+	fn test_instance_user() {
+		let config = serde_json::json!({
+			"users": {
+				"alice": { "type": "microsoft" },
+				"bob": { "type": "microsoft" }
+			},
+			"default_user": "alice",
+			"instances": {
+				"client": {
+					"type": "client",
+					"version": "1.20.1",
+					"user": "bob"
+				}
+			}
+		});
+		let loaded = Config::load_from_deser(
+			serde_json::from_value(config.clone()).unwrap(),
+			PluginManager::new(),
+			true,
+			&Paths::new_no_create().unwrap(),
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+		let instance = loaded.instances.get("client").unwrap();
+		assert_eq!(instance.get_config().user.as_deref(), Some("bob"));
+
+		let mut config = config;
+		config["instances"]["client"]["user"] = "carol".into();
+		let result = Config::load_from_deser(
+			serde_json::from_value(config).unwrap(),
+			PluginManager::new(),
+			true,
+			&Paths::new_no_create().unwrap(),
+			&mut output::Simple(output::MessageLevel::Debug),
+		);
+		let Err(e) = result else {
+			panic!("Config with an unknown instance user should fail to load");
+		};
+		assert!(format!("{e:#}").contains("carol"));
+	}
+
+	#[test]
+	fn test_offline_users() {
+		let config = serde_json::json!({
+			"users": {
+				"demo": { "type": "demo" },
+				"offline": { "type": "unverified", "username": "Steve" }
+			},
+			"default_user": "offline"
+		});
+		let loaded = Config::load_from_deser(
+			serde_json::from_value(config).unwrap(),
+			PluginManager::new(),
+			true,
+			&Paths::new_no_create().unwrap(),
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+
+		let demo = loaded.users.get_user("demo").unwrap();
+		assert!(demo.is_demo());
+		let offline = loaded.users.get_chosen_user().unwrap();
+		assert!(offline.is_offline());
+		assert_eq!(offline.get_name().map(String::as_str), Some("Steve"));
+	}
 }
@@ -1,8 +1,11 @@
 #![allow(dead_code)]
 use std::fs::File;
 
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
+use mcvm_core::io::json_from_file;
+use serde_json::Value;
 
+use crate::config::instance::is_valid_instance_id;
 use crate::io::paths::Paths;
 use mcvm_shared::id::{InstanceID, ProfileID};
 
@@ -20,8 +23,16 @@ pub enum ConfigModification {
 	AddProfile(ProfileID, ProfileConfig),
 	/// Adds a new instance
 	AddInstance(InstanceID, InstanceConfig),
+	/// Removes an instance and any references to it
+	RemoveInstance(InstanceID),
+	/// Changes the ID of an instance and any references to it
+	RenameInstance(InstanceID, InstanceID),
+	/// Changes the ID of a profile and any references to it
+	RenameProfile(ProfileID, ProfileID),
 	/// Adds a new package to a profile
 	AddPackage(ProfileID, PackageConfigDeser),
+	/// Sets or removes the user that an instance launches with
+	SetInstanceUser(InstanceID, Option<String>),
 }
 
 /// Applies modifications to the config
@@ -38,8 +49,63 @@ pub fn apply_modifications(
 				config.profiles.insert(id, profile);
 			}
 			ConfigModification::AddInstance(instance_id, instance) => {
+				check_instance_id_available(config, &instance_id)?;
 				config.instances.insert(instance_id, instance);
 			}
+			ConfigModification::RemoveInstance(instance_id) => {
+				if config.instances.remove(&instance_id).is_none() {
+					bail!("Unknown instance '{instance_id}'");
+				}
+				for group in config.instance_groups.values_mut() {
+					group.retain(|x| x != &instance_id);
+				}
+				config.daemon.instances.remove(&instance_id);
+				config
+					.daemon
+					.schedules
+					.retain(|_, x| x.instance != instance_id);
+			}
+			ConfigModification::RenameInstance(old, new) => {
+				check_instance_id_available(config, &new)?;
+				let instance = config
+					.instances
+					.remove(&old)
+					.ok_or(anyhow!("Unknown instance '{old}'"))?;
+				config.instances.insert(new.clone(), instance);
+				for group in config.instance_groups.values_mut() {
+					for id in group.iter_mut().filter(|x| **x == old) {
+						*id = new.clone();
+					}
+				}
+				if let Some(supervised) = config.daemon.instances.remove(&old) {
+					config.daemon.instances.insert(new.clone(), supervised);
+				}
+				for schedule in config.daemon.schedules.values_mut() {
+					if schedule.instance == old {
+						schedule.instance = new.clone();
+					}
+				}
+			}
+			ConfigModification::RenameProfile(old, new) => {
+				if config.profiles.contains_key(&new) {
+					bail!("A profile with the ID '{new}' already exists");
+				}
+				let profile = config
+					.profiles
+					.remove(&old)
+					.ok_or(anyhow!("Unknown profile '{old}'"))?;
+				config.profiles.insert(new.clone(), profile);
+				let configs = config
+					.instances
+					.values_mut()
+					.chain(config.profiles.values_mut().map(|x| &mut x.instance))
+					.chain(config.preferences.instance_presets.values_mut());
+				for instance in configs {
+					if instance.common.from.as_deref() == Some(&old) {
+						instance.common.from = Some(new.to_string());
+					}
+				}
+			}
 			ConfigModification::AddPackage(profile_id, package) => {
 				let profile = config
 					.profiles
@@ -47,6 +113,18 @@ pub fn apply_modifications(
 					.ok_or(anyhow!("Unknown profile '{profile_id}'"))?;
 				profile.packages.add_global_package(package);
 			}
+			ConfigModification::SetInstanceUser(instance_id, user) => {
+				if let Some(user) = &user {
+					if !config.users.contains_key(user) {
+						bail!("User '{user}' does not exist");
+					}
+				}
+				let instance = config
+					.instances
+					.get_mut(&instance_id)
+					.ok_or(anyhow!("Unknown instance '{instance_id}'"))?;
+				instance.user = user;
+			}
 		};
 	}
 	Ok(())
@@ -58,15 +136,66 @@ pub fn apply_modifications_and_write(
 	modifications: Vec<ConfigModification>,
 	paths: &Paths,
 ) -> anyhow::Result<()> {
+	let before = serde_json::to_value(&*config).context("Failed to serialize config")?;
 	apply_modifications(config, modifications)?;
+	let after = serde_json::to_value(&*config).context("Failed to serialize config")?;
+
+	// Only change the parts of the file that were modified so that the rest stays as the user wrote it
 	let path = Config::get_path(paths);
+	let mut out = if path.exists() {
+		json_from_file(&path).context("Failed to read config file")?
+	} else {
+		after.clone()
+	};
+	apply_changes(&mut out, &before, &after);
+
 	let mut file = File::create(path).context("Failed to open config file")?;
-	serde_json::to_writer_pretty(&mut file, config)
-		.context("Failed to write default configuration")?;
+	serde_json::to_writer_pretty(&mut file, &out).context("Failed to write configuration")?;
 
 	Ok(())
 }
 
+/// Make sure that an ID for a new instance is valid and doesn't collide with anything else in the config
+pub fn check_instance_id_available(config: &ConfigDeser, id: &InstanceID) -> anyhow::Result<()> {
+	if !is_valid_instance_id(id) {
+		bail!("Invalid instance ID '{id}'");
+	}
+	if config.instances.contains_key(id) {
+		bail!("An instance with the ID '{id}' already exists");
+	}
+	if config.instance_groups.contains_key(id) {
+		bail!("The ID '{id}' is already used by an instance group");
+	}
+
+	Ok(())
+}
+
+/// Apply the differences between two serialized configs to the raw config.
+/// Values that didn't change are left untouched
+fn apply_changes(out: &mut Value, before: &Value, after: &Value) {
+	let (Value::Object(out_map), Value::Object(before), Value::Object(after)) =
+		(&mut *out, before, after)
+	else {
+		*out = after.clone();
+		return;
+	};
+
+	for (key, new) in after {
+		match (before.get(key), out_map.get_mut(key)) {
+			(Some(old), _) if old == new => {}
+			(Some(old), Some(existing)) => apply_changes(existing, old, new),
+			_ => {
+				out_map.insert(key.clone(), new.clone());
+			}
+		}
+	}
+	for key in before.keys() {
+		if !after.contains_key(key) {
+			out_map.remove(key);
+		}
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -85,4 +214,142 @@ mod tests {
 		apply_modifications(&mut config, modifications).unwrap();
 		assert!(config.users.contains_key("bob"));
 	}
+
+	fn test_instance() -> InstanceConfig {
+		InstanceConfig {
+			side: Some(mcvm_shared::Side::Client),
+			name: None,
+			description: None,
+			icon: None,
+			whitelist: None,
+			ops: None,
+			user: None,
+			common: Default::default(),
+			window: Default::default(),
+		}
+	}
+
+	#[test]
+	fn test_instance_add_remove_modification() {
+		let mut config = ConfigDeser::default();
+		config
+			.instance_groups
+			.insert("group".into(), vec!["foo".into()]);
+
+		let modifications = vec![ConfigModification::AddInstance(
+			"foo".into(),
+			test_instance(),
+		)];
+		apply_modifications(&mut config, modifications).unwrap();
+		assert!(config.instances.contains_key("foo"));
+
+		let modifications = vec![ConfigModification::AddInstance(
+			"foo".into(),
+			test_instance(),
+		)];
+		assert!(apply_modifications(&mut config, modifications).is_err());
+		let modifications = vec![ConfigModification::AddInstance(
+			"group".into(),
+			test_instance(),
+		)];
+		assert!(apply_modifications(&mut config, modifications).is_err());
+
+		let modifications = vec![ConfigModification::RemoveInstance("foo".into())];
+		apply_modifications(&mut config, modifications).unwrap();
+		assert!(!config.instances.contains_key("foo"));
+		assert!(config.instance_groups["group"].is_empty());
+
+		let modifications = vec![ConfigModification::RemoveInstance("foo".into())];
+		assert!(apply_modifications(&mut config, modifications).is_err());
+	}
+
+	#[test]
+	fn test_set_instance_user_modification() {
+		let mut config = ConfigDeser::default();
+		config.instances.insert("foo".into(), test_instance());
+		config.users.insert(
+			"bob".into(),
+			UserConfig {
+				variant: UserVariant::Demo {},
+			},
+		);
+
+		let modifications = vec![ConfigModification::SetInstanceUser(
+			"foo".into(),
+			Some("bob".into()),
+		)];
+		apply_modifications(&mut config, modifications).unwrap();
+		assert_eq!(config.instances["foo"].user.as_deref(), Some("bob"));
+
+		let modifications = vec![ConfigModification::SetInstanceUser(
+			"foo".into(),
+			Some("alice".into()),
+		)];
+		assert!(apply_modifications(&mut config, modifications).is_err());
+
+		let modifications = vec![ConfigModification::SetInstanceUser("foo".into(), None)];
+		apply_modifications(&mut config, modifications).unwrap();
+		assert_eq!(config.instances["foo"].user, None);
+	}
+
+	#[test]
+	fn test_rename_modifications() {
+		let mut config = ConfigDeser::default();
+		let mut instance = test_instance();
+		instance.common.from = Some("profile".into());
+		config.instances.insert("foo".into(), instance);
+		config.instances.insert("other".into(), test_instance());
+		config
+			.instance_groups
+			.insert("group".into(), vec!["foo".into()]);
+		config
+			.profiles
+			.insert("profile".into(), serde_json::from_str("{}").unwrap());
+
+		let modifications = vec![ConfigModification::RenameInstance(
+			"foo".into(),
+			"other".into(),
+		)];
+		assert!(apply_modifications(&mut config, modifications).is_err());
+
+		let modifications = vec![
+			ConfigModification::RenameInstance("foo".into(), "bar".into()),
+			ConfigModification::RenameProfile("profile".into(), "new_profile".into()),
+		];
+		apply_modifications(&mut config, modifications).unwrap();
+		assert!(!config.instances.contains_key("foo"));
+		assert_eq!(config.instance_groups["group"], vec!["bar".into()]);
+		assert!(config.profiles.contains_key("new_profile"));
+		assert_eq!(
+			config.instances["bar"].common.from.as_deref(),
+			Some("new_profile")
+		);
+	}
+
+	#[test]
+	fn test_apply_changes() {
+		let mut out = serde_json::json!({
+			"users": { "bob": { "type": "demo" } },
+			"instances": {},
+			"$schema": "./schema.json"
+		});
+		let before = serde_json::json!({
+			"users": { "bob": { "type": "demo" } },
+			"instances": {}
+		});
+		let after = serde_json::json!({
+			"users": {},
+			"instances": { "foo": { "type": "client" } }
+		});
+
+		apply_changes(&mut out, &before, &after);
+		assert_eq!(
+			out,
+			serde_json::json!({
+				"users": {},
+				"instances": { "foo": { "type": "client" } },
+				"$schema": "./schema.json"
+			})
+		);
+	}
 }
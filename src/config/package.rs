@@ -2,15 +2,16 @@ use std::borrow::Cow;
 use std::fmt::Display;
 use std::sync::Arc;
 
-use anyhow::{bail, ensure};
+use anyhow::bail;
+use itertools::Itertools;
 use mcvm_pkg::properties::PackageProperties;
-use mcvm_shared::pkg::{is_valid_package_id, ArcPkgReq, PackageID, PackageStability};
+use mcvm_shared::pkg::{ArcPkgReq, PackageID, PackageStability};
 use mcvm_shared::util::is_valid_identifier;
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::pkg::eval::EvalPermissions;
+use crate::pkg::eval::{EvalParameters, EvalPermissions};
 use mcvm_pkg::{PkgRequest, PkgRequestSource};
 
 /// Stored configuration for a package
@@ -30,6 +31,10 @@ pub struct PackageConfig {
 	pub stability: PackageStability,
 	/// Worlds to use for the package
 	pub worlds: Vec<String>,
+	/// The repository to get the package from instead of the highest-priority one that has it
+	pub repository: Option<String>,
+	/// The repository or plugin namespace given explicitly in the package ID
+	pub namespace: Option<Arc<str>>,
 }
 
 impl PackageConfig {
@@ -43,39 +48,69 @@ impl PackageConfig {
 			permissions: EvalPermissions::default(),
 			stability: PackageStability::default(),
 			worlds: Vec::new(),
+			repository: None,
+			namespace: None,
 		}
 	}
 
-	/// Calculate the features of the config
+	/// Calculate the features of the config, expanding any feature groups.
+	/// Configured features that the package doesn't declare are an error in strict mode.
+	/// Otherwise they are skipped, and can be found with `get_unknown_features`
 	pub fn calculate_features(
 		&self,
 		properties: &PackageProperties,
+		strict: bool,
 	) -> anyhow::Result<Vec<String>> {
-		let empty = Vec::new();
-		let allowed_features = properties.features.as_ref().unwrap_or(&empty);
-
-		for feature in &self.features {
-			ensure!(
-				allowed_features.contains(feature),
-				"Configured feature '{feature}' does not exist"
-			);
+		if strict {
+			if let Some(feature) = self.get_unknown_features(properties).first() {
+				bail!("Configured feature '{feature}' does not exist");
+			}
 		}
 
-		let mut out = self.features.clone();
+		let known: Vec<_> = self
+			.features
+			.iter()
+			.filter(|x| properties.has_feature(x))
+			.cloned()
+			.collect();
+		let mut out = properties.expand_feature_groups(&known);
 		if self.use_default_features {
 			let default_features = properties.default_features.clone().unwrap_or_default();
 			out.extend(default_features);
 		}
 
-		Ok(out)
+		Ok(out.into_iter().unique().collect())
+	}
+
+	/// Apply this configuration to the parameters for evaluating the package
+	pub fn apply_to_params(
+		&self,
+		properties: &PackageProperties,
+		strict: bool,
+		params: &mut EvalParameters,
+	) -> anyhow::Result<()> {
+		params.config_source = self.source;
+		params.features = self.calculate_features(properties, strict)?;
+		params.perms = self.permissions;
+		params.stability = self.stability;
+
+		Ok(())
+	}
+
+	/// Get the configured features that the package doesn't declare as features or feature groups
+	pub fn get_unknown_features(&self, properties: &PackageProperties) -> Vec<String> {
+		self.features
+			.iter()
+			.filter(|x| !properties.has_feature(x))
+			.cloned()
+			.collect()
 	}
 
 	/// Get the request of the config
 	pub fn get_request(&self) -> ArcPkgReq {
-		Arc::new(PkgRequest::parse(
-			self.id.clone(),
-			PkgRequestSource::UserRequire,
-		))
+		let mut req = PkgRequest::parse(self.id.clone(), PkgRequestSource::UserRequire);
+		req.namespace = self.namespace.clone();
+		Arc::new(req)
 	}
 }
 
@@ -113,13 +148,18 @@ pub struct FullPackageConfig {
 	pub use_default_features: bool,
 	/// Permissions for the package
 	#[serde(default)]
-	pub permissions: EvalPermissions,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub permissions: Option<EvalPermissions>,
 	/// Expected stability for the package
 	#[serde(default)]
 	pub stability: Option<PackageStability>,
 	/// Worlds to use for the package
 	#[serde(default)]
 	pub worlds: Vec<String>,
+	/// The repository to get the package from instead of the highest-priority one that has it
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub repository: Option<String>,
 }
 
 /// Trick enum used to make deserialization work in the way we want
@@ -154,16 +194,20 @@ impl PackageConfigDeser {
 	pub fn to_package_config(
 		self,
 		profile_stability: PackageStability,
+		default_permissions: EvalPermissions,
 		source: PackageConfigSource,
 	) -> PackageConfig {
+		let req = PkgRequest::parse(self.get_pkg_id(), PkgRequestSource::UserRequire);
 		PackageConfig {
-			id: self.get_pkg_id(),
+			id: req.id,
 			source,
 			features: self.get_features(),
 			use_default_features: self.get_use_default_features(),
-			permissions: self.get_permissions(),
+			permissions: self.get_permissions(default_permissions),
 			stability: self.get_stability(profile_stability),
 			worlds: self.get_worlds().into_owned(),
+			repository: self.get_repository().map(String::from),
+			namespace: req.namespace,
 		}
 	}
 
@@ -192,10 +236,10 @@ impl PackageConfigDeser {
 	}
 
 	/// Get the permissions of the config
-	pub fn get_permissions(&self) -> EvalPermissions {
+	pub fn get_permissions(&self, default_permissions: EvalPermissions) -> EvalPermissions {
 		match &self {
-			Self::Basic(..) => EvalPermissions::Standard,
-			Self::Full(cfg) => cfg.permissions,
+			Self::Basic(..) => default_permissions,
+			Self::Full(cfg) => cfg.permissions.unwrap_or(default_permissions),
 		}
 	}
 
@@ -208,18 +252,27 @@ impl PackageConfigDeser {
 	}
 
 	/// Get the  worlds of the config
-	pub fn get_worlds(&self) -> Cow<[String]> {
+	pub fn get_worlds(&self) -> Cow<'_, [String]> {
 		match &self {
 			Self::Basic(..) => Cow::Owned(Vec::new()),
 			Self::Full(cfg) => Cow::Borrowed(&cfg.worlds),
 		}
 	}
 
+	/// Get the repository override of the config
+	pub fn get_repository(&self) -> Option<&str> {
+		match &self {
+			Self::Basic(..) => None,
+			Self::Full(cfg) => cfg.repository.as_deref(),
+		}
+	}
+
 	/// Validate this config
 	pub fn validate(&self) -> anyhow::Result<()> {
 		let id = self.get_pkg_id();
-		if !is_valid_package_id(&id) {
-			bail!("Invalid package ID '{id}'");
+		let req = PkgRequest::parse_and_validate(&id, PkgRequestSource::UserRequire)?;
+		if req.namespace.is_some() && self.get_repository().is_some() {
+			bail!("Package '{id}' sets a repository but already has a namespace in its ID");
 		}
 
 		for feature in self.get_features() {
@@ -231,3 +284,72 @@ impl PackageConfigDeser {
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use super::*;
+
+	#[test]
+	fn test_calculate_features() {
+		let properties = PackageProperties {
+			features: Some(vec!["shaders".into(), "particles".into(), "sounds".into()]),
+			default_features: Some(vec!["sounds".into()]),
+			feature_groups: Some(HashMap::from([(
+				"all-visuals".into(),
+				vec!["shaders".into(), "particles".into()],
+			)])),
+			..Default::default()
+		};
+
+		let mut config = PackageConfig::from_id("pkg".into());
+		assert_eq!(
+			config.calculate_features(&properties, false).unwrap(),
+			vec!["sounds"]
+		);
+
+		config.features = vec!["all-visuals".into(), "sounds".into(), "music".into()];
+		assert_eq!(
+			config.calculate_features(&properties, false).unwrap(),
+			vec!["shaders", "particles", "sounds"]
+		);
+		assert_eq!(config.get_unknown_features(&properties), vec!["music"]);
+		assert!(config.calculate_features(&properties, true).is_err());
+
+		config.use_default_features = false;
+		config.features = vec!["particles".into()];
+		assert_eq!(
+			config.calculate_features(&properties, false).unwrap(),
+			vec!["particles"]
+		);
+		assert!(config.get_unknown_features(&properties).is_empty());
+		assert_eq!(
+			config.calculate_features(&properties, true).unwrap(),
+			vec!["particles"]
+		);
+	}
+
+	#[test]
+	fn test_namespaced_package_id() {
+		let deser: PackageConfigDeser = serde_json::from_str(r#""modrinth:sodium""#).unwrap();
+		deser.validate().unwrap();
+		let config = deser.to_package_config(
+			PackageStability::Stable,
+			EvalPermissions::Standard,
+			PackageConfigSource::Instance,
+		);
+		assert_eq!(&*config.id, "sodium");
+		assert_eq!(config.namespace.as_deref(), Some("modrinth"));
+		assert_eq!(config.get_request().to_string(), "modrinth:sodium");
+
+		let deser: PackageConfigDeser =
+			serde_json::from_str(r#"{ "id": "repo:sodium", "repository": "other" }"#).unwrap();
+		assert!(deser.validate().is_err());
+
+		for id in [r#"":sodium""#, r#""repo:Sodium""#, r#""a:b:c""#] {
+			let deser: PackageConfigDeser = serde_json::from_str(id).unwrap();
+			assert!(deser.validate().is_err());
+		}
+	}
+}
@@ -1,16 +1,27 @@
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 use std::sync::{Arc, MutexGuard};
+use std::time::{Duration, UNIX_EPOCH};
 
 use crate::io::paths::Paths;
-use anyhow::{anyhow, Context};
-use mcvm_core::io::{json_from_file, json_to_file_pretty};
-use mcvm_shared::output::MCVMOutput;
+use anyhow::{anyhow, bail, Context};
+use itertools::Itertools;
+use mcvm_core::io::{json_from_file, json_to_file, json_to_file_pretty};
+use mcvm_core::net::download;
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::translate;
+use mcvm_shared::util::is_valid_identifier;
+use mcvm_shared::Side;
+use reqwest::Client;
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use mcvm_plugin::hooks::{Hook, HookHandle};
-use mcvm_plugin::plugin::{Plugin, PluginManifest};
+use mcvm_plugin::context::PluginContextData;
+use mcvm_plugin::hooks::{
+	DescribeSubcommands, Hook, HookHandle, OnInstanceSetup, SubcommandDescription,
+};
+use mcvm_plugin::plugin::{HookHandler, Plugin, PluginManifest, PROTOCOL_VERSION};
 use mcvm_plugin::PluginManager as LoadedPluginManager;
 use std::sync::Mutex;
 
@@ -23,6 +34,62 @@ pub struct PluginsConfig {
 	pub plugins: Vec<PluginConfigDeser>,
 }
 
+impl PluginsConfig {
+	/// Open the plugins config, creating it if it doesn't exist
+	pub fn open(paths: &Paths) -> anyhow::Result<Self> {
+		let path = PluginManager::get_path(paths);
+		if path.exists() {
+			json_from_file(path).context("Failed to load plugin config from file")
+		} else {
+			let out = Self::default();
+			out.write(paths)?;
+			Ok(out)
+		}
+	}
+
+	/// Write the plugins config to its file
+	pub fn write(&self, paths: &Paths) -> anyhow::Result<()> {
+		json_to_file_pretty(PluginManager::get_path(paths), self)
+			.context("Failed to write plugin config to file")
+	}
+
+	/// Check whether a plugin is configured and not disabled
+	pub fn is_enabled(&self, plugin: &str) -> bool {
+		self.plugins
+			.iter()
+			.any(|x| x.get_id() == plugin && !x.is_disabled())
+	}
+
+	/// Enable or disable a plugin, adding it to the list if it isn't there
+	pub fn set_enabled(&mut self, plugin: &str, enabled: bool) {
+		let Some(config) = self.plugins.iter_mut().find(|x| x.get_id() == plugin) else {
+			if enabled {
+				self.plugins.push(PluginConfigDeser::Simple(plugin.into()));
+			} else {
+				self.plugins.push(PluginConfigDeser::Full {
+					id: plugin.into(),
+					custom_config: None,
+					disabled: true,
+				});
+			}
+			return;
+		};
+
+		match config {
+			PluginConfigDeser::Simple(id) => {
+				if !enabled {
+					*config = PluginConfigDeser::Full {
+						id: id.clone(),
+						custom_config: None,
+						disabled: true,
+					};
+				}
+			}
+			PluginConfigDeser::Full { disabled, .. } => *disabled = !enabled,
+		}
+	}
+}
+
 /// User configuration for a plugin
 #[derive(Debug)]
 pub struct PluginConfig {
@@ -46,11 +113,28 @@ pub enum PluginConfigDeser {
 		/// The custom config for the plugin
 		#[serde(default)]
 		#[serde(rename = "config")]
+		#[serde(skip_serializing_if = "Option::is_none")]
 		custom_config: Option<serde_json::Value>,
+		/// Whether the plugin is disabled and should not be loaded
+		#[serde(default)]
+		#[serde(skip_serializing_if = "std::ops::Not::not")]
+		disabled: bool,
 	},
 }
 
 impl PluginConfigDeser {
+	/// Get the ID of the configured plugin
+	pub fn get_id(&self) -> &str {
+		match self {
+			Self::Simple(id) | Self::Full { id, .. } => id,
+		}
+	}
+
+	/// Check whether the plugin is disabled
+	pub fn is_disabled(&self) -> bool {
+		matches!(self, Self::Full { disabled: true, .. })
+	}
+
 	/// Convert this deserialized plugin config to the final version
 	pub fn to_config(&self) -> PluginConfig {
 		let id = match self {
@@ -83,20 +167,14 @@ pub struct PluginManagerInner {
 impl PluginManager {
 	/// Load the PluginManager from the plugins.json file
 	pub fn load(paths: &Paths, o: &mut impl MCVMOutput) -> anyhow::Result<Self> {
-		let path = Self::get_path(paths);
-		let config = if path.exists() {
-			json_from_file(path).context("Failed to load plugin config from file")?
-		} else {
-			let out = PluginsConfig::default();
-			json_to_file_pretty(path, &out)
-				.context("Failed to write default plugin config to file")?;
-
-			out
-		};
+		let config = PluginsConfig::open(paths)?;
 
 		let mut out = Self::new();
 
 		for plugin in config.plugins {
+			if plugin.is_disabled() {
+				continue;
+			}
 			let plugin = plugin.to_config();
 			out.load_plugin(plugin, paths, o)
 				.context("Failed to load plugin")?;
@@ -167,21 +245,42 @@ impl PluginManager {
 		paths: &Paths,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<()> {
-		// Get the path for the manifest
-		let path = paths.plugins.join(format!("{}.json", plugin.id));
-		let (path, plugin_dir) = if path.exists() {
-			(path, None)
-		} else {
-			let dir = paths.plugins.join(&plugin.id);
-			(dir.join("plugin.json"), Some(dir))
-		};
-		let manifest = json_from_file(path).context("Failed to read plugin manifest from file")?;
+		let (manifest, plugin_dir) = read_plugin_manifest(&plugin.id, paths)?;
+
+		if !manifest.is_protocol_supported() {
+			o.display(
+				MessageContents::Warning(translate!(
+					o,
+					PluginProtocolTooNew,
+					"plugin" = &plugin.id,
+					"version" = &manifest.protocol_version.unwrap_or_default().to_string(),
+					"supported" = &PROTOCOL_VERSION.to_string()
+				)),
+				MessageLevel::Important,
+			);
+			return Ok(());
+		}
 
 		self.add_plugin(plugin, manifest, paths, plugin_dir.as_deref(), o)?;
 
 		Ok(())
 	}
 
+	/// Share information about the user's instances with plugins by writing it to a file
+	/// that their hooks can read. Nothing is written if there are no plugins
+	pub fn set_context(&self, data: &PluginContextData, paths: &Paths) -> anyhow::Result<()> {
+		let mut inner = self.inner.lock().map_err(|x| anyhow!("{x}"))?;
+		if !inner.manager.has_plugins() {
+			return Ok(());
+		}
+
+		let path = paths.internal.join("plugin_context.json");
+		json_to_file(&path, data).context("Failed to write instance information for plugins")?;
+		inner.manager.set_context_path(path);
+
+		Ok(())
+	}
+
 	/// Call a plugin hook on the manager and collects the results into a Vec
 	pub fn call_hook<H: Hook>(
 		&self,
@@ -209,8 +308,36 @@ impl PluginManager {
 			.call_hook_on_plugin(hook, plugin_id, arg, &paths.core, o)
 	}
 
+	/// Get the ID of the plugin that claims the namespace of a package, if any
+	pub fn get_package_provider(&self, package: &str) -> anyhow::Result<Option<String>> {
+		let inner = self.inner.lock().map_err(|x| anyhow!("{x}"))?;
+		let plugin = inner
+			.manager
+			.iter_plugins()
+			.find(|x| x.claims_package(package));
+		Ok(plugin.map(|x| x.get_id().clone()))
+	}
+
+	/// Get the files in an instance's game directory that the loaded plugins rewrite
+	/// whenever an instance on a side is set up, without duplicates
+	pub fn get_setup_files(&self, side: Side) -> anyhow::Result<Vec<String>> {
+		let inner = self.inner.lock().map_err(|x| anyhow!("{x}"))?;
+		let out = inner
+			.manager
+			.iter_plugins()
+			.map(|x| x.get_manifest())
+			.filter(|x| x.hooks.contains_key(OnInstanceSetup.get_name()))
+			.filter_map(|x| x.setup_files.get(&side))
+			.flatten()
+			.cloned()
+			.sorted()
+			.dedup()
+			.collect();
+		Ok(out)
+	}
+
 	/// Get a lock for the inner mutex
-	pub fn get_lock(&self) -> anyhow::Result<MutexGuard<PluginManagerInner>> {
+	pub fn get_lock(&self) -> anyhow::Result<MutexGuard<'_, PluginManagerInner>> {
 		let inner = self.inner.lock().map_err(|x| anyhow!("{x}"))?;
 		Ok(inner)
 	}
@@ -221,3 +348,578 @@ impl Default for PluginManager {
 		Self::new()
 	}
 }
+
+/// Read the manifest of an installed plugin, along with the plugin's directory if it has one
+pub fn read_plugin_manifest(
+	plugin: &str,
+	paths: &Paths,
+) -> anyhow::Result<(PluginManifest, Option<PathBuf>)> {
+	// Get the path for the manifest
+	let path = paths.plugins.join(format!("{plugin}.json"));
+	let (path, plugin_dir) = if path.exists() {
+		(path, None)
+	} else {
+		let dir = paths.plugins.join(plugin);
+		(dir.join("plugin.json"), Some(dir))
+	};
+	let manifest = json_from_file(path).context("Failed to read plugin manifest from file")?;
+
+	Ok((manifest, plugin_dir))
+}
+
+/// Get the IDs of all of the plugins installed in the plugins directory
+pub fn get_installed_plugins(paths: &Paths) -> anyhow::Result<Vec<String>> {
+	if !paths.plugins.exists() {
+		return Ok(Vec::new());
+	}
+
+	let mut out = Vec::new();
+	for entry in std::fs::read_dir(&paths.plugins).context("Failed to read plugins directory")? {
+		let path = entry?.path();
+		let id = if path.is_dir() {
+			if !path.join("plugin.json").exists() {
+				continue;
+			}
+			path.file_name()
+		} else if path.extension().is_some_and(|x| x == "json") {
+			path.file_stem()
+		} else {
+			continue;
+		};
+		if let Some(id) = id {
+			out.push(id.to_string_lossy().to_string());
+		}
+	}
+	out.sort();
+	out.dedup();
+
+	Ok(out)
+}
+
+/// Install a plugin from a local manifest file, a local plugin directory, or the URL of a manifest.
+/// The manifest is checked before anything is copied, and executables next to a local manifest
+/// are installed along with it. Returns the ID of the installed plugin
+pub async fn install_plugin(
+	source: &str,
+	id: Option<&str>,
+	paths: &Paths,
+	client: &Client,
+) -> anyhow::Result<String> {
+	let is_url = source.starts_with("http://") || source.starts_with("https://");
+	let source_path = PathBuf::from(source);
+	let id = match id {
+		Some(id) => id.to_string(),
+		None => {
+			let name = if is_url {
+				source
+					.rsplit('/')
+					.next()
+					.map(|x| x.trim_end_matches(".json"))
+			} else if source_path.is_dir() {
+				source_path.file_name().and_then(|x| x.to_str())
+			} else {
+				source_path.file_stem().and_then(|x| x.to_str())
+			};
+			name.context("Failed to get plugin ID from source")?
+				.to_string()
+		}
+	};
+	if !is_valid_identifier(&id) || id.is_empty() {
+		bail!("Invalid plugin ID '{id}'");
+	}
+
+	std::fs::create_dir_all(&paths.plugins).context("Failed to create plugins directory")?;
+	let existing = get_installed_plugins(paths)?;
+	if existing.contains(&id) {
+		bail!("A plugin with the ID '{id}' is already installed");
+	}
+
+	if is_url {
+		let text = download::text(source, client)
+			.await
+			.context("Failed to download plugin manifest")?;
+		let manifest: PluginManifest =
+			serde_json::from_str(&text).context("Failed to parse plugin manifest")?;
+		check_manifest(&manifest)?;
+
+		// Bundle any executables that are hosted next to the manifest
+		let executables =
+			download_executables(source, &get_bundled_executables(&manifest)?, client).await?;
+		if executables.is_empty() {
+			std::fs::write(paths.plugins.join(format!("{id}.json")), text)
+				.context("Failed to write plugin manifest")?;
+		} else {
+			let plugin_dir = paths.plugins.join(&id);
+			std::fs::create_dir_all(&plugin_dir)?;
+			std::fs::write(plugin_dir.join("plugin.json"), text)
+				.context("Failed to write plugin manifest")?;
+			for (executable, data) in executables {
+				write_executable(&plugin_dir.join(executable), &data)
+					.with_context(|| format!("Failed to write plugin executable '{executable}'"))?;
+			}
+		}
+	} else if source_path.is_dir() {
+		let manifest: PluginManifest = json_from_file(source_path.join("plugin.json"))
+			.context("Failed to read plugin manifest")?;
+		check_manifest(&manifest)?;
+		copy_dir(&source_path, &paths.plugins.join(&id))
+			.context("Failed to copy plugin directory")?;
+	} else {
+		let manifest: PluginManifest =
+			json_from_file(&source_path).context("Failed to read plugin manifest")?;
+		check_manifest(&manifest)?;
+
+		// Bundle any executables that are shipped next to the manifest
+		let source_dir = source_path.parent().unwrap_or(Path::new("."));
+		let executables: Vec<_> = get_bundled_executables(&manifest)?
+			.into_iter()
+			.filter(|x| source_dir.join(x).is_file())
+			.collect();
+		if executables.is_empty() {
+			std::fs::copy(&source_path, paths.plugins.join(format!("{id}.json")))
+				.context("Failed to copy plugin manifest")?;
+		} else {
+			let plugin_dir = paths.plugins.join(&id);
+			std::fs::create_dir_all(&plugin_dir)?;
+			std::fs::copy(&source_path, plugin_dir.join("plugin.json"))
+				.context("Failed to copy plugin manifest")?;
+			for executable in executables {
+				let target = plugin_dir.join(executable);
+				if let Some(parent) = target.parent() {
+					std::fs::create_dir_all(parent)?;
+				}
+				std::fs::copy(source_dir.join(executable), target)
+					.with_context(|| format!("Failed to copy plugin executable '{executable}'"))?;
+			}
+		}
+	}
+
+	Ok(id)
+}
+
+/// Get the executables of a plugin that can be bundled with it. Only relative paths inside the
+/// directory of the manifest are bundled, and absolute paths are run from where they are
+fn get_bundled_executables(manifest: &PluginManifest) -> anyhow::Result<Vec<&str>> {
+	let mut out = Vec::new();
+	for executable in manifest.get_executables() {
+		let path = Path::new(executable);
+		if path.is_absolute() || path.has_root() {
+			continue;
+		}
+		if !path
+			.components()
+			.all(|x| matches!(x, Component::Normal(..) | Component::CurDir))
+		{
+			bail!("Plugin executable '{executable}' is outside of the plugin directory");
+		}
+		out.push(executable);
+	}
+
+	Ok(out)
+}
+
+/// Download the executables of a plugin that are hosted next to its manifest. Executables
+/// that are just a name and aren't hosted there are commands on the PATH, so they are skipped
+async fn download_executables<'a>(
+	manifest_url: &str,
+	executables: &[&'a str],
+	client: &Client,
+) -> anyhow::Result<Vec<(&'a str, Vec<u8>)>> {
+	let manifest_url = reqwest::Url::parse(manifest_url).context("Invalid plugin URL")?;
+	let mut out = Vec::new();
+	for executable in executables {
+		let url = manifest_url
+			.join(executable)
+			.with_context(|| format!("Invalid plugin executable '{executable}'"))?;
+		match download::bytes(url, client).await {
+			Ok(data) => out.push((*executable, data.to_vec())),
+			Err(..) if !executable.contains(['/', '\\']) => {}
+			Err(e) => {
+				return Err(e).with_context(|| {
+					format!("Failed to download plugin executable '{executable}'")
+				})
+			}
+		}
+	}
+
+	Ok(out)
+}
+
+/// Write a downloaded plugin executable, allowing it to be run
+fn write_executable(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+	std::fs::write(path, data)?;
+	#[cfg(target_family = "unix")]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+	}
+
+	Ok(())
+}
+
+/// Check that a plugin manifest can be used by this version of MCVM
+fn check_manifest(manifest: &PluginManifest) -> anyhow::Result<()> {
+	if !manifest.is_protocol_supported() {
+		bail!(
+			"Plugin uses protocol version {}, but only versions up to {PROTOCOL_VERSION} are supported",
+			manifest.protocol_version.unwrap_or_default()
+		);
+	}
+
+	Ok(())
+}
+
+/// Recursively copy a directory
+fn copy_dir(src: &Path, dest: &Path) -> anyhow::Result<()> {
+	std::fs::create_dir_all(dest)?;
+	for entry in std::fs::read_dir(src)? {
+		let entry = entry?;
+		let target = dest.join(entry.file_name());
+		if entry.file_type()?.is_dir() {
+			copy_dir(&entry.path(), &target)?;
+		} else {
+			std::fs::copy(entry.path(), target)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// How long to wait for a plugin to describe its subcommands
+const DESCRIBE_SUBCOMMANDS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Cached responses to the describe_subcommands hook, stored in the internal directory
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct SubcommandCache {
+	/// The cached responses for each plugin
+	plugins: HashMap<String, SubcommandCacheEntry>,
+}
+
+/// The cached response of a single plugin
+#[derive(Serialize, Deserialize)]
+struct SubcommandCacheEntry {
+	/// The path and modification time of the executable that gave the response
+	key: String,
+	/// The subcommands that the plugin described
+	subcommands: Vec<SubcommandDescription>,
+}
+
+/// Get the subcommands that the enabled plugins provide, sorted by name. Plugins that handle the
+/// describe_subcommands hook are asked for them, and the answers are cached until the plugin's
+/// executable changes. The subcommands in plugin manifests are included as well.
+/// Plugins that fail to load or answer are only represented by their manifests
+pub fn get_plugin_subcommands(
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<Vec<SubcommandDescription>> {
+	let config = PluginsConfig::open(paths)?;
+	let cache_path = paths.internal.join("plugin_subcommands.json");
+	let mut cache: SubcommandCache = if cache_path.exists() {
+		json_from_file(&cache_path).unwrap_or_default()
+	} else {
+		SubcommandCache::default()
+	};
+	let mut cache_changed = false;
+
+	let mut out = Vec::new();
+	for plugin in config.plugins {
+		if plugin.is_disabled() {
+			continue;
+		}
+		let plugin = plugin.to_config();
+		let Ok((manifest, plugin_dir)) = read_plugin_manifest(&plugin.id, paths) else {
+			continue;
+		};
+		if !manifest.is_protocol_supported() {
+			continue;
+		}
+
+		let described = match manifest.hooks.get(DescribeSubcommands.get_name()) {
+			Some(HookHandler::Constant { constant }) => {
+				serde_json::from_value(constant.clone()).unwrap_or_default()
+			}
+			Some(handler) => {
+				let key = get_executable_key(handler, plugin_dir.as_deref());
+				let cached = cache
+					.plugins
+					.get(&plugin.id)
+					.filter(|x| key.as_ref() == Some(&x.key));
+				if let Some(cached) = cached {
+					cached.subcommands.clone()
+				} else {
+					let id = plugin.id.clone();
+					match describe_plugin_subcommands(plugin, paths, o) {
+						Ok(described) => {
+							if let Some(key) = key {
+								let entry = SubcommandCacheEntry {
+									key,
+									subcommands: described.clone(),
+								};
+								cache.plugins.insert(id, entry);
+								cache_changed = true;
+							}
+							described
+						}
+						Err(e) => {
+							o.display(
+								MessageContents::Warning(format!(
+									"Plugin '{id}' failed to describe its subcommands: {e:#}"
+								)),
+								MessageLevel::Debug,
+							);
+							Vec::new()
+						}
+					}
+				}
+			}
+			None => Vec::new(),
+		};
+
+		out.extend(merge_subcommands(described, &manifest.subcommands));
+	}
+
+	if cache_changed {
+		json_to_file(&cache_path, &cache).context("Failed to write plugin subcommand cache")?;
+	}
+
+	out.sort_by(|a, b| a.name.cmp(&b.name));
+	out.dedup_by(|a, b| a.name == b.name);
+
+	Ok(out)
+}
+
+/// Load a single plugin and call its describe_subcommands hook
+fn describe_plugin_subcommands(
+	plugin: PluginConfig,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<Vec<SubcommandDescription>> {
+	let id = plugin.id.clone();
+	let mut manager = PluginManager::new();
+	manager.load_plugin(plugin, paths, o)?;
+	let handle = manager.call_hook_on_plugin(DescribeSubcommands, &id, &(), paths, o)?;
+	match handle {
+		Some(handle) => handle.result_timeout(DESCRIBE_SUBCOMMANDS_TIMEOUT, o),
+		None => Ok(Vec::new()),
+	}
+}
+
+/// Get the key that a plugin's cached subcommands are stored under, made from the path and
+/// modification time of the executable that handles the hook. Returns None if the executable can't be found
+fn get_executable_key(handler: &HookHandler, plugin_dir: Option<&Path>) -> Option<String> {
+	let HookHandler::Execute { executable, .. } = handler else {
+		return None;
+	};
+	let path = plugin_dir
+		.map(|x| x.join(executable))
+		.filter(|x| x.is_file())
+		.or_else(|| which::which(executable).ok())?;
+	let modified = path.metadata().ok()?.modified().ok()?;
+	let modified = modified.duration_since(UNIX_EPOCH).ok()?;
+
+	Some(format!("{}@{}", path.display(), modified.as_nanos()))
+}
+
+/// Add the subcommands from a plugin manifest to the ones that a plugin described,
+/// skipping the ones that were already described under their name or an alias
+fn merge_subcommands(
+	mut described: Vec<SubcommandDescription>,
+	manifest: &HashMap<String, String>,
+) -> Vec<SubcommandDescription> {
+	let mut missing: Vec<_> = manifest
+		.iter()
+		.filter(|(name, _)| !described.iter().any(|x| x.matches(name)))
+		.map(|(name, about)| SubcommandDescription {
+			name: name.clone(),
+			aliases: Vec::new(),
+			about: about.clone(),
+		})
+		.collect();
+	missing.sort_by(|a, b| a.name.cmp(&b.name));
+	described.extend(missing);
+
+	described
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_set_plugin_enabled() {
+		let mut config = PluginsConfig {
+			plugins: vec![PluginConfigDeser::Simple("stats".into())],
+		};
+		assert!(config.is_enabled("stats"));
+		assert!(!config.is_enabled("backup"));
+
+		config.set_enabled("stats", false);
+		assert!(!config.is_enabled("stats"));
+		config.set_enabled("stats", true);
+		assert!(config.is_enabled("stats"));
+		config.set_enabled("backup", true);
+		assert!(config.is_enabled("backup"));
+		assert_eq!(config.plugins.len(), 2);
+
+		let serialized = serde_json::to_value(&config.plugins[0]).unwrap();
+		assert_eq!(serialized, serde_json::json!({"id": "stats"}));
+	}
+
+	#[test]
+	fn test_merge_subcommands() {
+		let described = vec![SubcommandDescription {
+			name: "backup".into(),
+			aliases: vec!["back".into()],
+			about: "Manage backups".into(),
+		}];
+		let manifest = HashMap::from([
+			("backup".to_string(), "Old description".to_string()),
+			("back".to_string(), "Old description".to_string()),
+			("restore".to_string(), "Restore things".to_string()),
+		]);
+
+		let merged = merge_subcommands(described, &manifest);
+		assert_eq!(merged.len(), 2);
+		assert_eq!(merged[0].about, "Manage backups");
+		assert_eq!(merged[1].name, "restore");
+		assert!(merged[1].aliases.is_empty());
+	}
+
+	#[test]
+	fn test_get_setup_files() {
+		let paths = Paths::new_no_create().unwrap();
+		let mut o = mcvm_shared::output::NoOp;
+		let mut manager = PluginManager::new();
+
+		let setup_hook = || {
+			HashMap::from([(
+				OnInstanceSetup.get_name().to_string(),
+				HookHandler::Constant {
+					constant: serde_json::json!({}),
+				},
+			)])
+		};
+		let plugins = [
+			(
+				"options",
+				setup_hook(),
+				vec![
+					(Side::Client, vec!["options.txt"]),
+					(Side::Server, vec!["server.properties"]),
+				],
+			),
+			(
+				"other",
+				setup_hook(),
+				vec![(Side::Client, vec!["servers.dat", "options.txt"])],
+			),
+			// Without the on_instance_setup hook the files are never written
+			(
+				"no_hook",
+				HashMap::new(),
+				vec![(Side::Client, vec!["config/foo.toml"])],
+			),
+		];
+		for (id, hooks, setup_files) in plugins {
+			let manifest = PluginManifest {
+				hooks,
+				setup_files: setup_files
+					.into_iter()
+					.map(|(side, files)| (side, files.into_iter().map(String::from).collect()))
+					.collect(),
+				..Default::default()
+			};
+			let config = PluginConfig {
+				id: id.into(),
+				custom_config: None,
+			};
+			manager
+				.add_plugin(config, manifest, &paths, None, &mut o)
+				.unwrap();
+		}
+
+		assert_eq!(
+			manager.get_setup_files(Side::Client).unwrap(),
+			vec!["options.txt".to_string(), "servers.dat".to_string()]
+		);
+		assert_eq!(
+			manager.get_setup_files(Side::Server).unwrap(),
+			vec!["server.properties".to_string()]
+		);
+	}
+
+	#[test]
+	fn test_executable_key_changes() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let executable = dir.join("plugin");
+		std::fs::write(&executable, "").unwrap();
+		let handler = HookHandler::Execute {
+			executable: "plugin".into(),
+			args: Vec::new(),
+		};
+
+		let key = get_executable_key(&handler, Some(dir)).unwrap();
+		assert_eq!(get_executable_key(&handler, Some(dir)), Some(key.clone()));
+
+		let file = std::fs::File::options()
+			.write(true)
+			.open(&executable)
+			.unwrap();
+		file.set_modified(UNIX_EPOCH + Duration::from_secs(1000))
+			.unwrap();
+		assert_ne!(get_executable_key(&handler, Some(dir)).unwrap(), key);
+
+		let constant = HookHandler::Constant {
+			constant: serde_json::Value::Null,
+		};
+		assert_eq!(get_executable_key(&constant, Some(dir)), None);
+	}
+
+	#[test]
+	fn test_install_bundled_executables() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let mut paths = Paths::new_no_create().unwrap();
+		paths.plugins = dir.join("plugins");
+		let source = dir.join("source");
+		std::fs::create_dir_all(&source).unwrap();
+		std::fs::write(source.join("plugin"), "plugin").unwrap();
+
+		let install = |manifest: serde_json::Value| {
+			std::fs::write(source.join("test.json"), manifest.to_string()).unwrap();
+			let source = source.join("test.json").to_string_lossy().to_string();
+			tokio::runtime::Runtime::new()
+				.unwrap()
+				.block_on(install_plugin(&source, None, &paths, &Client::new()))
+		};
+
+		// Absolute executables are run from where they are instead of being copied
+		let id = install(serde_json::json!({
+			"hooks": {
+				"on_load": { "executable": "./plugin" },
+				"subcommand": { "executable": "/usr/bin/python3" }
+			}
+		}))
+		.unwrap();
+		let plugin_dir = paths.plugins.join(&id);
+		assert_eq!(
+			std::fs::read_to_string(plugin_dir.join("plugin")).unwrap(),
+			"plugin"
+		);
+		assert!(plugin_dir.join("plugin.json").exists());
+		assert!(!plugin_dir.join("usr").exists());
+
+		std::fs::remove_dir_all(&paths.plugins).unwrap();
+		let err = install(serde_json::json!({
+			"hooks": { "on_load": { "executable": "../plugin" } }
+		}))
+		.unwrap_err();
+		assert!(err.to_string().contains("outside of the plugin directory"));
+		assert!(!paths.plugins.join(&id).exists());
+	}
+}
@@ -1,12 +1,24 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use crate::addon::{AddonConflictPolicy, AddonLinkMode};
+use crate::config::instance::InstanceConfig;
+use crate::config::plugin::PluginManager;
+use crate::io::paths::Paths;
 use crate::pkg::reg::CachingStrategy;
-use crate::pkg::repo::{PkgRepo, PkgRepoLocation};
-use mcvm_core::net::download::validate_url;
+use crate::pkg::repo::{parse_public_key, PkgRepo, PkgRepoLocation};
+use mcvm_core::io::json_from_file;
+use mcvm_core::net::download::{
+	create_client, validate_url, Client, DownloadLimits, ProxySettings, TimeoutSettings,
+};
+use mcvm_core::net::game_files::cache::DEFAULT_METADATA_FRESHNESS;
 
 use anyhow::{bail, Context};
+use mcvm_plugin::hooks::AddPackageRepositories;
 use mcvm_shared::lang::Language;
+use mcvm_shared::output::MCVMOutput;
+use mcvm_shared::util::DefaultExt;
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -18,10 +30,32 @@ pub struct ConfigPreferences {
 	pub package_caching_strategy: CachingStrategy,
 	/// The global language
 	pub language: Language,
+	/// Whether to back up world data before changing an instance's Minecraft version
+	pub snapshot_on_version_change: bool,
+	/// Notifications to send when an instance crashes
+	pub crash_notification: CrashNotificationConfig,
+	/// How addon files are put into instances
+	pub addon_link_mode: AddonLinkMode,
+	/// What to do when addons from different packages have the same file in an instance
+	pub addon_conflict_policy: AddonConflictPolicy,
+	/// The proxy to send requests through
+	pub proxy: Option<ProxySettings>,
+	/// The maximum number of files to download at once
+	pub download_concurrency: Option<usize>,
+	/// The maximum total download speed in bytes per second
+	pub download_rate_limit: Option<u64>,
+	/// Timeouts for network requests
+	pub timeouts: TimeoutSettings,
+	/// How long downloaded game metadata is used before checking for changes
+	pub metadata_freshness: Duration,
+	/// The download size in bytes above which launching asks for confirmation first
+	pub confirm_large_downloads: Option<u64>,
+	/// The user's presets of JVM arguments
+	pub jvm_presets: HashMap<String, Vec<String>>,
 }
 
 /// Deserialization struct for user preferences
-#[derive(Deserialize, Serialize, Default)]
+#[derive(Deserialize, Serialize)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(default)]
 pub struct PrefDeser {
@@ -31,6 +65,144 @@ pub struct PrefDeser {
 	pub package_caching_strategy: CachingStrategy,
 	/// The user's configured language
 	pub language: Language,
+	/// Whether to back up world data before changing an instance's Minecraft version
+	pub snapshot_on_version_change: bool,
+	/// Notifications to send when an instance crashes
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub crash_notification: CrashNotificationConfig,
+	/// How addon files are put into instances
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub addon_link_mode: AddonLinkMode,
+	/// What to do when addons from different packages have the same file in an instance
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub addon_conflict_policy: AddonConflictPolicy,
+	/// The proxy to send requests through
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub proxy: Option<ProxyConfig>,
+	/// The maximum number of files to download at once
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub download_concurrency: Option<usize>,
+	/// The maximum total download speed in bytes per second
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub download_rate_limit: Option<u64>,
+	/// How long to wait for a connection to a server, in seconds
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub connect_timeout: Option<u64>,
+	/// How long to wait for a whole network request, including the download, in seconds
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub request_timeout: Option<u64>,
+	/// How long downloaded game metadata like the version manifest is used before checking for changes, in seconds
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub metadata_freshness: Option<u64>,
+	/// The download size in bytes above which launching an instance asks for confirmation first
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub confirm_large_downloads: Option<u64>,
+	/// Named instance configurations that new instances can be created from
+	#[serde(skip_serializing_if = "HashMap::is_empty")]
+	pub instance_presets: HashMap<String, InstanceConfig>,
+	/// Named presets of JVM arguments that instances can use, in addition to the built-in ones
+	#[serde(skip_serializing_if = "HashMap::is_empty")]
+	pub jvm_presets: HashMap<String, Vec<String>>,
+	/// An absolute path to store data in instead of the default data directory.
+	/// The MCVM_DATA_DIR environment variable takes priority over this
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub data_dir: Option<String>,
+}
+
+impl Default for PrefDeser {
+	fn default() -> Self {
+		Self {
+			repositories: RepositoriesDeser::default(),
+			package_caching_strategy: CachingStrategy::default(),
+			language: Language::default(),
+			snapshot_on_version_change: true,
+			crash_notification: CrashNotificationConfig::default(),
+			addon_link_mode: AddonLinkMode::default(),
+			addon_conflict_policy: AddonConflictPolicy::default(),
+			proxy: None,
+			download_concurrency: None,
+			download_rate_limit: None,
+			connect_timeout: None,
+			request_timeout: None,
+			metadata_freshness: None,
+			confirm_large_downloads: None,
+			instance_presets: HashMap::new(),
+			jvm_presets: HashMap::new(),
+			data_dir: None,
+		}
+	}
+}
+
+impl PrefDeser {
+	/// Get the timeouts for network requests
+	pub fn get_timeouts(&self) -> TimeoutSettings {
+		TimeoutSettings {
+			connect: self
+				.connect_timeout
+				.map_or(TimeoutSettings::DEFAULT_CONNECT, Duration::from_secs),
+			total: self
+				.request_timeout
+				.map_or(TimeoutSettings::DEFAULT_TOTAL, Duration::from_secs),
+		}
+	}
+}
+
+/// Configuration for an HTTP proxy
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct ProxyConfig {
+	/// The URL of the proxy server
+	pub url: String,
+	/// The username to authenticate with the proxy using
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub username: Option<String>,
+	/// The password to authenticate with the proxy using
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub password: Option<String>,
+	/// Hosts that should be connected to directly instead of through the proxy
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+	/// Convert this config to the settings used to create a client
+	pub fn to_settings(&self) -> ProxySettings {
+		ProxySettings {
+			url: self.url.clone(),
+			username: self.username.clone(),
+			password: self.password.clone(),
+			no_proxy: self.no_proxy.clone(),
+		}
+	}
+}
+
+/// Configuration for notifications sent when an instance crashes
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(default)]
+pub struct CrashNotificationConfig {
+	/// A URL to send a POST request with a JSON summary of the crash to
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub webhook: Option<String>,
+	/// An executable to run with a JSON summary of the crash on its standard input
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub command: Option<String>,
+	/// The maximum number of notifications to send for an instance within the window
+	pub max_notifications: u32,
+	/// The length of the rate limiting window in seconds
+	pub window: u64,
+}
+
+impl Default for CrashNotificationConfig {
+	fn default() -> Self {
+		Self {
+			webhook: None,
+			command: None,
+			max_notifications: 3,
+			window: 3600,
+		}
+	}
 }
 
 /// Deserialization struct for a package repo
@@ -48,6 +220,14 @@ pub struct RepoDeser {
 	/// Whether to disable the repo and not add it to the list
 	#[serde(default)]
 	pub disable: bool,
+	/// A hex-encoded ed25519 public key that the index of the repository must be signed with
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub public_key: Option<String>,
+	/// The priority of the repository. Repositories with a higher priority are consulted first,
+	/// and repositories with the same priority are consulted in the order they are listed
+	#[serde(default)]
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	pub priority: i32,
 }
 
 /// Deserialization struct for all configured package repositories
@@ -98,6 +278,32 @@ impl ConfigPreferences {
 			}
 		}
 
+		if let Some(webhook) = &prefs.crash_notification.webhook {
+			validate_url(webhook)
+				.with_context(|| format!("Invalid crash notification webhook URL '{webhook}'"))?;
+		}
+
+		if let Some(proxy) = &prefs.proxy {
+			validate_url(&proxy.url)
+				.with_context(|| format!("Invalid proxy URL '{}'", proxy.url))?;
+		}
+
+		if let Some(data_dir) = &prefs.data_dir {
+			if !Path::new(data_dir).is_absolute() {
+				bail!("The data directory '{data_dir}' must be an absolute path");
+			}
+		}
+
+		if prefs.download_concurrency == Some(0) {
+			bail!("Download concurrency must be at least 1");
+		}
+		if prefs.download_rate_limit == Some(0) {
+			bail!("Download rate limit must be at least 1 byte per second");
+		}
+		if prefs.connect_timeout == Some(0) || prefs.request_timeout == Some(0) {
+			bail!("Network timeouts must be at least 1 second");
+		}
+
 		// Check for duplicate IDs
 		let mut existing = HashSet::new();
 		for repo in &repositories {
@@ -111,10 +317,91 @@ impl ConfigPreferences {
 			Self {
 				package_caching_strategy: prefs.package_caching_strategy.clone(),
 				language: prefs.language,
+				snapshot_on_version_change: prefs.snapshot_on_version_change,
+				crash_notification: prefs.crash_notification.clone(),
+				addon_link_mode: prefs.addon_link_mode,
+				addon_conflict_policy: prefs.addon_conflict_policy,
+				proxy: prefs.proxy.as_ref().map(ProxyConfig::to_settings),
+				download_concurrency: prefs.download_concurrency,
+				download_rate_limit: prefs.download_rate_limit,
+				timeouts: prefs.get_timeouts(),
+				metadata_freshness: prefs
+					.metadata_freshness
+					.map_or(DEFAULT_METADATA_FRESHNESS, Duration::from_secs),
+				confirm_large_downloads: prefs.confirm_large_downloads,
+				jvm_presets: prefs.jvm_presets.clone(),
 			},
 			repositories,
 		))
 	}
+
+	/// Create the client for network requests, which sends them through the configured proxy
+	pub fn create_client(&self) -> anyhow::Result<Client> {
+		create_client(self.proxy.as_ref(), self.timeouts)
+	}
+
+	/// Get the configured download limits, which apply to downloads run with `download::with_limits`
+	pub fn download_limits(&self) -> DownloadLimits {
+		DownloadLimits::new(self.download_concurrency, self.download_rate_limit)
+	}
+}
+
+/// Add the repositories that plugins provide to the end of the list
+pub fn add_plugin_repos(
+	repos: &mut Vec<PkgRepo>,
+	plugins: &PluginManager,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<()> {
+	let results = plugins
+		.call_hook(AddPackageRepositories, &(), paths, o)
+		.context("Failed to call add package repositories hook")?;
+	for result in results {
+		let plugin_id = result.get_id().clone();
+		let result = result.result(o)?;
+		for repo in result {
+			if repos.iter().any(|x| x.id == repo.id) {
+				bail!(
+					"Repository '{}' from plugin '{plugin_id}' has the same ID as an existing repository",
+					repo.id
+				);
+			}
+			let repo = RepoDeser {
+				id: repo.id,
+				url: repo.url,
+				path: repo.path,
+				disable: false,
+				public_key: None,
+				priority: 0,
+			};
+			add_repo(repos, &repo)
+				.with_context(|| format!("Invalid repository from plugin '{plugin_id}'"))?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Read the data directory preference from the config file before the rest of the config is loaded,
+/// since the config is loaded using paths that depend on it. Problems with the file are ignored here
+/// and reported when the whole config is loaded
+pub fn read_data_dir_preference(config_path: &Path) -> Option<PathBuf> {
+	#[derive(Deserialize)]
+	struct PartialConfig {
+		#[serde(default)]
+		preferences: PartialPrefs,
+	}
+
+	#[derive(Deserialize, Default)]
+	struct PartialPrefs {
+		data_dir: Option<PathBuf>,
+	}
+
+	if !config_path.exists() {
+		return None;
+	}
+	let config: PartialConfig = json_from_file(config_path).ok()?;
+	config.preferences.data_dir.filter(|x| x.is_absolute())
 }
 
 /// Add a repo to the list
@@ -129,6 +416,13 @@ fn add_repo(repos: &mut Vec<PkgRepo>, repo: &RepoDeser) -> anyhow::Result<()> {
 	} else {
 		bail!("Niether path nor URL was set for repository {}", repo.id);
 	};
-	repos.push(PkgRepo::new(&repo.id, location));
+	let mut pkg_repo = PkgRepo::new(&repo.id, location);
+	if let Some(key) = &repo.public_key {
+		let key = parse_public_key(key)
+			.with_context(|| format!("Invalid public key for repository '{}'", repo.id))?;
+		pkg_repo.set_public_key(key);
+	}
+	pkg_repo.set_priority(repo.priority);
+	repos.push(pkg_repo);
 	Ok(())
 }
@@ -25,6 +25,11 @@ pub enum UserVariant {
 	Microsoft {},
 	/// A demo user
 	Demo {},
+	/// A user that plays offline without an account
+	Unverified {
+		/// The username to play as
+		username: String,
+	},
 	/// An unknown user
 	#[cfg_attr(not(feature = "schema"), serde(untagged))]
 	Unknown(String),
@@ -35,6 +40,7 @@ impl UserVariant {
 		match self {
 			Self::Microsoft { .. } => UserKind::Microsoft { xbox_uid: None },
 			Self::Demo { .. } => UserKind::Demo,
+			Self::Unverified { .. } => UserKind::Unverified,
 			Self::Unknown(id) => UserKind::Unknown(id.clone()),
 		}
 	}
@@ -43,7 +49,12 @@ impl UserVariant {
 impl UserConfig {
 	/// Creates a user from this user config
 	pub fn to_user(&self, id: &str) -> User {
-		User::new(self.variant.to_user_kind(), id.into())
+		let mut user = User::new(self.variant.to_user_kind(), id.into());
+		if let UserVariant::Unverified { username } = &self.variant {
+			user.set_name(username);
+		}
+
+		user
 	}
 }
 
@@ -52,6 +63,7 @@ impl Display for UserVariant {
 		match self {
 			Self::Microsoft {} => write!(f, "microsoft"),
 			Self::Demo {} => write!(f, "demo"),
+			Self::Unverified { .. } => write!(f, "unverified"),
 			Self::Unknown(other) => write!(f, "{other}"),
 		}
 	}
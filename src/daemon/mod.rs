@@ -0,0 +1,31 @@
+/// Messages sent between the daemon and its clients
+pub mod protocol;
+/// Reloading the config of a running daemon
+pub mod reload;
+/// Restart policies and crash loop backoff
+pub mod restart;
+/// Cron schedules for running actions on instances
+pub mod schedule;
+
+use std::path::PathBuf;
+
+use crate::io::paths::Paths;
+
+/// Get the path to the socket that the daemon listens on.
+/// On Windows, this is the name of a named pipe
+pub fn get_socket_path(paths: &Paths) -> PathBuf {
+	#[cfg(windows)]
+	{
+		let _ = paths;
+		PathBuf::from(r"\\.\pipe\mcvm-daemon")
+	}
+	#[cfg(not(windows))]
+	{
+		paths.run.join("daemon.sock")
+	}
+}
+
+/// Get the directory where the logs for supervised instances are stored
+pub fn get_log_dir(paths: &Paths) -> PathBuf {
+	paths.logs.join("daemon")
+}
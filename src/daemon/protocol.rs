@@ -0,0 +1,189 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use mcvm_shared::id::InstanceID;
+use serde::{Deserialize, Serialize};
+
+use super::reload::DaemonConfigDiff;
+
+/// A request sent to the daemon. Requests and responses are sent as single lines of JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum DaemonRequest {
+	/// Get the status of all supervised instances
+	Status,
+	/// Start a supervised instance
+	Start {
+		/// The instance to start
+		instance: InstanceID,
+	},
+	/// Stop a supervised instance without restarting it
+	Stop {
+		/// The instance to stop
+		instance: InstanceID,
+	},
+	/// Stop and start a supervised instance
+	Restart {
+		/// The instance to restart
+		instance: InstanceID,
+	},
+	/// Get the most recent lines of output from a supervised instance
+	TailLog {
+		/// The instance to get the log of
+		instance: InstanceID,
+		/// The number of lines to get
+		lines: usize,
+	},
+	/// Get the status of all schedules
+	Schedules,
+	/// Load the config again and apply the changes to supervised instances and schedules
+	Reload,
+}
+
+/// A response from the daemon
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum DaemonResponse {
+	/// The request succeeded
+	Ok,
+	/// The status of all supervised instances
+	Status {
+		/// The status of each instance
+		instances: Vec<InstanceStatus>,
+	},
+	/// Lines from an instance's log
+	Log {
+		/// The lines of the log, oldest first
+		lines: Vec<String>,
+	},
+	/// The status of all schedules
+	Schedules {
+		/// The status of each schedule
+		schedules: Vec<ScheduleStatus>,
+	},
+	/// The config was reloaded
+	Reloaded {
+		/// The changes that were applied
+		diff: DaemonConfigDiff,
+	},
+	/// The request failed
+	Error {
+		/// The error message
+		message: String,
+	},
+}
+
+/// The status of a supervised instance
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstanceStatus {
+	/// The ID of the instance
+	pub id: InstanceID,
+	/// The current state of the instance
+	pub state: InstanceState,
+	/// The PID of the instance process, if it is running
+	pub pid: Option<u32>,
+	/// The number of times the instance has been restarted by the daemon
+	pub restarts: u32,
+	/// The number of times the instance has crashed in a row
+	pub consecutive_failures: u32,
+	/// Whether the instance is crashing repeatedly
+	pub crash_looping: bool,
+	/// The exit code of the last time the instance stopped
+	pub last_exit_code: Option<i32>,
+	/// The number of seconds until the instance is restarted, if it is waiting
+	pub restart_in: Option<u64>,
+	/// The last error that happened when launching the instance
+	pub last_error: Option<String>,
+}
+
+/// The status of a schedule
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleStatus {
+	/// The ID of the schedule
+	pub id: String,
+	/// The instance the schedule runs on
+	pub instance: InstanceID,
+	/// A description of the action the schedule runs
+	pub action: String,
+	/// The cron expression of the schedule
+	pub cron: String,
+	/// The next time the schedule will run
+	pub next_run: Option<DateTime<Utc>>,
+	/// The last time the schedule ran
+	pub last_run: Option<DateTime<Utc>>,
+	/// Whether the last run of the schedule is still in progress
+	pub running: bool,
+	/// The error from the last run, if it failed
+	pub last_error: Option<String>,
+}
+
+/// The state of a supervised instance
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceState {
+	/// The instance is running
+	Running,
+	/// The instance has been asked to stop and is shutting down
+	Stopping,
+	/// The instance has exited and is waiting to be restarted
+	WaitingToRestart,
+	/// The instance is not running and will not be restarted
+	Stopped,
+}
+
+impl DaemonRequest {
+	/// Serialize this request as a line
+	pub fn to_line(&self) -> anyhow::Result<String> {
+		to_line(self)
+	}
+
+	/// Deserialize a request from a line
+	pub fn from_line(line: &str) -> anyhow::Result<Self> {
+		serde_json::from_str(line.trim_end()).context("Failed to deserialize daemon request")
+	}
+}
+
+impl DaemonResponse {
+	/// Serialize this response as a line
+	pub fn to_line(&self) -> anyhow::Result<String> {
+		to_line(self)
+	}
+
+	/// Deserialize a response from a line
+	pub fn from_line(line: &str) -> anyhow::Result<Self> {
+		serde_json::from_str(line.trim_end()).context("Failed to deserialize daemon response")
+	}
+
+	/// Create an error response
+	pub fn error(error: anyhow::Error) -> Self {
+		Self::Error {
+			message: format!("{error:?}"),
+		}
+	}
+}
+
+/// Serialize a message as a single line of JSON
+fn to_line(message: &impl Serialize) -> anyhow::Result<String> {
+	let mut out = serde_json::to_string(message).context("Failed to serialize daemon message")?;
+	out.push('\n');
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_request_round_trip() {
+		let request = DaemonRequest::TailLog {
+			instance: "server".into(),
+			lines: 20,
+		};
+		let line = request.to_line().unwrap();
+		assert!(line.ends_with('\n'));
+		assert_eq!(line.matches('\n').count(), 1);
+		assert_eq!(DaemonRequest::from_line(&line).unwrap(), request);
+
+		let request = DaemonRequest::from_line(r#"{"command": "status"}"#).unwrap();
+		assert_eq!(request, DaemonRequest::Status);
+	}
+}
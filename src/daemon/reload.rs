@@ -0,0 +1,194 @@
+use mcvm_shared::id::InstanceID;
+use serde::{Deserialize, Serialize};
+
+use crate::config::daemon::DaemonConfig;
+
+/// The changes between the daemon config that is running and a newly loaded one
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaemonConfigDiff {
+	/// Instances that are now supervised
+	pub added_instances: Vec<InstanceID>,
+	/// Instances that are no longer supervised
+	pub removed_instances: Vec<InstanceID>,
+	/// Supervised instances whose restart policy or other supervision settings changed
+	pub changed_instances: Vec<InstanceID>,
+	/// Schedules that were added
+	pub added_schedules: Vec<String>,
+	/// Schedules that were removed
+	pub removed_schedules: Vec<String>,
+	/// Schedules whose cron expression, instance, action, or time zone changed
+	pub changed_schedules: Vec<String>,
+}
+
+impl DaemonConfigDiff {
+	/// Check whether nothing changed
+	pub fn is_empty(&self) -> bool {
+		self.added_instances.is_empty()
+			&& self.removed_instances.is_empty()
+			&& self.changed_instances.is_empty()
+			&& self.added_schedules.is_empty()
+			&& self.removed_schedules.is_empty()
+			&& self.changed_schedules.is_empty()
+	}
+}
+
+/// Find the supervised instances and schedules that were added, removed, or changed
+/// between two daemon configs. Every list in the result is sorted
+pub fn diff_daemon_configs(old: &DaemonConfig, new: &DaemonConfig) -> DaemonConfigDiff {
+	let mut out = DaemonConfigDiff::default();
+
+	for (id, config) in &new.instances {
+		match old.instances.get(id) {
+			Some(old_config) if old_config != config => out.changed_instances.push(id.clone()),
+			Some(..) => {}
+			None => out.added_instances.push(id.clone()),
+		}
+	}
+	out.removed_instances = old
+		.instances
+		.keys()
+		.filter(|x| !new.instances.contains_key(*x))
+		.cloned()
+		.collect();
+
+	for (id, config) in &new.schedules {
+		match old.schedules.get(id) {
+			Some(old_config) if old_config != config => out.changed_schedules.push(id.clone()),
+			Some(..) => {}
+			None => out.added_schedules.push(id.clone()),
+		}
+	}
+	out.removed_schedules = old
+		.schedules
+		.keys()
+		.filter(|x| !new.schedules.contains_key(*x))
+		.cloned()
+		.collect();
+
+	out.added_instances.sort();
+	out.removed_instances.sort();
+	out.changed_instances.sort();
+	out.added_schedules.sort();
+	out.removed_schedules.sort();
+	out.changed_schedules.sort();
+
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::config::daemon::{
+		RestartPolicy, ScheduleAction, ScheduleConfig, SupervisedInstanceConfig,
+	};
+
+	use super::*;
+
+	fn config(instances: &[(&str, RestartPolicy)], schedules: &[(&str, &str)]) -> DaemonConfig {
+		DaemonConfig {
+			instances: instances
+				.iter()
+				.map(|(id, restart)| {
+					let config = SupervisedInstanceConfig {
+						restart: *restart,
+						..Default::default()
+					};
+					(InstanceID::from(*id), config)
+				})
+				.collect(),
+			schedules: schedules
+				.iter()
+				.map(|(id, cron)| {
+					let config = ScheduleConfig {
+						cron: cron.to_string(),
+						instance: "server".into(),
+						action: ScheduleAction::Restart,
+						timezone: None,
+					};
+					(id.to_string(), config)
+				})
+				.collect(),
+		}
+	}
+
+	#[test]
+	fn test_unchanged_config() {
+		let config = config(
+			&[("server", RestartPolicy::Always)],
+			&[("nightly", "0 4 * * *")],
+		);
+		assert!(diff_daemon_configs(&config, &config.clone()).is_empty());
+		assert!(diff_daemon_configs(&DaemonConfig::default(), &DaemonConfig::default()).is_empty());
+	}
+
+	#[test]
+	fn test_instance_diff() {
+		let old = config(
+			&[
+				("server", RestartPolicy::Always),
+				("lobby", RestartPolicy::OnFailure),
+				("old", RestartPolicy::Never),
+			],
+			&[],
+		);
+		let new = config(
+			&[
+				("server", RestartPolicy::Always),
+				("lobby", RestartPolicy::Never),
+				("new2", RestartPolicy::Always),
+				("new1", RestartPolicy::Always),
+			],
+			&[],
+		);
+
+		let diff = diff_daemon_configs(&old, &new);
+		assert_eq!(
+			diff,
+			DaemonConfigDiff {
+				added_instances: vec!["new1".into(), "new2".into()],
+				removed_instances: vec!["old".into()],
+				changed_instances: vec!["lobby".into()],
+				..Default::default()
+			}
+		);
+	}
+
+	#[test]
+	fn test_schedule_diff() {
+		let old = config(
+			&[("server", RestartPolicy::Always)],
+			&[("nightly", "0 4 * * *"), ("hourly", "@hourly")],
+		);
+		let mut new = config(
+			&[("server", RestartPolicy::Always)],
+			&[("nightly", "0 5 * * *"), ("weekly", "@weekly")],
+		);
+
+		let diff = diff_daemon_configs(&old, &new);
+		assert_eq!(
+			diff,
+			DaemonConfigDiff {
+				added_schedules: vec!["weekly".into()],
+				removed_schedules: vec!["hourly".into()],
+				changed_schedules: vec!["nightly".into()],
+				..Default::default()
+			}
+		);
+
+		// Changing only the time zone is still a change
+		new.schedules.get_mut("weekly").unwrap().timezone = Some("UTC".into());
+		let diff = diff_daemon_configs(&config(&[], &[("weekly", "@weekly")]), &new);
+		assert_eq!(diff.changed_schedules, vec!["weekly".to_string()]);
+		assert_eq!(diff.added_instances, vec![InstanceID::from("server")]);
+	}
+
+	#[test]
+	fn test_diff_serialization() {
+		let diff = diff_daemon_configs(
+			&DaemonConfig::default(),
+			&config(&[("server", RestartPolicy::Always)], &[]),
+		);
+		let json = serde_json::to_string(&diff).unwrap();
+		let deserialized: DaemonConfigDiff = serde_json::from_str(&json).unwrap();
+		assert_eq!(deserialized, diff);
+	}
+}
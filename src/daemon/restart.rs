@@ -0,0 +1,205 @@
+use std::time::{Duration, Instant};
+
+use crate::config::daemon::{RestartPolicy, SupervisedInstanceConfig};
+
+/// The number of failures in a row after which an instance is considered to be crash looping
+pub const CRASH_LOOP_THRESHOLD: u32 = 3;
+
+/// Keeps track of restarts for a supervised instance and decides when it should be restarted
+#[derive(Debug, Clone)]
+pub struct RestartTracker {
+	config: SupervisedInstanceConfig,
+	/// When the instance was last started
+	started_at: Option<Instant>,
+	/// When the instance should be restarted next
+	next_restart: Option<Instant>,
+	/// The number of failures in a row
+	consecutive_failures: u32,
+	/// The total number of restarts done by the tracker
+	restarts: u32,
+}
+
+impl RestartTracker {
+	/// Create a new RestartTracker
+	pub fn new(config: SupervisedInstanceConfig) -> Self {
+		Self {
+			config,
+			started_at: None,
+			next_restart: None,
+			consecutive_failures: 0,
+			restarts: 0,
+		}
+	}
+
+	/// Record that the instance has been started
+	pub fn on_start(&mut self, now: Instant) {
+		if self.next_restart.take().is_some() {
+			self.restarts += 1;
+		}
+		self.started_at = Some(now);
+	}
+
+	/// Record that the instance failed to launch at all
+	pub fn on_launch_failure(&mut self, now: Instant) {
+		self.on_exit(now, false, false);
+	}
+
+	/// Record that the instance has exited, scheduling a restart if needed.
+	/// Returns the delay until the restart, if there is one
+	pub fn on_exit(&mut self, now: Instant, success: bool, requested: bool) -> Option<Duration> {
+		let started_at = self.started_at.take();
+		self.next_restart = None;
+		if requested {
+			self.consecutive_failures = 0;
+			return None;
+		}
+
+		// Crashes only count towards backoff if the instance didn't stay up for long
+		let was_stable = started_at
+			.is_some_and(|x| now.duration_since(x) >= Duration::from_secs(self.config.stable_time));
+		if success || was_stable {
+			self.consecutive_failures = 0;
+		}
+		if !success {
+			self.consecutive_failures += 1;
+		}
+
+		let should_restart = match self.config.restart {
+			RestartPolicy::Always => true,
+			RestartPolicy::OnFailure => !success,
+			RestartPolicy::Never => false,
+		};
+		if !should_restart {
+			return None;
+		}
+
+		let delay = self.get_backoff();
+		self.next_restart = Some(now + delay);
+		Some(delay)
+	}
+
+	/// Replace the config of the tracker, keeping the restart and failure counts.
+	/// A scheduled restart is cancelled if the new policy never restarts
+	pub fn set_config(&mut self, config: SupervisedInstanceConfig) {
+		if config.restart == RestartPolicy::Never {
+			self.next_restart = None;
+		}
+		self.config = config;
+	}
+
+	/// Cancel any scheduled restart
+	pub fn cancel_restart(&mut self) {
+		self.next_restart = None;
+	}
+
+	/// Check whether the instance is due to be restarted
+	pub fn should_restart(&self, now: Instant) -> bool {
+		self.next_restart.is_some_and(|x| now >= x)
+	}
+
+	/// Get the time until the next restart, if one is scheduled
+	pub fn get_time_until_restart(&self, now: Instant) -> Option<Duration> {
+		self.next_restart.map(|x| x.saturating_duration_since(now))
+	}
+
+	/// Get the delay before the next restart, which doubles with each failure in a row
+	pub fn get_backoff(&self) -> Duration {
+		let exponent = self.consecutive_failures.saturating_sub(1).min(32);
+		let backoff = self
+			.config
+			.initial_backoff
+			.saturating_mul(2u64.saturating_pow(exponent));
+		Duration::from_secs(backoff.min(self.config.max_backoff))
+	}
+
+	/// Get the number of failures in a row
+	pub fn get_consecutive_failures(&self) -> u32 {
+		self.consecutive_failures
+	}
+
+	/// Get the total number of restarts
+	pub fn get_restarts(&self) -> u32 {
+		self.restarts
+	}
+
+	/// Check whether the instance is crashing repeatedly
+	pub fn is_crash_looping(&self) -> bool {
+		self.consecutive_failures >= CRASH_LOOP_THRESHOLD
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn config(restart: RestartPolicy) -> SupervisedInstanceConfig {
+		SupervisedInstanceConfig {
+			restart,
+			initial_backoff: 1,
+			max_backoff: 10,
+			stable_time: 60,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_exponential_backoff() {
+		let mut tracker = RestartTracker::new(config(RestartPolicy::OnFailure));
+		let mut now = Instant::now();
+		let mut delays = Vec::new();
+		for _ in 0..6 {
+			tracker.on_start(now);
+			now += Duration::from_secs(1);
+			delays.push(tracker.on_exit(now, false, false).unwrap().as_secs());
+			now += Duration::from_secs(30);
+			assert!(tracker.should_restart(now));
+		}
+		assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+		assert!(tracker.is_crash_looping());
+		assert_eq!(tracker.get_restarts(), 5);
+
+		// Staying up for a while resets the backoff
+		tracker.on_start(now);
+		now += Duration::from_secs(120);
+		assert_eq!(tracker.on_exit(now, false, false).unwrap().as_secs(), 1);
+		assert!(!tracker.is_crash_looping());
+	}
+
+	#[test]
+	fn test_restart_policies() {
+		let now = Instant::now();
+
+		let mut tracker = RestartTracker::new(config(RestartPolicy::OnFailure));
+		tracker.on_start(now);
+		assert!(tracker.on_exit(now, true, false).is_none());
+
+		let mut tracker = RestartTracker::new(config(RestartPolicy::Always));
+		tracker.on_start(now);
+		assert!(tracker.on_exit(now, true, false).is_some());
+		tracker.on_start(now);
+		assert!(tracker.on_exit(now, false, true).is_none());
+
+		let mut tracker = RestartTracker::new(config(RestartPolicy::Never));
+		tracker.on_start(now);
+		assert!(tracker.on_exit(now, false, false).is_none());
+		assert_eq!(tracker.get_consecutive_failures(), 1);
+	}
+
+	#[test]
+	fn test_set_config() {
+		let now = Instant::now();
+		let mut tracker = RestartTracker::new(config(RestartPolicy::Always));
+		tracker.on_start(now);
+		assert!(tracker.on_exit(now, false, false).is_some());
+		tracker.set_config(SupervisedInstanceConfig {
+			initial_backoff: 5,
+			..config(RestartPolicy::Always)
+		});
+		assert!(tracker.should_restart(now + Duration::from_secs(1)));
+		assert_eq!(tracker.get_backoff().as_secs(), 5);
+		assert_eq!(tracker.get_consecutive_failures(), 1);
+
+		tracker.set_config(config(RestartPolicy::Never));
+		assert!(!tracker.should_restart(now + Duration::from_secs(1)));
+	}
+}
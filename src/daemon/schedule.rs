@@ -0,0 +1,248 @@
+use anyhow::{bail, Context};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use croner::Cron;
+
+use crate::config::daemon::ScheduleConfig;
+
+/// How many years ahead to search for a matching time before giving up
+const MAX_SEARCH_YEARS: i64 = 5;
+
+/// A schedule that has been parsed from its config
+#[derive(Debug, Clone)]
+pub struct Schedule {
+	cron: Cron,
+	timezone: ScheduleTimezone,
+}
+
+impl Schedule {
+	/// Parse a schedule from its config
+	pub fn from_config(config: &ScheduleConfig) -> anyhow::Result<Self> {
+		let cron = parse_cron(&config.cron).context("Failed to parse cron expression")?;
+		let timezone = match &config.timezone {
+			Some(timezone) => ScheduleTimezone::parse(timezone)?,
+			None => ScheduleTimezone::Local,
+		};
+
+		Ok(Self { cron, timezone })
+	}
+
+	/// Get the next time that the schedule will run, strictly after the given time
+	pub fn next_run(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+		match self.timezone {
+			ScheduleTimezone::Local => next_run_in(&self.cron, &Local, after),
+			ScheduleTimezone::Fixed(offset) => next_run_in(&self.cron, &offset, after),
+			ScheduleTimezone::Named(tz) => next_run_in(&self.cron, &tz, after),
+		}
+	}
+}
+
+/// Parse a standard five-field cron expression: minute, hour, day of month, month, and day of week.
+/// Shorthands like `@daily` are also supported
+pub fn parse_cron(string: &str) -> anyhow::Result<Cron> {
+	let fields = string.split_whitespace().count();
+	if !string.trim().starts_with('@') && fields != 5 {
+		bail!("Cron expression must have 5 fields, but it has {fields}");
+	}
+
+	Cron::new(string)
+		.parse()
+		.map_err(|e| anyhow::anyhow!("{e}"))
+}
+
+/// Get the next run of a cron expression when evaluated in a time zone
+fn next_run_in<Tz: TimeZone>(cron: &Cron, tz: &Tz, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+	let mut local = after.with_timezone(tz).naive_local();
+	let limit = local + chrono::Duration::days(366 * MAX_SEARCH_YEARS);
+	loop {
+		let next = next_local_run(cron, local)?;
+		if next > limit {
+			return None;
+		}
+		// Times that are skipped over by daylight saving changes don't exist, so we move past them
+		if let Some(time) = tz.from_local_datetime(&next).earliest() {
+			return Some(time.with_timezone(&Utc));
+		}
+		local = next;
+	}
+}
+
+/// Get the next matching wall clock time strictly after the given one. The search is done
+/// without a time zone so that daylight saving changes can be handled by the caller
+fn next_local_run(cron: &Cron, after: NaiveDateTime) -> Option<NaiveDateTime> {
+	cron.find_next_occurrence(&Utc.from_utc_datetime(&after), false)
+		.ok()
+		.map(|x| x.naive_utc())
+}
+
+/// The time zone that a schedule is evaluated in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleTimezone {
+	/// The local time zone of the system
+	Local,
+	/// A fixed offset from UTC
+	Fixed(FixedOffset),
+	/// A named time zone from the IANA database, such as `Europe/Berlin`
+	Named(chrono_tz::Tz),
+}
+
+impl ScheduleTimezone {
+	/// Parse a time zone. Accepts `local`, `UTC`, an offset like `+02:00`,
+	/// or a name like `Europe/Berlin`
+	pub fn parse(string: &str) -> anyhow::Result<Self> {
+		match string.to_lowercase().as_str() {
+			"local" => return Ok(Self::Local),
+			"utc" | "z" => {
+				return Ok(Self::Fixed(
+					FixedOffset::east_opt(0).expect("Offset is valid"),
+				))
+			}
+			_ => {}
+		}
+
+		let (sign, rest) = if let Some(rest) = string.strip_prefix('+') {
+			(1, rest)
+		} else if let Some(rest) = string.strip_prefix('-') {
+			(-1, rest)
+		} else {
+			let tz = string.parse().map_err(|_| {
+				anyhow::anyhow!("Time zone '{string}' must be 'local', 'UTC', an offset like '+02:00', or a name like 'Europe/Berlin'")
+			})?;
+			return Ok(Self::Named(tz));
+		};
+		let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+		let hours: i32 = hours
+			.parse()
+			.with_context(|| format!("Invalid hours in time zone offset '{string}'"))?;
+		let minutes: i32 = minutes
+			.parse()
+			.with_context(|| format!("Invalid minutes in time zone offset '{string}'"))?;
+		if minutes >= 60 {
+			bail!("Invalid minutes in time zone offset '{string}'");
+		}
+		let offset = FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+			.with_context(|| format!("Time zone offset '{string}' is out of range"))?;
+
+		Ok(Self::Fixed(offset))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn time(string: &str) -> NaiveDateTime {
+		NaiveDateTime::parse_from_str(string, "%Y-%m-%d %H:%M").unwrap()
+	}
+
+	fn next(cron: &str, after: &str) -> Option<NaiveDateTime> {
+		next_local_run(&parse_cron(cron).unwrap(), time(after))
+	}
+
+	#[test]
+	fn test_cron_next_after() {
+		assert_eq!(
+			next("0 */6 * * *", "2024-05-01 05:59"),
+			Some(time("2024-05-01 06:00"))
+		);
+		assert_eq!(
+			next("0 */6 * * *", "2024-05-01 06:00"),
+			Some(time("2024-05-01 12:00"))
+		);
+		assert_eq!(
+			next("0 4 * * *", "2024-12-31 04:30"),
+			Some(time("2025-01-01 04:00"))
+		);
+		assert_eq!(
+			next("30 2 * * mon-fri", "2024-05-03 03:00"),
+			Some(time("2024-05-06 02:30"))
+		);
+		assert_eq!(
+			next("0 0 29 feb *", "2024-03-01 00:00"),
+			Some(time("2028-02-29 00:00"))
+		);
+		assert_eq!(
+			next("@weekly", "2024-05-01 00:00"),
+			Some(time("2024-05-05 00:00"))
+		);
+	}
+
+	#[test]
+	fn test_cron_day_fields() {
+		// When both day fields are set, either one matching is enough
+		assert_eq!(
+			next("0 0 15 * sun", "2024-05-01 00:00"),
+			Some(time("2024-05-05 00:00"))
+		);
+		// Sunday can be written as 7
+		assert_eq!(
+			next("0 0 * * 7", "2024-05-01 00:00"),
+			Some(time("2024-05-05 00:00"))
+		);
+	}
+
+	#[test]
+	fn test_cron_parse_errors() {
+		assert!(parse_cron("* * * *").is_err());
+		assert!(parse_cron("60 * * * *").is_err());
+		assert!(parse_cron("*/0 * * * *").is_err());
+		assert!(parse_cron("5-1 * * * *").is_err());
+		assert!(parse_cron("* * * foo *").is_err());
+	}
+
+	#[test]
+	fn test_timezone_override() {
+		let schedule = Schedule {
+			cron: parse_cron("0 4 * * *").unwrap(),
+			timezone: ScheduleTimezone::parse("+02:00").unwrap(),
+		};
+		let after = Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+		assert_eq!(
+			schedule.next_run(after),
+			Some(Utc.with_ymd_and_hms(2024, 5, 1, 2, 0, 0).unwrap())
+		);
+		assert!(ScheduleTimezone::parse("Mars/Olympus_Mons").is_err());
+		assert_eq!(
+			ScheduleTimezone::parse("local").unwrap(),
+			ScheduleTimezone::Local
+		);
+	}
+
+	#[test]
+	fn test_named_timezone() {
+		let schedule = Schedule {
+			cron: parse_cron("0 4 * * *").unwrap(),
+			timezone: ScheduleTimezone::parse("Europe/Berlin").unwrap(),
+		};
+		// Berlin is on summer time in May and standard time in December
+		let after = Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+		assert_eq!(
+			schedule.next_run(after),
+			Some(Utc.with_ymd_and_hms(2024, 5, 1, 2, 0, 0).unwrap())
+		);
+		let after = Utc.with_ymd_and_hms(2024, 12, 1, 0, 0, 0).unwrap();
+		assert_eq!(
+			schedule.next_run(after),
+			Some(Utc.with_ymd_and_hms(2024, 12, 1, 3, 0, 0).unwrap())
+		);
+
+		// 02:30 doesn't exist on the day that summer time starts, so that day is skipped
+		let schedule = Schedule {
+			cron: parse_cron("30 2 * * *").unwrap(),
+			timezone: ScheduleTimezone::parse("Europe/Berlin").unwrap(),
+		};
+		let after = Utc.with_ymd_and_hms(2024, 3, 30, 12, 0, 0).unwrap();
+		assert_eq!(
+			schedule.next_run(after),
+			Some(Utc.with_ymd_and_hms(2024, 4, 1, 0, 30, 0).unwrap())
+		);
+	}
+
+	#[test]
+	fn test_impossible_schedule() {
+		let schedule = Schedule {
+			cron: parse_cron("0 0 30 2 *").unwrap(),
+			timezone: ScheduleTimezone::parse("UTC").unwrap(),
+		};
+		assert_eq!(schedule.next_run(Utc::now()), None);
+	}
+}
@@ -0,0 +1,462 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use mcvm_core::io::persistent::PersistentData;
+use mcvm_plugin::hooks::Ping;
+use mcvm_shared::output::{MCVMOutput, Message, MessageContents, MessageLevel};
+use serde::Serialize;
+
+use crate::config::plugin::{PluginConfig, PluginManager, PluginsConfig};
+use crate::config::Config;
+use crate::instance::running::find_dead_records;
+use crate::io::lock::Lockfile;
+use crate::io::paths::Paths;
+use crate::pkg::repo::PkgRepoLocation;
+
+/// Services that are checked by the network check
+pub const NETWORK_ENDPOINTS: &[(&str, &str)] = &[
+	(
+		"Mojang",
+		"https://piston-meta.mojang.com/mc/game/version_manifest_v2.json",
+	),
+	("Mojang Assets", "https://resources.download.minecraft.net/"),
+	("Microsoft Login", "https://login.microsoftonline.com/"),
+	("Minecraft Services", "https://api.minecraftservices.com/"),
+	("Modrinth", "https://api.modrinth.com/v2"),
+	("Paper", "https://api.papermc.io/v2/projects"),
+	("Fabric", "https://meta.fabricmc.net/v2/versions"),
+];
+/// The free disk space below which the disk space check fails
+pub const MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+/// How long a plugin has to respond to the ping hook
+pub const PLUGIN_PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome of a check
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+	/// Nothing is wrong
+	Pass,
+	/// Something might cause problems
+	Warn,
+	/// Something is broken
+	Fail,
+}
+
+/// The result of a single check
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+	/// The category of the check, like `java` or `network`
+	pub check: String,
+	/// What was checked, like the name of a directory or plugin
+	pub name: String,
+	/// The outcome of the check
+	pub status: CheckStatus,
+	/// Details about the outcome
+	pub message: String,
+}
+
+impl CheckResult {
+	/// Create a new check result
+	pub fn new(
+		check: &str,
+		name: impl Into<String>,
+		status: CheckStatus,
+		message: impl Into<String>,
+	) -> Self {
+		Self {
+			check: check.into(),
+			name: name.into(),
+			status,
+			message: message.into(),
+		}
+	}
+}
+
+/// Run all of the checks that don't need the network
+pub fn run_local_checks(paths: &Paths) -> Vec<CheckResult> {
+	let mut out = Vec::new();
+	out.extend(check_directories(paths));
+	out.extend(check_disk_space(paths));
+	out.extend(check_config(paths));
+	out.extend(check_lockfile(paths));
+	out.extend(check_running_records(paths));
+	out.extend(check_java(paths));
+	out.extend(check_plugins(paths));
+
+	out
+}
+
+/// Check that the directories that MCVM uses are writable
+pub fn check_directories(paths: &Paths) -> Vec<CheckResult> {
+	let dirs = [
+		("Data", paths.data.as_path()),
+		("Cache", paths.cache.as_path()),
+		("Config", paths.project.config_dir()),
+		("Internal", paths.internal.as_path()),
+	];
+
+	let mut out = Vec::new();
+	for (name, dir) in dirs {
+		let result = if !dir.exists() {
+			CheckResult::new(
+				"directories",
+				name,
+				CheckStatus::Warn,
+				format!("{} does not exist yet", dir.display()),
+			)
+		} else {
+			let test_file = dir.join(".mcvm_doctor");
+			let result = fs::write(&test_file, "").and_then(|_| fs::remove_file(&test_file));
+			match result {
+				Ok(()) => CheckResult::new(
+					"directories",
+					name,
+					CheckStatus::Pass,
+					dir.display().to_string(),
+				),
+				Err(e) => CheckResult::new(
+					"directories",
+					name,
+					CheckStatus::Fail,
+					format!("{} is not writable: {e}", dir.display()),
+				),
+			}
+		};
+		out.push(result);
+	}
+
+	if paths.core.legacy_layout {
+		out.push(CheckResult::new(
+			"directories",
+			"Layout",
+			CheckStatus::Warn,
+			"Downloaded files are stored in the old layout. Run `mcvm files migrate-layout` to move them to the cache directory",
+		));
+	}
+
+	out
+}
+
+/// Check that there is enough free space on the disk of the data directory
+pub fn check_disk_space(paths: &Paths) -> Vec<CheckResult> {
+	let result = match get_free_space(&paths.data) {
+		Some(free) if free < MIN_FREE_SPACE => CheckResult::new(
+			"disk_space",
+			"Data",
+			CheckStatus::Fail,
+			format!(
+				"Only {} MiB free, which is less than {} MiB",
+				free / 1024 / 1024,
+				MIN_FREE_SPACE / 1024 / 1024
+			),
+		),
+		Some(free) => CheckResult::new(
+			"disk_space",
+			"Data",
+			CheckStatus::Pass,
+			format!("{} MiB free", free / 1024 / 1024),
+		),
+		None => CheckResult::new(
+			"disk_space",
+			"Data",
+			CheckStatus::Warn,
+			"Could not get the free disk space",
+		),
+	};
+
+	vec![result]
+}
+
+/// Get the free space in bytes on the disk containing a path, using df
+fn get_free_space(path: &Path) -> Option<u64> {
+	if cfg!(target_family = "windows") {
+		return None;
+	}
+	// The directory may not exist yet, so use the closest parent that does
+	let path = path.ancestors().find(|x| x.exists())?;
+	let output = Command::new("df").arg("-Pk").arg(path).output().ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	parse_df_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Get the available bytes from the POSIX output of `df -Pk`
+fn parse_df_output(output: &str) -> Option<u64> {
+	let line = output.lines().nth(1)?;
+	let available: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+	Some(available * 1024)
+}
+
+/// Check that the config can be loaded without errors or warnings
+pub fn check_config(paths: &Paths) -> Vec<CheckResult> {
+	let mut o = WarningCollector::default();
+	// Broken plugins are reported by their own check
+	let plugins = PluginManager::load(paths, &mut o).unwrap_or_else(|_| PluginManager::new());
+	o.0.clear();
+
+	let result = match Config::load(&Config::get_path(paths), plugins, true, paths, &mut o) {
+		Ok(..) if o.0.is_empty() => {
+			CheckResult::new("config", "Config", CheckStatus::Pass, "Config is valid")
+		}
+		Ok(..) => CheckResult::new("config", "Config", CheckStatus::Warn, o.0.join("; ")),
+		Err(e) => CheckResult::new("config", "Config", CheckStatus::Fail, format!("{e:#}")),
+	};
+
+	vec![result]
+}
+
+/// Check that the lockfile can be read
+pub fn check_lockfile(paths: &Paths) -> Vec<CheckResult> {
+	let result = match Lockfile::open(paths) {
+		Ok(..) => CheckResult::new(
+			"lockfile",
+			"Lockfile",
+			CheckStatus::Pass,
+			"Lockfile is valid",
+		),
+		Err(e) => CheckResult::new("lockfile", "Lockfile", CheckStatus::Fail, format!("{e:#}")),
+	};
+
+	vec![result]
+}
+
+/// Check for records of running instances whose processes have exited without removing them
+pub fn check_running_records(paths: &Paths) -> Vec<CheckResult> {
+	let result = match find_dead_records(paths) {
+		Ok(records) if records.is_empty() => CheckResult::new(
+			"running",
+			"Running instances",
+			CheckStatus::Pass,
+			"No stale process records",
+		),
+		Ok(records) => CheckResult::new(
+			"running",
+			"Running instances",
+			CheckStatus::Warn,
+			format!(
+				"{} records of processes that are no longer running. Run `mcvm instance ps` to remove them",
+				records.len()
+			),
+		),
+		Err(e) => CheckResult::new(
+			"running",
+			"Running instances",
+			CheckStatus::Fail,
+			format!("{e:#}"),
+		),
+	};
+
+	vec![result]
+}
+
+/// Check that the Java installations downloaded by MCVM and the one on the PATH can run
+pub fn check_java(paths: &Paths) -> Vec<CheckResult> {
+	let mut out = Vec::new();
+
+	let jvm_file = if cfg!(target_family = "windows") {
+		"bin/java.exe"
+	} else {
+		"bin/java"
+	};
+	match PersistentData::open(&paths.core) {
+		Ok(data) => {
+			for (name, path) in data.get_java_installations() {
+				out.push(check_java_binary(&name, &path.join(jvm_file)));
+			}
+		}
+		Err(e) => out.push(CheckResult::new(
+			"java",
+			"Installations",
+			CheckStatus::Fail,
+			format!("{e:#}"),
+		)),
+	}
+
+	if let Ok(path) = which::which("java") {
+		out.push(check_java_binary("system", &path));
+	}
+
+	out
+}
+
+/// Check that a Java binary runs
+fn check_java_binary(name: &str, path: &Path) -> CheckResult {
+	match Command::new(path).arg("-version").output() {
+		Ok(output) if output.status.success() => {
+			// Java prints its version to stderr
+			let version = String::from_utf8_lossy(&output.stderr)
+				.lines()
+				.next()
+				.unwrap_or_default()
+				.to_string();
+			CheckResult::new("java", name, CheckStatus::Pass, version)
+		}
+		Ok(output) => CheckResult::new(
+			"java",
+			name,
+			CheckStatus::Fail,
+			format!("{} exited with {}", path.display(), output.status),
+		),
+		Err(e) => CheckResult::new(
+			"java",
+			name,
+			CheckStatus::Fail,
+			format!("Failed to run {}: {e}", path.display()),
+		),
+	}
+}
+
+/// Check that each enabled plugin loads and responds to the ping hook
+pub fn check_plugins(paths: &Paths) -> Vec<CheckResult> {
+	let config = match PluginsConfig::open(paths) {
+		Ok(config) => config,
+		Err(e) => {
+			return vec![CheckResult::new(
+				"plugins",
+				"Plugins",
+				CheckStatus::Fail,
+				format!("{e:#}"),
+			)]
+		}
+	};
+
+	let mut out = Vec::new();
+	for plugin in config.plugins {
+		if plugin.is_disabled() {
+			continue;
+		}
+		let plugin = plugin.to_config();
+		let id = plugin.id.clone();
+		out.push(check_plugin(plugin, &id, paths));
+	}
+
+	out
+}
+
+/// Load a single plugin and ping it
+fn check_plugin(plugin: PluginConfig, id: &str, paths: &Paths) -> CheckResult {
+	let mut o = WarningCollector::default();
+	let mut manager = PluginManager::new();
+	if let Err(e) = manager.load_plugin(plugin, paths, &mut o) {
+		return CheckResult::new("plugins", id, CheckStatus::Fail, format!("{e:#}"));
+	}
+
+	let result = manager
+		.call_hook_on_plugin(Ping, id, &(), paths, &mut o)
+		.and_then(|handle| match handle {
+			Some(handle) => handle
+				.result_timeout(PLUGIN_PING_TIMEOUT, &mut o)
+				.map(|_| true),
+			None => Ok(false),
+		});
+	match result {
+		Ok(..) if !o.0.is_empty() => {
+			CheckResult::new("plugins", id, CheckStatus::Warn, o.0.join("; "))
+		}
+		Ok(true) => CheckResult::new("plugins", id, CheckStatus::Pass, "Responded to ping"),
+		Ok(false) => CheckResult::new(
+			"plugins",
+			id,
+			CheckStatus::Pass,
+			"Loaded, but doesn't handle the ping hook",
+		),
+		Err(e) => CheckResult::new("plugins", id, CheckStatus::Fail, format!("{e:#}")),
+	}
+}
+
+/// Check that the services MCVM uses and the remote package repositories are reachable
+pub async fn check_network(config: &Config) -> Vec<CheckResult> {
+	let mut endpoints: Vec<(String, String)> = NETWORK_ENDPOINTS
+		.iter()
+		.map(|(name, url)| (name.to_string(), url.to_string()))
+		.collect();
+	for repo in &config.packages.repos {
+		if let PkgRepoLocation::Remote(url) = repo.get_location() {
+			endpoints.push((format!("Repository '{}'", repo.id), url.clone()));
+		}
+	}
+
+	let mut out = Vec::new();
+	if let Some(proxy) = &config.prefs.proxy {
+		out.push(CheckResult::new(
+			"network",
+			"Proxy",
+			CheckStatus::Pass,
+			format!("Using proxy {}", proxy.url),
+		));
+	}
+	for (name, url) in endpoints {
+		// Any response means that the service is reachable, even if it is an error status
+		let result = match config.client.head(&url).send().await {
+			Ok(response) => CheckResult::new(
+				"network",
+				name,
+				CheckStatus::Pass,
+				response.status().to_string(),
+			),
+			Err(e) => CheckResult::new("network", name, CheckStatus::Fail, e.to_string()),
+		};
+		out.push(result);
+	}
+
+	out
+}
+
+/// Output that only keeps the warnings and errors shown to it
+#[derive(Default)]
+struct WarningCollector(Vec<String>);
+
+impl MCVMOutput for WarningCollector {
+	fn display_text(&mut self, _text: String, _level: MessageLevel) {}
+
+	fn display_message(&mut self, message: Message) {
+		if let MessageContents::Warning(text) | MessageContents::Error(text) = message.contents {
+			self.0.push(text);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_df_output() {
+		let output = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n/dev/nvme0n1p2   479670376 312345678 143000000      69% /\n";
+		assert_eq!(parse_df_output(output), Some(143000000 * 1024));
+		assert_eq!(parse_df_output("Filesystem\n"), None);
+		assert_eq!(parse_df_output(""), None);
+	}
+
+	#[test]
+	fn test_result_serialization() {
+		let result = CheckResult::new("java", "system", CheckStatus::Warn, "message");
+		assert_eq!(
+			serde_json::to_value(&result).unwrap(),
+			serde_json::json!({
+				"check": "java",
+				"name": "system",
+				"status": "warn",
+				"message": "message"
+			})
+		);
+	}
+
+	#[test]
+	fn test_check_directories() {
+		let mut paths = Paths::new_no_create().unwrap();
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		paths.data = dir.to_path_buf();
+		paths.cache = dir.join("missing");
+
+		let results = check_directories(&paths);
+		assert_eq!(results[0].status, CheckStatus::Pass);
+		assert_eq!(results[1].status, CheckStatus::Warn);
+		assert!(!dir.join(".mcvm_doctor").exists());
+	}
+}
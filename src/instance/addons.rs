@@ -1,34 +1,51 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
 use anyhow::{ensure, Context};
 use mcvm_shared::addon::{Addon, AddonKind};
 use mcvm_shared::versions::{VersionInfo, VersionPattern};
 
-use crate::addon::{self, AddonExt};
+use crate::addon::{self, AddonExt, AddonLinkMode};
+use crate::io::lock::{FileOwner, Lockfile};
 use crate::io::paths::Paths;
 
-use super::{InstKind, Instance};
+use super::ignore::InstanceIgnore;
+use super::{InstKind, Instance, InstanceSubdir};
 
 impl Instance {
-	/// Creates an addon on the instance
+	/// Creates an addon on the instance. The previous link mode is how the addon was
+	/// put into the instance the last time it was installed. Returns the link mode that was used,
+	/// which is a copy if any of the addon's files had to be copied. Files that are ignored are left alone.
+	#[allow(clippy::too_many_arguments)]
 	pub fn create_addon(
 		&mut self,
 		addon: &Addon,
 		selected_worlds: &[String],
 		paths: &Paths,
 		version_info: &VersionInfo,
-	) -> anyhow::Result<()> {
+		link_mode: AddonLinkMode,
+		previous_link_mode: Option<AddonLinkMode>,
+		ignore: &InstanceIgnore,
+	) -> anyhow::Result<AddonLinkMode> {
 		self.ensure_dirs(paths)?;
 
+		let mut out = AddonLinkMode::Hardlink;
 		for path in self
 			.get_linked_addon_paths(addon, selected_worlds, paths, version_info)
 			.context("Failed to get linked directory")?
 		{
-			Self::link_addon(&path, addon, paths, &self.id)
-				.with_context(|| format!("Failed to link addon {}", addon.id))?;
+			if ignore.is_ignored(&path.join(&addon.file_name)) {
+				continue;
+			}
+			let mode =
+				Self::link_addon(&path, addon, paths, &self.id, link_mode, previous_link_mode)
+					.with_context(|| format!("Failed to link addon {}", addon.id))?;
+			if mode == AddonLinkMode::Copy {
+				out = AddonLinkMode::Copy;
+			}
 		}
 
-		Ok(())
+		Ok(out)
 	}
 
 	/// Get the paths on this instance to hardlink an addon to
@@ -39,8 +56,8 @@ impl Instance {
 		paths: &Paths,
 		version_info: &VersionInfo,
 	) -> anyhow::Result<Vec<PathBuf>> {
-		self.ensure_dirs(paths)?;
-		let game_dir = &self.dirs.get().game_dir;
+		self.ensure_dirs_set(paths);
+		let game_dir = self.dirs.get().game_dir.clone();
 		Ok(match addon.kind {
 			AddonKind::ResourcePack => {
 				if let InstKind::Client { .. } = self.kind {
@@ -54,10 +71,10 @@ impl Instance {
 					vec![]
 				}
 			}
-			AddonKind::Mod => vec![game_dir.join("mods")],
+			AddonKind::Mod => vec![self.get_subdir(InstanceSubdir::Mods, paths)],
 			AddonKind::Plugin => {
 				if let InstKind::Server { .. } = self.kind {
-					vec![game_dir.join("plugins")]
+					vec![self.get_subdir(InstanceSubdir::Plugins, paths)]
 				} else {
 					vec![]
 				}
@@ -73,60 +90,100 @@ impl Instance {
 				if let Some(datapack_folder) = &self.config.datapack_folder {
 					vec![game_dir.join(datapack_folder)]
 				} else {
+					let world_dir = self.get_subdir(InstanceSubdir::World, paths);
 					match &self.kind {
-						InstKind::Client { .. } => {
-							game_dir
-								.join("saves")
-								.read_dir()
-								.context("Failed to read saves directory")?
-								.filter_map(|world| {
-									let world = world.ok()?;
-									let path = world.path();
-									// Filter worlds not in the list
-									if !selected_worlds.is_empty() {
-										let dir_name = path.file_name()?.to_string_lossy();
-										if !selected_worlds.iter().any(|x| x == dir_name.as_ref()) {
-											return None;
-										}
-									}
-									Some(path.join("datapacks"))
-								})
-								.collect()
-						}
-						InstKind::Server { world_name, .. } => {
-							let world_dir = world_name.as_deref().unwrap_or("world");
-							vec![game_dir.join(world_dir).join("datapacks")]
-						}
+						// Clients can have any number of worlds, so they have to be picked explicitly
+						InstKind::Client { .. } => selected_worlds
+							.iter()
+							.map(|world| world_dir.join(world).join("datapacks"))
+							.collect(),
+						InstKind::Server { .. } => vec![world_dir.join("datapacks")],
 					}
 				}
 			}
 		})
 	}
 
-	/// Hardlinks the addon from the path in addon storage to the correct in the instance,
+	/// Hardlinks or copies the addon from the path in addon storage to the correct in the instance,
 	/// under the specified directory
 	fn link_addon(
 		dir: &Path,
 		addon: &Addon,
 		paths: &Paths,
 		instance_id: &str,
-	) -> anyhow::Result<()> {
+		link_mode: AddonLinkMode,
+		previous_link_mode: Option<AddonLinkMode>,
+	) -> anyhow::Result<AddonLinkMode> {
 		let link = dir.join(addon.file_name.clone());
 		let addon_path = addon.get_path(paths, instance_id);
 		mcvm_core::io::files::create_leading_dirs(&link)?;
 		// These checks are to make sure that we properly link the hardlink to the right location
-		// We have to remove the current link since it doesnt let us update it in place
 		ensure!(addon_path.exists(), "Addon path does not exist");
-		if link.exists() {
-			std::fs::remove_file(&link).context("Failed to remove instance addon file")?;
+		addon::link_addon_file(&addon_path, &link, link_mode, previous_link_mode)
+	}
+
+	/// Get the files in the folders that addons are installed to that weren't put there by MCVM.
+	/// These are never removed by MCVM. Ignored files aren't included
+	pub fn get_unmanaged_addon_files(
+		&self,
+		lock: &Lockfile,
+		ignore: &InstanceIgnore,
+	) -> Vec<PathBuf> {
+		let managed_files = lock.get_instance_addon_files(&self.id);
+		let managed_dirs: HashSet<_> = managed_files.iter().filter_map(|x| x.parent()).collect();
+
+		let mut out: Vec<_> = managed_dirs
+			.into_iter()
+			.filter_map(|dir| dir.read_dir().ok())
+			.flatten()
+			.filter_map(|entry| entry.ok())
+			.map(|entry| entry.path())
+			.filter(|path| {
+				path.is_file() && !managed_files.contains(path) && !ignore.is_ignored(path)
+			})
+			.collect();
+		out.sort();
+
+		out
+	}
+
+	/// Finds the package that put a file into this instance. Relative paths are relative to the game directory.
+	/// Returns None if the file isn't managed by MCVM
+	pub fn get_file_owner(
+		&mut self,
+		path: &Path,
+		paths: &Paths,
+		lock: &Lockfile,
+	) -> anyhow::Result<Option<FileOwner>> {
+		self.ensure_dirs_set(paths);
+		let game_dir = &self.dirs.get().game_dir;
+		let path = game_dir.join(path);
+		if let Some(owner) = lock.get_file_owner(&self.id, &path)? {
+			return Ok(Some(owner));
 		}
-		mcvm_core::io::files::update_hardlink(&addon_path, &link)
-			.context("Failed to create hard link")?;
-		Ok(())
+
+		// The path could lead to the game directory in a different way, such as through a symlink
+		let (Ok(canonical_path), Ok(canonical_game_dir)) =
+			(path.canonicalize(), game_dir.canonicalize())
+		else {
+			return Ok(None);
+		};
+		let Ok(relative) = canonical_path.strip_prefix(canonical_game_dir) else {
+			return Ok(None);
+		};
+		lock.get_file_owner(&self.id, &game_dir.join(relative))
 	}
 
-	/// Removes an addon file from this instance
-	pub fn remove_addon_file(&self, path: &Path, paths: &Paths) -> anyhow::Result<()> {
+	/// Removes an addon file from this instance, unless it is ignored
+	pub fn remove_addon_file(
+		&self,
+		path: &Path,
+		paths: &Paths,
+		ignore: &InstanceIgnore,
+	) -> anyhow::Result<()> {
+		if ignore.is_ignored(path) {
+			return Ok(());
+		}
 		// We check if it is a stored addon path due to the old behavior to put that path in the lockfile.
 		// Also some other sanity checks
 		if path.exists() && !addon::is_stored_addon_path(path, paths) && !path.is_dir() {
@@ -136,3 +193,113 @@ impl Instance {
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use mcvm_shared::id::InstanceID;
+	use mcvm_shared::output;
+	use mcvm_shared::pkg::{PackageAddonOptionalHashes, PackageID};
+
+	use crate::config::plugin::PluginManager;
+	use crate::config::Config;
+
+	use super::*;
+
+	#[test]
+	fn test_linked_addon_path_matrix() {
+		let paths = Paths::new_no_create().unwrap();
+		let server_types = [
+			"vanilla",
+			"paper",
+			"folia",
+			"sponge",
+			"spongeforge",
+			"fabric",
+			"forge",
+		];
+		let mut instances = serde_json::Map::new();
+		for server_type in server_types {
+			instances.insert(
+				server_type.into(),
+				serde_json::json!({
+					"type": "server",
+					"version": "1.20.1",
+					"server_type": server_type
+				}),
+			);
+		}
+		instances.insert(
+			"client".into(),
+			serde_json::json!({ "type": "client", "version": "1.20.1" }),
+		);
+		let deser = serde_json::from_value(serde_json::json!({ "instances": instances })).unwrap();
+		let mut config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			false,
+			&paths,
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+
+		let version_info = VersionInfo {
+			version: "1.20.1".into(),
+			versions: vec!["13w24a".into(), "1.20.1".into()],
+		};
+		let mut get_paths = |id: &str, kind| {
+			let instance = config.instances.get_mut(&InstanceID::from(id)).unwrap();
+			let addon = Addon {
+				kind,
+				id: "addon".into(),
+				file_name: "addon.jar".into(),
+				pkg_id: PackageID::from("pkg"),
+				version: None,
+				hashes: PackageAddonOptionalHashes::default(),
+			};
+			let game_dir = paths.instances.join(id);
+			instance
+				.get_linked_addon_paths(&addon, &["saved".into()], &paths, &version_info)
+				.unwrap()
+				.into_iter()
+				.map(|x| {
+					let rel = x.strip_prefix(&game_dir).unwrap();
+					rel.components()
+						.map(|x| x.as_os_str().to_string_lossy().to_string())
+						.collect::<Vec<_>>()
+						.join("/")
+				})
+				.collect::<Vec<_>>()
+		};
+
+		for server_type in server_types {
+			let plugins = if server_type == "spongeforge" {
+				"mods"
+			} else {
+				"plugins"
+			};
+			assert_eq!(get_paths(server_type, AddonKind::Mod), vec!["mods"]);
+			assert_eq!(get_paths(server_type, AddonKind::Plugin), vec![plugins]);
+			assert!(get_paths(server_type, AddonKind::ResourcePack).is_empty());
+			assert!(get_paths(server_type, AddonKind::Shader).is_empty());
+			assert_eq!(
+				get_paths(server_type, AddonKind::Datapack),
+				vec!["world/datapacks"]
+			);
+		}
+
+		assert_eq!(get_paths("client", AddonKind::Mod), vec![".minecraft/mods"]);
+		assert!(get_paths("client", AddonKind::Plugin).is_empty());
+		assert_eq!(
+			get_paths("client", AddonKind::ResourcePack),
+			vec![".minecraft/resourcepacks"]
+		);
+		assert_eq!(
+			get_paths("client", AddonKind::Shader),
+			vec![".minecraft/shaderpacks"]
+		);
+		assert_eq!(
+			get_paths("client", AddonKind::Datapack),
+			vec![".minecraft/saves/saved/datapacks"]
+		);
+	}
+}
@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use mcvm_core::io::files::create_leading_dirs;
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::translate;
+
+use crate::addon::{self, AddonExt};
+use crate::io::lock::Lockfile;
+use crate::io::paths::Paths;
+
+use super::snapshot::is_non_empty_dir;
+use super::Instance;
+
+impl Instance {
+	/// Copy the files of this instance into a new instance that was created from the same config,
+	/// optionally leaving out the world data. The new instance is registered in the lockfile and its
+	/// addons are linked again, so that the two instances never share addon files
+	pub fn clone_into(
+		&mut self,
+		dest: &mut Instance,
+		include_worlds: bool,
+		lock: &mut Lockfile,
+		paths: &Paths,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<()> {
+		self.ensure_dirs(paths)?;
+		dest.ensure_dirs(paths)?;
+		let src_dir = self.dirs.get().inst_dir.clone();
+		let dest_dir = dest.dirs.get().inst_dir.clone();
+		if is_non_empty_dir(&dest_dir) {
+			bail!(
+				"The directory for instance '{}' already has files in it",
+				dest.id
+			);
+		}
+
+		// Addon files are linked separately so that they aren't shared with the original
+		let mut excluded = lock.get_instance_addon_files(&self.id);
+		let world_paths = if include_worlds {
+			Vec::new()
+		} else {
+			self.get_world_paths(paths)
+		};
+		excluded.extend(world_paths.iter().map(|x| src_dir.join(x)));
+
+		let files = list_files(&src_dir, &excluded).context("Failed to list instance files")?;
+		copy_files(&files, &src_dir, &dest_dir, o).context("Failed to copy instance files")?;
+
+		let addons = lock
+			.clone_instance(&self.id, &dest.id, &src_dir, &dest_dir)
+			.context("Failed to copy instance in lockfile")?;
+		for cloned in addons {
+			let stored = cloned.addon.get_path(paths, &self.id);
+			let dest_stored = cloned.addon.get_path(paths, &dest.id);
+			// Addons without a version are stored separately for each instance
+			if stored != dest_stored && stored.exists() {
+				fs::copy(&stored, &dest_stored).context("Failed to copy stored addon")?;
+			}
+			// If the stored addon is missing, the next update will download and link it again
+			if !dest_stored.exists() {
+				continue;
+			}
+
+			for file in &cloned.files {
+				// Datapacks in worlds that weren't copied would create an incomplete world
+				if world_paths
+					.iter()
+					.any(|x| file.starts_with(dest_dir.join(x)))
+				{
+					continue;
+				}
+				create_leading_dirs(file).context("Failed to create addon directory")?;
+				let mode = addon::link_addon_file(&dest_stored, file, cloned.link_mode, None)?;
+				lock.set_addon_link_mode(&dest.id, &cloned.addon.pkg_id, &cloned.addon.id, mode);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Recursively list the files in a directory, relative to it. Excluded paths are absolute
+/// and can be either files or directories
+fn list_files(dir: &Path, excluded: &HashSet<PathBuf>) -> anyhow::Result<Vec<PathBuf>> {
+	let mut out = Vec::new();
+	let mut stack = vec![dir.to_path_buf()];
+	while let Some(current) = stack.pop() {
+		for entry in fs::read_dir(&current)? {
+			let path = entry?.path();
+			if excluded.contains(&path) {
+				continue;
+			}
+			if path.is_dir() {
+				stack.push(path);
+			} else if let Ok(relative) = path.strip_prefix(dir) {
+				out.push(relative.to_path_buf());
+			}
+		}
+	}
+	out.sort();
+
+	Ok(out)
+}
+
+/// Copy files from one directory to another, displaying the progress
+fn copy_files(
+	files: &[PathBuf],
+	src_dir: &Path,
+	dest_dir: &Path,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<()> {
+	let total = files.len() as u32;
+	o.display(
+		MessageContents::StartProcess(translate!(
+			o,
+			StartCopyingInstanceFiles,
+			"count" = &total.to_string()
+		)),
+		MessageLevel::Important,
+	);
+	o.start_process();
+
+	for (i, file) in files.iter().enumerate() {
+		let dest = dest_dir.join(file);
+		create_leading_dirs(&dest)?;
+		fs::copy(src_dir.join(file), &dest)
+			.with_context(|| format!("Failed to copy file '{}'", file.display()))?;
+
+		o.display(
+			MessageContents::Associated(
+				Box::new(MessageContents::Progress {
+					current: i as u32 + 1,
+					total,
+				}),
+				Box::new(MessageContents::Simple(translate!(
+					o,
+					CopiedInstanceFile,
+					"file" = &file.to_string_lossy()
+				))),
+			),
+			MessageLevel::Important,
+		);
+	}
+
+	o.display(
+		MessageContents::Success(translate!(o, FinishCopyingInstanceFiles)),
+		MessageLevel::Important,
+	);
+	o.end_process();
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use mcvm_shared::output::NoOp;
+
+	use super::*;
+
+	#[test]
+	fn test_copy_instance_files() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let src = dir.join("src");
+		let dest = dir.join("dest");
+		fs::create_dir_all(src.join("world/region")).unwrap();
+		fs::create_dir_all(src.join("mods")).unwrap();
+		fs::write(src.join("server.properties"), "level-name=world").unwrap();
+		fs::write(src.join("world/region/r.0.0.mca"), "region").unwrap();
+		fs::write(src.join("mods/managed.jar"), "mod").unwrap();
+		fs::write(src.join("mods/user.jar"), "mod").unwrap();
+
+		let excluded = HashSet::from([src.join("world"), src.join("mods/managed.jar")]);
+		let files = list_files(&src, &excluded).unwrap();
+		assert_eq!(
+			files,
+			vec![
+				PathBuf::from("mods/user.jar"),
+				PathBuf::from("server.properties")
+			]
+		);
+
+		copy_files(&files, &src, &dest, &mut NoOp).unwrap();
+		assert_eq!(
+			fs::read_to_string(dest.join("server.properties")).unwrap(),
+			"level-name=world"
+		);
+		assert!(dest.join("mods/user.jar").exists());
+		assert!(!dest.join("mods/managed.jar").exists());
+		assert!(!dest.join("world").exists());
+	}
+}
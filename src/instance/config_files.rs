@@ -0,0 +1,188 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use mcvm_core::net::download;
+use mcvm_shared::pkg::ConfigFilePolicy;
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::io::lock::Lockfile;
+use crate::io::paths::Paths;
+use crate::pkg::eval::{ConfigFileLocation, ConfigFileRequest};
+
+use super::ignore::InstanceIgnore;
+use super::Instance;
+
+impl Instance {
+	/// Gets the paths in this instance that config files would be written to
+	pub fn get_config_file_paths(
+		&mut self,
+		config_files: &[ConfigFileRequest],
+		paths: &Paths,
+	) -> Vec<PathBuf> {
+		self.ensure_dirs_set(paths);
+		let game_dir = &self.dirs.get().game_dir;
+		config_files
+			.iter()
+			.map(|x| game_dir.join(&x.path))
+			.collect()
+	}
+
+	/// Writes the config files of a package into this instance. Files that are ignored are left alone.
+	/// Returns the files that the package created, including ones that it created in an earlier install
+	pub async fn install_config_files(
+		&mut self,
+		package: &str,
+		config_files: &[ConfigFileRequest],
+		paths: &Paths,
+		lock: &Lockfile,
+		ignore: &InstanceIgnore,
+		client: &Client,
+	) -> anyhow::Result<Vec<PathBuf>> {
+		let previous = lock.get_package_config_files(&self.id, package);
+		let destinations = self.get_config_file_paths(config_files, paths);
+
+		let mut out = Vec::new();
+		for (config_file, path) in config_files.iter().zip(destinations) {
+			let created_before = previous.contains(&path);
+			if ignore.is_ignored(&path)
+				|| (config_file.policy == ConfigFilePolicy::Create && path.exists())
+			{
+				if created_before {
+					out.push(path);
+				}
+				continue;
+			}
+
+			let contents = match &config_file.location {
+				ConfigFileLocation::Contents(contents) => contents.clone(),
+				ConfigFileLocation::Remote(url) => {
+					download::text(url, client).await.with_context(|| {
+						format!("Failed to download config file '{}'", config_file.path)
+					})?
+				}
+			};
+
+			let created = write_config_file(&path, &contents, config_file.policy)
+				.with_context(|| format!("Failed to write config file '{}'", config_file.path))?;
+			if created || created_before {
+				out.push(path);
+			}
+		}
+
+		Ok(out)
+	}
+}
+
+/// Writes a config file using a policy. Returns true if the file didn't exist before
+pub fn write_config_file(
+	path: &Path,
+	contents: &str,
+	policy: ConfigFilePolicy,
+) -> anyhow::Result<bool> {
+	let exists = path.exists();
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent).context("Failed to create config file directory")?;
+	}
+
+	match policy {
+		ConfigFilePolicy::Create => {
+			if !exists {
+				std::fs::write(path, contents)?;
+			}
+		}
+		ConfigFilePolicy::Overwrite => std::fs::write(path, contents)?,
+		ConfigFilePolicy::JsonMerge => {
+			let mut new: Value =
+				serde_json::from_str(contents).context("Package contents are not valid JSON")?;
+			if exists {
+				let existing = std::fs::read_to_string(path)?;
+				let mut existing: Value =
+					serde_json::from_str(&existing).context("Existing file is not valid JSON")?;
+				merge_json(&mut existing, new);
+				new = existing;
+			}
+			std::fs::write(path, serde_json::to_string_pretty(&new)?)?;
+		}
+	}
+
+	Ok(!exists)
+}
+
+/// Recursively merges a JSON value into another. Values from the new one replace
+/// the existing ones, except for objects which have their keys merged
+fn merge_json(existing: &mut Value, new: Value) {
+	match (existing, new) {
+		(Value::Object(existing), Value::Object(new)) => {
+			for (key, value) in new {
+				match existing.get_mut(&key) {
+					Some(current) => merge_json(current, value),
+					None => {
+						existing.insert(key, value);
+					}
+				}
+			}
+		}
+		(existing, new) => *existing = new,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_create_policy() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("config/foo.toml");
+
+		assert!(write_config_file(&path, "a = 1", ConfigFilePolicy::Create).unwrap());
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "a = 1");
+
+		// The user's changes are kept
+		std::fs::write(&path, "a = 2").unwrap();
+		assert!(!write_config_file(&path, "a = 1", ConfigFilePolicy::Create).unwrap());
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "a = 2");
+	}
+
+	#[test]
+	fn test_overwrite_policy() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("options.txt");
+
+		std::fs::write(&path, "old").unwrap();
+		assert!(!write_config_file(&path, "new", ConfigFilePolicy::Overwrite).unwrap());
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+	}
+
+	#[test]
+	fn test_json_merge_policy() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("config/foo.json");
+
+		assert!(write_config_file(&path, r#"{"a": 1}"#, ConfigFilePolicy::JsonMerge).unwrap());
+
+		std::fs::write(
+			&path,
+			r#"{"a": 2, "telemetry": {"enabled": true, "level": 3}, "list": [1, 2]}"#,
+		)
+		.unwrap();
+		let created = write_config_file(
+			&path,
+			r#"{"telemetry": {"enabled": false}, "list": [3]}"#,
+			ConfigFilePolicy::JsonMerge,
+		)
+		.unwrap();
+		assert!(!created);
+		let merged: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+		assert_eq!(
+			merged,
+			serde_json::json!({"a": 2, "telemetry": {"enabled": false, "level": 3}, "list": [3]})
+		);
+
+		// Broken files are not replaced
+		std::fs::write(&path, "{").unwrap();
+		assert!(write_config_file(&path, "{}", ConfigFilePolicy::JsonMerge).is_err());
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "{");
+	}
+}
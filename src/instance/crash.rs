@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use anyhow::Context;
+use mcvm_core::io::{json_from_file, json_to_file_pretty};
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::translate;
+use mcvm_shared::util::utc_timestamp;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::config::preferences::CrashNotificationConfig;
+use crate::io::logging::tail_log;
+use crate::io::paths::Paths;
+
+use super::Instance;
+
+/// The number of lines from the end of the game log to include in a crash bundle
+pub const CRASH_LOG_LINES: usize = 200;
+/// Name of the summary file in a crash bundle
+pub const CRASH_SUMMARY_FILE: &str = "crash.json";
+
+/// Summary of a crash, which is stored in the crash bundle and sent in notifications
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CrashSummary {
+	/// The ID of the instance that crashed
+	pub instance: String,
+	/// The exit code of the instance process, if it had one
+	pub exit_code: Option<i32>,
+	/// The timestamp of the crash
+	pub timestamp: u64,
+	/// The path to the crash bundle directory
+	pub bundle_path: String,
+}
+
+impl Instance {
+	/// Handle an abnormal exit of this instance by writing a crash bundle and sending any configured
+	/// notifications. Crash reports created after the given launch time are included in the bundle
+	pub async fn report_crash(
+		&mut self,
+		exit_code: Option<i32>,
+		launched_at: SystemTime,
+		notifications: &CrashNotificationConfig,
+		paths: &Paths,
+		client: &Client,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<CrashSummary> {
+		let summary = self
+			.create_crash_bundle(exit_code, launched_at, paths)
+			.context("Failed to create crash bundle")?;
+
+		let code = exit_code.map_or_else(|| "unknown".into(), |x| x.to_string());
+		o.display(
+			MessageContents::Error(translate!(
+				o,
+				InstanceCrashed,
+				"inst" = &self.id,
+				"code" = &code,
+				"path" = &summary.bundle_path
+			)),
+			MessageLevel::Important,
+		);
+
+		notify_crash(&summary, notifications, paths, client)
+			.await
+			.context("Failed to send crash notification")?;
+
+		Ok(summary)
+	}
+
+	/// Collect the end of the game log and any new crash reports into a crash bundle directory
+	pub fn create_crash_bundle(
+		&mut self,
+		exit_code: Option<i32>,
+		launched_at: SystemTime,
+		paths: &Paths,
+	) -> anyhow::Result<CrashSummary> {
+		self.ensure_dirs(paths)?;
+		let dirs = self.dirs.get();
+		let timestamp = utc_timestamp()?;
+		let bundle_dir = create_bundle_dir(&dirs.inst_dir.join("crashes"), timestamp)?;
+
+		let log_path = dirs.game_dir.join("logs").join("latest.log");
+		let log_tail =
+			extract_log_tail(&log_path, CRASH_LOG_LINES).context("Failed to read game log")?;
+		fs::write(bundle_dir.join("latest.log"), log_tail)
+			.context("Failed to write log to crash bundle")?;
+
+		let crash_reports =
+			get_new_crash_reports(&dirs.game_dir.join("crash-reports"), launched_at);
+		if !crash_reports.is_empty() {
+			let reports_dir = bundle_dir.join("crash-reports");
+			fs::create_dir_all(&reports_dir)
+				.context("Failed to create crash reports directory in bundle")?;
+			for report in crash_reports {
+				let Some(file_name) = report.file_name() else {
+					continue;
+				};
+				fs::copy(&report, reports_dir.join(file_name))
+					.context("Failed to copy crash report into bundle")?;
+			}
+		}
+
+		let summary = CrashSummary {
+			instance: self.id.to_string(),
+			exit_code,
+			timestamp,
+			bundle_path: bundle_dir.to_string_lossy().into(),
+		};
+		json_to_file_pretty(bundle_dir.join(CRASH_SUMMARY_FILE), &summary)
+			.context("Failed to write crash summary")?;
+
+		Ok(summary)
+	}
+}
+
+/// Create a new directory for a crash bundle. Crashes in the same second get numbered suffixes
+/// so that they don't overwrite each other
+fn create_bundle_dir(crashes_dir: &Path, timestamp: u64) -> anyhow::Result<PathBuf> {
+	fs::create_dir_all(crashes_dir).context("Failed to create crashes directory")?;
+	let mut index = 0;
+	loop {
+		let name = if index == 0 {
+			timestamp.to_string()
+		} else {
+			format!("{timestamp}-{index}")
+		};
+		let dir = crashes_dir.join(name);
+		match fs::create_dir(&dir) {
+			Ok(()) => return Ok(dir),
+			Err(e) if e.kind() == ErrorKind::AlreadyExists => index += 1,
+			Err(e) => return Err(e).context("Failed to create crash bundle directory"),
+		}
+	}
+}
+
+/// Get the last lines of a log file as a single string. Returns an empty string if the log doesn't exist
+pub fn extract_log_tail(path: &Path, lines: usize) -> anyhow::Result<String> {
+	let lines = tail_log(path, lines)?;
+	let mut out = lines.join("\n");
+	if !out.is_empty() {
+		out.push('\n');
+	}
+
+	Ok(out)
+}
+
+/// Get the files in a crash reports directory that were modified after a time
+fn get_new_crash_reports(dir: &Path, since: SystemTime) -> Vec<PathBuf> {
+	let Ok(entries) = fs::read_dir(dir) else {
+		return Vec::new();
+	};
+
+	entries
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| {
+			entry
+				.metadata()
+				.and_then(|x| x.modified())
+				.is_ok_and(|x| x >= since)
+		})
+		.map(|entry| entry.path())
+		.filter(|path| path.is_file())
+		.collect()
+}
+
+/// Send the configured notifications for a crash, unless too many have been sent recently.
+/// Returns whether any notifications were sent
+pub async fn notify_crash(
+	summary: &CrashSummary,
+	config: &CrashNotificationConfig,
+	paths: &Paths,
+	client: &Client,
+) -> anyhow::Result<bool> {
+	if config.webhook.is_none() && config.command.is_none() {
+		return Ok(false);
+	}
+
+	let mut limiter = CrashRateLimiter::open(paths)?;
+	let allowed = limiter.try_record(
+		&summary.instance,
+		summary.timestamp,
+		config.max_notifications,
+		config.window,
+	);
+	limiter.write(paths)?;
+	if !allowed {
+		return Ok(false);
+	}
+
+	if let Some(webhook) = &config.webhook {
+		client
+			.post(webhook)
+			.json(summary)
+			.send()
+			.await
+			.context("Failed to send webhook request")?
+			.error_for_status()
+			.context("Webhook returned an error")?;
+	}
+
+	if let Some(command) = &config.command {
+		run_notification_command(command, summary)?;
+	}
+
+	Ok(true)
+}
+
+/// Run a notification command, giving it the crash summary on its standard input
+fn run_notification_command(command: &str, summary: &CrashSummary) -> anyhow::Result<()> {
+	let summary = serde_json::to_string(summary).context("Failed to serialize crash summary")?;
+	let parts = shlex::split(command)
+		.with_context(|| format!("Notification command '{command}' has unclosed quotes"))?;
+	let mut parts = parts.into_iter();
+	let program = parts.next().context("Notification command is empty")?;
+	let mut child = Command::new(program)
+		.args(parts)
+		.stdin(Stdio::piped())
+		.spawn()
+		.with_context(|| format!("Failed to run notification command '{command}'"))?;
+	if let Some(mut stdin) = child.stdin.take() {
+		stdin
+			.write_all(summary.as_bytes())
+			.context("Failed to write crash summary to notification command")?;
+	}
+	// Don't hold up the caller while the command runs
+	std::thread::spawn(move || child.wait());
+
+	Ok(())
+}
+
+/// Persistent record of the crash notifications that have been sent, used to rate limit them
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct CrashRateLimiter {
+	/// Timestamps of recent notifications for each instance
+	notifications: HashMap<String, Vec<u64>>,
+}
+
+impl CrashRateLimiter {
+	/// Open the rate limiter, creating it if it doesn't exist
+	pub fn open(paths: &Paths) -> anyhow::Result<Self> {
+		let path = Self::get_path(paths);
+		if path.exists() {
+			json_from_file(path).context("Failed to read crash notification history")
+		} else {
+			Ok(Self::default())
+		}
+	}
+
+	/// Write the rate limiter to disk
+	pub fn write(&self, paths: &Paths) -> anyhow::Result<()> {
+		json_to_file_pretty(Self::get_path(paths), self)
+			.context("Failed to write crash notification history")
+	}
+
+	/// Check whether a notification for an instance is allowed at the given time,
+	/// recording it if it is. At most `max` notifications are allowed within `window` seconds
+	pub fn try_record(&mut self, instance: &str, now: u64, max: u32, window: u64) -> bool {
+		let history = self.notifications.entry(instance.to_string()).or_default();
+		history.retain(|x| now.saturating_sub(*x) < window);
+		if history.len() >= max as usize {
+			return false;
+		}
+		history.push(now);
+
+		true
+	}
+
+	fn get_path(paths: &Paths) -> PathBuf {
+		paths.internal.join("crash_notifications.json")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_extract_log_tail() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let path = dir.join("latest.log");
+
+		let log: String = (1..=300).map(|x| format!("line {x}\n")).collect();
+		fs::write(&path, log).unwrap();
+		let tail = extract_log_tail(&path, CRASH_LOG_LINES).unwrap();
+		assert_eq!(tail.lines().count(), 200);
+		assert!(tail.starts_with("line 101\n"));
+		assert!(tail.ends_with("line 300\n"));
+
+		fs::write(&path, "only\nthree\nlines").unwrap();
+		assert_eq!(
+			extract_log_tail(&path, 200).unwrap(),
+			"only\nthree\nlines\n"
+		);
+
+		fs::write(&path, "").unwrap();
+		assert_eq!(extract_log_tail(&path, 200).unwrap(), "");
+		assert_eq!(extract_log_tail(&dir.join("missing.log"), 200).unwrap(), "");
+	}
+
+	#[test]
+	fn test_bundle_dirs_in_same_second() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path().join("crashes");
+
+		let first = create_bundle_dir(&dir, 1000).unwrap();
+		let second = create_bundle_dir(&dir, 1000).unwrap();
+		let third = create_bundle_dir(&dir, 1000).unwrap();
+		assert_eq!(first, dir.join("1000"));
+		assert_eq!(second, dir.join("1000-1"));
+		assert_eq!(third, dir.join("1000-2"));
+	}
+
+	#[test]
+	fn test_crash_rate_limiter() {
+		let mut limiter = CrashRateLimiter::default();
+		assert!(limiter.try_record("server", 1000, 2, 60));
+		assert!(limiter.try_record("server", 1010, 2, 60));
+		assert!(!limiter.try_record("server", 1020, 2, 60));
+		// Other instances are limited separately
+		assert!(limiter.try_record("other", 1020, 2, 60));
+		// Once the first notification leaves the window, another is allowed
+		assert!(limiter.try_record("server", 1060, 2, 60));
+		assert!(!limiter.try_record("server", 1065, 2, 60));
+		// Denied notifications don't count against the limit
+		assert!(limiter.try_record("server", 1125, 2, 60));
+	}
+}
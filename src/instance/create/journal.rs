@@ -0,0 +1,542 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Context;
+use mcvm_core::io::{json_from_file, json_to_file};
+use mcvm_core::net::game_files::assets::AssetIndex;
+use mcvm_shared::id::InstanceID;
+use serde::{Deserialize, Serialize};
+
+use crate::io::paths::Paths;
+use crate::util::hash::hash_file_sha256;
+
+/// Set when the user asks for instance creation to stop, such as with Ctrl-C
+static INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+/// Ask instance creation to stop once the step that it is on has finished
+pub fn request_interrupt() {
+	INTERRUPT.store(true, Ordering::SeqCst);
+}
+
+/// Fails with [CreationInterrupted] if an interrupt has been requested
+pub fn check_interrupt(instance: &InstanceID) -> anyhow::Result<()> {
+	check_interrupt_flag(&INTERRUPT, instance)
+}
+
+fn check_interrupt_flag(flag: &AtomicBool, instance: &InstanceID) -> anyhow::Result<()> {
+	if flag.load(Ordering::SeqCst) {
+		Err(CreationInterrupted {
+			instance: instance.clone(),
+		}
+		.into())
+	} else {
+		Ok(())
+	}
+}
+
+/// Error for instance creation that stopped between two steps because it was interrupted.
+/// The journal is up to date, so running the update again picks up where it left off
+#[derive(Debug, Clone)]
+pub struct CreationInterrupted {
+	/// The instance that was being created
+	pub instance: InstanceID,
+}
+
+impl Display for CreationInterrupted {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"Creation of instance '{}' was interrupted. Run the command again to resume it",
+			self.instance
+		)
+	}
+}
+
+impl std::error::Error for CreationInterrupted {}
+
+/// A step of instance creation that is recorded in the journal
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CreationStep {
+	/// Setting up the modloader or server type
+	Modifications,
+	/// Running the instance setup hooks of plugins
+	SetupHooks,
+	/// Getting the game JAR and the other files of the Minecraft version
+	CoreInstance,
+}
+
+/// The stored contents of a creation journal
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct JournalContents {
+	/// The steps that have been started, along with the files they write
+	pub steps: BTreeMap<CreationStep, JournalStep>,
+}
+
+/// A step in the creation journal
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct JournalStep {
+	/// Whether the step finished. Steps that were started but never finished may have left partial files
+	pub complete: bool,
+	/// The files that the step writes
+	pub files: Vec<JournalFile>,
+	/// The files that the step downloads into the shared game files, like libraries and assets
+	pub downloads: Vec<JournalDownload>,
+}
+
+/// A file written by a step in the creation journal
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalFile {
+	/// The path to the file
+	pub path: PathBuf,
+	/// The size of the file when it was last known to be whole
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub size: Option<u64>,
+	/// The SHA-256 hash of the file when it was last known to be whole
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub sha256: Option<String>,
+}
+
+/// Files that a step of creation downloads, which are checked by their size instead of their hash.
+/// A download that was cut off is shorter than it should be, and hashing every library and asset
+/// on each update would be too slow
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JournalDownload {
+	/// A single file whose size is known from the version metadata
+	File {
+		/// The path to the file
+		path: PathBuf,
+		/// The size of the whole file
+		size: u64,
+	},
+	/// The objects of an asset index, whose sizes are listed in the index
+	Assets {
+		/// The path to the asset index
+		index: PathBuf,
+		/// The directory that the asset objects are stored in
+		objects_dir: PathBuf,
+	},
+}
+
+impl JournalFile {
+	fn new(path: PathBuf) -> Self {
+		Self {
+			path,
+			size: None,
+			sha256: None,
+		}
+	}
+}
+
+/// Records the progress of creating an instance, so that creation that was interrupted partway
+/// through can be resumed without trusting files that were only partially written.
+/// Every change is written to disk right away
+pub struct CreationJournal {
+	path: PathBuf,
+	instance: InstanceID,
+	contents: JournalContents,
+	interrupt: &'static AtomicBool,
+}
+
+impl CreationJournal {
+	/// Open the journal for an instance. A missing or broken journal is treated as empty
+	pub fn open(paths: &Paths, instance: &InstanceID) -> Self {
+		Self::open_at(get_journal_path(paths, instance), instance, &INTERRUPT)
+	}
+
+	fn open_at(path: PathBuf, instance: &InstanceID, interrupt: &'static AtomicBool) -> Self {
+		let contents = json_from_file(&path).unwrap_or_default();
+		Self {
+			path,
+			instance: instance.clone(),
+			contents,
+			interrupt,
+		}
+	}
+
+	/// Get the contents of the journal
+	pub fn get_contents(&self) -> &JournalContents {
+		&self.contents
+	}
+
+	/// Check the files of the steps in the journal, removing the ones that can't be trusted so that
+	/// they are written again. Files from steps that never finished are only kept if they still match
+	/// what was recorded before the step started, and files from finished steps are removed if they
+	/// changed. Downloaded files are removed if they don't have the right size. Returns the files that were removed
+	pub fn verify(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+		let mut removed = Vec::new();
+		for step in self.contents.steps.values_mut() {
+			for file in &mut step.files {
+				if is_file_trusted(file, step.complete) {
+					continue;
+				}
+				fs::remove_file(&file.path).with_context(|| {
+					format!("Failed to remove incomplete file '{}'", file.path.display())
+				})?;
+				file.size = None;
+				file.sha256 = None;
+				removed.push(file.path.clone());
+			}
+			for download in &step.downloads {
+				removed.extend(verify_download(download)?);
+			}
+		}
+
+		if !removed.is_empty() {
+			self.write()?;
+		}
+
+		Ok(removed)
+	}
+
+	/// Record that a step is starting, along with the files that it will write and download. Fails with
+	/// [CreationInterrupted] instead if creation has been asked to stop.
+	/// Should be called after the journal is verified
+	pub fn begin_step(
+		&mut self,
+		step: CreationStep,
+		files: Vec<PathBuf>,
+		downloads: Vec<JournalDownload>,
+	) -> anyhow::Result<()> {
+		check_interrupt_flag(self.interrupt, &self.instance)?;
+
+		// Files that were verified keep their hashes, so that they aren't removed if this step is interrupted
+		let previous = self.contents.steps.remove(&step).unwrap_or_default();
+		let files = files
+			.into_iter()
+			.map(|path| {
+				previous
+					.files
+					.iter()
+					.find(|x| x.path == path)
+					.cloned()
+					.unwrap_or_else(|| JournalFile::new(path))
+			})
+			.collect();
+		self.contents.steps.insert(
+			step,
+			JournalStep {
+				complete: false,
+				files,
+				downloads,
+			},
+		);
+
+		self.write()
+	}
+
+	/// Record that a step finished, along with the sizes and hashes of the files it wrote
+	pub fn finish_step(&mut self, step: CreationStep) -> anyhow::Result<()> {
+		let Some(entry) = self.contents.steps.get_mut(&step) else {
+			return Ok(());
+		};
+		for file in &mut entry.files {
+			match fs::metadata(&file.path) {
+				Ok(meta) if meta.is_file() => {
+					let hash = hash_file_sha256(&file.path).with_context(|| {
+						format!("Failed to hash file '{}'", file.path.display())
+					})?;
+					file.size = Some(meta.len());
+					file.sha256 = Some(hash);
+				}
+				_ => {
+					file.size = None;
+					file.sha256 = None;
+				}
+			}
+		}
+		entry.complete = true;
+
+		self.write()
+	}
+
+	/// Write the journal. It is replaced in one step so that being killed while writing can't break it
+	fn write(&self) -> anyhow::Result<()> {
+		if let Some(parent) = self.path.parent() {
+			fs::create_dir_all(parent).context("Failed to create creation journal directory")?;
+		}
+		let tmp_path = self.path.with_extension("json.tmp");
+		json_to_file(&tmp_path, &self.contents).context("Failed to write creation journal")?;
+		fs::rename(&tmp_path, &self.path).context("Failed to replace creation journal")?;
+
+		Ok(())
+	}
+}
+
+/// Checks whether a file from the journal can be kept. Missing files are always fine since they
+/// will be written again, and files without a recorded hash are only trusted in finished steps,
+/// where they didn't exist yet when the step finished
+fn is_file_trusted(file: &JournalFile, complete: bool) -> bool {
+	let Ok(meta) = fs::metadata(&file.path) else {
+		return true;
+	};
+	if !meta.is_file() {
+		return true;
+	}
+
+	match (file.size, &file.sha256) {
+		(Some(size), Some(sha256)) => {
+			// The size is checked first since it is much faster than hashing
+			meta.len() == size && hash_file_sha256(&file.path).is_ok_and(|x| &x == sha256)
+		}
+		_ => complete,
+	}
+}
+
+/// Removes the downloaded files that don't have the size they should, which happens when a download
+/// is cut off. Missing files are fine since they will be downloaded again. Returns the files that were removed
+fn verify_download(download: &JournalDownload) -> anyhow::Result<Vec<PathBuf>> {
+	let files = match download {
+		JournalDownload::File { path, size } => vec![(path.clone(), *size)],
+		JournalDownload::Assets { index, objects_dir } => {
+			if !index.exists() {
+				return Ok(Vec::new());
+			}
+			match json_from_file::<AssetIndex>(index) {
+				Ok(contents) => contents
+					.objects
+					.values()
+					.map(|x| (x.get_object_path(objects_dir), x.size as u64))
+					.collect(),
+				// The index is only broken if it was cut off while it was being written
+				Err(..) => {
+					fs::remove_file(index).with_context(|| {
+						format!(
+							"Failed to remove incomplete asset index '{}'",
+							index.display()
+						)
+					})?;
+					return Ok(vec![index.clone()]);
+				}
+			}
+		}
+	};
+
+	let mut removed = Vec::new();
+	for (path, size) in files {
+		let Ok(meta) = fs::metadata(&path) else {
+			continue;
+		};
+		if !meta.is_file() || meta.len() == size {
+			continue;
+		}
+		fs::remove_file(&path)
+			.with_context(|| format!("Failed to remove incomplete file '{}'", path.display()))?;
+		removed.push(path);
+	}
+
+	Ok(removed)
+}
+
+/// Get the path to the creation journal of an instance
+pub fn get_journal_path(paths: &Paths, instance: &InstanceID) -> PathBuf {
+	paths
+		.internal
+		.join("creation")
+		.join(format!("{instance}.json"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	struct TestDir {
+		dir: tempfile::TempDir,
+	}
+
+	impl TestDir {
+		fn new() -> Self {
+			Self {
+				dir: tempfile::tempdir().unwrap(),
+			}
+		}
+
+		fn open(&self, interrupt: &'static AtomicBool) -> CreationJournal {
+			CreationJournal::open_at(
+				self.dir.path().join("journal.json"),
+				&"test".into(),
+				interrupt,
+			)
+		}
+
+		fn file(&self, name: &str) -> PathBuf {
+			self.dir.path().join(name)
+		}
+	}
+
+	/// Runs the steps of a fake creation, writing each step's file unless it already exists, like
+	/// the real steps do. The interrupt is requested once `interrupt_after` steps have finished
+	fn create(
+		dir: &TestDir,
+		interrupt: &'static AtomicBool,
+		interrupt_after: Option<usize>,
+	) -> anyhow::Result<Vec<PathBuf>> {
+		let mut journal = dir.open(interrupt);
+		let removed = journal.verify()?;
+		let steps = [
+			(CreationStep::Modifications, "paper.jar", "paper"),
+			(CreationStep::SetupHooks, "server.properties", "properties"),
+			(CreationStep::CoreInstance, "server.jar", "server"),
+		];
+		for (i, (step, file, contents)) in steps.into_iter().enumerate() {
+			if interrupt_after == Some(i) {
+				interrupt.store(true, Ordering::SeqCst);
+			}
+			let path = dir.file(file);
+			journal.begin_step(step, vec![path.clone()], Vec::new())?;
+			if !path.exists() {
+				fs::write(&path, contents)?;
+			}
+			journal.finish_step(step)?;
+		}
+
+		Ok(removed)
+	}
+
+	#[test]
+	fn test_interrupt_between_steps() {
+		static INTERRUPT: AtomicBool = AtomicBool::new(false);
+		let dir = TestDir::new();
+
+		let result = create(&dir, &INTERRUPT, Some(2));
+		let error = result.unwrap_err();
+		assert!(error.is::<CreationInterrupted>());
+		assert!(dir.file("server.properties").exists());
+		assert!(!dir.file("server.jar").exists());
+
+		let journal = dir.open(&INTERRUPT);
+		let steps = &journal.get_contents().steps;
+		assert!(steps[&CreationStep::Modifications].complete);
+		assert!(steps[&CreationStep::SetupHooks].complete);
+		assert!(!steps.contains_key(&CreationStep::CoreInstance));
+
+		// Resuming keeps the files from the finished steps
+		INTERRUPT.store(false, Ordering::SeqCst);
+		let removed = create(&dir, &INTERRUPT, None).unwrap();
+		assert!(removed.is_empty());
+		assert_eq!(
+			fs::read_to_string(dir.file("server.jar")).unwrap(),
+			"server"
+		);
+		let journal = dir.open(&INTERRUPT);
+		assert!(journal.get_contents().steps.values().all(|x| x.complete));
+	}
+
+	#[test]
+	fn test_resume_partial_step() {
+		static INTERRUPT: AtomicBool = AtomicBool::new(false);
+		let dir = TestDir::new();
+
+		// Simulate being killed while a step was writing its file
+		let mut journal = dir.open(&INTERRUPT);
+		journal
+			.begin_step(
+				CreationStep::Modifications,
+				vec![dir.file("paper.jar")],
+				Vec::new(),
+			)
+			.unwrap();
+		fs::write(dir.file("paper.jar"), "pap").unwrap();
+		drop(journal);
+
+		// The partial file exists, but isn't trusted
+		let removed = create(&dir, &INTERRUPT, None).unwrap();
+		assert_eq!(removed, vec![dir.file("paper.jar")]);
+		assert_eq!(fs::read_to_string(dir.file("paper.jar")).unwrap(), "paper");
+	}
+
+	#[test]
+	fn test_verify_changed_file() {
+		static INTERRUPT: AtomicBool = AtomicBool::new(false);
+		let dir = TestDir::new();
+		create(&dir, &INTERRUPT, None).unwrap();
+
+		// A file with the same size but different contents is caught by its hash
+		fs::write(dir.file("server.jar"), "broken").unwrap();
+		fs::remove_file(dir.file("paper.jar")).unwrap();
+		let mut journal = dir.open(&INTERRUPT);
+		let removed = journal.verify().unwrap();
+		assert_eq!(removed, vec![dir.file("server.jar")]);
+		assert!(!dir.file("server.jar").exists());
+		assert!(dir.file("server.properties").exists());
+
+		// A step that is interrupted after verification keeps its verified files
+		journal
+			.begin_step(
+				CreationStep::SetupHooks,
+				vec![dir.file("server.properties")],
+				Vec::new(),
+			)
+			.unwrap();
+		drop(journal);
+		let removed = dir.open(&INTERRUPT).verify().unwrap();
+		assert!(removed.is_empty());
+		assert!(dir.file("server.properties").exists());
+	}
+
+	#[test]
+	fn test_verify_downloads() {
+		static INTERRUPT: AtomicBool = AtomicBool::new(false);
+		let dir = TestDir::new();
+
+		let objects_dir = dir.file("objects");
+		let object = |hash: &str| objects_dir.join(&hash[..2]).join(hash);
+		let index = serde_json::json!({
+			"objects": {
+				"lang/en_us.json": {"hash": "aa01", "size": 5},
+				"sounds/cave1.ogg": {"hash": "bb02", "size": 6},
+			}
+		});
+		json_to_file(dir.file("index.json"), &index).unwrap();
+		for (hash, contents) in [("aa01", "whole"), ("bb02", "cut")] {
+			fs::create_dir_all(object(hash).parent().unwrap()).unwrap();
+			fs::write(object(hash), contents).unwrap();
+		}
+		fs::write(dir.file("library.jar"), "library").unwrap();
+		fs::write(dir.file("natives.jar"), "nat").unwrap();
+
+		// Simulate being killed while the libraries and assets were downloading
+		let mut journal = dir.open(&INTERRUPT);
+		let downloads = vec![
+			JournalDownload::File {
+				path: dir.file("library.jar"),
+				size: 7,
+			},
+			JournalDownload::File {
+				path: dir.file("natives.jar"),
+				size: 7,
+			},
+			JournalDownload::File {
+				path: dir.file("missing.jar"),
+				size: 7,
+			},
+			JournalDownload::Assets {
+				index: dir.file("index.json"),
+				objects_dir: objects_dir.clone(),
+			},
+		];
+		journal
+			.begin_step(CreationStep::CoreInstance, Vec::new(), downloads)
+			.unwrap();
+		drop(journal);
+
+		// Only the files that were cut off are removed
+		let mut removed = dir.open(&INTERRUPT).verify().unwrap();
+		removed.sort();
+		assert_eq!(removed, vec![dir.file("natives.jar"), object("bb02")]);
+		assert!(dir.file("library.jar").exists());
+		assert!(object("aa01").exists());
+
+		// An asset index that was cut off is removed so that it is downloaded again
+		fs::write(dir.file("index.json"), "{\"objects\": {").unwrap();
+		let removed = dir.open(&INTERRUPT).verify().unwrap();
+		assert_eq!(removed, vec![dir.file("index.json")]);
+		assert!(object("aa01").exists());
+	}
+}
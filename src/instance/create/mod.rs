@@ -1,33 +1,45 @@
 /// Creation of the client
 mod client;
+/// Journal of instance creation progress, for resuming interrupted creation
+pub mod journal;
 /// Creation of the server
 mod server;
 
 use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
+use itertools::Itertools;
 use mcvm_core::instance::WindowResolution;
 use mcvm_core::io::java::classpath::Classpath;
 use mcvm_core::io::json_to_file;
+use mcvm_core::io::minecraft::game_jar;
 use mcvm_core::launch::LaunchConfiguration;
+use mcvm_core::net::game_files::assets::{self, get_asset_layout, AssetLayout};
+use mcvm_core::net::game_files::client_meta::ClientMeta;
+use mcvm_core::net::game_files::libraries;
+use mcvm_core::net::stats::{self, TransferPhase};
 use mcvm_core::user::uuid::hyphenate_uuid;
 use mcvm_core::user::{User, UserManager};
 use mcvm_core::version::InstalledVersion;
 use mcvm_core::QuickPlayType;
-use mcvm_mods::fabric_quilt;
+use mcvm_mods::{fabric_quilt, paper, sponge};
 use mcvm_plugin::hooks::{OnInstanceSetup, OnInstanceSetupArg};
-use mcvm_shared::modifications::Modloader;
-use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::modifications::{Modloader, ServerType};
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel, OutputSection};
 use mcvm_shared::translate;
 use mcvm_shared::Side;
 use reqwest::Client;
 
-use crate::config::instance::QuickPlay;
+use crate::config::instance::{ClientWindowConfig, QuickPlay};
 use crate::config::plugin::PluginManager;
+use crate::io::lock::Lockfile;
 use crate::io::paths::Paths;
 
+use journal::{CreationJournal, CreationStep, JournalDownload};
+
+use super::launch::LaunchModifications;
 use super::update::manager::{UpdateManager, UpdateMethodResult, UpdateRequirement};
 use super::{InstKind, Instance};
 
@@ -44,12 +56,14 @@ impl Instance {
 			Modloader::Fabric => {
 				out.insert(UpdateRequirement::FabricQuilt(
 					fabric_quilt::Mode::Fabric,
+					self.config.loader_version.clone(),
 					self.kind.to_side(),
 				));
 			}
 			Modloader::Quilt => {
 				out.insert(UpdateRequirement::FabricQuilt(
 					fabric_quilt::Mode::Quilt,
+					self.config.loader_version.clone(),
 					self.kind.to_side(),
 				));
 			}
@@ -67,51 +81,76 @@ impl Instance {
 	}
 
 	/// Create the data for the instance.
-	pub async fn create<'core>(
+	pub async fn create(
 		&mut self,
-		manager: &'core mut UpdateManager,
+		manager: &mut UpdateManager,
 		plugins: &PluginManager,
 		paths: &Paths,
 		users: &UserManager,
 		client: &Client,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<UpdateMethodResult> {
-		// Start by setting up custom changes
-		let result = match &self.kind {
-			InstKind::Client { .. } => {
+		let ignore = self.load_ignore(paths, o);
+
+		let header = match &self.kind {
+			InstKind::Client { .. } => translate!(o, StartUpdatingClient, "id" = &self.id),
+			InstKind::Server { .. } => translate!(o, StartUpdatingServer, "id" = &self.id),
+		};
+		o.display(MessageContents::Header(header), MessageLevel::Important);
+		// The section is ended even if creation fails partway through
+		let section = OutputSection::new(o);
+		let o = &mut *section.0;
+
+		// Files left behind by an interrupted creation are written again instead of being trusted
+		self.ensure_dirs(paths)?;
+		let mut journal = CreationJournal::open(paths, &self.id);
+		let removed = journal
+			.verify()
+			.context("Failed to check the files from the last creation")?;
+		if !removed.is_empty() {
+			o.display(
+				MessageContents::Notice(format!(
+					"Redownloading {} files that were left incomplete by an interrupted update",
+					removed.len()
+				)),
+				MessageLevel::Important,
+			);
+			for path in removed {
 				o.display(
-					MessageContents::Header(translate!(o, StartUpdatingClient, "id" = &self.id)),
-					MessageLevel::Important,
+					MessageContents::Simple(format!("Removed '{}'", path.display())),
+					MessageLevel::Debug,
 				);
-				o.start_section();
-				let result = self
+			}
+		}
+
+		// Start by setting up custom changes
+		let files = self.get_creation_step_files(CreationStep::Modifications, manager, paths);
+		journal.begin_step(CreationStep::Modifications, files, Vec::new())?;
+		let modifications = async {
+			match &self.kind {
+				InstKind::Client { .. } => self
 					.create_client(manager, paths, users)
 					.await
-					.context("Failed to create client")?;
-				Ok::<_, anyhow::Error>(result)
-			}
-			InstKind::Server { .. } => {
-				o.display(
-					MessageContents::Header(translate!(o, StartUpdatingServer, "id" = &self.id)),
-					MessageLevel::Important,
-				);
-				o.start_section();
-				let result = self
-					.create_server(manager, paths, client, o)
+					.context("Failed to create client"),
+				InstKind::Server { .. } => self
+					.create_server(manager, paths, client, &ignore, o)
 					.await
-					.context("Failed to create server")?;
-				Ok(result)
+					.context("Failed to create server"),
 			}
-		}?;
+		};
+		let result = stats::phase(TransferPhase::Loaders, modifications).await?;
+		journal.finish_step(CreationStep::Modifications)?;
 
 		// Run plugin setup hooks
-		self.ensure_dirs(paths)?;
+		journal.begin_step(CreationStep::SetupHooks, Vec::new(), Vec::new())?;
 		let arg = OnInstanceSetupArg {
 			id: self.id.to_string(),
 			side: Some(self.get_side()),
 			game_dir: self.dirs.get().game_dir.to_string_lossy().to_string(),
 			version_info: manager.version_info.get_clone(),
 			custom_config: self.config.plugin_config.clone(),
+			show_diffs: manager.settings.show_diffs,
+			strict: manager.settings.strict,
 		};
 		let results = plugins
 			.call_hook(OnInstanceSetup, &arg, paths, o)
@@ -119,46 +158,166 @@ impl Instance {
 		for result in results {
 			result.result(o)?;
 		}
+		// The setup hooks can change the level-name in server.properties
+		self.update_world_name(paths);
+
+		// The player lists depend on the online-mode and op-permission-level written by the hooks
+		if let InstKind::Server { .. } = &self.kind {
+			if let Err(e) = self
+				.sync_player_lists(paths, client, manager.settings.sync_exact, &ignore)
+				.await
+			{
+				o.display(
+					MessageContents::Warning(format!("Failed to update whitelist and ops: {e:#}")),
+					MessageLevel::Important,
+				);
+			}
+		}
+		journal.finish_step(CreationStep::SetupHooks)?;
 
 		// Make the core instance
+		let files = self.get_creation_step_files(CreationStep::CoreInstance, manager, paths);
 		let mut version = manager
 			.get_core_version(o)
 			.await
 			.context("Failed to get manager version")?;
-
-		self.create_core_instance(&mut version, paths, o)
-			.await
-			.context("Failed to create core instance")?;
-		o.end_section();
+		let downloads = self
+			.get_core_instance_downloads(version.get_client_meta(), paths)
+			.context("Failed to find the libraries of the Minecraft version")?;
+		journal.begin_step(CreationStep::CoreInstance, files, downloads)?;
+		let assets_version = version.get_client_meta().assets_version.clone();
+
+		let modifications = LaunchModifications::default();
+		self.create_core_instance(
+			&mut version,
+			paths,
+			false,
+			false,
+			&modifications,
+			&ClientWindowConfig::default(),
+			o,
+		)
+		.await
+		.context("Failed to create core instance")?;
+		journal.finish_step(CreationStep::CoreInstance)?;
+
+		self.version_files = self
+			.get_version_files(&assets_version, paths)
+			.context("Failed to find the files for the Minecraft version")?;
 
 		Ok(result)
 	}
 
+	/// Get the files that a step of creation writes, which are checked when creation is resumed
+	fn get_creation_step_files(
+		&self,
+		step: CreationStep,
+		manager: &UpdateManager,
+		paths: &Paths,
+	) -> Vec<PathBuf> {
+		let side = self.kind.to_side();
+		let version = &manager.version_info.get().version;
+		let mut out = Vec::new();
+		match step {
+			CreationStep::Modifications => {
+				if let Some(mode) = self.get_paper_mode() {
+					if let Some(build_num) = manager.paper_build {
+						out.push(paper::get_local_jar_path(
+							mode,
+							version,
+							build_num,
+							&paths.core,
+						));
+					}
+				} else if self.config.modifications.server_type == ServerType::Sponge {
+					out.push(sponge::get_local_jar_path(
+						sponge::Mode::Vanilla,
+						version,
+						&paths.core,
+					));
+				}
+			}
+			CreationStep::SetupHooks => {}
+			CreationStep::CoreInstance => {
+				if self.modification_data.jar_path_override.is_none() {
+					out.push(game_jar::get_path(side, version, None, &paths.core));
+				}
+				if side == Side::Server {
+					out.push(self.dirs.get().game_dir.join("server.jar"));
+				}
+			}
+		}
+		out
+	}
+
+	/// Get the libraries and assets that making the core instance downloads. Only clients use them
+	fn get_core_instance_downloads(
+		&self,
+		client_meta: &ClientMeta,
+		paths: &Paths,
+	) -> anyhow::Result<Vec<JournalDownload>> {
+		if self.kind.to_side() != Side::Client {
+			return Ok(Vec::new());
+		}
+
+		// Libraries without a size in the meta can't be checked
+		let mut out: Vec<_> = libraries::get_files(client_meta, &paths.core)?
+			.into_iter()
+			.filter_map(|x| {
+				Some(JournalDownload::File {
+					size: x.size?,
+					path: x.path,
+				})
+			})
+			.collect();
+		out.push(JournalDownload::Assets {
+			index: assets::get_index_path(&client_meta.assets_version, &paths.core),
+			objects_dir: assets::get_objects_dir_path(&paths.core),
+		});
+
+		Ok(out)
+	}
+
 	/// Ensure the directories are set and exist
 	pub fn ensure_dirs(&mut self, paths: &Paths) -> anyhow::Result<()> {
-		self.dirs
-			.ensure_full(|| InstanceDirs::new(paths, &self.id, &self.kind.to_side()));
+		self.ensure_dirs_set(paths);
 		self.dirs.get().ensure_exist()?;
 
 		Ok(())
 	}
 
+	/// Ensure the directories are set, without creating them
+	pub fn ensure_dirs_set(&mut self, paths: &Paths) {
+		self.dirs
+			.ensure_full(|| InstanceDirs::new(paths, &self.id, &self.kind.to_side()));
+	}
+
 	/// Create the core instance
+	#[allow(clippy::too_many_arguments)]
 	pub(super) async fn create_core_instance<'core>(
 		&mut self,
 		version: &'core mut InstalledVersion<'core, 'core>,
 		paths: &Paths,
+		pipe_stdin: bool,
+		pipe_output: bool,
+		modifications: &LaunchModifications,
+		window_override: &ClientWindowConfig,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<mcvm_core::Instance<'core>> {
 		self.ensure_dirs(paths)?;
 		let side = match &self.kind {
-			InstKind::Client { window, .. } => mcvm_core::InstanceKind::Client {
-				window: mcvm_core::ClientWindowConfig {
-					resolution: window
-						.resolution
-						.map(|x| WindowResolution::new(x.width, x.height)),
-				},
-			},
+			InstKind::Client { window, .. } => {
+				let mut window = window.clone();
+				window.merge(window_override.clone());
+				mcvm_core::InstanceKind::Client {
+					window: mcvm_core::ClientWindowConfig {
+						resolution: window
+							.resolution
+							.map(|x| WindowResolution::new(x.width, x.height)),
+						fullscreen: window.fullscreen,
+					},
+				}
+			}
 			InstKind::Server { .. } => mcvm_core::InstanceKind::Server {
 				create_eula: true,
 				show_gui: false,
@@ -170,33 +329,43 @@ impl Instance {
 			QuickPlay::World { world } => QuickPlayType::World { world },
 			QuickPlay::Realm { realm } => QuickPlayType::Realm { realm },
 		};
-		let wrapper = self
+		// Core applies wrappers from the innermost outwards
+		let wrappers = self
 			.config
 			.launch
-			.wrapper
-			.as_ref()
+			.wrappers
+			.iter()
+			.rev()
 			.map(|x| mcvm_core::WrapperCommand {
 				cmd: x.cmd.clone(),
 				args: x.args.clone(),
-			});
+			})
+			.collect();
+		let mut env = self.get_launch_env();
+		env.extend(modifications.env.clone());
 		let launch_config = LaunchConfiguration {
 			java: self.config.launch.java.clone(),
-			jvm_args: self.config.launch.jvm_args.clone(),
-			game_args: self.config.launch.game_args.clone(),
+			jvm_args: [&self.config.launch.jvm_args[..], &modifications.jvm_args].concat(),
+			game_args: [&self.config.launch.game_args[..], &modifications.game_args].concat(),
 			min_mem: self.config.launch.min_mem.clone(),
 			max_mem: self.config.launch.max_mem.clone(),
-			env: self.config.launch.env.clone(),
-			wrappers: Vec::from_iter(wrapper),
+			env,
+			working_dir: self.get_launch_working_dir(),
+			wrappers,
 			quick_play,
 			use_log4j_config: self.config.launch.use_log4j_config,
+			pipe_stdin,
+			pipe_output,
 		};
+		let mut additional_libs = self.modification_data.classpath_extension.get_paths();
+		additional_libs.extend(modifications.classpath.iter().cloned());
 		let config = mcvm_core::InstanceConfiguration {
 			side,
 			path: self.dirs.get().game_dir.clone(),
 			launch: launch_config,
 			jar_path: self.modification_data.jar_path_override.clone(),
 			main_class: self.modification_data.main_class_override.clone(),
-			additional_libs: self.modification_data.classpath_extension.get_paths(),
+			additional_libs,
 		};
 		let inst = version
 			.get_instance(config, o)
@@ -205,48 +374,92 @@ impl Instance {
 		Ok(inst)
 	}
 
-	/// Removes the paper server jar file from a server instance
-	pub fn remove_paper(&mut self, paths: &Paths, paper_file_name: String) -> anyhow::Result<()> {
+	/// Removes the server.jar that is linked into a server instance, so that the
+	/// stored JAR is linked again on the next update
+	pub fn remove_server_jar(&mut self, paths: &Paths) -> anyhow::Result<()> {
 		self.ensure_dirs(paths)?;
 		let game_dir = &self.dirs.get().game_dir;
-		let paper_path = game_dir.join(paper_file_name);
-		if paper_path.exists() {
-			fs::remove_file(paper_path).context("Failed to remove Paper jar")?;
+		// Jars that replace the server, like Paper's and Sponge's, are linked to server.jar when the core instance is created
+		let jar_path = game_dir.join("server.jar");
+		if jar_path.exists() {
+			fs::remove_file(jar_path).context("Failed to remove server.jar")?;
 		}
 
 		Ok(())
 	}
 
-	/// Removes files such as the game jar for when the profile version changes
-	pub fn teardown(
-		&mut self,
-		paths: &Paths,
-		paper_properties: Option<(u16, String)>,
-	) -> anyhow::Result<()> {
+	/// Removes the files that belong to the old Minecraft version, such as the game jar, for when the
+	/// profile version changes. Only the files recorded in the lockfile when the instance was created
+	/// are removed, so worlds, configs, and other user files are never touched
+	pub fn teardown(&mut self, lock: &Lockfile, paths: &Paths) -> anyhow::Result<()> {
 		self.ensure_dirs(paths)?;
+		let files = match lock.get_instance_version_files(&self.id) {
+			Some(files) => files.to_vec(),
+			// Instances from older versions of mcvm only ever had the game jar
+			None => match self.kind {
+				InstKind::Client { .. } => vec!["client.jar".into()],
+				InstKind::Server { .. } => vec!["server.jar".into()],
+			},
+		};
+		remove_version_files(&self.dirs.get().inst_dir, &files)
+	}
+
+	/// Get the files in the instance that belong to its Minecraft version, relative to the instance directory.
+	/// Files that the game or loader only create once it is launched are included even if they don't
+	/// exist yet. Natives aren't included, since they are extracted to the shared directory of the version
+	fn get_version_files(
+		&self,
+		assets_version: &str,
+		paths: &Paths,
+	) -> anyhow::Result<Vec<String>> {
+		let dirs = self.dirs.get();
+		let game_dir = &dirs.game_dir;
+		let mut out = Vec::new();
 		match self.kind {
 			InstKind::Client { .. } => {
-				let inst_dir = &self.dirs.get().inst_dir;
-				let jar_path = inst_dir.join("client.jar");
-				if jar_path.exists() {
-					fs::remove_file(jar_path).context("Failed to remove client.jar")?;
+				// Old versions read their assets from a directory in the instance
+				if get_asset_layout(assets_version, &paths.core)? == AssetLayout::Resources {
+					out.push(game_dir.join("resources"));
 				}
 			}
 			InstKind::Server { .. } => {
-				let game_dir = &self.dirs.get().game_dir;
-				let jar_path = game_dir.join("server.jar");
-				if jar_path.exists() {
-					fs::remove_file(jar_path).context("Failed to remove server.jar")?;
+				// Jars that replace the server, like Paper's and Sponge's, are also linked to server.jar
+				out.push(game_dir.join("server.jar"));
+				// The server jar of 1.18 and above, along with Paper and Sponge, extracts the game and its libraries
+				out.push(game_dir.join("versions"));
+				out.push(game_dir.join("libraries"));
+				if self.get_paper_mode().is_some() {
+					out.push(game_dir.join("cache"));
 				}
-
-				if let Some((_, file_name)) = paper_properties {
-					self.remove_paper(paths, file_name)
-						.context("Failed to remove Paper")?;
+				// Fabric server launchers are built for a single Minecraft version
+				if let Ok(entries) = game_dir.read_dir() {
+					for entry in entries {
+						let entry = entry?;
+						if fabric_quilt::is_server_launcher_file_name(
+							&entry.file_name().to_string_lossy(),
+						) {
+							out.push(entry.path());
+						}
+					}
 				}
 			}
 		}
 
-		Ok(())
+		// Fabric and Quilt cache the game remapped to intermediary names, along with mods processed for it
+		if let Modloader::Fabric | Modloader::Quilt =
+			self.config.modifications.get_modloader(self.kind.to_side())
+		{
+			out.push(game_dir.join(".fabric"));
+			out.push(game_dir.join(".quilt"));
+		}
+
+		let out = out
+			.iter()
+			.filter_map(|x| x.strip_prefix(&dirs.inst_dir).ok())
+			.map(|x| x.to_string_lossy().to_string())
+			.sorted()
+			.collect();
+		Ok(out)
 	}
 
 	fn get_fabric_quilt(
@@ -266,24 +479,54 @@ impl Instance {
 		Ok(classpath)
 	}
 
-	/// Create a keypair file in the instance
+	/// Create a keypair file in the instance. Only authenticated users have a keypair
 	fn create_keypair(&mut self, user: &User, paths: &Paths) -> anyhow::Result<()> {
-		if let Some(uuid) = user.get_uuid() {
-			if let Some(keypair) = user.get_keypair() {
-				self.ensure_dirs(paths)?;
-				let keys_dir = self.dirs.get().game_dir.join("profilekeys");
-				let hyphenated_uuid = hyphenate_uuid(uuid).context("Failed to hyphenate UUID")?;
-				let path = keys_dir.join(format!("{hyphenated_uuid}.json"));
-				mcvm_core::io::files::create_leading_dirs(&path)?;
-
-				json_to_file(path, keypair).context("Failed to write keypair to file")?;
-			}
+		if !user.is_microsoft() {
+			return Ok(());
 		}
+		let (Some(uuid), Some(keypair)) = (user.get_uuid(), user.get_keypair()) else {
+			return Ok(());
+		};
+
+		self.ensure_dirs(paths)?;
+		let keys_dir = self.dirs.get().game_dir.join("profilekeys");
+		let hyphenated_uuid = hyphenate_uuid(uuid).context("Failed to hyphenate UUID")?;
+		let path = keys_dir.join(format!("{hyphenated_uuid}.json"));
+		mcvm_core::io::files::create_leading_dirs(&path)?;
+
+		json_to_file(path, keypair).context("Failed to write keypair to file")?;
 
 		Ok(())
 	}
 }
 
+/// Remove the files of an instance that belong to its Minecraft version. Paths must be
+/// relative to the instance directory and can't leave it
+fn remove_version_files(inst_dir: &Path, files: &[String]) -> anyhow::Result<()> {
+	for file in files {
+		let relative = Path::new(file);
+		if !relative
+			.components()
+			.all(|x| matches!(x, Component::Normal(..)))
+		{
+			bail!("Version file '{file}' is not inside of the instance directory");
+		}
+		let path = inst_dir.join(relative);
+		// Links are removed without following them
+		let Ok(meta) = path.symlink_metadata() else {
+			continue;
+		};
+		if meta.is_dir() {
+			fs::remove_dir_all(&path)
+		} else {
+			fs::remove_file(&path)
+		}
+		.with_context(|| format!("Failed to remove '{file}'"))?;
+	}
+
+	Ok(())
+}
+
 /// Directories that an instance uses
 #[derive(Debug)]
 pub struct InstanceDirs {
@@ -296,7 +539,7 @@ pub struct InstanceDirs {
 impl InstanceDirs {
 	/// Create a new InstanceDirs
 	pub fn new(paths: &Paths, instance_id: &str, side: &Side) -> Self {
-		let inst_dir = paths.project.data_dir().join("instances").join(instance_id);
+		let inst_dir = paths.instances.join(instance_id);
 
 		let game_dir = match side {
 			Side::Client => inst_dir.join(".minecraft"),
@@ -342,3 +585,118 @@ impl Default for ModificationData {
 		Self::new()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use mcvm_shared::id::InstanceID;
+	use mcvm_shared::output;
+
+	use super::*;
+	use crate::config::Config;
+
+	#[test]
+	fn test_server_version_files() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let mut paths = Paths::new_no_create().unwrap();
+		paths.internal = dir.join("internal");
+		paths.instances = dir.join("instances");
+
+		let deser = serde_json::from_value(serde_json::json!({
+			"instances": {
+				"fabric": { "type": "server", "version": "1.20.1", "modloader": "fabric" },
+				"paper": { "type": "server", "version": "1.20.1", "server_type": "paper" },
+				"sponge": { "type": "server", "version": "1.20.1", "server_type": "sponge" }
+			}
+		}))
+		.unwrap();
+		let mut config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			false,
+			&paths,
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+
+		let fabric = config
+			.instances
+			.get_mut(&InstanceID::from("fabric"))
+			.unwrap();
+		fabric.ensure_dirs(&paths).unwrap();
+		let game_dir = fabric.dirs.get().game_dir.clone();
+		fs::write(
+			game_dir.join("fabric-server-mc.1.20.1-loader.0.15.0-launcher.1.0.0.jar"),
+			"",
+		)
+		.unwrap();
+		fs::write(game_dir.join("my-plugin.jar"), "").unwrap();
+		assert_eq!(
+			fabric.get_version_files("", &paths).unwrap(),
+			[
+				".fabric",
+				".quilt",
+				"fabric-server-mc.1.20.1-loader.0.15.0-launcher.1.0.0.jar",
+				"libraries",
+				"server.jar",
+				"versions",
+			]
+		);
+
+		let paper = config
+			.instances
+			.get_mut(&InstanceID::from("paper"))
+			.unwrap();
+		paper.ensure_dirs(&paths).unwrap();
+		assert_eq!(
+			paper.get_version_files("", &paths).unwrap(),
+			["cache", "libraries", "server.jar", "versions"]
+		);
+
+		// The Sponge jar is linked to server.jar, so it is removed along with the other version files
+		let sponge = config
+			.instances
+			.get_mut(&InstanceID::from("sponge"))
+			.unwrap();
+		sponge.ensure_dirs(&paths).unwrap();
+		assert_eq!(
+			sponge.get_version_files("", &paths).unwrap(),
+			["libraries", "server.jar", "versions"]
+		);
+	}
+
+	#[test]
+	fn test_remove_version_files() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let files = [
+			"server.jar",
+			"resources/sound/step.ogg",
+			"world/level.dat",
+			"config/mod.toml",
+			"screenshots/2024-01-01.png",
+			"server.properties",
+			"notes.txt",
+		];
+		for file in files {
+			let path = dir.join(file);
+			fs::create_dir_all(path.parent().unwrap()).unwrap();
+			fs::write(path, "foo").unwrap();
+		}
+
+		let version_files = vec![
+			"server.jar".to_string(),
+			"resources".to_string(),
+			"missing.jar".to_string(),
+		];
+		remove_version_files(dir, &version_files).unwrap();
+		let outside = remove_version_files(dir, &["../notes.txt".to_string()]);
+
+		let exists = files.map(|x| dir.join(x).exists());
+		let resources_exists = dir.join("resources").exists();
+
+		assert!(outside.is_err());
+		assert!(!resources_exists);
+		assert_eq!(exists, [false, false, true, true, true, true, true]);
+	}
+}
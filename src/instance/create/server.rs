@@ -1,5 +1,6 @@
 use anyhow::Context;
 use mcvm_core::io::java::classpath::Classpath;
+use mcvm_mods::fabric_quilt::{self, LoaderVersion};
 use mcvm_mods::paper;
 use mcvm_mods::sponge;
 use mcvm_shared::modifications::{Modloader, ServerType};
@@ -8,16 +9,27 @@ use reqwest::Client;
 
 use crate::io::paths::Paths;
 
+use super::super::ignore::InstanceIgnore;
 use super::super::update::manager::{UpdateManager, UpdateMethodResult};
 use super::{InstKind, Instance};
 
 impl Instance {
+	/// Get which PaperMC project this instance uses for its server, if any
+	pub fn get_paper_mode(&self) -> Option<paper::Mode> {
+		match self.config.modifications.server_type {
+			ServerType::Paper => Some(paper::Mode::Paper),
+			ServerType::Folia => Some(paper::Mode::Folia),
+			_ => None,
+		}
+	}
+
 	/// Create a server
 	pub async fn create_server(
 		&mut self,
 		manager: &UpdateManager,
 		paths: &Paths,
 		client: &Client,
+		ignore: &InstanceIgnore,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<UpdateMethodResult> {
 		debug_assert!(matches!(self.kind, InstKind::Server { .. }));
@@ -26,6 +38,13 @@ impl Instance {
 
 		self.ensure_dirs(paths)?;
 
+		if let Err(e) = self.install_server_icon(ignore) {
+			o.display(
+				MessageContents::Warning(format!("Failed to install server icon: {e:#}")),
+				MessageLevel::Important,
+			);
+		}
+
 		// Initialize the classpath based on the modifications we are using
 		let classpath = if let Modloader::Fabric | Modloader::Quilt =
 			self.config.modifications.get_modloader(self.kind.to_side())
@@ -36,6 +55,16 @@ impl Instance {
 			Classpath::new()
 		};
 
+		if let Some(installer_version) = self.config.installer_version.clone() {
+			if self.config.modifications.get_modloader(self.kind.to_side()) == Modloader::Fabric {
+				let result = self
+					.create_fabric_server_launcher(&installer_version, manager, paths, client, o)
+					.await
+					.context("Failed to create Fabric server launcher")?;
+				out.merge(result);
+			}
+		}
+
 		match self.config.modifications.server_type {
 			ServerType::Paper => {
 				let result = self
@@ -66,6 +95,66 @@ impl Instance {
 		Ok(out)
 	}
 
+	/// Download the standalone Fabric server launcher for the installer version into the server
+	/// directory, so that the server can also be started without mcvm. Launchers for other versions are removed
+	async fn create_fabric_server_launcher(
+		&mut self,
+		installer_version: &LoaderVersion,
+		manager: &UpdateManager,
+		paths: &Paths,
+		client: &Client,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<UpdateMethodResult> {
+		let versions = fabric_quilt::get_installer_versions(client).await?;
+		let installer_version =
+			fabric_quilt::select_installer_version(&versions, installer_version)?;
+		let file_name = fabric_quilt::get_server_launcher_file_name(
+			&manager.version_info.get().version,
+			manager.fq_meta.get().get_loader_version(),
+			installer_version,
+		);
+
+		self.ensure_dirs(paths)?;
+		let game_dir = &self.dirs.get().game_dir;
+		for entry in game_dir
+			.read_dir()
+			.context("Failed to read server directory")?
+		{
+			let entry = entry?;
+			let name = entry.file_name();
+			let name = name.to_string_lossy();
+			if fabric_quilt::is_server_launcher_file_name(&name) && name != file_name {
+				std::fs::remove_file(entry.path())
+					.context("Failed to remove old Fabric server launcher")?;
+			}
+		}
+
+		let path = game_dir.join(&file_name);
+		if manager.should_update_file(&path) {
+			let process = OutputProcess::new(o);
+			process.0.display(
+				MessageContents::StartProcess(format!(
+					"Downloading Fabric server launcher {installer_version}"
+				)),
+				MessageLevel::Important,
+			);
+			fabric_quilt::download_server_launcher(
+				&manager.version_info.get().version,
+				manager.fq_meta.get().get_loader_version(),
+				installer_version,
+				&path,
+				client,
+			)
+			.await?;
+			process.0.display(
+				MessageContents::Success("Fabric server launcher downloaded".into()),
+				MessageLevel::Important,
+			);
+		}
+
+		Ok(UpdateMethodResult::from_path(path))
+	}
+
 	/// Create data for Paper or Folia on the server
 	async fn create_paper_folia(
 		&mut self,
@@ -79,17 +168,19 @@ impl Instance {
 
 		let process = OutputProcess::new(o);
 		process.0.display(
-			MessageContents::StartProcess("Checking for {mode} updates".into()),
+			MessageContents::StartProcess(format!("Checking for {mode} updates")),
 			MessageLevel::Important,
 		);
 
-		let build_num = paper::get_newest_build(mode, version, client)
-			.await
-			.context("Failed to get the newest {mode} version")?;
-		let file_name = paper::get_jar_file_name(mode, version, build_num, client)
-			.await
-			.context("Failed to get the {mode} file name")?;
-		let paper_jar_path = paper::get_local_jar_path(mode, version, &paths.core);
+		let build_num = if let Some(build_num) = manager.paper_build {
+			build_num
+		} else {
+			paper::get_newest_build(mode, version, client)
+				.await
+				.with_context(|| format!("Failed to get the newest {mode} build"))?
+		};
+		// Builds are stored in a shared location, so they only have to be downloaded once
+		let paper_jar_path = paper::get_local_jar_path(mode, version, build_num, &paths.core);
 		if !manager.should_update_file(&paper_jar_path) {
 			process.0.display(
 				MessageContents::Success(format!("{mode} is up to date")),
@@ -97,21 +188,17 @@ impl Instance {
 			);
 		} else {
 			process.0.display(
-				MessageContents::StartProcess("Downloading {mode} server".into()),
+				MessageContents::StartProcess(format!("Downloading {mode} server")),
 				MessageLevel::Important,
 			);
-			paper::download_server_jar(
-				paper::Mode::Paper,
-				version,
-				build_num,
-				&file_name,
-				&paths.core,
-				client,
-			)
-			.await
-			.context("Failed to download {mode} server JAR")?;
+			let file_name = paper::get_jar_file_name(mode, version, build_num, client)
+				.await
+				.with_context(|| format!("Failed to get the {mode} file name"))?;
+			paper::download_server_jar(mode, version, build_num, &file_name, &paths.core, client)
+				.await
+				.with_context(|| format!("Failed to download {mode} server JAR"))?;
 			process.0.display(
-				MessageContents::Success("{mode} server downloaded".into()),
+				MessageContents::Success(format!("{mode} server downloaded")),
 				MessageLevel::Important,
 			);
 		}
@@ -137,9 +224,13 @@ impl Instance {
 			MessageLevel::Important,
 		);
 
-		let sponge_version = sponge::get_newest_version(sponge::Mode::Vanilla, version, client)
-			.await
-			.context("Failed to get newest Sponge version")?;
+		let sponge_version = if let Some(sponge_version) = &manager.sponge_version {
+			sponge_version.clone()
+		} else {
+			sponge::get_newest_version(sponge::Mode::Vanilla, version, client)
+				.await
+				.context("Failed to get newest Sponge version")?
+		};
 		let sponge_jar_path =
 			sponge::get_local_jar_path(sponge::Mode::Vanilla, version, &paths.core);
 		if !manager.should_update_file(&sponge_jar_path) {
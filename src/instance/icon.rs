@@ -0,0 +1,189 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use mcvm_shared::Side;
+
+use super::ignore::InstanceIgnore;
+use super::{InstKind, Instance};
+
+/// The width and height that servers require for their icon in the multiplayer server list
+pub const SERVER_ICON_SIZE: u32 = 64;
+/// The file in the server directory that the server reads its icon from
+pub const SERVER_ICON_FILE_NAME: &str = "server-icon.png";
+
+/// The signature at the start of every PNG file
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Read the width and height of a PNG image from its header
+pub fn get_png_size(data: &[u8]) -> anyhow::Result<(u32, u32)> {
+	if data.len() < 24 || data[..8] != PNG_SIGNATURE {
+		bail!("File is not a PNG image");
+	}
+	// The header chunk always comes first, after its length and type
+	if &data[12..16] != b"IHDR" {
+		bail!("PNG image is missing its header");
+	}
+	let width = u32::from_be_bytes(data[16..20].try_into().expect("Slice should be 4 bytes"));
+	let height = u32::from_be_bytes(data[20..24].try_into().expect("Slice should be 4 bytes"));
+
+	Ok((width, height))
+}
+
+/// Check that an icon can be used for an instance. Returns a warning if the icon
+/// is valid but won't be shown everywhere
+pub fn check_icon(path: &Path, side: Side) -> anyhow::Result<Option<String>> {
+	let data = fs::read(path).context("Failed to read icon")?;
+	let (width, height) = get_png_size(&data)?;
+	if side == Side::Server && !is_server_icon_size(width, height) && !cfg!(feature = "icon_resize")
+	{
+		return Ok(Some(format!(
+			"Server icons must be {SERVER_ICON_SIZE}x{SERVER_ICON_SIZE} pixels, but this one is {width}x{height}. It won't be shown in the server list"
+		)));
+	}
+
+	Ok(None)
+}
+
+/// Check whether an image has the size required for server icons
+fn is_server_icon_size(width: u32, height: u32) -> bool {
+	width == SERVER_ICON_SIZE && height == SERVER_ICON_SIZE
+}
+
+/// Get the contents of the server icon file from an icon image. Icons with the wrong
+/// size are resized when the `icon_resize` feature is enabled, and are an error otherwise
+pub fn get_server_icon_data(data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+	let (width, height) = get_png_size(&data)?;
+	if is_server_icon_size(width, height) {
+		return Ok(data);
+	}
+
+	#[cfg(feature = "icon_resize")]
+	{
+		resize_server_icon(&data).context("Failed to resize icon")
+	}
+	#[cfg(not(feature = "icon_resize"))]
+	{
+		bail!(
+			"Server icons must be {SERVER_ICON_SIZE}x{SERVER_ICON_SIZE} pixels, but this one is {width}x{height}"
+		);
+	}
+}
+
+/// Scale a PNG image to the server icon size, cropping the middle of it if it isn't square
+#[cfg(feature = "icon_resize")]
+fn resize_server_icon(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+	use image::imageops::FilterType;
+	use image::ImageFormat;
+
+	let image = image::load_from_memory_with_format(data, ImageFormat::Png)
+		.context("Failed to decode image")?;
+	let image = image.resize_to_fill(SERVER_ICON_SIZE, SERVER_ICON_SIZE, FilterType::Lanczos3);
+
+	let mut out = std::io::Cursor::new(Vec::new());
+	image
+		.write_to(&mut out, ImageFormat::Png)
+		.context("Failed to encode image")?;
+
+	Ok(out.into_inner())
+}
+
+impl Instance {
+	/// Copy the icon of this server into its directory so that it is shown in the
+	/// multiplayer server list. Ignored icon files are left alone
+	pub fn install_server_icon(&self, ignore: &InstanceIgnore) -> anyhow::Result<()> {
+		debug_assert!(matches!(self.kind, InstKind::Server { .. }));
+
+		let Some(icon) = &self.config.icon else {
+			return Ok(());
+		};
+
+		let path = self.dirs.get().game_dir.join(SERVER_ICON_FILE_NAME);
+		if ignore.is_ignored(&path) {
+			return Ok(());
+		}
+		let data = fs::read(icon).context("Failed to read icon")?;
+		let data = get_server_icon_data(data)?;
+		if fs::read(&path).is_ok_and(|x| x == data) {
+			return Ok(());
+		}
+		fs::write(path, data).context("Failed to write server icon")?;
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Create the start of a PNG file with the given size
+	fn png_header(width: u32, height: u32) -> Vec<u8> {
+		let mut out = PNG_SIGNATURE.to_vec();
+		out.extend(13u32.to_be_bytes());
+		out.extend(b"IHDR");
+		out.extend(width.to_be_bytes());
+		out.extend(height.to_be_bytes());
+		out.extend([8, 6, 0, 0, 0]);
+		out
+	}
+
+	#[test]
+	fn test_png_size() {
+		assert_eq!(get_png_size(&png_header(64, 32)).unwrap(), (64, 32));
+		assert!(get_png_size(b"GIF89a").is_err());
+		assert!(get_png_size(&png_header(64, 64)[..20]).is_err());
+
+		let mut data = png_header(64, 64);
+		data[12..16].copy_from_slice(b"IDAT");
+		assert!(get_png_size(&data).is_err());
+	}
+
+	#[test]
+	fn test_check_icon() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+
+		let path = dir.join("icon.png");
+		fs::write(&path, png_header(64, 64)).unwrap();
+		assert_eq!(check_icon(&path, Side::Server).unwrap(), None);
+
+		fs::write(&path, png_header(128, 128)).unwrap();
+		assert_eq!(check_icon(&path, Side::Client).unwrap(), None);
+		// Icons with the wrong size can only be used by servers if they can be resized
+		assert_eq!(
+			check_icon(&path, Side::Server).unwrap().is_some(),
+			cfg!(not(feature = "icon_resize"))
+		);
+
+		fs::write(&path, "not an image").unwrap();
+		assert!(check_icon(&path, Side::Client).is_err());
+		assert!(check_icon(&dir.join("missing.png"), Side::Client).is_err());
+	}
+
+	#[test]
+	fn test_server_icon_data() {
+		let data = png_header(64, 64);
+		assert_eq!(get_server_icon_data(data.clone()).unwrap(), data);
+		assert!(get_server_icon_data(b"not an image".to_vec()).is_err());
+
+		#[cfg(not(feature = "icon_resize"))]
+		assert!(get_server_icon_data(png_header(128, 96)).is_err());
+	}
+
+	#[cfg(feature = "icon_resize")]
+	#[test]
+	fn test_resize_server_icon() {
+		let image = image::RgbaImage::from_pixel(128, 96, image::Rgba([255, 0, 0, 255]));
+		let mut data = std::io::Cursor::new(Vec::new());
+		image.write_to(&mut data, image::ImageFormat::Png).unwrap();
+
+		let resized = get_server_icon_data(data.into_inner()).unwrap();
+		assert_eq!(get_png_size(&resized).unwrap(), (64, 64));
+		let resized = image::load_from_memory(&resized).unwrap().to_rgba8();
+		assert_eq!(resized.get_pixel(32, 32), &image::Rgba([255, 0, 0, 255]));
+
+		// Headers that claim the wrong size but aren't real images can't be resized
+		assert!(get_server_icon_data(png_header(128, 96)).is_err());
+	}
+}
@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+
+use crate::io::paths::Paths;
+
+use super::create::InstanceDirs;
+use super::Instance;
+
+/// The file in the game directory of an instance that lists the paths MCVM must not touch
+pub const IGNORE_FILE_NAME: &str = ".mcvmignore";
+
+/// Paths in the game directory of an instance that are protected from MCVM, using gitignore-style
+/// patterns from the .mcvmignore file. Ignored files are never deleted, overwritten, or reported as orphans
+#[derive(Debug, Clone)]
+pub struct InstanceIgnore {
+	/// The game directory that the patterns are relative to
+	root: PathBuf,
+	/// The matcher for the patterns
+	matcher: Gitignore,
+}
+
+impl InstanceIgnore {
+	/// Load the .mcvmignore file from a game directory. Invalid patterns are skipped with a warning
+	pub fn load(game_dir: &Path, o: &mut impl MCVMOutput) -> Self {
+		let path = game_dir.join(IGNORE_FILE_NAME);
+		let contents = match std::fs::read_to_string(&path) {
+			Ok(contents) => contents,
+			Err(e) => {
+				if e.kind() != std::io::ErrorKind::NotFound {
+					o.display(
+						MessageContents::Warning(format!("Failed to read {IGNORE_FILE_NAME}: {e}")),
+						MessageLevel::Important,
+					);
+				}
+				return Self::default();
+			}
+		};
+
+		let (out, errors) = Self::parse(game_dir, &contents);
+		for error in errors {
+			o.display(MessageContents::Warning(error), MessageLevel::Important);
+		}
+
+		out
+	}
+
+	/// Parse the contents of an ignore file, returning errors for the lines with invalid patterns
+	fn parse(game_dir: &Path, contents: &str) -> (Self, Vec<String>) {
+		let mut builder = GitignoreBuilder::new(game_dir);
+		let mut errors = Vec::new();
+		for (i, line) in contents.lines().enumerate() {
+			if let Err(e) = builder.add_line(None, line) {
+				errors.push(format!(
+					"Invalid pattern on line {} of {IGNORE_FILE_NAME}: {e}",
+					i + 1
+				));
+			}
+		}
+
+		match builder.build() {
+			Ok(matcher) => (
+				Self {
+					root: game_dir.to_owned(),
+					matcher,
+				},
+				errors,
+			),
+			Err(e) => {
+				errors.push(format!("Failed to load {IGNORE_FILE_NAME}: {e}"));
+				(Self::default(), errors)
+			}
+		}
+	}
+
+	/// Check whether a path is protected from MCVM. Paths outside of the game directory are never ignored
+	pub fn is_ignored(&self, path: &Path) -> bool {
+		if self.matcher.is_empty() {
+			return false;
+		}
+		let Ok(relative) = path.strip_prefix(&self.root) else {
+			return false;
+		};
+		if relative.as_os_str().is_empty() {
+			return false;
+		}
+
+		self.matcher
+			.matched_path_or_any_parents(relative, path.is_dir())
+			.is_ignore()
+	}
+}
+
+impl Default for InstanceIgnore {
+	/// An ignore that doesn't protect any paths
+	fn default() -> Self {
+		Self {
+			root: PathBuf::new(),
+			matcher: Gitignore::empty(),
+		}
+	}
+}
+
+impl Instance {
+	/// Load the .mcvmignore file of this instance. This should be done once at the start
+	/// of every operation that changes files in the instance
+	pub fn load_ignore(&self, paths: &Paths, o: &mut impl MCVMOutput) -> InstanceIgnore {
+		let dirs = InstanceDirs::new(paths, &self.id, &self.get_side());
+		InstanceIgnore::load(&dirs.game_dir, o)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_ignore_patterns() {
+		let root = Path::new("/instance");
+		let contents = "# My own mods\nmods/sodium.jar\nconfig/\n*.local.json\n!keep.local.json\n";
+		let (ignore, errors) = InstanceIgnore::parse(root, contents);
+		assert!(errors.is_empty());
+
+		assert!(ignore.is_ignored(&root.join("mods/sodium.jar")));
+		assert!(!ignore.is_ignored(&root.join("mods/lithium.jar")));
+		assert!(ignore.is_ignored(&root.join("config/sodium.json")));
+		assert!(ignore.is_ignored(&root.join("config/nested/file.txt")));
+		assert!(ignore.is_ignored(&root.join("mods/settings.local.json")));
+		assert!(!ignore.is_ignored(&root.join("keep.local.json")));
+		assert!(!ignore.is_ignored(root));
+		assert!(!ignore.is_ignored(Path::new("/other/mods/sodium.jar")));
+	}
+
+	#[test]
+	fn test_ignore_errors() {
+		let root = Path::new("/instance");
+		let (ignore, errors) = InstanceIgnore::parse(root, "mods/a.jar\nmods/[z-a].jar\nb.jar");
+		assert_eq!(errors.len(), 1);
+		assert!(errors[0].contains("line 2"), "{}", errors[0]);
+		// The other patterns still work
+		assert!(ignore.is_ignored(&root.join("mods/a.jar")));
+		assert!(ignore.is_ignored(&root.join("b.jar")));
+	}
+}
@@ -0,0 +1,673 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use mcvm_core::io::files::dir_symlink;
+use mcvm_core::io::json_from_file;
+use mcvm_core::util::versions::{MinecraftLatestVersion, MinecraftVersionDeser};
+use mcvm_options::client::{read_client_options, read_options_txt, ClientOptions};
+use mcvm_shared::id::InstanceID;
+use mcvm_shared::modifications::Modloader;
+use mcvm_shared::Side;
+use serde::Deserialize;
+
+use crate::config::instance::{CommonInstanceConfig, InstanceConfig};
+use crate::config::modifications::ConfigModification;
+use crate::config::profile::ProfileConfig;
+use crate::config::ConfigDeser;
+
+use super::snapshot::{copy_recursive, is_non_empty_dir};
+
+/// Files and folders in a .minecraft folder that belong to the launcher instead of the game,
+/// which are never imported
+const LAUNCHER_FILES: &[&str] = &[
+	"assets",
+	"bin",
+	"libraries",
+	"logs",
+	"runtime",
+	"versions",
+	"webcache2",
+	"launcher_accounts.json",
+	"launcher_accounts_microsoft_store.json",
+	"launcher_cef_log.txt",
+	"launcher_entitlements.json",
+	"launcher_gamer_pics.json",
+	"launcher_log.txt",
+	"launcher_msa_credentials.bin",
+	"launcher_product_state.json",
+	"launcher_profiles.json",
+	"launcher_settings.json",
+	"launcher_skins.json",
+	"launcher_ui_state.json",
+	"treatment_tags.json",
+	"usercache.json",
+];
+
+/// A launcher that instances can be imported from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportSource {
+	/// A MultiMC or Prism Launcher instance folder
+	Prism,
+	/// The .minecraft folder of the official launcher
+	DotMinecraft,
+}
+
+impl FromStr for ImportSource {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"prism" | "multimc" => Ok(Self::Prism),
+			"dotminecraft" | ".minecraft" | "vanilla" => Ok(Self::DotMinecraft),
+			other => bail!("Unknown import source '{other}'. Use prism or dotminecraft"),
+		}
+	}
+}
+
+/// One way that a source instance could be set up in MCVM. A source can have more than
+/// one of these when it lists multiple loaders or launcher profiles
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportCandidate {
+	/// The display name of the source instance
+	pub name: Option<String>,
+	/// The Minecraft version
+	pub version: MinecraftVersionDeser,
+	/// The modloader
+	pub modloader: Modloader,
+	/// The version of the Fabric or Quilt loader
+	pub loader_version: Option<String>,
+	/// The game directory to import files from
+	pub game_dir: PathBuf,
+}
+
+impl Display for ImportCandidate {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let version = match &self.version {
+			MinecraftVersionDeser::Version(version) => version.to_string(),
+			MinecraftVersionDeser::Latest(MinecraftLatestVersion::Release) => "latest".into(),
+			MinecraftVersionDeser::Latest(MinecraftLatestVersion::Snapshot) => {
+				"latest snapshot".into()
+			}
+			MinecraftVersionDeser::Custom { custom } => format!("custom version {}", custom.json),
+		};
+		write!(f, "Minecraft {version}")?;
+		if self.modloader != Modloader::Vanilla {
+			write!(f, " with {}", self.modloader)?;
+			if let Some(loader_version) = &self.loader_version {
+				write!(f, " {loader_version}")?;
+			}
+		}
+		if let Some(name) = &self.name {
+			write!(f, " ({name})")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Read the ways that an instance of another launcher could be imported
+pub fn read_candidates(source: ImportSource, path: &Path) -> anyhow::Result<Vec<ImportCandidate>> {
+	if !path.is_dir() {
+		bail!("{} is not a directory", path.display());
+	}
+	match source {
+		ImportSource::Prism => read_prism_candidates(path),
+		ImportSource::DotMinecraft => read_dotminecraft_candidates(path),
+	}
+}
+
+/// Get the candidates that use a modloader, or all of them if no modloader is given
+pub fn filter_candidates(
+	candidates: Vec<ImportCandidate>,
+	modloader: Option<&Modloader>,
+) -> Vec<ImportCandidate> {
+	match modloader {
+		Some(modloader) => candidates
+			.into_iter()
+			.filter(|x| &x.modloader == modloader)
+			.collect(),
+		None => candidates,
+	}
+}
+
+/// Pick the only candidate that uses a modloader, failing if the choice is ambiguous
+pub fn pick_candidate(
+	candidates: Vec<ImportCandidate>,
+	modloader: Option<&Modloader>,
+) -> anyhow::Result<ImportCandidate> {
+	let mut candidates = filter_candidates(candidates, modloader);
+	match candidates.len() {
+		0 => match modloader {
+			Some(modloader) => bail!("The instance does not use the {modloader} modloader"),
+			None => bail!("Could not find anything to import"),
+		},
+		1 => Ok(candidates.remove(0)),
+		_ => {
+			let list = candidates
+				.iter()
+				.map(|x| format!("\n - {x}"))
+				.collect::<String>();
+			bail!("The instance can be imported in more than one way. Pick a modloader with --modloader:{list}")
+		}
+	}
+}
+
+/// Get an instance ID from the name of the folder being imported
+pub fn get_default_id(path: &Path) -> Option<InstanceID> {
+	let name = path.file_name()?.to_string_lossy();
+	let id: String = name
+		.trim_start_matches('.')
+		.chars()
+		.map(|c| {
+			if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') {
+				c.to_ascii_lowercase()
+			} else {
+				'-'
+			}
+		})
+		.collect();
+	if id.is_empty() {
+		None
+	} else {
+		Some(id.into())
+	}
+}
+
+/// Create the config changes that add an imported instance, along with a profile with the same ID
+/// that holds its version and modloader
+pub fn get_import_modifications(
+	config: &ConfigDeser,
+	id: &InstanceID,
+	candidate: &ImportCandidate,
+	options: Option<&ClientOptions>,
+) -> anyhow::Result<Vec<ConfigModification>> {
+	if config.profiles.contains_key(&**id) {
+		bail!("A profile with the ID '{id}' already exists");
+	}
+
+	let profile = ProfileConfig {
+		instance: InstanceConfig {
+			side: None,
+			name: None,
+			description: None,
+			icon: None,
+			whitelist: None,
+			ops: None,
+			user: None,
+			common: CommonInstanceConfig {
+				version: Some(candidate.version.clone()),
+				modloader: Some(candidate.modloader.clone()),
+				loader_version: candidate.loader_version.clone(),
+				..Default::default()
+			},
+			window: Default::default(),
+		},
+		packages: Default::default(),
+	};
+
+	let mut plugin_config = serde_json::Map::new();
+	if let Some(options) = options {
+		let options =
+			serde_json::to_value(options).context("Failed to serialize imported options")?;
+		plugin_config.insert("options".into(), options);
+	}
+	let instance = InstanceConfig {
+		side: Some(Side::Client),
+		name: candidate.name.clone(),
+		description: None,
+		icon: None,
+		whitelist: None,
+		ops: None,
+		user: None,
+		common: CommonInstanceConfig {
+			from: Some(id.to_string()),
+			plugin_config,
+			..Default::default()
+		},
+		window: Default::default(),
+	};
+
+	Ok(vec![
+		ConfigModification::AddProfile(id.to_string().into(), profile),
+		ConfigModification::AddInstance(id.clone(), instance),
+	])
+}
+
+/// Read the options.txt of a candidate into options, if it has one
+pub fn read_candidate_options(
+	candidate: &ImportCandidate,
+) -> anyhow::Result<Option<ClientOptions>> {
+	let path = candidate.game_dir.join("options.txt");
+	if !path.exists() {
+		return Ok(None);
+	}
+	let keys = read_options_txt(&path)?;
+
+	Ok(Some(read_client_options(&keys)))
+}
+
+/// The files that were brought into an instance
+#[derive(Debug, Default)]
+pub struct ImportedFiles {
+	/// The file names of the mods that were imported. MCVM doesn't manage these
+	pub mods: Vec<String>,
+}
+
+/// Copy the files in the game directory of a candidate into the game directory of a new instance.
+/// When linking, the folders are linked instead so that both launchers share them
+pub fn import_files(
+	candidate: &ImportCandidate,
+	game_dir: &Path,
+	link: bool,
+) -> anyhow::Result<ImportedFiles> {
+	if is_non_empty_dir(game_dir) {
+		bail!(
+			"The directory {} already has files in it",
+			game_dir.display()
+		);
+	}
+	fs::create_dir_all(game_dir).context("Failed to create game directory")?;
+
+	for entry in fs::read_dir(&candidate.game_dir).context("Failed to read game directory")? {
+		let entry = entry?;
+		let name = entry.file_name();
+		if LAUNCHER_FILES.contains(&name.to_string_lossy().as_ref()) {
+			continue;
+		}
+		let src = entry.path();
+		let dest = game_dir.join(&name);
+		if link && src.is_dir() {
+			dir_symlink(&src, &dest)
+				.with_context(|| format!("Failed to link {}", src.display()))?;
+		} else {
+			copy_recursive(&src, &dest)
+				.with_context(|| format!("Failed to copy {}", src.display()))?;
+		}
+	}
+
+	let mut out = ImportedFiles::default();
+	if let Ok(mods) = fs::read_dir(game_dir.join("mods")) {
+		out.mods = mods
+			.filter_map(|x| x.ok())
+			.filter(|x| x.path().is_file())
+			.map(|x| x.file_name().to_string_lossy().to_string())
+			.collect();
+		out.mods.sort();
+	}
+
+	Ok(out)
+}
+
+/// The mmc-pack.json file of a MultiMC or Prism instance
+#[derive(Deserialize)]
+struct MMCPack {
+	#[serde(default)]
+	components: Vec<MMCComponent>,
+}
+
+/// A component in an mmc-pack.json file
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MMCComponent {
+	uid: String,
+	version: Option<String>,
+	cached_version: Option<String>,
+}
+
+fn read_prism_candidates(path: &Path) -> anyhow::Result<Vec<ImportCandidate>> {
+	let cfg = fs::read_to_string(path.join("instance.cfg"))
+		.context("Failed to read instance.cfg. Is this a MultiMC or Prism instance folder?")?;
+	let cfg = parse_instance_cfg(&cfg);
+
+	let game_dir = [".minecraft", "minecraft"]
+		.into_iter()
+		.map(|x| path.join(x))
+		.find(|x| x.is_dir())
+		.context("The instance does not have a .minecraft folder")?;
+
+	let pack_path = path.join("mmc-pack.json");
+	let pack: MMCPack = if pack_path.exists() {
+		json_from_file(pack_path).context("Failed to read mmc-pack.json")?
+	} else {
+		MMCPack {
+			components: Vec::new(),
+		}
+	};
+
+	let mut minecraft_version = None;
+	let mut loaders = Vec::new();
+	for component in pack.components {
+		let version = component.version.or(component.cached_version);
+		if component.uid == "net.minecraft" {
+			minecraft_version = version;
+		} else if let Some(modloader) = get_component_modloader(&component.uid) {
+			loaders.push((modloader, version));
+		}
+	}
+	// Old MultiMC instances store the version in instance.cfg instead
+	let minecraft_version = minecraft_version
+		.or_else(|| cfg.get("IntendedVersion").cloned())
+		.context("The instance does not list a Minecraft version")?;
+
+	if loaders.is_empty() {
+		loaders.push((Modloader::Vanilla, None));
+	}
+	let name = cfg.get("name").filter(|x| !x.is_empty()).cloned();
+	let version = MinecraftVersionDeser::Version(minecraft_version.into());
+	let out = loaders
+		.into_iter()
+		.map(|(modloader, loader_version)| ImportCandidate {
+			name: name.clone(),
+			version: version.clone(),
+			loader_version: loader_version.filter(|_| uses_loader_version(&modloader)),
+			modloader,
+			game_dir: game_dir.clone(),
+		})
+		.collect();
+
+	Ok(out)
+}
+
+/// Parse the keys of an instance.cfg file, ignoring the section headers
+fn parse_instance_cfg(contents: &str) -> HashMap<String, String> {
+	contents
+		.lines()
+		.filter(|x| !x.starts_with('['))
+		.filter_map(|x| x.split_once('='))
+		.map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+		.collect()
+}
+
+/// Get the modloader that a MultiMC component installs
+fn get_component_modloader(uid: &str) -> Option<Modloader> {
+	match uid {
+		"net.fabricmc.fabric-loader" => Some(Modloader::Fabric),
+		"org.quiltmc.quilt-loader" => Some(Modloader::Quilt),
+		"net.minecraftforge" => Some(Modloader::Forge),
+		"net.neoforged" => Some(Modloader::NeoForged),
+		"com.mumfrey.liteloader" => Some(Modloader::LiteLoader),
+		_ => None,
+	}
+}
+
+/// Whether MCVM can install a specific version of the loader
+fn uses_loader_version(modloader: &Modloader) -> bool {
+	matches!(modloader, Modloader::Fabric | Modloader::Quilt)
+}
+
+/// The launcher_profiles.json file of the official launcher
+#[derive(Deserialize)]
+struct LauncherProfiles {
+	#[serde(default)]
+	profiles: HashMap<String, LauncherProfile>,
+}
+
+/// A profile in launcher_profiles.json
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LauncherProfile {
+	#[serde(default)]
+	name: String,
+	#[serde(rename = "type")]
+	#[serde(default)]
+	kind: String,
+	last_version_id: Option<String>,
+	game_dir: Option<PathBuf>,
+}
+
+/// A version JSON file in the versions folder. Modded versions inherit from the game version
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VersionFile {
+	inherits_from: Option<String>,
+}
+
+fn read_dotminecraft_candidates(path: &Path) -> anyhow::Result<Vec<ImportCandidate>> {
+	let profiles: LauncherProfiles = json_from_file(path.join("launcher_profiles.json"))
+		.context("Failed to read launcher_profiles.json. Is this a .minecraft folder?")?;
+
+	let mut out = Vec::new();
+	// Sort the profiles so that the candidates are always in the same order
+	let mut profiles: Vec<_> = profiles.profiles.into_iter().collect();
+	profiles.sort_by(|a, b| a.0.cmp(&b.0));
+	for (_, profile) in profiles {
+		let version = match profile.kind.as_str() {
+			"latest-release" => MinecraftVersionDeser::Latest(MinecraftLatestVersion::Release),
+			"latest-snapshot" => MinecraftVersionDeser::Latest(MinecraftLatestVersion::Snapshot),
+			_ => {
+				let Some(version_id) = profile.last_version_id else {
+					continue;
+				};
+				let (modloader, loader_version, version) = parse_version_id(&version_id);
+				// The version file knows the game version better than the name does
+				let version_file: Option<VersionFile> = json_from_file(
+					path.join("versions")
+						.join(&version_id)
+						.join(format!("{version_id}.json")),
+				)
+				.ok();
+				let version = version_file
+					.and_then(|x| x.inherits_from)
+					.unwrap_or(version);
+				let candidate = ImportCandidate {
+					name: Some(profile.name).filter(|x| !x.is_empty()),
+					version: MinecraftVersionDeser::Version(version.into()),
+					modloader,
+					loader_version,
+					game_dir: profile.game_dir.unwrap_or(path.to_owned()),
+				};
+				out.push(candidate);
+				continue;
+			}
+		};
+		out.push(ImportCandidate {
+			name: Some(profile.name).filter(|x| !x.is_empty()),
+			version,
+			modloader: Modloader::Vanilla,
+			loader_version: None,
+			game_dir: profile.game_dir.unwrap_or(path.to_owned()),
+		});
+	}
+
+	Ok(out)
+}
+
+/// Get the modloader, loader version, and Minecraft version from the name of a
+/// version that the official launcher runs
+fn parse_version_id(id: &str) -> (Modloader, Option<String>, String) {
+	for (prefix, modloader) in [
+		("fabric-loader-", Modloader::Fabric),
+		("quilt-loader-", Modloader::Quilt),
+	] {
+		if let Some(rest) = id.strip_prefix(prefix) {
+			if let Some((loader_version, version)) = rest.split_once('-') {
+				return (modloader, Some(loader_version.into()), version.into());
+			}
+		}
+	}
+
+	if let Some(rest) = id.strip_prefix("neoforge-") {
+		// NeoForge versions start with the minor and patch versions of the game
+		let mut parts = rest.split('.');
+		let version = match (parts.next(), parts.next()) {
+			(Some(minor), Some("0")) => format!("1.{minor}"),
+			(Some(minor), Some(patch)) => format!("1.{minor}.{patch}"),
+			_ => rest.to_string(),
+		};
+		return (Modloader::NeoForged, None, version);
+	}
+
+	let lower = id.to_lowercase();
+	if let Some(index) = lower.find("-forge") {
+		return (Modloader::Forge, None, id[..index].to_string());
+	}
+	if let Some(index) = lower.find("-liteloader") {
+		return (Modloader::LiteLoader, None, id[..index].to_string());
+	}
+
+	(Modloader::Vanilla, None, id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use mcvm_shared::output;
+
+	use crate::config::modifications::apply_modifications;
+	use crate::config::plugin::PluginManager;
+	use crate::config::Config;
+	use crate::io::paths::Paths;
+
+	use super::*;
+
+	fn get_fixture(name: &str) -> PathBuf {
+		Path::new(env!("CARGO_MANIFEST_DIR"))
+			.join("src/instance/fixtures/import")
+			.join(name)
+	}
+
+	#[test]
+	fn test_prism_import() {
+		let path = get_fixture("prism_fabric");
+		let candidates = read_candidates(ImportSource::Prism, &path).unwrap();
+		let candidate = pick_candidate(candidates, None).unwrap();
+		assert_eq!(candidate.name.as_deref(), Some("Fabric Survival"));
+		assert_eq!(
+			candidate.version,
+			MinecraftVersionDeser::Version("1.20.1".into())
+		);
+		assert_eq!(candidate.modloader, Modloader::Fabric);
+		assert_eq!(candidate.loader_version.as_deref(), Some("0.15.7"));
+
+		let options = read_candidate_options(&candidate).unwrap().unwrap();
+		assert_eq!(options.video.fov, Some(90));
+		assert_eq!(options.video.render_distance, Some(16));
+
+		// The imported config loads as a normal instance
+		let mut config = ConfigDeser::default();
+		let id = InstanceID::from("survival");
+		let modifications =
+			get_import_modifications(&config, &id, &candidate, Some(&options)).unwrap();
+		apply_modifications(&mut config, modifications).unwrap();
+		assert!(get_import_modifications(&config, &id, &candidate, None).is_err());
+		let config = Config::load_from_deser(
+			config,
+			PluginManager::new(),
+			true,
+			&Paths::new_no_create().unwrap(),
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+		let instance = config.instances.get(&id).unwrap();
+		assert_eq!(instance.get_side(), Side::Client);
+		let stored = instance.get_config();
+		assert_eq!(stored.name.as_deref(), Some("Fabric Survival"));
+		assert_eq!(stored.profile.as_deref(), Some("survival"));
+		assert_eq!(
+			stored.modifications.get_modloader(Side::Client),
+			Modloader::Fabric
+		);
+		assert!(stored.plugin_config.contains_key("options"));
+
+		let temp_dir = tempfile::tempdir().unwrap();
+		let game_dir = temp_dir.path();
+		let files = import_files(&candidate, game_dir, false).unwrap();
+		assert_eq!(files.mods, vec!["sodium.jar".to_string()]);
+		assert!(game_dir.join("saves/New World/level.dat").exists());
+		assert!(game_dir.join("options.txt").exists());
+		// Importing into the same directory again would mix the files together
+		assert!(import_files(&candidate, game_dir, false).is_err());
+	}
+
+	#[test]
+	fn test_prism_multiple_loaders() {
+		let path = get_fixture("prism_mixed");
+		let candidates = read_candidates(ImportSource::Prism, &path).unwrap();
+		assert_eq!(candidates.len(), 2);
+		assert!(candidates[0].game_dir.ends_with("minecraft"));
+		assert!(pick_candidate(candidates.clone(), None).is_err());
+		assert!(pick_candidate(candidates.clone(), Some(&Modloader::Quilt)).is_err());
+
+		let candidate = pick_candidate(candidates, Some(&Modloader::Forge)).unwrap();
+		assert_eq!(candidate.modloader, Modloader::Forge);
+		// MCVM can't pick the Forge version, so it isn't kept
+		assert_eq!(candidate.loader_version, None);
+	}
+
+	#[test]
+	fn test_dotminecraft_import() {
+		let path = get_fixture("dotminecraft");
+		let candidates = read_candidates(ImportSource::DotMinecraft, &path).unwrap();
+		assert_eq!(candidates.len(), 2);
+		assert_eq!(
+			candidates[0].version,
+			MinecraftVersionDeser::Latest(MinecraftLatestVersion::Release)
+		);
+
+		let candidate = pick_candidate(candidates, Some(&Modloader::Fabric)).unwrap();
+		assert_eq!(
+			candidate.version,
+			MinecraftVersionDeser::Version("1.20.1".into())
+		);
+		assert_eq!(candidate.loader_version.as_deref(), Some("0.15.7"));
+
+		let temp_dir = tempfile::tempdir().unwrap();
+		let game_dir = temp_dir.path();
+		let files = import_files(&candidate, game_dir, false).unwrap();
+		assert!(files.mods.is_empty());
+		assert!(game_dir.join("saves/World/level.dat").exists());
+		// Launcher files are left behind
+		assert!(!game_dir.join("libraries").exists());
+		assert!(!game_dir.join("launcher_profiles.json").exists());
+	}
+
+	#[test]
+	fn test_parse_version_id() {
+		assert_eq!(
+			parse_version_id("quilt-loader-0.23.1-1.20.4"),
+			(Modloader::Quilt, Some("0.23.1".into()), "1.20.4".into())
+		);
+		assert_eq!(
+			parse_version_id("1.20.1-forge-47.2.0"),
+			(Modloader::Forge, None, "1.20.1".into())
+		);
+		assert_eq!(
+			parse_version_id("1.7.10-Forge10.13.4.1614-1.7.10"),
+			(Modloader::Forge, None, "1.7.10".into())
+		);
+		assert_eq!(
+			parse_version_id("neoforge-20.4.80"),
+			(Modloader::NeoForged, None, "1.20.4".into())
+		);
+		assert_eq!(
+			parse_version_id("neoforge-21.0.10"),
+			(Modloader::NeoForged, None, "1.21".into())
+		);
+		assert_eq!(
+			parse_version_id("23w13a"),
+			(Modloader::Vanilla, None, "23w13a".into())
+		);
+	}
+
+	#[test]
+	fn test_default_id() {
+		assert_eq!(
+			get_default_id(Path::new("/home/me/.minecraft")),
+			Some("minecraft".into())
+		);
+		assert_eq!(
+			get_default_id(Path::new("instances/My Pack (1)")),
+			Some("my-pack--1-".into())
+		);
+	}
+
+	#[test]
+	fn test_parse_instance_cfg() {
+		let cfg = parse_instance_cfg("[General]\nname=My Pack\nIntendedVersion=1.7.10\n");
+		assert_eq!(cfg.get("name").unwrap(), "My Pack");
+		assert_eq!(cfg.get("IntendedVersion").unwrap(), "1.7.10");
+	}
+}
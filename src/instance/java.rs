@@ -0,0 +1,106 @@
+use anyhow::Context;
+use mcvm_core::io::java::install::JavaInstallationKind;
+use mcvm_core::io::java::JavaMajorVersion;
+use mcvm_core::io::persistent::{ManagedJavaInstallation, PersistentDataJavaInstallation};
+use mcvm_core::net::game_files::client_meta;
+
+use crate::io::lock::Lockfile;
+use crate::io::paths::Paths;
+
+use super::Instance;
+
+impl Instance {
+	/// Gets the major Java version that this instance needs. Like when the instance is created,
+	/// this comes from the client meta of its Minecraft version. Returns None if the instance
+	/// hasn't been created yet, since its version isn't known until then
+	pub fn get_java_major_version(
+		&self,
+		paths: &Paths,
+		lock: &Lockfile,
+	) -> anyhow::Result<Option<JavaMajorVersion>> {
+		let Some(version) = lock.get_instance_version(&self.id) else {
+			return Ok(None);
+		};
+		let meta = client_meta::read_stored(version, &paths.core)
+			.with_context(|| format!("Failed to read the client meta for version {version}"))?;
+
+		Ok(meta.map(|x| x.java_info.major_version))
+	}
+
+	/// Checks if this instance launches with a downloaded Java installation, given the major Java version it needs
+	pub fn uses_java_installation(
+		&self,
+		major_version: JavaMajorVersion,
+		installation: &ManagedJavaInstallation,
+	) -> bool {
+		java_kind_uses_installation(&self.config.launch.java, major_version, installation)
+	}
+}
+
+/// Checks if a configured kind of Java could launch with a downloaded Java installation.
+/// Automatic Java could pick any vendor, so it uses all of them
+pub fn java_kind_uses_installation(
+	kind: &JavaInstallationKind,
+	major_version: JavaMajorVersion,
+	installation: &ManagedJavaInstallation,
+) -> bool {
+	if installation.major_version != major_version.to_string() {
+		return false;
+	}
+
+	match kind {
+		JavaInstallationKind::Auto => true,
+		JavaInstallationKind::Adoptium => {
+			installation.vendor == PersistentDataJavaInstallation::Adoptium
+		}
+		JavaInstallationKind::Zulu => installation.vendor == PersistentDataJavaInstallation::Zulu,
+		JavaInstallationKind::GraalVM => {
+			installation.vendor == PersistentDataJavaInstallation::GraalVM
+		}
+		JavaInstallationKind::System | JavaInstallationKind::Custom { .. } => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::*;
+
+	#[test]
+	fn test_java_kind_uses_installation() {
+		let installation = ManagedJavaInstallation {
+			vendor: PersistentDataJavaInstallation::Zulu,
+			major_version: "17".into(),
+			version: "17.0.9".into(),
+			path: PathBuf::from("zulu17"),
+		};
+		let java_17 = JavaMajorVersion::new(17);
+
+		assert!(java_kind_uses_installation(
+			&JavaInstallationKind::Zulu,
+			java_17,
+			&installation
+		));
+		assert!(java_kind_uses_installation(
+			&JavaInstallationKind::Auto,
+			java_17,
+			&installation
+		));
+		assert!(!java_kind_uses_installation(
+			&JavaInstallationKind::Zulu,
+			JavaMajorVersion::new(21),
+			&installation
+		));
+		assert!(!java_kind_uses_installation(
+			&JavaInstallationKind::Adoptium,
+			java_17,
+			&installation
+		));
+		assert!(!java_kind_uses_installation(
+			&JavaInstallationKind::System,
+			java_17,
+			&installation
+		));
+	}
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use mcvm_core::io::{json_from_file, json_to_file};
+use mcvm_shared::id::InstanceID;
+use mcvm_shared::Side;
+use serde::{Deserialize, Serialize};
+
+use crate::io::paths::Paths;
+
+/// The instances that were launched most recently, so that they can be launched again without picking them
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct LastLaunched {
+	/// The instance that was launched last, on either side
+	pub instance: Option<InstanceID>,
+	/// The client that was launched last
+	pub client: Option<InstanceID>,
+	/// The server that was launched last
+	pub server: Option<InstanceID>,
+}
+
+impl LastLaunched {
+	/// Open the stored instances. A missing or broken file is treated as no instances having been launched
+	pub fn open(paths: &Paths) -> Self {
+		json_from_file(Self::get_path(paths)).unwrap_or_default()
+	}
+
+	/// Remember an instance that was launched successfully
+	pub fn record(paths: &Paths, id: &InstanceID, side: Side) -> anyhow::Result<()> {
+		let mut last = Self::open(paths);
+		last.set(id, side);
+		last.write(paths)
+	}
+
+	/// Set the last launched instance for a side
+	pub fn set(&mut self, id: &InstanceID, side: Side) {
+		self.instance = Some(id.clone());
+		match side {
+			Side::Client => self.client = Some(id.clone()),
+			Side::Server => self.server = Some(id.clone()),
+		}
+	}
+
+	/// Get the instance that was launched last, on a side if one is given
+	pub fn get(&self, side: Option<Side>) -> Option<&InstanceID> {
+		match side {
+			None => self.instance.as_ref(),
+			Some(Side::Client) => self.client.as_ref(),
+			Some(Side::Server) => self.server.as_ref(),
+		}
+	}
+
+	/// Write the stored instances. This file is separate from the lockfile and is replaced in one step,
+	/// so launching at the same time as an update can't break either of them
+	pub fn write(&self, paths: &Paths) -> anyhow::Result<()> {
+		let path = Self::get_path(paths);
+		let tmp_path = path.with_extension("json.tmp");
+		json_to_file(&tmp_path, self).context("Failed to write last launched instances")?;
+		fs::rename(&tmp_path, &path).context("Failed to replace last launched instances")?;
+
+		Ok(())
+	}
+
+	fn get_path(paths: &Paths) -> PathBuf {
+		paths.internal.join("last_launched.json")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_last_launched() {
+		let mut last = LastLaunched::default();
+		assert_eq!(last.get(None), None);
+
+		last.set(&"survival".into(), Side::Client);
+		last.set(&"smp".into(), Side::Server);
+		assert_eq!(last.get(None), Some(&"smp".into()));
+		assert_eq!(last.get(Some(Side::Client)), Some(&"survival".into()));
+		assert_eq!(last.get(Some(Side::Server)), Some(&"smp".into()));
+
+		last.set(&"creative".into(), Side::Client);
+		assert_eq!(last.get(None), Some(&"creative".into()));
+		assert_eq!(last.get(Some(Side::Server)), Some(&"smp".into()));
+	}
+}
@@ -1,12 +1,20 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Context;
 use mcvm_core::auth_crate::mc::ClientId;
 use mcvm_core::io::java::args::MemoryNum;
 use mcvm_core::io::java::install::JavaInstallationKind;
+use mcvm_core::launch::{LaunchCommand, ProcessInterrupter};
+use mcvm_core::net::game_files::download_size::DownloadSize;
 use mcvm_core::user::UserManager;
+use mcvm_parse::vars::{HashMapVariableStore, Value, VariableStore};
 use mcvm_plugin::hooks::{
-	HookHandle, InstanceLaunchArg, OnInstanceLaunch, OnInstanceStop, WhileInstanceLaunch,
+	HookHandle, InstanceLaunchArg, OnInstanceLaunch, OnInstancePrepareLaunch,
+	OnInstancePrepareLaunchResult, OnInstanceStop, WhileInstanceLaunch,
 };
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
 use mcvm_shared::translate;
@@ -16,10 +24,18 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use super::update::manager::UpdateManager;
-use crate::config::instance::QuickPlay;
+use crate::config::instance::{ClientWindowConfig, QuickPlay};
 use crate::config::plugin::PluginManager;
+use crate::config::preferences::ConfigPreferences;
+use crate::io::lock::Lockfile;
+use crate::io::logging::{
+	capture_output, RotatingLog, DEFAULT_MAX_LOG_FILES, DEFAULT_MAX_LOG_SIZE,
+};
 use crate::io::paths::Paths;
 
+use super::last_launched::LastLaunched;
+use super::running::RunningInstance;
+use super::verify;
 use super::Instance;
 
 impl Instance {
@@ -29,50 +45,34 @@ impl Instance {
 		paths: &Paths,
 		users: &mut UserManager,
 		plugins: &PluginManager,
+		client: &Client,
 		settings: LaunchSettings,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<InstanceHandle> {
-		o.display(
-			MessageContents::StartProcess(translate!(o, StartUpdatingInstance, "inst" = &self.id)),
-			MessageLevel::Important,
-		);
-
-		let mut manager = UpdateManager::new(false, true);
-		let client = Client::new();
-		manager.set_version(&self.config.version);
-		manager.add_requirements(self.get_requirements());
-		manager.set_client_id(settings.ms_client_id);
-		if settings.offline_auth {
-			manager.offline_auth();
-		}
-		manager
-			.fulfill_requirements(users, plugins, paths, &client, o)
-			.await
-			.context("Update failed")?;
-
-		let result = self
-			.create(&mut manager, plugins, paths, users, &client, o)
-			.await
-			.context("Failed to update instance")?;
-		manager.add_result(result);
-
-		let hook_arg = InstanceLaunchArg {
-			id: self.id.to_string(),
-			side: Some(self.get_side()),
-			dir: self.dirs.get().inst_dir.to_string_lossy().into(),
-			game_dir: self.dirs.get().game_dir.to_string_lossy().into(),
-			version_info: manager.version_info.get_clone(),
-			custom_config: self.config.plugin_config.clone(),
-			pid: None,
-		};
+		let PreparedLaunch {
+			mut manager,
+			hook_arg,
+			modifications,
+		} = self
+			.prepare_launch(paths, users, plugins, client, &settings, o)
+			.await?;
 
 		let mut installed_version = manager
 			.get_core_version(o)
 			.await
 			.context("Failed to get core version")?;
 
+		let capture_output = self.config.launch.log.capture;
 		let mut instance = self
-			.create_core_instance(&mut installed_version, paths, o)
+			.create_core_instance(
+				&mut installed_version,
+				paths,
+				settings.pipe_stdin,
+				capture_output,
+				&modifications,
+				&settings.window,
+				o,
+			)
 			.await
 			.context("Failed to create core instance")?;
 
@@ -93,10 +93,33 @@ impl Instance {
 		}
 
 		// Launch the instance using core
-		let handle = instance
+		let launched_at = SystemTime::now();
+		let mut handle = instance
 			.launch_with_handle(o)
 			.await
 			.context("Failed to launch core instance")?;
+		let output_threads = if capture_output {
+			self.capture_output(&mut handle, settings.mirror_output, paths)
+				.context("Failed to capture instance output")?
+		} else {
+			Vec::new()
+		};
+
+		// Record the process so that it can be listed while it is running
+		let record = RunningInstance::new(self.id.clone(), self.get_side(), handle.get_pid())
+			.and_then(|x| x.register(paths));
+		if let Err(e) = record {
+			o.display(
+				MessageContents::Warning(format!("Failed to record running instance: {e:#}")),
+				MessageLevel::Important,
+			);
+		}
+		if let Err(e) = LastLaunched::record(paths, &self.id, self.get_side()) {
+			o.display(
+				MessageContents::Warning(format!("Failed to record last launched instance: {e:#}")),
+				MessageLevel::Important,
+			);
+		}
 
 		// Run while_instance_launch hooks alongside
 		let hook_handles = plugins
@@ -106,10 +129,321 @@ impl Instance {
 			inner: handle,
 			hook_handles,
 			hook_arg,
+			launched_at,
+			output_threads,
 		};
 
 		Ok(handle)
 	}
+
+	/// Get the command that launching the instance would run, without running it or any launch hooks.
+	/// The instance is still updated and the user is still authenticated like for a normal launch
+	pub async fn get_launch_command(
+		&mut self,
+		paths: &Paths,
+		users: &mut UserManager,
+		plugins: &PluginManager,
+		client: &Client,
+		settings: LaunchSettings,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<LaunchCommand> {
+		let PreparedLaunch {
+			mut manager,
+			modifications,
+			..
+		} = self
+			.prepare_launch(paths, users, plugins, client, &settings, o)
+			.await?;
+
+		let mut installed_version = manager
+			.get_core_version(o)
+			.await
+			.context("Failed to get core version")?;
+
+		let mut instance = self
+			.create_core_instance(
+				&mut installed_version,
+				paths,
+				settings.pipe_stdin,
+				self.config.launch.log.capture,
+				&modifications,
+				&settings.window,
+				o,
+			)
+			.await
+			.context("Failed to create core instance")?;
+		o.end_process();
+
+		instance.get_launch_command(o).await
+	}
+
+	/// Update the instance files and collect everything needed to create the core instance
+	async fn prepare_launch(
+		&mut self,
+		paths: &Paths,
+		users: &mut UserManager,
+		plugins: &PluginManager,
+		client: &Client,
+		settings: &LaunchSettings,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<PreparedLaunch> {
+		check_wrappers(&self.config.launch.wrappers)?;
+
+		o.display(
+			MessageContents::StartProcess(translate!(o, StartUpdatingInstance, "inst" = &self.id)),
+			MessageLevel::Important,
+		);
+
+		let mut manager = UpdateManager::new(false, true);
+		manager.set_version(&self.config.version);
+		manager.add_requirements(self.get_requirements());
+		manager.set_client_id(settings.ms_client_id.clone());
+		// Use the Paper build from the last update instead of checking for a new one
+		if self.get_paper_mode().is_some() {
+			let lock = Lockfile::open(paths).context("Failed to open lockfile")?;
+			manager.paper_build = self
+				.get_pinned_paper_build()
+				.or(lock.get_instance_paper_build(&self.id));
+		}
+		if settings.offline_auth {
+			manager.offline_auth();
+		}
+		if let Some(user) = settings.user.as_ref().or(self.config.user.as_ref()) {
+			users
+				.choose_user(user)
+				.with_context(|| format!("Failed to choose user '{user}'"))?;
+		}
+		manager
+			.fulfill_requirements(users, plugins, paths, client, o)
+			.await
+			.context("Update failed")?;
+
+		let damaged = if settings.verify {
+			self.verify_files(&mut manager, paths, o)
+				.await
+				.context("Failed to verify instance files")?
+		} else {
+			Vec::new()
+		};
+
+		let result = self
+			.create(&mut manager, plugins, paths, users, client, o)
+			.await
+			.context("Failed to update instance")?;
+		manager.add_result(result);
+		verify::report_repairs(&damaged, o);
+
+		let hook_arg = InstanceLaunchArg {
+			id: self.id.to_string(),
+			side: Some(self.get_side()),
+			dir: self.dirs.get().inst_dir.to_string_lossy().into(),
+			game_dir: self.dirs.get().game_dir.to_string_lossy().into(),
+			version_info: manager.version_info.get_clone(),
+			custom_config: self.config.plugin_config.clone(),
+			pid: None,
+			env: if self.config.launch.share_env_with_hooks {
+				self.get_launch_env()
+			} else {
+				HashMap::new()
+			},
+		};
+
+		let modifications = self
+			.get_plugin_launch_modifications(&hook_arg, plugins, paths, o)
+			.context("Failed to get launch modifications from plugins")?;
+
+		Ok(PreparedLaunch {
+			manager,
+			hook_arg,
+			modifications,
+		})
+	}
+
+	/// Collect the additions to the launch command from plugins, in the order that the plugins were loaded
+	fn get_plugin_launch_modifications(
+		&self,
+		hook_arg: &InstanceLaunchArg,
+		plugins: &PluginManager,
+		paths: &Paths,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<LaunchModifications> {
+		let mut out = LaunchModifications::default();
+		let results = plugins
+			.call_hook(OnInstancePrepareLaunch, hook_arg, paths, o)
+			.context("Failed to call prepare launch hook")?;
+		for result in results {
+			let plugin_id = result.get_id().clone();
+			let result = result.result(o)?;
+			for (var, other) in out.merge(&plugin_id, result) {
+				o.display(
+					MessageContents::Warning(translate!(
+						o,
+						PluginEnvConflict,
+						"plugin" = &plugin_id,
+						"var" = &var,
+						"other" = &other
+					)),
+					MessageLevel::Important,
+				);
+			}
+		}
+
+		Ok(out)
+	}
+
+	/// Get the variables that can be used in the environment variables and working directory
+	/// of the launch config. Includes the environment of MCVM itself
+	fn get_launch_vars(&self) -> HashMapVariableStore {
+		let mut vars = HashMapVariableStore::new();
+		for (var, value) in std::env::vars() {
+			vars.set_var(var, value);
+		}
+		let dirs = self.dirs.get();
+		vars.set_var("INSTANCE_ID".into(), self.id.to_string());
+		vars.set_var(
+			"INSTANCE_DIR".into(),
+			dirs.inst_dir.to_string_lossy().into(),
+		);
+		vars.set_var("GAME_DIR".into(), dirs.game_dir.to_string_lossy().into());
+
+		vars
+	}
+
+	/// Get the environment variables from the launch config with any variables in them expanded
+	pub(super) fn get_launch_env(&self) -> HashMap<String, String> {
+		expand_launch_env(&self.config.launch.env, &self.get_launch_vars())
+	}
+
+	/// Get the directory to launch the game process in, if it is not the game directory
+	pub(super) fn get_launch_working_dir(&self) -> Option<PathBuf> {
+		let working_dir = self.config.launch.working_dir.as_ref()?;
+		let working_dir = Value::substitute_tokens(working_dir, &self.get_launch_vars());
+		Some(self.dirs.get().game_dir.join(working_dir))
+	}
+
+	/// Estimate how much launching this instance would download, without downloading
+	/// anything other than the version metadata. Java and package files aren't counted
+	pub async fn estimate_download_size(
+		&mut self,
+		paths: &Paths,
+		users: &UserManager,
+		plugins: &PluginManager,
+		client: &Client,
+		prefs: &ConfigPreferences,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<DownloadSize> {
+		let mut manager = UpdateManager::new(false, true);
+		manager.set_metadata_freshness(prefs.metadata_freshness);
+		manager.set_version(&self.config.version);
+		manager
+			.fulfill_requirements(users, plugins, paths, client, o)
+			.await
+			.context("Failed to fulfill update manager")?;
+
+		manager
+			.estimate_download_size(self.get_side(), paths, o)
+			.await
+	}
+
+	/// Get the path to the log that the output of this instance is captured in
+	pub fn get_output_log_path(&mut self, paths: &Paths) -> anyhow::Result<PathBuf> {
+		self.ensure_dirs(paths)?;
+		Ok(self
+			.dirs
+			.get()
+			.inst_dir
+			.join("mcvm_logs")
+			.join("output.log"))
+	}
+
+	/// Start capturing the output of a launched instance into its log
+	fn capture_output(
+		&mut self,
+		handle: &mut mcvm_core::InstanceHandle,
+		mirror: bool,
+		paths: &Paths,
+	) -> anyhow::Result<Vec<JoinHandle<()>>> {
+		let path = self.get_output_log_path(paths)?;
+		let log_config = &self.config.launch.log;
+		let log = RotatingLog::open(&path, log_config.max_size, log_config.max_files)?;
+		let log = Arc::new(Mutex::new(log));
+
+		let (stdout, stderr) = handle.take_output();
+		let mut threads = Vec::new();
+		if let Some(stdout) = stdout {
+			let mirror: Option<fn(&str)> = mirror.then_some(|x| println!("{x}"));
+			threads.push(capture_output(stdout, log.clone(), mirror));
+		}
+		if let Some(stderr) = stderr {
+			let mirror: Option<fn(&str)> = mirror.then_some(|x| eprintln!("{x}"));
+			threads.push(capture_output(stderr, log, mirror));
+		}
+
+		Ok(threads)
+	}
+}
+
+/// Additions to the launch command of an instance that come from plugins
+#[derive(Debug, Default)]
+pub struct LaunchModifications {
+	/// Additional JVM args
+	pub jvm_args: Vec<String>,
+	/// Additional game args
+	pub game_args: Vec<String>,
+	/// Additional environment variables
+	pub env: HashMap<String, String>,
+	/// Additional classpath entries
+	pub classpath: Vec<PathBuf>,
+	/// The plugin that set each environment variable
+	env_sources: HashMap<String, String>,
+}
+
+impl LaunchModifications {
+	/// Merge the result of a plugin's prepare launch hook into these modifications.
+	/// Environment variables from later plugins win, and the ones that were overridden
+	/// are returned along with the plugin that originally set them
+	pub fn merge(
+		&mut self,
+		plugin_id: &str,
+		result: OnInstancePrepareLaunchResult,
+	) -> Vec<(String, String)> {
+		self.jvm_args.extend(result.jvm_args);
+		self.game_args.extend(result.game_args);
+		self.classpath
+			.extend(result.classpath.into_iter().map(PathBuf::from));
+
+		let mut conflicts = Vec::new();
+		let mut env: Vec<_> = result.env.into_iter().collect();
+		env.sort();
+		for (var, value) in env {
+			let previous = self.env_sources.insert(var.clone(), plugin_id.to_string());
+			if let Some(previous) = previous {
+				if previous != plugin_id {
+					conflicts.push((var.clone(), previous));
+				}
+			}
+			self.env.insert(var, value);
+		}
+
+		conflicts
+	}
+}
+
+/// Expand variables like `${GAME_DIR}` in the values of launch environment variables
+fn expand_launch_env(
+	env: &HashMap<String, String>,
+	vars: &impl VariableStore,
+) -> HashMap<String, String> {
+	env.iter()
+		.map(|(var, value)| (var.clone(), Value::substitute_tokens(value, vars)))
+		.collect()
+}
+
+/// An instance whose files are ready for launching
+struct PreparedLaunch {
+	manager: UpdateManager,
+	hook_arg: InstanceLaunchArg,
+	modifications: LaunchModifications,
 }
 
 /// Settings for launch provided to the instance launch function
@@ -118,8 +452,49 @@ pub struct LaunchSettings {
 	pub ms_client_id: ClientId,
 	/// Whether to do offline auth
 	pub offline_auth: bool,
+	/// Whether to pipe the stdin of the instance process so that commands can be sent to it
+	pub pipe_stdin: bool,
+	/// Whether to print the output of the instance to the terminal while it is being captured
+	pub mirror_output: bool,
+	/// Overrides for the window configuration of the instance for this launch
+	pub window: ClientWindowConfig,
+	/// How long the update before the first launch can take before it is cancelled
+	pub update_timeout: Option<Duration>,
+	/// Whether to check for and repair missing or corrupted files before launching
+	pub verify: bool,
+	/// The number of bytes that can be downloaded before launching without failing with
+	/// a [LargeDownload] error. A limit of zero refuses to download anything
+	pub download_limit: Option<u64>,
+	/// The ID of a user to launch a client with, instead of the user of the instance or the default user
+	pub user: Option<String>,
 }
 
+/// Error for when launching would download more than the limit in the launch settings
+#[derive(Debug)]
+pub struct LargeDownload {
+	/// The estimated size of the download
+	pub size: DownloadSize,
+	/// The limit that it exceeds
+	pub limit: u64,
+}
+
+impl std::fmt::Display for LargeDownload {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if self.limit == 0 {
+			write!(f, "Launching would download {}", self.size)
+		} else {
+			write!(
+				f,
+				"Launching would download {}, which is more than the limit of {:.1} MiB",
+				self.size,
+				self.limit as f64 / 1024.0 / 1024.0
+			)
+		}
+	}
+}
+
+impl std::error::Error for LargeDownload {}
+
 /// Options for launching after conversion from the deserialized version
 #[derive(Debug)]
 pub struct LaunchOptions {
@@ -135,12 +510,18 @@ pub struct LaunchOptions {
 	pub max_mem: Option<MemoryNum>,
 	/// Environment variables
 	pub env: HashMap<String, String>,
-	/// Wrapper command
-	pub wrapper: Option<WrapperCommand>,
+	/// The directory to launch the process in, relative to the game directory
+	pub working_dir: Option<String>,
+	/// Whether launch hooks are also given the environment variables
+	pub share_env_with_hooks: bool,
+	/// Wrapper commands, with the outermost one first
+	pub wrappers: Vec<WrapperCommand>,
 	/// Quick Play options
 	pub quick_play: QuickPlay,
 	/// Whether or not to use the Log4J configuration
 	pub use_log4j_config: bool,
+	/// Options for capturing the output of the game
+	pub log: InstanceLogConfig,
 }
 
 /// A wrapper command
@@ -153,6 +534,82 @@ pub struct WrapperCommand {
 	pub args: Vec<String>,
 }
 
+/// Configuration for the commands that the game is run with, like `gamemoderun`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(untagged)]
+pub enum WrapperConfig {
+	/// A single command with its arguments
+	Command(WrapperCommand),
+	/// A single command line, like `nice -n 10`
+	CommandLine(String),
+	/// Multiple command lines that are prefixed to the launch command in order
+	CommandLines(Vec<String>),
+}
+
+impl WrapperConfig {
+	/// Get the wrapper commands, with the outermost one first
+	pub fn get_commands(self) -> anyhow::Result<Vec<WrapperCommand>> {
+		let lines = match self {
+			Self::Command(command) => return Ok(vec![command]),
+			Self::CommandLine(line) => vec![line],
+			Self::CommandLines(lines) => lines,
+		};
+
+		lines
+			.into_iter()
+			.map(|line| {
+				let parts = shlex::split(&line)
+					.with_context(|| format!("Wrapper command '{line}' has unclosed quotes"))?;
+				let mut parts = parts.into_iter();
+				let cmd = parts.next().context("Wrapper command is empty")?;
+				Ok(WrapperCommand {
+					cmd,
+					args: parts.collect(),
+				})
+			})
+			.collect()
+	}
+}
+
+/// Check that the programs of wrapper commands can be found
+pub fn check_wrappers(wrappers: &[WrapperCommand]) -> anyhow::Result<()> {
+	for wrapper in wrappers {
+		which::which(&wrapper.cmd).with_context(|| {
+			format!(
+				"Wrapper command '{}' was not found. Make sure that it is installed and on your PATH",
+				wrapper.cmd
+			)
+		})?;
+	}
+
+	Ok(())
+}
+
+/// Options for capturing the output of an instance into rotating log files
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(default)]
+pub struct InstanceLogConfig {
+	/// Whether to capture the output of the game. If this is disabled,
+	/// the output will go to wherever the output of MCVM goes
+	pub capture: bool,
+	/// The maximum size of a log file in bytes before it is rotated
+	pub max_size: u64,
+	/// The number of rotated log files to keep
+	pub max_files: u32,
+}
+
+impl Default for InstanceLogConfig {
+	fn default() -> Self {
+		Self {
+			capture: true,
+			max_size: DEFAULT_MAX_LOG_SIZE,
+			max_files: DEFAULT_MAX_LOG_FILES,
+		}
+	}
+}
+
 /// A handle for an instance
 pub struct InstanceHandle {
 	/// Core InstanceHandle with the process
@@ -161,6 +618,10 @@ pub struct InstanceHandle {
 	hook_handles: Vec<HookHandle<WhileInstanceLaunch>>,
 	/// Arg to pass to the stop hook when the instance is stopped
 	hook_arg: InstanceLaunchArg,
+	/// When the instance was launched
+	launched_at: SystemTime,
+	/// Threads that are capturing the output of the instance
+	output_threads: Vec<JoinHandle<()>>,
 }
 
 impl InstanceHandle {
@@ -172,6 +633,8 @@ impl InstanceHandle {
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<std::process::ExitStatus> {
 		let result = self.inner.wait()?;
+		let _ = RunningInstance::unregister(paths, self.get_pid());
+		self.join_output_threads();
 		// Kill any sibling processes now that the main one is complete
 		for handle in self.hook_handles {
 			handle
@@ -191,7 +654,7 @@ impl InstanceHandle {
 		paths: &Paths,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<()> {
-		for handle in self.hook_handles {
+		for handle in self.hook_handles.drain(..) {
 			handle
 				.kill(o)
 				.context("Failed to kill plugin sibling process")?;
@@ -199,6 +662,10 @@ impl InstanceHandle {
 		self.inner
 			.kill()
 			.context("Failed to kill inner instance handle")?;
+		// Reap the process so that its output is closed
+		let _ = self.inner.wait();
+		let _ = RunningInstance::unregister(paths, self.get_pid());
+		self.join_output_threads();
 
 		Self::call_stop_hooks(&self.hook_arg, plugins, paths, o)?;
 
@@ -211,6 +678,46 @@ impl InstanceHandle {
 		self.inner.get_process()
 	}
 
+	/// Checks if the process has exited without blocking. Once it has,
+	/// wait() should still be called to clean up
+	pub fn try_wait(&mut self) -> std::io::Result<Option<std::process::ExitStatus>> {
+		self.inner.try_wait()
+	}
+
+	/// Asks the process to exit gracefully instead of killing it.
+	/// wait() or kill() should still be called afterwards
+	pub fn terminate(&mut self) -> std::io::Result<()> {
+		self.inner.terminate()
+	}
+
+	/// Gets the PID of the process
+	pub fn get_pid(&self) -> u32 {
+		self.inner.get_pid()
+	}
+
+	/// Gets something that can forward interrupts to the process while it is being waited on
+	pub fn get_interrupter(&self) -> ProcessInterrupter {
+		self.inner.get_interrupter()
+	}
+
+	/// Gets the time when the instance was launched
+	pub fn get_launch_time(&self) -> SystemTime {
+		self.launched_at
+	}
+
+	/// Takes the piped standard input of the process,
+	/// if the instance was launched with piped stdin
+	pub fn take_stdin(&mut self) -> Option<std::process::ChildStdin> {
+		self.inner.take_stdin()
+	}
+
+	/// Waits for all of the output of the instance to be written to its log
+	fn join_output_threads(&mut self) {
+		for thread in self.output_threads.drain(..) {
+			let _ = thread.join();
+		}
+	}
+
 	/// Calls on stop hooks
 	fn call_stop_hooks(
 		arg: &InstanceLaunchArg,
@@ -227,3 +734,94 @@ impl InstanceHandle {
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_wrapper_config() {
+		let config: WrapperConfig =
+			serde_json::from_str(r#"["gamemoderun", "nice -n 10"]"#).unwrap();
+		let commands = config.get_commands().unwrap();
+		assert_eq!(commands[0].cmd, "gamemoderun");
+		assert!(commands[0].args.is_empty());
+		assert_eq!(commands[1].cmd, "nice");
+		assert_eq!(commands[1].args, ["-n", "10"]);
+
+		let config: WrapperConfig =
+			serde_json::from_str(r#"{"cmd": "firejail", "args": ["--noprofile"]}"#).unwrap();
+		assert_eq!(config.get_commands().unwrap()[0].cmd, "firejail");
+
+		let config: WrapperConfig =
+			serde_json::from_str(r#""env 'MY VAR=a b' \"quoted arg\"""#).unwrap();
+		let commands = config.get_commands().unwrap();
+		assert_eq!(commands[0].cmd, "env");
+		assert_eq!(commands[0].args, ["MY VAR=a b", "quoted arg"]);
+
+		let config: WrapperConfig = serde_json::from_str(r#"[" "]"#).unwrap();
+		assert!(config.get_commands().is_err());
+		let config: WrapperConfig = serde_json::from_str(r#""nice 'unclosed""#).unwrap();
+		assert!(config.get_commands().is_err());
+	}
+
+	#[test]
+	fn test_check_wrappers() {
+		let wrapper = |cmd: &str| WrapperCommand {
+			cmd: cmd.into(),
+			args: Vec::new(),
+		};
+		assert!(check_wrappers(&[]).is_ok());
+		let error = check_wrappers(&[wrapper("mcvm-missing-wrapper")]).unwrap_err();
+		assert!(error.to_string().contains("mcvm-missing-wrapper"));
+		#[cfg(target_family = "unix")]
+		assert!(check_wrappers(&[wrapper("sh")]).is_ok());
+	}
+
+	#[test]
+	fn test_expand_launch_env() {
+		let mut vars = HashMapVariableStore::new();
+		vars.set_var("GAME_DIR".into(), "/games/test".into());
+		let env = HashMap::from([
+			("MODS".into(), "${GAME_DIR}/mods".into()),
+			("PLAIN".into(), "value".into()),
+			("ESCAPED".into(), "\\${GAME_DIR}".into()),
+		]);
+		let env = expand_launch_env(&env, &vars);
+		assert_eq!(env["MODS"], "/games/test/mods");
+		assert_eq!(env["PLAIN"], "value");
+		assert_eq!(env["ESCAPED"], "${GAME_DIR}");
+	}
+
+	#[test]
+	fn test_launch_modifications_merge() {
+		let mut mods = LaunchModifications::default();
+		let conflicts = mods.merge(
+			"first",
+			OnInstancePrepareLaunchResult {
+				jvm_args: vec!["-javaagent:agent.jar".into()],
+				env: HashMap::from([("A".into(), "1".into()), ("B".into(), "1".into())]),
+				classpath: vec!["agent.jar".into()],
+				..Default::default()
+			},
+		);
+		assert!(conflicts.is_empty());
+
+		let conflicts = mods.merge(
+			"second",
+			OnInstancePrepareLaunchResult {
+				jvm_args: vec!["-Dfoo=bar".into()],
+				game_args: vec!["--demo".into()],
+				env: HashMap::from([("B".into(), "2".into()), ("C".into(), "2".into())]),
+				..Default::default()
+			},
+		);
+		assert_eq!(conflicts, vec![("B".to_string(), "first".to_string())]);
+		assert_eq!(mods.jvm_args, vec!["-javaagent:agent.jar", "-Dfoo=bar"]);
+		assert_eq!(mods.game_args, vec!["--demo"]);
+		assert_eq!(mods.classpath, vec![PathBuf::from("agent.jar")]);
+		assert_eq!(mods.env["A"], "1");
+		assert_eq!(mods.env["B"], "2");
+		assert_eq!(mods.env["C"], "2");
+	}
+}
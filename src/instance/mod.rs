@@ -1,18 +1,54 @@
 /// Addon-related functions for instances
 mod addons;
+/// Cloning instances
+pub mod clone;
+/// Configuration files that packages install into instances
+pub mod config_files;
+/// Crash bundles and notifications for instances that exit abnormally
+pub mod crash;
 /// Creation of instance contents
 pub mod create;
+/// Instance icons
+pub mod icon;
+/// Protecting files in instances from MCVM with .mcvmignore
+pub mod ignore;
+/// Importing instances from other launchers
+pub mod import;
+/// Finding the Java installations that instances launch with
+pub mod java;
+/// Remembering the most recently launched instances
+pub mod last_launched;
 /// Launching an instance
 pub mod launch;
 /// Managing and installing packages on an instance
 pub mod packages;
+/// The whitelist and operators of servers
+pub mod players;
+/// Renaming instances along with their files
+pub mod rename;
+/// Tracking the processes of running instances
+pub mod running;
+/// Snapshots of instance world data
+pub mod snapshot;
 /// Import and export of instances to other formats
 pub mod transfer;
 /// Updating an instance
 pub mod update;
+/// Checking and repairing the files of an instance before launch
+pub mod verify;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
 
 use mcvm_core::util::versions::MinecraftVersion;
+use mcvm_mods::fabric_quilt::LoaderVersion;
+use mcvm_options::server::{get_world_name, read_server_properties};
+use mcvm_plugin::context::{InstalledAddonInfo, InstalledPackageInfo, InstanceInfo};
 use mcvm_shared::later::Later;
+use mcvm_shared::modifications::ServerType;
 use mcvm_shared::pkg::PackageStability;
 use mcvm_shared::Side;
 
@@ -22,7 +58,11 @@ use self::launch::LaunchOptions;
 use super::config::instance::ClientWindowConfig;
 use super::config::package::PackageConfig;
 use super::config::profile::GameModifications;
-use mcvm_shared::id::InstanceID;
+use super::io::lock::{Lockfile, LockfileUpdateRecord};
+use super::io::paths::Paths;
+use super::net::rcon::{RconClient, RconSettings};
+use super::pkg::eval::EvalPermissions;
+use mcvm_shared::id::{InstanceID, ProfileID};
 
 /// An instance of the game on a profile
 #[derive(Debug)]
@@ -37,6 +77,10 @@ pub struct Instance {
 	pub(crate) config: InstanceStoredConfig,
 	/// Modification data
 	modification_data: ModificationData,
+	/// Files in the instance that belong to its Minecraft version, found when it is created
+	version_files: Vec<String>,
+	/// A previous update to pin versions to, used when rolling back
+	pub(crate) pinned_update: Option<LockfileUpdateRecord>,
 }
 
 /// Different kinds of instances and their associated data
@@ -49,7 +93,7 @@ pub enum InstKind {
 	},
 	/// A server instance
 	Server {
-		/// The new world name if it is changed by the options
+		/// The world name from the server.properties written by the last update
 		world_name: Option<String>,
 	},
 }
@@ -74,15 +118,81 @@ impl InstKind {
 	}
 }
 
+/// The world name that servers use when server.properties doesn't set one
+pub const DEFAULT_WORLD_NAME: &str = "world";
+
+/// Read the level-name from the server.properties in a server's game directory
+pub fn read_world_name(game_dir: &Path) -> Option<String> {
+	let properties = read_server_properties(&game_dir.join("server.properties")).ok()?;
+	get_world_name(&properties).cloned()
+}
+
+/// Get the name of the directory that a server type loads plugins from.
+/// SpongeForge loads its plugins from the mods directory along with Forge mods
+fn get_plugins_dir_name(server_type: &ServerType) -> &'static str {
+	match server_type {
+		ServerType::SpongeForge => "mods",
+		_ => "plugins",
+	}
+}
+
+/// A well-known subdirectory of an instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceSubdir {
+	/// The game directory itself, such as .minecraft
+	Game,
+	/// The directory that mods are installed to
+	Mods,
+	/// The directory that mods store their configuration in
+	Config,
+	/// The directory that plugins are installed to
+	Plugins,
+	/// The world of a server, or the saves directory of a client
+	World,
+}
+
+impl FromStr for InstanceSubdir {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"game" => Ok(Self::Game),
+			"mods" => Ok(Self::Mods),
+			"config" => Ok(Self::Config),
+			"plugins" => Ok(Self::Plugins),
+			"world" => Ok(Self::World),
+			_ => bail!(
+				"Unknown instance subdirectory '{s}'. Possible values are: game, mods, config, plugins, world"
+			),
+		}
+	}
+}
+
 /// The stored configuration on an instance
 #[derive(Debug)]
 pub struct InstanceStoredConfig {
 	/// The instance display name
 	pub name: Option<String>,
+	/// The instance description
+	pub description: Option<String>,
+	/// Path to the PNG icon of the instance
+	pub icon: Option<PathBuf>,
+	/// Usernames or UUIDs of the players to whitelist on a server
+	pub whitelist: Option<Vec<String>>,
+	/// Usernames or UUIDs of the players to make operators on a server
+	pub ops: Option<Vec<String>>,
+	/// The ID of the user to launch a client with instead of the default user
+	pub user: Option<String>,
+	/// The profile that the instance derives from, if any
+	pub profile: Option<ProfileID>,
 	/// The Minecraft version
 	pub version: MinecraftVersion,
 	/// Modifications to the instance
 	pub modifications: GameModifications,
+	/// The requested Fabric or Quilt loader version
+	pub loader_version: LoaderVersion,
+	/// The requested Fabric installer version for the standalone server launcher
+	pub installer_version: Option<LoaderVersion>,
 	/// Launch options for the instance
 	pub launch: LaunchOptions,
 	/// The instance's global datapack folder
@@ -91,6 +201,8 @@ pub struct InstanceStoredConfig {
 	pub packages: Vec<PackageConfig>,
 	/// Default stability for packages
 	pub package_stability: PackageStability,
+	/// Default permissions for packages
+	pub package_permissions: EvalPermissions,
 	/// Custom plugin config
 	pub plugin_config: serde_json::Map<String, serde_json::Value>,
 }
@@ -104,6 +216,8 @@ impl Instance {
 			config,
 			dirs: Later::Empty,
 			modification_data: ModificationData::new(),
+			version_files: Vec::new(),
+			pinned_update: None,
 		}
 	}
 
@@ -131,4 +245,321 @@ impl Instance {
 	pub fn get_config(&self) -> &InstanceStoredConfig {
 		&self.config
 	}
+
+	/// Get the name of the active world of a server instance, which is the level-name in its server.properties.
+	/// The name is cached when the instance is created, so that the file doesn't have to be read every time
+	pub fn get_world_name(&mut self, paths: &Paths) -> String {
+		if let InstKind::Server {
+			world_name: Some(world_name),
+		} = &self.kind
+		{
+			return world_name.clone();
+		}
+
+		self.ensure_dirs_set(paths);
+		read_world_name(&self.dirs.get().game_dir).unwrap_or_else(|| DEFAULT_WORLD_NAME.into())
+	}
+
+	/// Run a command on this server through RCON and get its output.
+	/// RCON has to be enabled in the server options and the server has to be running
+	pub fn run_rcon_command(&mut self, paths: &Paths, command: &str) -> anyhow::Result<String> {
+		if self.get_side() != Side::Server {
+			bail!("Commands can only be run on server instances");
+		}
+
+		self.ensure_dirs_set(paths);
+		let properties =
+			read_server_properties(&self.dirs.get().game_dir.join("server.properties"))
+				.context("Failed to read server.properties. Has the server been updated?")?;
+		let settings = RconSettings::from_server_properties(&properties)?;
+
+		let mut client = RconClient::connect(&settings)?;
+		client.run_command(command)
+	}
+
+	/// Read the world name of a server instance from its server.properties again and cache it.
+	/// This is done after the options are written so that changes to the level-name are picked up
+	pub(crate) fn update_world_name(&mut self, paths: &Paths) {
+		self.ensure_dirs_set(paths);
+		let new_name = read_world_name(&self.dirs.get().game_dir);
+		if let InstKind::Server { world_name } = &mut self.kind {
+			*world_name = new_name;
+		}
+	}
+
+	/// Get the directory of the active world of a server, or the saves directory of a client.
+	/// This does not create the directory
+	pub fn get_world_dir(&mut self, paths: &Paths) -> PathBuf {
+		self.ensure_dirs_set(paths);
+		match &self.kind {
+			InstKind::Client { .. } => self.dirs.get().game_dir.join("saves"),
+			InstKind::Server { .. } => {
+				let world_name = self.get_world_name(paths);
+				self.dirs.get().game_dir.join(world_name)
+			}
+		}
+	}
+
+	/// Get the path to a subdirectory of the game directory of this instance.
+	/// This does not create the directory
+	pub fn get_subdir(&mut self, subdir: InstanceSubdir, paths: &Paths) -> PathBuf {
+		self.ensure_dirs_set(paths);
+		let game_dir = self.dirs.get().game_dir.clone();
+		match subdir {
+			InstanceSubdir::Game => game_dir,
+			InstanceSubdir::Mods => game_dir.join("mods"),
+			InstanceSubdir::Config => game_dir.join("config"),
+			InstanceSubdir::Plugins => {
+				game_dir.join(get_plugins_dir_name(&self.config.modifications.server_type))
+			}
+			InstanceSubdir::World => self.get_world_dir(paths),
+		}
+	}
+
+	/// Get the information about this instance that is shared with plugins
+	pub fn get_plugin_info(&self, lock: &Lockfile, paths: &Paths) -> InstanceInfo {
+		let side = self.get_side();
+		let dirs = InstanceDirs::new(paths, &self.id, &side);
+		let version = lock
+			.get_instance_version(&self.id)
+			.map(String::from)
+			.unwrap_or_else(|| self.config.version.to_string());
+
+		InstanceInfo {
+			id: self.id.to_string(),
+			side: Some(side),
+			dir: dirs.inst_dir.to_string_lossy().into(),
+			game_dir: dirs.game_dir.to_string_lossy().into(),
+			profile: self.config.profile.as_ref().map(|x| x.to_string()),
+			version: Some(version),
+		}
+	}
+
+	/// Get the packages installed on this instance in the form that is shared with plugins
+	pub fn get_plugin_package_info(
+		&self,
+		lock: &Lockfile,
+	) -> anyhow::Result<Vec<InstalledPackageInfo>> {
+		let mut packages: Vec<_> = lock
+			.get_instance_packages(&self.id)
+			.into_iter()
+			.map(|x| InstalledPackageInfo {
+				id: x.id.to_string(),
+				reason: x.reason,
+				addons: Vec::new(),
+			})
+			.collect();
+		for addon in lock.get_instance_installed_addons(&self.id)? {
+			let Some(package) = packages.iter_mut().find(|x| x.id == *addon.addon.pkg_id) else {
+				continue;
+			};
+			package.addons.push(InstalledAddonInfo {
+				id: addon.addon.id,
+				kind: addon.addon.kind,
+				version: addon.addon.version,
+				files: addon
+					.files
+					.iter()
+					.map(|x| x.to_string_lossy().into())
+					.collect(),
+			});
+		}
+
+		Ok(packages)
+	}
+
+	/// Pin the Minecraft version, Paper build, and addon versions of this instance
+	/// to those from a previous update, so that the next update restores them
+	pub fn pin_to_update(&mut self, record: LockfileUpdateRecord) {
+		// Custom versions always load the same version JSON, so they are kept as they are
+		if !self.config.version.is_custom() {
+			self.config.version = MinecraftVersion::Version(record.version.clone().into());
+		}
+		self.pinned_update = Some(record);
+	}
+
+	/// Get the pinned addon versions for a package, keyed by addon ID
+	pub fn get_pinned_addon_versions(&self, package: &str) -> HashMap<String, String> {
+		self.pinned_update
+			.as_ref()
+			.and_then(|x| x.packages.get(package))
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	/// Get the pinned Paper build for this instance
+	pub fn get_pinned_paper_build(&self) -> Option<u16> {
+		self.pinned_update.as_ref().and_then(|x| x.paper_build)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use mcvm_shared::addon::{Addon, AddonKind};
+	use mcvm_shared::output;
+	use mcvm_shared::pkg::{PackageAddonOptionalHashes, PackageID};
+	use mcvm_shared::versions::VersionInfo;
+
+	use crate::config::plugin::PluginManager;
+	use crate::config::Config;
+	use crate::io::paths::PathKind;
+
+	use super::*;
+
+	#[test]
+	fn test_instance_paths() {
+		let paths = Paths::new_no_create().unwrap();
+		let deser = serde_json::from_value(serde_json::json!({
+			"instances": {
+				"client": { "type": "client", "version": "1.20.1" },
+				"server": { "type": "server", "version": "1.20.1" }
+			}
+		}))
+		.unwrap();
+		let mut config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			false,
+			&paths,
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+
+		let version_info = VersionInfo {
+			version: "1.20.1".into(),
+			versions: vec!["1.20.1".into()],
+		};
+		let addon = |kind| Addon {
+			kind,
+			id: "addon".into(),
+			file_name: "addon.jar".into(),
+			pkg_id: PackageID::from("pkg"),
+			version: None,
+			hashes: PackageAddonOptionalHashes::default(),
+		};
+
+		for (id, side) in [("client", Side::Client), ("server", Side::Server)] {
+			let instance = config.instances.get_mut(&InstanceID::from(id)).unwrap();
+			// These are the directories that the instance is created in
+			let dirs = InstanceDirs::new(&paths, id, &side);
+			assert_eq!(dirs.inst_dir.parent(), Some(paths.get(PathKind::Instances)));
+			assert_eq!(
+				instance.get_subdir(InstanceSubdir::Game, &paths),
+				dirs.game_dir
+			);
+
+			let mods = instance
+				.get_linked_addon_paths(&addon(AddonKind::Mod), &[], &paths, &version_info)
+				.unwrap();
+			assert_eq!(
+				mods,
+				vec![instance.get_subdir(InstanceSubdir::Mods, &paths)]
+			);
+			assert_eq!(
+				instance.get_subdir(InstanceSubdir::Config, &paths),
+				dirs.game_dir.join("config")
+			);
+		}
+
+		let server = config
+			.instances
+			.get_mut(&InstanceID::from("server"))
+			.unwrap();
+		let world = server.get_subdir(InstanceSubdir::World, &paths);
+		let datapacks = server
+			.get_linked_addon_paths(&addon(AddonKind::Datapack), &[], &paths, &version_info)
+			.unwrap();
+		assert_eq!(datapacks, vec![world.join("datapacks")]);
+		let plugins = server
+			.get_linked_addon_paths(&addon(AddonKind::Plugin), &[], &paths, &version_info)
+			.unwrap();
+		assert_eq!(
+			plugins,
+			vec![server.get_subdir(InstanceSubdir::Plugins, &paths)]
+		);
+	}
+
+	#[test]
+	fn test_custom_world_name() {
+		let mut paths = Paths::new_no_create().unwrap();
+		let temp_dir = tempfile::tempdir().unwrap();
+		paths.instances = temp_dir.path().to_path_buf();
+		let deser = serde_json::from_value(serde_json::json!({
+			"instances": {
+				"server": { "type": "server", "version": "1.20.1" }
+			}
+		}))
+		.unwrap();
+		let mut config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			false,
+			&paths,
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+		let server = config
+			.instances
+			.get_mut(&InstanceID::from("server"))
+			.unwrap();
+		server.ensure_dirs(&paths).unwrap();
+		let game_dir = server.get_subdir(InstanceSubdir::Game, &paths);
+
+		assert_eq!(server.get_world_name(&paths), DEFAULT_WORLD_NAME);
+		assert_eq!(server.get_world_dir(&paths), game_dir.join("world"));
+
+		let properties_path = game_dir.join("server.properties");
+		std::fs::write(
+			&properties_path,
+			include_str!("fixtures/custom_level_name.properties"),
+		)
+		.unwrap();
+		server.update_world_name(&paths);
+		assert_eq!(server.get_world_name(&paths), "survival");
+		assert_eq!(
+			server.get_subdir(InstanceSubdir::World, &paths),
+			game_dir.join("survival")
+		);
+		let version_info = VersionInfo {
+			version: "1.20.1".into(),
+			versions: vec!["1.20.1".into()],
+		};
+		let datapack = Addon {
+			kind: AddonKind::Datapack,
+			id: "addon".into(),
+			file_name: "addon.zip".into(),
+			pkg_id: PackageID::from("pkg"),
+			version: None,
+			hashes: PackageAddonOptionalHashes::default(),
+		};
+		assert_eq!(
+			server
+				.get_linked_addon_paths(&datapack, &[], &paths, &version_info)
+				.unwrap(),
+			vec![game_dir.join("survival").join("datapacks")]
+		);
+
+		std::fs::create_dir_all(game_dir.join("survival/region")).unwrap();
+		std::fs::write(game_dir.join("survival/level.dat"), "").unwrap();
+		assert_eq!(server.get_world_paths(&paths), vec!["survival"]);
+
+		// Changes to the level-name are picked up on the next update
+		std::fs::write(&properties_path, "level-name=creative\n").unwrap();
+		assert_eq!(server.get_world_name(&paths), "survival");
+		server.update_world_name(&paths);
+		assert_eq!(server.get_world_name(&paths), "creative");
+	}
+
+	#[test]
+	fn test_instance_subdir_parse() {
+		assert_eq!(
+			InstanceSubdir::from_str("mods").unwrap(),
+			InstanceSubdir::Mods
+		);
+		assert_eq!(
+			InstanceSubdir::from_str("world").unwrap(),
+			InstanceSubdir::World
+		);
+		assert!(InstanceSubdir::from_str("foo").is_err());
+	}
 }
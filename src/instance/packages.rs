@@ -1,22 +1,27 @@
 use anyhow::{bail, Context};
+use mcvm_core::net::stats;
+use mcvm_pkg::resolve::get_reachable_packages;
+use mcvm_shared::addon::AddonKind;
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
-use mcvm_shared::pkg::ArcPkgReq;
+use mcvm_shared::pkg::{ArcPkgReq, PackageID};
 use mcvm_shared::translate;
 use mcvm_shared::versions::VersionInfo;
 use reqwest::Client;
 
-use crate::addon::AddonExt;
+use crate::addon::{AddonExt, AddonLinkMode};
 use crate::config::plugin::PluginManager;
 use crate::io::lock::{Lockfile, LockfileAddon};
 use crate::io::paths::Paths;
 use crate::pkg::eval::{EvalData, EvalInput, Routine};
 use crate::pkg::reg::PkgRegistry;
 
+use super::ignore::InstanceIgnore;
 use super::Instance;
 use crate::config::package::PackageConfig;
 
 use std::collections::HashMap;
 use std::future::Future;
+use std::path::PathBuf;
 
 impl Instance {
 	/// Installs a package on this instance
@@ -29,6 +34,7 @@ impl Instance {
 		paths: &'a Paths,
 		lock: &mut Lockfile,
 		force: bool,
+		link_mode: AddonLinkMode,
 		client: &Client,
 		plugins: &'a PluginManager,
 		o: &mut impl MCVMOutput,
@@ -47,9 +53,20 @@ impl Instance {
 			task.await.context("Failed to install addon")?;
 		}
 
-		self.install_eval_data(pkg, &eval, &version_info, paths, lock, o)
-			.await
-			.context("Failed to install evaluation data on instance")?;
+		let ignore = self.load_ignore(paths, o);
+		self.install_eval_data(
+			pkg,
+			&eval,
+			&version_info,
+			paths,
+			lock,
+			link_mode,
+			&ignore,
+			client,
+			o,
+		)
+		.await
+		.context("Failed to install evaluation data on instance")?;
 
 		Ok(eval)
 	}
@@ -75,17 +92,60 @@ impl Instance {
 			.await
 			.context("Failed to evaluate package")?;
 
+		let tasks = self.get_addon_tasks(&eval, paths, force, client)?;
+
+		Ok((eval, tasks))
+	}
+
+	/// Gets the tasks for acquiring the addons from an evaluated package that need to be updated
+	pub fn get_addon_tasks(
+		&self,
+		eval: &EvalData<'_>,
+		paths: &Paths,
+		force: bool,
+		client: &Client,
+	) -> anyhow::Result<HashMap<String, impl Future<Output = anyhow::Result<()>> + Send + 'static>>
+	{
 		let mut tasks = HashMap::new();
 		for addon in eval.addon_reqs.iter() {
 			if addon.addon.should_update(paths, &self.id) || force {
+				stats::record_cache_miss();
 				let task = addon
 					.get_acquire_task(paths, &self.id, client)
 					.context("Failed to get task for acquiring addon")?;
 				tasks.insert(addon.get_unique_id(&self.id), task);
+			} else {
+				stats::record_cache_hit();
 			}
 		}
 
-		Ok((eval, tasks))
+		Ok(tasks)
+	}
+
+	/// Gets the paths of the files in this instance that the addons of an evaluated package would be linked to
+	pub fn get_eval_addon_files(
+		&mut self,
+		pkg: &ArcPkgReq,
+		eval: &EvalData<'_>,
+		version_info: &VersionInfo,
+		paths: &Paths,
+	) -> anyhow::Result<Vec<(String, Vec<PathBuf>)>> {
+		let worlds = self
+			.get_package_config(&pkg.id)
+			.map(|x| x.worlds.clone())
+			.unwrap_or_default();
+
+		eval.addon_reqs
+			.iter()
+			.map(|x| {
+				let files = self
+					.get_linked_addon_paths(&x.addon, &worlds, paths, version_info)?
+					.iter()
+					.map(|y| y.join(&x.addon.file_name))
+					.collect();
+				Ok((x.addon.id.clone(), files))
+			})
+			.collect()
 	}
 
 	/// Install the EvalData resulting from evaluating a package onto this instance
@@ -97,6 +157,9 @@ impl Instance {
 		version_info: &VersionInfo,
 		paths: &Paths,
 		lock: &mut Lockfile,
+		link_mode: AddonLinkMode,
+		ignore: &InstanceIgnore,
+		client: &Client,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<()> {
 		// Get the configuration for the package or the default if it is not configured by the user
@@ -119,9 +182,22 @@ impl Instance {
 			.addon_reqs
 			.iter()
 			.map(|x| {
+				let linked_paths =
+					self.get_linked_addon_paths(&x.addon, &pkg_config.worlds, paths, version_info)?;
+				if linked_paths.is_empty() && matches!(x.addon.kind, AddonKind::Datapack) {
+					o.display(
+						MessageContents::Warning(translate!(
+							o,
+							DatapackSkipped,
+							"addon" = &x.addon.id
+						)),
+						MessageLevel::Important,
+					);
+				}
+
 				Ok(LockfileAddon::from_addon(
 					&x.addon,
-					self.get_linked_addon_paths(&x.addon, &pkg_config.worlds, paths, version_info)?
+					linked_paths
 						.iter()
 						.map(|y| y.join(x.addon.file_name.clone()))
 						.collect(),
@@ -130,20 +206,67 @@ impl Instance {
 			.collect::<anyhow::Result<Vec<LockfileAddon>>>()
 			.context("Failed to convert addons to the lockfile format")?;
 
+		// Remember how the addons were installed before, since the lockfile entries get replaced
+		let previous_link_modes: HashMap<_, _> = eval
+			.addon_reqs
+			.iter()
+			.filter_map(|x| {
+				let mode = lock.get_addon_link_mode(&self.id, &pkg.id, &x.addon.id)?;
+				Some((x.addon.id.clone(), mode))
+			})
+			.collect();
+
 		let files_to_remove = lock
-			.update_package(&pkg.id, &self.id, &lockfile_addons, o)
+			.update_package(&pkg.id, &self.id, &lockfile_addons, ignore, o)
 			.context("Failed to update package in lockfile")?;
 
 		for addon in eval.addon_reqs.iter() {
-			self.create_addon(&addon.addon, &pkg_config.worlds, paths, version_info)
+			let used_link_mode = self
+				.create_addon(
+					&addon.addon,
+					&pkg_config.worlds,
+					paths,
+					version_info,
+					link_mode,
+					previous_link_modes.get(&addon.addon.id).copied(),
+					ignore,
+				)
 				.with_context(|| format!("Failed to install addon '{}'", addon.addon.id))?;
+			let provenance = addon
+				.get_provenance(
+					paths,
+					&self.id,
+					lock.get_addon_provenance(&self.id, &pkg.id, &addon.addon.id),
+				)
+				.with_context(|| {
+					format!(
+						"Failed to record where addon '{}' came from",
+						addon.addon.id
+					)
+				})?;
+			lock.record_addon_install(
+				&self.id,
+				&pkg.id,
+				&addon.addon.id,
+				used_link_mode,
+				provenance,
+			);
 		}
 
 		for path in files_to_remove {
-			self.remove_addon_file(&path, paths)
+			self.remove_addon_file(&path, paths, ignore)
 				.context("Failed to remove addon file from instance")?;
 		}
 
+		let config_files = self
+			.install_config_files(&pkg.id, &eval.config_files, paths, lock, ignore, client)
+			.await
+			.context("Failed to install config files")?;
+		for path in lock.update_package_config_files(&self.id, &pkg.id, &config_files) {
+			self.remove_addon_file(&path, paths, ignore)
+				.context("Failed to remove config file from instance")?;
+		}
+
 		Ok(())
 	}
 
@@ -158,6 +281,49 @@ impl Instance {
 
 		configured_packages.iter().find(|x| x.id == package.into())
 	}
+
+	/// Gets the installed packages on this instance that are no longer needed by any
+	/// configured package. Local packages and ones installed before reasons were recorded are kept
+	pub fn get_unneeded_packages(&self, lock: &Lockfile) -> Vec<PackageID> {
+		let installed = lock.get_instance_packages(&self.id);
+		let roots = self
+			.get_configured_packages()
+			.iter()
+			.map(|x| x.id.clone())
+			.chain(
+				installed
+					.iter()
+					.filter(|x| x.reason.is_none() || x.local)
+					.map(|x| x.id.clone()),
+			);
+		let required_by: HashMap<_, _> = installed
+			.iter()
+			.map(|x| (x.id.clone(), x.required_by.clone()))
+			.collect();
+		let reachable = get_reachable_packages(roots, &required_by);
+
+		installed
+			.into_iter()
+			.map(|x| x.id)
+			.filter(|x| !reachable.contains(x))
+			.collect()
+	}
+
+	/// Removes an installed package and the addon and config files it created from this instance. Ignored files are kept
+	pub fn remove_installed_package(
+		&self,
+		package: &str,
+		lock: &mut Lockfile,
+		paths: &Paths,
+		ignore: &InstanceIgnore,
+	) -> anyhow::Result<()> {
+		for path in lock.remove_package(&self.id, package) {
+			self.remove_addon_file(&path, paths, ignore)
+				.context("Failed to remove addon file from instance")?;
+		}
+
+		Ok(())
+	}
 }
 
 /// Runs package commands
@@ -191,3 +357,177 @@ fn run_package_commands(commands: &[Vec<String>], o: &mut impl MCVMOutput) -> an
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use mcvm_shared::addon::Addon;
+	use mcvm_shared::id::InstanceID;
+	use mcvm_shared::output::{self, NoOp};
+	use mcvm_shared::pkg::{ConfigFilePolicy, PackageAddonOptionalHashes};
+
+	use crate::config::Config;
+	use crate::pkg::eval::{ConfigFileLocation, ConfigFileRequest};
+
+	use super::super::ignore::IGNORE_FILE_NAME;
+	use super::*;
+
+	#[test]
+	fn test_ignored_addon_survives_removal() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let mut paths = Paths::new_no_create().unwrap();
+		paths.internal = dir.join("internal");
+		paths.addons = dir.join("addons");
+		paths.instances = dir.join("instances");
+
+		let deser = serde_json::from_value(serde_json::json!({
+			"instances": {
+				"server": { "type": "server", "version": "1.20.1" }
+			}
+		}))
+		.unwrap();
+		let config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			false,
+			&paths,
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+		let instance = config.instances.get(&InstanceID::from("server")).unwrap();
+
+		let mods_dir = paths.instances.join("server").join("mods");
+		fs::create_dir_all(&mods_dir).unwrap();
+		let addon = |id: &str| {
+			let addon = Addon {
+				kind: AddonKind::Mod,
+				id: id.into(),
+				file_name: format!("{id}.jar"),
+				pkg_id: PackageID::from("pkg"),
+				version: None,
+				hashes: PackageAddonOptionalHashes::default(),
+			};
+			LockfileAddon::from_addon(&addon, vec![mods_dir.join(&addon.file_name)])
+		};
+
+		// The user placed their own sodium.jar, which has the same name as the tracked addon
+		fs::write(mods_dir.join("sodium.jar"), "custom").unwrap();
+		fs::write(
+			paths.instances.join("server").join(IGNORE_FILE_NAME),
+			"# My own build\nmods/sodium.jar\n",
+		)
+		.unwrap();
+		let ignore = instance.load_ignore(&paths, &mut NoOp);
+
+		// Ignored files don't need to be confirmed before installing over them
+		let mut lock = Lockfile::open(&paths).unwrap();
+		lock.update_package(
+			"pkg",
+			"server",
+			&[addon("sodium"), addon("lithium")],
+			&ignore,
+			&mut NoOp,
+		)
+		.unwrap();
+		fs::write(mods_dir.join("lithium.jar"), "lithium").unwrap();
+		assert!(lock
+			.get_instance_addon_files("server")
+			.contains(&mods_dir.join("sodium.jar")));
+
+		instance
+			.remove_installed_package("pkg", &mut lock, &paths, &ignore)
+			.unwrap();
+
+		assert_eq!(
+			fs::read_to_string(mods_dir.join("sodium.jar")).unwrap(),
+			"custom"
+		);
+		assert!(!mods_dir.join("lithium.jar").exists());
+	}
+
+	#[test]
+	fn test_config_files_removed_with_package() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let mut paths = Paths::new_no_create().unwrap();
+		paths.internal = dir.join("internal");
+		paths.instances = dir.join("instances");
+
+		let deser = serde_json::from_value(serde_json::json!({
+			"instances": {
+				"server": { "type": "server", "version": "1.20.1" }
+			}
+		}))
+		.unwrap();
+		let mut config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			false,
+			&paths,
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+		let instance = config
+			.instances
+			.get_mut(&InstanceID::from("server"))
+			.unwrap();
+		let ignore = instance.load_ignore(&paths, &mut NoOp);
+		let game_dir = paths.instances.join("server");
+		let config_dir = game_dir.join("config");
+		fs::create_dir_all(&config_dir).unwrap();
+		fs::write(config_dir.join("existing.json"), r#"{"user": true}"#).unwrap();
+
+		let config_file = |path: &str, policy| ConfigFileRequest {
+			path: path.into(),
+			location: ConfigFileLocation::Contents(r#"{"package": true}"#.into()),
+			policy,
+		};
+		let config_files = [
+			config_file("config/new.json", ConfigFilePolicy::Create),
+			config_file("config/existing.json", ConfigFilePolicy::JsonMerge),
+		];
+
+		let mut lock = Lockfile::open(&paths).unwrap();
+		lock.update_package("pkg", "server", &[], &ignore, &mut NoOp)
+			.unwrap();
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let created = runtime
+			.block_on(instance.install_config_files(
+				"pkg",
+				&config_files,
+				&paths,
+				&lock,
+				&ignore,
+				&Client::new(),
+			))
+			.unwrap();
+		assert_eq!(created, vec![config_dir.join("new.json")]);
+		assert!(lock
+			.update_package_config_files("server", "pkg", &created)
+			.is_empty());
+
+		// Reinstalling keeps track of the files created the first time
+		let created = runtime
+			.block_on(instance.install_config_files(
+				"pkg",
+				&config_files,
+				&paths,
+				&lock,
+				&ignore,
+				&Client::new(),
+			))
+			.unwrap();
+		assert_eq!(created, vec![config_dir.join("new.json")]);
+
+		instance
+			.remove_installed_package("pkg", &mut lock, &paths, &ignore)
+			.unwrap();
+		assert!(!config_dir.join("new.json").exists());
+		assert_eq!(
+			fs::read_to_string(config_dir.join("existing.json")).unwrap(),
+			"{\n  \"package\": true,\n  \"user\": true\n}"
+		);
+	}
+}
@@ -0,0 +1,489 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use mcvm_core::io::{json_from_file, json_to_file_pretty};
+use mcvm_core::net::minecraft::{get_player_by_name, get_player_by_uuid, PlayerProfile};
+use mcvm_core::user::uuid::{hyphenate_uuid, offline_uuid};
+use mcvm_options::server::read_server_properties;
+use mcvm_shared::util::utc_timestamp;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::io::paths::Paths;
+
+use super::ignore::InstanceIgnore;
+use super::{InstKind, Instance};
+
+/// The file in the server directory that holds the whitelisted players
+pub const WHITELIST_FILE_NAME: &str = "whitelist.json";
+/// The file in the server directory that holds the operators
+pub const OPS_FILE_NAME: &str = "ops.json";
+/// The permission level that servers give to operators when server.properties doesn't set one
+pub const DEFAULT_OP_LEVEL: u8 = 4;
+/// How many seconds a player looked up from the Mojang API is cached for
+pub const PLAYER_CACHE_TIME: u64 = 60 * 60 * 24 * 7;
+
+/// A player in whitelist.json
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WhitelistEntry {
+	/// The hyphenated UUID of the player
+	pub uuid: String,
+	/// The username of the player
+	pub name: String,
+}
+
+/// An operator in ops.json
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OpEntry {
+	/// The hyphenated UUID of the player
+	pub uuid: String,
+	/// The username of the player
+	pub name: String,
+	/// The permission level of the operator, from 1 to 4
+	pub level: u8,
+	/// Whether the operator can join when the server is full
+	pub bypasses_player_limit: bool,
+}
+
+/// An entry in one of the player list files
+trait PlayerEntry: Sized {
+	/// Create an entry for a player that isn't in the file yet
+	fn new(player: &PlayerProfile, op_level: u8) -> Self;
+
+	/// Get the UUID of the player
+	fn uuid(&self) -> &str;
+
+	/// Update the name of the player, keeping everything else
+	fn set_name(&mut self, name: &str);
+}
+
+impl PlayerEntry for WhitelistEntry {
+	fn new(player: &PlayerProfile, _op_level: u8) -> Self {
+		Self {
+			uuid: player.uuid.clone(),
+			name: player.name.clone(),
+		}
+	}
+
+	fn uuid(&self) -> &str {
+		&self.uuid
+	}
+
+	fn set_name(&mut self, name: &str) {
+		self.name = name.into();
+	}
+}
+
+impl PlayerEntry for OpEntry {
+	fn new(player: &PlayerProfile, op_level: u8) -> Self {
+		Self {
+			uuid: player.uuid.clone(),
+			name: player.name.clone(),
+			level: op_level,
+			bypasses_player_limit: false,
+		}
+	}
+
+	fn uuid(&self) -> &str {
+		&self.uuid
+	}
+
+	fn set_name(&mut self, name: &str) {
+		self.name = name.into();
+	}
+}
+
+/// Merge the configured players into the entries of a player list file. Entries that were added
+/// in-game are kept and existing entries only get their name updated, unless `exact` is set,
+/// in which case the file will contain only the configured players
+fn merge_entries<E: PlayerEntry>(
+	existing: Vec<E>,
+	players: &[PlayerProfile],
+	op_level: u8,
+	exact: bool,
+) -> Vec<E> {
+	if exact {
+		return players.iter().map(|x| E::new(x, op_level)).collect();
+	}
+
+	let mut out = existing;
+	for player in players {
+		let entry = out
+			.iter_mut()
+			.find(|x| x.uuid().eq_ignore_ascii_case(&player.uuid));
+		if let Some(entry) = entry {
+			entry.set_name(&player.name);
+		} else {
+			out.push(E::new(player, op_level));
+		}
+	}
+
+	out
+}
+
+/// Merge configured players into a player list file, only writing it if it changes
+fn sync_player_file<E: PlayerEntry + Serialize + DeserializeOwned + PartialEq + Clone>(
+	path: &Path,
+	players: &[PlayerProfile],
+	op_level: u8,
+	exact: bool,
+) -> anyhow::Result<()> {
+	let existing: Option<Vec<E>> = if path.exists() {
+		let existing =
+			json_from_file(path).with_context(|| format!("Failed to read {}", path.display()))?;
+		Some(existing)
+	} else {
+		None
+	};
+
+	let merged = merge_entries(
+		existing.clone().unwrap_or_default(),
+		players,
+		op_level,
+		exact,
+	);
+	if existing.is_some_and(|x| x == merged) {
+		return Ok(());
+	}
+	json_to_file_pretty(path, &merged)
+		.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Check whether a player in the config is a UUID instead of a username
+fn is_uuid(player: &str) -> bool {
+	let player = player.replace('-', "");
+	player.len() == 32 && player.chars().all(|x| x.is_ascii_hexdigit())
+}
+
+/// Persistent cache of the players looked up from the Mojang API, to avoid its rate limits
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct PlayerCache {
+	/// Cached players by their lowercase username or UUID without hyphens
+	players: HashMap<String, CachedPlayer>,
+}
+
+/// A player in the cache
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedPlayer {
+	/// The profile of the player
+	#[serde(flatten)]
+	profile: PlayerProfile,
+	/// When the player was looked up
+	time: u64,
+}
+
+impl PlayerCache {
+	/// Open the cache, creating it if it doesn't exist
+	pub fn open(paths: &Paths) -> anyhow::Result<Self> {
+		let path = Self::get_path(paths);
+		if path.exists() {
+			json_from_file(path).context("Failed to read player cache")
+		} else {
+			Ok(Self::default())
+		}
+	}
+
+	/// Write the cache to disk
+	pub fn write(&self, paths: &Paths) -> anyhow::Result<()> {
+		json_to_file_pretty(Self::get_path(paths), self).context("Failed to write player cache")
+	}
+
+	/// Get a player from the cache if it was looked up recently enough
+	fn get(&self, key: &str, now: u64) -> Option<&PlayerProfile> {
+		self.players
+			.get(key)
+			.filter(|x| now.saturating_sub(x.time) < PLAYER_CACHE_TIME)
+			.map(|x| &x.profile)
+	}
+
+	/// Add a player to the cache
+	fn insert(&mut self, key: String, profile: PlayerProfile, now: u64) {
+		self.players
+			.insert(key, CachedPlayer { profile, time: now });
+	}
+
+	fn get_path(paths: &Paths) -> PathBuf {
+		paths.internal.join("player_cache.json")
+	}
+}
+
+/// Resolve a username or UUID from the config to the profile of the player, with a hyphenated UUID.
+/// Offline-mode servers use the offline UUID of the username instead of asking the Mojang API
+async fn resolve_player(
+	player: &str,
+	online_mode: bool,
+	cache: &mut PlayerCache,
+	client: &Client,
+) -> anyhow::Result<PlayerProfile> {
+	let uuid = is_uuid(player);
+	if !online_mode {
+		if uuid {
+			bail!("Offline-mode servers can't look up the username of UUID '{player}'. Use the username of the player instead");
+		}
+		return Ok(PlayerProfile {
+			name: player.into(),
+			uuid: hyphenate_uuid(&offline_uuid(player))?,
+		});
+	}
+
+	let key = if uuid {
+		player.replace('-', "").to_ascii_lowercase()
+	} else {
+		player.to_ascii_lowercase()
+	};
+	let now = utc_timestamp()?;
+	let profile = if let Some(profile) = cache.get(&key, now) {
+		profile.clone()
+	} else {
+		let profile = if uuid {
+			get_player_by_uuid(&key, client).await
+		} else {
+			get_player_by_name(player, client).await
+		}
+		.with_context(|| format!("Failed to look up player '{player}'"))?
+		.with_context(|| format!("No Minecraft player named '{player}' exists"))?;
+		cache.insert(key, profile.clone(), now);
+		profile
+	};
+
+	Ok(PlayerProfile {
+		name: profile.name,
+		uuid: hyphenate_uuid(&profile.uuid.replace('-', ""))?,
+	})
+}
+
+/// Resolve a list of players from the config
+async fn resolve_players(
+	players: &[String],
+	online_mode: bool,
+	cache: &mut PlayerCache,
+	client: &Client,
+) -> anyhow::Result<Vec<PlayerProfile>> {
+	let mut out = Vec::with_capacity(players.len());
+	for player in players {
+		out.push(resolve_player(player, online_mode, cache, client).await?);
+	}
+	Ok(out)
+}
+
+impl Instance {
+	/// Write the whitelist and ops from the config of this server to its whitelist.json and ops.json.
+	/// Players that were added in-game are kept unless `exact` is set. Ignored files are not changed
+	pub async fn sync_player_lists(
+		&self,
+		paths: &Paths,
+		client: &Client,
+		exact: bool,
+		ignore: &InstanceIgnore,
+	) -> anyhow::Result<()> {
+		debug_assert!(matches!(self.kind, InstKind::Server { .. }));
+
+		let game_dir = &self.dirs.get().game_dir;
+		let whitelist_path = game_dir.join(WHITELIST_FILE_NAME);
+		let ops_path = game_dir.join(OPS_FILE_NAME);
+		let whitelist = self
+			.config
+			.whitelist
+			.as_ref()
+			.filter(|_| !ignore.is_ignored(&whitelist_path));
+		let ops = self
+			.config
+			.ops
+			.as_ref()
+			.filter(|_| !ignore.is_ignored(&ops_path));
+		if whitelist.is_none() && ops.is_none() {
+			return Ok(());
+		}
+
+		let properties =
+			read_server_properties(&game_dir.join("server.properties")).unwrap_or_default();
+		let online_mode = properties.get("online-mode").map_or(true, |x| x != "false");
+		let op_level = properties
+			.get("op-permission-level")
+			.and_then(|x| x.parse().ok())
+			.unwrap_or(DEFAULT_OP_LEVEL);
+
+		let mut cache = PlayerCache::open(paths)?;
+		let result = async {
+			if let Some(whitelist) = whitelist {
+				let players = resolve_players(whitelist, online_mode, &mut cache, client)
+					.await
+					.context("Failed to resolve whitelisted players")?;
+				sync_player_file::<WhitelistEntry>(&whitelist_path, &players, op_level, exact)?;
+			}
+			if let Some(ops) = ops {
+				let players = resolve_players(ops, online_mode, &mut cache, client)
+					.await
+					.context("Failed to resolve operators")?;
+				sync_player_file::<OpEntry>(&ops_path, &players, op_level, exact)?;
+			}
+
+			Ok::<(), anyhow::Error>(())
+		}
+		.await;
+		// Keep the players that were looked up even if another one failed
+		cache.write(paths)?;
+
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+
+	fn player(name: &str, uuid: &str) -> PlayerProfile {
+		PlayerProfile {
+			name: name.into(),
+			uuid: uuid.into(),
+		}
+	}
+
+	const NOTCH: &str = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+	const JEB: &str = "853c80ef-3c37-49fd-aa49-938b674adae6";
+
+	#[test]
+	fn test_file_format() {
+		let ops: Vec<OpEntry> = merge_entries(Vec::new(), &[player("Notch", NOTCH)], 4, false);
+		assert_eq!(
+			serde_json::to_value(&ops).unwrap(),
+			serde_json::json!([{
+				"uuid": NOTCH,
+				"name": "Notch",
+				"level": 4,
+				"bypassesPlayerLimit": false
+			}])
+		);
+
+		let whitelist: Vec<WhitelistEntry> =
+			merge_entries(Vec::new(), &[player("Notch", NOTCH)], 4, false);
+		assert_eq!(
+			serde_json::to_value(&whitelist).unwrap(),
+			serde_json::json!([{ "uuid": NOTCH, "name": "Notch" }])
+		);
+
+		// Files written by the server can be read back
+		let vanilla = r#"[
+  {
+    "uuid": "853c80ef-3c37-49fd-aa49-938b674adae6",
+    "name": "jeb_",
+    "level": 2,
+    "bypassesPlayerLimit": true
+  }
+]"#;
+		let ops: Vec<OpEntry> = serde_json::from_str(vanilla).unwrap();
+		assert_eq!(ops[0].level, 2);
+		assert!(ops[0].bypasses_player_limit);
+	}
+
+	#[test]
+	fn test_merge_entries() {
+		let existing = vec![
+			OpEntry {
+				uuid: JEB.into(),
+				name: "jeb_".into(),
+				level: 2,
+				bypasses_player_limit: true,
+			},
+			OpEntry {
+				uuid: NOTCH.into(),
+				name: "OldName".into(),
+				level: 3,
+				bypasses_player_limit: true,
+			},
+		];
+		let players = [player("Notch", &NOTCH.to_uppercase())];
+
+		// Players added in-game are kept, and existing players keep their settings
+		let merged = merge_entries(existing.clone(), &players, 4, false);
+		assert_eq!(merged.len(), 2);
+		assert_eq!(merged[0], existing[0]);
+		assert_eq!(merged[1].name, "Notch");
+		assert_eq!(merged[1].level, 3);
+		assert!(merged[1].bypasses_player_limit);
+
+		let merged = merge_entries(existing, &players, 4, true);
+		assert_eq!(merged.len(), 1);
+		assert_eq!(merged[0].name, "Notch");
+		assert_eq!(merged[0].level, 4);
+		assert!(!merged[0].bypasses_player_limit);
+	}
+
+	#[test]
+	fn test_offline_players() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let client = Client::new();
+		let mut cache = PlayerCache::default();
+
+		let player = runtime
+			.block_on(resolve_player("Notch", false, &mut cache, &client))
+			.unwrap();
+		assert_eq!(player.uuid, "b50ad385-829d-3141-a216-7e7d7539ba7f");
+		assert_eq!(player.name, "Notch");
+
+		assert!(runtime
+			.block_on(resolve_player(NOTCH, false, &mut cache, &client))
+			.is_err());
+	}
+
+	#[test]
+	fn test_player_cache() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let client = Client::new();
+		let mut cache = PlayerCache::default();
+		let now = utc_timestamp().unwrap();
+		cache.insert(
+			"notch".into(),
+			player("Notch", &NOTCH.replace('-', "")),
+			now,
+		);
+		cache.insert(
+			"jeb_".into(),
+			player("jeb_", &JEB.replace('-', "")),
+			now - PLAYER_CACHE_TIME,
+		);
+
+		// Cached players are case-insensitive and don't use the API
+		let player = runtime
+			.block_on(resolve_player("NOTCH", true, &mut cache, &client))
+			.unwrap();
+		assert_eq!(player.uuid, NOTCH);
+		assert!(cache.get("jeb_", now).is_none());
+		assert!(is_uuid(NOTCH));
+		assert!(!is_uuid("Notch"));
+	}
+
+	#[test]
+	fn test_sync_player_file() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let path = dir.join(WHITELIST_FILE_NAME);
+
+		fs::write(
+			&path,
+			r#"[{"uuid": "853c80ef-3c37-49fd-aa49-938b674adae6", "name": "jeb_"}]"#,
+		)
+		.unwrap();
+		sync_player_file::<WhitelistEntry>(&path, &[player("Notch", NOTCH)], 4, false).unwrap();
+		let whitelist: Vec<WhitelistEntry> = json_from_file(&path).unwrap();
+		assert_eq!(whitelist.len(), 2);
+
+		sync_player_file::<WhitelistEntry>(&path, &[player("Notch", NOTCH)], 4, true).unwrap();
+		let whitelist: Vec<WhitelistEntry> = json_from_file(&path).unwrap();
+		assert_eq!(
+			whitelist,
+			vec![WhitelistEntry::new(&player("Notch", NOTCH), 4)]
+		);
+
+		// Broken files aren't overwritten
+		fs::write(&path, "not json").unwrap();
+		assert!(sync_player_file::<WhitelistEntry>(&path, &[], 4, false).is_err());
+		assert_eq!(fs::read_to_string(&path).unwrap(), "not json");
+	}
+}
@@ -0,0 +1,233 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use mcvm_plugin::hooks::{RenameInstance, RenameInstanceArg};
+use mcvm_shared::id::InstanceID;
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::translate;
+
+use crate::addon::AddonExt;
+use crate::config::plugin::PluginManager;
+use crate::io::lock::Lockfile;
+use crate::io::paths::Paths;
+
+use super::snapshot::get_snapshots_dir;
+
+/// A file or directory that has to be moved when an instance is renamed
+#[derive(Debug, Clone)]
+pub struct RenameMove {
+	/// What is being moved, for reporting to the user
+	pub description: String,
+	/// The current path
+	pub from: PathBuf,
+	/// The path after the rename
+	pub to: PathBuf,
+}
+
+/// The changes on disk needed to rename an instance. Everything is checked when the
+/// rename is planned so that nothing is touched if the rename can't be completed
+#[derive(Debug)]
+pub struct InstanceRename {
+	/// The current ID of the instance
+	pub old: InstanceID,
+	/// The new ID of the instance
+	pub new: InstanceID,
+	/// The files and directories that will be moved
+	pub moves: Vec<RenameMove>,
+	old_dir: PathBuf,
+	new_dir: PathBuf,
+}
+
+impl InstanceRename {
+	/// Work out what has to be moved to rename an instance, making sure that nothing will be overwritten
+	pub fn plan(
+		old: InstanceID,
+		new: InstanceID,
+		lock: &Lockfile,
+		paths: &Paths,
+	) -> anyhow::Result<Self> {
+		let old_dir = get_instance_dir(paths, &old);
+		let new_dir = get_instance_dir(paths, &new);
+
+		let mut moves = vec![
+			RenameMove {
+				description: "instance directory".into(),
+				from: old_dir.clone(),
+				to: new_dir.clone(),
+			},
+			RenameMove {
+				description: "snapshots".into(),
+				from: get_snapshots_dir(paths, &old),
+				to: get_snapshots_dir(paths, &new),
+			},
+		];
+		// Addons without a version are stored separately for each instance
+		let addons = lock
+			.get_instance_addons(&old)
+			.context("Failed to get instance addons from lockfile")?;
+		for addon in addons.iter().filter(|x| x.version.is_none()) {
+			moves.push(RenameMove {
+				description: format!(
+					"stored addon '{}' from package '{}'",
+					addon.id, addon.pkg_id
+				),
+				from: addon.get_path(paths, &old),
+				to: addon.get_path(paths, &new),
+			});
+		}
+
+		moves.retain(|x| x.from.exists());
+		for step in &moves {
+			if step.to.exists() {
+				bail!(
+					"Cannot move the {} of instance '{old}' since '{}' already exists",
+					step.description,
+					step.to.display()
+				);
+			}
+		}
+
+		Ok(Self {
+			old,
+			new,
+			moves,
+			old_dir,
+			new_dir,
+		})
+	}
+
+	/// Move the files of the instance. If a move fails, the ones that were already done are moved back,
+	/// and the error describes what was and wasn't moved
+	pub fn move_files(&self) -> anyhow::Result<()> {
+		for (i, step) in self.moves.iter().enumerate() {
+			let Err(e) = move_path(step) else {
+				continue;
+			};
+
+			let mut restored = Vec::new();
+			let mut not_restored = Vec::new();
+			for done in self.moves[..i].iter().rev() {
+				let back = RenameMove {
+					description: done.description.clone(),
+					from: done.to.clone(),
+					to: done.from.clone(),
+				};
+				if move_path(&back).is_ok() {
+					restored.push(done.description.clone());
+				} else {
+					not_restored.push(format!(
+						"{} (now at '{}')",
+						done.description,
+						done.to.display()
+					));
+				}
+			}
+			let not_moved = self.moves[i..].iter().map(|x| &x.description).join(", ");
+
+			let mut message = format!(
+				"Failed to move the {} of instance '{}'. Not moved: {not_moved}",
+				step.description, self.old
+			);
+			if !restored.is_empty() {
+				message.push_str(&format!(". Moved back: {}", restored.join(", ")));
+			}
+			if !not_restored.is_empty() {
+				message.push_str(&format!(
+					". Could not be moved back: {}",
+					not_restored.join(", ")
+				));
+			}
+
+			return Err(e).context(message);
+		}
+
+		Ok(())
+	}
+
+	/// Update the lockfile for the new ID and let plugins move their data for the instance.
+	/// Should be called after the files are moved
+	pub fn finish(
+		&self,
+		lock: &mut Lockfile,
+		plugins: &PluginManager,
+		paths: &Paths,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<()> {
+		lock.rename_instance(&self.old, &self.new, &self.old_dir, &self.new_dir);
+
+		let arg = RenameInstanceArg {
+			old_id: self.old.to_string(),
+			new_id: self.new.to_string(),
+			dir: self.new_dir.to_string_lossy().into(),
+		};
+		let results = plugins
+			.call_hook(RenameInstance, &arg, paths, o)
+			.context("Failed to call rename instance hook")?;
+		// A plugin failing to move its data shouldn't undo the rename
+		for result in results {
+			if let Err(e) = result.result(o) {
+				o.display(
+					MessageContents::Warning(translate!(
+						o,
+						PluginRenameFailed,
+						"inst" = &self.new,
+						"error" = &format!("{e:?}")
+					)),
+					MessageLevel::Important,
+				);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Get the base directory of an instance
+fn get_instance_dir(paths: &Paths, instance_id: &str) -> PathBuf {
+	paths.instances.join(instance_id)
+}
+
+/// Move a file or directory, creating the parent directories of the destination
+fn move_path(step: &RenameMove) -> anyhow::Result<()> {
+	mcvm_core::io::files::create_leading_dirs(&step.to)?;
+	fs::rename(&step.from, &step.to).with_context(|| {
+		format!(
+			"Failed to move '{}' to '{}'",
+			step.from.display(),
+			step.to.display()
+		)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_failed_move_rollback() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		fs::create_dir_all(dir.join("a")).unwrap();
+		let step = |name: &str, from: &str, to: &str| RenameMove {
+			description: name.into(),
+			from: dir.join(from),
+			to: dir.join(to),
+		};
+		let rename = InstanceRename {
+			old: "old".into(),
+			new: "new".into(),
+			moves: vec![step("first", "a", "b"), step("second", "missing", "c")],
+			old_dir: dir.join("a"),
+			new_dir: dir.join("b"),
+		};
+
+		let err = rename.move_files().unwrap_err();
+		let message = err.to_string();
+		assert!(message.contains("Not moved: second"));
+		assert!(message.contains("Moved back: first"));
+		assert!(dir.join("a").exists());
+		assert!(!dir.join("b").exists());
+	}
+}
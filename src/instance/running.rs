@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use mcvm_core::io::{json_from_file, json_to_file};
+use mcvm_shared::id::InstanceID;
+use mcvm_shared::util::utc_timestamp;
+use mcvm_shared::Side;
+use serde::{Deserialize, Serialize};
+use sysinfo::{Pid, ProcessRefreshKind, System, MINIMUM_CPU_UPDATE_INTERVAL};
+
+use crate::io::paths::Paths;
+
+/// How long CPU usage is measured for when sampling running instances
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A record of an instance process that mcvm launched, which is stored while it is running
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunningInstance {
+	/// The ID of the instance
+	pub id: InstanceID,
+	/// The side of the instance
+	pub side: Side,
+	/// The PID of the game process
+	pub pid: u32,
+	/// When the instance was launched, as a UTC timestamp in seconds
+	pub launched_at: u64,
+}
+
+impl RunningInstance {
+	/// Create a record for an instance process that was just launched
+	pub fn new(id: InstanceID, side: Side, pid: u32) -> anyhow::Result<Self> {
+		Ok(Self {
+			id,
+			side,
+			pid,
+			launched_at: utc_timestamp()?,
+		})
+	}
+
+	/// Store this record so that the instance is listed as running
+	pub fn register(&self, paths: &Paths) -> anyhow::Result<()> {
+		let dir = get_dir(paths);
+		std::fs::create_dir_all(&dir).context("Failed to create running instances directory")?;
+		json_to_file(get_record_path(paths, self.pid), self)
+			.context("Failed to write running instance record")
+	}
+
+	/// Remove the record of an instance process once it has stopped
+	pub fn unregister(paths: &Paths, pid: u32) -> anyhow::Result<()> {
+		let path = get_record_path(paths, pid);
+		if path.exists() {
+			std::fs::remove_file(path).context("Failed to remove running instance record")?;
+		}
+		Ok(())
+	}
+
+	/// Get how many seconds the instance has been running for, given the current time
+	pub fn get_uptime(&self, now: u64) -> u64 {
+		now.saturating_sub(self.launched_at)
+	}
+}
+
+/// Get the directory that records of running instances are stored in
+fn get_dir(paths: &Paths) -> PathBuf {
+	paths.run.join("instances")
+}
+
+/// Get the path to the record of a running instance process
+fn get_record_path(paths: &Paths, pid: u32) -> PathBuf {
+	get_dir(paths).join(format!("{pid}.json"))
+}
+
+/// List the instances that mcvm launched which are still running, sorted by ID.
+/// Records of processes that no longer exist are removed
+pub fn list_running_instances(paths: &Paths) -> anyhow::Result<Vec<RunningInstance>> {
+	let dir = get_dir(paths);
+	if !dir.exists() {
+		return Ok(Vec::new());
+	}
+
+	let mut out = Vec::new();
+	for entry in std::fs::read_dir(dir).context("Failed to read running instances directory")? {
+		let path = entry?.path();
+		let record: Option<RunningInstance> = json_from_file(&path).ok();
+		match record {
+			Some(record) if is_process_alive(record.pid) => out.push(record),
+			_ => {
+				std::fs::remove_file(&path)
+					.with_context(|| format!("Failed to remove dead record {}", path.display()))?;
+			}
+		}
+	}
+	out.sort_by(|a, b| a.id.cmp(&b.id).then(a.pid.cmp(&b.pid)));
+
+	Ok(out)
+}
+
+/// Find the records of running instances whose processes no longer exist, without removing them
+pub fn find_dead_records(paths: &Paths) -> anyhow::Result<Vec<PathBuf>> {
+	let dir = get_dir(paths);
+	if !dir.exists() {
+		return Ok(Vec::new());
+	}
+
+	let mut out = Vec::new();
+	for entry in std::fs::read_dir(dir).context("Failed to read running instances directory")? {
+		let path = entry?.path();
+		let record: Option<RunningInstance> = json_from_file(&path).ok();
+		if !record.is_some_and(|x| is_process_alive(x.pid)) {
+			out.push(path);
+		}
+	}
+	out.sort();
+
+	Ok(out)
+}
+
+/// Check whether a process with a PID exists
+pub fn is_process_alive(pid: u32) -> bool {
+	let mut system = System::new();
+	system.refresh_process_specifics(Pid::from_u32(pid), ProcessRefreshKind::new())
+}
+
+/// Resource usage of a process
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProcessUsage {
+	/// The resident memory of the process in bytes
+	pub rss: Option<u64>,
+	/// The CPU usage of the process, where 100 is one full core
+	pub cpu_percent: Option<f64>,
+}
+
+/// Measure the resource usage of processes over an interval. Processes that
+/// have stopped by the end of the interval are left out
+pub async fn sample_usage(pids: &[u32], interval: Duration) -> HashMap<u32, ProcessUsage> {
+	let pids: Vec<_> = pids.iter().map(|x| Pid::from_u32(*x)).collect();
+	let refresh_kind = ProcessRefreshKind::new().with_cpu().with_memory();
+	let mut system = System::new();
+	// CPU usage is measured from the difference between two refreshes
+	for pid in &pids {
+		system.refresh_process_specifics(*pid, refresh_kind);
+	}
+	tokio::time::sleep(interval.max(MINIMUM_CPU_UPDATE_INTERVAL)).await;
+
+	let mut out = HashMap::new();
+	for pid in pids {
+		if !system.refresh_process_specifics(pid, refresh_kind) {
+			continue;
+		}
+		let Some(process) = system.process(pid) else {
+			continue;
+		};
+		out.insert(
+			pid.as_u32(),
+			ProcessUsage {
+				rss: Some(process.memory()),
+				cpu_percent: Some(process.cpu_usage() as f64),
+			},
+		);
+	}
+
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Get the PID of a process that has already exited
+	fn get_dead_pid() -> u32 {
+		let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+			.arg("--list")
+			.stdout(std::process::Stdio::null())
+			.spawn()
+			.unwrap();
+		child.wait().unwrap();
+		let pid = child.id();
+		assert!(!is_process_alive(pid));
+		pid
+	}
+
+	#[test]
+	fn test_sample_usage() {
+		let pid = std::process::id();
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let usage = runtime.block_on(sample_usage(&[pid], Duration::from_millis(250)));
+		let usage = usage
+			.get(&pid)
+			.expect("The current process should be sampled");
+		assert!(usage.rss.is_some_and(|x| x > 0));
+		assert!(usage.cpu_percent.is_some());
+
+		let dead_pid = get_dead_pid();
+		let usage = runtime.block_on(sample_usage(&[dead_pid], Duration::ZERO));
+		assert!(usage.is_empty());
+	}
+
+	#[test]
+	fn test_running_instance_gc() {
+		let mut paths = Paths::new_no_create().unwrap();
+		let temp_dir = tempfile::tempdir().unwrap();
+		paths.run = temp_dir.path().to_path_buf();
+
+		let current =
+			RunningInstance::new("alive".into(), Side::Server, std::process::id()).unwrap();
+		current.register(&paths).unwrap();
+		let dead = RunningInstance::new("dead".into(), Side::Client, get_dead_pid()).unwrap();
+		dead.register(&paths).unwrap();
+
+		assert_eq!(
+			find_dead_records(&paths).unwrap(),
+			vec![get_record_path(&paths, dead.pid)]
+		);
+		assert_eq!(
+			list_running_instances(&paths).unwrap(),
+			vec![current.clone()]
+		);
+		assert!(!get_record_path(&paths, dead.pid).exists());
+
+		RunningInstance::unregister(&paths, current.pid).unwrap();
+		assert!(list_running_instances(&paths).unwrap().is_empty());
+	}
+}
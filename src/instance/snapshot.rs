@@ -0,0 +1,236 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use mcvm_core::io::json_to_file_pretty;
+use mcvm_plugin::hooks::{
+	BackupInstance, BackupInstanceArg, SnapshotInstance, SnapshotInstanceArg,
+};
+use mcvm_shared::output::MCVMOutput;
+use mcvm_shared::util::utc_timestamp;
+use mcvm_shared::Side;
+use serde::{Deserialize, Serialize};
+
+use crate::config::plugin::PluginManager;
+use crate::io::paths::Paths;
+
+use super::{InstKind, Instance};
+
+/// Name of the metadata file stored alongside a built-in snapshot
+pub const SNAPSHOT_META_FILE: &str = "snapshot.json";
+
+/// Metadata for a built-in snapshot
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SnapshotMeta {
+	/// The timestamp when the snapshot was created
+	pub date: u64,
+	/// The Minecraft version the instance was on when the snapshot was taken
+	pub version: String,
+	/// The paths that were backed up, relative to the instance directory
+	pub paths: Vec<String>,
+}
+
+impl Instance {
+	/// Get the paths of existing world data in this instance, relative to the instance directory
+	pub fn get_world_paths(&mut self, paths: &Paths) -> Vec<String> {
+		let world_name = match &self.kind {
+			InstKind::Client { .. } => String::new(),
+			InstKind::Server { .. } => self.get_world_name(paths),
+		};
+
+		self.ensure_dirs_set(paths);
+		find_world_paths(&self.dirs.get().inst_dir, self.get_side(), &world_name)
+	}
+
+	/// Create a snapshot of the world data in this instance before its Minecraft version is changed.
+	/// Plugins are given the chance to handle the snapshot first, and the built-in snapshot store is used
+	/// if none of them do. Returns the ID of the snapshot, or None if there was no world data to back up.
+	pub fn snapshot_worlds(
+		&mut self,
+		old_version: &str,
+		new_version: &str,
+		plugins: &PluginManager,
+		paths: &Paths,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<Option<String>> {
+		self.ensure_dirs(paths)?;
+		let world_paths = self.get_world_paths(paths);
+		if world_paths.is_empty() {
+			return Ok(None);
+		}
+
+		let arg = SnapshotInstanceArg {
+			id: self.id.to_string(),
+			side: Some(self.get_side()),
+			dir: self.dirs.get().inst_dir.to_string_lossy().into(),
+			game_dir: self.dirs.get().game_dir.to_string_lossy().into(),
+			world_paths: world_paths.clone(),
+			old_version: old_version.to_string(),
+			new_version: new_version.to_string(),
+			custom_config: self.config.plugin_config.clone(),
+		};
+		let results = plugins
+			.call_hook(SnapshotInstance, &arg, paths, o)
+			.context("Failed to call snapshot instance hook")?;
+		let mut snapshot_id = None;
+		for result in results {
+			let result = result.result(o)?;
+			if snapshot_id.is_none() {
+				snapshot_id = result;
+			}
+		}
+		if snapshot_id.is_some() {
+			return Ok(snapshot_id);
+		}
+
+		let id = create_builtin_snapshot(
+			&self.id,
+			&self.dirs.get().inst_dir,
+			world_paths,
+			old_version,
+			paths,
+		)
+		.context("Failed to create built-in snapshot")?;
+
+		Ok(Some(id))
+	}
+
+	/// Get the argument for the backup_instance hook, which plugins use to back up this instance
+	pub fn get_backup_arg(
+		&mut self,
+		group: Option<String>,
+		paths: &Paths,
+	) -> anyhow::Result<BackupInstanceArg> {
+		self.ensure_dirs(paths)?;
+		Ok(BackupInstanceArg {
+			id: self.id.to_string(),
+			side: Some(self.get_side()),
+			dir: self.dirs.get().inst_dir.to_string_lossy().into(),
+			game_dir: self.dirs.get().game_dir.to_string_lossy().into(),
+			group,
+			custom_config: self.config.plugin_config.clone(),
+		})
+	}
+}
+
+/// Ask plugins to back up an instance, returning the ID of the backup.
+/// Fails if there are no plugins that can create backups
+pub fn backup_instance(
+	arg: &BackupInstanceArg,
+	plugins: &PluginManager,
+	paths: &Paths,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<String> {
+	let results = plugins
+		.call_hook(BackupInstance, arg, paths, o)
+		.context("Failed to call backup instance hook")?;
+	let mut backup_id = None;
+	for result in results {
+		let result = result.result(o)?;
+		if backup_id.is_none() {
+			backup_id = result;
+		}
+	}
+
+	backup_id.context("No plugins are installed that can back up instances")
+}
+
+/// Get the directory where built-in snapshots for an instance are stored
+pub fn get_snapshots_dir(paths: &Paths, instance_id: &str) -> PathBuf {
+	paths.snapshots.join(instance_id)
+}
+
+/// Copy the given paths from an instance into a new built-in snapshot, returning its ID
+fn create_builtin_snapshot(
+	instance_id: &str,
+	inst_dir: &Path,
+	world_paths: Vec<String>,
+	version: &str,
+	paths: &Paths,
+) -> anyhow::Result<String> {
+	let date = utc_timestamp()?;
+	let id = format!("{}-{date}", sanitize_version(version));
+	let snapshot_dir = get_snapshots_dir(paths, instance_id).join(&id);
+	fs::create_dir_all(&snapshot_dir).context("Failed to create snapshot directory")?;
+
+	for path in &world_paths {
+		copy_recursive(&inst_dir.join(path), &snapshot_dir.join(path))
+			.with_context(|| format!("Failed to copy '{path}' into snapshot"))?;
+	}
+
+	let meta = SnapshotMeta {
+		date,
+		version: version.to_string(),
+		paths: world_paths,
+	};
+	json_to_file_pretty(snapshot_dir.join(SNAPSHOT_META_FILE), &meta)
+		.context("Failed to write snapshot metadata")?;
+
+	Ok(id)
+}
+
+/// Replace characters in a version that are not safe to use in a directory name
+fn sanitize_version(version: &str) -> String {
+	version
+		.chars()
+		.map(|c| {
+			if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+				c
+			} else {
+				'_'
+			}
+		})
+		.collect()
+}
+
+/// Check if a path is a directory with at least one entry
+/// Find the existing world data in an instance directory, relative to that directory.
+/// The name of the active world is only used for servers
+pub fn find_world_paths(inst_dir: &Path, side: Side, world_name: &str) -> Vec<String> {
+	let candidates = match side {
+		Side::Client => vec![Path::new(".minecraft").join("saves")],
+		Side::Server => vec![
+			PathBuf::from(world_name),
+			PathBuf::from(format!("{world_name}_nether")),
+			PathBuf::from(format!("{world_name}_the_end")),
+		],
+	};
+
+	candidates
+		.into_iter()
+		.filter(|path| is_non_empty_dir(&inst_dir.join(path)))
+		.map(|path| path.to_string_lossy().to_string())
+		.collect()
+}
+
+pub(super) fn is_non_empty_dir(path: &Path) -> bool {
+	fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_some())
+}
+
+/// Copy a file or directory recursively
+pub(super) fn copy_recursive(src: &Path, dest: &Path) -> anyhow::Result<()> {
+	if src.is_dir() {
+		fs::create_dir_all(dest)?;
+		for entry in fs::read_dir(src)? {
+			let entry = entry?;
+			copy_recursive(&entry.path(), &dest.join(entry.file_name()))?;
+		}
+	} else {
+		fs::copy(src, dest)?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_version_sanitization() {
+		assert_eq!(sanitize_version("1.20.1"), "1.20.1");
+		assert_eq!(sanitize_version("24w14a"), "24w14a");
+		assert_eq!(sanitize_version("1.14 Pre-Release 2"), "1.14_Pre-Release_2");
+		assert_eq!(sanitize_version("a/b"), "a_b");
+	}
+}
@@ -50,6 +50,12 @@ impl Instance {
 			side: Some(self.get_side()),
 			game_dir: self.dirs.get().game_dir.to_string_lossy().to_string(),
 			result_path: result_path.to_string_lossy().to_string(),
+			description: self.config.description.clone(),
+			icon: self
+				.config
+				.icon
+				.as_ref()
+				.map(|x| x.to_string_lossy().to_string()),
 		};
 		let result = plugins
 			.call_hook_on_plugin(ExportInstance, &format.plugin, &arg, paths, o)
@@ -1,10 +1,14 @@
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Context;
 use mcvm_core::auth_crate::mc::ClientId;
 use mcvm_core::config::BrandingProperties;
+use mcvm_core::net::game_files::cache::DEFAULT_METADATA_FRESHNESS;
+use mcvm_core::net::game_files::download_size::{estimate_version_size, DownloadSize};
+use mcvm_core::net::stats::{self, TransferPhase};
 use mcvm_core::user::UserManager;
 use mcvm_core::util::versions::MinecraftVersion;
 use mcvm_core::version::InstalledVersion;
@@ -19,13 +23,14 @@ use reqwest::Client;
 
 use crate::config::plugin::PluginManager;
 use crate::io::paths::Paths;
-use mcvm_mods::fabric_quilt::{self, FabricQuiltMeta};
+use mcvm_mods::fabric_quilt::{self, FabricQuiltMeta, LoaderVersion};
+use mcvm_mods::sponge;
 
 /// Requirements for operations that may be shared by multiple instances in a profile
 #[derive(Debug, Hash, PartialEq, Eq)]
 pub enum UpdateRequirement {
 	/// Fabric and Quilt
-	FabricQuilt(fabric_quilt::Mode, Side),
+	FabricQuilt(fabric_quilt::Mode, LoaderVersion, Side),
 	/// Client logging configuration
 	ClientLoggingConfig,
 }
@@ -39,6 +44,15 @@ pub struct UpdateSettings {
 	pub allow_offline: bool,
 	/// Whether to do offline authentication
 	pub offline_auth: bool,
+	/// How long downloaded metadata is used before checking for changes
+	pub metadata_freshness: Duration,
+	/// Whether to show the changes made to options files instead of only logging them
+	pub show_diffs: bool,
+	/// Whether to fail on problems with the configuration that would otherwise be warnings
+	pub strict: bool,
+	/// Whether to make the whitelist and ops of servers match their config exactly,
+	/// removing players that were added in-game
+	pub sync_exact: bool,
 }
 
 /// Manager for when we are updating profile files.
@@ -60,6 +74,12 @@ pub struct UpdateManager {
 	pub version_info: Later<VersionInfo>,
 	/// The Fabric/Quilt metadata to be fulfilled later
 	pub fq_meta: Later<FabricQuiltMeta>,
+	/// The newest Fabric/Quilt loader version, if it is newer than the one that was selected
+	pub fq_newer_version: Option<String>,
+	/// The Paper or Folia build to use, if it is already known
+	pub paper_build: Option<u16>,
+	/// The Sponge version to use, if it is already known
+	pub sponge_version: Option<sponge::Version>,
 }
 
 impl UpdateManager {
@@ -69,6 +89,10 @@ impl UpdateManager {
 			force,
 			allow_offline,
 			offline_auth: false,
+			metadata_freshness: DEFAULT_METADATA_FRESHNESS,
+			show_diffs: false,
+			strict: false,
+			sync_exact: false,
 		};
 
 		Self {
@@ -79,6 +103,9 @@ impl UpdateManager {
 			files: HashSet::new(),
 			version_info: Later::Empty,
 			fq_meta: Later::new(),
+			fq_newer_version: None,
+			paper_build: None,
+			sponge_version: None,
 			mc_version: Later::Empty,
 		}
 	}
@@ -88,6 +115,11 @@ impl UpdateManager {
 		self.settings.offline_auth = true;
 	}
 
+	/// Set how long downloaded metadata is used before checking for changes
+	pub fn set_metadata_freshness(&mut self, freshness: Duration) {
+		self.settings.metadata_freshness = freshness;
+	}
+
 	/// Set the MS client ID
 	pub fn set_client_id(&mut self, id: ClientId) {
 		self.ms_client_id = Some(id);
@@ -134,6 +166,7 @@ impl UpdateManager {
 		// We have to clear these now since they are out of date
 		self.version_info.clear();
 		self.fq_meta.clear();
+		self.fq_newer_version = None;
 	}
 
 	/// Run all of the operations that are part of the requirements.
@@ -161,7 +194,8 @@ impl UpdateManager {
 			.context("Failed to get version")?;
 		let version_info = version.get_version_info();
 
-		self.update_fabric_quilt(&version_info, paths, client, o)
+		let fabric_quilt = self.update_fabric_quilt(&version_info, paths, client, o);
+		stats::phase(TransferPhase::Loaders, fabric_quilt)
 			.await
 			.context("Failed to update Fabric/Quilt")?;
 
@@ -187,6 +221,7 @@ impl UpdateManager {
 		let mut core_config = mcvm_core::ConfigBuilder::new()
 			.allow_offline(self.settings.allow_offline)
 			.force_reinstall(self.settings.force)
+			.metadata_freshness(self.settings.metadata_freshness)
 			.branding(BrandingProperties::new(
 				"mcvm".into(),
 				crate::VERSION.into(),
@@ -244,7 +279,7 @@ impl UpdateManager {
 	pub async fn get_core_version(
 		&mut self,
 		o: &mut impl MCVMOutput,
-	) -> anyhow::Result<InstalledVersion> {
+	) -> anyhow::Result<InstalledVersion<'_, '_>> {
 		let version = self
 			.core
 			.get_mut()
@@ -255,6 +290,30 @@ impl UpdateManager {
 		Ok(version)
 	}
 
+	/// Estimate how much creating an instance of the version on the given side would download.
+	/// Requirements must be fulfilled first. Java and package files aren't counted
+	pub async fn estimate_download_size(
+		&mut self,
+		side: Side,
+		paths: &Paths,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<DownloadSize> {
+		let client_meta = self
+			.get_core_version(o)
+			.await
+			.context("Failed to get core version")?
+			.get_client_meta()
+			.clone();
+
+		estimate_version_size(
+			&client_meta,
+			&self.version_info.get().version,
+			side,
+			&paths.core,
+			self.core.get().get_update_manager(),
+		)
+	}
+
 	/// Update Fabric or Quilt if it is required
 	async fn update_fabric_quilt(
 		&mut self,
@@ -280,9 +339,9 @@ impl UpdateManager {
 		// Update Fabric / Quilt
 		if required {
 			for req in self.requirements.iter() {
-				if let UpdateRequirement::FabricQuilt(mode, side) = req {
+				if let UpdateRequirement::FabricQuilt(mode, loader_version, side) = req {
 					if self.fq_meta.is_empty() {
-						let meta = fabric_quilt::get_meta(
+						let list = fabric_quilt::get_meta_list(
 							&version_info.version,
 							mode,
 							&paths.core,
@@ -291,6 +350,12 @@ impl UpdateManager {
 						)
 						.await
 						.context("Failed to download Fabric/Quilt metadata")?;
+						let meta = fabric_quilt::select_meta(&list, loader_version, *mode)?.clone();
+						self.fq_newer_version = list
+							.first()
+							.map(|x| x.get_loader_version())
+							.filter(|x| *x != meta.get_loader_version())
+							.map(String::from);
 						fabric_quilt::download_files(
 							&meta,
 							&paths.core,
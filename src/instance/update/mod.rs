@@ -2,6 +2,10 @@
 pub mod manager;
 /// Updating packages on a profile
 pub mod packages;
+/// Tracking of the operations that are running during an update
+pub mod pending;
+/// Planning updates without changing anything
+pub mod plan;
 
 use crate::config::plugin::PluginManager;
 use crate::config::preferences::ConfigPreferences;
@@ -11,14 +15,22 @@ use mcvm_core::user::UserManager;
 use mcvm_shared::translate;
 #[cfg(not(feature = "disable_profile_update_packages"))]
 use packages::print_package_support_messages;
-use packages::update_instance_packages;
+use packages::{update_instance_packages, PackageFailures};
 #[cfg(not(feature = "disable_profile_update_packages"))]
 use std::collections::HashSet;
+use std::future::Future;
 
 use anyhow::Context;
-use mcvm_mods::paper;
+use futures_util::stream::{self, StreamExt};
+use itertools::Itertools;
+use mcvm_core::net::stats::{self, TransferPhase};
+use mcvm_mods::{paper, sponge};
 use mcvm_shared::modifications::ServerType;
-use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::output::{
+	replay_output, Buffered, MCVMOutput, MessageContents, MessageLevel, SharedOutput,
+};
+use mcvm_shared::pkg::PackageStability;
+use mcvm_shared::util::utc_timestamp;
 use reqwest::Client;
 
 use crate::io::lock::Lockfile;
@@ -26,9 +38,14 @@ use crate::io::paths::Paths;
 use crate::pkg::reg::PkgRegistry;
 
 use manager::UpdateManager;
+use pending::PendingOperations;
 
+use super::create::journal::check_interrupt;
 use super::Instance;
 
+/// The default number of Minecraft versions whose instances are created at the same time
+pub const DEFAULT_UPDATE_JOBS: usize = 4;
+
 /// Shared objects for instance updating functions
 pub struct InstanceUpdateContext<'a, O: MCVMOutput> {
 	/// The package registry
@@ -45,22 +62,76 @@ pub struct InstanceUpdateContext<'a, O: MCVMOutput> {
 	pub lock: &'a mut Lockfile,
 	/// The reqwest client
 	pub client: &'a Client,
+	/// Whether to fail on problems with the configuration that would otherwise be warnings
+	pub strict: bool,
+	/// Whether to show the changes made to options files instead of only logging them
+	pub show_diffs: bool,
+	/// Whether to stop at the first package that fails to install instead of
+	/// rolling it back and continuing with the other packages
+	pub fail_fast: bool,
 	/// The output object
 	pub output: &'a mut O,
+	/// The operations that are currently running
+	pub pending: PendingOperations,
+	/// The stability to use for every package instead of the configured ones, for this update only
+	pub stability: Option<PackageStability>,
+	/// Whether to make the whitelist and ops of servers match their config exactly
+	pub sync_exact: bool,
+	/// Whether to skip backing up worlds when the Minecraft version changes, for this update only
+	pub no_snapshot: bool,
+}
+
+/// An instance update that has been checked against the lockfile, but hasn't created
+/// any of the instance's files yet
+pub struct PreparedUpdate {
+	manager: UpdateManager,
+	mc_version: String,
 }
 
 impl Instance {
-	/// Update this instance
+	/// Update this instance. The changes to the lockfile are not written, so that a failed or
+	/// cancelled update leaves it untouched. Call `finish` on the lockfile once the update succeeds
 	pub async fn update<'a, O: MCVMOutput>(
 		&mut self,
 		update_packages: bool,
 		force: bool,
 		ctx: &mut InstanceUpdateContext<'a, O>,
 	) -> anyhow::Result<()> {
-		#[cfg(feature = "disable_profile_update_packages")]
-		let _update_packages = update_packages;
+		let mut prepared = self.prepare_update(force, ctx).await?;
+
+		let operation = ctx
+			.pending
+			.start(format!("Creating instance '{}'", self.id));
+		self.create(
+			&mut prepared.manager,
+			ctx.plugins,
+			ctx.paths,
+			ctx.users,
+			ctx.client,
+			ctx.output,
+		)
+		.await
+		.context("Failed to create instance")?;
+		drop(operation);
 
+		self.finish_update(prepared, update_packages, force, ctx)
+			.await
+	}
+
+	/// Resolve the version of this instance and apply any changes to it that are recorded in the lockfile
+	async fn prepare_update<'a, O: MCVMOutput>(
+		&mut self,
+		force: bool,
+		ctx: &mut InstanceUpdateContext<'a, O>,
+	) -> anyhow::Result<PreparedUpdate> {
+		let _operation = ctx
+			.pending
+			.start(format!("Preparing update for instance '{}'", self.id));
 		let mut manager = UpdateManager::new(force, false);
+		manager.set_metadata_freshness(ctx.prefs.metadata_freshness);
+		manager.settings.show_diffs = ctx.show_diffs;
+		manager.settings.strict = ctx.strict;
+		manager.settings.sync_exact = ctx.sync_exact;
 
 		ctx.output.display(
 			MessageContents::Header(translate!(
@@ -79,33 +150,81 @@ impl Instance {
 			.context("Failed to fulfill update manager")?;
 		let mc_version = manager.version_info.get().version.clone();
 
-		let paper_properties = get_paper_properties(self, &mc_version, ctx)
+		let paper_build = get_paper_build(self, &mc_version, force, ctx);
+		let paper_build = stats::phase(TransferPhase::Loaders, paper_build)
 			.await
-			.context("Failed to get Paper build number and filename")?;
+			.context("Failed to get Paper build number")?;
+		manager.paper_build = paper_build;
 
-		check_instance_version_change(self, &mc_version, paper_properties.clone(), ctx)
+		let sponge_version = get_sponge_version(self, &mc_version, ctx);
+		let sponge_version = stats::phase(TransferPhase::Loaders, sponge_version)
 			.await
-			.context("Failed to check for a profile version update")?;
+			.context("Failed to get Sponge version")?;
+		// Versions from the API without a version string can't be compared
+		let sponge_version_str = sponge_version
+			.as_ref()
+			.map(|x| x.version.clone())
+			.filter(|x| !x.is_empty());
+		manager.sponge_version = sponge_version;
 
-		check_instance_paper_update(self, paper_properties, ctx)
+		let loader_version = manager
+			.fq_meta
+			.is_full()
+			.then(|| manager.fq_meta.get().get_loader_version().to_string());
+
+		let changes = ctx.lock.get_instance_changes(
+			&self.id,
+			&mc_version,
+			paper_build,
+			loader_version.as_deref(),
+			sponge_version_str.as_deref(),
+		);
+		ctx.lock.ensure_unchanged(&self.id, &changes)?;
+
+		check_instance_version_change(self, &mc_version, ctx)
 			.await
-			.context("Failed to check for Paper updates")?;
+			.context("Failed to check for a profile version update")?;
 
 		ctx.lock
-			.finish(ctx.paths)
-			.context("Failed to finish using lockfile")?;
+			.update_instance_loader_version(&self.id, loader_version.as_deref());
+		if let (Some(loader_version), Some(newer)) = (&loader_version, &manager.fq_newer_version) {
+			ctx.output.display(
+				MessageContents::Notice(format!(
+					"A newer loader version {newer} is available, but {loader_version} is being used because of the loader_version setting '{}'",
+					self.config.loader_version
+				)),
+				MessageLevel::Important,
+			);
+		}
 
-		self.create(
-			&mut manager,
-			ctx.plugins,
-			ctx.paths,
-			ctx.users,
-			ctx.client,
-			ctx.output,
-		)
-		.await
-		.context("Failed to create instance")?;
+		check_instance_paper_update(self, paper_build, ctx)
+			.context("Failed to check for Paper updates")?;
+		check_instance_sponge_update(self, &mc_version, sponge_version_str.as_deref(), ctx)
+			.context("Failed to check for Sponge updates")?;
 
+		Ok(PreparedUpdate {
+			manager,
+			mc_version,
+		})
+	}
+
+	/// Install packages on this instance after it has been created and record the update
+	async fn finish_update<'a, O: MCVMOutput>(
+		&mut self,
+		prepared: PreparedUpdate,
+		update_packages: bool,
+		force: bool,
+		ctx: &mut InstanceUpdateContext<'a, O>,
+	) -> anyhow::Result<()> {
+		#[cfg(feature = "disable_profile_update_packages")]
+		let _ = (prepared, update_packages, force);
+		// Packages aren't installed after an interrupted creation, so that it can be resumed as a whole
+		check_interrupt(&self.id)?;
+
+		let _operation = ctx
+			.pending
+			.start(format!("Installing packages on instance '{}'", self.id));
+		let mut failures = PackageFailures::default();
 		if update_packages {
 			#[cfg(not(feature = "disable_profile_update_packages"))]
 			{
@@ -117,54 +236,248 @@ impl Instance {
 				);
 
 				let constants = EvalConstants {
-					version: mc_version.to_string(),
+					version: prepared.mc_version.clone(),
 					modifications: self.config.modifications.clone(),
-					version_list: manager.version_info.get().versions.clone(),
+					version_list: prepared.manager.version_info.get().versions.clone(),
 					language: ctx.prefs.language,
 					profile_stability: self.config.package_stability,
 				};
 
-				let packages =
-					update_instance_packages(&mut [self], &constants, ctx, force).await?;
+				let mut instances = [&mut *self];
+				let update = update_instance_packages(&mut instances, &constants, ctx, force);
+				let (packages, package_failures) =
+					stats::phase(TransferPhase::Packages, update).await?;
 
-				ctx.output.display(
-					MessageContents::Success(translate!(ctx.output, FinishUpdatingPackages)),
-					MessageLevel::Important,
-				);
+				if package_failures.is_empty() {
+					ctx.output.display(
+						MessageContents::Success(translate!(ctx.output, FinishUpdatingPackages)),
+						MessageLevel::Important,
+					);
+				}
 
 				all_packages.extend(packages);
 
-				ctx.lock
-					.finish(ctx.paths)
-					.context("Failed to finish using lockfile")?;
-
 				let all_packages = Vec::from_iter(all_packages);
 				print_package_support_messages(&all_packages, ctx)
 					.await
 					.context("Failed to print support messages")?;
+				failures = package_failures;
+			}
+		}
+
+		ctx.lock
+			.update_instance_version_files(&self.id, self.version_files.clone());
+
+		// Remember this update so that it can be rolled back to later
+		ctx.lock
+			.record_instance_update(&self.id)
+			.context("Failed to record update in lockfile")?;
+
+		if failures.is_empty() {
+			Ok(())
+		} else {
+			Err(failures.into())
+		}
+	}
+}
+
+/// Update multiple instances. The files for instances with different Minecraft versions are
+/// created concurrently, with at most `jobs` versions at a time. Everything that uses the lockfile
+/// or the package registry still happens for one instance at a time. Like [Instance::update],
+/// this doesn't write the lockfile
+pub async fn update_instances<'a, O: MCVMOutput>(
+	instances: &mut [&mut Instance],
+	update_packages: bool,
+	force: bool,
+	jobs: usize,
+	ctx: &mut InstanceUpdateContext<'a, O>,
+) -> anyhow::Result<()> {
+	let mut prepared = Vec::with_capacity(instances.len());
+	for instance in instances.iter_mut() {
+		let update = instance
+			.prepare_update(force, ctx)
+			.await
+			.with_context(|| format!("Failed to update instance '{}'", instance.id))?;
+		prepared.push(update);
+	}
+
+	// Forced updates redownload the files shared between versions, so they can't overlap
+	let jobs = if force { 1 } else { jobs.max(1) };
+	create_instances(instances, &mut prepared, jobs, ctx).await?;
+
+	// Instances with failed packages are still updated, so the other instances are too
+	let mut failures = PackageFailures::default();
+	for (instance, prepared) in instances.iter_mut().zip(prepared) {
+		let result = instance
+			.finish_update(prepared, update_packages, force, ctx)
+			.await;
+		match result.map_err(|e| e.downcast::<PackageFailures>()) {
+			Ok(()) => {}
+			Err(Ok(instance_failures)) => failures.extend(instance_failures),
+			Err(Err(e)) => {
+				return Err(e.context(format!("Failed to update instance '{}'", instance.id)))
 			}
 		}
+	}
 
+	if failures.is_empty() {
 		Ok(())
+	} else {
+		Err(failures.into())
 	}
 }
 
+/// Create prepared instances. Instances on the same Minecraft version share game files, so they are
+/// created one after another, while different versions are created concurrently
+async fn create_instances<'a, O: MCVMOutput>(
+	instances: &mut [&mut Instance],
+	prepared: &mut [PreparedUpdate],
+	jobs: usize,
+	ctx: &mut InstanceUpdateContext<'a, O>,
+) -> anyhow::Result<()> {
+	let mut groups: Vec<Vec<_>> = Vec::new();
+	for (instance, prepared) in instances.iter_mut().zip(prepared.iter_mut()) {
+		let group = groups
+			.iter_mut()
+			.find(|x: &&mut Vec<(_, &mut PreparedUpdate)>| {
+				x[0].1.mc_version == prepared.mc_version
+			});
+		if let Some(group) = group {
+			group.push((instance, prepared));
+		} else {
+			groups.push(vec![(instance, prepared)]);
+		}
+	}
+
+	// With nothing to run concurrently, the output can be shown as it happens
+	if jobs == 1 || groups.len() == 1 {
+		for (instance, prepared) in groups.into_iter().flatten() {
+			let _operation = ctx
+				.pending
+				.start(format!("Creating instance '{}'", instance.id));
+			instance
+				.create(
+					&mut prepared.manager,
+					ctx.plugins,
+					ctx.paths,
+					ctx.users,
+					ctx.client,
+					ctx.output,
+				)
+				.await
+				.with_context(|| format!("Failed to create instance '{}'", instance.id))?;
+		}
+		return Ok(());
+	}
+
+	let (plugins, paths, users, client) = (ctx.plugins, ctx.paths, ctx.users, ctx.client);
+	let pending = &ctx.pending;
+	let shared = SharedOutput::new(&mut *ctx.output);
+	let results = run_concurrently(groups, jobs, |group| {
+		let shared = &shared;
+		async move {
+			let mut o = Buffered::new(shared);
+			let mut result = Ok(());
+			for (instance, prepared) in group {
+				let _operation = pending.start(format!("Creating instance '{}'", instance.id));
+				result = instance
+					.create(&mut prepared.manager, plugins, paths, users, client, &mut o)
+					.await
+					.map(|_| ())
+					.with_context(|| format!("Failed to create instance '{}'", instance.id));
+				if result.is_err() {
+					break;
+				}
+			}
+			(o.into_events(), result)
+		}
+	})
+	.await;
+
+	// Show the output of each group in the same order that they would have run sequentially
+	let output = shared.into_inner()?;
+	let mut out = Ok(());
+	for (events, result) in results {
+		replay_output(events, output);
+		if out.is_ok() {
+			out = result;
+		}
+	}
+
+	out
+}
+
+/// Run a task for each item, with at most `jobs` tasks running at the same time.
+/// The results are in the same order as the items
+async fn run_concurrently<T, F, Fut>(items: Vec<T>, jobs: usize, mut f: F) -> Vec<Fut::Output>
+where
+	F: FnMut(T) -> Fut,
+	Fut: Future,
+{
+	let tasks = items.into_iter().enumerate().map(|(i, item)| {
+		let task = f(item);
+		async move { (i, task.await) }
+	});
+	let results: Vec<_> = stream::iter(tasks).buffer_unordered(jobs).collect().await;
+
+	results
+		.into_iter()
+		.sorted_by_key(|x| x.0)
+		.map(|x| x.1)
+		.collect()
+}
+
 /// Update an instance when the Minecraft version has changed
 async fn check_instance_version_change<'a, O: MCVMOutput>(
 	instance: &mut Instance,
 	mc_version: &str,
-	paper_properties: Option<(u16, String)>,
 	ctx: &mut InstanceUpdateContext<'a, O>,
 ) -> anyhow::Result<()> {
+	let old_version = ctx
+		.lock
+		.get_instance_version(&instance.id)
+		.map(String::from);
 	if ctx.lock.update_instance_version(&instance.id, mc_version) {
+		if let Some(old_version) = &old_version {
+			ctx.output.display(
+				MessageContents::Simple(translate!(
+					ctx.output,
+					InstanceVersionChanged,
+					"inst" = &instance.id,
+					"old" = old_version,
+					"new" = mc_version
+				)),
+				MessageLevel::Important,
+			);
+		}
 		ctx.output.start_process();
 		ctx.output.display(
 			MessageContents::StartProcess(translate!(ctx.output, StartUpdatingProfileVersion)),
 			MessageLevel::Important,
 		);
 
+		// Back up worlds before anything gets torn down
+		if let Some(old_version) =
+			old_version.filter(|_| ctx.prefs.snapshot_on_version_change && !ctx.no_snapshot)
+		{
+			let snapshot_id = instance
+				.snapshot_worlds(&old_version, mc_version, ctx.plugins, ctx.paths, ctx.output)
+				.context("Failed to back up world data before changing version")?;
+			if let Some(snapshot_id) = snapshot_id {
+				ctx.output.display(
+					MessageContents::Success(translate!(
+						ctx.output,
+						SnapshotCreated,
+						"version" = &old_version,
+						"id" = &snapshot_id
+					)),
+					MessageLevel::Important,
+				);
+			}
+		}
+
 		instance
-			.teardown(ctx.paths, paper_properties.clone())
+			.teardown(ctx.lock, ctx.paths)
 			.context("Failed to remove old files when updating Minecraft version")?;
 
 		ctx.output.display(
@@ -176,45 +489,138 @@ async fn check_instance_version_change<'a, O: MCVMOutput>(
 	Ok(())
 }
 
-/// Get the updated Paper file name and build number for an instance that uses it
-async fn get_paper_properties<'a, O: MCVMOutput>(
+/// Get the Paper or Folia build for an instance that uses one. The newest build that was
+/// found is remembered in the lockfile, and the API is only checked again once it is older than
+/// the metadata freshness or when forced
+async fn get_paper_build<'a, O: MCVMOutput>(
 	instance: &Instance,
 	mc_version: &str,
+	force: bool,
 	ctx: &mut InstanceUpdateContext<'a, O>,
-) -> anyhow::Result<Option<(u16, String)>> {
-	let out = if let ServerType::Paper = instance.config.modifications.server_type {
-		let build_num = paper::get_newest_build(paper::Mode::Paper, mc_version, ctx.client)
-			.await
-			.context("Failed to get the newest Paper build number")?;
-		let paper_file_name =
-			paper::get_jar_file_name(paper::Mode::Paper, mc_version, build_num, ctx.client)
-				.await
-				.context("Failed to get the name of the Paper Jar file")?;
-		Some((build_num, paper_file_name))
-	} else {
-		None
+) -> anyhow::Result<Option<u16>> {
+	let Some(mode) = instance.get_paper_mode() else {
+		return Ok(None);
 	};
+	if let Some(build_num) = instance.get_pinned_paper_build() {
+		return Ok(Some(build_num));
+	}
 
-	Ok(out)
+	if let Some(known) = ctx.lock.get_server_build(mode.to_str(), mc_version) {
+		let age = utc_timestamp()?.saturating_sub(known.checked);
+		if !force && age < ctx.prefs.metadata_freshness.as_secs() {
+			return Ok(Some(known.build));
+		}
+	}
+
+	let build_num = paper::get_newest_build(mode, mc_version, ctx.client)
+		.await
+		.with_context(|| format!("Failed to get the newest {mode} build number"))?;
+	ctx.lock
+		.update_server_build(mode.to_str(), mc_version, build_num)?;
+
+	Ok(Some(build_num))
+}
+
+/// Get the newest Sponge version for an instance that uses Sponge
+pub(super) async fn get_sponge_version<'a, O: MCVMOutput>(
+	instance: &Instance,
+	mc_version: &str,
+	ctx: &mut InstanceUpdateContext<'a, O>,
+) -> anyhow::Result<Option<sponge::Version>> {
+	if instance.config.modifications.server_type != ServerType::Sponge {
+		return Ok(None);
+	}
+
+	let version = sponge::get_newest_version(sponge::Mode::Vanilla, mc_version, ctx.client)
+		.await
+		.context("Failed to get newest Sponge version")?;
+
+	Ok(Some(version))
+}
+
+/// Remove the old Sponge JAR if the Sponge version of an instance has changed, so that the new one is downloaded.
+/// Sponge JARs aren't stored separately for each version, so the stored JAR is removed as well
+fn check_instance_sponge_update<'a, O: MCVMOutput>(
+	instance: &mut Instance,
+	mc_version: &str,
+	sponge_version: Option<&str>,
+	ctx: &mut InstanceUpdateContext<'a, O>,
+) -> anyhow::Result<()> {
+	if ctx
+		.lock
+		.update_instance_sponge_version(&instance.id, sponge_version)
+	{
+		let jar_path =
+			sponge::get_local_jar_path(sponge::Mode::Vanilla, mc_version, &ctx.paths.core);
+		if jar_path.exists() {
+			std::fs::remove_file(jar_path).context("Failed to remove the old Sponge JAR")?;
+		}
+		instance
+			.remove_server_jar(ctx.paths)
+			.context("Failed to remove the old server JAR")?;
+	}
+
+	Ok(())
 }
 
-// TODO: Make this work with Folia
-/// Remove the old Paper files for an instance if they have updated
-async fn check_instance_paper_update<'a, O: MCVMOutput>(
+/// Unlink the old Paper or Folia JAR from an instance if its build has changed
+fn check_instance_paper_update<'a, O: MCVMOutput>(
 	instance: &mut Instance,
-	paper_properties: Option<(u16, String)>,
+	paper_build: Option<u16>,
 	ctx: &mut InstanceUpdateContext<'a, O>,
 ) -> anyhow::Result<()> {
-	if let Some((build_num, file_name)) = paper_properties {
+	if let Some(build_num) = paper_build {
 		if ctx
 			.lock
 			.update_instance_paper_build(&instance.id, build_num)
 		{
 			instance
-				.remove_paper(ctx.paths, file_name.clone())
-				.context("Failed to remove Paper")?;
+				.remove_server_jar(ctx.paths)
+				.context("Failed to remove the old server JAR")?;
 		}
 	}
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use super::*;
+
+	#[test]
+	fn test_run_concurrently_order() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		// Later items finish first, but the results keep the order of the items
+		let results = runtime.block_on(run_concurrently(vec![3, 2, 1], 3, |x| async move {
+			tokio::time::sleep(Duration::from_millis(x * 20)).await;
+			x
+		}));
+		assert_eq!(results, vec![3, 2, 1]);
+	}
+
+	#[test]
+	fn test_run_concurrently_speedup() {
+		const VERSIONS: usize = 4;
+		const DOWNLOAD_TIME: Duration = Duration::from_millis(200);
+
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		// Each version spends most of its time waiting on downloads
+		let measure = |jobs| {
+			let start = Instant::now();
+			runtime.block_on(run_concurrently(vec![(); VERSIONS], jobs, |_| {
+				tokio::time::sleep(DOWNLOAD_TIME)
+			}));
+			start.elapsed()
+		};
+
+		let sequential = measure(1);
+		let concurrent = measure(VERSIONS);
+		assert!(sequential >= DOWNLOAD_TIME * VERSIONS as u32);
+		assert!(
+			concurrent < DOWNLOAD_TIME * 2,
+			"Concurrent updates took {concurrent:?}, compared to {sequential:?} sequentially"
+		);
+	}
+}
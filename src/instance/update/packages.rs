@@ -1,34 +1,180 @@
 use std::collections::{HashMap, HashSet};
 use std::future::Future;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use itertools::Itertools;
-use mcvm_core::net::download::get_transfer_limit;
+use mcvm_core::io::files::{create_leading_dirs, update_hardlink_or_copy};
+use mcvm_core::net::download::{self, get_transfer_limit};
+use mcvm_core::net::stats;
 use mcvm_pkg::repo::PackageFlag;
+use mcvm_pkg::resolve::PackageRelations;
 use mcvm_pkg::PkgRequest;
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
-use mcvm_shared::pkg::{ArcPkgReq, PackageID};
+use mcvm_shared::pkg::{ArcPkgReq, PackageAddonOptionalHashes, PackageID};
 use mcvm_shared::translate;
 use mcvm_shared::versions::VersionInfo;
 use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
 
+use crate::addon::AddonConflictPolicy;
+use crate::instance::ignore::InstanceIgnore;
 use crate::instance::Instance;
-use crate::pkg::eval::{resolve, EvalConstants, EvalInput, EvalParameters};
+use crate::io::lock::{Lockfile, LockfilePackage};
+use crate::io::paths::Paths;
+use crate::pkg::eval::{resolve, EvalConstants, EvalData, EvalInput, EvalParameters, Routine};
+use crate::pkg::reg::apply_repository_override;
 use crate::util::select_random_n_items_from_list;
 use mcvm_shared::id::InstanceID;
 
+use super::pending::PendingOperations;
 use super::InstanceUpdateContext;
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 
-/// Install packages on multiple instances. Returns a set of all unique packages
+/// Install packages on multiple instances. Returns a set of all unique packages, along with
+/// the packages that failed to install if the update isn't failing fast
 pub async fn update_instance_packages<'a, O: MCVMOutput>(
 	instances: &mut [&mut Instance],
 	constants: &EvalConstants,
 	ctx: &mut InstanceUpdateContext<'a, O>,
 	force: bool,
-) -> anyhow::Result<HashSet<ArcPkgReq>> {
+) -> anyhow::Result<(HashSet<ArcPkgReq>, PackageFailures)> {
+	let mut plan = plan_instance_packages(instances, constants, ctx).await?;
+	let version_info = VersionInfo {
+		version: constants.version.clone(),
+		versions: constants.version_list.clone(),
+	};
+	plan.resolve_addon_conflicts(instances, &version_info, ctx)?;
+
+	// Make sure nothing changed before any addons are downloaded
+	if ctx.lock.is_frozen() {
+		for instance_id in plan.resolved.instance_to_packages.keys().sorted() {
+			let changes = ctx
+				.lock
+				.get_package_changes(instance_id, &plan.get_addon_versions(instance_id));
+			ctx.lock.ensure_unchanged(instance_id, &changes)?;
+		}
+	}
+
+	apply_package_plan(plan, instances, constants, ctx, force).await
+}
+
+/// The packages that will be installed on a set of instances, along with their evaluations.
+/// Computing this doesn't change any instance files or the lockfile
+pub struct PackagePlan<'a> {
+	resolved: ResolvedPackages,
+	evals: HashMap<(ArcPkgReq, InstanceID), EvalData<'a>>,
+}
+
+impl<'a> PackagePlan<'a> {
+	/// Get the packages that will be installed on an instance
+	pub fn get_packages(&self, instance: &InstanceID) -> &[ArcPkgReq] {
+		self.resolved
+			.instance_to_packages
+			.get(instance)
+			.map(Vec::as_slice)
+			.unwrap_or_default()
+	}
+
+	/// Get the evaluation of a package on an instance
+	pub fn get_eval(&self, package: &ArcPkgReq, instance: &InstanceID) -> Option<&EvalData<'a>> {
+		self.evals.get(&(package.clone(), instance.clone()))
+	}
+
+	/// Find addons from different packages that would be put at the same file in an instance and resolve
+	/// them with the configured conflict policy. This happens before any files are written, and the addons
+	/// that lose are removed from their evaluations so that they are never installed
+	pub fn resolve_addon_conflicts<'c, O: MCVMOutput>(
+		&mut self,
+		instances: &mut [&mut Instance],
+		version_info: &VersionInfo,
+		ctx: &mut InstanceUpdateContext<'c, O>,
+	) -> anyhow::Result<()> {
+		for (instance_id, packages) in self
+			.resolved
+			.instance_to_packages
+			.iter()
+			.sorted_by_key(|x| x.0)
+		{
+			let instance = instances
+				.iter_mut()
+				.find(|x| &x.id == instance_id)
+				.expect("Instance should exist");
+			let mut files = Vec::with_capacity(packages.len());
+			for package in packages {
+				let eval = self
+					.evals
+					.get(&(package.clone(), instance_id.clone()))
+					.expect("Evaluation should be in map");
+				let addon_files = instance
+					.get_eval_addon_files(package, eval, version_info, ctx.paths)
+					.context("Failed to get addon files")?;
+				files.push(addon_files);
+			}
+
+			// Packages are kept in the order that they are installed in
+			let mut evals: Vec<_> = self
+				.evals
+				.iter_mut()
+				.filter(|((_, id), _)| id == instance_id)
+				.map(|((package, _), eval)| {
+					let index = packages.iter().position(|x| x == package);
+					(index, eval)
+				})
+				.collect();
+			evals.sort_by_key(|x| x.0);
+			let mut evals: Vec<_> = evals.into_iter().map(|x| x.1).zip(files).collect();
+
+			let conflicts = resolve_addon_conflicts(&mut evals, ctx.prefs.addon_conflict_policy)
+				.with_context(|| format!("Conflicting addons on instance '{instance_id}'"))?;
+			for conflict in conflicts {
+				let level = if conflict.identical {
+					MessageLevel::Debug
+				} else {
+					MessageLevel::Important
+				};
+				ctx.output.display(
+					MessageContents::Warning(format!(
+						"Packages '{}' and '{}' both install the file '{}' on instance '{instance_id}', so only the one from '{}' is installed",
+						conflict.first, conflict.second, conflict.file_name, conflict.first
+					)),
+					level,
+				);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Get the IDs and versions of the addons of every package that will be installed on an instance
+	pub fn get_addon_versions(
+		&self,
+		instance: &InstanceID,
+	) -> HashMap<String, Vec<(String, Option<String>)>> {
+		self.get_packages(instance)
+			.iter()
+			.map(|package| {
+				let eval = self
+					.get_eval(package, instance)
+					.expect("Evaluation should be in map");
+				let addons = eval
+					.addon_reqs
+					.iter()
+					.map(|x| (x.addon.id.clone(), x.addon.version.clone()))
+					.collect();
+				(package.id.to_string(), addons)
+			})
+			.collect()
+	}
+}
+
+/// Resolve and evaluate the packages for multiple instances without installing anything
+pub async fn plan_instance_packages<'a: 'e, 'e, O: MCVMOutput>(
+	instances: &mut [&mut Instance],
+	constants: &'e EvalConstants,
+	ctx: &mut InstanceUpdateContext<'a, O>,
+) -> anyhow::Result<PackagePlan<'e>> {
 	// Resolve dependencies
 	ctx.output.start_process();
 	ctx.output.display(
@@ -44,12 +190,6 @@ pub async fn update_instance_packages<'a, O: MCVMOutput>(
 	);
 	ctx.output.end_process();
 
-	// Evaluate first to install all of the addons
-	ctx.output.display(
-		MessageContents::StartProcess(translate!(ctx.output, StartAcquiringAddons)),
-		MessageLevel::Important,
-	);
-	let mut tasks = HashMap::new();
 	let mut evals = HashMap::new();
 	for (package, package_instances) in resolved_packages
 		.package_to_instances
@@ -61,7 +201,6 @@ pub async fn update_instance_packages<'a, O: MCVMOutput>(
 			.await
 			.with_context(|| format!("Failed to check package {package}"))?;
 
-		// Install the package on it's instances
 		let mut notices = Vec::new();
 		for instance_id in package_instances {
 			let instance = instances
@@ -71,24 +210,35 @@ pub async fn update_instance_packages<'a, O: MCVMOutput>(
 
 			let mut params = EvalParameters::new(instance.kind.to_side());
 			params.stability = instance.config.package_stability;
+			params.perms = instance.config.package_permissions;
+			// Packages that are configured on the instance use their own settings
+			if let Some(config) = instance.get_package_config(&package.id) {
+				let properties = ctx
+					.packages
+					.get_properties(package, ctx.paths, ctx.client, ctx.output)
+					.await
+					.with_context(|| format!("Failed to get properties of package '{package}'"))?;
+				config.apply_to_params(properties, ctx.strict, &mut params)?;
+			}
+			if let Some(stability) = ctx.stability {
+				params.stability = stability;
+			}
+			params.pinned_addon_versions = instance.get_pinned_addon_versions(&package.id);
 
 			let input = EvalInput { constants, params };
-			let (eval, new_tasks) = instance
-				.get_package_addon_tasks(
+			let eval = ctx
+				.packages
+				.eval(
 					package,
-					input,
-					ctx.packages,
 					ctx.paths,
-					force,
+					Routine::Install,
+					input,
 					ctx.client,
 					ctx.plugins,
 					ctx.output,
 				)
 				.await
-				.with_context(|| {
-					format!("Failed to get addon install tasks for package '{package}' on instance")
-				})?;
-			tasks.extend(new_tasks);
+				.with_context(|| format!("Failed to evaluate package '{package}' on instance"))?;
 
 			// Add any notices to the list
 			notices.extend(
@@ -97,11 +247,10 @@ pub async fn update_instance_packages<'a, O: MCVMOutput>(
 					.map(|x| (instance_id.clone(), x.to_owned())),
 			);
 
-			// Add the eval to the map
-			evals.insert((package, instance_id), eval);
+			evals.insert((package.clone(), instance_id.clone()), eval);
 		}
 
-		// Display any accumulated notices from the installation
+		// Display any accumulated notices from the evaluation
 		for (instance, notice) in notices {
 			ctx.output.display(
 				format_package_update_message(
@@ -114,10 +263,232 @@ pub async fn update_instance_packages<'a, O: MCVMOutput>(
 		}
 	}
 
+	Ok(PackagePlan {
+		resolved: resolved_packages,
+		evals,
+	})
+}
+
+/// A package that failed to install on an instance
+#[derive(Debug)]
+pub struct PackageFailure {
+	/// The package that failed
+	pub package: PackageID,
+	/// The instance that it failed on
+	pub instance: InstanceID,
+	/// Why it failed
+	pub error: anyhow::Error,
+}
+
+/// The packages that failed during an update that kept going after the first failure.
+/// Failed packages are rolled back, so the lockfile only has the changes from the ones that succeeded
+#[derive(Debug, Default)]
+pub struct PackageFailures {
+	/// The number of packages that were installed successfully
+	pub succeeded: usize,
+	/// The packages that failed
+	pub failed: Vec<PackageFailure>,
+}
+
+impl PackageFailures {
+	/// Check whether no packages failed
+	pub fn is_empty(&self) -> bool {
+		self.failed.is_empty()
+	}
+
+	/// Add the results of installing packages on another set of instances
+	pub fn extend(&mut self, other: Self) {
+		self.succeeded += other.succeeded;
+		self.failed.extend(other.failed);
+	}
+}
+
+impl std::fmt::Display for PackageFailures {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{} packages succeeded and {} failed:",
+			self.succeeded,
+			self.failed.len()
+		)?;
+		for failure in &self.failed {
+			write!(
+				f,
+				"\n - '{}' on instance '{}': {:#}",
+				failure.package, failure.instance, failure.error
+			)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl std::error::Error for PackageFailures {}
+
+/// Addons from two packages that would be put at the same file in an instance
+#[derive(Debug, PartialEq)]
+pub struct AddonConflict {
+	/// The name of the file
+	pub file_name: String,
+	/// The package that was installed first, whose addon is kept
+	pub first: PackageID,
+	/// The package whose addon was dropped
+	pub second: PackageID,
+	/// Whether the two addons have the same hash
+	pub identical: bool,
+}
+
+/// The files in an instance that each addon of a package would be linked to, by addon ID
+pub type AddonFiles = Vec<(String, Vec<PathBuf>)>;
+
+/// Resolve the addons from different packages that would be put at the same file in an instance.
+/// The evaluations are given in install order, along with the files that each of their addons would be
+/// linked to. Addons that are dropped by the policy are removed from their evaluations
+pub fn resolve_addon_conflicts(
+	evals: &mut [(&mut EvalData<'_>, AddonFiles)],
+	policy: AddonConflictPolicy,
+) -> anyhow::Result<Vec<AddonConflict>> {
+	let mut out = Vec::new();
+	// The package and hashes of the addon that each file belongs to
+	let mut claimed: HashMap<PathBuf, (PackageID, PackageAddonOptionalHashes)> = HashMap::new();
+	for (eval, files) in evals.iter_mut() {
+		let mut dropped = HashSet::new();
+		for (addon_id, paths) in files.iter() {
+			let Some(addon) = eval.addon_reqs.iter().find(|x| &x.addon.id == addon_id) else {
+				continue;
+			};
+			for path in paths {
+				let Some((first, hashes)) = claimed.get(path) else {
+					continue;
+				};
+				if first == &eval.id {
+					continue;
+				}
+
+				let file_name = path
+					.file_name()
+					.unwrap_or(path.as_os_str())
+					.to_string_lossy()
+					.to_string();
+				let identical = are_hashes_identical(hashes, &addon.addon.hashes);
+				match policy {
+					AddonConflictPolicy::Error => bail!(
+						"Packages '{first}' and '{}' both install the file '{file_name}'",
+						eval.id
+					),
+					AddonConflictPolicy::AllowIdentical if !identical => bail!(
+						"Packages '{first}' and '{}' both install the file '{file_name}' with different contents",
+						eval.id
+					),
+					_ => {}
+				}
+				out.push(AddonConflict {
+					file_name,
+					first: first.clone(),
+					second: eval.id.clone(),
+					identical,
+				});
+				dropped.insert(addon_id.clone());
+			}
+		}
+
+		eval.addon_reqs.retain(|x| !dropped.contains(&x.addon.id));
+		for (addon_id, paths) in files.iter() {
+			let Some(addon) = eval.addon_reqs.iter().find(|x| &x.addon.id == addon_id) else {
+				continue;
+			};
+			for path in paths {
+				claimed
+					.entry(path.clone())
+					.or_insert_with(|| (eval.id.clone(), addon.addon.hashes.clone()));
+			}
+		}
+	}
+
+	Ok(out)
+}
+
+/// Check whether two addons are known to have the same contents, which is the case when they share a
+/// hash and none of their hashes differ
+fn are_hashes_identical(a: &PackageAddonOptionalHashes, b: &PackageAddonOptionalHashes) -> bool {
+	let pairs = [(&a.sha256, &b.sha256), (&a.sha512, &b.sha512)];
+	let compared: Vec<_> = pairs
+		.iter()
+		.filter_map(|(a, b)| Some((a.as_ref()?, b.as_ref()?)))
+		.collect();
+	!compared.is_empty() && compared.iter().all(|(a, b)| a == b)
+}
+
+/// Acquire the addons in a package plan and install the packages on their instances,
+/// removing any packages that are no longer used. Returns a set of all unique packages.
+/// Unless the update fails fast, a package that fails is rolled back and returned with the
+/// other failures, and the rest of the packages are still installed
+pub async fn apply_package_plan<'a, O: MCVMOutput>(
+	plan: PackagePlan<'_>,
+	instances: &mut [&mut Instance],
+	constants: &EvalConstants,
+	ctx: &mut InstanceUpdateContext<'a, O>,
+	force: bool,
+) -> anyhow::Result<(HashSet<ArcPkgReq>, PackageFailures)> {
+	let PackagePlan {
+		resolved: resolved_packages,
+		evals,
+	} = plan;
+	let mut failures = PackageFailures::default();
+	let mut failed_packages = HashSet::new();
+
+	ctx.output.display(
+		MessageContents::StartProcess(translate!(ctx.output, StartAcquiringAddons)),
+		MessageLevel::Important,
+	);
+	let mut tasks = HashMap::new();
+	// The packages that need each of the tasks
+	let mut task_packages: HashMap<String, Vec<(ArcPkgReq, InstanceID)>> = HashMap::new();
+	for ((package, instance_id), eval) in evals.iter().sorted_by_key(|x| x.0) {
+		let instance = instances
+			.iter()
+			.find(|x| &x.id == instance_id)
+			.expect("Instance should exist");
+		let new_tasks = match instance.get_addon_tasks(eval, ctx.paths, force, ctx.client) {
+			Ok(new_tasks) => new_tasks,
+			Err(e) if !ctx.fail_fast => {
+				if failed_packages.insert(package.clone()) {
+					failures.failed.push(PackageFailure {
+						package: package.id.clone(),
+						instance: instance_id.clone(),
+						error: e.context("Failed to get addon install tasks for instance"),
+					});
+				}
+				continue;
+			}
+			Err(e) => return Err(e.context("Failed to get addon install tasks for instance")),
+		};
+		for id in new_tasks.keys() {
+			task_packages
+				.entry(id.clone())
+				.or_default()
+				.push((package.clone(), instance_id.clone()));
+		}
+		tasks.extend(new_tasks);
+	}
+
 	// Run the acquire tasks
-	run_addon_tasks(tasks, ctx.output)
+	let failed_tasks = run_addon_tasks(tasks, ctx.fail_fast, &ctx.pending, ctx.output)
 		.await
 		.context("Failed to acquire addons")?;
+	for (id, error) in failed_tasks.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+		let (package, instance_id) = task_packages
+			.get(&id)
+			.and_then(|x| x.first())
+			.expect("Task should have a package");
+		if failed_packages.insert(package.clone()) {
+			failures.failed.push(PackageFailure {
+				package: package.id.clone(),
+				instance: instance_id.clone(),
+				error: error.context("Failed to acquire addon"),
+			});
+		}
+	}
 
 	ctx.output.display(
 		MessageContents::Success(translate!(ctx.output, FinishAcquiringAddons)),
@@ -129,47 +500,107 @@ pub async fn update_instance_packages<'a, O: MCVMOutput>(
 		MessageContents::StartProcess(translate!(ctx.output, StartInstallingPackages)),
 		MessageLevel::Important,
 	);
+	let version_info = VersionInfo {
+		version: constants.version.clone(),
+		versions: constants.version_list.clone(),
+	};
+	let ignores: HashMap<_, _> = instances
+		.iter()
+		.map(|x| (x.id.clone(), x.load_ignore(ctx.paths, ctx.output)))
+		.collect();
 	for (package, package_instances) in resolved_packages
 		.package_to_instances
 		.iter()
 		.sorted_by_key(|x| x.0)
 	{
+		if failed_packages.contains(package) {
+			continue;
+		}
 		ctx.output.start_process();
 
+		let mut checkpoints = Vec::new();
+		let mut result = Ok(());
 		for instance_id in package_instances {
 			let instance = instances
 				.iter_mut()
 				.find(|x| &x.id == instance_id)
 				.expect("Instance should exist");
-
-			let version_info = VersionInfo {
-				version: constants.version.clone(),
-				versions: constants.version_list.clone(),
-			};
 			let eval = evals
-				.get(&(package, instance_id))
+				.get(&(package.clone(), instance_id.clone()))
 				.expect("Evaluation should be in map");
-			instance
-				.install_eval_data(
-					package,
-					eval,
-					&version_info,
-					ctx.paths,
-					ctx.lock,
-					ctx.output,
-				)
-				.await
-				.context("Failed to install package on instance")?;
-		}
 
-		ctx.output.display(
-			format_package_update_message(
+			result = install_package_with_checkpoint(
+				instance,
 				package,
-				None,
-				MessageContents::Success(translate!(ctx.output, FinishInstallingPackage)),
-			),
-			MessageLevel::Important,
-		);
+				eval,
+				&version_info,
+				&ignores[instance_id],
+				&mut checkpoints,
+				ctx,
+			)
+			.await
+			.context("Failed to install package on instance")
+			.map_err(|e| (instance_id.clone(), e));
+			if result.is_err() {
+				break;
+			}
+
+			if let Some(relations) = resolved_packages.instance_relations.get(instance_id) {
+				ctx.lock.set_package_reason(
+					instance_id,
+					&package.id,
+					relations.get_reason(&package.id),
+					relations.get_required_by(&package.id),
+					ctx.packages.is_local(package),
+				);
+			}
+		}
+
+		match result {
+			Ok(()) => {
+				failures.succeeded += 1;
+				ctx.output.display(
+					format_package_update_message(
+						package,
+						None,
+						MessageContents::Success(translate!(ctx.output, FinishInstallingPackage)),
+					),
+					MessageLevel::Important,
+				);
+			}
+			Err((_, error)) if ctx.fail_fast => return Err(error),
+			Err((instance_id, error)) => {
+				ctx.output.display(
+					format_package_update_message(
+						package,
+						Some(&instance_id),
+						MessageContents::Error(format!("{error:#}")),
+					),
+					MessageLevel::Important,
+				);
+				for checkpoint in checkpoints {
+					let instance = instances
+						.iter()
+						.find(|x| x.id == checkpoint.instance)
+						.expect("Instance should exist");
+					let ignore = &ignores[&checkpoint.instance];
+					if let Err(e) = checkpoint.restore(instance, ctx.lock, ctx.paths, ignore) {
+						ctx.output.display(
+							MessageContents::Warning(format!(
+								"Failed to undo the changes from package '{package}': {e:#}"
+							)),
+							MessageLevel::Important,
+						);
+					}
+				}
+				failed_packages.insert(package.clone());
+				failures.failed.push(PackageFailure {
+					package: package.id.clone(),
+					instance: instance_id,
+					error,
+				});
+			}
+		}
 		ctx.output.end_process();
 	}
 
@@ -192,7 +623,7 @@ pub async fn update_instance_packages<'a, O: MCVMOutput>(
 			.context("Failed to remove unused packages")?;
 		for file in files_to_remove {
 			instance
-				.remove_addon_file(&file, ctx.paths)
+				.remove_addon_file(&file, ctx.paths, &ignores[&instance_id])
 				.with_context(|| {
 					format!(
 						"Failed to remove addon file {} for instance {}",
@@ -207,33 +638,198 @@ pub async fn update_instance_packages<'a, O: MCVMOutput>(
 	let mut out = HashSet::new();
 	out.extend(resolved_packages.package_to_instances.keys().cloned());
 
-	Ok(out)
+	Ok((out, failures))
+}
+
+/// The state of a package on an instance from before it was installed,
+/// so that the install can be undone if it fails. The backups are deleted when the checkpoint is dropped
+struct InstallCheckpoint {
+	instance: InstanceID,
+	package: PackageID,
+	entry: Option<LockfilePackage>,
+	/// The files that the install could write that didn't exist before
+	new_files: Vec<PathBuf>,
+	/// The existing files that the install could overwrite or remove, along with their backups
+	backups: Vec<(PathBuf, PathBuf)>,
+	backup_dir: PathBuf,
+}
+
+impl InstallCheckpoint {
+	/// Create a checkpoint before a package is installed on an instance, backing up
+	/// the files that the install can overwrite or remove
+	fn new(
+		instance: &mut Instance,
+		package: &ArcPkgReq,
+		eval: &EvalData<'_>,
+		version_info: &VersionInfo,
+		lock: &Lockfile,
+		paths: &Paths,
+	) -> anyhow::Result<Self> {
+		let backup_dir = paths
+			.internal
+			.join("install_backups")
+			.join(instance.id.to_string())
+			.join(package.id.to_string());
+		// Clear out backups left behind by an update that was killed
+		if backup_dir.exists() {
+			std::fs::remove_dir_all(&backup_dir).context("Failed to remove old backups")?;
+		}
+
+		let addon_files = instance
+			.get_eval_addon_files(package, eval, version_info, paths)
+			.context("Failed to get the addon files of the package")?
+			.into_iter()
+			.flat_map(|x| x.1);
+		let config_files: HashSet<_> = instance
+			.get_config_file_paths(&eval.config_files, paths)
+			.into_iter()
+			.chain(lock.get_package_config_files(&instance.id, &package.id))
+			.collect();
+		let files: HashSet<_> = addon_files
+			.chain(lock.get_package_files(&instance.id, &package.id))
+			.chain(config_files.iter().cloned())
+			.collect();
+
+		let mut new_files = Vec::new();
+		let mut backups = Vec::new();
+		for file in files.into_iter().sorted() {
+			if !file.exists() {
+				new_files.push(file);
+				continue;
+			}
+			if !file.is_file() {
+				continue;
+			}
+
+			std::fs::create_dir_all(&backup_dir).context("Failed to create backup directory")?;
+			let backup = backup_dir.join(backups.len().to_string());
+			// Addons are always removed before they are replaced, so a hard link to them stays intact.
+			// Config files are written in place and need a real copy
+			let result = if config_files.contains(&file) {
+				std::fs::copy(&file, &backup).map(|_| ())
+			} else {
+				update_hardlink_or_copy(&file, &backup)
+			};
+			result.with_context(|| format!("Failed to back up file {}", file.display()))?;
+			backups.push((file, backup));
+		}
+
+		Ok(Self {
+			instance: instance.id.clone(),
+			package: package.id.clone(),
+			entry: lock.get_package_entry(&instance.id, &package.id),
+			new_files,
+			backups,
+			backup_dir,
+		})
+	}
+
+	/// Remove the files that the install created, put back the files that it changed,
+	/// and put back the old lockfile entry
+	fn restore(
+		&self,
+		instance: &Instance,
+		lock: &mut Lockfile,
+		paths: &Paths,
+		ignore: &InstanceIgnore,
+	) -> anyhow::Result<()> {
+		lock.restore_package_entry(&self.instance, &self.package, self.entry.clone());
+		for file in &self.new_files {
+			instance
+				.remove_addon_file(file, paths, ignore)
+				.with_context(|| format!("Failed to remove addon file {}", file.display()))?;
+		}
+		for (file, backup) in &self.backups {
+			// The file might be a hard link to a stored addon now, which can't be written through
+			if file.exists() {
+				std::fs::remove_file(file)
+					.with_context(|| format!("Failed to remove file {}", file.display()))?;
+			}
+			create_leading_dirs(file)?;
+			std::fs::copy(backup, file)
+				.with_context(|| format!("Failed to restore file {}", file.display()))?;
+		}
+
+		Ok(())
+	}
+}
+
+impl Drop for InstallCheckpoint {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.backup_dir);
+	}
 }
 
-/// Evaluates addon acquire tasks efficiently with a progress display to the user
+/// Install a package on an instance, adding a checkpoint that can undo the install first
+async fn install_package_with_checkpoint<'a, O: MCVMOutput>(
+	instance: &mut Instance,
+	package: &ArcPkgReq,
+	eval: &EvalData<'_>,
+	version_info: &VersionInfo,
+	ignore: &InstanceIgnore,
+	checkpoints: &mut Vec<InstallCheckpoint>,
+	ctx: &mut InstanceUpdateContext<'a, O>,
+) -> anyhow::Result<()> {
+	let checkpoint =
+		InstallCheckpoint::new(instance, package, eval, version_info, ctx.lock, ctx.paths)
+			.context("Failed to back up the files of the package")?;
+	checkpoints.push(checkpoint);
+
+	instance
+		.install_eval_data(
+			package,
+			eval,
+			version_info,
+			ctx.paths,
+			ctx.lock,
+			ctx.prefs.addon_link_mode,
+			ignore,
+			ctx.client,
+			ctx.output,
+		)
+		.await
+}
+
+/// Evaluates addon acquire tasks efficiently with a progress display to the user.
+/// If this is cancelled, dropping the task set aborts the tasks that are still running,
+/// which releases their permits. Unless `fail_fast` is set, tasks that fail are returned
+/// instead of stopping the others
 async fn run_addon_tasks(
 	tasks: HashMap<String, impl Future<Output = anyhow::Result<()>> + Send + 'static>,
+	fail_fast: bool,
+	pending: &PendingOperations,
 	o: &mut impl MCVMOutput,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<HashMap<String, anyhow::Error>> {
 	let total_count = tasks.len();
 	let mut task_set = JoinSet::new();
 
 	let sem = Arc::new(Semaphore::new(get_transfer_limit()));
-	for task in tasks.into_values() {
-		let permit = sem.clone().acquire_owned().await;
+	for (id, task) in tasks {
+		let sem = sem.clone();
+		let operation = pending.start(format!("Acquiring addon '{id}'"));
 		let task = async move {
-			let _permit = permit?;
+			let _operation = operation;
+			let result = match sem.acquire_owned().await {
+				Ok(_permit) => task.await,
+				Err(e) => Err(e.into()),
+			};
 
-			task.await
+			(id, result)
 		};
-		task_set.spawn(task);
+		task_set.spawn(download::inherit_limits(stats::inherit(task)));
 	}
 
+	let mut failed = HashMap::new();
 	o.start_process();
 	while let Some(result) = task_set.join_next().await {
-		result
-			.context("Failed to run addon acquire task")?
-			.context("Failed to acquire addon")?;
+		let (id, result) = result.context("Failed to run addon acquire task")?;
+		match result {
+			Ok(()) => {}
+			Err(e) if fail_fast => return Err(e.context("Failed to acquire addon")),
+			Err(e) => {
+				failed.insert(id, e);
+			}
+		}
 
 		// Update progress bar
 		let progress = MessageContents::Progress {
@@ -246,7 +842,7 @@ async fn run_addon_tasks(
 
 	o.end_process();
 
-	Ok(())
+	Ok(failed)
 }
 
 /// Resolve packages and create a mapping of packages to a list of instances.
@@ -259,14 +855,30 @@ async fn resolve_and_batch<'a, O: MCVMOutput>(
 ) -> anyhow::Result<ResolvedPackages> {
 	let mut batched: HashMap<ArcPkgReq, Vec<InstanceID>> = HashMap::new();
 	let mut resolved = HashMap::new();
+	let mut relations = HashMap::new();
 
 	for instance in instances {
 		let mut params = EvalParameters::new(instance.kind.to_side());
 		params.stability = instance.config.package_stability;
+		params.perms = instance.config.package_permissions;
 
-		let instance_pkgs = instance.get_configured_packages();
+		check_package_features(instance, ctx).await?;
+
+		let mut instance_pkgs = instance.get_configured_packages().clone();
+		if let Some(stability) = ctx.stability {
+			params.stability = stability;
+			for config in &mut instance_pkgs {
+				config.stability = stability;
+			}
+		}
+		// Repository overrides apply to the packages of this instance and their dependencies
+		let overrides: HashMap<_, _> = instance_pkgs
+			.iter()
+			.filter_map(|x| Some((x.id.clone(), x.repository.clone()?)))
+			.collect();
+		ctx.packages.set_repository_overrides(overrides.clone());
 		let instance_resolved = resolve(
-			instance_pkgs,
+			&instance_pkgs,
 			constants,
 			params,
 			ctx.paths,
@@ -275,26 +887,35 @@ async fn resolve_and_batch<'a, O: MCVMOutput>(
 			ctx.plugins,
 			ctx.output,
 		)
-		.await
-		.with_context(|| {
+		.await;
+		ctx.packages.clear_repository_overrides();
+		let instance_resolved = instance_resolved.with_context(|| {
 			format!(
 				"Failed to resolve package dependencies for instance '{}'",
 				instance.id
 			)
 		})?;
-		for package in &instance_resolved.packages {
+
+		let instance_packages: Vec<_> = instance_resolved
+			.packages
+			.iter()
+			.map(|x| apply_repository_override(x, &overrides))
+			.collect();
+		for package in &instance_packages {
 			if let Some(entry) = batched.get_mut(package) {
 				entry.push(instance.id.clone());
 			} else {
 				batched.insert(package.clone(), vec![instance.id.clone()]);
 			}
 		}
-		resolved.insert(instance.id.clone(), instance_resolved.packages);
+		resolved.insert(instance.id.clone(), instance_packages);
+		relations.insert(instance.id.clone(), instance_resolved.relations);
 	}
 
 	Ok(ResolvedPackages {
 		package_to_instances: batched,
 		instance_to_packages: resolved,
+		instance_relations: relations,
 	})
 }
 
@@ -303,6 +924,51 @@ struct ResolvedPackages {
 	pub package_to_instances: HashMap<ArcPkgReq, Vec<InstanceID>>,
 	/// A reverse mapping of instance IDs to all of the packages they have resolved
 	pub instance_to_packages: HashMap<InstanceID, Vec<ArcPkgReq>>,
+	/// Why the packages on each instance were installed
+	pub instance_relations: HashMap<InstanceID, PackageRelations>,
+}
+
+/// Checks that the features configured for the packages on an instance exist,
+/// warning about or failing on any unknown ones
+async fn check_package_features<'a, O: MCVMOutput>(
+	instance: &Instance,
+	ctx: &mut InstanceUpdateContext<'a, O>,
+) -> anyhow::Result<()> {
+	for config in instance.get_configured_packages() {
+		if config.features.is_empty() {
+			continue;
+		}
+
+		let properties = ctx
+			.packages
+			.get_properties(&config.get_request(), ctx.paths, ctx.client, ctx.output)
+			.await
+			.with_context(|| format!("Failed to get properties for package '{}'", config.id))?;
+		if ctx.strict {
+			config
+				.calculate_features(properties, true)
+				.with_context(|| {
+					format!(
+						"Invalid features for package '{}' on instance '{}'",
+						config.id, instance.id
+					)
+				})?;
+			continue;
+		}
+		for feature in config.get_unknown_features(properties) {
+			ctx.output.display(
+				MessageContents::Warning(translate!(
+					ctx.output,
+					UnknownPackageFeature,
+					"pkg" = &config.id,
+					"feature" = &feature
+				)),
+				MessageLevel::Important,
+			);
+		}
+	}
+
+	Ok(())
 }
 
 /// Checks a package with the registry to report any warnings about it
@@ -399,3 +1065,307 @@ fn format_package_update_message(
 
 	MessageContents::ListItem(Box::new(msg))
 }
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use mcvm_shared::addon::{Addon, AddonKind};
+	use mcvm_shared::lang::Language;
+	use mcvm_shared::modifications::{ClientType, Modloader, ServerType};
+	use mcvm_shared::output::{self, NoOp};
+	use mcvm_shared::pkg::{ConfigFilePolicy, PackageStability, PkgRequestSource};
+	use mcvm_shared::Side;
+	use reqwest::Client;
+
+	use crate::addon::{AddonExt, AddonLinkMode, AddonLocation, AddonRequest};
+	use crate::config::plugin::PluginManager;
+	use crate::config::profile::GameModifications;
+	use crate::config::Config;
+	use crate::io::lock::LockfileAddon;
+	use crate::pkg::eval::{ConfigFileLocation, ConfigFileRequest, EvalParameters};
+	use mcvm_pkg::properties::PackageProperties;
+
+	use super::*;
+
+	#[test]
+	fn test_addon_conflicts() {
+		let constants = EvalConstants {
+			version: "1.20.1".into(),
+			version_list: vec!["1.20.1".into()],
+			modifications: GameModifications::new(
+				Modloader::Fabric,
+				ClientType::Fabric,
+				ServerType::Fabric,
+			),
+			language: Language::AmericanEnglish,
+			profile_stability: PackageStability::Latest,
+		};
+		let plugins = PluginManager::new();
+		// Creates the evaluation of a package with one library addon and the file it would be linked to
+		let eval = |package: &str, sha256: Option<&str>| {
+			let input = EvalInput {
+				constants: &constants,
+				params: EvalParameters::new(Side::Client),
+			};
+			let mut eval = EvalData::new(
+				input,
+				PackageID::from(package),
+				PackageProperties::default(),
+				&Routine::Install,
+				&plugins,
+			);
+			let addon = Addon {
+				kind: AddonKind::Mod,
+				id: "library".into(),
+				file_name: "library.jar".into(),
+				pkg_id: PackageID::from(package),
+				version: Some("1".into()),
+				hashes: PackageAddonOptionalHashes {
+					sha256: sha256.map(String::from),
+					sha512: None,
+				},
+			};
+			eval.addon_reqs.push(AddonRequest::new(
+				addon,
+				AddonLocation::Remote("https://example.com/library.jar".into()),
+			));
+			let files = vec![(
+				"library".to_string(),
+				vec![PathBuf::from("mods/library.jar")],
+			)];
+			(eval, files)
+		};
+		let resolve = |hash_a, hash_b, policy| {
+			let (mut a, files_a) = eval("a", hash_a);
+			let (mut b, files_b) = eval("b", hash_b);
+			let result =
+				resolve_addon_conflicts(&mut [(&mut a, files_a), (&mut b, files_b)], policy);
+			result.map(|x| (x, a.addon_reqs.len(), b.addon_reqs.len()))
+		};
+
+		let error = resolve(Some("foo"), Some("foo"), AddonConflictPolicy::Error).unwrap_err();
+		assert_eq!(
+			error.to_string(),
+			"Packages 'a' and 'b' both install the file 'library.jar'"
+		);
+
+		let (conflicts, a, b) =
+			resolve(Some("foo"), Some("bar"), AddonConflictPolicy::FirstWins).unwrap();
+		assert_eq!(
+			conflicts,
+			vec![AddonConflict {
+				file_name: "library.jar".into(),
+				first: "a".into(),
+				second: "b".into(),
+				identical: false,
+			}]
+		);
+		assert_eq!((a, b), (1, 0));
+
+		let (conflicts, a, b) = resolve(
+			Some("foo"),
+			Some("foo"),
+			AddonConflictPolicy::AllowIdentical,
+		)
+		.unwrap();
+		assert!(conflicts[0].identical);
+		assert_eq!((a, b), (1, 0));
+		assert!(resolve(
+			Some("foo"),
+			Some("bar"),
+			AddonConflictPolicy::AllowIdentical
+		)
+		.is_err());
+		// Addons without hashes can't be known to be identical
+		assert!(resolve(None, None, AddonConflictPolicy::AllowIdentical).is_err());
+
+		// Different files never conflict
+		let (mut a, files_a) = eval("a", None);
+		let (mut b, _) = eval("b", None);
+		let files_b = vec![("library".to_string(), vec![PathBuf::from("mods/other.jar")])];
+		let conflicts = resolve_addon_conflicts(
+			&mut [(&mut a, files_a), (&mut b, files_b)],
+			AddonConflictPolicy::Error,
+		)
+		.unwrap();
+		assert!(conflicts.is_empty());
+	}
+
+	#[test]
+	fn test_install_rollback() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let mut paths = Paths::new_no_create().unwrap();
+		paths.internal = dir.join("internal");
+		paths.addons = dir.join("addons");
+		paths.instances = dir.join("instances");
+
+		let deser = serde_json::from_value(serde_json::json!({
+			"instances": {
+				"server": { "type": "server", "version": "1.20.1" }
+			}
+		}))
+		.unwrap();
+		let mut config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			false,
+			&paths,
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+		let instance = config
+			.instances
+			.get_mut(&InstanceID::from("server"))
+			.unwrap();
+		let ignore = instance.load_ignore(&paths, &mut NoOp);
+		let game_dir = paths.instances.join("server");
+		let mods_dir = game_dir.join("mods");
+		let config_dir = game_dir.join("config");
+		fs::create_dir_all(&mods_dir).unwrap();
+		fs::create_dir_all(&config_dir).unwrap();
+
+		let addon = |version: &str| Addon {
+			kind: AddonKind::Mod,
+			id: "library".into(),
+			file_name: format!("library-{version}.jar"),
+			pkg_id: PackageID::from("pkg"),
+			version: Some(version.into()),
+			hashes: PackageAddonOptionalHashes::default(),
+		};
+
+		// Version 1 of the package is installed, and the user has their own settings
+		let mut lock = Lockfile::open(&paths).unwrap();
+		let old_addon =
+			LockfileAddon::from_addon(&addon("1"), vec![mods_dir.join("library-1.jar")]);
+		lock.update_package("pkg", "server", &[old_addon], &ignore, &mut NoOp)
+			.unwrap();
+		fs::write(mods_dir.join("library-1.jar"), "old").unwrap();
+		fs::write(config_dir.join("settings.json"), r#"{"user": true}"#).unwrap();
+		fs::write(config_dir.join("broken.json"), "not json").unwrap();
+		let old_files = lock.get_package_files("server", "pkg");
+
+		// Version 2 replaces the addon and merges into both config files, which fails on the second one
+		let new_addon = addon("2");
+		let stored_path = new_addon.get_path(&paths, "server");
+		fs::create_dir_all(stored_path.parent().unwrap()).unwrap();
+		fs::write(&stored_path, "new").unwrap();
+		let constants = EvalConstants {
+			version: "1.20.1".into(),
+			version_list: vec!["1.20.1".into()],
+			modifications: GameModifications::new(
+				Modloader::Vanilla,
+				ClientType::Vanilla,
+				ServerType::Vanilla,
+			),
+			language: Language::AmericanEnglish,
+			profile_stability: PackageStability::Latest,
+		};
+		let plugins = PluginManager::new();
+		let input = EvalInput {
+			constants: &constants,
+			params: EvalParameters::new(Side::Server),
+		};
+		let mut eval = EvalData::new(
+			input,
+			PackageID::from("pkg"),
+			PackageProperties::default(),
+			&Routine::Install,
+			&plugins,
+		);
+		eval.addon_reqs.push(AddonRequest::new(
+			new_addon,
+			AddonLocation::Local(stored_path.clone()),
+		));
+		for file in ["settings.json", "broken.json"] {
+			eval.config_files.push(ConfigFileRequest {
+				path: format!("config/{file}"),
+				location: ConfigFileLocation::Contents(r#"{"package": true}"#.into()),
+				policy: ConfigFilePolicy::JsonMerge,
+			});
+		}
+
+		let package = Arc::new(PkgRequest::parse("pkg", PkgRequestSource::UserRequire));
+		let version_info = VersionInfo {
+			version: "1.20.1".into(),
+			versions: vec!["1.20.1".into()],
+		};
+		let checkpoint =
+			InstallCheckpoint::new(instance, &package, &eval, &version_info, &lock, &paths)
+				.unwrap();
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let result = runtime.block_on(instance.install_eval_data(
+			&package,
+			&eval,
+			&version_info,
+			&paths,
+			&mut lock,
+			AddonLinkMode::Hardlink,
+			&ignore,
+			&Client::new(),
+			&mut NoOp,
+		));
+		assert!(result.is_err());
+		// The install got far enough to change files before it failed
+		assert!(!mods_dir.join("library-1.jar").exists());
+		assert_ne!(
+			fs::read_to_string(config_dir.join("settings.json")).unwrap(),
+			r#"{"user": true}"#
+		);
+
+		checkpoint
+			.restore(instance, &mut lock, &paths, &ignore)
+			.unwrap();
+		assert_eq!(
+			fs::read_to_string(mods_dir.join("library-1.jar")).unwrap(),
+			"old"
+		);
+		assert!(!mods_dir.join("library-2.jar").exists());
+		assert_eq!(
+			fs::read_to_string(config_dir.join("settings.json")).unwrap(),
+			r#"{"user": true}"#
+		);
+		assert_eq!(
+			fs::read_to_string(config_dir.join("broken.json")).unwrap(),
+			"not json"
+		);
+		assert_eq!(fs::read_to_string(&stored_path).unwrap(), "new");
+		assert_eq!(lock.get_package_files("server", "pkg"), old_files);
+
+		let backup_dir = checkpoint.backup_dir.clone();
+		assert!(backup_dir.exists());
+		drop(checkpoint);
+		assert!(!backup_dir.exists());
+	}
+
+	#[test]
+	fn test_package_failures() {
+		let mut failures = PackageFailures {
+			succeeded: 2,
+			failed: Vec::new(),
+		};
+		assert!(failures.is_empty());
+
+		failures.extend(PackageFailures {
+			succeeded: 1,
+			failed: vec![PackageFailure {
+				package: "broken".into(),
+				instance: "client".into(),
+				error: anyhow::anyhow!("Download failed").context("Failed to acquire addon"),
+			}],
+		});
+		assert!(!failures.is_empty());
+		assert_eq!(
+			failures.to_string(),
+			"3 packages succeeded and 1 failed:\n - 'broken' on instance 'client': Failed to acquire addon: Download failed"
+		);
+
+		// The failures need to survive being passed around as an error
+		let error = anyhow::Error::from(failures).context("Failed to update");
+		assert_eq!(
+			error.downcast_ref::<PackageFailures>().unwrap().succeeded,
+			3
+		);
+	}
+}
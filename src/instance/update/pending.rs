@@ -0,0 +1,75 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// The operations that are running as part of an update, so that the ones that
+/// hadn't finished can be reported if the update is cancelled
+#[derive(Debug, Clone, Default)]
+pub struct PendingOperations {
+	operations: Arc<Mutex<Vec<String>>>,
+}
+
+impl PendingOperations {
+	/// Create a new empty set of operations
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Record that an operation has started. It is finished when the returned guard is dropped,
+	/// which also happens when the task running it is cancelled
+	pub fn start(&self, operation: impl Into<String>) -> PendingOperation {
+		let operation = operation.into();
+		self.lock().push(operation.clone());
+
+		PendingOperation {
+			operations: self.clone(),
+			operation,
+		}
+	}
+
+	/// Get the operations that haven't finished yet, in the order that they were started
+	pub fn get(&self) -> Vec<String> {
+		self.lock().clone()
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, Vec<String>> {
+		// The list is always valid, even if another thread panicked while holding it
+		self.operations
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+/// Guard for an operation in [PendingOperations] that finishes it when dropped
+#[must_use]
+pub struct PendingOperation {
+	operations: PendingOperations,
+	operation: String,
+}
+
+impl Drop for PendingOperation {
+	fn drop(&mut self) {
+		let mut operations = self.operations.lock();
+		if let Some(index) = operations.iter().position(|x| x == &self.operation) {
+			operations.remove(index);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_pending_operations() {
+		let pending = PendingOperations::new();
+		let first = pending.start("first");
+		let second = pending.start("second");
+		let duplicate = pending.start("first");
+		assert_eq!(pending.get(), vec!["first", "second", "first"]);
+
+		drop(first);
+		assert_eq!(pending.get(), vec!["second", "first"]);
+		drop(duplicate);
+		drop(second);
+		assert!(pending.get().is_empty());
+	}
+}
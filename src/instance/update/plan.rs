@@ -0,0 +1,269 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use itertools::Itertools;
+use mcvm_core::io::update::UpdateManager as CoreUpdateManager;
+use mcvm_core::net::game_files::download_size::DownloadSize;
+use mcvm_mods::fabric_quilt;
+use mcvm_shared::id::InstanceID;
+use mcvm_shared::modifications::Modloader;
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::versions::VersionInfo;
+
+use crate::io::lock::LockfileChange;
+use crate::pkg::eval::EvalConstants;
+
+use super::super::Instance;
+use super::manager::UpdateManager;
+use super::packages::plan_instance_packages;
+use super::{get_paper_build, get_sponge_version, InstanceUpdateContext};
+
+/// The changes that updating an instance would make, computed without changing any files
+/// or the lockfile
+#[derive(Debug, Clone)]
+pub struct UpdatePlan {
+	/// The ID of the instance
+	pub instance: InstanceID,
+	/// The Minecraft version that the instance would be updated to
+	pub version: String,
+	/// Whether the instance has never been updated before
+	pub first_update: bool,
+	/// Changes to the Minecraft version, Paper build, and packages compared to the lockfile
+	pub changes: Vec<LockfileChange>,
+	/// Addon files that would be written
+	pub written_files: Vec<PathBuf>,
+	/// Addon files that would be removed
+	pub removed_files: Vec<PathBuf>,
+	/// Options files that plugins would rewrite. These are written on every update,
+	/// so they don't count as pending changes
+	pub options_files: Vec<PathBuf>,
+	/// The game files that would be downloaded. Java and package files aren't counted, and
+	/// missing game files don't count as pending changes since they don't change the instance
+	pub download: DownloadSize,
+}
+
+impl UpdatePlan {
+	/// Whether the update would change anything
+	pub fn has_changes(&self) -> bool {
+		self.first_update
+			|| !self.changes.is_empty()
+			|| !self.written_files.is_empty()
+			|| !self.removed_files.is_empty()
+	}
+}
+
+impl Instance {
+	/// Work out what updating this instance would change. The version manifest and package
+	/// repositories are fetched as usual, but nothing is installed and the lockfile isn't written
+	pub async fn plan_update<'a, O: MCVMOutput>(
+		&mut self,
+		update_packages: bool,
+		ctx: &mut InstanceUpdateContext<'a, O>,
+	) -> anyhow::Result<UpdatePlan> {
+		// Modloader requirements are left out since fulfilling them downloads files
+		let mut manager = UpdateManager::new(false, false);
+		manager.set_metadata_freshness(ctx.prefs.metadata_freshness);
+		manager.set_version(&self.config.version);
+		manager
+			.fulfill_requirements(ctx.users, ctx.plugins, ctx.paths, ctx.client, ctx.output)
+			.await
+			.context("Failed to fulfill update manager")?;
+		let version_info = manager.version_info.get().clone();
+		let download = manager
+			.estimate_download_size(self.get_side(), ctx.paths, ctx.output)
+			.await
+			.context("Failed to estimate download size")?;
+
+		let paper_build = get_paper_build(self, &version_info.version, false, ctx)
+			.await
+			.context("Failed to get Paper build number")?;
+		let sponge_version = get_sponge_version(self, &version_info.version, ctx)
+			.await
+			.context("Failed to get Sponge version")?
+			.map(|x| x.version)
+			.filter(|x| !x.is_empty());
+
+		let loader_version = self
+			.get_planned_loader_version(&version_info.version, ctx)
+			.await?;
+
+		let first_update = ctx.lock.get_instance_version(&self.id).is_none();
+		let mut plan = UpdatePlan {
+			instance: self.id.clone(),
+			version: version_info.version.clone(),
+			first_update,
+			changes: ctx.lock.get_instance_changes(
+				&self.id,
+				&version_info.version,
+				paper_build,
+				loader_version.as_deref(),
+				sponge_version.as_deref(),
+			),
+			written_files: Vec::new(),
+			removed_files: Vec::new(),
+			options_files: self.get_options_files(ctx)?,
+			download,
+		};
+
+		if update_packages {
+			self.plan_package_update(&mut plan, &version_info, ctx)
+				.await?;
+		}
+
+		Ok(plan)
+	}
+
+	/// Add the changes to packages and addon files to an update plan
+	async fn plan_package_update<'a, O: MCVMOutput>(
+		&mut self,
+		plan: &mut UpdatePlan,
+		version_info: &VersionInfo,
+		ctx: &mut InstanceUpdateContext<'a, O>,
+	) -> anyhow::Result<()> {
+		let constants = EvalConstants {
+			version: version_info.version.clone(),
+			modifications: self.config.modifications.clone(),
+			version_list: version_info.versions.clone(),
+			language: ctx.prefs.language,
+			profile_stability: self.config.package_stability,
+		};
+		let id = self.id.clone();
+		let mut packages = plan_instance_packages(&mut [&mut *self], &constants, ctx).await?;
+		packages.resolve_addon_conflicts(&mut [&mut *self], version_info, ctx)?;
+
+		let addon_versions = packages.get_addon_versions(&id);
+		let package_changes = if plan.first_update {
+			addon_versions
+				.keys()
+				.sorted()
+				.map(|x| LockfileChange::AddedPackage(x.clone()))
+				.collect()
+		} else {
+			ctx.lock.get_package_changes(&id, &addon_versions)
+		};
+		let changed_addons: HashSet<_> = package_changes
+			.iter()
+			.filter_map(|x| match x {
+				LockfileChange::AddedPackage(package) => Some((package.clone(), None)),
+				LockfileChange::Addon { package, addon, .. } => {
+					Some((package.clone(), Some(addon.clone())))
+				}
+				_ => None,
+			})
+			.collect();
+
+		let current_files = ctx.lock.get_instance_addon_files(&id);
+		let mut new_files = HashSet::new();
+		for package in packages.get_packages(&id) {
+			let eval = packages
+				.get_eval(package, &id)
+				.expect("Evaluation should be in plan");
+			let addon_files = self
+				.get_eval_addon_files(package, eval, version_info, ctx.paths)
+				.context("Failed to get addon files")?;
+			for (addon, files) in addon_files {
+				let package = package.id.to_string();
+				let changed = changed_addons.contains(&(package.clone(), None))
+					|| changed_addons.contains(&(package, Some(addon)));
+				for file in files {
+					if changed || !current_files.contains(&file) || !file.exists() {
+						plan.written_files.push(file.clone());
+					}
+					new_files.insert(file);
+				}
+			}
+		}
+		plan.written_files.sort();
+		plan.removed_files = current_files
+			.into_iter()
+			.filter(|x| !new_files.contains(x))
+			.sorted()
+			.collect();
+		plan.changes.extend(package_changes);
+
+		Ok(())
+	}
+
+	/// Get the Fabric or Quilt loader version that updating this instance would install. If the loader
+	/// metadata can't be downloaded, the version in the lockfile is used so that no change is shown
+	async fn get_planned_loader_version<'a, O: MCVMOutput>(
+		&self,
+		mc_version: &str,
+		ctx: &mut InstanceUpdateContext<'a, O>,
+	) -> anyhow::Result<Option<String>> {
+		let mode = match self.config.modifications.get_modloader(self.get_side()) {
+			Modloader::Fabric => fabric_quilt::Mode::Fabric,
+			Modloader::Quilt => fabric_quilt::Mode::Quilt,
+			_ => return Ok(None),
+		};
+
+		let mut manager = CoreUpdateManager::new(false, false);
+		manager.set_metadata_freshness(ctx.prefs.metadata_freshness);
+		let list =
+			fabric_quilt::get_meta_list(mc_version, &mode, &ctx.paths.core, &manager, ctx.client)
+				.await;
+		let list = match list {
+			Ok(list) => list,
+			Err(e) => {
+				ctx.output.display(
+					MessageContents::Warning(format!(
+						"Failed to get {mode} versions, so the loader version is assumed to be unchanged: {e:#}"
+					)),
+					MessageLevel::Important,
+				);
+				let recorded = ctx.lock.get_instance_loader_version(&self.id);
+				return Ok(recorded.map(String::from));
+			}
+		};
+		let meta = fabric_quilt::select_meta(&list, &self.config.loader_version, mode)?;
+
+		Ok(Some(meta.get_loader_version().to_string()))
+	}
+
+	/// Get the options files that plugins will write when this instance is set up
+	fn get_options_files<'a, O: MCVMOutput>(
+		&mut self,
+		ctx: &mut InstanceUpdateContext<'a, O>,
+	) -> anyhow::Result<Vec<PathBuf>> {
+		let files = ctx
+			.plugins
+			.get_setup_files(self.get_side())
+			.context("Failed to get plugins")?;
+
+		self.ensure_dirs_set(ctx.paths);
+		let game_dir = &self.dirs.get().game_dir;
+		Ok(files.iter().map(|x| game_dir.join(x)).collect())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_plan_has_changes() {
+		let mut plan = UpdatePlan {
+			instance: "test".into(),
+			version: "1.20.1".into(),
+			first_update: false,
+			changes: Vec::new(),
+			written_files: Vec::new(),
+			removed_files: Vec::new(),
+			options_files: vec![PathBuf::from("options.txt")],
+			download: DownloadSize {
+				files: 1,
+				bytes: 100,
+				unknown: 0,
+			},
+		};
+		assert!(!plan.has_changes());
+
+		plan.removed_files.push(PathBuf::from("mods/old.jar"));
+		assert!(plan.has_changes());
+
+		plan.removed_files.clear();
+		plan.first_update = true;
+		assert!(plan.has_changes());
+	}
+}
@@ -0,0 +1,315 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use mcvm_core::io::files::create_leading_dirs;
+use mcvm_core::net::game_files::client_meta::ClientMeta;
+use mcvm_core::net::game_files::libraries;
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::pkg::PackageAddonOptionalHashes;
+use mcvm_shared::Side;
+
+use crate::addon::{self, AddonExt};
+use crate::io::lock::Lockfile;
+use crate::io::paths::Paths;
+
+use super::snapshot::is_non_empty_dir;
+use super::update::manager::UpdateManager;
+use super::Instance;
+
+/// A file of an instance that was found to be missing or corrupted
+#[derive(Debug, Clone, PartialEq)]
+pub enum DamagedFile {
+	/// The vanilla game jar
+	GameJar(PathBuf),
+	/// A library jar
+	Library(PathBuf),
+	/// The directory that natives are extracted to
+	Natives(PathBuf),
+	/// An addon file that was linked into the instance
+	Addon(PathBuf),
+}
+
+impl DamagedFile {
+	/// Get the path to the damaged file
+	pub fn get_path(&self) -> &Path {
+		match self {
+			Self::GameJar(path) | Self::Library(path) | Self::Natives(path) | Self::Addon(path) => {
+				path
+			}
+		}
+	}
+
+	/// Check whether this file has been repaired
+	pub fn is_repaired(&self) -> bool {
+		match self {
+			Self::Natives(dir) => is_non_empty_dir(dir),
+			_ => self.get_path().exists(),
+		}
+	}
+}
+
+impl Display for DamagedFile {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let kind = match self {
+			Self::GameJar(..) => "game jar",
+			Self::Library(..) => "library",
+			Self::Natives(..) => "natives",
+			Self::Addon(..) => "addon",
+		};
+		write!(f, "{kind} {}", self.get_path().display())
+	}
+}
+
+/// The state of a downloaded file compared to what it should be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+	/// The file exists and looks complete
+	Intact,
+	/// The file doesn't exist
+	Missing,
+	/// The file is empty or doesn't have the expected size
+	Corrupted,
+}
+
+/// Check the state of a downloaded file, given the size that it should have if it is known
+pub fn check_file(path: &Path, expected_size: Option<u64>) -> FileState {
+	let Ok(meta) = std::fs::metadata(path) else {
+		return FileState::Missing;
+	};
+	if meta.len() == 0 || expected_size.is_some_and(|x| x != meta.len()) {
+		FileState::Corrupted
+	} else {
+		FileState::Intact
+	}
+}
+
+/// Remove a downloaded file if it is corrupted so that it will be downloaded again.
+/// Returns the state that the file was in
+pub fn ensure_file_downloadable(
+	path: &Path,
+	expected_size: Option<u64>,
+) -> anyhow::Result<FileState> {
+	let state = check_file(path, expected_size);
+	if state == FileState::Corrupted {
+		std::fs::remove_file(path)
+			.with_context(|| format!("Failed to remove corrupted file {}", path.display()))?;
+	}
+	Ok(state)
+}
+
+/// Remove the game jar if it is corrupted. Returns the jar if it needs to be downloaded again
+pub fn ensure_game_jar(
+	client_meta: &ClientMeta,
+	version: &str,
+	side: Side,
+	paths: &Paths,
+) -> anyhow::Result<Option<DamagedFile>> {
+	let path = mcvm_core::io::minecraft::game_jar::get_path(side, version, None, &paths.core);
+	let download = match side {
+		Side::Client => &client_meta.downloads.client,
+		Side::Server => &client_meta.downloads.server,
+	};
+	let state = ensure_file_downloadable(&path, download.size)?;
+	Ok((state != FileState::Intact).then_some(DamagedFile::GameJar(path)))
+}
+
+/// Remove any corrupted libraries. Returns the libraries that need to be downloaded again
+pub fn ensure_libraries(
+	client_meta: &ClientMeta,
+	paths: &Paths,
+) -> anyhow::Result<Vec<DamagedFile>> {
+	let mut out = Vec::new();
+	for lib in libraries::get_files(client_meta, &paths.core)? {
+		if ensure_file_downloadable(&lib.path, lib.size)? != FileState::Intact {
+			out.push(DamagedFile::Library(lib.path));
+		}
+	}
+	Ok(out)
+}
+
+/// Clear the natives directory if it is missing files or any of the native jars were damaged.
+/// Returns the directory if the natives need to be extracted again
+pub fn ensure_natives(
+	client_meta: &ClientMeta,
+	version: &str,
+	damaged_libraries: &[DamagedFile],
+	paths: &Paths,
+) -> anyhow::Result<Option<DamagedFile>> {
+	let native_jars: Vec<_> = libraries::get_files(client_meta, &paths.core)?
+		.into_iter()
+		.filter(|x| x.is_native)
+		.collect();
+	if native_jars.is_empty() {
+		return Ok(None);
+	}
+
+	let dir = libraries::get_natives_dir(version, &paths.core);
+	let jar_damaged = native_jars
+		.iter()
+		.any(|jar| damaged_libraries.contains(&DamagedFile::Library(jar.path.clone())));
+	if jar_damaged && dir.exists() {
+		// Natives are only extracted when they are missing, so the old ones have to be removed
+		std::fs::remove_dir_all(&dir).context("Failed to remove natives directory")?;
+	}
+
+	if is_non_empty_dir(&dir) {
+		Ok(None)
+	} else {
+		Ok(Some(DamagedFile::Natives(dir)))
+	}
+}
+
+impl Instance {
+	/// Check the files that this instance needs to launch before it is created. Damaged game files are removed
+	/// so that creating the instance downloads them again, and are returned to be reported once it has been.
+	/// Missing or changed addon files are linked again from addon storage right away
+	pub async fn verify_files(
+		&mut self,
+		manager: &mut UpdateManager,
+		paths: &Paths,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<Vec<DamagedFile>> {
+		let side = self.get_side();
+		let version = manager
+			.get_core_version(o)
+			.await
+			.context("Failed to get core version")?;
+		let client_meta = version.get_client_meta();
+		let version = version.get_version().to_string();
+
+		let mut out = Vec::from_iter(
+			ensure_game_jar(client_meta, &version, side, paths)
+				.context("Failed to check game jar")?,
+		);
+		if side == Side::Client {
+			let damaged_libraries =
+				ensure_libraries(client_meta, paths).context("Failed to check libraries")?;
+			let natives = ensure_natives(client_meta, &version, &damaged_libraries, paths)
+				.context("Failed to check natives")?;
+			out.extend(damaged_libraries);
+			out.extend(natives);
+		}
+
+		let mut lock = Lockfile::open(paths).context("Failed to open lockfile")?;
+		let repaired_addons = self
+			.ensure_addon_links(&mut lock, paths, o)
+			.context("Failed to check addon files")?;
+		if !repaired_addons.is_empty() {
+			lock.finish(paths).context("Failed to write lockfile")?;
+		}
+		report_repairs(&repaired_addons, o);
+
+		Ok(out)
+	}
+
+	/// Link any addon files that the lockfile records for this instance again if they are missing
+	/// or no longer match the stored addon. Returns the files that were repaired
+	pub fn ensure_addon_links(
+		&self,
+		lock: &mut Lockfile,
+		paths: &Paths,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<Vec<DamagedFile>> {
+		let mut out = Vec::new();
+		for installed in lock.get_instance_installed_addons(&self.id)? {
+			let stored = installed.addon.get_path(paths, &self.id);
+			// The hash recorded when the addon was installed is checked first, since the package might not list one
+			let hashes = match &installed.provenance {
+				Some(provenance) => PackageAddonOptionalHashes {
+					sha256: Some(provenance.sha256.clone()),
+					sha512: None,
+				},
+				None => installed.addon.hashes.clone(),
+			};
+			for file in installed.files {
+				if addon::is_addon_file_intact(&stored, &file, installed.link_mode, &hashes) {
+					continue;
+				}
+				if !stored.exists() {
+					o.display(
+						MessageContents::Warning(format!(
+							"Addon file {} is damaged and can't be repaired until the instance is updated",
+							file.display()
+						)),
+						MessageLevel::Important,
+					);
+					continue;
+				}
+
+				create_leading_dirs(&file).context("Failed to create addon directory")?;
+				let mode = addon::link_addon_file(&stored, &file, installed.link_mode, None)?;
+				lock.set_addon_link_mode(
+					&self.id,
+					&installed.addon.pkg_id,
+					&installed.addon.id,
+					mode,
+				);
+				out.push(DamagedFile::Addon(file));
+			}
+		}
+
+		Ok(out)
+	}
+}
+
+/// Report damaged files that have been repaired, and warn about ones that are still missing.
+/// Game jars are left out when they are still missing, since instances that replace the jar don't use them
+pub fn report_repairs(files: &[DamagedFile], o: &mut impl MCVMOutput) {
+	for file in files {
+		if file.is_repaired() {
+			o.display(
+				MessageContents::Success(format!("Repaired {file}")),
+				MessageLevel::Important,
+			);
+		} else if !matches!(file, DamagedFile::GameJar(..)) {
+			o.display(
+				MessageContents::Warning(format!("Failed to repair {file}")),
+				MessageLevel::Important,
+			);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_ensure_file_downloadable() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let path = dir.join("lib.jar");
+
+		assert_eq!(check_file(&path, Some(4)), FileState::Missing);
+		assert_eq!(
+			ensure_file_downloadable(&path, Some(4)).unwrap(),
+			FileState::Missing
+		);
+
+		std::fs::write(&path, "jar").unwrap();
+		assert_eq!(check_file(&path, None), FileState::Intact);
+		assert_eq!(
+			ensure_file_downloadable(&path, Some(4)).unwrap(),
+			FileState::Corrupted
+		);
+		assert!(!path.exists());
+
+		std::fs::write(&path, "").unwrap();
+		assert_eq!(check_file(&path, None), FileState::Corrupted);
+
+		std::fs::write(&path, "jars").unwrap();
+		assert_eq!(
+			ensure_file_downloadable(&path, Some(4)).unwrap(),
+			FileState::Intact
+		);
+		assert!(path.exists());
+
+		let natives = DamagedFile::Natives(dir.join("natives"));
+		assert!(!natives.is_repaired());
+		std::fs::create_dir_all(dir.join("natives")).unwrap();
+		assert!(!natives.is_repaired());
+		std::fs::write(dir.join("natives").join("liblwjgl.so"), "").unwrap();
+		assert!(natives.is_repaired());
+	}
+}
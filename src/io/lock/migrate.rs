@@ -0,0 +1,61 @@
+use anyhow::{bail, Context};
+use serde_json::Value;
+
+/// The current version of the lockfile format
+pub const LOCKFILE_VERSION: u32 = 1;
+
+/// Migrate the raw contents of a lockfile to the current format. Lockfiles from newer
+/// versions are left as they are
+pub fn migrate(mut contents: Value) -> anyhow::Result<Value> {
+	let Some(obj) = contents.as_object_mut() else {
+		bail!("Lockfile is not an object");
+	};
+	// Lockfiles from before the format was versioned don't have a version field
+	let version = match obj.get("version") {
+		Some(version) => version
+			.as_u64()
+			.context("Lockfile version is not a number")?
+			.try_into()
+			.context("Lockfile version is too large")?,
+		None => 0,
+	};
+
+	if version < 1 {
+		migrate_v0(&mut contents);
+	}
+
+	Ok(contents)
+}
+
+/// Migrate from the unversioned format, where addons could use `name` instead of `id`
+/// and didn't always store their file name
+fn migrate_v0(contents: &mut Value) {
+	let Some(obj) = contents.as_object_mut() else {
+		return;
+	};
+	obj.insert("version".into(), 1.into());
+
+	let Some(packages) = obj.get_mut("packages").and_then(Value::as_object_mut) else {
+		return;
+	};
+	let addons = packages
+		.values_mut()
+		.filter_map(Value::as_object_mut)
+		.flat_map(|instance| instance.values_mut())
+		.filter_map(|package| package.get_mut("addons"))
+		.filter_map(Value::as_array_mut)
+		.flatten()
+		.filter_map(Value::as_object_mut);
+	for addon in addons {
+		if !addon.contains_key("id") {
+			if let Some(name) = addon.remove("name") {
+				addon.insert("id".into(), name);
+			}
+		}
+		if addon.get("file_name").map_or(true, Value::is_null) {
+			if let Some(id) = addon.get("id").cloned() {
+				addon.insert("file_name".into(), id);
+			}
+		}
+	}
+}
@@ -0,0 +1,1890 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+use itertools::Itertools;
+use mcvm_core::io::{json_from_file, json_to_file_pretty};
+use mcvm_shared::output::{MCVMOutput, MessageContents};
+use mcvm_shared::translate;
+use mcvm_shared::util::{utc_timestamp, DefaultExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use mcvm_pkg::resolve::InstallReason;
+use mcvm_shared::addon::{Addon, AddonKind};
+use mcvm_shared::pkg::{PackageAddonOptionalHashes, PackageID};
+
+use super::paths::Paths;
+use crate::addon::AddonLinkMode;
+use crate::instance::ignore::InstanceIgnore;
+
+/// Migrations from older formats of the lockfile
+mod migrate;
+
+pub use migrate::LOCKFILE_VERSION;
+
+/// A file that remembers important info like what files and packages are currently installed
+#[derive(Debug)]
+pub struct Lockfile {
+	contents: LockfileContents,
+	/// Whether changes to resolved versions are refused
+	frozen: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+struct LockfileContents {
+	/// The version of the lockfile format. This only changes when the format changes in a way that
+	/// older versions of mcvm can't read, as fields that they don't know about are kept as they are
+	version: u32,
+	packages: HashMap<String, HashMap<String, LockfilePackage>>,
+	instances: HashMap<String, LockfileInstance>,
+	/// Instances that have done their first update
+	created_instances: HashSet<String>,
+	/// The newest builds of server JARs like Paper that were found, keyed by project and Minecraft version
+	#[serde(skip_serializing_if = "HashMap::is_empty")]
+	server_builds: HashMap<String, LockfileServerBuild>,
+	/// Fields from newer versions of mcvm, which are kept when the lockfile is written again
+	#[serde(flatten)]
+	extra: Map<String, Value>,
+}
+
+impl Default for LockfileContents {
+	fn default() -> Self {
+		Self {
+			version: LOCKFILE_VERSION,
+			packages: HashMap::new(),
+			instances: HashMap::new(),
+			created_instances: HashSet::new(),
+			server_builds: HashMap::new(),
+			extra: Map::new(),
+		}
+	}
+}
+
+/// The newest build of a server JAR that was found for a Minecraft version
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockfileServerBuild {
+	/// The build number
+	pub build: u16,
+	/// The timestamp of when the build was checked
+	pub checked: u64,
+}
+
+/// The maximum number of updates remembered for each instance
+pub const MAX_UPDATE_HISTORY: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+struct LockfileInstance {
+	version: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	paper_build: Option<u16>,
+	/// The resolved Fabric or Quilt loader version
+	#[serde(skip_serializing_if = "Option::is_none")]
+	loader_version: Option<String>,
+	/// The version of Sponge that the server JAR was downloaded for
+	#[serde(skip_serializing_if = "Option::is_none")]
+	sponge_version: Option<String>,
+	/// Journal of previous successful updates, oldest first
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	history: Vec<LockfileUpdateRecord>,
+	/// Files in the instance directory that belong to its Minecraft version, relative to the
+	/// instance directory. These are removed when the version changes
+	#[serde(skip_serializing_if = "Option::is_none")]
+	version_files: Option<Vec<String>>,
+	/// Fields from newer versions of mcvm
+	#[serde(flatten)]
+	extra: Map<String, Value>,
+}
+
+/// The state of an instance after a successful update, used for rolling back
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct LockfileUpdateRecord {
+	/// The timestamp of the update
+	pub date: u64,
+	/// The Minecraft version of the instance
+	pub version: String,
+	/// The Paper build of the instance
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub paper_build: Option<u16>,
+	/// Map of package IDs to maps of their addon IDs and versions
+	pub packages: HashMap<String, HashMap<String, String>>,
+	/// Fields from newer versions of mcvm
+	#[serde(flatten)]
+	extra: Map<String, Value>,
+}
+
+impl LockfileUpdateRecord {
+	/// Check whether two records describe the same instance state, ignoring the date
+	fn same_state(&self, other: &Self) -> bool {
+		self.version == other.version
+			&& self.paper_build == other.paper_build
+			&& self.packages == other.packages
+	}
+}
+
+/// Package stored in the lockfile
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LockfilePackage {
+	addons: Vec<LockfileAddon>,
+	/// Why the package was installed. Packages installed by older versions of mcvm don't have one
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	reason: Option<InstallReason>,
+	/// The packages that required this one
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	required_by: Vec<PackageID>,
+	/// Whether the package was loaded from a local file instead of a remote repository
+	#[serde(default)]
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	local: bool,
+	/// Config files that the package created in the instance. Files that already existed
+	/// and were only changed by the package are not included, so that they aren't removed with it
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	config_files: Vec<String>,
+	/// Fields from newer versions of mcvm
+	#[serde(flatten)]
+	extra: Map<String, Value>,
+}
+
+/// Format for an addon in the lockfile
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct LockfileAddon {
+	id: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	file_name: Option<String>,
+	files: Vec<String>,
+	kind: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	version: Option<String>,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "PackageAddonOptionalHashes::is_empty")]
+	hashes: PackageAddonOptionalHashes,
+	/// Whether the addon files were hard linked or copied into the instance
+	#[serde(default)]
+	#[serde(skip_serializing_if = "DefaultExt::is_default")]
+	link_mode: AddonLinkMode,
+	/// Where the addon files came from. Addons installed by older versions of mcvm don't have one
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	provenance: Option<AddonProvenance>,
+	/// Fields from newer versions of mcvm
+	#[serde(flatten)]
+	extra: Map<String, Value>,
+}
+
+/// Where the files of an addon came from, recorded when they are put into an instance
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct AddonProvenance {
+	/// The URL that the addon was downloaded from
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub url: Option<String>,
+	/// The local path that the addon was copied from
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub path: Option<String>,
+	/// The SHA-256 hash of the addon file, as a hex string
+	pub sha256: String,
+	/// The timestamp of when this version of the addon was first installed
+	pub installed: u64,
+}
+
+impl LockfileAddon {
+	/// Converts an addon to the format used by the lockfile.
+	/// Paths is the list of paths for the addon in the instance
+	pub fn from_addon(addon: &Addon, paths: Vec<PathBuf>) -> Self {
+		Self {
+			id: addon.id.clone(),
+			file_name: Some(addon.file_name.clone()),
+			files: paths
+				.iter()
+				.map(|x| {
+					x.to_str()
+						.expect("Failed to convert addon path to a string")
+						.to_owned()
+				})
+				.collect(),
+			kind: addon.kind.to_string(),
+			version: addon.version.clone(),
+			hashes: addon.hashes.clone(),
+			link_mode: AddonLinkMode::default(),
+			provenance: None,
+			extra: Map::new(),
+		}
+	}
+
+	/// Converts this LockfileAddon to an Addon
+	pub fn to_addon(&self, pkg_id: PackageID) -> anyhow::Result<Addon> {
+		Ok(Addon {
+			kind: AddonKind::parse_from_str(&self.kind)
+				.ok_or(anyhow!("Invalid addon kind '{}'", self.kind))?,
+			id: self.id.clone(),
+			file_name: self
+				.file_name
+				.clone()
+				.expect("Filename should have been filled in or fixed"),
+			pkg_id,
+			version: self.version.clone(),
+			hashes: self.hashes.clone(),
+		})
+	}
+
+	/// Remove this addon
+	pub fn remove(&self) -> anyhow::Result<()> {
+		for file in self.files.iter() {
+			let path = PathBuf::from(file);
+			if path.exists() {
+				fs::remove_file(path).context("Failed to remove addon")?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// A package that is installed on an instance in the lockfile
+#[derive(Debug, Clone)]
+pub struct InstalledPackage {
+	/// The ID of the package
+	pub id: PackageID,
+	/// Why the package was installed, if it is known
+	pub reason: Option<InstallReason>,
+	/// The packages that required this one
+	pub required_by: Vec<PackageID>,
+	/// Whether the package was loaded from a local file
+	pub local: bool,
+}
+
+/// An addon that is installed on an instance in the lockfile, along with its files
+#[derive(Debug, Clone)]
+pub struct InstalledAddon {
+	/// The addon
+	pub addon: Addon,
+	/// The paths of the addon files in the instance
+	pub files: Vec<PathBuf>,
+	/// How the files were put into the instance
+	pub link_mode: AddonLinkMode,
+	/// Where the files came from, if it was recorded
+	pub provenance: Option<AddonProvenance>,
+}
+
+/// What put a file into an instance
+#[derive(Debug, Clone)]
+pub enum FileOwner {
+	/// The file belongs to an addon of a package
+	Addon {
+		/// The package that installed the addon
+		package: PackageID,
+		/// The addon
+		addon: Box<InstalledAddon>,
+	},
+	/// The file is a config file that a package created
+	ConfigFile {
+		/// The package that created the file
+		package: PackageID,
+	},
+}
+
+/// A change to the resolved versions of an instance compared to what the lockfile records
+#[derive(Debug, Clone, PartialEq)]
+pub enum LockfileChange {
+	/// The Minecraft version changed
+	Version {
+		/// The recorded version
+		old: String,
+		/// The newly resolved version
+		new: String,
+	},
+	/// The Paper build changed
+	PaperBuild {
+		/// The recorded build, or None if no build was recorded
+		old: Option<u16>,
+		/// The newly resolved build, or None if the instance no longer uses Paper
+		new: Option<u16>,
+	},
+	/// The Fabric or Quilt loader version changed
+	LoaderVersion {
+		/// The recorded loader version
+		old: String,
+		/// The newly resolved loader version
+		new: String,
+	},
+	/// The Sponge version changed
+	SpongeVersion {
+		/// The recorded Sponge version
+		old: String,
+		/// The newly resolved Sponge version
+		new: String,
+	},
+	/// A package was added
+	AddedPackage(String),
+	/// A package was removed
+	RemovedPackage(String),
+	/// An addon of a package was added, removed, or changed version
+	Addon {
+		/// The package of the addon
+		package: String,
+		/// The ID of the addon
+		addon: String,
+		/// The recorded version, or None if the addon is new
+		old: Option<String>,
+		/// The newly resolved version, or None if the addon was removed
+		new: Option<String>,
+	},
+}
+
+impl std::fmt::Display for LockfileChange {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Version { old, new } => write!(f, "~ minecraft {old} -> {new}"),
+			Self::PaperBuild { old, new } => {
+				let format_build =
+					|x: &Option<u16>| x.map_or("none".to_string(), |x| x.to_string());
+				write!(
+					f,
+					"~ paper build {} -> {}",
+					format_build(old),
+					format_build(new)
+				)
+			}
+			Self::LoaderVersion { old, new } => write!(f, "~ loader {old} -> {new}"),
+			Self::SpongeVersion { old, new } => write!(f, "~ sponge {old} -> {new}"),
+			Self::AddedPackage(id) => write!(f, "+ package {id}"),
+			Self::RemovedPackage(id) => write!(f, "- package {id}"),
+			Self::Addon {
+				package,
+				addon,
+				old,
+				new,
+			} => {
+				let format_version = |x: &Option<String>| x.as_deref().unwrap_or("*").to_string();
+				match (old, new) {
+					(None, _) => write!(f, "+ addon {package}:{addon} {}", format_version(new)),
+					(_, None) => write!(f, "- addon {package}:{addon} {}", format_version(old)),
+					_ => write!(
+						f,
+						"~ addon {package}:{addon} {} -> {}",
+						format_version(old),
+						format_version(new)
+					),
+				}
+			}
+		}
+	}
+}
+
+impl Lockfile {
+	/// Open the lockfile
+	pub fn open(paths: &Paths) -> anyhow::Result<Self> {
+		let path = Self::get_path(paths);
+		let contents = if path.exists() {
+			let contents: Value = json_from_file(path).context("Failed to open lockfile")?;
+			Self::parse(contents)?
+		} else {
+			LockfileContents::default()
+		};
+		Ok(Self {
+			contents,
+			frozen: false,
+		})
+	}
+
+	/// Parse the contents of a lockfile, migrating them from older formats
+	fn parse(contents: Value) -> anyhow::Result<LockfileContents> {
+		let contents = migrate::migrate(contents).context("Failed to migrate lockfile")?;
+		serde_json::from_value(contents).context("Failed to parse lockfile")
+	}
+
+	/// Check whether the lockfile was written by a newer version of mcvm with a format that this one
+	/// doesn't understand. These lockfiles can be read, but not written
+	pub fn is_from_newer_version(&self) -> bool {
+		self.contents.version > LOCKFILE_VERSION
+	}
+
+	/// Get the path to the lockfile
+	pub fn get_path(paths: &Paths) -> PathBuf {
+		paths.internal.join("lock.json")
+	}
+
+	/// Set whether the lockfile is frozen. A frozen lockfile refuses any changes to
+	/// the resolved Minecraft version, Paper build, and package versions of instances
+	pub fn set_frozen(&mut self, frozen: bool) {
+		self.frozen = frozen;
+	}
+
+	/// Check whether the lockfile is frozen
+	pub fn is_frozen(&self) -> bool {
+		self.frozen
+	}
+
+	/// Fail with a diff of the changes to an instance if the lockfile is frozen
+	pub fn ensure_unchanged(
+		&self,
+		instance: &str,
+		changes: &[LockfileChange],
+	) -> anyhow::Result<()> {
+		if !self.frozen || changes.is_empty() {
+			return Ok(());
+		}
+
+		let diff = changes.iter().map(|x| format!("\t{x}")).join("\n");
+		bail!("The lockfile is frozen, but updating instance '{instance}' would change it:\n{diff}\nRun without --frozen to allow these changes");
+	}
+
+	/// Get the changes to the Minecraft version, Paper build, and modloader version of an instance compared to the lockfile.
+	/// Instances that are not in the lockfile yet have nothing to compare to and are never changed
+	pub fn get_instance_changes(
+		&self,
+		instance: &str,
+		version: &str,
+		paper_build: Option<u16>,
+		loader_version: Option<&str>,
+		sponge_version: Option<&str>,
+	) -> Vec<LockfileChange> {
+		let mut out = Vec::new();
+		let Some(instance) = self.contents.instances.get(instance) else {
+			return out;
+		};
+		if instance.version != version {
+			out.push(LockfileChange::Version {
+				old: instance.version.clone(),
+				new: version.to_string(),
+			});
+		}
+		if instance.paper_build != paper_build {
+			out.push(LockfileChange::PaperBuild {
+				old: instance.paper_build,
+				new: paper_build,
+			});
+		}
+		if let (Some(old), Some(new)) = (&instance.loader_version, loader_version) {
+			if old != new {
+				out.push(LockfileChange::LoaderVersion {
+					old: old.clone(),
+					new: new.to_string(),
+				});
+			}
+		}
+		if let (Some(old), Some(new)) = (&instance.sponge_version, sponge_version) {
+			if old != new {
+				out.push(LockfileChange::SpongeVersion {
+					old: old.clone(),
+					new: new.to_string(),
+				});
+			}
+		}
+
+		out
+	}
+
+	/// Get the changes to the packages of an instance compared to the lockfile, given the
+	/// newly resolved packages and the IDs and versions of their addons
+	pub fn get_package_changes(
+		&self,
+		instance: &str,
+		packages: &HashMap<String, Vec<(String, Option<String>)>>,
+	) -> Vec<LockfileChange> {
+		let mut out = Vec::new();
+		if !self.contents.instances.contains_key(instance) {
+			return out;
+		}
+		let empty = HashMap::new();
+		let current = self.contents.packages.get(instance).unwrap_or(&empty);
+
+		for id in current.keys().sorted() {
+			if !packages.contains_key(id) {
+				out.push(LockfileChange::RemovedPackage(id.clone()));
+			}
+		}
+		for (id, addons) in packages.iter().sorted_by_key(|x| x.0) {
+			let Some(current) = current.get(id) else {
+				out.push(LockfileChange::AddedPackage(id.clone()));
+				continue;
+			};
+			for old in &current.addons {
+				if !addons.iter().any(|(addon, ..)| addon == &old.id) {
+					out.push(LockfileChange::Addon {
+						package: id.clone(),
+						addon: old.id.clone(),
+						old: old.version.clone(),
+						new: None,
+					});
+				}
+			}
+			for (addon, version) in addons {
+				let old = current.addons.iter().find(|x| &x.id == addon);
+				match old {
+					Some(old) if &old.version == version => {}
+					Some(old) => out.push(LockfileChange::Addon {
+						package: id.clone(),
+						addon: addon.clone(),
+						old: old.version.clone(),
+						new: version.clone(),
+					}),
+					None => out.push(LockfileChange::Addon {
+						package: id.clone(),
+						addon: addon.clone(),
+						old: None,
+						new: version.clone(),
+					}),
+				}
+			}
+		}
+
+		out
+	}
+
+	/// Finish using the lockfile and write to the disk
+	pub fn finish(&mut self, paths: &Paths) -> anyhow::Result<()> {
+		if self.is_from_newer_version() {
+			bail!(
+				"The lockfile uses format version {}, which is newer than the version {LOCKFILE_VERSION} that this version of mcvm supports. Update mcvm to make changes to it",
+				self.contents.version
+			);
+		}
+		// Write to a temporary file first so that an interrupted write never leaves a broken lockfile
+		let path = Self::get_path(paths);
+		let tmp_path = path.with_extension("json.tmp");
+		json_to_file_pretty(&tmp_path, &self.contents).context("Failed to write to lockfile")?;
+		fs::rename(&tmp_path, &path).context("Failed to replace lockfile")?;
+
+		Ok(())
+	}
+
+	/// Updates a package with a new version. New files that are ignored in the instance
+	/// won't be overwritten, so they don't need confirmation.
+	/// Returns a list of addon files to be removed
+	pub fn update_package(
+		&mut self,
+		id: &str,
+		instance: &str,
+		addons: &[LockfileAddon],
+		ignore: &InstanceIgnore,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<Vec<PathBuf>> {
+		let mut files_to_remove = Vec::new();
+		let mut new_files = Vec::new();
+		if let Some(instance) = self.contents.packages.get_mut(instance) {
+			if let Some(pkg) = instance.get_mut(id) {
+				let mut indices = Vec::new();
+				// Check for addons that need to be removed
+				for (i, current) in pkg.addons.iter().enumerate() {
+					if !addons.iter().any(|x| x.id == current.id) {
+						indices.push(i);
+						files_to_remove.extend(current.files.iter().map(PathBuf::from));
+					}
+				}
+				for i in indices.into_iter().rev() {
+					pkg.addons.remove(i);
+				}
+				// Check for addons that need to be updated
+				for requested in addons {
+					if let Some(current) = pkg.addons.iter().find(|x| x.id == requested.id) {
+						files_to_remove.extend(
+							current
+								.files
+								.iter()
+								.filter(|x| !requested.files.contains(x))
+								.map(PathBuf::from),
+						);
+						new_files.extend(
+							requested
+								.files
+								.iter()
+								.filter(|x| !current.files.contains(x))
+								.cloned(),
+						);
+					} else {
+						new_files.extend(requested.files.clone());
+					};
+				}
+
+				// Keep fields from newer versions on the addons that are still installed,
+				// and where they came from if their files haven't changed
+				let mut new_addons = addons.to_vec();
+				for addon in &mut new_addons {
+					if let Some(current) = pkg.addons.iter().find(|x| x.id == addon.id) {
+						addon.extra = current.extra.clone();
+						if current.version == addon.version && current.files == addon.files {
+							addon.provenance = current.provenance.clone();
+						}
+					}
+				}
+				pkg.addons = new_addons;
+			} else {
+				instance.insert(
+					id.to_owned(),
+					LockfilePackage {
+						addons: addons.to_vec(),
+						reason: None,
+						required_by: Vec::new(),
+						local: false,
+						config_files: Vec::new(),
+						extra: Map::new(),
+					},
+				);
+				new_files.extend(addons.iter().flat_map(|x| x.files.clone()));
+			}
+		} else {
+			self.contents
+				.packages
+				.insert(instance.to_owned(), HashMap::new());
+			self.update_package(id, instance, addons, ignore, o)?;
+		}
+
+		for file in &new_files {
+			let path = PathBuf::from(file);
+			if path.exists() && !ignore.is_ignored(&path) {
+				let allow = o
+					.prompt_yes_no(
+						false,
+						MessageContents::Warning(translate!(
+							o,
+							OverwriteAddonFilePrompt,
+							"file" = file
+						)),
+					)
+					.context("Prompt failed")?;
+
+				if !allow {
+					bail!("File '{file}' would be overwritten by an addon");
+				}
+			}
+		}
+
+		Ok(files_to_remove)
+	}
+
+	/// Records why a package that is installed on an instance was installed
+	pub fn set_package_reason(
+		&mut self,
+		instance: &str,
+		package: &str,
+		reason: InstallReason,
+		required_by: Vec<PackageID>,
+		local: bool,
+	) {
+		let package = self
+			.contents
+			.packages
+			.get_mut(instance)
+			.and_then(|x| x.get_mut(package));
+		if let Some(package) = package {
+			package.reason = Some(reason);
+			package.required_by = required_by;
+			package.local = local;
+		}
+	}
+
+	/// Gets all of the packages that are installed on an instance
+	pub fn get_instance_packages(&self, instance: &str) -> Vec<InstalledPackage> {
+		let Some(packages) = self.contents.packages.get(instance) else {
+			return Vec::new();
+		};
+
+		packages
+			.iter()
+			.map(|(id, pkg)| InstalledPackage {
+				id: PackageID::from(id.clone()),
+				reason: pkg.reason,
+				required_by: pkg.required_by.clone(),
+				local: pkg.local,
+			})
+			.sorted_by(|a, b| a.id.cmp(&b.id))
+			.collect()
+	}
+
+	/// Gets a copy of the entry for a package on an instance, so that it can be restored
+	/// if installing a new version of the package fails
+	pub fn get_package_entry(&self, instance: &str, package: &str) -> Option<LockfilePackage> {
+		self.contents.packages.get(instance)?.get(package).cloned()
+	}
+
+	/// Restores an entry taken with `get_package_entry`. If the package wasn't installed
+	/// before, it is removed from the instance instead
+	pub fn restore_package_entry(
+		&mut self,
+		instance: &str,
+		package: &str,
+		entry: Option<LockfilePackage>,
+	) {
+		if let Some(entry) = entry {
+			self.contents
+				.packages
+				.entry(instance.into())
+				.or_default()
+				.insert(package.into(), entry);
+		} else if let Some(packages) = self.contents.packages.get_mut(instance) {
+			packages.remove(package);
+		}
+	}
+
+	/// Remove a package from an instance. Returns the addon files that need to be removed
+	pub fn remove_package(&mut self, instance: &str, package: &str) -> Vec<PathBuf> {
+		let removed = self
+			.contents
+			.packages
+			.get_mut(instance)
+			.and_then(|x| x.remove(package));
+		let Some(removed) = removed else {
+			return Vec::new();
+		};
+
+		removed
+			.addons
+			.iter()
+			.flat_map(|x| x.files.iter())
+			.chain(removed.config_files.iter())
+			.map(PathBuf::from)
+			.collect()
+	}
+
+	/// Gets the paths of all the addon files that have been installed on an instance
+	pub fn get_instance_addon_files(&self, instance: &str) -> HashSet<PathBuf> {
+		let Some(packages) = self.contents.packages.get(instance) else {
+			return HashSet::new();
+		};
+
+		packages
+			.values()
+			.flat_map(|x| x.addons.iter())
+			.flat_map(|x| x.files.iter().map(PathBuf::from))
+			.collect()
+	}
+
+	/// Gets the config files that a package created in an instance
+	pub fn get_package_config_files(&self, instance: &str, package: &str) -> Vec<PathBuf> {
+		self.contents
+			.packages
+			.get(instance)
+			.and_then(|x| x.get(package))
+			.map(|x| x.config_files.iter().map(PathBuf::from).collect())
+			.unwrap_or_default()
+	}
+
+	/// Records the config files that a package created in an instance.
+	/// Returns the files it created before that it doesn't anymore, which need to be removed
+	pub fn update_package_config_files(
+		&mut self,
+		instance: &str,
+		package: &str,
+		files: &[PathBuf],
+	) -> Vec<PathBuf> {
+		let package = self
+			.contents
+			.packages
+			.get_mut(instance)
+			.and_then(|x| x.get_mut(package));
+		let Some(package) = package else {
+			return Vec::new();
+		};
+
+		let files: Vec<_> = files
+			.iter()
+			.map(|x| x.to_string_lossy().to_string())
+			.collect();
+		let old = std::mem::replace(&mut package.config_files, files);
+		old.into_iter()
+			.filter(|x| !package.config_files.contains(x))
+			.map(PathBuf::from)
+			.collect()
+	}
+
+	/// Gets all of the addons that have been installed on an instance
+	pub fn get_instance_addons(&self, instance: &str) -> anyhow::Result<Vec<Addon>> {
+		let Some(packages) = self.contents.packages.get(instance) else {
+			return Ok(Vec::new());
+		};
+
+		packages
+			.iter()
+			.flat_map(|(pkg_id, pkg)| pkg.addons.iter().map(move |x| (pkg_id, x)))
+			.map(|(pkg_id, addon)| addon.to_addon(PackageID::from(pkg_id.clone())))
+			.collect()
+	}
+
+	/// Gets all of the addons that have been installed on an instance, along with their files
+	pub fn get_instance_installed_addons(
+		&self,
+		instance: &str,
+	) -> anyhow::Result<Vec<InstalledAddon>> {
+		let Some(packages) = self.contents.packages.get(instance) else {
+			return Ok(Vec::new());
+		};
+
+		packages
+			.iter()
+			.flat_map(|(pkg_id, pkg)| pkg.addons.iter().map(move |x| (pkg_id, x)))
+			.map(|(pkg_id, addon)| {
+				Ok(InstalledAddon {
+					addon: addon.to_addon(PackageID::from(pkg_id.clone()))?,
+					files: addon.files.iter().map(PathBuf::from).collect(),
+					link_mode: addon.link_mode,
+					provenance: addon.provenance.clone(),
+				})
+			})
+			.collect()
+	}
+
+	/// Gets how the files of an addon were put into an instance when it was last installed
+	pub fn get_addon_link_mode(
+		&self,
+		instance: &str,
+		package: &str,
+		addon: &str,
+	) -> Option<AddonLinkMode> {
+		self.contents
+			.packages
+			.get(instance)?
+			.get(package)?
+			.addons
+			.iter()
+			.find(|x| x.id == addon)
+			.map(|x| x.link_mode)
+	}
+
+	/// Records how the files of an installed addon were put into an instance
+	pub fn set_addon_link_mode(
+		&mut self,
+		instance: &str,
+		package: &str,
+		addon: &str,
+		link_mode: AddonLinkMode,
+	) {
+		let addon = self
+			.contents
+			.packages
+			.get_mut(instance)
+			.and_then(|x| x.get_mut(package))
+			.and_then(|x| x.addons.iter_mut().find(|x| x.id == addon));
+		if let Some(addon) = addon {
+			addon.link_mode = link_mode;
+		}
+	}
+
+	/// Gets where the files of an installed addon came from
+	pub fn get_addon_provenance(
+		&self,
+		instance: &str,
+		package: &str,
+		addon: &str,
+	) -> Option<&AddonProvenance> {
+		self.contents
+			.packages
+			.get(instance)?
+			.get(package)?
+			.addons
+			.iter()
+			.find(|x| x.id == addon)?
+			.provenance
+			.as_ref()
+	}
+
+	/// Records how the files of an addon were put into an instance and where they came from.
+	/// This should be done whenever the files are linked or copied
+	pub fn record_addon_install(
+		&mut self,
+		instance: &str,
+		package: &str,
+		addon: &str,
+		link_mode: AddonLinkMode,
+		provenance: AddonProvenance,
+	) {
+		let addon = self
+			.contents
+			.packages
+			.get_mut(instance)
+			.and_then(|x| x.get_mut(package))
+			.and_then(|x| x.addons.iter_mut().find(|x| x.id == addon));
+		if let Some(addon) = addon {
+			addon.link_mode = link_mode;
+			addon.provenance = Some(provenance);
+		}
+	}
+
+	/// Finds the package that put a file into an instance. Returns None if the file isn't managed by mcvm
+	pub fn get_file_owner(&self, instance: &str, path: &Path) -> anyhow::Result<Option<FileOwner>> {
+		let Some(packages) = self.contents.packages.get(instance) else {
+			return Ok(None);
+		};
+
+		for (pkg_id, pkg) in packages.iter().sorted_by_key(|x| x.0) {
+			let package = PackageID::from(pkg_id.clone());
+			for addon in &pkg.addons {
+				if addon.files.iter().any(|x| Path::new(x) == path) {
+					let addon = Box::new(InstalledAddon {
+						addon: addon.to_addon(package.clone())?,
+						files: addon.files.iter().map(PathBuf::from).collect(),
+						link_mode: addon.link_mode,
+						provenance: addon.provenance.clone(),
+					});
+					return Ok(Some(FileOwner::Addon { package, addon }));
+				}
+			}
+			if pkg.config_files.iter().any(|x| Path::new(x) == path) {
+				return Ok(Some(FileOwner::ConfigFile { package }));
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// Gets all of the files that a package put into an instance, including its addon files and config files
+	pub fn get_package_files(&self, instance: &str, package: &str) -> Vec<PathBuf> {
+		let Some(pkg) = self
+			.contents
+			.packages
+			.get(instance)
+			.and_then(|x| x.get(package))
+		else {
+			return Vec::new();
+		};
+
+		pkg.addons
+			.iter()
+			.flat_map(|x| x.files.iter())
+			.chain(pkg.config_files.iter())
+			.map(PathBuf::from)
+			.collect()
+	}
+
+	/// Remove any unused packages for an instance.
+	/// Returns any addon files that need to be removed from the instance.
+	pub fn remove_unused_packages(
+		&mut self,
+		instance: &str,
+		used_packages: &[PackageID],
+	) -> anyhow::Result<Vec<PathBuf>> {
+		if let Some(inst) = self.contents.packages.get_mut(instance) {
+			let mut pkgs_to_remove = Vec::new();
+			for (pkg, ..) in inst.iter() {
+				if !used_packages.contains(&PackageID::from(pkg.clone())) {
+					pkgs_to_remove.push(pkg.clone());
+				}
+			}
+
+			let mut files_to_remove = Vec::new();
+			for pkg_id in pkgs_to_remove {
+				if let Some(pkg) = inst.remove(&pkg_id) {
+					for addon in pkg.addons {
+						files_to_remove.extend(addon.files.iter().map(PathBuf::from));
+					}
+					files_to_remove.extend(pkg.config_files.iter().map(PathBuf::from));
+				}
+			}
+
+			Ok(files_to_remove)
+		} else {
+			Ok(vec![])
+		}
+	}
+
+	/// Remove all of the records for an instance. Returns the addon files that
+	/// were installed on the instance
+	pub fn remove_instance(&mut self, instance: &str) -> Vec<PathBuf> {
+		let files = self.get_instance_addon_files(instance);
+		self.contents.packages.remove(instance);
+		self.contents.instances.remove(instance);
+		self.contents.created_instances.remove(instance);
+
+		files.into_iter().collect()
+	}
+
+	/// Move all of the records for an instance to a new ID. Addon file paths in the old instance
+	/// directory are moved to the new one
+	pub fn rename_instance(&mut self, old: &str, new: &str, old_dir: &Path, new_dir: &Path) {
+		if let Some(mut packages) = self.contents.packages.remove(old) {
+			let files = packages.values_mut().flat_map(|x| {
+				x.addons
+					.iter_mut()
+					.flat_map(|x| x.files.iter_mut())
+					.chain(x.config_files.iter_mut())
+			});
+			for file in files {
+				if let Ok(relative) = Path::new(file).strip_prefix(old_dir) {
+					*file = new_dir.join(relative).to_string_lossy().to_string();
+				}
+			}
+			self.contents.packages.insert(new.to_owned(), packages);
+		}
+		if let Some(instance) = self.contents.instances.remove(old) {
+			self.contents.instances.insert(new.to_owned(), instance);
+		}
+		if self.contents.created_instances.remove(old) {
+			self.contents.created_instances.insert(new.to_owned());
+		}
+	}
+
+	/// Copy all of the records for an instance to a new instance whose directory is somewhere else.
+	/// Addon file paths are moved from the old instance directory to the new one, and files outside of it
+	/// are dropped so that the two instances never share files. Returns the addons of the new instance
+	pub fn clone_instance(
+		&mut self,
+		src: &str,
+		dest: &str,
+		src_dir: &Path,
+		dest_dir: &Path,
+	) -> anyhow::Result<Vec<InstalledAddon>> {
+		let mut out = Vec::new();
+		let mut packages = self.contents.packages.get(src).cloned().unwrap_or_default();
+		for (pkg_id, pkg) in &mut packages {
+			pkg.config_files = pkg
+				.config_files
+				.iter()
+				.filter_map(|x| Path::new(x).strip_prefix(src_dir).ok())
+				.map(|x| dest_dir.join(x).to_string_lossy().to_string())
+				.collect();
+			for addon in &mut pkg.addons {
+				addon.files = addon
+					.files
+					.iter()
+					.filter_map(|x| Path::new(x).strip_prefix(src_dir).ok())
+					.map(|x| dest_dir.join(x).to_string_lossy().to_string())
+					.collect();
+				out.push(InstalledAddon {
+					addon: addon.to_addon(PackageID::from(pkg_id.clone()))?,
+					files: addon.files.iter().map(PathBuf::from).collect(),
+					link_mode: addon.link_mode,
+					provenance: addon.provenance.clone(),
+				});
+			}
+		}
+		self.contents.packages.insert(dest.to_owned(), packages);
+
+		if let Some(instance) = self.contents.instances.get(src).cloned() {
+			self.contents.instances.insert(dest.to_owned(), instance);
+		}
+		if self.contents.created_instances.contains(src) {
+			self.contents.created_instances.insert(dest.to_owned());
+		}
+
+		Ok(out)
+	}
+
+	/// Gets the Minecraft version of an instance that was recorded in the lockfile
+	pub fn get_instance_version(&self, instance: &str) -> Option<&str> {
+		self.contents
+			.instances
+			.get(instance)
+			.map(|x| x.version.as_str())
+	}
+
+	/// Updates an instance in the lockfile. Returns true if the version has changed.
+	pub fn update_instance_version(&mut self, instance: &str, version: &str) -> bool {
+		if let Some(instance) = self.contents.instances.get_mut(instance) {
+			if instance.version == version {
+				false
+			} else {
+				instance.version = version.to_owned();
+				true
+			}
+		} else {
+			self.contents.instances.insert(
+				instance.to_owned(),
+				LockfileInstance {
+					version: version.to_owned(),
+					paper_build: None,
+					loader_version: None,
+					sponge_version: None,
+					history: Vec::new(),
+					version_files: None,
+					extra: Map::new(),
+				},
+			);
+
+			false
+		}
+	}
+
+	/// Gets the files of an instance that belong to its Minecraft version, relative to the instance directory.
+	/// Returns None if they were never recorded, such as for instances created by older versions of mcvm
+	pub fn get_instance_version_files(&self, instance: &str) -> Option<&[String]> {
+		self.contents
+			.instances
+			.get(instance)
+			.and_then(|x| x.version_files.as_deref())
+	}
+
+	/// Records the files of an instance that belong to its Minecraft version
+	pub fn update_instance_version_files(&mut self, instance: &str, files: Vec<String>) {
+		if let Some(instance) = self.contents.instances.get_mut(instance) {
+			instance.version_files = Some(files);
+		}
+	}
+
+	/// Updates an instance with a new Paper build. Returns true if the version has changed.
+	pub fn update_instance_paper_build(&mut self, instance: &str, build_num: u16) -> bool {
+		if let Some(instance) = self.contents.instances.get_mut(instance) {
+			if let Some(paper_build) = instance.paper_build.as_mut() {
+				if *paper_build == build_num {
+					false
+				} else {
+					*paper_build = build_num;
+					true
+				}
+			} else {
+				instance.paper_build = Some(build_num);
+				false
+			}
+		} else {
+			false
+		}
+	}
+
+	/// Gets the Paper or Folia build of an instance that was recorded in the lockfile
+	pub fn get_instance_paper_build(&self, instance: &str) -> Option<u16> {
+		self.contents
+			.instances
+			.get(instance)
+			.and_then(|x| x.paper_build)
+	}
+
+	/// Gets the newest build of a server JAR project for a Minecraft version that was found
+	pub fn get_server_build(&self, project: &str, version: &str) -> Option<LockfileServerBuild> {
+		self.contents
+			.server_builds
+			.get(&format!("{project}/{version}"))
+			.copied()
+	}
+
+	/// Records the newest build of a server JAR project for a Minecraft version, checked now
+	pub fn update_server_build(
+		&mut self,
+		project: &str,
+		version: &str,
+		build: u16,
+	) -> anyhow::Result<()> {
+		let build = LockfileServerBuild {
+			build,
+			checked: utc_timestamp()?,
+		};
+		self.contents
+			.server_builds
+			.insert(format!("{project}/{version}"), build);
+
+		Ok(())
+	}
+
+	/// Gets the Minecraft versions and server JAR builds that are used by instances or are the newest ones found,
+	/// so that stored JARs for any other builds can be removed
+	pub fn get_used_server_builds(&self) -> HashSet<(String, u16)> {
+		let instances = self.contents.instances.values().flat_map(|inst| {
+			let history = inst
+				.history
+				.iter()
+				.filter_map(|x| Some((x.version.clone(), x.paper_build?)));
+			inst.paper_build
+				.map(|build| (inst.version.clone(), build))
+				.into_iter()
+				.chain(history)
+		});
+		let newest = self.contents.server_builds.iter().filter_map(|(key, x)| {
+			let (_, version) = key.split_once('/')?;
+			Some((version.to_string(), x.build))
+		});
+
+		instances.chain(newest).collect()
+	}
+
+	/// Gets the Fabric or Quilt loader version of an instance that was recorded in the lockfile
+	pub fn get_instance_loader_version(&self, instance: &str) -> Option<&str> {
+		self.contents
+			.instances
+			.get(instance)
+			.and_then(|x| x.loader_version.as_deref())
+	}
+
+	/// Updates the Fabric or Quilt loader version of an instance, or removes it if the instance
+	/// no longer uses one. Returns true if the version has changed.
+	pub fn update_instance_loader_version(
+		&mut self,
+		instance: &str,
+		loader_version: Option<&str>,
+	) -> bool {
+		let Some(instance) = self.contents.instances.get_mut(instance) else {
+			return false;
+		};
+		if instance.loader_version.as_deref() == loader_version {
+			return false;
+		}
+		let changed = instance.loader_version.is_some() && loader_version.is_some();
+		instance.loader_version = loader_version.map(String::from);
+		changed
+	}
+
+	/// Updates the Sponge version of an instance, or removes it if the instance
+	/// no longer uses Sponge. Returns true if the version has changed.
+	pub fn update_instance_sponge_version(
+		&mut self,
+		instance: &str,
+		sponge_version: Option<&str>,
+	) -> bool {
+		let Some(instance) = self.contents.instances.get_mut(instance) else {
+			return false;
+		};
+		if instance.sponge_version.as_deref() == sponge_version {
+			return false;
+		}
+		let changed = instance.sponge_version.is_some() && sponge_version.is_some();
+		instance.sponge_version = sponge_version.map(String::from);
+		changed
+	}
+
+	/// Get the current state of an instance in the same form as its update history.
+	/// The date of the record is not set
+	pub fn get_instance_state(&self, instance: &str) -> Option<LockfileUpdateRecord> {
+		let inst = self.contents.instances.get(instance)?;
+		let packages = self
+			.contents
+			.packages
+			.get(instance)
+			.map(|packages| {
+				packages
+					.iter()
+					.map(|(id, pkg)| {
+						let addons = pkg
+							.addons
+							.iter()
+							.filter_map(|x| Some((x.id.clone(), x.version.clone()?)))
+							.collect();
+						(id.clone(), addons)
+					})
+					.collect()
+			})
+			.unwrap_or_default();
+
+		Some(LockfileUpdateRecord {
+			date: 0,
+			version: inst.version.clone(),
+			paper_build: inst.paper_build,
+			packages,
+			extra: Map::new(),
+		})
+	}
+
+	/// Record the current state of an instance in its update history
+	pub fn record_instance_update(&mut self, instance: &str) -> anyhow::Result<()> {
+		let Some(mut record) = self.get_instance_state(instance) else {
+			return Ok(());
+		};
+		record.date = utc_timestamp()?;
+		let Some(inst) = self.contents.instances.get_mut(instance) else {
+			return Ok(());
+		};
+
+		if inst
+			.history
+			.last()
+			.is_some_and(|last| last.same_state(&record))
+		{
+			return Ok(());
+		}
+		inst.history.push(record);
+		if inst.history.len() > MAX_UPDATE_HISTORY {
+			let excess = inst.history.len() - MAX_UPDATE_HISTORY;
+			inst.history.drain(0..excess);
+		}
+
+		Ok(())
+	}
+
+	/// Get the update history of an instance, oldest first
+	pub fn get_instance_history(&self, instance: &str) -> &[LockfileUpdateRecord] {
+		self.contents
+			.instances
+			.get(instance)
+			.map(|x| x.history.as_slice())
+			.unwrap_or_default()
+	}
+
+	/// Get the update record to roll an instance back to, which is the one before the most recent
+	pub fn get_rollback_record(&self, instance: &str) -> Option<&LockfileUpdateRecord> {
+		let history = self.get_instance_history(instance);
+		history.len().checked_sub(2).map(|i| &history[i])
+	}
+
+	/// Finish rolling back an instance. The update that it was rolled back from is removed,
+	/// along with any record of the rollback itself, so that the record it was rolled back to
+	/// is the latest one again. `history_len` is the length of the history before the rollback
+	pub fn finish_instance_rollback(&mut self, instance: &str, history_len: usize) {
+		if let Some(inst) = self.contents.instances.get_mut(instance) {
+			inst.history.truncate(history_len.saturating_sub(1));
+		}
+	}
+
+	/// Check whether an instance has done its first update successfully
+	pub fn has_instance_done_first_update(&mut self, instance: &str) -> bool {
+		self.contents.created_instances.contains(instance)
+	}
+
+	/// Update whether an instance has done its first update
+	pub fn update_instance_has_done_first_update(&mut self, instance: &str) {
+		self.contents.created_instances.insert(instance.to_string());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use mcvm_shared::output::NoOp;
+
+	use super::*;
+
+	#[test]
+	fn test_update_history() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: false,
+		};
+		lock.update_instance_version("foo", "1.19.2");
+		lock.record_instance_update("foo").unwrap();
+		// Updates that don't change anything shouldn't be recorded
+		lock.record_instance_update("foo").unwrap();
+		assert_eq!(lock.get_instance_history("foo").len(), 1);
+		assert!(lock.get_rollback_record("foo").is_none());
+
+		lock.update_instance_version("foo", "1.20.1");
+		lock.record_instance_update("foo").unwrap();
+		let record = lock.get_rollback_record("foo").unwrap();
+		assert_eq!(record.version, "1.19.2");
+
+		// Rolling back removes the newer update and the record of the rollback
+		let history_len = lock.get_instance_history("foo").len();
+		lock.update_instance_version("foo", "1.19.2");
+		lock.record_instance_update("foo").unwrap();
+		assert_eq!(lock.get_instance_history("foo").len(), 3);
+		lock.finish_instance_rollback("foo", history_len);
+		assert_eq!(lock.get_instance_history("foo").len(), 1);
+		assert_eq!(lock.get_instance_history("foo")[0].version, "1.19.2");
+		assert!(lock.get_rollback_record("foo").is_none());
+
+		for i in 0..(MAX_UPDATE_HISTORY * 2) {
+			lock.update_instance_version("foo", &i.to_string());
+			lock.record_instance_update("foo").unwrap();
+		}
+		assert_eq!(lock.get_instance_history("foo").len(), MAX_UPDATE_HISTORY);
+	}
+
+	#[test]
+	fn test_renamed_addon_files() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: false,
+		};
+		let addon = |file_name: &str| {
+			let addon = Addon {
+				kind: AddonKind::Mod,
+				id: "mod".into(),
+				file_name: file_name.into(),
+				pkg_id: PackageID::from("pkg"),
+				version: None,
+				hashes: PackageAddonOptionalHashes::default(),
+			};
+			LockfileAddon::from_addon(&addon, vec![PathBuf::from("mods").join(file_name)])
+		};
+
+		let removed = lock
+			.update_package(
+				"pkg",
+				"foo",
+				&[addon("mod-1.0.jar")],
+				&InstanceIgnore::default(),
+				&mut NoOp,
+			)
+			.unwrap();
+		assert!(removed.is_empty());
+
+		// The file from the old version should be removed when the file name changes
+		let removed = lock
+			.update_package(
+				"pkg",
+				"foo",
+				&[addon("mod-1.1.jar")],
+				&InstanceIgnore::default(),
+				&mut NoOp,
+			)
+			.unwrap();
+		assert_eq!(removed, vec![PathBuf::from("mods/mod-1.0.jar")]);
+		assert_eq!(
+			lock.get_instance_addon_files("foo"),
+			HashSet::from([PathBuf::from("mods/mod-1.1.jar")])
+		);
+
+		let removed = lock.remove_unused_packages("foo", &[]).unwrap();
+		assert_eq!(removed, vec![PathBuf::from("mods/mod-1.1.jar")]);
+		assert!(lock.get_instance_addon_files("foo").is_empty());
+	}
+
+	#[test]
+	fn test_addon_provenance_on_update() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: false,
+		};
+		let mods_dir = PathBuf::from("/instances/foo/mods");
+		let addon = |version: &str| {
+			let addon = Addon {
+				kind: AddonKind::Mod,
+				id: "sodium".into(),
+				file_name: format!("sodium-{version}.jar"),
+				pkg_id: PackageID::from("sodium"),
+				version: Some(version.into()),
+				hashes: PackageAddonOptionalHashes::default(),
+			};
+			LockfileAddon::from_addon(&addon, vec![mods_dir.join(&addon.file_name)])
+		};
+		let provenance = |version: &str| AddonProvenance {
+			url: Some(format!("example.com/sodium-{version}.jar")),
+			path: None,
+			sha256: version.into(),
+			installed: 1,
+		};
+		let get_provenance =
+			|lock: &Lockfile, path: &Path| match lock.get_file_owner("foo", path).unwrap() {
+				Some(FileOwner::Addon { package, addon }) => {
+					assert_eq!(&*package, "sodium");
+					addon.provenance
+				}
+				_ => panic!("File should belong to an addon"),
+			};
+		let update = |lock: &mut Lockfile, version: &str| {
+			lock.update_package(
+				"sodium",
+				"foo",
+				&[addon(version)],
+				&InstanceIgnore::default(),
+				&mut NoOp,
+			)
+			.unwrap()
+		};
+
+		update(&mut lock, "0.5.3");
+		lock.record_addon_install(
+			"foo",
+			"sodium",
+			"sodium",
+			AddonLinkMode::Hardlink,
+			provenance("0.5.3"),
+		);
+		let old_file = mods_dir.join("sodium-0.5.3.jar");
+		assert_eq!(get_provenance(&lock, &old_file), Some(provenance("0.5.3")));
+
+		// Updating to the same version keeps the record
+		update(&mut lock, "0.5.3");
+		assert_eq!(get_provenance(&lock, &old_file), Some(provenance("0.5.3")));
+
+		// The renamed file of a new version gets its own record, and the old file isn't managed anymore
+		let removed = update(&mut lock, "0.5.4");
+		assert_eq!(removed, vec![old_file.clone()]);
+		let new_file = mods_dir.join("sodium-0.5.4.jar");
+		assert!(lock.get_file_owner("foo", &old_file).unwrap().is_none());
+		assert_eq!(get_provenance(&lock, &new_file), None);
+		lock.record_addon_install(
+			"foo",
+			"sodium",
+			"sodium",
+			AddonLinkMode::Hardlink,
+			provenance("0.5.4"),
+		);
+		assert_eq!(get_provenance(&lock, &new_file), Some(provenance("0.5.4")));
+
+		// Renaming the instance moves the files along with their records
+		lock.rename_instance(
+			"foo",
+			"bar",
+			Path::new("/instances/foo"),
+			Path::new("/instances/bar"),
+		);
+		let owner = lock
+			.get_file_owner("bar", Path::new("/instances/bar/mods/sodium-0.5.4.jar"))
+			.unwrap();
+		assert!(matches!(
+			owner,
+			Some(FileOwner::Addon { addon, .. }) if addon.provenance == Some(provenance("0.5.4"))
+		));
+		assert_eq!(
+			lock.get_package_files("bar", "sodium"),
+			vec![PathBuf::from("/instances/bar/mods/sodium-0.5.4.jar")]
+		);
+	}
+
+	#[test]
+	fn test_clone_instance() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: false,
+		};
+		let addon = Addon {
+			kind: AddonKind::Mod,
+			id: "mod".into(),
+			file_name: "mod.jar".into(),
+			pkg_id: PackageID::from("pkg"),
+			version: Some("1".into()),
+			hashes: PackageAddonOptionalHashes::default(),
+		};
+		let files = vec![
+			PathBuf::from("/instances/foo/mods/mod.jar"),
+			PathBuf::from("/elsewhere/mod.jar"),
+		];
+		lock.update_package(
+			"pkg",
+			"foo",
+			&[LockfileAddon::from_addon(&addon, files)],
+			&InstanceIgnore::default(),
+			&mut NoOp,
+		)
+		.unwrap();
+		lock.update_instance_version("foo", "1.20.1");
+
+		let cloned = lock
+			.clone_instance(
+				"foo",
+				"bar",
+				Path::new("/instances/foo"),
+				Path::new("/instances/bar"),
+			)
+			.unwrap();
+		assert_eq!(cloned.len(), 1);
+		assert_eq!(
+			cloned[0].files,
+			vec![PathBuf::from("/instances/bar/mods/mod.jar")]
+		);
+		assert_eq!(lock.get_instance_version("bar"), Some("1.20.1"));
+		assert_eq!(
+			lock.get_instance_addon_files("bar"),
+			HashSet::from([PathBuf::from("/instances/bar/mods/mod.jar")])
+		);
+
+		// The original instance should be unaffected by removing the clone
+		lock.remove_instance("bar");
+		assert_eq!(lock.get_instance_addon_files("foo").len(), 2);
+	}
+
+	#[test]
+	fn test_frozen_lockfile() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: true,
+		};
+		// New instances have nothing to be frozen to
+		assert!(lock
+			.get_instance_changes("foo", "1.20.1", None, None, None)
+			.is_empty());
+		lock.update_instance_version("foo", "1.20.1");
+		lock.update_instance_paper_build("foo", 100);
+
+		let changes = lock.get_instance_changes("foo", "1.20.1", Some(100), None, None);
+		assert!(lock.ensure_unchanged("foo", &changes).is_ok());
+
+		let changes = lock.get_instance_changes("foo", "1.20.2", Some(120), None, None);
+		assert_eq!(
+			changes,
+			vec![
+				LockfileChange::Version {
+					old: "1.20.1".into(),
+					new: "1.20.2".into()
+				},
+				LockfileChange::PaperBuild {
+					old: Some(100),
+					new: Some(120)
+				},
+			]
+		);
+		let err = lock.ensure_unchanged("foo", &changes).unwrap_err();
+		assert!(err.to_string().contains("~ minecraft 1.20.1 -> 1.20.2"));
+
+		// Starting to use Paper is a change too
+		lock.update_instance_version("bar", "1.20.1");
+		let changes = lock.get_instance_changes("bar", "1.20.1", Some(100), None, None);
+		assert_eq!(
+			changes,
+			vec![LockfileChange::PaperBuild {
+				old: None,
+				new: Some(100)
+			}]
+		);
+		assert_eq!(changes[0].to_string(), "~ paper build none -> 100");
+		assert_eq!(lock.get_instance_version("foo"), Some("1.20.1"));
+
+		lock.set_frozen(false);
+		assert!(lock.ensure_unchanged("foo", &changes).is_ok());
+	}
+
+	#[test]
+	fn test_frozen_package_changes() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: true,
+		};
+		lock.update_instance_version("foo", "1.20.1");
+		let addon = Addon {
+			kind: AddonKind::Mod,
+			id: "mod".into(),
+			file_name: "mod.jar".into(),
+			pkg_id: PackageID::from("pkg"),
+			version: Some("1.0".into()),
+			hashes: PackageAddonOptionalHashes::default(),
+		};
+		let addon = LockfileAddon::from_addon(&addon, vec![PathBuf::from("mods/mod.jar")]);
+		lock.update_package(
+			"pkg",
+			"foo",
+			&[addon],
+			&InstanceIgnore::default(),
+			&mut NoOp,
+		)
+		.unwrap();
+
+		let same = HashMap::from([("pkg".into(), vec![("mod".into(), Some("1.0".into()))])]);
+		assert!(lock.get_package_changes("foo", &same).is_empty());
+
+		let bumped = HashMap::from([
+			("pkg".into(), vec![("mod".into(), Some("1.1".into()))]),
+			("new".into(), vec![]),
+		]);
+		let changes = lock.get_package_changes("foo", &bumped);
+		assert_eq!(
+			changes,
+			vec![
+				LockfileChange::AddedPackage("new".into()),
+				LockfileChange::Addon {
+					package: "pkg".into(),
+					addon: "mod".into(),
+					old: Some("1.0".into()),
+					new: Some("1.1".into()),
+				},
+			]
+		);
+		assert!(lock.ensure_unchanged("foo", &changes).is_err());
+
+		let changes = lock.get_package_changes("foo", &HashMap::new());
+		assert_eq!(changes, vec![LockfileChange::RemovedPackage("pkg".into())]);
+	}
+
+	#[test]
+	fn test_package_reasons() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: false,
+		};
+		let addon = Addon {
+			kind: AddonKind::Mod,
+			id: "mod".into(),
+			file_name: "mod.jar".into(),
+			pkg_id: PackageID::from("lib"),
+			version: None,
+			hashes: PackageAddonOptionalHashes::default(),
+		};
+		let addon = LockfileAddon::from_addon(&addon, vec![PathBuf::from("mods/mod.jar")]);
+		lock.update_package(
+			"lib",
+			"foo",
+			&[addon],
+			&InstanceIgnore::default(),
+			&mut NoOp,
+		)
+		.unwrap();
+		lock.update_package("pkg", "foo", &[], &InstanceIgnore::default(), &mut NoOp)
+			.unwrap();
+		lock.set_package_reason(
+			"foo",
+			"lib",
+			InstallReason::Dependency,
+			vec!["pkg".into()],
+			false,
+		);
+		lock.set_package_reason("foo", "pkg", InstallReason::Explicit, Vec::new(), false);
+
+		let contents = serde_json::to_value(&lock.contents).unwrap();
+		lock.contents = serde_json::from_value(contents).unwrap();
+		let packages = lock.get_instance_packages("foo");
+		assert_eq!(packages.len(), 2);
+		assert_eq!(packages[0].id, "lib".into());
+		assert_eq!(packages[0].reason, Some(InstallReason::Dependency));
+		assert_eq!(packages[0].required_by, vec![PackageID::from("pkg")]);
+		assert_eq!(packages[1].reason, Some(InstallReason::Explicit));
+
+		let entry = lock.get_package_entry("foo", "lib");
+		lock.update_package("lib", "foo", &[], &InstanceIgnore::default(), &mut NoOp)
+			.unwrap();
+		lock.restore_package_entry("foo", "lib", entry);
+		assert!(lock
+			.get_instance_addon_files("foo")
+			.contains(&PathBuf::from("mods/mod.jar")));
+		lock.restore_package_entry("foo", "new", None);
+		assert_eq!(lock.get_instance_packages("foo").len(), 2);
+
+		let files = lock.remove_package("foo", "lib");
+		assert_eq!(files, vec![PathBuf::from("mods/mod.jar")]);
+		assert_eq!(lock.get_instance_packages("foo").len(), 1);
+	}
+
+	#[test]
+	fn test_loader_version() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: true,
+		};
+		assert!(!lock.update_instance_loader_version("foo", Some("0.15.11")));
+		assert_eq!(lock.get_instance_loader_version("foo"), None);
+
+		lock.update_instance_version("foo", "1.20.1");
+		assert!(!lock.update_instance_loader_version("foo", Some("0.15.11")));
+		assert_eq!(lock.get_instance_loader_version("foo"), Some("0.15.11"));
+
+		let changes = lock.get_instance_changes("foo", "1.20.1", None, Some("0.16.0"), None);
+		assert_eq!(
+			changes,
+			vec![LockfileChange::LoaderVersion {
+				old: "0.15.11".into(),
+				new: "0.16.0".into()
+			}]
+		);
+		assert!(lock.ensure_unchanged("foo", &changes).is_err());
+
+		assert!(lock.update_instance_loader_version("foo", Some("0.16.0")));
+		assert!(!lock.update_instance_loader_version("foo", None));
+		assert_eq!(lock.get_instance_loader_version("foo"), None);
+	}
+
+	#[test]
+	fn test_sponge_version() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: false,
+		};
+		lock.update_instance_version("foo", "1.20.1");
+		assert!(!lock.update_instance_sponge_version("foo", Some("1.20.1-11.0.0")));
+
+		let changes = lock.get_instance_changes("foo", "1.20.1", None, None, Some("1.20.1-11.0.1"));
+		assert_eq!(
+			changes,
+			vec![LockfileChange::SpongeVersion {
+				old: "1.20.1-11.0.0".into(),
+				new: "1.20.1-11.0.1".into()
+			}]
+		);
+		assert_eq!(
+			changes[0].to_string(),
+			"~ sponge 1.20.1-11.0.0 -> 1.20.1-11.0.1"
+		);
+		assert!(lock
+			.get_instance_changes("foo", "1.20.1", None, None, Some("1.20.1-11.0.0"))
+			.is_empty());
+
+		assert!(lock.update_instance_sponge_version("foo", Some("1.20.1-11.0.1")));
+		assert!(!lock.update_instance_sponge_version("foo", None));
+	}
+
+	#[test]
+	fn test_version_files() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: false,
+		};
+		lock.update_instance_version("foo", "1.20.1");
+		assert_eq!(lock.get_instance_version_files("foo"), None);
+
+		lock.update_instance_version_files("foo", vec!["server.jar".into()]);
+		assert_eq!(
+			lock.get_instance_version_files("foo"),
+			Some(&["server.jar".to_string()][..])
+		);
+
+		// The files are relative to the instance, so they stay the same when it is moved
+		lock.rename_instance("foo", "bar", Path::new("/foo"), Path::new("/bar"));
+		assert_eq!(lock.get_instance_version_files("foo"), None);
+		assert_eq!(
+			lock.get_instance_version_files("bar"),
+			Some(&["server.jar".to_string()][..])
+		);
+	}
+
+	fn parse_fixture(contents: &str) -> Lockfile {
+		let contents = serde_json::from_str(contents).unwrap();
+		Lockfile {
+			contents: Lockfile::parse(contents).unwrap(),
+			frozen: false,
+		}
+	}
+
+	#[test]
+	fn test_server_builds() {
+		let mut lock = Lockfile {
+			contents: LockfileContents::default(),
+			frozen: false,
+		};
+		lock.update_instance_version("foo", "1.20.1");
+		lock.update_instance_paper_build("foo", 100);
+		lock.record_instance_update("foo").unwrap();
+		assert!(lock.update_instance_paper_build("foo", 110));
+		lock.update_server_build("paper", "1.20.4", 20).unwrap();
+
+		assert_eq!(lock.get_instance_paper_build("foo"), Some(110));
+		assert_eq!(lock.get_server_build("paper", "1.20.4").unwrap().build, 20);
+		assert!(lock.get_server_build("folia", "1.20.4").is_none());
+		let used = lock.get_used_server_builds();
+		assert_eq!(used.len(), 3);
+		for build in [("1.20.1", 100), ("1.20.1", 110), ("1.20.4", 20)] {
+			assert!(used.contains(&(build.0.to_string(), build.1)));
+		}
+	}
+
+	#[test]
+	fn test_migrate_v0() {
+		let lock = parse_fixture(include_str!("fixtures/v0.json"));
+		assert_eq!(lock.contents.version, LOCKFILE_VERSION);
+		let addon = &lock.contents.packages["client"]["sodium"].addons[0];
+		assert_eq!(addon.id, "sodium.jar");
+		assert_eq!(addon.file_name.as_deref(), Some("sodium.jar"));
+		assert_eq!(addon.files, vec!["mods/sodium.jar"]);
+		assert_eq!(lock.get_instance_version("client"), Some("1.19.3"));
+		assert!(lock.contents.created_instances.contains("client"));
+	}
+
+	#[test]
+	fn test_unknown_fields_kept() {
+		let lock = parse_fixture(include_str!("fixtures/unknown_fields.json"));
+		assert!(!lock.is_from_newer_version());
+
+		let written = serde_json::to_value(&lock.contents).unwrap();
+		assert_eq!(written["last_sync"], 1700000000);
+		assert_eq!(written["instances"]["client"]["java_version"], 17);
+		let package = &written["packages"]["client"]["sodium"];
+		assert_eq!(package["pinned"], true);
+		assert_eq!(package["addons"][0]["signature"], "abc123");
+
+		// Fields are kept for addons that are updated too
+		let mut lock = lock;
+		let mut addon = lock.contents.packages["client"]["sodium"].addons[0].clone();
+		addon.extra.clear();
+		addon.version = Some("0.5.9".into());
+		lock.update_package(
+			"sodium",
+			"client",
+			&[addon],
+			&InstanceIgnore::default(),
+			&mut NoOp,
+		)
+		.unwrap();
+		let written = serde_json::to_value(&lock.contents).unwrap();
+		let addon = &written["packages"]["client"]["sodium"]["addons"][0];
+		assert_eq!(addon["version"], "0.5.9");
+		assert_eq!(addon["signature"], "abc123");
+	}
+
+	#[test]
+	fn test_future_version() {
+		let mut lock = parse_fixture(include_str!("fixtures/future.json"));
+		assert!(lock.is_from_newer_version());
+		assert_eq!(lock.get_instance_version("client"), Some("1.20.4"));
+
+		let err = lock.finish(&Paths::new_no_create().unwrap()).unwrap_err();
+		assert!(err.to_string().contains("newer"));
+	}
+}
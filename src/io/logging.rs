@@ -0,0 +1,262 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use anyhow::Context;
+
+/// The default maximum size of a log file before it is rotated
+pub const DEFAULT_MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+/// The default number of rotated log files to keep
+pub const DEFAULT_MAX_LOG_FILES: u32 = 5;
+
+/// A log file that is rotated when it gets too large. The current log is stored at
+/// the base path, and older logs get numbered extensions like `.1`, `.2`, etc.
+#[derive(Debug)]
+pub struct RotatingLog {
+	path: PathBuf,
+	file: File,
+	size: u64,
+	max_size: u64,
+	max_files: u32,
+}
+
+impl RotatingLog {
+	/// Open a rotating log at a path, appending to it if it already exists
+	pub fn open(path: &Path, max_size: u64, max_files: u32) -> anyhow::Result<Self> {
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent).context("Failed to create log directory")?;
+		}
+		let file = open_append(path)?;
+		let size = file.metadata().map(|x| x.len()).unwrap_or_default();
+
+		Ok(Self {
+			path: path.to_owned(),
+			file,
+			size,
+			max_size,
+			max_files,
+		})
+	}
+
+	/// Write a line to the log, rotating it first if it is full
+	pub fn write_line(&mut self, line: &str) -> anyhow::Result<()> {
+		let len = line.len() as u64 + 1;
+		if self.size > 0 && self.size + len > self.max_size {
+			self.rotate().context("Failed to rotate log")?;
+		}
+		writeln!(self.file, "{line}").context("Failed to write to log")?;
+		self.size += len;
+
+		Ok(())
+	}
+
+	/// Move every log file up by one number and start a new log
+	fn rotate(&mut self) -> anyhow::Result<()> {
+		if self.max_files == 0 {
+			self.file = File::create(&self.path)?;
+			self.size = 0;
+			return Ok(());
+		}
+
+		let oldest = get_rotated_path(&self.path, self.max_files);
+		if oldest.exists() {
+			fs::remove_file(oldest)?;
+		}
+		for i in (1..self.max_files).rev() {
+			let from = get_rotated_path(&self.path, i);
+			if from.exists() {
+				fs::rename(from, get_rotated_path(&self.path, i + 1))?;
+			}
+		}
+		fs::rename(&self.path, get_rotated_path(&self.path, 1))?;
+
+		self.file = open_append(&self.path)?;
+		self.size = 0;
+
+		Ok(())
+	}
+}
+
+/// Read the last lines of a log, oldest first
+pub fn tail_log(path: &Path, lines: usize) -> anyhow::Result<Vec<String>> {
+	if !path.exists() {
+		return Ok(Vec::new());
+	}
+	let file = BufReader::new(File::open(path).context("Failed to open log")?);
+	let mut out = VecDeque::with_capacity(lines);
+	for line in file.lines() {
+		let line = line.context("Failed to read log")?;
+		if out.len() == lines {
+			out.pop_front();
+		}
+		if lines > 0 {
+			out.push_back(line);
+		}
+	}
+
+	Ok(out.into())
+}
+
+/// Copy lines of output from a process into a log on a separate thread. ANSI escape codes are
+/// removed from the lines written to the log, but kept in the lines given to the mirror function
+pub fn capture_output(
+	output: impl Read + Send + 'static,
+	log: Arc<Mutex<RotatingLog>>,
+	mirror: Option<fn(&str)>,
+) -> JoinHandle<()> {
+	std::thread::spawn(move || {
+		let mut output = BufReader::new(output);
+		let mut buf = Vec::new();
+		loop {
+			buf.clear();
+			match output.read_until(b'\n', &mut buf) {
+				Ok(0) | Err(..) => break,
+				Ok(..) => {}
+			}
+			let line = String::from_utf8_lossy(&buf);
+			let line = line.trim_end_matches(['\n', '\r']);
+			if let Some(mirror) = mirror {
+				mirror(line);
+			}
+			let Ok(mut log) = log.lock() else {
+				break;
+			};
+			let _ = log.write_line(&strip_ansi(line));
+		}
+	})
+}
+
+/// Remove ANSI escape sequences, such as colors, from text
+pub fn strip_ansi(text: &str) -> Cow<'_, str> {
+	if !text.contains('\x1b') {
+		return Cow::Borrowed(text);
+	}
+
+	let mut out = String::with_capacity(text.len());
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next() {
+		if c != '\x1b' {
+			out.push(c);
+			continue;
+		}
+		match chars.next() {
+			// Control sequences end with a byte in the range @ to ~
+			Some('[') => {
+				for c in chars.by_ref() {
+					if ('@'..='~').contains(&c) {
+						break;
+					}
+				}
+			}
+			// Operating system commands end with BEL or ESC \
+			Some(']') => {
+				while let Some(c) = chars.next() {
+					if c == '\x07' {
+						break;
+					}
+					if c == '\x1b' {
+						if chars.peek() == Some(&'\\') {
+							chars.next();
+						}
+						break;
+					}
+				}
+			}
+			// Other escapes are only one character long
+			_ => {}
+		}
+	}
+
+	Cow::Owned(out)
+}
+
+/// Get the path of a rotated log file
+fn get_rotated_path(path: &Path, number: u32) -> PathBuf {
+	let mut path = path.as_os_str().to_owned();
+	path.push(format!(".{number}"));
+	PathBuf::from(path)
+}
+
+/// Open a file for appending, creating it if it doesn't exist
+fn open_append(path: &Path) -> anyhow::Result<File> {
+	OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(path)
+		.context("Failed to open log file")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_log_rotation() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let path = dir.join("test.log");
+
+		let mut log = RotatingLog::open(&path, 12, 2).unwrap();
+		for line in [
+			"aaaaa", "bbbbb", "ccccc", "ddddd", "eeeee", "fffff", "ggggg",
+		] {
+			log.write_line(line).unwrap();
+		}
+
+		assert_eq!(tail_log(&path, 10).unwrap(), vec!["ggggg"]);
+		assert_eq!(
+			tail_log(&get_rotated_path(&path, 1), 10).unwrap(),
+			vec!["eeeee", "fffff"]
+		);
+		assert_eq!(
+			tail_log(&get_rotated_path(&path, 2), 10).unwrap(),
+			vec!["ccccc", "ddddd"]
+		);
+		assert!(!get_rotated_path(&path, 3).exists());
+		assert_eq!(
+			tail_log(&get_rotated_path(&path, 1), 1).unwrap(),
+			vec!["fffff"]
+		);
+	}
+
+	#[test]
+	fn test_log_reopen_after_rotation() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		let path = dir.join("test.log");
+
+		let mut log = RotatingLog::open(&path, 12, 1).unwrap();
+		log.write_line("aaaaa").unwrap();
+		log.write_line("bbbbb").unwrap();
+		log.write_line("ccccc").unwrap();
+		// Opening the log again should pick up its existing size and keep rotating correctly
+		drop(log);
+		let mut log = RotatingLog::open(&path, 12, 1).unwrap();
+		log.write_line("ddddd").unwrap();
+		log.write_line("eeeee").unwrap();
+
+		assert_eq!(tail_log(&path, 10).unwrap(), vec!["eeeee"]);
+		assert_eq!(
+			tail_log(&get_rotated_path(&path, 1), 10).unwrap(),
+			vec!["ccccc", "ddddd"]
+		);
+		assert!(!get_rotated_path(&path, 2).exists());
+	}
+
+	#[test]
+	fn test_strip_ansi() {
+		assert_eq!(strip_ansi("plain text"), "plain text");
+		assert_eq!(
+			strip_ansi("\x1b[32m[Server thread/INFO]\x1b[0m Done"),
+			"[Server thread/INFO] Done"
+		);
+		assert_eq!(strip_ansi("\x1b[1;38;5;208mbold\x1b[m"), "bold");
+		assert_eq!(strip_ansi("\x1b]0;title\x07text"), "text");
+		assert_eq!(strip_ansi("\x1b]0;title\x1b\\text"), "text");
+		assert_eq!(strip_ansi("a\x1b7b"), "ab");
+	}
+}
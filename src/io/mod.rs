@@ -1,4 +1,6 @@
 /// Use of the lockfile for persistent data
 pub mod lock;
+/// Rotating log files and capture of process output
+pub mod logging;
 /// Standard paths for MCVM
 pub mod paths;
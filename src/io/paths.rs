@@ -1,7 +1,13 @@
-use anyhow::{anyhow, Context};
+use anyhow::Context;
 use directories::{BaseDirs, ProjectDirs};
+use mcvm_core::io::files::paths::get_project_dirs;
 
-use std::path::PathBuf;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::config::preferences::read_data_dir_preference;
+use crate::config::Config;
 
 /// Store for all of the paths that are used throughout the application
 #[derive(Debug, Clone)]
@@ -14,6 +20,8 @@ pub struct Paths {
 	pub core: mcvm_core::Paths,
 	/// Holds program data
 	pub data: PathBuf,
+	/// Holds cached files that can be downloaded again
+	pub cache: PathBuf,
 	/// Holds internal data
 	pub internal: PathBuf,
 	/// Holds addons
@@ -36,6 +44,8 @@ pub struct Paths {
 	pub proxy: PathBuf,
 	/// Holding user plugins
 	pub plugins: PathBuf,
+	/// Holds the directories of instances
+	pub instances: PathBuf,
 }
 
 impl Paths {
@@ -51,7 +61,7 @@ impl Paths {
 	pub async fn create_dirs(&self) -> anyhow::Result<()> {
 		tokio::try_join!(
 			tokio::fs::create_dir_all(&self.data),
-			tokio::fs::create_dir_all(self.project.cache_dir()),
+			tokio::fs::create_dir_all(&self.cache),
 			tokio::fs::create_dir_all(self.project.config_dir()),
 			tokio::fs::create_dir_all(&self.internal),
 			tokio::fs::create_dir_all(&self.addons),
@@ -70,35 +80,35 @@ impl Paths {
 		Ok(())
 	}
 
-	/// Create the paths without creating any directories
+	/// Create the paths without creating any directories. The data directory
+	/// can be moved using the data_dir preference or the MCVM_DATA_DIR environment variable
 	pub fn new_no_create() -> anyhow::Result<Self> {
-		let base = BaseDirs::new().ok_or(anyhow!("Base directories failed"))?;
-		let project =
-			ProjectDirs::from("", "mcvm", "mcvm").ok_or(anyhow!("Base directories failed"))?;
+		let config_dir = get_project_dirs()?.config_dir().to_owned();
+		let data_dir_preference = read_data_dir_preference(&Config::get_path_in(&config_dir));
+		let core_paths = mcvm_core::Paths::resolve(data_dir_preference)
+			.context("Failed to create core paths")?;
 
-		let data = project.data_dir().to_owned();
-		let internal = data.join("internal");
+		let data = core_paths.data.clone();
+		let cache = core_paths.cache.clone();
+		let internal = core_paths.internal.clone();
 		let addons = internal.join("addons");
-		let pkg_cache = project.cache_dir().join("pkg");
+		let pkg_cache = cache.join("pkg");
 		let pkg_index_cache = pkg_cache.join("index");
-		let logs = data.join("logs");
-		let launch_logs = logs.join("launch");
-		let run = project
-			.runtime_dir()
-			.map(|x| x.to_path_buf())
-			.unwrap_or(internal.join("run"));
+		let logs = core_paths.logs.clone();
+		let launch_logs = core_paths.launch_logs.clone();
+		let run = core_paths.run.clone();
 		let snapshots = internal.join("snapshots");
 		let fabric_quilt = internal.join("fabric_quilt");
 		let proxy = data.join("proxy");
 		let plugins = data.join("plugins");
-
-		let core_paths = mcvm_core::Paths::new().context("Failed to create core paths")?;
+		let instances = data.join("instances");
 
 		Ok(Paths {
-			base,
-			project,
+			base: core_paths.base.clone(),
+			project: core_paths.project.clone(),
 			core: core_paths,
 			data,
+			cache,
 			internal,
 			addons,
 			pkg_cache,
@@ -110,6 +120,132 @@ impl Paths {
 			fabric_quilt,
 			proxy,
 			plugins,
+			instances,
 		})
 	}
+
+	/// Get one of the directories by its kind
+	pub fn get(&self, kind: PathKind) -> &Path {
+		match kind {
+			PathKind::Data => &self.data,
+			PathKind::Cache => &self.cache,
+			PathKind::Config => self.project.config_dir(),
+			PathKind::Internal => &self.internal,
+			PathKind::Instances => &self.instances,
+			PathKind::Assets => &self.core.assets,
+			PathKind::Libraries => &self.core.libraries,
+			PathKind::Jars => &self.core.jars,
+			PathKind::Java => &self.core.java,
+			PathKind::Addons => &self.addons,
+			PathKind::Logs => &self.logs,
+			PathKind::Plugins => &self.plugins,
+		}
+	}
+}
+
+/// The directories that can be looked up by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+	/// The data directory
+	Data,
+	/// The cache directory
+	Cache,
+	/// The config directory
+	Config,
+	/// The internal data directory
+	Internal,
+	/// The directory holding instances
+	Instances,
+	/// Game assets
+	Assets,
+	/// Game libraries
+	Libraries,
+	/// Game jar files
+	Jars,
+	/// Java installations
+	Java,
+	/// Stored addon files
+	Addons,
+	/// Log files
+	Logs,
+	/// User plugins
+	Plugins,
+}
+
+impl PathKind {
+	/// All of the kinds of paths
+	pub const ALL: [Self; 12] = [
+		Self::Data,
+		Self::Cache,
+		Self::Config,
+		Self::Internal,
+		Self::Instances,
+		Self::Assets,
+		Self::Libraries,
+		Self::Jars,
+		Self::Java,
+		Self::Addons,
+		Self::Logs,
+		Self::Plugins,
+	];
+
+	/// Get the name of this kind of path
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Data => "data",
+			Self::Cache => "cache",
+			Self::Config => "config",
+			Self::Internal => "internal",
+			Self::Instances => "instances",
+			Self::Assets => "assets",
+			Self::Libraries => "libraries",
+			Self::Jars => "jars",
+			Self::Java => "java",
+			Self::Addons => "addons",
+			Self::Logs => "logs",
+			Self::Plugins => "plugins",
+		}
+	}
+}
+
+impl FromStr for PathKind {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.into_iter()
+			.find(|x| x.as_str() == s)
+			.with_context(|| {
+				format!(
+					"Unknown path kind '{s}'. Possible values are: {}",
+					Self::ALL.map(|x| x.as_str()).join(", ")
+				)
+			})
+	}
+}
+
+impl Display for PathKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.as_str())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_path_kinds() {
+		let paths = Paths::new_no_create().unwrap();
+		for kind in PathKind::ALL {
+			assert_eq!(PathKind::from_str(kind.as_str()).unwrap(), kind);
+			assert!(paths.get(kind).is_absolute());
+		}
+		assert!(PathKind::from_str("foo").is_err());
+		assert_eq!(paths.get(PathKind::Jars), paths.core.jars);
+		assert_eq!(
+			paths.get(PathKind::Config),
+			Config::get_path(&paths).parent().unwrap()
+		);
+	}
 }
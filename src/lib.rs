@@ -1,4 +1,5 @@
 #![warn(missing_docs)]
+#![allow(clippy::doc_lazy_continuation)]
 
 //! This is the library for MCVM and pretty much all of the features that the
 //! CLI uses.
@@ -18,6 +19,7 @@
 //! - `schema`: Enable generation of JSON schemas using the `schemars` crate
 
 pub use mcvm_core as core;
+pub use mcvm_options as options;
 pub use mcvm_parse as parse;
 pub use mcvm_pkg as pkg_crate;
 pub use mcvm_plugin as plugin;
@@ -25,12 +27,20 @@ pub use mcvm_shared as shared;
 
 /// Installable addons
 pub mod addon;
+/// Stable entry points for applications that embed MCVM
+pub mod api;
 /// MCVM configuration
 pub mod config;
+/// Supervising long-running instances in the background
+pub mod daemon;
+/// Health checks for the installation and environment
+pub mod doctor;
 /// Launchable instances
 pub mod instance;
 /// File and data format input / output
 pub mod io;
+/// Network protocols for talking to running instances
+pub mod net;
 /// Dealing with packages
 pub mod pkg;
 /// Configuration profiles for instances
@@ -0,0 +1,2 @@
+/// Client for the remote console (RCON) of Minecraft servers
+pub mod rcon;
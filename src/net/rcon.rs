@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+
+/// The port that servers use for RCON when server.properties doesn't set one
+pub const DEFAULT_RCON_PORT: u16 = 25575;
+/// How long to wait for the server when connecting and for each response
+pub const RCON_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Packet type for logging in with the password
+const PACKET_AUTH: i32 = 3;
+/// Packet type for running a command, also used by the server to respond to logins
+const PACKET_EXEC_COMMAND: i32 = 2;
+/// Packet type for responses to commands
+const PACKET_RESPONSE: i32 = 0;
+/// The request ID that the server responds with when the password is wrong
+const AUTH_FAILED_ID: i32 = -1;
+/// The largest command that the server accepts
+pub const MAX_COMMAND_LENGTH: usize = 1446;
+/// The largest response body that the server sends in a single packet. Longer
+/// responses are split into multiple packets
+const MAX_RESPONSE_BODY_LENGTH: usize = 4096;
+/// The largest packet that we will read, to avoid allocating garbage sizes
+const MAX_PACKET_LENGTH: usize = 4 + 4 + MAX_RESPONSE_BODY_LENGTH + 2;
+
+/// Settings for connecting to the RCON server of a Minecraft server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RconSettings {
+	/// The host to connect to
+	pub host: String,
+	/// The port of the RCON server
+	pub port: u16,
+	/// The RCON password
+	pub password: String,
+}
+
+impl RconSettings {
+	/// Read the RCON settings from the keys of a server.properties file
+	pub fn from_server_properties(properties: &HashMap<String, String>) -> anyhow::Result<Self> {
+		let enabled = properties.get("enable-rcon").is_some_and(|x| x == "true");
+		if !enabled {
+			bail!("RCON is not enabled on this server. Set the 'rcon.enable' server option to true and give it an 'rcon.password', then update and restart the server");
+		}
+
+		let password = properties
+			.get("rcon.password")
+			.filter(|x| !x.is_empty())
+			.context("RCON is enabled on this server, but it has no password, so the server won't start RCON. Set the 'rcon.password' server option, then update and restart the server")?
+			.clone();
+
+		let port = match properties.get("rcon.port").filter(|x| !x.is_empty()) {
+			Some(port) => port
+				.parse()
+				.with_context(|| format!("Invalid RCON port '{port}' in server.properties"))?,
+			None => DEFAULT_RCON_PORT,
+		};
+
+		// The server only listens on server-ip if it is set
+		let host = properties
+			.get("server-ip")
+			.filter(|x| !x.is_empty())
+			.cloned()
+			.unwrap_or_else(|| "127.0.0.1".into());
+
+		Ok(Self {
+			host,
+			port,
+			password,
+		})
+	}
+}
+
+/// A packet sent to or received from an RCON server
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Packet {
+	/// The ID of the request, which the server uses for its response
+	id: i32,
+	/// The type of the packet
+	kind: i32,
+	/// The contents of the packet
+	body: String,
+}
+
+/// Write an RCON packet. The length comes first, then the request ID and type, all as little-endian
+/// integers, then the body and two null terminators
+fn write_packet(writer: &mut impl Write, packet: &Packet) -> anyhow::Result<()> {
+	let body = packet.body.as_bytes();
+	let length = 4 + 4 + body.len() + 2;
+	let mut out = Vec::with_capacity(4 + length);
+	out.extend((length as i32).to_le_bytes());
+	out.extend(packet.id.to_le_bytes());
+	out.extend(packet.kind.to_le_bytes());
+	out.extend(body);
+	out.extend([0, 0]);
+	writer.write_all(&out)?;
+	writer.flush()?;
+
+	Ok(())
+}
+
+/// Read an RCON packet
+fn read_packet(reader: &mut impl Read) -> anyhow::Result<Packet> {
+	let mut length = [0; 4];
+	reader.read_exact(&mut length)?;
+	let length = i32::from_le_bytes(length);
+	if length < 10 || length as usize > MAX_PACKET_LENGTH {
+		bail!("Server sent an RCON packet with an invalid length of {length}");
+	}
+
+	let mut data = vec![0; length as usize];
+	reader.read_exact(&mut data)?;
+	let id = i32::from_le_bytes(data[0..4].try_into().expect("Slice should be 4 bytes"));
+	let kind = i32::from_le_bytes(data[4..8].try_into().expect("Slice should be 4 bytes"));
+	let Some(body) = data[8..].strip_suffix(&[0, 0]) else {
+		bail!("Server sent an RCON packet that isn't terminated");
+	};
+	let body = String::from_utf8_lossy(body).to_string();
+
+	Ok(Packet { id, kind, body })
+}
+
+/// A client for the remote console of a Minecraft server
+pub struct RconClient<S: Read + Write> {
+	stream: S,
+	next_id: i32,
+}
+
+impl RconClient<TcpStream> {
+	/// Connect to an RCON server and log in
+	pub fn connect(settings: &RconSettings) -> anyhow::Result<Self> {
+		let address = (settings.host.as_str(), settings.port)
+			.to_socket_addrs()
+			.context("Failed to resolve the RCON address")?
+			.next()
+			.context("RCON address did not resolve to anything")?;
+		let stream = TcpStream::connect_timeout(&address, RCON_TIMEOUT).with_context(|| {
+			format!("Failed to connect to RCON at {address}. Is the server running?")
+		})?;
+		stream.set_read_timeout(Some(RCON_TIMEOUT))?;
+		stream.set_write_timeout(Some(RCON_TIMEOUT))?;
+
+		let mut client = Self::new(stream);
+		client.authenticate(&settings.password)?;
+		Ok(client)
+	}
+}
+
+impl<S: Read + Write> RconClient<S> {
+	/// Create a client from a connection to an RCON server
+	pub fn new(stream: S) -> Self {
+		Self { stream, next_id: 1 }
+	}
+
+	/// Send a packet and get its request ID
+	fn send(&mut self, kind: i32, body: &str) -> anyhow::Result<i32> {
+		let id = self.next_id;
+		self.next_id += 1;
+		let packet = Packet {
+			id,
+			kind,
+			body: body.into(),
+		};
+		write_packet(&mut self.stream, &packet).context("Failed to send RCON packet")?;
+		Ok(id)
+	}
+
+	/// Log in to the server with a password
+	pub fn authenticate(&mut self, password: &str) -> anyhow::Result<()> {
+		let id = self.send(PACKET_AUTH, password)?;
+		// Some servers send an empty response before the result of the login
+		loop {
+			let response =
+				read_packet(&mut self.stream).context("Failed to read RCON login response")?;
+			if response.id == AUTH_FAILED_ID {
+				bail!("The RCON password was not accepted by the server");
+			}
+			if response.kind == PACKET_EXEC_COMMAND {
+				if response.id != id {
+					bail!("Server responded to the RCON login with an unexpected request ID");
+				}
+				return Ok(());
+			}
+		}
+	}
+
+	/// Run a command on the server and get its output
+	pub fn run_command(&mut self, command: &str) -> anyhow::Result<String> {
+		if command.len() > MAX_COMMAND_LENGTH {
+			bail!("Command is too long to send over RCON (>{MAX_COMMAND_LENGTH} bytes)");
+		}
+		let id = self.send(PACKET_EXEC_COMMAND, command)?;
+		// Long responses are split into packets with no marker for the last one, so we send
+		// a packet that the server doesn't understand afterwards. The server handles packets
+		// in order, so its reply to that one comes after the whole command response
+		let terminator_id = self.send(PACKET_RESPONSE, "")?;
+
+		let mut out = String::new();
+		loop {
+			let response =
+				read_packet(&mut self.stream).context("Failed to read RCON command response")?;
+			if response.id == terminator_id {
+				break;
+			}
+			if response.id != id || response.kind != PACKET_RESPONSE {
+				bail!("Server sent an unexpected RCON response");
+			}
+			out.push_str(&response.body);
+		}
+
+		Ok(out)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+
+	/// A fake connection that has responses ready and records what was written
+	struct FakeStream {
+		input: Cursor<Vec<u8>>,
+		output: Vec<u8>,
+	}
+
+	impl Read for FakeStream {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			self.input.read(buf)
+		}
+	}
+
+	impl Write for FakeStream {
+		fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+			self.output.write(buf)
+		}
+
+		fn flush(&mut self) -> std::io::Result<()> {
+			Ok(())
+		}
+	}
+
+	fn client(input: Vec<u8>) -> RconClient<FakeStream> {
+		RconClient::new(FakeStream {
+			input: Cursor::new(input),
+			output: Vec::new(),
+		})
+	}
+
+	/// The login packet for request 1 with the password "hunter2"
+	const AUTH_PACKET: &[u8] = &[
+		0x11, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, b'h', b'u', b'n', b't', b'e', b'r', b'2', 0, 0,
+	];
+	/// The response to a successful login of request 1
+	const AUTH_RESPONSE: &[u8] = &[0x0a, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0];
+	/// The response to a failed login
+	const AUTH_FAILED_RESPONSE: &[u8] = &[0x0a, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 2, 0, 0, 0, 0, 0];
+	/// The command packet for request 2 with the command "list"
+	const LIST_PACKET: &[u8] = &[
+		0x0e, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, b'l', b'i', b's', b't', 0, 0,
+	];
+	/// The empty packet for request 3 that marks the end of a command response
+	const TERMINATOR_PACKET: &[u8] = &[0x0a, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+	/// Write the server's response to the end of a command response
+	fn write_terminator_response(input: &mut Vec<u8>) {
+		let packet = Packet {
+			id: 3,
+			kind: PACKET_RESPONSE,
+			body: "Unknown request 0".into(),
+		};
+		write_packet(input, &packet).unwrap();
+	}
+
+	#[test]
+	fn test_packet_framing() {
+		let packet = Packet {
+			id: 1,
+			kind: PACKET_AUTH,
+			body: "hunter2".into(),
+		};
+		let mut out = Vec::new();
+		write_packet(&mut out, &packet).unwrap();
+		assert_eq!(out, AUTH_PACKET);
+		assert_eq!(read_packet(&mut Cursor::new(AUTH_PACKET)).unwrap(), packet);
+
+		// Bad lengths and missing terminators are rejected
+		assert!(read_packet(&mut Cursor::new([0x02, 0, 0, 0, 0, 0])).is_err());
+		assert!(read_packet(&mut Cursor::new([0xff, 0xff, 0, 0])).is_err());
+		let mut unterminated = AUTH_RESPONSE.to_vec();
+		unterminated[13] = b'a';
+		assert!(read_packet(&mut Cursor::new(unterminated)).is_err());
+		assert!(read_packet(&mut Cursor::new(&AUTH_PACKET[..10])).is_err());
+	}
+
+	#[test]
+	fn test_command() {
+		let response = Packet {
+			id: 2,
+			kind: PACKET_RESPONSE,
+			body: "There are 0 of a max of 20 players online: ".into(),
+		};
+		let mut input = AUTH_RESPONSE.to_vec();
+		write_packet(&mut input, &response).unwrap();
+		write_terminator_response(&mut input);
+
+		let mut client = client(input);
+		client.authenticate("hunter2").unwrap();
+		assert_eq!(client.run_command("list").unwrap(), response.body);
+		assert_eq!(
+			client.stream.output,
+			[AUTH_PACKET, LIST_PACKET, TERMINATOR_PACKET].concat()
+		);
+	}
+
+	#[test]
+	fn test_split_response() {
+		let run = |bodies: &[String]| {
+			let mut input = AUTH_RESPONSE.to_vec();
+			for body in bodies {
+				let packet = Packet {
+					id: 2,
+					kind: PACKET_RESPONSE,
+					body: body.clone(),
+				};
+				write_packet(&mut input, &packet).unwrap();
+			}
+			write_terminator_response(&mut input);
+
+			let mut client = client(input);
+			client.authenticate("hunter2").unwrap();
+			client.run_command("list").unwrap()
+		};
+
+		let output = run(&["a".repeat(MAX_RESPONSE_BODY_LENGTH), "b".into()]);
+		assert_eq!(output.len(), MAX_RESPONSE_BODY_LENGTH + 1);
+		assert!(output.ends_with('b'));
+
+		// A response that exactly fills its packets still ends at the terminator
+		let output = run(&["a".repeat(MAX_RESPONSE_BODY_LENGTH)]);
+		assert_eq!(output.len(), MAX_RESPONSE_BODY_LENGTH);
+		assert_eq!(run(&[]), "");
+	}
+
+	#[test]
+	fn test_missing_terminator() {
+		let mut input = AUTH_RESPONSE.to_vec();
+		let packet = Packet {
+			id: 2,
+			kind: PACKET_RESPONSE,
+			body: "a".repeat(MAX_RESPONSE_BODY_LENGTH),
+		};
+		write_packet(&mut input, &packet).unwrap();
+
+		// The connection closing before the terminator is an error, not a short response
+		let mut client = client(input);
+		client.authenticate("hunter2").unwrap();
+		assert!(client.run_command("list").is_err());
+	}
+
+	#[test]
+	fn test_failed_login() {
+		let mut client = client(AUTH_FAILED_RESPONSE.to_vec());
+		let err = client.authenticate("wrong").unwrap_err();
+		assert!(err.to_string().contains("password was not accepted"));
+	}
+
+	#[test]
+	fn test_settings() {
+		let properties = |pairs: &[(&str, &str)]| {
+			pairs
+				.iter()
+				.map(|(x, y)| (x.to_string(), y.to_string()))
+				.collect::<HashMap<_, _>>()
+		};
+
+		let err = RconSettings::from_server_properties(&properties(&[])).unwrap_err();
+		assert!(err.to_string().contains("not enabled"));
+		let err = RconSettings::from_server_properties(&properties(&[("enable-rcon", "true")]))
+			.unwrap_err();
+		assert!(err.to_string().contains("no password"));
+
+		let settings = RconSettings::from_server_properties(&properties(&[
+			("enable-rcon", "true"),
+			("rcon.password", "hunter2"),
+		]))
+		.unwrap();
+		assert_eq!(
+			settings,
+			RconSettings {
+				host: "127.0.0.1".into(),
+				port: DEFAULT_RCON_PORT,
+				password: "hunter2".into(),
+			}
+		);
+
+		let settings = RconSettings::from_server_properties(&properties(&[
+			("enable-rcon", "true"),
+			("rcon.password", "hunter2"),
+			("rcon.port", "25580"),
+			("server-ip", "10.0.0.2"),
+		]))
+		.unwrap();
+		assert_eq!(settings.port, 25580);
+		assert_eq!(settings.host, "10.0.0.2");
+	}
+}
@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use ed25519_dalek::{Signer, SigningKey};
+use mcvm_core::io::{json_from_file, json_to_file_pretty};
+use mcvm_pkg::metadata::PackageMetadata;
+use mcvm_pkg::properties::PackageProperties;
+use mcvm_pkg::repo::{RepoMetadata, RepoPkgEntry};
+use mcvm_pkg::{parse_and_evaluate, PackageContentType};
+use mcvm_shared::pkg::{is_valid_package_id, PackageID};
+use serde::{Deserialize, Serialize};
+
+use super::hash_contents;
+
+/// The file at the root of an authored repository that holds its settings
+pub const REPO_CONFIG_FILE: &str = "repo.json";
+/// The directory of an authored repository that is served. This matches the
+/// URLs that MCVM uses for remote repositories, so the root of the repository can be hosted as-is
+pub const REPO_API_DIR: &str = "api/mcvm";
+/// The directory in the API directory that package files are stored in
+pub const REPO_PACKAGES_DIR: &str = "packages";
+
+/// Settings for an authored repository
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct RepoAuthorConfig {
+	/// The metadata that is written to the index
+	pub metadata: RepoMetadata,
+}
+
+/// The index as it is written by [generate_index], with sorted packages so that
+/// the same packages always produce the same file
+#[derive(Serialize)]
+struct GeneratedIndex<'a> {
+	metadata: &'a RepoMetadata,
+	packages: BTreeMap<String, RepoPkgEntry>,
+}
+
+/// Get the path to the index of an authored repository
+pub fn get_index_path(dir: &Path) -> PathBuf {
+	dir.join(REPO_API_DIR).join("index.json")
+}
+
+/// Get the directory of the package files of an authored repository
+pub fn get_packages_dir(dir: &Path) -> PathBuf {
+	dir.join(REPO_API_DIR).join(REPO_PACKAGES_DIR)
+}
+
+/// Create the layout of a new repository in a directory, along with an empty index
+pub fn init_repo(dir: &Path, metadata: RepoMetadata) -> anyhow::Result<()> {
+	let config_path = dir.join(REPO_CONFIG_FILE);
+	if config_path.exists() {
+		bail!("A repository already exists in {}", dir.display());
+	}
+
+	fs::create_dir_all(get_packages_dir(dir)).context("Failed to create packages directory")?;
+	json_to_file_pretty(&config_path, &RepoAuthorConfig { metadata })
+		.context("Failed to write repository settings")?;
+	generate_index(dir, None).context("Failed to create index")?;
+
+	Ok(())
+}
+
+/// Get the ID and content type of a package from its file name, which must be
+/// either `<id>.json` for declarative packages or `<id>.pkg.txt` for scripts
+pub fn parse_package_file_name(file_name: &str) -> anyhow::Result<(PackageID, PackageContentType)> {
+	let (id, content_type) = if let Some(id) = file_name.strip_suffix(".json") {
+		(id, PackageContentType::Declarative)
+	} else if let Some(id) = file_name.strip_suffix(".pkg.txt") {
+		(id, PackageContentType::Script)
+	} else {
+		bail!("Package file '{file_name}' must be named '<id>.json' for a declarative package or '<id>.pkg.txt' for a script package");
+	};
+	if !is_valid_package_id(id) {
+		bail!(
+			"'{id}' is not a valid package ID. IDs may contain only letters, numbers, and hyphens"
+		);
+	}
+
+	Ok((id.into(), content_type))
+}
+
+/// A package file that was read and validated
+struct PackageFile {
+	id: PackageID,
+	content_type: PackageContentType,
+	contents: String,
+	metadata: PackageMetadata,
+	properties: PackageProperties,
+}
+
+/// Read and validate a package file
+fn read_package_file(path: &Path) -> anyhow::Result<PackageFile> {
+	let file_name = path
+		.file_name()
+		.context("Package path has no file name")?
+		.to_string_lossy();
+	let (id, content_type) = parse_package_file_name(&file_name)?;
+	let contents = fs::read_to_string(path)
+		.with_context(|| format!("Failed to read package file {}", path.display()))?;
+	let (metadata, properties) = parse_and_evaluate(&contents, content_type)
+		.with_context(|| format!("Package '{id}' is invalid"))?;
+
+	Ok(PackageFile {
+		id,
+		content_type,
+		contents,
+		metadata,
+		properties,
+	})
+}
+
+/// A package that was added to a repository
+pub struct AddedPackage {
+	/// The ID of the package
+	pub id: PackageID,
+	/// The hex SHA-256 hash of the package file
+	pub hash: String,
+	/// Whether the package replaced one with the same ID
+	pub replaced: bool,
+}
+
+/// Validate a package file and copy it into a repository. The index must be generated again
+/// for the package to be available
+pub fn add_package(dir: &Path, package_path: &Path) -> anyhow::Result<AddedPackage> {
+	if !dir.join(REPO_CONFIG_FILE).exists() {
+		bail!(
+			"{} is not a repository. Create one with `mcvm repo init`",
+			dir.display()
+		);
+	}
+
+	let PackageFile {
+		id,
+		content_type,
+		contents,
+		..
+	} = read_package_file(package_path)?;
+	let packages_dir = get_packages_dir(dir);
+	fs::create_dir_all(&packages_dir).context("Failed to create packages directory")?;
+
+	// Only one file can provide each package
+	let mut replaced = false;
+	for other_type in [PackageContentType::Script, PackageContentType::Declarative] {
+		let other_path = packages_dir.join(get_package_file_name(&id, other_type));
+		if other_path.exists() {
+			replaced = true;
+			fs::remove_file(&other_path).context("Failed to remove replaced package")?;
+		}
+	}
+
+	let target = packages_dir.join(get_package_file_name(&id, content_type));
+	fs::write(&target, &contents).context("Failed to write package to repository")?;
+
+	Ok(AddedPackage {
+		hash: hash_contents(&contents),
+		id,
+		replaced,
+	})
+}
+
+/// Get the file name that a package is stored under
+fn get_package_file_name(id: &str, content_type: PackageContentType) -> String {
+	match content_type {
+		PackageContentType::Script => format!("{id}.pkg.txt"),
+		PackageContentType::Declarative => format!("{id}.json"),
+	}
+}
+
+/// Write the index of a repository from the packages in it, returning the number of packages.
+/// Every package is validated first. If a key is given, the index is also signed,
+/// and the hex signature is written next to it. Otherwise, any old signature is removed
+pub fn generate_index(dir: &Path, key: Option<&SigningKey>) -> anyhow::Result<usize> {
+	let config: RepoAuthorConfig =
+		json_from_file(dir.join(REPO_CONFIG_FILE)).context("Failed to read repository settings")?;
+
+	let packages_dir = get_packages_dir(dir);
+	let mut packages = BTreeMap::new();
+	if packages_dir.exists() {
+		for entry in packages_dir
+			.read_dir()
+			.context("Failed to read packages directory")?
+		{
+			let path = entry?.path();
+			if !path.is_file() {
+				continue;
+			}
+			let package = read_package_file(&path)?;
+			let file_name = get_package_file_name(&package.id, package.content_type);
+			// The name, description, and properties let users search for the package
+			// without downloading every package in the repository
+			let entry = RepoPkgEntry {
+				url: None,
+				path: Some(format!("{REPO_PACKAGES_DIR}/{file_name}")),
+				content_type: Some(package.content_type),
+				flags: Default::default(),
+				hash: Some(hash_contents(&package.contents)),
+				name: package.metadata.name,
+				description: package.metadata.description,
+				properties: Some(package.properties).filter(|x| !x.is_empty()),
+			};
+			if packages.insert(package.id.to_string(), entry).is_some() {
+				bail!(
+					"Package '{}' is in the repository more than once",
+					package.id
+				);
+			}
+		}
+	}
+
+	let count = packages.len();
+	let index = GeneratedIndex {
+		metadata: &config.metadata,
+		packages,
+	};
+	let mut contents = serde_json::to_string_pretty(&index).context("Failed to serialize index")?;
+	contents.push('\n');
+
+	let index_path = get_index_path(dir);
+	let sig_path = get_signature_path(&index_path);
+	fs::write(&index_path, &contents).context("Failed to write index")?;
+	if let Some(key) = key {
+		let signature = key.sign(contents.as_bytes());
+		fs::write(&sig_path, hex::encode(signature.to_bytes()) + "\n")
+			.context("Failed to write index signature")?;
+	} else if sig_path.exists() {
+		fs::remove_file(&sig_path).context("Failed to remove old index signature")?;
+	}
+
+	Ok(count)
+}
+
+/// Get the path to the signature of an index
+fn get_signature_path(index_path: &Path) -> PathBuf {
+	let mut path = index_path.to_owned().into_os_string();
+	path.push(".sig");
+	path.into()
+}
+
+/// Parse a hex-encoded ed25519 secret key that is used to sign an index
+pub fn parse_signing_key(key: &str) -> anyhow::Result<SigningKey> {
+	let bytes = hex::decode(key.trim()).context("Signing key is not valid hex")?;
+	let bytes: [u8; 32] = bytes
+		.try_into()
+		.map_err(|_| anyhow::anyhow!("Signing key must be 32 bytes long"))?;
+	Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Get the hex-encoded public key for a signing key, which users put in their config to verify the index
+pub fn get_public_key(key: &SigningKey) -> String {
+	hex::encode(key.verifying_key().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use mcvm_shared::addon::AddonKind;
+	use mcvm_shared::lang::Language;
+	use mcvm_shared::modifications::{ClientType, Modloader, ServerType};
+	use mcvm_shared::output::NoOp;
+	use mcvm_shared::pkg::{PackageStability, PkgRequest, PkgRequestSource};
+	use mcvm_shared::Side;
+	use reqwest::Client;
+
+	use super::*;
+	use crate::config::plugin::PluginManager;
+	use crate::config::profile::GameModifications;
+	use crate::io::paths::Paths;
+	use crate::pkg::eval::{EvalConstants, EvalInput, EvalParameters, Routine};
+	use crate::pkg::reg::{CachingStrategy, PkgRegistry};
+	use crate::pkg::repo::{PkgRepo, PkgRepoLocation};
+
+	const PACKAGE: &str = r#"{
+	"meta": { "name": "Test" },
+	"addons": {
+		"test": {
+			"kind": "mod",
+			"versions": [ { "url": "https://example.com/test.jar", "version": "1" } ]
+		}
+	}
+}"#;
+
+	#[test]
+	fn test_package_file_names() {
+		let (id, content_type) = parse_package_file_name("foo-bar.json").unwrap();
+		assert_eq!(id, "foo-bar".into());
+		assert!(matches!(content_type, PackageContentType::Declarative));
+		let (_, content_type) = parse_package_file_name("foo.pkg.txt").unwrap();
+		assert!(matches!(content_type, PackageContentType::Script));
+		assert!(parse_package_file_name("foo.txt").is_err());
+		assert!(parse_package_file_name("foo_bar.json").is_err());
+	}
+
+	#[test]
+	fn test_deterministic_index() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path().join("repo");
+		init_repo(&dir, RepoMetadata::default()).unwrap();
+		assert!(init_repo(&dir, RepoMetadata::default()).is_err());
+
+		let source = dir.join("source");
+		fs::create_dir_all(&source).unwrap();
+		for id in ["zeta", "alpha", "middle"] {
+			fs::write(source.join(format!("{id}.json")), PACKAGE).unwrap();
+			add_package(&dir, &source.join(format!("{id}.json"))).unwrap();
+		}
+		fs::write(source.join("broken.json"), "{").unwrap();
+		assert!(add_package(&dir, &source.join("broken.json")).is_err());
+
+		assert_eq!(generate_index(&dir, None).unwrap(), 3);
+		let first = fs::read_to_string(get_index_path(&dir)).unwrap();
+		generate_index(&dir, None).unwrap();
+		let second = fs::read_to_string(get_index_path(&dir)).unwrap();
+		assert_eq!(first, second);
+		assert!(first.find("alpha").unwrap() < first.find("middle").unwrap());
+		assert!(first.find("middle").unwrap() < first.find("zeta").unwrap());
+		let index: mcvm_pkg::repo::RepoIndex = serde_json::from_str(&first).unwrap();
+		assert_eq!(index.packages["alpha"].name.as_deref(), Some("Test"));
+		assert!(index.packages["alpha"].properties.is_none());
+	}
+
+	#[test]
+	fn test_repo_round_trip() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path().join("repo");
+		init_repo(
+			&dir,
+			RepoMetadata {
+				name: Some("Test".into()),
+				..Default::default()
+			},
+		)
+		.unwrap();
+		let source = dir.join("test.json");
+		fs::write(&source, PACKAGE).unwrap();
+		let added = add_package(&dir, &source).unwrap();
+		assert_eq!(added.id, "test".into());
+		assert!(!added.replaced);
+
+		let key = SigningKey::from_bytes(&[7; 32]);
+		generate_index(&dir, Some(&key)).unwrap();
+
+		// Sync the generated repository like a configured local repository
+		let mut paths = Paths::new_no_create().unwrap();
+		paths.cache = dir.join("cache");
+		paths.pkg_cache = dir.join("cache/pkg");
+		paths.pkg_index_cache = dir.join("cache/pkg/index");
+		fs::create_dir_all(&paths.pkg_index_cache).unwrap();
+		let mut repo = PkgRepo::new("test", PkgRepoLocation::Local(get_index_path(&dir)));
+		repo.set_public_key(key.verifying_key());
+		let mut reg = PkgRegistry::new(vec![repo], CachingStrategy::All);
+
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let client = Client::new();
+		let plugins = PluginManager::new();
+		runtime
+			.block_on(reg.update_cached_packages(&paths, &client, &mut NoOp))
+			.unwrap();
+
+		let constants = EvalConstants {
+			version: "1.20.1".into(),
+			version_list: vec!["1.20.1".into()],
+			modifications: GameModifications::new(
+				Modloader::Vanilla,
+				ClientType::Vanilla,
+				ServerType::Vanilla,
+			),
+			language: Language::AmericanEnglish,
+			profile_stability: PackageStability::Latest,
+		};
+		let input = EvalInput {
+			constants: &constants,
+			params: EvalParameters::new(Side::Client),
+		};
+		let req = Arc::new(PkgRequest::parse("test", PkgRequestSource::UserRequire));
+		let eval = runtime
+			.block_on(reg.eval(
+				&req,
+				&paths,
+				Routine::Install,
+				input,
+				&client,
+				&plugins,
+				&mut NoOp,
+			))
+			.unwrap();
+		assert_eq!(eval.addon_reqs.len(), 1);
+		assert_eq!(eval.addon_reqs[0].addon.id, "test");
+		assert!(matches!(eval.addon_reqs[0].addon.kind, AddonKind::Mod));
+
+		// A tampered index is rejected
+		let index_path = get_index_path(&dir);
+		let index = fs::read_to_string(&index_path).unwrap();
+		fs::write(&index_path, index.replace("Test", "Evil")).unwrap();
+		let mut repo = PkgRepo::new("test", PkgRepoLocation::Local(index_path));
+		repo.set_public_key(key.verifying_key());
+		assert!(runtime
+			.block_on(repo.sync(&paths, &client, &mut NoOp))
+			.is_err());
+	}
+}
@@ -1,82 +1,105 @@
+/// Overrides for core packages that are published separately from mcvm releases
+pub mod overrides;
+
 use std::collections::HashSet;
 
 use mcvm_pkg::{repo::RepoPkgEntry, PackageContentType};
 
+use self::overrides::CoreOverrides;
+
+/// A package that is built into the binary
+#[derive(Debug)]
+pub struct CorePackage {
+	/// The ID of the package
+	pub id: &'static str,
+	/// The bundled contents of the package
+	pub contents: &'static str,
+	/// The content type of the bundled contents
+	pub content_type: PackageContentType,
+	/// The version of the bundled contents. This is increased whenever the contents change,
+	/// and overrides are only used when they are newer than it
+	pub content_version: u32,
+}
+
 macro_rules! define_core_packages {
-	($($pkg:literal,$ext:literal,$id:ident,$content:ident);*$(;)?) => {
-		const ALL_CORE_PACKAGE_IDS: &[&str] = &[
+	($($pkg:literal, $ext:literal, $content:ident, $version:literal);*$(;)?) => {
+		static CORE_PACKAGES: &[CorePackage] = &[
 			$(
-				$pkg,
+				CorePackage {
+					id: $pkg,
+					contents: include_str!(concat!($pkg, ".", $ext)),
+					content_type: PackageContentType::$content,
+					content_version: $version,
+				},
 			)*
 		];
+	};
+}
 
-		$(
-			const $id: &str = include_str!(concat!($pkg, ".", $ext));
-		)*
+define_core_packages! {
+	"animated-textures-support", "json", Declarative, 2;
+	"cem-support", "json", Declarative, 2;
+	"cit-support", "json", Declarative, 2;
+	"ctm-support", "json", Declarative, 2;
+	"custom-colors-support", "json", Declarative, 2;
+	"custom-gui-support", "json", Declarative, 2;
+	"custom-sky-support", "json", Declarative, 2;
+	"emissive-blocks-support", "json", Declarative, 2;
+	"emissive-entities-support", "json", Declarative, 2;
+	"fabric-rendering-api", "json", Declarative, 1;
+	"fabriclike-api", "json", Declarative, 2;
+	"fail", "pkg.txt", Script, 1;
+	"hd-fonts-support", "pkg.txt", Script, 2;
+	"kotlin-support", "json", Declarative, 2;
+	"kotlin-support-forgelin", "json", Declarative, 2;
+	"kubejs-script-support", "json", Declarative, 2;
+	"natural-textures-support", "pkg.txt", Script, 2;
+	"none", "json", Declarative, 2;
+	"optifine-resource-packs", "json", Declarative, 2;
+	"optifine-support", "json", Declarative, 2;
+	"quilt-standard-libraries", "json", Declarative, 1;
+	"quilted-fabric-api", "json", Declarative, 1;
+	"random-entities-support", "json", Declarative, 2;
+	"shader-support", "json", Declarative, 2;
+	"splash-screen-support", "json", Declarative, 2;
+}
 
-		pub fn get_core_package(package: &str) -> Option<&'static str> {
-			match package {
-				$(
-					$pkg => Some($id),
-				)*
-				_ => None,
-			}
-		}
+/// Get the bundled information for a core package
+pub fn get_core_package_info(package: &str) -> Option<&'static CorePackage> {
+	CORE_PACKAGES.iter().find(|x| x.id == package)
+}
 
-		pub fn get_core_package_content_type(package: &str) -> Option<PackageContentType> {
-			match package {
-				$(
-					$pkg => Some(PackageContentType::$content),
-				)*
-				_ => None,
-			}
-		}
-	};
+/// Get the bundled contents of a core package
+pub fn get_core_package(package: &str) -> Option<&'static str> {
+	get_core_package_info(package).map(|x| x.contents)
 }
 
-define_core_packages! {
-	"animated-textures-support", "pkg.txt", ANIMATED_TEXTURES_SUPPORT, Script;
-	"cem-support", "pkg.txt", CEM_SUPPORT, Script;
-	"cit-support", "pkg.txt", CIT_SUPPORT, Script;
-	"ctm-support", "pkg.txt", CTM_SUPPORT, Script;
-	"custom-colors-support", "pkg.txt", CUSTOM_COLORS_SUPPORT, Script;
-	"custom-gui-support", "pkg.txt", CUSTOM_GUI_SUPPORT, Script;
-	"custom-sky-support", "pkg.txt", CUSTOM_SKY_SUPPORT, Script;
-	"emissive-blocks-support", "pkg.txt", EMISSIVE_BLOCKS_SUPPORT, Script;
-	"emissive-entities-support", "pkg.txt", EMISSIVE_ENTITIES_SUPPORT, Script;
-	"fabric-rendering-api", "json", FABRIC_RENDERING_API, Declarative;
-	"fabriclike-api", "pkg.txt", FABRICLIKE_API, Script;
-	"fail", "pkg.txt", FAIL, Script;
-	"hd-fonts-support", "pkg.txt", HD_FONTS_SUPPORT, Script;
-	"kotlin-support", "pkg.txt", KOTLIN_SUPPORT, Script;
-	"kotlin-support-forgelin", "pkg.txt", KOTLIN_SUPPORT_FORGELIN, Script;
-	"kubejs-script-support", "pkg.txt", KUBEJS_SCRIPT_SUPPORT, Script;
-	"natural-textures-support", "pkg.txt", NATURAL_TEXTURES_SUPPORT, Script;
-	"none", "pkg.txt", NONE, Script;
-	"optifine-resource-packs", "pkg.txt", OPTIFINE_RESOURCE_PACKS, Script;
-	"optifine-support", "pkg.txt", OPTIFINE_SUPPORT, Script;
-	"quilt-standard-libraries", "json", QUILT_STANDARD_LIBRARIES, Declarative;
-	"quilted-fabric-api", "json", QUILTED_FABRIC_API, Declarative;
-	"random-entities-support", "pkg.txt", RANDOM_ENTITIES_SUPPORT, Script;
-	"shader-support", "pkg.txt", SHADER_SUPPORT, Script;
-	"splash-screen-support", "pkg.txt", SPLASH_SCREEN_SUPPORT, Script;
+/// Get the content type of the bundled contents of a core package
+pub fn get_core_package_content_type(package: &str) -> Option<PackageContentType> {
+	get_core_package_info(package).map(|x| x.content_type)
 }
 
 pub fn is_core_package(package: &str) -> bool {
-	get_core_package(package).is_some()
+	get_core_package_info(package).is_some()
 }
 
-pub fn get_all_core_packages() -> Vec<(String, RepoPkgEntry)> {
+pub fn get_all_core_packages(overrides: &CoreOverrides) -> Vec<(String, RepoPkgEntry)> {
 	let mut out = Vec::new();
-	for pkg in ALL_CORE_PACKAGE_IDS {
-		let content_type = get_core_package_content_type(pkg).expect("Content type should exist");
+	for pkg in CORE_PACKAGES {
+		let content_type = overrides
+			.get(pkg.id)
+			.map_or(pkg.content_type, |x| x.content_type);
 		out.push((
-			pkg.to_string(),
+			pkg.id.to_string(),
 			RepoPkgEntry {
 				url: None,
 				path: None,
 				content_type: Some(content_type),
 				flags: HashSet::new(),
+				hash: None,
+				name: None,
+				description: None,
+				properties: None,
 			},
 		));
 	}
@@ -85,7 +108,7 @@ pub fn get_all_core_packages() -> Vec<(String, RepoPkgEntry)> {
 }
 
 pub fn get_core_package_count() -> usize {
-	ALL_CORE_PACKAGE_IDS.len()
+	CORE_PACKAGES.len()
 }
 
 #[cfg(test)]
@@ -93,21 +116,275 @@ mod tests {
 	use super::*;
 	use mcvm_parse::{parse::lex_and_parse, routine::INSTALL_ROUTINE};
 	use mcvm_pkg::parse_and_validate;
+	use mcvm_shared::lang::Language;
+	use mcvm_shared::modifications::{ClientType, Modloader, ServerType};
+	use mcvm_shared::pkg::{PackageID, PackageStability};
+	use mcvm_shared::Side;
+	use reqwest::Client;
+	use std::path::PathBuf;
+
+	use crate::config::plugin::PluginManager;
+	use crate::config::profile::GameModifications;
+	use crate::io::paths::Paths;
+	use crate::pkg::eval::cases::{get_case_files, run_eval_cases, EvalCaseStatus};
+	use crate::pkg::eval::{EvalConstants, EvalInput, EvalParameters, Routine};
+	use crate::pkg::{Package, PkgLocation};
 
 	#[test]
 	fn test_core_package_parse() {
-		for package in ALL_CORE_PACKAGE_IDS {
-			println!("Package: {package}");
-			let contents = get_core_package(package).unwrap();
-			let content_type = get_core_package_content_type(package).unwrap();
-			parse_and_validate(contents, content_type).unwrap();
-
-			if let PackageContentType::Script = content_type {
-				let parsed = lex_and_parse(contents).unwrap();
-				if *package != "none" {
-					assert!(parsed.routines.contains_key(INSTALL_ROUTINE));
+		for package in CORE_PACKAGES {
+			println!("Package: {}", package.id);
+			parse_and_validate(package.contents, package.content_type).unwrap();
+
+			if let PackageContentType::Script = package.content_type {
+				let parsed = lex_and_parse(package.contents).unwrap();
+				assert!(parsed.routines.contains_key(INSTALL_ROUTINE));
+			}
+		}
+	}
+
+	/// The result of evaluating a core package
+	#[derive(Debug)]
+	struct EvalResult {
+		deps: Vec<String>,
+		explicit_deps: Vec<String>,
+		bundled: Vec<String>,
+	}
+
+	/// Every modloader, including an unknown one
+	fn all_modloaders() -> [Modloader; 9] {
+		[
+			Modloader::Vanilla,
+			Modloader::Forge,
+			Modloader::NeoForged,
+			Modloader::Fabric,
+			Modloader::Quilt,
+			Modloader::LiteLoader,
+			Modloader::Risugamis,
+			Modloader::Rift,
+			Modloader::Unknown("custom".into()),
+		]
+	}
+
+	fn eval_core_package(package: &str, modloader: Modloader) -> anyhow::Result<EvalResult> {
+		let (client_type, server_type) = match &modloader {
+			Modloader::Vanilla => (ClientType::Vanilla, ServerType::Vanilla),
+			Modloader::Forge => (ClientType::Forge, ServerType::Forge),
+			Modloader::Fabric => (ClientType::Fabric, ServerType::Fabric),
+			Modloader::Quilt => (ClientType::Quilt, ServerType::Quilt),
+			Modloader::NeoForged => (ClientType::NeoForged, ServerType::NeoForged),
+			Modloader::LiteLoader => (ClientType::LiteLoader, ServerType::None),
+			Modloader::Risugamis => (ClientType::Risugamis, ServerType::Risugamis),
+			Modloader::Rift => (ClientType::Rift, ServerType::Rift),
+			Modloader::Unknown(loader) => (
+				ClientType::Unknown(loader.clone()),
+				ServerType::Unknown(loader.clone()),
+			),
+		};
+		let constants = EvalConstants {
+			version: "1.20.1".into(),
+			version_list: ["1.7.10", "1.8", "1.12.2", "1.13", "1.20.1"]
+				.map(String::from)
+				.to_vec(),
+			modifications: GameModifications::new(modloader, client_type, server_type),
+			language: Language::AmericanEnglish,
+			profile_stability: PackageStability::Latest,
+		};
+		let input = EvalInput {
+			constants: &constants,
+			params: EvalParameters::new(Side::Client),
+		};
+		let paths = Paths::new_no_create().unwrap();
+		let plugins = PluginManager::new();
+		let client = Client::new();
+
+		let info = get_core_package_info(package).unwrap();
+		let mut pkg = Package::new(
+			PackageID::from(package),
+			PkgLocation::Core,
+			info.content_type,
+			HashSet::new(),
+		);
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let eval = runtime.block_on(pkg.eval(
+			&paths,
+			Routine::InstallResolve,
+			input,
+			&client,
+			&plugins,
+		))?;
+
+		let mut out = EvalResult {
+			deps: Vec::new(),
+			explicit_deps: Vec::new(),
+			bundled: eval.bundled.iter().map(|x| x.to_string()).collect(),
+		};
+		for dep in eval.deps.iter().flatten() {
+			if dep.explicit {
+				out.explicit_deps.push(dep.value.to_string());
+			} else {
+				out.deps.push(dep.value.to_string());
+			}
+		}
+
+		Ok(out)
+	}
+
+	#[test]
+	fn test_core_package_eval() {
+		for package in CORE_PACKAGES {
+			let mut succeeded = false;
+			for modloader in all_modloaders() {
+				println!("Package: {} ({modloader})", package.id);
+				succeeded |= eval_core_package(package.id, modloader).is_ok();
+			}
+			assert_eq!(succeeded, package.id != "fail");
+		}
+	}
+
+	#[test]
+	fn test_core_package_eval_relations() {
+		let result = eval_core_package("cem-support", Modloader::Forge).unwrap();
+		assert_eq!(result.deps, vec!["optifine-support"]);
+		let result = eval_core_package("cem-support", Modloader::Quilt).unwrap();
+		assert_eq!(result.deps, vec!["entity-model-features"]);
+		assert!(eval_core_package("cem-support", Modloader::Vanilla).is_err());
+
+		let result = eval_core_package("fabriclike-api", Modloader::Quilt).unwrap();
+		assert_eq!(result.deps, vec!["quilted-fabric-api"]);
+		assert!(eval_core_package("fabriclike-api", Modloader::Forge).is_err());
+
+		let result = eval_core_package("kotlin-support-forgelin", Modloader::Forge).unwrap();
+		assert_eq!(result.deps, vec!["forgelin"]);
+
+		let result = eval_core_package("shader-support", Modloader::Fabric).unwrap();
+		assert_eq!(result.explicit_deps, vec!["iris"]);
+		assert!(result.deps.is_empty());
+
+		let result = eval_core_package("optifine-support", Modloader::Vanilla).unwrap();
+		assert_eq!(result.explicit_deps, vec!["optifine"]);
+		assert!(result.deps.is_empty());
+		let result = eval_core_package("optifine-support", Modloader::Fabric).unwrap();
+		assert_eq!(result.deps, vec!["optifabric"]);
+
+		let result = eval_core_package("optifine-resource-packs", Modloader::Fabric).unwrap();
+		assert_eq!(result.bundled.len(), 13);
+		assert!(result.deps.is_empty());
+	}
+
+	#[test]
+	fn test_core_package_eval_modloaders() {
+		// The dependencies of each package on every modloader, or None if it doesn't support it
+		let expected: [(&str, [Option<&str>; 9]); 3] = [
+			(
+				"fabriclike-api",
+				[
+					None,
+					None,
+					None,
+					Some("fabric-api"),
+					Some("quilted-fabric-api"),
+					None,
+					None,
+					None,
+					None,
+				],
+			),
+			(
+				"cem-support",
+				[
+					None,
+					Some("optifine-support"),
+					Some("optifine-support"),
+					Some("entity-model-features"),
+					Some("entity-model-features"),
+					None,
+					None,
+					None,
+					None,
+				],
+			),
+			(
+				"kotlin-support",
+				[
+					None,
+					Some("kotlin-for-forge"),
+					Some("kotlin-for-forge"),
+					Some("fabric-language-kotlin"),
+					Some("quilt-kotlin-libraries"),
+					None,
+					None,
+					None,
+					None,
+				],
+			),
+		];
+
+		for (package, deps) in expected {
+			for (modloader, dep) in all_modloaders().into_iter().zip(deps) {
+				let result = eval_core_package(package, modloader.clone());
+				match dep {
+					Some(dep) => {
+						assert_eq!(result.unwrap().deps, vec![dep], "{package} on {modloader}")
+					}
+					None => assert!(result.is_err(), "{package} on {modloader}"),
+				}
+			}
+		}
+	}
+
+	/// Set this environment variable to replace the snapshots of the core package cases
+	/// with the current evaluation results
+	const UPDATE_SNAPSHOTS_VAR: &str = "MCVM_UPDATE_SNAPSHOTS";
+
+	#[test]
+	fn test_core_package_cases() {
+		let cases_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/pkg/core/cases");
+		let update = std::env::var_os(UPDATE_SNAPSHOTS_VAR).is_some();
+		let paths = Paths::new_no_create().unwrap();
+		let plugins = PluginManager::new();
+		let client = Client::new();
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+
+		let mut failures = Vec::new();
+		for package in CORE_PACKAGES {
+			let dir = cases_dir.join(package.id);
+			let case_count = get_case_files(&dir).map_or(0, |x| x.len());
+			assert!(
+				case_count >= 3,
+				"Core package '{}' needs at least three cases in {}",
+				package.id,
+				dir.display()
+			);
+
+			let mut pkg = Package::new(
+				PackageID::from(package.id),
+				PkgLocation::Core,
+				package.content_type,
+				HashSet::new(),
+			);
+			let results = runtime
+				.block_on(run_eval_cases(
+					&mut pkg, &dir, update, &paths, &client, &plugins,
+				))
+				.unwrap();
+			for result in results {
+				match result.status {
+					EvalCaseStatus::Failed(differences) => failures.push(format!(
+						"{}/{}:\n  {}",
+						package.id,
+						result.name,
+						differences.join("\n  ")
+					)),
+					EvalCaseStatus::MissingSnapshot => failures.push(format!(
+						"{}/{}: No expected result. Run with {UPDATE_SNAPSHOTS_VAR}=1 to create it",
+						package.id, result.name
+					)),
+					EvalCaseStatus::Passed | EvalCaseStatus::Updated => {}
 				}
 			}
 		}
+
+		assert!(failures.is_empty(), "{}", failures.join("\n"));
 	}
 }
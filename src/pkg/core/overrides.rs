@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use mcvm_core::io::{json_from_file, json_to_file};
+use mcvm_core::net::download;
+use mcvm_pkg::PackageContentType;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::io::paths::Paths;
+use crate::pkg::repo::{parse_public_key, verify_index_signature};
+
+use super::get_core_package_info;
+
+/// The URL that core package overrides are published to
+pub const CORE_OVERRIDES_URL: &str = "https://mcvm-launcher.github.io/packages/core-overrides";
+/// The hex-encoded key that the core override manifest is signed with
+pub const CORE_OVERRIDES_PUBLIC_KEY: &str =
+	"3003c1bd7ac353c5b73f60095e5f7f20b4079aae9413536cb21191261d316bce";
+
+/// The file name of the override manifest
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Signed list of the core packages that have newer contents than the ones bundled with mcvm
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct CoreOverrideManifest {
+	/// The overridden packages
+	pub packages: HashMap<String, CoreOverrideEntry>,
+}
+
+/// Entry for a single package in the override manifest
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CoreOverrideEntry {
+	/// The content version of the override
+	pub content_version: u32,
+	/// The content type of the override
+	#[serde(default)]
+	pub content_type: PackageContentType,
+	/// The SHA-256 hash of the override contents, encoded as hex
+	pub hash: String,
+}
+
+impl CoreOverrideEntry {
+	/// Get the file name of the override contents
+	fn filename(&self, id: &str) -> String {
+		let extension = match self.content_type {
+			PackageContentType::Declarative => "json",
+			PackageContentType::Script => "pkg.txt",
+		};
+		format!("{id}.{extension}")
+	}
+}
+
+/// An override that should be used instead of the bundled contents of a core package
+#[derive(Debug, Clone)]
+pub struct CoreOverride {
+	/// The path to the override contents
+	pub path: PathBuf,
+	/// The content type of the override
+	pub content_type: PackageContentType,
+}
+
+/// The core package overrides that have been downloaded
+#[derive(Debug, Default)]
+pub struct CoreOverrides {
+	manifest: CoreOverrideManifest,
+	dir: PathBuf,
+}
+
+impl CoreOverrides {
+	/// Load the downloaded overrides. If none have been downloaded, this will be empty
+	pub fn load(paths: &Paths) -> anyhow::Result<Self> {
+		let dir = get_overrides_dir(paths);
+		let manifest_path = dir.join(MANIFEST_FILE);
+		let manifest = if manifest_path.exists() {
+			json_from_file(manifest_path).context("Failed to read core override manifest")?
+		} else {
+			CoreOverrideManifest::default()
+		};
+
+		Ok(Self { manifest, dir })
+	}
+
+	/// Get a string that changes whenever the overrides change
+	pub fn get_fingerprint(&self) -> String {
+		self.manifest
+			.packages
+			.iter()
+			.map(|(id, entry)| format!("{id}:{}", entry.hash))
+			.sorted()
+			.join(",")
+	}
+
+	/// Get the override for a core package, if there is one that is newer than the bundled contents
+	pub fn get(&self, id: &str) -> Option<CoreOverride> {
+		let entry = self.manifest.packages.get(id)?;
+		if !should_use_override(id, entry.content_version) {
+			return None;
+		}
+		let path = self.dir.join(entry.filename(id));
+		if !path.exists() {
+			return None;
+		}
+
+		Some(CoreOverride {
+			path,
+			content_type: entry.content_type,
+		})
+	}
+}
+
+/// Check whether an override with a content version should be used instead of the bundled package
+fn should_use_override(id: &str, content_version: u32) -> bool {
+	get_core_package_info(id).is_some_and(|x| content_version > x.content_version)
+}
+
+/// Get the directory that core package overrides are stored in
+pub fn get_overrides_dir(paths: &Paths) -> PathBuf {
+	paths.internal.join("core_overrides")
+}
+
+/// Download the latest core package overrides and verify them. If the server doesn't
+/// publish any overrides, the existing ones are removed so that the bundled packages are used
+pub async fn sync_core_overrides(paths: &Paths, client: &Client) -> anyhow::Result<()> {
+	let dir = get_overrides_dir(paths);
+	let manifest_path = dir.join(MANIFEST_FILE);
+
+	let manifest_url = format!("{CORE_OVERRIDES_URL}/{MANIFEST_FILE}");
+	let response = client
+		.get(&manifest_url)
+		.send()
+		.await
+		.context("Failed to send request for override manifest")?;
+	if response.status() == StatusCode::NOT_FOUND {
+		if manifest_path.exists() {
+			std::fs::remove_file(&manifest_path)
+				.context("Failed to remove old override manifest")?;
+		}
+		return Ok(());
+	}
+	let manifest_bytes = response
+		.error_for_status()
+		.context("Server reported an error")?
+		.bytes()
+		.await
+		.context("Failed to download override manifest")?;
+	let signature = download::bytes(format!("{manifest_url}.sig"), client)
+		.await
+		.context("Failed to download override manifest signature")?;
+
+	let key = parse_public_key(CORE_OVERRIDES_PUBLIC_KEY)?;
+	verify_index_signature(&manifest_bytes, &signature, &key)
+		.context("Core override manifest failed verification")?;
+	let manifest: CoreOverrideManifest =
+		serde_json::from_slice(&manifest_bytes).context("Failed to parse override manifest")?;
+
+	std::fs::create_dir_all(&dir).context("Failed to create core overrides directory")?;
+	for (id, entry) in &manifest.packages {
+		// Overrides for packages we don't have, or that we already have newer versions of, are useless
+		if !should_use_override(id, entry.content_version) {
+			continue;
+		}
+		let filename = entry.filename(id);
+		let contents = download::bytes(format!("{CORE_OVERRIDES_URL}/{filename}"), client)
+			.await
+			.with_context(|| format!("Failed to download override for package '{id}'"))?;
+		let hash = hex::encode(Sha256::digest(&contents));
+		if !hash.eq_ignore_ascii_case(&entry.hash) {
+			bail!("Override for package '{id}' does not match the hash in the manifest");
+		}
+		std::fs::write(dir.join(filename), &contents)
+			.with_context(|| format!("Failed to write override for package '{id}'"))?;
+	}
+
+	// The manifest is written last so that it never points to overrides that weren't downloaded
+	json_to_file(manifest_path, &manifest).context("Failed to write override manifest")?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_override_version() {
+		let bundled = get_core_package_info("cem-support")
+			.unwrap()
+			.content_version;
+		assert!(!should_use_override("cem-support", bundled));
+		assert!(!should_use_override("cem-support", bundled - 1));
+		assert!(should_use_override("cem-support", bundled + 1));
+		assert!(!should_use_override("not-a-core-package", 100));
+	}
+
+	#[test]
+	fn test_public_key() {
+		parse_public_key(CORE_OVERRIDES_PUBLIC_KEY).unwrap();
+	}
+}
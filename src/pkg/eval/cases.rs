@@ -0,0 +1,475 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use mcvm_parse::vars::is_reserved_constant_var;
+use mcvm_pkg::{RecommendedPackage, RequiredPackage};
+use mcvm_shared::addon::AddonKind;
+use mcvm_shared::lang::Language;
+use mcvm_shared::modifications::{ClientType, Modloader, ServerType};
+use mcvm_shared::pkg::{PackageID, PackageStability};
+use mcvm_shared::Side;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{EvalConstants, EvalData, EvalInput, EvalParameters, EvalPermissions, Routine};
+use crate::addon::AddonLocation;
+use crate::config::plugin::PluginManager;
+use crate::config::profile::GameModifications;
+use crate::io::paths::Paths;
+use crate::pkg::Package;
+
+/// A test case for a package, which evaluates it with some input and checks the result
+#[derive(Deserialize, Debug, Clone)]
+pub struct EvalCase {
+	/// What this case is testing
+	#[serde(default)]
+	pub description: Option<String>,
+	/// The input to evaluate the package with
+	pub input: EvalCaseInput,
+	/// The result that the evaluation should have. Cases without one always fail
+	/// until their snapshot is updated
+	#[serde(default)]
+	pub expected: Option<EvalSummary>,
+}
+
+/// The input for an evaluation in a test case. Every field is optional
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct EvalCaseInput {
+	/// The Minecraft version
+	pub version: String,
+	/// The list of available Minecraft versions, used to match version patterns.
+	/// Defaults to only the Minecraft version
+	pub version_list: Vec<String>,
+	/// The modloader
+	pub modloader: Modloader,
+	/// The client type, which overrides the modloader on the client
+	pub client_type: ClientType,
+	/// The server type, which overrides the modloader on the server
+	pub server_type: ServerType,
+	/// The side the package is installed on
+	pub side: Side,
+	/// Features enabled for the package
+	pub features: Vec<String>,
+	/// Requested stability of the package's contents
+	pub stability: PackageStability,
+	/// Permissions for the package
+	pub permissions: EvalPermissions,
+	/// The user's configured language
+	pub language: Language,
+}
+
+impl Default for EvalCaseInput {
+	fn default() -> Self {
+		Self {
+			version: "1.20.1".into(),
+			version_list: Vec::new(),
+			modloader: Modloader::Vanilla,
+			client_type: ClientType::None,
+			server_type: ServerType::None,
+			side: Side::Client,
+			features: Vec::new(),
+			stability: PackageStability::default(),
+			permissions: EvalPermissions::default(),
+			language: Language::AmericanEnglish,
+		}
+	}
+}
+
+impl EvalCaseInput {
+	/// Create the constants for evaluating with this input
+	pub fn get_constants(&self) -> EvalConstants {
+		let version_list = if self.version_list.is_empty() {
+			vec![self.version.clone()]
+		} else {
+			self.version_list.clone()
+		};
+
+		EvalConstants {
+			version: self.version.clone(),
+			modifications: GameModifications::new(
+				self.modloader.clone(),
+				self.client_type.clone(),
+				self.server_type.clone(),
+			),
+			version_list,
+			language: self.language,
+			profile_stability: self.stability,
+		}
+	}
+
+	/// Create the parameters for evaluating with this input
+	pub fn get_params(&self) -> EvalParameters {
+		let mut params = EvalParameters::new(self.side);
+		params.features = self.features.clone();
+		params.stability = self.stability;
+		params.perms = self.permissions;
+		params
+	}
+}
+
+/// The parts of an evaluation that are checked by test cases. Empty fields are
+/// left out so that snapshots only show what the package produced
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EvalSummary {
+	/// The error that the evaluation failed with
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+	/// The requested addons
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub addons: Vec<AddonSummary>,
+	/// The dependencies, from evaluating for resolution
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub deps: Vec<Vec<RequiredPackage>>,
+	/// The conflicts
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub conflicts: Vec<PackageID>,
+	/// The recommendations
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub recommendations: Vec<RecommendedPackage>,
+	/// The bundled packages
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub bundled: Vec<PackageID>,
+	/// The compats
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub compats: Vec<(PackageID, PackageID)>,
+	/// The package extensions
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub extensions: Vec<PackageID>,
+	/// The notices
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub notices: Vec<String>,
+	/// The commands
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub commands: Vec<Vec<String>>,
+	/// The paths of the config files
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub config_files: Vec<String>,
+	/// The variables that a script package set, not including reserved constants
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	pub vars: BTreeMap<String, String>,
+}
+
+/// The parts of an addon request that are checked by test cases
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AddonSummary {
+	/// The ID of the addon
+	pub id: String,
+	/// The kind of the addon
+	pub kind: AddonKind,
+	/// The file name of the addon
+	pub file_name: String,
+	/// The version of the addon
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub version: Option<String>,
+	/// The URL the addon is downloaded from
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub url: Option<String>,
+	/// The local path the addon is copied from
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub path: Option<PathBuf>,
+}
+
+impl EvalSummary {
+	/// Summarize the results of evaluating a package for resolution and installation
+	pub fn from_evals(resolve_eval: &EvalData, install_eval: &EvalData) -> Self {
+		let addons = install_eval
+			.addon_reqs
+			.iter()
+			.map(|x| {
+				let (url, path) = match x.get_location() {
+					AddonLocation::Remote(url) => (Some(url.clone()), None),
+					AddonLocation::Local(path) => (None, Some(path.clone())),
+				};
+				AddonSummary {
+					id: x.addon.id.clone(),
+					kind: x.addon.kind,
+					file_name: x.addon.file_name.clone(),
+					version: x.addon.version.clone(),
+					url,
+					path,
+				}
+			})
+			.collect();
+
+		let vars = install_eval
+			.vars
+			.iter()
+			.filter(|(key, _)| !is_reserved_constant_var(key))
+			.map(|(key, value)| (key.clone(), value.clone()))
+			.collect();
+
+		Self {
+			error: None,
+			addons,
+			deps: resolve_eval.deps.clone(),
+			conflicts: resolve_eval.conflicts.clone(),
+			recommendations: resolve_eval.recommendations.clone(),
+			bundled: resolve_eval.bundled.clone(),
+			compats: resolve_eval.compats.clone(),
+			extensions: resolve_eval.extensions.clone(),
+			notices: install_eval.notices.clone(),
+			commands: install_eval.commands.clone(),
+			config_files: install_eval
+				.config_files
+				.iter()
+				.map(|x| x.path.clone())
+				.collect(),
+			vars,
+		}
+	}
+
+	/// Summarize an evaluation that failed
+	pub fn from_error(error: &anyhow::Error) -> Self {
+		Self {
+			error: Some(format!("{error:#}")),
+			..Default::default()
+		}
+	}
+}
+
+/// The result of running a test case
+#[derive(Debug, Clone)]
+pub struct EvalCaseResult {
+	/// The name of the case file, without its extension
+	pub name: String,
+	/// What happened when the case was run
+	pub status: EvalCaseStatus,
+}
+
+/// What happened when a test case was run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalCaseStatus {
+	/// The evaluation matched the expected result
+	Passed,
+	/// The evaluation was different from the expected result, with a description of each difference
+	Failed(Vec<String>),
+	/// The case has no expected result yet
+	MissingSnapshot,
+	/// The expected result of the case was replaced with the result of the evaluation
+	Updated,
+}
+
+impl EvalCaseResult {
+	/// Checks if this case failed
+	pub fn is_failure(&self) -> bool {
+		matches!(
+			self.status,
+			EvalCaseStatus::Failed(..) | EvalCaseStatus::MissingSnapshot
+		)
+	}
+}
+
+/// Evaluate a package with the input of a test case. Like when installing, the relations of the package come
+/// from evaluating it for resolution and everything else comes from evaluating it for installation
+pub async fn eval_case(
+	package: &mut Package,
+	input: &EvalCaseInput,
+	paths: &Paths,
+	client: &Client,
+	plugins: &PluginManager,
+) -> EvalSummary {
+	let constants = input.get_constants();
+	let params = input.get_params();
+	let resolve_input = EvalInput {
+		constants: &constants,
+		params: params.clone(),
+	};
+	let resolve_eval = match package
+		.eval(
+			paths,
+			Routine::InstallResolve,
+			resolve_input,
+			client,
+			plugins,
+		)
+		.await
+	{
+		Ok(eval) => eval,
+		Err(e) => return EvalSummary::from_error(&e),
+	};
+
+	let install_input = EvalInput {
+		constants: &constants,
+		params,
+	};
+	match package
+		.eval(paths, Routine::Install, install_input, client, plugins)
+		.await
+	{
+		Ok(install_eval) => EvalSummary::from_evals(&resolve_eval, &install_eval),
+		Err(e) => EvalSummary::from_error(&e),
+	}
+}
+
+/// Run a package against every case file in a directory. With `update`, the expected results
+/// of the cases are replaced with the actual ones instead of being compared
+pub async fn run_eval_cases(
+	package: &mut Package,
+	dir: &Path,
+	update: bool,
+	paths: &Paths,
+	client: &Client,
+	plugins: &PluginManager,
+) -> anyhow::Result<Vec<EvalCaseResult>> {
+	let mut out = Vec::new();
+	for path in get_case_files(dir)? {
+		let name = path
+			.file_stem()
+			.unwrap_or_default()
+			.to_string_lossy()
+			.to_string();
+		let contents = std::fs::read_to_string(&path)
+			.with_context(|| format!("Failed to read case file '{name}'"))?;
+		let case: EvalCase = serde_json::from_str(&contents)
+			.with_context(|| format!("Failed to parse case file '{name}'"))?;
+
+		let actual = eval_case(package, &case.input, paths, client, plugins).await;
+
+		let status = if update {
+			write_snapshot(&path, &contents, &actual)
+				.with_context(|| format!("Failed to update snapshot of case '{name}'"))?;
+			EvalCaseStatus::Updated
+		} else if let Some(expected) = &case.expected {
+			let differences = diff_summaries(expected, &actual)?;
+			if differences.is_empty() {
+				EvalCaseStatus::Passed
+			} else {
+				EvalCaseStatus::Failed(differences)
+			}
+		} else {
+			EvalCaseStatus::MissingSnapshot
+		};
+
+		out.push(EvalCaseResult { name, status });
+	}
+
+	Ok(out)
+}
+
+/// Get the case files in a directory, sorted by name
+pub fn get_case_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+	let mut out = Vec::new();
+	let entries = dir
+		.read_dir()
+		.with_context(|| format!("Failed to read case directory {}", dir.display()))?;
+	for entry in entries {
+		let path = entry?.path();
+		if path.is_file() && path.extension().is_some_and(|x| x == "json") {
+			out.push(path);
+		}
+	}
+	out.sort();
+
+	Ok(out)
+}
+
+/// Replace the expected result in a case file, keeping the rest of the file
+fn write_snapshot(path: &Path, contents: &str, actual: &EvalSummary) -> anyhow::Result<()> {
+	let mut case: serde_json::Map<String, Value> = serde_json::from_str(contents)?;
+	case.insert("expected".into(), serde_json::to_value(actual)?);
+	let mut contents = serde_json::to_string_pretty(&case)?;
+	contents.push('\n');
+	std::fs::write(path, contents)?;
+
+	Ok(())
+}
+
+/// Compare an expected and actual summary, describing every difference between them
+pub fn diff_summaries(expected: &EvalSummary, actual: &EvalSummary) -> anyhow::Result<Vec<String>> {
+	let expected = serde_json::to_value(expected)?;
+	let actual = serde_json::to_value(actual)?;
+	let mut out = Vec::new();
+	diff_values("", &expected, &actual, &mut out);
+	Ok(out)
+}
+
+/// Recursively compare JSON values, adding the differences to the output with the path to them
+fn diff_values(path: &str, expected: &Value, actual: &Value, out: &mut Vec<String>) {
+	match (expected, actual) {
+		(Value::Object(expected), Value::Object(actual)) => {
+			let mut keys: Vec<_> = expected.keys().chain(actual.keys()).collect();
+			keys.sort();
+			keys.dedup();
+			for key in keys {
+				let path = if path.is_empty() {
+					key.clone()
+				} else {
+					format!("{path}.{key}")
+				};
+				match (expected.get(key), actual.get(key)) {
+					(Some(expected), Some(actual)) => diff_values(&path, expected, actual, out),
+					(Some(expected), None) => {
+						out.push(format!("{path}: expected {expected}, but it is missing"))
+					}
+					(None, Some(actual)) => out.push(format!("{path}: unexpected {actual}")),
+					(None, None) => {}
+				}
+			}
+		}
+		(Value::Array(expected), Value::Array(actual)) => {
+			for i in 0..expected.len().max(actual.len()) {
+				let path = format!("{path}[{i}]");
+				match (expected.get(i), actual.get(i)) {
+					(Some(expected), Some(actual)) => diff_values(&path, expected, actual, out),
+					(Some(expected), None) => {
+						out.push(format!("{path}: expected {expected}, but it is missing"))
+					}
+					(None, Some(actual)) => out.push(format!("{path}: unexpected {actual}")),
+					(None, None) => {}
+				}
+			}
+		}
+		(expected, actual) => {
+			if expected != actual {
+				out.push(format!("{path}: expected {expected}, found {actual}"));
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_summary_diff() {
+		let expected: EvalSummary = serde_json::from_value(serde_json::json!({
+			"deps": [[{ "value": "foo", "explicit": false }]],
+			"notices": ["Hello"],
+			"vars": { "a": "1" }
+		}))
+		.unwrap();
+		let actual: EvalSummary = serde_json::from_value(serde_json::json!({
+			"deps": [[{ "value": "bar", "explicit": false }], [{ "value": "baz", "explicit": true }]],
+			"vars": { "a": "1", "b": "2" }
+		}))
+		.unwrap();
+
+		assert!(diff_summaries(&expected, &expected).unwrap().is_empty());
+		assert_eq!(
+			diff_summaries(&expected, &actual).unwrap(),
+			vec![
+				r#"deps[0][0].value: expected "foo", found "bar""#,
+				r#"deps[1]: unexpected [{"explicit":true,"value":"baz"}]"#,
+				r#"notices: expected ["Hello"], but it is missing"#,
+				r#"vars.b: unexpected "2""#,
+			]
+		);
+	}
+
+	#[test]
+	fn test_case_input_defaults() {
+		let input: EvalCaseInput = serde_json::from_str(r#"{ "modloader": "fabric" }"#).unwrap();
+		let constants = input.get_constants();
+		assert_eq!(constants.version_list, vec!["1.20.1"]);
+		assert_eq!(
+			constants.modifications.get_modloader(Side::Server),
+			Modloader::Fabric
+		);
+		assert_eq!(input.get_params().perms, EvalPermissions::Standard);
+	}
+}
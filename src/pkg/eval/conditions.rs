@@ -1,3 +1,4 @@
+use anyhow::Context;
 use mcvm_shared::versions::VersionPattern;
 use mcvm_shared::Side;
 
@@ -17,7 +18,9 @@ pub fn eval_condition(condition: &ConditionKind, eval: &EvalData) -> anyhow::Res
 		}
 		ConditionKind::Version(version) => {
 			let version = version.get(&eval.vars)?;
-			let version = VersionPattern::from(&version);
+			let version: VersionPattern = version
+				.parse()
+				.with_context(|| format!("Invalid version pattern '{version}'"))?;
 			Ok(version.matches_single(
 				&eval.input.constants.version,
 				&eval.input.constants.version_list,
@@ -3,7 +3,7 @@ use mcvm_pkg::declarative::{
 	DeclarativeAddon, DeclarativeAddonVersion, DeclarativeConditionSet, DeclarativePackage,
 };
 use mcvm_pkg::properties::PackageProperties;
-use mcvm_pkg::script_eval::AddonInstructionData;
+use mcvm_pkg::script_eval::{AddonInstructionData, ConfigFileInstructionData};
 use mcvm_pkg::RequiredPackage;
 use mcvm_shared::pkg::PackageID;
 
@@ -11,8 +11,8 @@ use crate::config::plugin::PluginManager;
 
 use super::conditions::{check_arch_condition, check_os_condition};
 use super::{
-	create_valid_addon_request, EvalData, EvalInput, Routine, MAX_NOTICE_CHARACTERS,
-	MAX_NOTICE_INSTRUCTIONS,
+	create_valid_addon_request, create_valid_config_file_request, EvalData, EvalInput, Routine,
+	MAX_NOTICE_CHARACTERS, MAX_NOTICE_INSTRUCTIONS,
 };
 
 /// Evaluate a declarative package
@@ -49,10 +49,8 @@ fn eval_declarative_package_impl<'a>(
 
 	// Apply conditional rules
 	for rule in &contents.conditional_rules {
-		for condition in &rule.conditions {
-			if !check_condition_set(condition, &eval_data.input) {
-				continue;
-			}
+		if !check_multiple_condition_sets(&rule.conditions, &eval_data.input) {
+			continue;
 		}
 
 		relations.merge(rule.properties.relations.clone());
@@ -67,7 +65,7 @@ fn eval_declarative_package_impl<'a>(
 		}
 
 		// Pick the best version
-		let version = pick_best_addon_version(&addon.versions, &eval_data.input);
+		let version = pick_best_addon_version(addon_id, &addon.versions, &eval_data.input);
 		if let Some(version) = version {
 			let data = AddonInstructionData {
 				id: addon_id.clone(),
@@ -90,6 +88,30 @@ fn eval_declarative_package_impl<'a>(
 		}
 	}
 
+	// Select config files
+	for config_file in &contents.config_files {
+		if !check_multiple_condition_sets(&config_file.conditions, &eval_data.input) {
+			continue;
+		}
+
+		if eval_data
+			.config_files
+			.iter()
+			.any(|x| x.path == config_file.path)
+		{
+			bail!("Duplicate config file '{}'", config_file.path);
+		}
+
+		let data = ConfigFileInstructionData {
+			path: config_file.path.clone(),
+			contents: config_file.contents.clone(),
+			url: config_file.url.clone(),
+			policy: config_file.policy,
+		};
+		let request = create_valid_config_file_request(data, &eval_data.input)?;
+		eval_data.config_files.push(request);
+	}
+
 	eval_data
 		.deps
 		.extend(relations.dependencies.iter().map(|x| {
@@ -143,9 +165,18 @@ fn eval_declarative_package_impl<'a>(
 
 /// Pick the best addon version from a list of declarative addon versions
 pub fn pick_best_addon_version<'a>(
+	addon_id: &str,
 	versions: &'a [DeclarativeAddonVersion],
 	input: &'a EvalInput<'a>,
 ) -> Option<&'a DeclarativeAddonVersion> {
+	// A pinned version overrides the normal selection if it still exists
+	if let Some(pinned) = input.params.pinned_addon_versions.get(addon_id) {
+		let version = versions.iter().find(|x| x.version.as_ref() == Some(pinned));
+		if version.is_some() {
+			return version;
+		}
+	}
+
 	// Filter versions that are not allowed
 	let mut versions = versions
 		.iter()
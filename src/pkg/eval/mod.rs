@@ -1,3 +1,5 @@
+/// Running packages against test cases with expected results
+pub mod cases;
 /// Evaluating script package conditions
 pub mod conditions;
 /// Evaluating declarative packages
@@ -5,6 +7,7 @@ pub mod declarative;
 /// Evaluating script packages
 pub mod script;
 
+use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use async_trait::async_trait;
@@ -13,6 +16,7 @@ use mcvm_parse::vars::HashMapVariableStore;
 use mcvm_pkg::properties::PackageProperties;
 use mcvm_pkg::resolve::ResolutionResult;
 use mcvm_pkg::script_eval::AddonInstructionData;
+use mcvm_pkg::script_eval::ConfigFileInstructionData;
 use mcvm_pkg::script_eval::EvalReason;
 use mcvm_pkg::ConfiguredPackage;
 use mcvm_pkg::PackageContentType;
@@ -22,13 +26,15 @@ use mcvm_pkg::{
 	PackageEvalRelationsResult as EvalRelationsResultTrait,
 	PackageEvaluator as PackageEvaluatorTrait,
 };
-use mcvm_shared::addon::{is_addon_version_valid, is_filename_valid, Addon};
+use mcvm_shared::addon::{is_addon_version_valid, is_filename_valid, Addon, AddonKind};
 use mcvm_shared::lang::Language;
+use mcvm_shared::modifications::ServerType;
 use mcvm_shared::output;
 use mcvm_shared::output::MCVMOutput;
 use mcvm_shared::output::MessageContents;
 use mcvm_shared::output::MessageLevel;
 use mcvm_shared::pkg::ArcPkgReq;
+use mcvm_shared::pkg::ConfigFilePolicy;
 use mcvm_shared::pkg::PackageID;
 use mcvm_shared::util::is_valid_identifier;
 use reqwest::Client;
@@ -55,7 +61,8 @@ use crate::util::hash::{
 use mcvm_shared::pkg::PackageStability;
 use mcvm_shared::Side;
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 
 /// Max notice instructions per package
 const MAX_NOTICE_INSTRUCTIONS: usize = 10;
@@ -63,7 +70,7 @@ const MAX_NOTICE_INSTRUCTIONS: usize = 10;
 const MAX_NOTICE_CHARACTERS: usize = 128;
 
 /// Permissions level for an evaluation
-#[derive(Deserialize, Serialize, Debug, Copy, Clone, Default)]
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum EvalPermissions {
@@ -142,6 +149,8 @@ pub struct EvalParameters {
 	pub stability: PackageStability,
 	/// Requested worlds to put addons in
 	pub worlds: Vec<String>,
+	/// Addon versions to use instead of the best match, keyed by addon ID
+	pub pinned_addon_versions: HashMap<String, String>,
 }
 
 impl EvalParameters {
@@ -154,6 +163,7 @@ impl EvalParameters {
 			perms: EvalPermissions::default(),
 			stability: PackageStability::default(),
 			worlds: Vec::new(),
+			pinned_addon_versions: HashMap::new(),
 		}
 	}
 }
@@ -191,6 +201,8 @@ pub struct EvalData<'a> {
 	pub notices: Vec<String>,
 	/// The output commands
 	pub commands: Vec<Vec<String>>,
+	/// The output configuration files
+	pub config_files: Vec<ConfigFileRequest>,
 	/// Whether the package uses custom instructions
 	pub uses_custom_instructions: bool,
 }
@@ -220,6 +232,7 @@ impl<'a> EvalData<'a> {
 			extensions: Vec::new(),
 			notices: Vec::new(),
 			commands: Vec::new(),
+			config_files: Vec::new(),
 			uses_custom_instructions: false,
 		}
 	}
@@ -239,7 +252,7 @@ impl Package {
 
 		// Check properties
 		let properties = self.get_properties(paths, client).await?.clone();
-		if eval_check_properties(&input, &properties)? {
+		if eval_check_properties(&self.id, &input, &properties)? {
 			return Ok(EvalData::new(
 				input,
 				self.id.clone(),
@@ -249,10 +262,10 @@ impl Package {
 			));
 		}
 
-		match self.content_type {
+		let mut eval = match self.content_type {
 			PackageContentType::Script => {
 				let parsed = self.data.get_mut().contents.get_mut().get_script_contents();
-				let eval = eval_script_package(
+				eval_script_package(
 					self.id.clone(),
 					parsed,
 					routine,
@@ -260,27 +273,54 @@ impl Package {
 					input,
 					plugins,
 					paths,
-				)?;
-				Ok(eval)
+				)?
 			}
 			PackageContentType::Declarative => {
 				let contents = self.data.get().contents.get().get_declarative_contents();
-				let eval = eval_declarative_package(
+				eval_declarative_package(
 					self.id.clone(),
 					contents,
 					input,
 					properties,
 					routine,
 					plugins,
-				)?;
-				Ok(eval)
+				)?
 			}
+		};
+
+		check_folia_plugins(&eval)?;
+		check_pinned_addon_versions(&mut eval, self.content_type);
+
+		Ok(eval)
+	}
+}
+
+/// Add notices for addons that couldn't be pinned to the version from a previous update,
+/// either because that version is gone or because the package picks its versions in a script
+fn check_pinned_addon_versions(eval: &mut EvalData, content_type: PackageContentType) {
+	let mut notices = Vec::new();
+	for req in &eval.addon_reqs {
+		let Some(pinned) = eval.input.params.pinned_addon_versions.get(&req.addon.id) else {
+			continue;
+		};
+		if req.addon.version.as_ref() == Some(pinned) {
+			continue;
 		}
+		let reason = match content_type {
+			PackageContentType::Script => "script packages choose their own addon versions",
+			PackageContentType::Declarative => "that version is no longer available",
+		};
+		notices.push(format!(
+			"Addon '{}' could not be pinned to version {pinned} because {reason}",
+			req.addon.id
+		));
 	}
+	eval.notices.extend(notices);
 }
 
 /// Check properties when evaluating. Returns true if the package should finish evaluating with no error
 pub fn eval_check_properties(
+	id: &PackageID,
 	input: &EvalInput,
 	properties: &PackageProperties,
 ) -> anyhow::Result<bool> {
@@ -311,6 +351,9 @@ pub fn eval_check_properties(
 			.iter()
 			.any(|x| x.matches(&input.constants.modifications.server_type))
 		{
+			if input.constants.modifications.server_type == ServerType::Folia {
+				return Err(folia_unsupported_error(id));
+			}
 			bail!("Package does not support this plugin loader");
 		}
 	}
@@ -336,6 +379,38 @@ pub fn eval_check_properties(
 	Ok(false)
 }
 
+/// Check that a package isn't installing plugins onto a Folia server without declaring that
+/// it supports Folia. Folia breaks most Bukkit plugins, so plugins are not assumed to work on it
+fn check_folia_plugins(eval: &EvalData) -> anyhow::Result<()> {
+	if eval.input.params.side != Side::Server
+		|| eval.input.constants.modifications.server_type != ServerType::Folia
+	{
+		return Ok(());
+	}
+
+	let installs_plugins = eval
+		.addon_reqs
+		.iter()
+		.any(|x| matches!(x.addon.kind, AddonKind::Plugin));
+	let supports_folia = eval
+		.properties
+		.supported_plugin_loaders
+		.as_ref()
+		.is_some_and(|x| x.iter().any(|x| x.matches(&ServerType::Folia)));
+	if installs_plugins && !supports_folia {
+		return Err(folia_unsupported_error(&eval.id));
+	}
+
+	Ok(())
+}
+
+/// Create the error for when a package is installed on Folia without supporting it
+fn folia_unsupported_error(id: &PackageID) -> anyhow::Error {
+	anyhow!(
+		"Package '{id}' does not support the Folia server type. Folia is incompatible with most Bukkit plugins, so the package must list 'folia' in its supported plugin loaders to be installed. Use a different server type, such as Paper, or remove the package"
+	)
+}
+
 /// Utility for evaluation that validates addon arguments and creates a request
 pub fn create_valid_addon_request(
 	data: AddonInstructionData,
@@ -424,6 +499,107 @@ pub fn create_valid_addon_request(
 	}
 }
 
+/// A configuration file that a package installs into an instance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigFileRequest {
+	/// The path to the file, relative to the game directory
+	pub path: String,
+	/// Where to get the contents of the file from
+	pub location: ConfigFileLocation,
+	/// How to combine the file with an existing one
+	pub policy: ConfigFilePolicy,
+}
+
+/// Where the contents of a configuration file come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigFileLocation {
+	/// The contents are written in the package
+	Contents(String),
+	/// The contents are downloaded from a URL
+	Remote(String),
+}
+
+/// Utility for evaluation that validates config file arguments and creates a request
+pub fn create_valid_config_file_request(
+	data: ConfigFileInstructionData,
+	eval_input: &EvalInput,
+) -> anyhow::Result<ConfigFileRequest> {
+	if !is_config_file_path_valid(&data.path) {
+		bail!(
+			"Invalid config file path '{}'. It must be a relative path inside of the game directory",
+			data.path
+		);
+	}
+
+	// Changing files that already exist could break the instance, so restricted packages can only create new ones
+	if data.policy != ConfigFilePolicy::Create
+		&& eval_input.params.perms == EvalPermissions::Restricted
+	{
+		bail!(
+			"Insufficient permissions to change the existing config file '{}'",
+			data.path
+		);
+	}
+
+	if data.policy == ConfigFilePolicy::JsonMerge {
+		if !data.path.ends_with(".json") {
+			bail!(
+				"Config file '{}' must be a JSON file to be merged",
+				data.path
+			);
+		}
+		if let Some(contents) = &data.contents {
+			let value: serde_json::Value = serde_json::from_str(contents).with_context(|| {
+				format!("Contents of config file '{}' are not valid JSON", data.path)
+			})?;
+			if !value.is_object() {
+				bail!(
+					"Contents of config file '{}' must be a JSON object to be merged",
+					data.path
+				);
+			}
+		}
+	}
+
+	let location = match (data.contents, data.url) {
+		(Some(contents), None) => ConfigFileLocation::Contents(contents),
+		(None, Some(url)) => ConfigFileLocation::Remote(url),
+		(Some(..), Some(..)) => bail!(
+			"Config file '{}' can't have both contents and a URL",
+			data.path
+		),
+		(None, None) => bail!(
+			"No contents or URL were specified for config file '{}'",
+			data.path
+		),
+	};
+
+	Ok(ConfigFileRequest {
+		path: data.path,
+		location,
+		policy: data.policy,
+	})
+}
+
+/// Checks that a config file path stays inside of the directory it is relative to
+fn is_config_file_path_valid(path: &str) -> bool {
+	// Backslashes are separators on Windows, so they could be used to sneak in parent directories
+	if path.is_empty() || path.contains('\\') {
+		return false;
+	}
+
+	let mut has_name = false;
+	for component in Path::new(path).components() {
+		match component {
+			Component::Normal(..) => has_name = true,
+			Component::CurDir => {}
+			Component::ParentDir | Component::RootDir | Component::Prefix(..) => return false,
+		}
+	}
+
+	has_name
+}
+
 /// Evaluator used as an input for dependency resolution
 struct PackageEvaluator<'a> {
 	reg: &'a mut PkgRegistry,
@@ -452,17 +628,8 @@ impl ConfiguredPackage for EvalPackageConfig {
 		properties: &PackageProperties,
 		input: &mut Self::EvalInput<'_>,
 	) -> anyhow::Result<()> {
-		let features = self
-			.0
-			.calculate_features(properties)
-			.context("Failed to calculate features")?;
-
-		input.params.config_source = self.0.source;
-		input.params.features = features;
-		input.params.perms = self.0.permissions;
-		input.params.stability = self.0.stability;
-
-		Ok(())
+		// Unknown features have already been checked before resolution
+		self.0.apply_to_params(properties, false, &mut input.params)
 	}
 }
 
@@ -626,3 +793,463 @@ fn print_recommendation_warning(
 
 	o.display(message, MessageLevel::Important);
 }
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+
+	use mcvm_shared::modifications::{ClientType, Modloader};
+
+	use mcvm_shared::id::InstanceID;
+
+	use crate::config::Config;
+	use crate::pkg::{PkgData, PkgLocation};
+
+	use super::*;
+
+	/// Evaluate a fixture declarative package on a server with the given server type
+	fn eval_fixture(contents: &str, server_type: ServerType) -> anyhow::Result<Vec<AddonRequest>> {
+		eval_fixture_with_params(contents, server_type, EvalParameters::new(Side::Server))
+	}
+
+	/// Evaluate a fixture declarative package with custom parameters
+	fn eval_fixture_with_params(
+		contents: &str,
+		server_type: ServerType,
+		params: EvalParameters,
+	) -> anyhow::Result<Vec<AddonRequest>> {
+		let (addons, ..) = eval_package_fixture(
+			contents,
+			PackageContentType::Declarative,
+			server_type,
+			Language::AmericanEnglish,
+			params,
+		)?;
+		Ok(addons)
+	}
+
+	/// Evaluate a fixture package, returning its addons, notices, and config files
+	fn eval_package_fixture(
+		contents: &str,
+		content_type: PackageContentType,
+		server_type: ServerType,
+		language: Language,
+		params: EvalParameters,
+	) -> anyhow::Result<(Vec<AddonRequest>, Vec<String>, Vec<ConfigFileRequest>)> {
+		let constants = EvalConstants {
+			version: "1.20.1".into(),
+			version_list: vec!["1.20.1".into()],
+			modifications: GameModifications::new(
+				Modloader::Vanilla,
+				ClientType::Vanilla,
+				server_type,
+			),
+			language,
+			profile_stability: PackageStability::Latest,
+		};
+		let input = EvalInput {
+			constants: &constants,
+			params,
+		};
+		let paths = Paths::new_no_create().unwrap();
+		let plugins = PluginManager::new();
+		let client = Client::new();
+
+		let mut pkg = Package::new(
+			PackageID::from("fixture"),
+			PkgLocation::Local(PathBuf::from("fixture.json")),
+			content_type,
+			HashSet::new(),
+		);
+		pkg.data.fill(PkgData::new(contents));
+
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let eval =
+			runtime.block_on(pkg.eval(&paths, Routine::Install, input, &client, &plugins))?;
+		Ok((eval.addon_reqs, eval.notices, eval.config_files))
+	}
+
+	/// Create a fixture plugin package with the given supported plugin loaders
+	fn plugin_package(supported_plugin_loaders: Option<&str>) -> String {
+		let properties = if let Some(loaders) = supported_plugin_loaders {
+			format!(r#""properties": {{ "supported_plugin_loaders": {loaders} }},"#)
+		} else {
+			String::new()
+		};
+		format!(
+			r#"{{
+				{properties}
+				"addons": {{
+					"plugin": {{
+						"kind": "plugin",
+						"versions": [ {{ "url": "example.com", "version": "1" }} ]
+					}}
+				}}
+			}}"#
+		)
+	}
+
+	#[test]
+	fn test_folia_compatible_package() {
+		let contents = plugin_package(Some(r#"[ "bukkit", "folia" ]"#));
+		let addons = eval_fixture(&contents, ServerType::Folia).unwrap();
+		assert_eq!(addons.len(), 1);
+	}
+
+	#[test]
+	fn test_folia_incompatible_package() {
+		let contents = plugin_package(Some(r#"[ "bukkit" ]"#));
+		assert!(eval_fixture(&contents, ServerType::Paper).is_ok());
+		let err = eval_fixture(&contents, ServerType::Folia).unwrap_err();
+		let message = err.to_string();
+		assert!(message.contains("'fixture'"));
+		assert!(message.contains("Folia"));
+
+		// Plugin packages that don't declare any plugin loaders are not assumed to support Folia
+		let contents = plugin_package(None);
+		assert!(eval_fixture(&contents, ServerType::Paper).is_ok());
+		assert!(eval_fixture(&contents, ServerType::Folia).is_err());
+	}
+
+	#[test]
+	fn test_folia_non_plugin_package() {
+		let contents = r#"{ "relations": { "dependencies": [ "foo" ] } }"#;
+		assert!(eval_fixture(contents, ServerType::Folia).is_ok());
+	}
+
+	#[test]
+	fn test_package_settings_override_instance_defaults() {
+		let deser = serde_json::from_value(serde_json::json!({
+			"profiles": {
+				"test": {
+					"version": "1.20.1",
+					"default_package_permissions": "restricted",
+					"default_package_stability": "latest",
+					"packages": [ "inherited" ]
+				}
+			},
+			"instances": {
+				"client": {
+					"type": "client",
+					"from": "test",
+					"default_package_permissions": "elevated",
+					"packages": [
+						"default",
+						{ "id": "explicit", "permissions": "restricted", "stability": "stable" }
+					]
+				}
+			}
+		}))
+		.unwrap();
+		let config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			false,
+			&Paths::new_no_create().unwrap(),
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+		let instance = config.instances.get(&InstanceID::from("client")).unwrap();
+
+		let constants = EvalConstants {
+			version: "1.20.1".into(),
+			version_list: vec!["1.20.1".into()],
+			modifications: GameModifications::new(
+				Modloader::Vanilla,
+				ClientType::Vanilla,
+				ServerType::Vanilla,
+			),
+			language: Language::AmericanEnglish,
+			profile_stability: PackageStability::Latest,
+		};
+		let get_settings = |id: &str| {
+			let package = instance.get_package_config(id).unwrap().clone();
+			let req = package.get_request();
+			let mut input = EvalInput {
+				constants: &constants,
+				params: EvalParameters::new(Side::Client),
+			};
+			EvalPackageConfig(package, req)
+				.override_configured_package_input(&PackageProperties::default(), &mut input)
+				.unwrap();
+			(input.params.perms, input.params.stability)
+		};
+
+		// The instance default wins over the profile one
+		let defaults = (EvalPermissions::Elevated, PackageStability::Latest);
+		assert_eq!(get_settings("inherited"), defaults);
+		assert_eq!(get_settings("default"), defaults);
+		assert_eq!(
+			get_settings("explicit"),
+			(EvalPermissions::Restricted, PackageStability::Stable)
+		);
+	}
+
+	#[test]
+	fn test_configured_stability_selects_addon_version() {
+		let contents = r#"{
+			"addons": {
+				"mod": {
+					"kind": "mod",
+					"versions": [
+						{ "url": "example.com", "version": "2-beta", "stability": "latest" },
+						{ "url": "example.com", "version": "1" }
+					]
+				}
+			}
+		}"#;
+
+		let deser = serde_json::from_value(serde_json::json!({
+			"profiles": {
+				"test": {
+					"version": "1.20.1",
+					"packages": [ "fixture" ]
+				}
+			},
+			"instances": {
+				"stable": {
+					"type": "server",
+					"from": "test"
+				},
+				"latest_default": {
+					"type": "server",
+					"from": "test",
+					"default_package_stability": "latest"
+				},
+				"latest_package": {
+					"type": "server",
+					"from": "test",
+					"packages": [ { "id": "fixture", "stability": "latest" } ]
+				}
+			}
+		}))
+		.unwrap();
+		let config = Config::load_from_deser(
+			deser,
+			PluginManager::new(),
+			false,
+			&Paths::new_no_create().unwrap(),
+			&mut output::Simple(output::MessageLevel::Debug),
+		)
+		.unwrap();
+
+		let get_version = |instance: &str| {
+			let instance = config.instances.get(&InstanceID::from(instance)).unwrap();
+			let mut params = EvalParameters::new(Side::Server);
+			instance
+				.get_package_config("fixture")
+				.unwrap()
+				.apply_to_params(&PackageProperties::default(), false, &mut params)
+				.unwrap();
+			let addons = eval_fixture_with_params(contents, ServerType::Vanilla, params).unwrap();
+			assert_eq!(addons.len(), 1);
+			addons[0].addon.version.clone().unwrap()
+		};
+
+		assert_eq!(get_version("stable"), "1");
+		assert_eq!(get_version("latest_default"), "2-beta");
+		assert_eq!(get_version("latest_package"), "2-beta");
+	}
+
+	#[test]
+	fn test_unapplied_addon_pins() {
+		let mut params = EvalParameters::new(Side::Client);
+		params.pinned_addon_versions = HashMap::from([("mod".to_string(), "1".to_string())]);
+
+		let script = r#"@install {
+			addon "mod" "mod.jar" (
+				kind: mod,
+				url: "example.com",
+				version: "2"
+			);
+		}"#;
+		let (addons, notices, _) = eval_package_fixture(
+			script,
+			PackageContentType::Script,
+			ServerType::Vanilla,
+			Language::AmericanEnglish,
+			params.clone(),
+		)
+		.unwrap();
+		assert_eq!(addons[0].addon.version.as_deref(), Some("2"));
+		assert_eq!(notices.len(), 1);
+		assert!(notices[0].contains("script packages"));
+
+		let declarative = r#"{
+			"addons": {
+				"mod": {
+					"kind": "mod",
+					"versions": [
+						{ "url": "example.com", "version": "1" },
+						{ "url": "example.com", "version": "2" }
+					]
+				}
+			}
+		}"#;
+		let (addons, notices, _) = eval_package_fixture(
+			declarative,
+			PackageContentType::Declarative,
+			ServerType::Vanilla,
+			Language::AmericanEnglish,
+			params.clone(),
+		)
+		.unwrap();
+		assert_eq!(addons[0].addon.version.as_deref(), Some("1"));
+		assert!(notices.is_empty());
+
+		params.pinned_addon_versions = HashMap::from([("mod".to_string(), "0".to_string())]);
+		let (_, notices, _) = eval_package_fixture(
+			declarative,
+			PackageContentType::Declarative,
+			ServerType::Vanilla,
+			Language::AmericanEnglish,
+			params,
+		)
+		.unwrap();
+		assert!(notices[0].contains("no longer available"));
+	}
+
+	#[test]
+	fn test_localized_notice() {
+		let get_notices = |contents: &str, language: Language| {
+			eval_package_fixture(
+				contents,
+				PackageContentType::Script,
+				ServerType::Vanilla,
+				language,
+				EvalParameters::new(Side::Server),
+			)
+			.map(|(_, notices, _)| notices)
+		};
+
+		let contents = r#"@install {
+			notice_localized german "Hallo" american_english "Hello" european_french "Bonjour";
+		}"#;
+		assert_eq!(get_notices(contents, Language::German).unwrap(), ["Hallo"]);
+		assert_eq!(
+			get_notices(contents, Language::EuropeanFrench).unwrap(),
+			["Bonjour"]
+		);
+		// Languages without a message fall back to English
+		assert_eq!(
+			get_notices(contents, Language::Japanese).unwrap(),
+			["Hello"]
+		);
+
+		let contents = r#"@install {
+			if lang german {
+				notice "Deutsch";
+			}
+		}"#;
+		assert_eq!(
+			get_notices(contents, Language::German).unwrap(),
+			["Deutsch"]
+		);
+		assert!(get_notices(contents, Language::Dutch).unwrap().is_empty());
+
+		// The notice limits apply to the message that was selected
+		let long = "a".repeat(MAX_NOTICE_CHARACTERS + 1);
+		let contents =
+			format!(r#"@install {{ notice_localized american_english "Hello" german "{long}"; }}"#);
+		assert!(get_notices(&contents, Language::AmericanEnglish).is_ok());
+		assert!(get_notices(&contents, Language::German).is_err());
+	}
+
+	#[test]
+	fn test_config_files() {
+		let get_config_files = |contents: &str, content_type, perms| {
+			let mut params = EvalParameters::new(Side::Client);
+			params.perms = perms;
+			eval_package_fixture(
+				contents,
+				content_type,
+				ServerType::Vanilla,
+				Language::AmericanEnglish,
+				params,
+			)
+			.map(|(.., config_files)| config_files)
+		};
+		let get_script =
+			|contents: &str, perms| get_config_files(contents, PackageContentType::Script, perms);
+
+		let contents = r#"@install {
+			config_file create "config/foo.toml" "a = 1";
+			config_file overwrite "options.txt" "lang:en_us";
+			config_file_url json_merge "config/bar.json" "example.com";
+		}"#;
+		let config_files = get_script(contents, EvalPermissions::Standard).unwrap();
+		assert_eq!(
+			config_files,
+			vec![
+				ConfigFileRequest {
+					path: "config/foo.toml".into(),
+					location: ConfigFileLocation::Contents("a = 1".into()),
+					policy: ConfigFilePolicy::Create,
+				},
+				ConfigFileRequest {
+					path: "options.txt".into(),
+					location: ConfigFileLocation::Contents("lang:en_us".into()),
+					policy: ConfigFilePolicy::Overwrite,
+				},
+				ConfigFileRequest {
+					path: "config/bar.json".into(),
+					location: ConfigFileLocation::Remote("example.com".into()),
+					policy: ConfigFilePolicy::JsonMerge,
+				},
+			]
+		);
+
+		// Restricted packages can only create new files
+		assert!(get_script(contents, EvalPermissions::Restricted).is_err());
+		let contents = r#"@install { config_file create "config/foo.toml" "a = 1"; }"#;
+		assert!(get_script(contents, EvalPermissions::Restricted).is_ok());
+
+		let contents = r#"{
+			"config_files": [
+				{ "path": "config/foo.json", "contents": "{}", "policy": "json_merge" },
+				{ "path": "config/server.toml", "contents": "", "conditions": [ { "side": "server" } ] }
+			]
+		}"#;
+		let config_files = get_config_files(
+			contents,
+			PackageContentType::Declarative,
+			EvalPermissions::Standard,
+		)
+		.unwrap();
+		assert_eq!(config_files.len(), 1);
+		assert_eq!(config_files[0].policy, ConfigFilePolicy::JsonMerge);
+	}
+
+	#[test]
+	fn test_invalid_config_files() {
+		let get_error = |instruction: &str| {
+			let contents = format!("@install {{ {instruction} }}");
+			eval_package_fixture(
+				&contents,
+				PackageContentType::Script,
+				ServerType::Vanilla,
+				Language::AmericanEnglish,
+				EvalParameters::new(Side::Client),
+			)
+			.is_err()
+		};
+
+		assert!(get_error(r#"config_file create "../foo.txt" "";"#));
+		assert!(get_error(
+			r#"config_file create "config/../../foo.txt" "";"#
+		));
+		assert!(get_error(r#"config_file create "/etc/foo.txt" "";"#));
+		assert!(!is_config_file_path_valid("config\\..\\..\\foo.txt"));
+		assert!(get_error(r#"config_file create "." "";"#));
+		assert!(get_error(
+			r#"config_file json_merge "config/foo.toml" "{}";"#
+		));
+		assert!(get_error(
+			r#"config_file json_merge "config/foo.json" "[]";"#
+		));
+		assert!(get_error(
+			r#"config_file create "foo.txt" ""; config_file overwrite "foo.txt" "";"#
+		));
+		assert!(!get_error(r#"config_file create "./config/foo.txt" "";"#));
+	}
+}
@@ -4,7 +4,8 @@ use mcvm_parse::parse::Parsed;
 use mcvm_parse::vars::{HashMapVariableStore, ReservedConstantVariables, VariableStore};
 use mcvm_pkg::properties::PackageProperties;
 use mcvm_pkg::script_eval::{
-	AddonInstructionData, ScriptEvalConfig, ScriptEvaluator as ScriptEvaluatorTrait,
+	AddonInstructionData, ConfigFileInstructionData, ScriptEvalConfig,
+	ScriptEvaluator as ScriptEvaluatorTrait,
 };
 use mcvm_pkg::RecommendedPackage;
 use mcvm_plugin::hooks::{CustomPackageInstruction, CustomPackageInstructionArg};
@@ -16,8 +17,8 @@ use crate::io::paths::Paths;
 
 use super::conditions::eval_condition;
 use super::{
-	create_valid_addon_request, EvalData, EvalInput, EvalPermissions, RequiredPackage, Routine,
-	MAX_NOTICE_CHARACTERS, MAX_NOTICE_INSTRUCTIONS,
+	create_valid_addon_request, create_valid_config_file_request, EvalData, EvalInput,
+	EvalPermissions, RequiredPackage, Routine, MAX_NOTICE_CHARACTERS, MAX_NOTICE_INSTRUCTIONS,
 };
 
 struct SharedData<'a> {
@@ -96,6 +97,26 @@ impl ScriptEvaluatorTrait for ScriptEvaluator {
 		Ok(())
 	}
 
+	fn add_config_file(
+		&mut self,
+		shared: &mut Self::Shared<'_>,
+		config_file: ConfigFileInstructionData,
+	) -> anyhow::Result<()> {
+		if shared
+			.eval
+			.config_files
+			.iter()
+			.any(|x| x.path == config_file.path)
+		{
+			bail!("Duplicate config file '{}'", config_file.path);
+		}
+
+		let request = create_valid_config_file_request(config_file, &shared.eval.input)?;
+		shared.eval.config_files.push(request);
+
+		Ok(())
+	}
+
 	fn add_bundled(&mut self, shared: &mut Self::Shared<'_>, pkg: PackageID) -> anyhow::Result<()> {
 		shared.eval.bundled.push(pkg);
 		Ok(())
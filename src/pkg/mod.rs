@@ -1,3 +1,5 @@
+/// Tools for authors of package repositories
+pub mod author;
 /// Core packages that are built into the binary
 mod core;
 /// Package evaluation functions
@@ -6,8 +8,11 @@ pub mod eval;
 pub mod reg;
 /// Interacting with package repositories
 pub mod repo;
+/// Searching for packages across repositories
+pub mod search;
 
 use crate::io::paths::Paths;
+use mcvm_core::io::{json_from_file, json_to_file};
 use mcvm_core::net::download;
 use mcvm_pkg::declarative::{deserialize_declarative_package, DeclarativePackage};
 use mcvm_pkg::repo::PackageFlag;
@@ -17,7 +22,7 @@ use mcvm_shared::later::Later;
 use std::collections::HashSet;
 use std::fs;
 use std::future::Future;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use self::core::get_core_package;
 use anyhow::{anyhow, bail, Context};
@@ -26,6 +31,8 @@ use mcvm_pkg::metadata::{eval_metadata, PackageMetadata};
 use mcvm_pkg::properties::{eval_properties, PackageProperties};
 use mcvm_shared::pkg::PackageID;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 /// An installable package that loads content into your game
 #[derive(Debug)]
@@ -40,10 +47,32 @@ pub struct Package {
 	pub flags: HashSet<PackageFlag>,
 	/// The data of the package
 	pub data: Later<PkgData>,
+	/// The hash of the package file that the repository declares
+	pub hash: Option<String>,
+	/// The fingerprint of the repository index that the package was found in
+	pub index_fingerprint: Option<String>,
+}
+
+/// Stored next to a cached remote package to check whether the cached copy is still current
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedPackageRecord {
+	/// The SHA-256 hash of the cached contents
+	pub content_hash: String,
+	/// The fingerprint of the repository index when the package was cached
+	pub index_fingerprint: Option<String>,
+	/// The plugin that provided the package, if it came from one
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub plugin: Option<String>,
+	/// The content type of a package provided by a plugin, since there is no repository to get it from
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub content_type: Option<PackageContentType>,
+	/// The flags of a package provided by a plugin
+	#[serde(default, skip_serializing_if = "HashSet::is_empty")]
+	pub flags: HashSet<PackageFlag>,
 }
 
 /// Location of a package
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PkgLocation {
 	/// Contained on the local filesystem
 	Local(PathBuf),
@@ -56,6 +85,11 @@ pub enum PkgLocation {
 	},
 	/// Included in the binary
 	Core,
+	/// Provided by a plugin
+	Plugin {
+		/// The ID of the plugin that provided this package
+		plugin_id: String,
+	},
 }
 
 /// Data pertaining to the contents of a package
@@ -127,6 +161,8 @@ impl Package {
 			data: Later::new(),
 			content_type,
 			flags,
+			hash: None,
+			index_fingerprint: None,
 		}
 	}
 
@@ -141,19 +177,121 @@ impl Package {
 
 	/// Get the cached path of the package
 	pub fn cached_path(&self, paths: &Paths) -> PathBuf {
-		let cache_dir = paths.project.cache_dir().join("pkg");
+		let cache_dir = paths.cache.join("pkg");
 		cache_dir.join(self.filename())
 	}
 
+	/// Get the path to the record that is stored next to the cached package
+	pub fn cache_record_path(&self, paths: &Paths) -> PathBuf {
+		paths
+			.cache
+			.join("pkg")
+			.join(format!("{}.record.json", self.id))
+	}
+
 	/// Remove the cached package file
 	pub fn remove_cached(&self, paths: &Paths) -> anyhow::Result<()> {
-		let path = self.cached_path(paths);
-		if path.exists() {
-			fs::remove_file(path)?;
+		for path in [self.cached_path(paths), self.cache_record_path(paths)] {
+			if path.exists() {
+				fs::remove_file(path)?;
+			}
 		}
 		Ok(())
 	}
 
+	/// Load a package that a plugin provided earlier from the cache. Returns None if it
+	/// isn't cached or was cached without the record that stores its content type and flags
+	pub fn from_plugin_cache(
+		id: PackageID,
+		plugin_id: &str,
+		paths: &Paths,
+	) -> anyhow::Result<Option<Self>> {
+		let location = PkgLocation::Plugin {
+			plugin_id: plugin_id.to_string(),
+		};
+		let mut package = Self::new(id, location, PackageContentType::default(), HashSet::new());
+		let Ok(record) = json_from_file::<CachedPackageRecord>(package.cache_record_path(paths))
+		else {
+			return Ok(None);
+		};
+		let (Some(plugin), Some(content_type)) = (record.plugin, record.content_type) else {
+			return Ok(None);
+		};
+		if plugin != plugin_id {
+			return Ok(None);
+		}
+		package.content_type = content_type;
+		package.flags = record.flags;
+
+		let path = package.cached_path(paths);
+		if !path.exists() {
+			return Ok(None);
+		}
+		let contents = fs::read_to_string(path).context("Failed to read cached plugin package")?;
+		if hash_contents(&contents) != record.content_hash {
+			return Ok(None);
+		}
+		package.data.fill(PkgData::new(&contents));
+
+		Ok(Some(package))
+	}
+
+	/// Cache the contents of a package provided by a plugin, along with its content type and flags
+	pub fn write_plugin_cache(&self, contents: &str, paths: &Paths) -> anyhow::Result<()> {
+		let PkgLocation::Plugin { plugin_id } = &self.location else {
+			bail!("Package was not provided by a plugin");
+		};
+		fs::write(self.cached_path(paths), contents).context("Failed to cache plugin package")?;
+		let record = CachedPackageRecord {
+			content_hash: hash_contents(contents),
+			index_fingerprint: None,
+			plugin: Some(plugin_id.clone()),
+			content_type: Some(self.content_type),
+			flags: self.flags.clone(),
+		};
+		json_to_file(self.cache_record_path(paths), &record)
+			.context("Failed to write cached package record")
+	}
+
+	/// Check whether cached contents of this package are still current. If the repository
+	/// declares a hash, the contents must match it. Otherwise, the package must have
+	/// been cached from the current version of the repository index
+	pub fn is_cache_current(&self, contents: &str, record: Option<&CachedPackageRecord>) -> bool {
+		let content_hash = hash_contents(contents);
+		if let Some(hash) = &self.hash {
+			return hash.eq_ignore_ascii_case(&content_hash);
+		}
+		let Some(fingerprint) = &self.index_fingerprint else {
+			return true;
+		};
+		record.is_some_and(|x| {
+			x.content_hash == content_hash && x.index_fingerprint.as_ref() == Some(fingerprint)
+		})
+	}
+
+	/// Download the package from its URL and cache it along with its record
+	async fn download_remote(
+		&self,
+		url: &str,
+		paths: &Paths,
+		client: &Client,
+	) -> anyhow::Result<String> {
+		let text = download::text(url, client).await?;
+		if let Some(hash) = &self.hash {
+			if !hash.eq_ignore_ascii_case(&hash_contents(&text)) {
+				bail!("Downloaded package does not match the hash in the repository index");
+			}
+		}
+		write_cache(
+			&self.cached_path(paths),
+			&self.cache_record_path(paths),
+			&text,
+			self.index_fingerprint.clone(),
+		)?;
+
+		Ok(text)
+	}
+
 	/// Ensure the raw contents of the package
 	pub async fn ensure_loaded(
 		&mut self,
@@ -167,26 +305,51 @@ impl Package {
 					if !path.exists() {
 						bail!("Local package path does not exist");
 					}
-					self.data
-						.fill(PkgData::new(&tokio::fs::read_to_string(path).await?));
+					let text = tokio::fs::read_to_string(path).await?;
+					if let Some(hash) = &self.hash {
+						if !hash.eq_ignore_ascii_case(&hash_contents(&text)) {
+							bail!("Local package does not match the hash in the repository index");
+						}
+					}
+					self.data.fill(PkgData::new(&text));
 				}
 				PkgLocation::Remote { url, .. } => {
 					let path = self.cached_path(paths);
-					if !force && path.exists() {
-						self.data
-							.fill(PkgData::new(&tokio::fs::read_to_string(path).await?));
+					let cached = if !force && path.exists() {
+						Some(tokio::fs::read_to_string(path).await?)
 					} else {
-						let url = url.as_ref().expect("URL for remote package missing");
-						let text = download::text(url, client).await?;
-						tokio::fs::write(&path, &text).await?;
-						self.data.fill(PkgData::new(&text));
-					}
+						None
+					};
+					let record: Option<CachedPackageRecord> =
+						json_from_file(self.cache_record_path(paths)).ok();
+					let text = match cached {
+						Some(cached) if self.is_cache_current(&cached, record.as_ref()) => cached,
+						cached => {
+							let url = url.as_ref().expect("URL for remote package missing");
+							match self.download_remote(url, paths, client).await {
+								Ok(text) => text,
+								// Keep using the outdated copy if the repository can't be reached
+								Err(e) => cached.ok_or(e)?,
+							}
+						}
+					};
+					self.data.fill(PkgData::new(&text));
 				}
 				PkgLocation::Core => {
 					let contents = get_core_package(&self.id)
 						.ok_or(anyhow!("Package is not a core package"))?;
 					self.data.fill(PkgData::new(contents));
 				}
+				// Plugin packages are filled when they are provided, so they can
+				// only be reloaded from the cache
+				PkgLocation::Plugin { plugin_id } => {
+					let path = self.cached_path(paths);
+					if !path.exists() {
+						bail!("Package from plugin '{plugin_id}' is no longer cached");
+					}
+					self.data
+						.fill(PkgData::new(&tokio::fs::read_to_string(path).await?));
+				}
 			};
 		}
 		Ok(())
@@ -208,7 +371,16 @@ impl Package {
 					.expect("URL for remote package missing")
 					.clone();
 				let client = client.clone();
-				return Some(async move { download::file(url, path, &client).await });
+				let record_path = self.cache_record_path(paths);
+				let fingerprint = self.index_fingerprint.clone();
+				let hash = self.hash.clone();
+				return Some(async move {
+					let text = download::text(url, &client).await?;
+					if hash.is_some_and(|x| !x.eq_ignore_ascii_case(&hash_contents(&text))) {
+						bail!("Downloaded package does not match the hash in the repository index");
+					}
+					write_cache(&path, &record_path, &text, fingerprint)
+				});
 			}
 		}
 
@@ -289,6 +461,29 @@ impl Package {
 	}
 }
 
+/// Get the hex SHA-256 hash of package contents
+pub fn hash_contents(contents: &str) -> String {
+	hex::encode(Sha256::digest(contents.as_bytes()))
+}
+
+/// Write the contents of a remote package to the cache, along with its record
+fn write_cache(
+	path: &Path,
+	record_path: &Path,
+	contents: &str,
+	index_fingerprint: Option<String>,
+) -> anyhow::Result<()> {
+	fs::write(path, contents).context("Failed to write cached package")?;
+	let record = CachedPackageRecord {
+		content_hash: hash_contents(contents),
+		index_fingerprint,
+		plugin: None,
+		content_type: None,
+		flags: HashSet::new(),
+	};
+	json_to_file(record_path, &record).context("Failed to write cached package record")
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -317,4 +512,58 @@ mod tests {
 		);
 		assert_eq!(package.filename(), "fabriclike-api.json".to_string());
 	}
+
+	#[test]
+	fn test_cache_current() {
+		let mut package = Package::new(
+			PackageID::from("sodium"),
+			PkgLocation::Remote {
+				url: None,
+				repo_id: String::new(),
+			},
+			PackageContentType::Script,
+			HashSet::new(),
+		);
+		// Packages from repositories without fingerprints can't be checked
+		assert!(package.is_cache_current("old", None));
+
+		package.index_fingerprint = Some("index2".into());
+		let record = |contents: &str, fingerprint: &str| CachedPackageRecord {
+			content_hash: hash_contents(contents),
+			index_fingerprint: Some(fingerprint.into()),
+			plugin: None,
+			content_type: None,
+			flags: HashSet::new(),
+		};
+		assert!(package.is_cache_current("old", Some(&record("old", "index2"))));
+		assert!(!package.is_cache_current("old", Some(&record("old", "index1"))));
+		assert!(!package.is_cache_current("edited", Some(&record("old", "index2"))));
+		assert!(!package.is_cache_current("old", None));
+
+		// A declared hash takes priority over the record
+		package.hash = Some(hash_contents("new").to_uppercase());
+		assert!(package.is_cache_current("new", None));
+		assert!(!package.is_cache_current("old", Some(&record("old", "index2"))));
+	}
+
+	#[test]
+	fn test_plugin_cache_record() {
+		let record = CachedPackageRecord {
+			content_hash: hash_contents("{}"),
+			index_fingerprint: None,
+			plugin: Some("provider".into()),
+			content_type: Some(PackageContentType::Declarative),
+			flags: HashSet::from([PackageFlag::OutOfDate]),
+		};
+		let json = serde_json::to_string(&record).unwrap();
+		let deserialized: CachedPackageRecord = serde_json::from_str(&json).unwrap();
+		assert_eq!(deserialized, record);
+
+		// Records of packages from repositories don't store the plugin fields
+		let json = r#"{"content_hash": "abc", "index_fingerprint": null}"#;
+		let record: CachedPackageRecord = serde_json::from_str(json).unwrap();
+		assert_eq!(record.plugin, None);
+		assert_eq!(record.content_type, None);
+		assert!(record.flags.is_empty());
+	}
 }
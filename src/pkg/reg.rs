@@ -1,5 +1,8 @@
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
+use itertools::Itertools;
+use mcvm_core::io::json_from_file;
 use mcvm_core::net::download;
+use mcvm_core::net::stats;
 use mcvm_pkg::metadata::PackageMetadata;
 use mcvm_pkg::parse_and_validate;
 use mcvm_pkg::properties::PackageProperties;
@@ -7,8 +10,9 @@ use mcvm_pkg::repo::PackageFlag;
 use mcvm_pkg::PackageContentType;
 use mcvm_pkg::PkgRequest;
 use mcvm_pkg::PkgRequestSource;
+use mcvm_plugin::hooks::{ProvidePackage, ProvidePackageArg};
 use mcvm_shared::output::MCVMOutput;
-use mcvm_shared::pkg::ArcPkgReq;
+use mcvm_shared::pkg::{ArcPkgReq, PackageID};
 use reqwest::Client;
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
@@ -16,15 +20,20 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
 
+use super::core::is_core_package;
 use super::eval::{EvalData, EvalInput, Routine};
-use super::repo::{query_all, PkgRepo};
-use super::{Package, PkgContents};
+use super::repo::{query_all, PkgRepo, RepoQueryResult};
+use super::{CachedPackageRecord, Package, PkgContents, PkgData, PkgLocation};
 use crate::config::plugin::PluginManager;
 use crate::io::paths::Paths;
 
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
+
+/// How long to wait for a plugin to provide a package before giving up
+pub const PLUGIN_PACKAGE_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// An object used to store and cache all of the packages that we are working with.
 /// It queries repositories automatically when asking for a package that isn't in the
@@ -35,16 +44,53 @@ pub struct PkgRegistry {
 	pub repos: Vec<PkgRepo>,
 	packages: HashMap<ArcPkgReq, Package>,
 	caching_strategy: CachingStrategy,
+	/// Plugins that can provide packages that aren't in any repository
+	plugins: Option<PluginManager>,
+	/// Packages that must be taken from a specific repository
+	repo_overrides: HashMap<PackageID, String>,
 }
 
 impl PkgRegistry {
-	/// Create a new PkgRegistry with repositories and a caching strategy
-	pub fn new(repos: Vec<PkgRepo>, caching_strategy: CachingStrategy) -> Self {
+	/// Create a new PkgRegistry with repositories and a caching strategy.
+	/// The repositories are ordered by their priority, keeping the given order for ties
+	pub fn new(mut repos: Vec<PkgRepo>, caching_strategy: CachingStrategy) -> Self {
+		repos.sort_by_key(|x| std::cmp::Reverse(x.get_priority()));
 		Self {
 			repos,
 			packages: HashMap::new(),
 			caching_strategy,
+			plugins: None,
+			repo_overrides: HashMap::new(),
+		}
+	}
+
+	/// Force packages to be taken from specific repositories instead of the
+	/// highest-priority ones that have them, replacing any overrides that were set before.
+	/// Overrides belong to the packages of one instance, so they should be cleared once it is resolved
+	pub fn set_repository_overrides(&mut self, overrides: HashMap<PackageID, String>) {
+		// Packages may have already been loaded from a different repository
+		let changed: Vec<_> = self
+			.repo_overrides
+			.keys()
+			.chain(overrides.keys())
+			.filter(|x| self.repo_overrides.get(*x) != overrides.get(*x))
+			.cloned()
+			.collect();
+		for package in changed {
+			self.packages
+				.remove(&PkgRequest::any(package, PkgRequestSource::Repository));
 		}
+		self.repo_overrides = overrides;
+	}
+
+	/// Remove all repository overrides
+	pub fn clear_repository_overrides(&mut self) {
+		self.set_repository_overrides(HashMap::new());
+	}
+
+	/// Set the plugins that will be asked for packages that aren't in any repository
+	pub fn set_plugins(&mut self, plugins: PluginManager) {
+		self.plugins = Some(plugins);
 	}
 
 	/// Insert a package into the registry and return a mutable reference to the
@@ -69,25 +115,159 @@ impl PkgRegistry {
 		client: &Client,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<&mut Package> {
+		// Packages with an explicit namespace can only come from that repository or plugin
+		if let Some(namespace) = &req.namespace {
+			if let Some(repo) = self.repos.iter_mut().find(|x| *x.id == **namespace) {
+				let Some(result) = repo
+					.query(&req.id, paths, client, o)
+					.await
+					.with_context(|| format!("Failed to query repository '{namespace}'"))?
+				else {
+					bail!(
+						"Package '{}' does not exist in repository '{namespace}'",
+						req.id
+					);
+				};
+				return Ok(self.insert(req.clone(), package_from_query(req, result)));
+			}
+
+			let package = self
+				.query_plugins(req, paths, o)
+				.context("Failed to get package from plugin")?;
+			let Some(package) = package else {
+				bail!("Package '{req}' does not exist. No repository or plugin namespace is named '{namespace}'");
+			};
+			return Ok(self.insert(req.clone(), package));
+		}
+
+		// Packages with an overridden repository can only come from that repository
+		if let Some(repo_id) = self.repo_overrides.get(&req.id) {
+			let Some(repo) = self.repos.iter_mut().find(|x| &x.id == repo_id) else {
+				bail!("Repository '{repo_id}' configured for package '{req}' does not exist");
+			};
+			let Some(result) = repo
+				.query(&req.id, paths, client, o)
+				.await
+				.with_context(|| format!("Failed to query repository '{repo_id}'"))?
+			else {
+				bail!("Package '{req}' does not exist in repository '{repo_id}', which it is configured to be installed from");
+			};
+			return Ok(self.insert(req.clone(), package_from_query(req, result)));
+		}
+
 		// First check the remote repositories
 		let query = query_all(&mut self.repos, &req.id, paths, client, o)
 			.await
 			.context("Failed to query remote repositories")?;
 		if let Some(result) = query {
-			return Ok(self.insert(
-				req.clone(),
-				Package::new(
-					req.id.clone(),
-					result.location,
-					result.content_type,
-					result.flags,
-				),
-			));
+			return Ok(self.insert(req.clone(), package_from_query(req, result)));
+		}
+
+		// Then ask any plugin that claims the package
+		let package = self
+			.query_plugins(req, paths, o)
+			.context("Failed to get package from plugin")?;
+		if let Some(package) = package {
+			Ok(self.insert(req.clone(), package))
 		} else {
-			Err(anyhow!("Package '{req}' does not exist"))
+			Err(anyhow!(
+				"Package '{req}' does not exist. Repositories consulted: {}",
+				self.repos.iter().map(|x| x.id.as_str()).join(", ")
+			))
 		}
 	}
 
+	/// Find which repository a package is taken from, and which lower-priority
+	/// repositories also have a definition of it that is shadowed
+	pub async fn get_package_sources(
+		&mut self,
+		req: &PkgRequest,
+		paths: &Paths,
+		client: &Client,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<PackageSources> {
+		let package = &req.id;
+		let mut found = Vec::new();
+		for repo in &mut self.repos {
+			let query = repo
+				.query(package, paths, client, o)
+				.await
+				.with_context(|| format!("Failed to query repository '{}'", repo.id))?;
+			if query.is_some() {
+				found.push(repo.id.clone());
+			}
+		}
+
+		let repository = match req
+			.namespace
+			.as_deref()
+			.or(self.repo_overrides.get(package).map(String::as_str))
+		{
+			Some(repo) => found.iter().position(|x| x == repo),
+			None if found.is_empty() => None,
+			None => Some(0),
+		};
+		let Some(repository) = repository else {
+			return Ok(PackageSources {
+				repository: None,
+				shadowed: Vec::new(),
+			});
+		};
+		let repository = found.remove(repository);
+
+		Ok(PackageSources {
+			repository: Some(repository),
+			shadowed: found,
+		})
+	}
+
+	/// Get a package from the plugin that claims its namespace, using the cached
+	/// contents if they are allowed by the caching strategy
+	fn query_plugins(
+		&self,
+		req: &ArcPkgReq,
+		paths: &Paths,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<Option<Package>> {
+		let Some(plugins) = &self.plugins else {
+			return Ok(None);
+		};
+		// Plugins name their packages with the namespace as a prefix
+		let id: PackageID = match &req.namespace {
+			Some(namespace) => format!("{namespace}-{}", req.id).into(),
+			None => req.id.clone(),
+		};
+		let Some(plugin_id) = plugins.get_package_provider(&id)? else {
+			return Ok(None);
+		};
+		// With no caching, the plugin is asked for the package every time
+		if !matches!(self.caching_strategy, CachingStrategy::None) {
+			if let Some(package) = Package::from_plugin_cache(id.clone(), &plugin_id, paths)? {
+				return Ok(Some(package));
+			}
+		}
+
+		let arg = ProvidePackageArg { id: id.to_string() };
+		let Some(handle) =
+			plugins.call_hook_on_plugin(ProvidePackage, &plugin_id, &arg, paths, o)?
+		else {
+			return Ok(None);
+		};
+		let result = handle.result_timeout(PLUGIN_PACKAGE_TIMEOUT, o)?;
+		let Some(contents) = result.contents else {
+			return Ok(None);
+		};
+
+		let location = PkgLocation::Plugin { plugin_id };
+		let mut package = Package::new(id, location, result.content_type, result.flags);
+		if !matches!(self.caching_strategy, CachingStrategy::None) {
+			package.write_plugin_cache(&contents, paths)?;
+		}
+		package.data.fill(PkgData::new(&contents));
+
+		Ok(Some(package))
+	}
+
 	/// Get a package from the map if it exists, and query insert it otherwise
 	async fn get(
 		&mut self,
@@ -104,7 +284,7 @@ impl PkgRegistry {
 	}
 
 	/// Ensure package contents while following the caching strategy
-	async fn ensure_package_contents(
+	pub async fn ensure_package_contents(
 		&mut self,
 		req: &ArcPkgReq,
 		paths: &Paths,
@@ -227,7 +407,7 @@ impl PkgRegistry {
 	}
 
 	/// Get the content type of a package
-	pub async fn content_type<'a>(
+	pub async fn content_type(
 		&mut self,
 		req: &ArcPkgReq,
 		paths: &Paths,
@@ -250,6 +430,15 @@ impl PkgRegistry {
 		Ok(&pkg.flags)
 	}
 
+	/// Check whether a package that has already been loaded comes from a local file.
+	/// Core packages with a downloaded override don't count
+	pub fn is_local(&self, req: &PkgRequest) -> bool {
+		let Some(package) = self.packages.get(req) else {
+			return false;
+		};
+		matches!(package.location, PkgLocation::Local(..)) && !is_core_package(&req.id)
+	}
+
 	/// Remove a cached package
 	pub async fn remove_cached(
 		&mut self,
@@ -266,6 +455,28 @@ impl PkgRegistry {
 		Ok(())
 	}
 
+	/// Fetch a package again, replacing its cached copy and any contents that were already
+	/// loaded or evaluated in this registry
+	pub async fn refresh(
+		&mut self,
+		req: &ArcPkgReq,
+		paths: &Paths,
+		client: &Client,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<()> {
+		self.remove_cached(req, paths, client, o).await?;
+		self.packages.remove(req);
+		let pkg = self
+			.get(req, paths, client, o)
+			.await
+			.with_context(|| format!("Failed to get package {req}"))?;
+		pkg.ensure_loaded(paths, true, client)
+			.await
+			.with_context(|| format!("Failed to load package {req}"))?;
+
+		Ok(())
+	}
+
 	/// Iterator over all package requests in the registry
 	pub fn iter_requests(&self) -> impl Iterator<Item = &ArcPkgReq> {
 		self.packages.keys()
@@ -317,6 +528,8 @@ impl PkgRegistry {
 		client: &Client,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<()> {
+		// Packages that were already loaded may be from the old indexes
+		self.packages.clear();
 		let packages = self
 			.get_all_available_packages(paths, client, o)
 			.await
@@ -325,6 +538,9 @@ impl PkgRegistry {
 		self.remove_cached_packages(packages.iter(), paths, client, o)
 			.await
 			.context("Failed to remove all cached packages")?;
+		// Packages from plugins aren't in any repository, so they are asked for again the next
+		// time that they are used
+		remove_cached_plugin_packages(paths).context("Failed to remove cached plugin packages")?;
 
 		// Redownload all the packages
 		if let CachingStrategy::All = self.caching_strategy {
@@ -339,10 +555,10 @@ impl PkgRegistry {
 				if let Some(task) = pkg.get_download_task(paths, true, client) {
 					let semaphore = semaphore.clone();
 					let task = async move {
-						let _ = semaphore.acquire_owned().await;
+						let _permit = semaphore.acquire_owned().await?;
 						task.await
 					};
-					tasks.spawn(task);
+					tasks.spawn(download::inherit_limits(stats::inherit(task)));
 				}
 			}
 
@@ -360,6 +576,72 @@ impl PkgRegistry {
 	}
 }
 
+/// Create a package from the result of querying a repository
+fn package_from_query(req: &ArcPkgReq, result: RepoQueryResult) -> Package {
+	let mut package = Package::new(
+		req.id.clone(),
+		result.location,
+		result.content_type,
+		result.flags,
+	);
+	package.hash = result.hash;
+	package.index_fingerprint = result.index_fingerprint;
+	package
+}
+
+/// Pin a request to the repository that it is overridden to, so that it stays
+/// distinct from the same package taken from another repository once the overrides are cleared
+pub fn apply_repository_override(
+	req: &ArcPkgReq,
+	overrides: &HashMap<PackageID, String>,
+) -> ArcPkgReq {
+	match overrides.get(&req.id) {
+		Some(repo) if req.namespace.is_none() => {
+			let mut req = (**req).clone();
+			req.namespace = Some(Arc::from(repo.as_str()));
+			Arc::new(req)
+		}
+		_ => req.clone(),
+	}
+}
+
+/// The repositories that a package is defined in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSources {
+	/// The repository that the package is taken from
+	pub repository: Option<String>,
+	/// Other repositories that define the package, but are not used
+	pub shadowed: Vec<String>,
+}
+
+/// Remove every cached package that was provided by a plugin
+fn remove_cached_plugin_packages(paths: &Paths) -> anyhow::Result<()> {
+	let dir = paths.cache.join("pkg");
+	let Ok(entries) = std::fs::read_dir(&dir) else {
+		return Ok(());
+	};
+	for entry in entries {
+		let path = entry?.path();
+		let Some(id) = path
+			.file_name()
+			.and_then(|x| x.to_str())
+			.and_then(|x| x.strip_suffix(".record.json"))
+		else {
+			continue;
+		};
+		let Ok(record) = json_from_file::<CachedPackageRecord>(&path) else {
+			continue;
+		};
+		let (Some(plugin_id), Some(content_type)) = (record.plugin, record.content_type) else {
+			continue;
+		};
+		let location = PkgLocation::Plugin { plugin_id };
+		Package::new(id.into(), location, content_type, HashSet::new()).remove_cached(paths)?;
+	}
+
+	Ok(())
+}
+
 /// What strategy to use for the local caching of package scripts
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -373,3 +655,198 @@ pub enum CachingStrategy {
 	#[default]
 	All,
 }
+
+#[cfg(test)]
+mod tests {
+	use mcvm_pkg::repo::{RepoIndex, RepoPkgEntry};
+	use mcvm_shared::output::NoOp;
+
+	use super::*;
+	use crate::pkg::repo::PkgRepoLocation;
+
+	/// Create a fixture repository with a set of packages
+	fn fixture_repo(id: &str, priority: i32, packages: &[&str]) -> PkgRepo {
+		let mut repo = PkgRepo::new(id, PkgRepoLocation::Local(format!("/{id}").into()));
+		repo.set_priority(priority);
+		let index = RepoIndex {
+			metadata: Default::default(),
+			packages: packages
+				.iter()
+				.map(|x| {
+					let entry = RepoPkgEntry {
+						url: None,
+						path: Some(format!("{x}.json")),
+						content_type: None,
+						flags: HashSet::new(),
+						hash: None,
+						name: None,
+						description: None,
+						properties: None,
+					};
+					(x.to_string(), entry)
+				})
+				.collect(),
+		};
+		repo.fill_index(index);
+		repo
+	}
+
+	fn get_location(reg: &mut PkgRegistry, id: &str) -> anyhow::Result<PkgLocation> {
+		let paths = Paths::new_no_create().unwrap();
+		let req = Arc::new(PkgRequest::parse(id, PkgRequestSource::UserRequire));
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let client = Client::new();
+		let pkg = runtime.block_on(reg.get(&req, &paths, &client, &mut NoOp))?;
+		Ok(pkg.location.clone())
+	}
+
+	#[test]
+	fn test_repository_priority() {
+		let repos = vec![
+			fixture_repo("first", 0, &["shared", "first-only"]),
+			fixture_repo("second", 0, &["shared", "second-only"]),
+		];
+		let mut reg = PkgRegistry::new(repos, CachingStrategy::None);
+		assert_eq!(
+			get_location(&mut reg, "shared").unwrap(),
+			PkgLocation::Local("/first/shared.json".into())
+		);
+		assert_eq!(
+			get_location(&mut reg, "second-only").unwrap(),
+			PkgLocation::Local("/second/second-only.json".into())
+		);
+
+		// A higher priority takes precedence over the list order
+		let repos = vec![
+			fixture_repo("first", 0, &["shared"]),
+			fixture_repo("second", 5, &["shared"]),
+		];
+		let mut reg = PkgRegistry::new(repos, CachingStrategy::None);
+		assert_eq!(
+			get_location(&mut reg, "shared").unwrap(),
+			PkgLocation::Local("/second/shared.json".into())
+		);
+	}
+
+	#[test]
+	fn test_repository_override() {
+		let repos = vec![
+			fixture_repo("first", 0, &["shared", "first-only"]),
+			fixture_repo("second", 0, &["shared"]),
+		];
+		let mut reg = PkgRegistry::new(repos, CachingStrategy::None);
+		assert_eq!(
+			get_location(&mut reg, "shared").unwrap(),
+			PkgLocation::Local("/first/shared.json".into())
+		);
+		// Overriding replaces the package that was already loaded
+		let overrides = HashMap::from([("shared".into(), "second".into())]);
+		reg.set_repository_overrides(overrides);
+		assert_eq!(
+			get_location(&mut reg, "shared").unwrap(),
+			PkgLocation::Local("/second/shared.json".into())
+		);
+		// Clearing the overrides doesn't leave the overridden package behind
+		reg.clear_repository_overrides();
+		assert_eq!(
+			get_location(&mut reg, "shared").unwrap(),
+			PkgLocation::Local("/first/shared.json".into())
+		);
+
+		let overrides = HashMap::from([("first-only".into(), "second".into())]);
+		reg.set_repository_overrides(overrides);
+		let err = get_location(&mut reg, "first-only").unwrap_err();
+		assert!(err
+			.to_string()
+			.contains("does not exist in repository 'second'"));
+		let overrides = HashMap::from([("first-only".into(), "missing".into())]);
+		reg.set_repository_overrides(overrides);
+		assert!(get_location(&mut reg, "first-only").is_err());
+	}
+
+	#[test]
+	fn test_apply_repository_override() {
+		let overrides = HashMap::from([("shared".into(), "second".into())]);
+		let apply = |id: &str| {
+			let req = Arc::new(PkgRequest::parse(id, PkgRequestSource::UserRequire));
+			apply_repository_override(&req, &overrides).to_string()
+		};
+		assert_eq!(apply("shared"), "second:shared");
+		// Explicit namespaces and other packages are left alone
+		assert_eq!(apply("first:shared"), "first:shared");
+		assert_eq!(apply("other"), "other");
+	}
+
+	#[test]
+	fn test_package_sources() {
+		let repos = vec![
+			fixture_repo("first", 0, &["shared"]),
+			fixture_repo("second", 0, &["shared", "second-only"]),
+		];
+		let mut reg = PkgRegistry::new(repos, CachingStrategy::None);
+		let paths = Paths::new_no_create().unwrap();
+		let client = Client::new();
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let get_sources = |reg: &mut PkgRegistry, id: &str| {
+			let req = PkgRequest::parse(id, PkgRequestSource::UserRequire);
+			runtime
+				.block_on(reg.get_package_sources(&req, &paths, &client, &mut NoOp))
+				.unwrap()
+		};
+
+		let sources = get_sources(&mut reg, "shared");
+		assert_eq!(sources.repository.as_deref(), Some("first"));
+		assert_eq!(sources.shadowed, vec!["second"]);
+
+		reg.set_repository_overrides(HashMap::from([("shared".into(), "second".into())]));
+		let sources = get_sources(&mut reg, "shared");
+		assert_eq!(sources.repository.as_deref(), Some("second"));
+		assert_eq!(sources.shadowed, vec!["first"]);
+
+		let sources = get_sources(&mut reg, "second-only");
+		assert_eq!(sources.repository.as_deref(), Some("second"));
+		assert!(sources.shadowed.is_empty());
+
+		let sources = get_sources(&mut reg, "missing");
+		assert_eq!(sources.repository, None);
+
+		let sources = get_sources(&mut reg, "first:shared");
+		assert_eq!(sources.repository.as_deref(), Some("first"));
+		assert_eq!(sources.shadowed, vec!["second"]);
+
+		let err = get_location(&mut reg, "missing").unwrap_err();
+		assert!(err
+			.to_string()
+			.contains("Repositories consulted: first, second"));
+	}
+
+	#[test]
+	fn test_explicit_namespace() {
+		let repos = vec![
+			fixture_repo("first", 0, &["shared", "first-only"]),
+			fixture_repo("second", 0, &["shared"]),
+		];
+		let mut reg = PkgRegistry::new(repos, CachingStrategy::None);
+
+		// Without a namespace, repository priority is used
+		assert_eq!(
+			get_location(&mut reg, "shared").unwrap(),
+			PkgLocation::Local("/first/shared.json".into())
+		);
+		assert_eq!(
+			get_location(&mut reg, "second:shared").unwrap(),
+			PkgLocation::Local("/second/shared.json".into())
+		);
+		// The two requests are kept separately in the registry
+		assert_eq!(reg.get_all_packages().len(), 2);
+
+		let err = get_location(&mut reg, "second:first-only").unwrap_err();
+		assert!(err
+			.to_string()
+			.contains("does not exist in repository 'second'"));
+		let err = get_location(&mut reg, "missing:shared").unwrap_err();
+		assert!(err
+			.to_string()
+			.contains("No repository or plugin namespace is named 'missing'"));
+	}
+}
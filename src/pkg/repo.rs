@@ -1,4 +1,6 @@
 use crate::io::paths::Paths;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use mcvm_core::io::{json_from_file, json_to_file};
 use mcvm_core::net::download;
 use mcvm_pkg::repo::{
 	get_api_url, get_index_url, PackageFlag, RepoIndex, RepoMetadata, RepoPkgEntry,
@@ -10,14 +12,16 @@ use anyhow::{bail, Context};
 use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
 use mcvm_shared::translate;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use std::borrow::Cow;
 use std::collections::HashSet;
 use std::fmt::Display;
-use std::fs::File;
-use std::io::{BufReader, Cursor};
-use std::path::PathBuf;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
 
+use super::core::overrides::{sync_core_overrides, CoreOverrides};
 use super::core::{
 	get_all_core_packages, get_core_package_content_type, get_core_package_count, is_core_package,
 };
@@ -30,6 +34,11 @@ pub struct PkgRepo {
 	pub id: String,
 	location: PkgRepoLocation,
 	index: Later<RepoIndex>,
+	public_key: Option<VerifyingKey>,
+	priority: i32,
+	core_overrides: Later<CoreOverrides>,
+	/// The hash of the loaded index
+	fingerprint: Option<String>,
 }
 
 /// Location for a PkgRepo
@@ -60,9 +69,35 @@ impl PkgRepo {
 			id: id.to_owned(),
 			location,
 			index: Later::new(),
+			public_key: None,
+			priority: 0,
+			core_overrides: Later::new(),
+			fingerprint: None,
 		}
 	}
 
+	/// Set the priority of this repository. Repositories with a higher priority
+	/// are consulted first when looking for a package
+	pub fn set_priority(&mut self, priority: i32) {
+		self.priority = priority;
+	}
+
+	/// Get the priority of this repository
+	pub fn get_priority(&self) -> i32 {
+		self.priority
+	}
+
+	/// Set the key that the index of this repository must be signed with
+	pub fn set_public_key(&mut self, key: VerifyingKey) {
+		self.public_key = Some(key);
+	}
+
+	/// Check whether the index of this repository is verified with a signature.
+	/// The internal core repository is always trusted
+	pub fn is_verified(&self) -> bool {
+		matches!(self.location, PkgRepoLocation::Core) || self.public_key.is_some()
+	}
+
 	/// Create the core repository
 	pub fn core() -> Self {
 		Self::new("core", PkgRepoLocation::Core)
@@ -99,38 +134,117 @@ impl PkgRepo {
 		&self.location
 	}
 
+	/// Fill the index directly, used for fixture repositories in tests
+	#[cfg(test)]
+	pub(crate) fn fill_index(&mut self, index: RepoIndex) {
+		self.index.fill(index);
+	}
+
 	/// Set the index to serialized json text
-	fn set_index(&mut self, index: &mut impl std::io::Read) -> anyhow::Result<()> {
-		let parsed = simd_json::from_reader(index)?;
+	fn set_index(&mut self, index: &[u8]) -> anyhow::Result<()> {
+		let parsed = simd_json::from_reader(Cursor::new(index))?;
 		self.index.fill(parsed);
+		self.fingerprint = Some(hash_index(index));
 		Ok(())
 	}
 
+	/// The cached path of the record of verified indexes
+	fn get_verified_record_path(&self, paths: &Paths) -> PathBuf {
+		paths
+			.pkg_index_cache
+			.join(format!("{}.verified.json", &self.id))
+	}
+
 	/// Update the currently cached index file
-	pub async fn sync(&mut self, paths: &Paths, client: &Client) -> anyhow::Result<()> {
+	pub async fn sync(
+		&mut self,
+		paths: &Paths,
+		client: &Client,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<()> {
 		match &self.location {
 			PkgRepoLocation::Local(path) => {
-				let bytes = tokio::fs::read(path).await?;
+				let path = get_local_index_path(path);
+				let bytes = tokio::fs::read(&path)
+					.await
+					.with_context(|| format!("Failed to read index {}", path.display()))?;
+				if self.public_key.is_some() {
+					let mut sig_path = path.into_os_string();
+					sig_path.push(".sig");
+					let signature = tokio::fs::read(sig_path).await.context(
+						"Failed to read index signature. Repositories with a public key must have one",
+					)?;
+					self.verify_index(&bytes, &signature, paths, o)?;
+				}
 				tokio::fs::write(self.get_path(paths), &bytes).await?;
-				let mut cursor = Cursor::new(&bytes);
-				self.set_index(&mut cursor).context("Failed to set index")?;
+				self.set_index(&bytes).context("Failed to set index")?;
 			}
 			PkgRepoLocation::Remote(url) => {
-				let bytes = download::bytes(get_index_url(url), client)
+				let index_url = get_index_url(url);
+				let bytes = download::bytes(&index_url, client)
 					.await
 					.context("Failed to download index")?;
+				if self.public_key.is_some() {
+					let signature = download::bytes(index_url + ".sig", client)
+						.await
+						.context("Failed to download index signature. Repositories with a public key must serve index.json.sig")?;
+					self.verify_index(&bytes, &signature, paths, o)?;
+				}
 				tokio::fs::write(self.get_path(paths), &bytes)
 					.await
 					.context("Failed to write index to cached file")?;
-				let mut cursor = Cursor::new(&bytes);
-				self.set_index(&mut cursor).context("Failed to set index")?;
+				self.set_index(&bytes).context("Failed to set index")?;
+			}
+			PkgRepoLocation::Core => {
+				// The bundled packages are still usable if the overrides can't be updated
+				if let Err(e) = sync_core_overrides(paths, client).await {
+					o.display(
+						MessageContents::Warning(translate!(
+							o,
+							CoreOverridesFailed,
+							"error" = &format!("{e:?}")
+						)),
+						MessageLevel::Important,
+					);
+				}
+				self.core_overrides.clear();
 			}
-			PkgRepoLocation::Core => {}
 		}
 
 		Ok(())
 	}
 
+	/// Verify a new index against its signature and check it against the previously verified indexes
+	fn verify_index(
+		&self,
+		index: &[u8],
+		signature: &[u8],
+		paths: &Paths,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<()> {
+		let Some(key) = &self.public_key else {
+			return Ok(());
+		};
+		verify_index_signature(index, signature, key)
+			.with_context(|| format!("Index for repository '{}' failed verification", self.id))?;
+
+		let record_path = self.get_verified_record_path(paths);
+		let mut record = if record_path.exists() {
+			json_from_file(&record_path).context("Failed to read verified index record")?
+		} else {
+			VerifiedIndexRecord::default()
+		};
+		if record.record(&hash_index(index)) {
+			o.display(
+				MessageContents::Warning(translate!(o, RepoIndexRollback, "repo" = &self.id)),
+				MessageLevel::Important,
+			);
+		}
+		json_to_file(&record_path, &record).context("Failed to write verified index record")?;
+
+		Ok(())
+	}
+
 	/// Make sure that the repository index is downloaded
 	pub async fn ensure_index(
 		&mut self,
@@ -138,26 +252,39 @@ impl PkgRepo {
 		client: &Client,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<()> {
-		// The core repository doesn't have an index
+		// The core repository doesn't have an index, but it does have overrides
 		if let PkgRepoLocation::Core = &self.location {
+			if self.core_overrides.is_empty() {
+				let overrides = CoreOverrides::load(paths).unwrap_or_else(|e| {
+					o.display(
+						MessageContents::Warning(translate!(
+							o,
+							CoreOverridesFailed,
+							"error" = &format!("{e:?}")
+						)),
+						MessageLevel::Important,
+					);
+					CoreOverrides::default()
+				});
+				self.core_overrides.fill(overrides);
+			}
 			return Ok(());
 		}
 
 		if self.index.is_empty() {
 			let path = self.get_path(paths);
 			if path.exists() {
-				let file = File::open(&path).context("Failed to open cached index")?;
-				let mut file = BufReader::new(file);
-				match self.set_index(&mut file) {
+				let bytes = std::fs::read(&path).context("Failed to read cached index")?;
+				match self.set_index(&bytes) {
 					Ok(..) => {}
 					Err(..) => {
-						self.sync(paths, client)
+						self.sync(paths, client, o)
 							.await
 							.context("Failed to sync index")?;
 					}
 				};
 			} else {
-				self.sync(paths, client)
+				self.sync(paths, client, o)
 					.await
 					.context("Failed to sync index")?;
 			}
@@ -197,20 +324,30 @@ impl PkgRepo {
 		client: &Client,
 		o: &mut impl MCVMOutput,
 	) -> anyhow::Result<Option<RepoQueryResult>> {
+		self.ensure_index(paths, client, o).await?;
 		// Get from the core
 		if let PkgRepoLocation::Core = &self.location {
-			if is_core_package(id) {
+			if let Some(core_override) = self.core_overrides.get().get(id) {
+				Ok(Some(RepoQueryResult {
+					location: PkgLocation::Local(core_override.path),
+					content_type: core_override.content_type,
+					flags: HashSet::new(),
+					hash: None,
+					index_fingerprint: None,
+				}))
+			} else if is_core_package(id) {
 				Ok(Some(RepoQueryResult {
 					location: PkgLocation::Core,
 					content_type: get_core_package_content_type(id)
 						.expect("Core package exists and should have a content type"),
 					flags: HashSet::new(),
+					hash: None,
+					index_fingerprint: None,
 				}))
 			} else {
 				Ok(None)
 			}
 		} else {
-			self.ensure_index(paths, client, o).await?;
 			let index = self.index.get();
 			if let Some(entry) = index.packages.get(id) {
 				let location = get_package_location(entry, &self.location, &self.id)
@@ -219,12 +356,33 @@ impl PkgRepo {
 					location,
 					content_type: get_content_type(entry).await,
 					flags: entry.flags.clone(),
+					hash: entry.hash.clone(),
+					index_fingerprint: self.fingerprint.clone(),
 				}));
 			}
 			Ok(None)
 		}
 	}
 
+	/// Get a hash that changes whenever the packages in this repository might have changed
+	pub async fn get_fingerprint(
+		&mut self,
+		paths: &Paths,
+		client: &Client,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<String> {
+		self.ensure_index(paths, client, o).await?;
+		if let PkgRepoLocation::Core = &self.location {
+			let overrides = self.core_overrides.get().get_fingerprint();
+			return Ok(hash_index(
+				format!("{}\n{overrides}", crate::VERSION).as_bytes(),
+			));
+		}
+
+		let index = std::fs::read(self.get_path(paths)).context("Failed to read cached index")?;
+		Ok(hash_index(&index))
+	}
+
 	/// Get all packages from this repo
 	pub async fn get_all_packages(
 		&mut self,
@@ -235,7 +393,7 @@ impl PkgRepo {
 		self.ensure_index(paths, client, o).await?;
 		// Get list from core
 		if let PkgRepoLocation::Core = &self.location {
-			Ok(get_all_core_packages())
+			Ok(get_all_core_packages(self.core_overrides.get()))
 		} else {
 			let index = self.index.get();
 			Ok(index
@@ -268,7 +426,7 @@ impl PkgRepo {
 		paths: &Paths,
 		client: &Client,
 		o: &mut impl MCVMOutput,
-	) -> anyhow::Result<Cow<RepoMetadata>> {
+	) -> anyhow::Result<Cow<'_, RepoMetadata>> {
 		self.ensure_index(paths, client, o).await?;
 
 		if let PkgRepoLocation::Core = &self.location {
@@ -285,6 +443,99 @@ impl PkgRepo {
 	}
 }
 
+/// Record of the index hashes that have been verified for a repository, used to detect rollbacks
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct VerifiedIndexRecord {
+	/// The hash of the most recently verified index
+	pub current: Option<String>,
+	/// The hashes of verified indexes that have since been replaced
+	pub previous: Vec<String>,
+}
+
+/// The maximum number of replaced index hashes to remember
+const MAX_PREVIOUS_INDEXES: usize = 64;
+
+impl VerifiedIndexRecord {
+	/// Record the hash of a newly verified index. Returns true if the index is one
+	/// that was already replaced by a newer one, which means that it was rolled back
+	pub fn record(&mut self, hash: &str) -> bool {
+		if self.current.as_deref() == Some(hash) {
+			return false;
+		}
+
+		let rolled_back = self.previous.iter().any(|x| x == hash);
+		self.previous.retain(|x| x != hash);
+		if let Some(current) = self.current.replace(hash.to_string()) {
+			self.previous.push(current);
+		}
+		if self.previous.len() > MAX_PREVIOUS_INDEXES {
+			self.previous.remove(0);
+		}
+
+		rolled_back
+	}
+}
+
+/// Parse a hex-encoded ed25519 public key for a repository
+pub fn parse_public_key(key: &str) -> anyhow::Result<VerifyingKey> {
+	let bytes = hex::decode(key.trim()).context("Public key is not valid hex")?;
+	let bytes: [u8; 32] = bytes
+		.try_into()
+		.map_err(|_| anyhow::anyhow!("Public key must be 32 bytes long"))?;
+	VerifyingKey::from_bytes(&bytes).context("Public key is not a valid ed25519 key")
+}
+
+/// Verify the signature of a repository index. The signature can either be the raw
+/// 64 signature bytes or those bytes encoded as hex
+pub fn verify_index_signature(
+	index: &[u8],
+	signature: &[u8],
+	key: &VerifyingKey,
+) -> anyhow::Result<()> {
+	let bytes = if signature.len() == Signature::BYTE_SIZE {
+		signature.to_vec()
+	} else {
+		let text = std::str::from_utf8(signature).context("Signature is malformed")?;
+		hex::decode(text.trim()).context("Signature is malformed")?
+	};
+	if bytes.is_empty() {
+		bail!("Signature is missing");
+	}
+	let signature = Signature::from_slice(&bytes).context("Signature is malformed")?;
+	key.verify(index, &signature)
+		.context("Signature does not match the index")
+}
+
+/// Get the path to the index of a local repository. The configured path can
+/// either be the index file itself or the directory that contains index.json
+fn get_local_index_path(path: &Path) -> PathBuf {
+	if is_local_index_file(path) {
+		path.to_owned()
+	} else {
+		path.join("index.json")
+	}
+}
+
+/// Get the directory that relative package paths in a local repository are relative to
+fn get_local_index_dir(path: &Path) -> &Path {
+	if is_local_index_file(path) {
+		path.parent().unwrap_or(Path::new(""))
+	} else {
+		path
+	}
+}
+
+/// Check whether the configured path of a local repository is its index file
+fn is_local_index_file(path: &Path) -> bool {
+	path.extension().is_some_and(|x| x == "json")
+}
+
+/// Get the hash of an index that is stored to detect rollbacks
+fn hash_index(index: &[u8]) -> String {
+	hex::encode(Sha256::digest(index))
+}
+
 /// Query a list of repos
 pub async fn query_all(
 	repos: &mut [PkgRepo],
@@ -340,6 +591,10 @@ pub struct RepoQueryResult {
 	pub content_type: PackageContentType,
 	/// The flags for the package
 	pub flags: HashSet<PackageFlag>,
+	/// The hash of the package file declared by the repository
+	pub hash: Option<String>,
+	/// The fingerprint of the repository index that the package was found in
+	pub index_fingerprint: Option<String>,
 }
 
 /// Get the content type of a package from the repository
@@ -390,7 +645,7 @@ pub fn get_package_location(
 			// Local paths
 			PkgRepoLocation::Local(repo_path) => {
 				let path = if path.is_relative() {
-					repo_path.join(path)
+					get_local_index_dir(repo_path).join(path)
 				} else {
 					path
 				};
@@ -403,3 +658,57 @@ pub fn get_package_location(
 		bail!("Neither url nor path entry present in package")
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use ed25519_dalek::{Signer, SigningKey};
+
+	/// Seed for the fixture keypair
+	const FIXTURE_SEED: [u8; 32] = [7; 32];
+	const INDEX: &[u8] = br#"{"metadata":{},"packages":{}}"#;
+
+	#[test]
+	fn test_index_signature() {
+		let signing_key = SigningKey::from_bytes(&FIXTURE_SEED);
+		let key = parse_public_key(&hex::encode(signing_key.verifying_key().as_bytes())).unwrap();
+		let signature = signing_key.sign(INDEX).to_bytes();
+
+		// Good signatures, both raw and hex encoded
+		verify_index_signature(INDEX, &signature, &key).unwrap();
+		verify_index_signature(INDEX, hex::encode(signature).as_bytes(), &key).unwrap();
+
+		// Bad signatures
+		let tampered = br#"{"metadata":{},"packages":{"evil":{}}}"#;
+		assert!(verify_index_signature(tampered, &signature, &key).is_err());
+		let other_key = SigningKey::from_bytes(&[8; 32]).verifying_key();
+		assert!(verify_index_signature(INDEX, &signature, &other_key).is_err());
+		assert!(verify_index_signature(INDEX, b"not a signature", &key).is_err());
+
+		// Missing signature
+		assert!(verify_index_signature(INDEX, b"", &key).is_err());
+		assert!(verify_index_signature(INDEX, b"\n", &key).is_err());
+	}
+
+	#[test]
+	fn test_parse_public_key() {
+		assert!(parse_public_key("zz").is_err());
+		assert!(parse_public_key("abcd").is_err());
+		let key = SigningKey::from_bytes(&FIXTURE_SEED).verifying_key();
+		assert_eq!(parse_public_key(&hex::encode(key.as_bytes())).unwrap(), key);
+	}
+
+	#[test]
+	fn test_index_rollback_detection() {
+		let mut record = VerifiedIndexRecord::default();
+		assert!(!record.record("a"));
+		assert!(!record.record("a"));
+		assert!(!record.record("b"));
+		assert!(!record.record("c"));
+		// Going back to an index that was replaced is a rollback
+		assert!(record.record("a"));
+		assert_eq!(record.current.as_deref(), Some("a"));
+		assert_eq!(record.previous, vec!["b", "c"]);
+	}
+}
@@ -0,0 +1,567 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use mcvm_core::io::{json_from_file, json_to_file};
+use mcvm_pkg::properties::PackageProperties;
+use mcvm_shared::modifications::{Modloader, ModloaderMatch, PluginLoaderMatch, ServerType};
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+use mcvm_shared::pkg::PackageID;
+use mcvm_shared::versions::VersionPattern;
+use mcvm_shared::Side;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::reg::PkgRegistry;
+use super::repo::{PkgRepo, PkgRepoLocation};
+use super::Package;
+use crate::io::paths::Paths;
+
+/// A persisted index of the packages in every repository, used to search for packages
+/// without loading all of them each time
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SearchIndex {
+	/// The indexed repositories, in priority order
+	pub repos: Vec<RepoSearchIndex>,
+}
+
+/// The part of the search index for a single repository
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RepoSearchIndex {
+	/// The ID of the repository
+	pub id: String,
+	/// The fingerprint of the repository index that the entries were built from
+	pub fingerprint: String,
+	/// The packages in the repository
+	pub entries: Vec<SearchEntry>,
+}
+
+/// A package in the search index
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SearchEntry {
+	/// The ID of the package
+	pub id: PackageID,
+	/// The ID of the repository that the package is from
+	pub repository: String,
+	/// The display name of the package
+	pub name: Option<String>,
+	/// The short description of the package
+	pub description: Option<String>,
+	/// The Minecraft versions that the package supports
+	pub supported_versions: Option<Vec<VersionPattern>>,
+	/// The modloaders that the package supports
+	pub supported_modloaders: Option<Vec<ModloaderMatch>>,
+	/// The plugin loaders that the package supports
+	pub supported_plugin_loaders: Option<Vec<PluginLoaderMatch>>,
+	/// The sides that the package supports
+	pub supported_sides: Option<Vec<Side>>,
+}
+
+impl SearchEntry {
+	/// Create an entry for a package that couldn't be loaded, which can still be found by its ID
+	fn new(id: PackageID, repository: String) -> Self {
+		Self {
+			id,
+			repository,
+			..Default::default()
+		}
+	}
+
+	/// Fill in the searchable fields from the properties of the package
+	fn set_properties(&mut self, properties: &PackageProperties) {
+		self.supported_versions = properties.supported_versions.clone();
+		self.supported_modloaders = properties.supported_modloaders.clone();
+		self.supported_plugin_loaders = properties.supported_plugin_loaders.clone();
+		self.supported_sides = properties.supported_sides.clone();
+	}
+}
+
+/// A loader to filter search results by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoaderFilter {
+	/// A modloader
+	Modloader(Modloader),
+	/// A server that loads plugins
+	PluginLoader(ServerType),
+}
+
+impl FromStr for LoaderFilter {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let filter = match s {
+			"vanilla" => Self::Modloader(Modloader::Vanilla),
+			"forge" => Self::Modloader(Modloader::Forge),
+			"neoforged" => Self::Modloader(Modloader::NeoForged),
+			"fabric" => Self::Modloader(Modloader::Fabric),
+			"quilt" => Self::Modloader(Modloader::Quilt),
+			"liteloader" => Self::Modloader(Modloader::LiteLoader),
+			"risugamis" => Self::Modloader(Modloader::Risugamis),
+			"rift" => Self::Modloader(Modloader::Rift),
+			"paper" => Self::PluginLoader(ServerType::Paper),
+			"sponge" => Self::PluginLoader(ServerType::Sponge),
+			"spongeforge" => Self::PluginLoader(ServerType::SpongeForge),
+			"craftbukkit" => Self::PluginLoader(ServerType::CraftBukkit),
+			"spigot" => Self::PluginLoader(ServerType::Spigot),
+			"glowstone" => Self::PluginLoader(ServerType::Glowstone),
+			"pufferfish" => Self::PluginLoader(ServerType::Pufferfish),
+			"purpur" => Self::PluginLoader(ServerType::Purpur),
+			"folia" => Self::PluginLoader(ServerType::Folia),
+			other => bail!("Unknown loader '{other}'"),
+		};
+
+		Ok(filter)
+	}
+}
+
+/// Filters for a package search. Packages that don't declare the property that a filter
+/// checks are not filtered out
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilters {
+	/// The loader that packages must support
+	pub loader: Option<LoaderFilter>,
+	/// The side that packages must support
+	pub side: Option<Side>,
+	/// The Minecraft version that packages must support
+	pub version: Option<String>,
+	/// The list of Minecraft versions, used to check version patterns
+	pub version_list: Vec<String>,
+}
+
+impl SearchFilters {
+	/// Check whether a package passes the filters
+	pub fn matches(&self, entry: &SearchEntry) -> bool {
+		match &self.loader {
+			Some(LoaderFilter::Modloader(modloader)) => {
+				if let Some(supported) = &entry.supported_modloaders {
+					if !supported.iter().any(|x| x.matches(modloader)) {
+						return false;
+					}
+				}
+			}
+			Some(LoaderFilter::PluginLoader(server_type)) => {
+				if let Some(supported) = &entry.supported_plugin_loaders {
+					if !supported.iter().any(|x| x.matches(server_type)) {
+						return false;
+					}
+				}
+			}
+			None => {}
+		}
+
+		if let (Some(side), Some(supported)) = (&self.side, &entry.supported_sides) {
+			if !supported.contains(side) {
+				return false;
+			}
+		}
+
+		if let (Some(version), Some(supported)) = (&self.version, &entry.supported_versions) {
+			if !supported
+				.iter()
+				.any(|x| x.matches_single(version, &self.version_list))
+			{
+				return false;
+			}
+		}
+
+		true
+	}
+}
+
+impl SearchIndex {
+	/// Get the path that the search index is stored at
+	pub fn get_path(paths: &Paths) -> PathBuf {
+		paths.pkg_index_cache.join("search.json")
+	}
+
+	/// Open the stored search index. An index that is missing or can't be read is treated as empty,
+	/// since it will just be built again
+	pub fn open(paths: &Paths) -> Self {
+		let path = Self::get_path(paths);
+		if path.exists() {
+			json_from_file(path).unwrap_or_default()
+		} else {
+			Self::default()
+		}
+	}
+
+	/// Write the search index to its stored location
+	pub fn write(&self, paths: &Paths) -> anyhow::Result<()> {
+		json_to_file(Self::get_path(paths), self).context("Failed to write search index")
+	}
+
+	/// Search for packages, returning the matches from the most to the least relevant.
+	/// Every whitespace-separated term of the query has to match the package. Packages that
+	/// are shadowed by one in a higher-priority repository are left out
+	pub fn search(&self, query: &str, filters: &SearchFilters) -> Vec<&SearchEntry> {
+		let query = query.to_lowercase();
+		let terms: Vec<_> = query.split_whitespace().collect();
+
+		let mut seen = HashSet::new();
+		self.repos
+			.iter()
+			.flat_map(|x| &x.entries)
+			.filter(|x| seen.insert(x.id.clone()))
+			.filter(|x| filters.matches(x))
+			.filter_map(|x| Some((get_relevance(x, &terms)?, x)))
+			.sorted_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.id.cmp(&b.1.id)))
+			.map(|x| x.1)
+			.collect()
+	}
+}
+
+/// Get the search index, rebuilding the parts of it for repositories whose index has changed
+/// since it was last built
+pub async fn update_search_index(
+	reg: &mut PkgRegistry,
+	paths: &Paths,
+	client: &Client,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<SearchIndex> {
+	let mut old = SearchIndex::open(paths);
+	let old_ids: Vec<_> = old.repos.iter().map(|x| x.id.clone()).collect();
+	let mut index = SearchIndex::default();
+	let mut changed = false;
+	for repo in &mut reg.repos {
+		let fingerprint = repo
+			.get_fingerprint(paths, client, o)
+			.await
+			.with_context(|| format!("Failed to check index of repository '{}'", repo.id))?;
+		let existing = old
+			.repos
+			.iter()
+			.position(|x| x.id == repo.id && x.fingerprint == fingerprint);
+		if let Some(existing) = existing {
+			index.repos.push(old.repos.swap_remove(existing));
+			continue;
+		}
+
+		let entries = index_repo(repo, paths, client, o)
+			.await
+			.with_context(|| format!("Failed to index repository '{}'", repo.id))?;
+		index.repos.push(RepoSearchIndex {
+			id: repo.id.clone(),
+			fingerprint,
+			entries,
+		});
+		changed = true;
+	}
+
+	// Repositories can also be removed or reordered without any of them changing
+	if changed || index.repos.iter().map(|x| &x.id).ne(old_ids.iter()) {
+		index.write(paths)?;
+	}
+
+	Ok(index)
+}
+
+/// Create the search entries for all of the packages in a repository. The names, descriptions,
+/// and properties come from the repository index, so that remote packages don't have to be
+/// downloaded. Only the packages of the core repository, which are built in, are loaded instead
+async fn index_repo(
+	repo: &mut PkgRepo,
+	paths: &Paths,
+	client: &Client,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<Vec<SearchEntry>> {
+	let packages = repo.get_all_packages(paths, client, o).await?;
+	let mut out = Vec::new();
+	for (id, repo_entry) in packages
+		.into_iter()
+		.sorted_by(|a, b| a.0.cmp(&b.0))
+		.dedup_by(|a, b| a.0 == b.0)
+	{
+		let mut entry = SearchEntry::new(id.clone().into(), repo.id.clone());
+		entry.name = repo_entry.name;
+		entry.description = repo_entry.description;
+		if let Some(properties) = &repo_entry.properties {
+			entry.set_properties(properties);
+		}
+
+		if let PkgRepoLocation::Core = repo.get_location() {
+			index_core_package(&mut entry, repo, paths, client, o).await?;
+		}
+		out.push(entry);
+	}
+
+	Ok(out)
+}
+
+/// Fill in a search entry for a package from the core repository by loading it
+async fn index_core_package(
+	entry: &mut SearchEntry,
+	repo: &mut PkgRepo,
+	paths: &Paths,
+	client: &Client,
+	o: &mut impl MCVMOutput,
+) -> anyhow::Result<()> {
+	let Some(result) = repo.query(&entry.id, paths, client, o).await? else {
+		return Ok(());
+	};
+	let mut package = Package::new(
+		entry.id.clone(),
+		result.location,
+		result.content_type,
+		result.flags,
+	);
+	// A package that can't be loaded can still be found by its ID
+	match package.get_metadata(paths, client).await {
+		Ok(metadata) => {
+			entry.name = metadata.name.clone();
+			entry.description = metadata.description.clone();
+		}
+		Err(e) => {
+			o.display(
+				MessageContents::Warning(format!("Failed to index package '{}': {e:?}", entry.id)),
+				MessageLevel::Debug,
+			);
+		}
+	}
+	if let Ok(properties) = package.get_properties(paths, client).await {
+		entry.set_properties(properties);
+	}
+
+	Ok(())
+}
+
+/// Get how relevant a package is to the terms of a search query, or None if it doesn't match
+fn get_relevance(entry: &SearchEntry, terms: &[&str]) -> Option<u32> {
+	let id = entry.id.to_lowercase();
+	let name = entry.name.as_deref().unwrap_or_default().to_lowercase();
+	let description = entry
+		.description
+		.as_deref()
+		.unwrap_or_default()
+		.to_lowercase();
+
+	let mut out = 0;
+	for term in terms {
+		let relevance = if id == *term {
+			100
+		} else if name == *term {
+			80
+		} else if id.starts_with(term) {
+			60
+		} else if id.contains(term) {
+			40
+		} else if name.contains(term) {
+			30
+		} else if description.contains(term) {
+			10
+		} else if let Some(similarity) = get_similarity(term, &id, &name) {
+			// Closer typos rank higher
+			(similarity * 10.0) as u32
+		} else if term.len() >= 3 && is_subsequence(term, &id) {
+			5
+		} else {
+			return None;
+		};
+		out += relevance;
+	}
+
+	Some(out)
+}
+
+/// The lowest similarity that a search term can have with a word to count as a typo of it
+const MIN_SIMILARITY: f64 = 0.7;
+
+/// Get how similar a search term is to the ID or to any word of the ID or name of a package,
+/// from zero to one, or None if it isn't similar enough to be a typo of any of them
+fn get_similarity(term: &str, id: &str, name: &str) -> Option<f64> {
+	if term.len() < 3 {
+		return None;
+	}
+
+	std::iter::once(id)
+		.chain(id.split('-'))
+		.chain(name.split(|x: char| !x.is_alphanumeric()))
+		.filter(|x| !x.is_empty())
+		.map(|x| strsim::normalized_damerau_levenshtein(term, x))
+		.filter(|x| *x >= MIN_SIMILARITY)
+		.max_by(f64::total_cmp)
+}
+
+/// Check whether the characters of a string appear in another string in the same order,
+/// which catches abbreviations and some typos
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+	let mut haystack = haystack.chars();
+	needle.chars().all(|x| haystack.any(|y| y == x))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(id: &str, repository: &str, name: &str, description: &str) -> SearchEntry {
+		SearchEntry {
+			name: Some(name.into()),
+			description: Some(description.into()),
+			..SearchEntry::new(id.into(), repository.into())
+		}
+	}
+
+	fn index(entries: Vec<SearchEntry>) -> SearchIndex {
+		let repos = entries
+			.into_iter()
+			.group_by(|x| x.repository.clone())
+			.into_iter()
+			.map(|(id, entries)| RepoSearchIndex {
+				id,
+				fingerprint: String::new(),
+				entries: entries.collect(),
+			})
+			.collect();
+		SearchIndex { repos }
+	}
+
+	fn search_ids(index: &SearchIndex, query: &str, filters: &SearchFilters) -> Vec<String> {
+		index
+			.search(query, filters)
+			.into_iter()
+			.map(|x| x.id.to_string())
+			.collect()
+	}
+
+	#[test]
+	fn test_relevance_order() {
+		let index = index(vec![
+			entry(
+				"sodium-extra",
+				"std",
+				"Sodium Extra",
+				"Extra options for Sodium",
+			),
+			entry(
+				"indium",
+				"std",
+				"Indium",
+				"Rendering API support for sodium",
+			),
+			entry("sodium", "std", "Sodium", "A rendering engine"),
+			entry("lithium", "std", "Lithium", "Optimizes game logic"),
+			entry("magnesium", "std", "Rubidium", "A fork of sodium for Forge"),
+			entry("sdm-tweaks", "std", "SDM Tweaks", "Small tweaks"),
+		]);
+		let filters = SearchFilters::default();
+
+		assert_eq!(
+			search_ids(&index, "sodium", &filters),
+			vec!["sodium", "sodium-extra", "indium", "magnesium"]
+		);
+		assert_eq!(search_ids(&index, "rubidium", &filters), vec!["magnesium"]);
+		assert_eq!(
+			search_ids(&index, "SODIUM extra", &filters),
+			vec!["sodium-extra"]
+		);
+		assert_eq!(
+			search_ids(&index, "sdm", &filters),
+			vec!["sdm-tweaks", "sodium", "sodium-extra"]
+		);
+		assert!(search_ids(&index, "optifine", &filters).is_empty());
+		assert_eq!(search_ids(&index, "", &filters).len(), 6);
+	}
+
+	#[test]
+	fn test_fuzzy_search() {
+		let index = index(vec![
+			entry("sodium", "std", "Sodium", ""),
+			entry("sodium-extra", "std", "Sodium Extra", ""),
+			entry("lithium", "std", "Lithium", ""),
+			entry("iris", "std", "Iris Shaders", ""),
+		]);
+		let filters = SearchFilters::default();
+
+		// Typos of the ID or of a word in the name still match, and unrelated packages are left out
+		assert_eq!(
+			search_ids(&index, "sodum", &filters),
+			vec!["sodium", "sodium-extra"]
+		);
+		assert_eq!(search_ids(&index, "lithum", &filters), vec!["lithium"]);
+		assert_eq!(search_ids(&index, "shaderz", &filters), vec!["iris"]);
+		assert_eq!(
+			search_ids(&index, "sodim extar", &filters),
+			vec!["sodium-extra"]
+		);
+		assert!(search_ids(&index, "sxyz", &filters).is_empty());
+		assert_eq!(get_similarity("sodium", "sodium", ""), Some(1.0));
+		assert_eq!(get_similarity("so", "sodium", ""), None);
+	}
+
+	#[test]
+	fn test_shadowed_packages() {
+		let index = index(vec![
+			entry("sodium", "custom", "My Sodium", ""),
+			entry("sodium", "std", "Sodium", ""),
+			entry("lithium", "std", "Lithium", ""),
+		]);
+
+		let results = index.search("ium", &SearchFilters::default());
+		assert_eq!(results.len(), 2);
+		let sodium = results.iter().find(|x| &*x.id == "sodium").unwrap();
+		assert_eq!(sodium.repository, "custom");
+	}
+
+	#[test]
+	fn test_filters() {
+		let mut fabric = entry("fabric-mod", "std", "", "");
+		fabric.supported_modloaders = Some(vec![ModloaderMatch::FabricLike]);
+		fabric.supported_sides = Some(vec![Side::Client]);
+		fabric.supported_versions = Some(vec![VersionPattern::After("1.20".into())]);
+		let mut plugin = entry("paper-plugin", "std", "", "");
+		plugin.supported_plugin_loaders = Some(vec![PluginLoaderMatch::Bukkit]);
+		plugin.supported_sides = Some(vec![Side::Server]);
+		let anything = entry("anything", "std", "", "");
+		let index = index(vec![fabric, plugin, anything]);
+
+		let filters = SearchFilters {
+			loader: Some("quilt".parse().unwrap()),
+			..Default::default()
+		};
+		assert_eq!(
+			search_ids(&index, "", &filters),
+			vec!["anything", "fabric-mod", "paper-plugin"]
+		);
+
+		let filters = SearchFilters {
+			loader: Some("forge".parse().unwrap()),
+			..Default::default()
+		};
+		assert_eq!(
+			search_ids(&index, "", &filters),
+			vec!["anything", "paper-plugin"]
+		);
+
+		let filters = SearchFilters {
+			loader: Some("purpur".parse().unwrap()),
+			side: Some(Side::Server),
+			..Default::default()
+		};
+		assert_eq!(
+			search_ids(&index, "", &filters),
+			vec!["anything", "paper-plugin"]
+		);
+
+		let version_list = vec!["1.19.4".into(), "1.20".into(), "1.20.1".into()];
+		let filters = SearchFilters {
+			version: Some("1.19.4".into()),
+			version_list: version_list.clone(),
+			..Default::default()
+		};
+		assert_eq!(
+			search_ids(&index, "", &filters),
+			vec!["anything", "paper-plugin"]
+		);
+		let filters = SearchFilters {
+			version: Some("1.20.1".into()),
+			version_list,
+			..Default::default()
+		};
+		assert_eq!(search_ids(&index, "", &filters).len(), 3);
+
+		assert!("optifine".parse::<LoaderFilter>().is_err());
+	}
+}
@@ -1,2 +1,4 @@
 /// Installing and launching proxies on profiles
 pub mod proxy;
+/// Telling plugins what changed when a profile is updated
+pub mod update;
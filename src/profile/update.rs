@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use itertools::Itertools;
+use mcvm_plugin::hooks::{
+	OnProfileUpdate, OnProfileUpdateArg, PackageChangeKind, ProfilePackageChange,
+};
+use mcvm_shared::id::{InstanceID, ProfileID};
+use mcvm_shared::output::{MCVMOutput, MessageContents, MessageLevel};
+
+use crate::config::plugin::PluginManager;
+use crate::io::lock::{Lockfile, LockfileUpdateRecord};
+use crate::io::paths::Paths;
+
+/// The state of the instances of a profile in the lockfile from before they were updated
+pub struct ProfileUpdateSnapshot {
+	profile: ProfileID,
+	instances: Vec<(InstanceID, Option<LockfileUpdateRecord>)>,
+}
+
+impl ProfileUpdateSnapshot {
+	/// Remember the state of the instances of a profile before they are updated
+	pub fn new(profile: ProfileID, instances: &[InstanceID], lock: &Lockfile) -> Self {
+		let instances = instances
+			.iter()
+			.map(|id| (id.clone(), lock.get_instance_state(id)))
+			.collect();
+		Self { profile, instances }
+	}
+
+	/// Compare the snapshot to the updated lockfile to get what changed
+	pub fn get_changes(&self, lock: &Lockfile) -> OnProfileUpdateArg {
+		self.get_changes_to(|id| lock.get_instance_state(id))
+	}
+
+	/// Compare the snapshot to the new states of its instances
+	fn get_changes_to(
+		&self,
+		get_state: impl Fn(&str) -> Option<LockfileUpdateRecord>,
+	) -> OnProfileUpdateArg {
+		let mut out = OnProfileUpdateArg {
+			profile: self.profile.to_string(),
+			instances: self.instances.iter().map(|x| x.0.to_string()).collect(),
+			..Default::default()
+		};
+
+		for (id, old) in &self.instances {
+			let new = get_state(id);
+			if out.old_version.is_none() {
+				out.old_version = old.as_ref().map(|x| x.version.clone());
+			}
+			if out.new_version.is_none() {
+				out.new_version = new.as_ref().map(|x| x.version.clone());
+			}
+
+			let empty = HashMap::new();
+			let old = old.as_ref().map(|x| &x.packages).unwrap_or(&empty);
+			let new = new.as_ref().map(|x| &x.packages).unwrap_or(&empty);
+			for package in old.keys().chain(new.keys()).unique().sorted() {
+				let (old, new) = (old.get(package), new.get(package));
+				let kind = match (old, new) {
+					(None, Some(_)) => PackageChangeKind::Added,
+					(Some(_), None) => PackageChangeKind::Removed,
+					(Some(old), Some(new)) if old != new => PackageChangeKind::Upgraded,
+					_ => continue,
+				};
+				out.packages.push(ProfilePackageChange {
+					instance: id.to_string(),
+					package: package.clone(),
+					kind,
+					old_version: old.and_then(format_package_version),
+					new_version: new.and_then(format_package_version),
+				});
+			}
+		}
+
+		out
+	}
+
+	/// Tell plugins what changed in the update. A plugin failing to handle it
+	/// doesn't fail the update, since the update has already been written
+	pub fn call_hook(
+		&self,
+		lock: &Lockfile,
+		plugins: &PluginManager,
+		paths: &Paths,
+		o: &mut impl MCVMOutput,
+	) -> anyhow::Result<()> {
+		let arg = self.get_changes(lock);
+		let results = plugins
+			.call_hook(OnProfileUpdate, &arg, paths, o)
+			.context("Failed to call profile update hook")?;
+		for result in results {
+			if let Err(e) = result.result(o) {
+				o.display(
+					MessageContents::Warning(format!(
+						"A plugin failed to handle the update of profile '{}': {e:?}",
+						self.profile
+					)),
+					MessageLevel::Important,
+				);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Get the version of a package from the versions of its addons
+fn format_package_version(addons: &HashMap<String, String>) -> Option<String> {
+	let versions = addons.values().unique().sorted().join(", ");
+	(!versions.is_empty()).then_some(versions)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn state(version: &str, packages: &[(&str, &str)]) -> LockfileUpdateRecord {
+		let packages = packages
+			.iter()
+			.map(|(id, version)| {
+				let addons = HashMap::from([(id.to_string(), version.to_string())]);
+				(id.to_string(), addons)
+			})
+			.collect();
+		let mut out = LockfileUpdateRecord::default();
+		out.version = version.into();
+		out.packages = packages;
+		out
+	}
+
+	#[test]
+	fn test_profile_update_changes() {
+		let snapshot = ProfileUpdateSnapshot {
+			profile: "survival".into(),
+			instances: vec![
+				(
+					"client".into(),
+					Some(state("1.20.1", &[("sodium", "0.5.0"), ("iris", "1.6")])),
+				),
+				("server".into(), None),
+			],
+		};
+		let changes = snapshot.get_changes_to(|id| match id {
+			"client" => Some(state("1.20.4", &[("sodium", "0.5.8"), ("lithium", "0.12")])),
+			_ => Some(state("1.20.4", &[])),
+		});
+
+		assert_eq!(changes.profile, "survival");
+		assert_eq!(changes.old_version.as_deref(), Some("1.20.1"));
+		assert_eq!(changes.new_version.as_deref(), Some("1.20.4"));
+		assert_eq!(changes.instances, vec!["client", "server"]);
+		let packages: Vec<_> = changes
+			.packages
+			.iter()
+			.map(|x| {
+				(
+					x.package.as_str(),
+					x.kind,
+					x.old_version.as_deref(),
+					x.new_version.as_deref(),
+				)
+			})
+			.collect();
+		assert_eq!(
+			packages,
+			vec![
+				("iris", PackageChangeKind::Removed, Some("1.6"), None),
+				("lithium", PackageChangeKind::Added, None, Some("0.12")),
+				(
+					"sodium",
+					PackageChangeKind::Upgraded,
+					Some("0.5.0"),
+					Some("0.5.8")
+				),
+			]
+		);
+	}
+}
@@ -25,6 +25,7 @@ async fn run() -> anyhow::Result<()> {
 		&mut core,
 		&version_info,
 		fabric_quilt::Mode::Fabric,
+		&fabric_quilt::LoaderVersion::Latest,
 		Side::Client,
 		&mut o,
 	)